@@ -33,6 +33,7 @@ fn main() {
     generate_contract("SolutionSubmitter");
     generate_contract("TokenOWL");
     generate_contract("TokenOWLProxy");
+    generate_contract("WETH9");
 }
 
 fn generate_contract(name: &str) {
@@ -3,9 +3,12 @@
 //! they can be used by the build script.
 
 use anyhow::{anyhow, bail, Context as _, Result};
-use contracts::*;
+use contracts::{deployment::Deployment, paths};
 use env_logger::Env;
-use ethcontract::{Address, Http, Web3};
+use ethcontract::{
+    dyns::{DynTransport, DynWeb3},
+    Address, Http,
+};
 use filetime::FileTime;
 use std::{
     fs,
@@ -28,53 +31,23 @@ async fn run() -> Result<()> {
     const NODE_URL: &str = "http://localhost:8545";
 
     let http = Http::new(NODE_URL)?;
-    let web3 = Web3::new(http);
+    let web3 = DynWeb3::new(DynTransport::new(http));
 
     log::info!("checking connection to local test node {}", NODE_URL);
     wait_for_node(&web3).await?;
 
-    macro_rules! deploy {
-            ($contract:ident) => { deploy!($contract ()) };
-            ($contract:ident ( $($param:expr),* $(,)? )) => {{
-                const NAME: &str = stringify!($contract);
-
-                log::debug!("deploying {}...", NAME);
-                let instance = $contract::builder(&web3 $(, $param)*)
-                    .gas(8_000_000.into())
-                    .deploy()
-                    .await
-                    .with_context(|| format!("failed to deploy {}", NAME))?;
-
-                log::debug!(
-                    "writing deployment to {}",
-                    paths::contract_address_file(NAME).display(),
-                );
-                write_contract_address(stringify!($contract), instance.address())
-                    .with_context(|| format!("failed to write contract address for {}", NAME))?;
-
-                log::info!("deployed {} to {:?}", NAME, instance.address());
-                instance
-            }};
-        }
+    log::info!("deploying contracts");
+    let deployment = Deployment::deploy(&web3, 8_000_000.into()).await?;
+    for (name, address) in deployment.addresses() {
+        log::debug!(
+            "writing deployment to {}",
+            paths::contract_address_file(name).display(),
+        );
+        write_contract_address(name, address)
+            .with_context(|| format!("failed to write contract address for {}", name))?;
 
-    log::info!("deploying library contracts");
-    let bi_map = deploy!(IdToAddressBiMap);
-    let iterable_set = deploy!(IterableAppendOnlySet);
-
-    log::info!("deploying fee token contracts");
-    let owl = deploy!(TokenOWL);
-    let owl_proxy = deploy!(TokenOWLProxy(owl.address()));
-
-    log::info!("deploying exchange and viewer contracts");
-    let exchange = deploy!(BatchExchange(
-        batch_exchange::Libraries {
-            id_to_address_bi_map: bi_map.address(),
-            iterable_append_only_set: iterable_set.address(),
-        },
-        u16::max_value().into(),
-        owl_proxy.address(),
-    ));
-    deploy!(BatchExchangeViewer(exchange.address()));
+        log::info!("deployed {} to {:?}", name, address);
+    }
 
     touch_build_script()
 }
@@ -107,7 +80,7 @@ fn touch_build_script() -> Result<()> {
 
 /// Waits for the local development node to become available. Returns an error
 /// if the node does not become available after a certain amount of time.
-async fn wait_for_node(web3: &Web3<Http>) -> Result<()> {
+async fn wait_for_node(web3: &DynWeb3) -> Result<()> {
     const NODE_READY_TIMEOUT: Duration = Duration::from_secs(30);
     const NODE_READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
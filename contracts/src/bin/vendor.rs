@@ -23,6 +23,7 @@ const ARTIFACTS: &[(&str, &[&str])] = &[
         "@openzeppelin/contracts@2.5.0",
         &["ERC20Mintable", "IERC20"],
     ),
+    ("canonical-weth@1.4.0", &["WETH9"]),
 ];
 
 fn main() {
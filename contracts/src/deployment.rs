@@ -0,0 +1,98 @@
+//! Module for deploying the exchange contracts and their dependencies to a
+//! test network. This is shared by the `deploy` script and the e2e tests so
+//! that both deploy the contracts in the same order, which makes a fresh
+//! deterministic test node end up with the same contract addresses.
+
+use crate::*;
+use ethcontract::{dyns::DynWeb3, errors::DeployError, Address, U256};
+use std::{error::Error, fmt};
+
+/// The contracts deployed to a test network.
+pub struct Deployment {
+    pub id_to_address_bi_map: IdToAddressBiMap,
+    pub iterable_append_only_set: IterableAppendOnlySet,
+    pub token_owl: TokenOWL,
+    /// The OWL fee token proxy, which is the token with id 0 on the exchange.
+    pub token_owl_proxy: TokenOWLProxy,
+    pub batch_exchange: BatchExchange,
+    pub batch_exchange_viewer: BatchExchangeViewer,
+}
+
+impl Deployment {
+    /// Deploys the contracts, each with the specified amount of gas.
+    pub async fn deploy(web3: &DynWeb3, gas: U256) -> Result<Self, DeploymentError> {
+        macro_rules! deploy {
+            ($contract:ident) => { deploy!($contract ()) };
+            ($contract:ident ( $($param:expr),* $(,)? )) => {
+                $contract::builder(web3 $(, $param)*)
+                    .gas(gas)
+                    .deploy()
+                    .await
+                    .map_err(|source| DeploymentError {
+                        contract: stringify!($contract),
+                        source,
+                    })?
+            };
+        }
+
+        let id_to_address_bi_map = deploy!(IdToAddressBiMap);
+        let iterable_append_only_set = deploy!(IterableAppendOnlySet);
+
+        let token_owl = deploy!(TokenOWL);
+        let token_owl_proxy = deploy!(TokenOWLProxy(token_owl.address()));
+
+        let batch_exchange = deploy!(BatchExchange(
+            batch_exchange::Libraries {
+                id_to_address_bi_map: id_to_address_bi_map.address(),
+                iterable_append_only_set: iterable_append_only_set.address(),
+            },
+            u16::max_value().into(),
+            token_owl_proxy.address(),
+        ));
+        let batch_exchange_viewer = deploy!(BatchExchangeViewer(batch_exchange.address()));
+
+        Ok(Deployment {
+            id_to_address_bi_map,
+            iterable_append_only_set,
+            token_owl,
+            token_owl_proxy,
+            batch_exchange,
+            batch_exchange_viewer,
+        })
+    }
+
+    /// The names of the deployed contracts with their addresses, in the order
+    /// they were deployed.
+    pub fn addresses(&self) -> Vec<(&'static str, Address)> {
+        vec![
+            ("IdToAddressBiMap", self.id_to_address_bi_map.address()),
+            (
+                "IterableAppendOnlySet",
+                self.iterable_append_only_set.address(),
+            ),
+            ("TokenOWL", self.token_owl.address()),
+            ("TokenOWLProxy", self.token_owl_proxy.address()),
+            ("BatchExchange", self.batch_exchange.address()),
+            ("BatchExchangeViewer", self.batch_exchange_viewer.address()),
+        ]
+    }
+}
+
+/// An error deploying one of the contracts.
+#[derive(Debug)]
+pub struct DeploymentError {
+    pub contract: &'static str,
+    pub source: DeployError,
+}
+
+impl fmt::Display for DeploymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to deploy {}", self.contract)
+    }
+}
+
+impl Error for DeploymentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(feature = "bin")]
 pub mod paths;
 
+pub mod deployment;
+
 pub use ethcontract;
 
 include!(concat!(env!("OUT_DIR"), "/BatchExchange.rs"));
@@ -12,3 +14,4 @@ include!(concat!(env!("OUT_DIR"), "/IterableAppendOnlySet.rs"));
 include!(concat!(env!("OUT_DIR"), "/SolutionSubmitter.rs"));
 include!(concat!(env!("OUT_DIR"), "/TokenOWL.rs"));
 include!(concat!(env!("OUT_DIR"), "/TokenOWLProxy.rs"));
+include!(concat!(env!("OUT_DIR"), "/WETH9.rs"));
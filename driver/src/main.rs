@@ -1,34 +1,73 @@
-use services_core::contracts::{stablex_contract::StableXContractImpl, web3_provider, Web3};
+use services_core::config::{
+    self, duration_millis, duration_secs, ConfigOptions, EconomicViabilityOptions, OrderbookOptions,
+};
+use services_core::contracts::{
+    exchange_client::{ExchangeClient as _, ExchangeClientImpl},
+    private_key::{read_private_key, PrivateKeyOptions},
+    stablex_contract::{CachedStableXContract, StableXContract as _, StableXContractImpl},
+    web3_provider,
+    wrapped_native_token::WrappedNativeTokenImpl,
+    Signer, Web3,
+};
 use services_core::driver::{
-    scheduler::{AuctionTimingConfiguration, SchedulerKind},
-    stablex_driver::StableXDriverImpl,
+    alerting::{AlertRules, Alerting, WebhookAlertSender},
+    balance_monitor::BalanceMonitor,
+    batch_clock::BatchClock,
+    fee_compounding::FeeCompounding,
+    fee_funds::FeeFundsManager,
+    relayer::Relayer,
+    scheduler::{AuctionTimingConfiguration, Scheduler, SchedulerKind},
+    solution_ranking::{self, SolutionRankingPolicy},
+    stablex_driver::{CircuitBreakerConfig, SkipBatchPolicy, StableXDriverImpl},
+    submission_receipts::SubmissionReceipts,
 };
-use services_core::economic_viability::EconomicViabilityStrategy;
-use services_core::gas_price::{self, GasEstimatorType, GasPriceEstimating};
+use services_core::gas_price::{self, GasEstimateFeedback, GasEstimatorType, GasPriceEstimating};
 use services_core::health::{HealthReporting, HttpHealthEndpoint};
-use services_core::http::HttpFactory;
+use services_core::history::events::EventRegistry;
+use services_core::http::{
+    CircuitBreakerOptions, HttpFactory, HttpPoolOptions, HttpProfiles, HttpProxyOptions,
+};
 use services_core::http_server::{DefaultRouter, RouilleServer, Serving};
 use services_core::logging;
 use services_core::metrics::{HttpMetrics, MetricsHandler, SolverMetrics, StableXMetrics};
+use services_core::models::batch_id::BatchTiming;
 use services_core::orderbook::{
-    EventBasedOrderbook, FilteredOrderbookReader, OrderbookFilter, StableXOrderBookReading,
+    streamed::checkpoint::CheckpointFile, EventBasedOrderbook, FilteredOrderbookReader,
+    StableXOrderBookReading,
 };
 use services_core::price_estimation::PriceOracle;
-use services_core::price_finding::{self, Fee, InternalOptimizer, SolverType};
-use services_core::solution_submission::{CustomBenignErrors, StableXSolutionSubmitter};
+use services_core::price_finding::{
+    self, Fee, GrpcSolver, InternalOptimizer, SolverPlugin, SolverType,
+};
+use services_core::solution_submission::{
+    CustomBenignErrors, ReplacementPolicy, RoundRobinSolutionSubmitter, StableXSolutionSubmitter,
+    StableXSolutionSubmitting,
+};
+use services_core::startup::StartupProgress;
 use services_core::token_info::hardcoded::TokenData;
+use services_core::transport::RetryPolicy;
 use services_core::util::FutureWaitExt as _;
 
-use ethcontract::PrivateKey;
-use log::info;
+use ethcontract::Address;
+use futures::future;
+use log::{error, info, warn};
 use prometheus::Registry;
-use std::num::ParseIntError;
+use std::convert::TryFrom;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
 use url::Url;
 
+/// The interval in which the startup progress is logged while initializing.
+const STARTUP_PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of solution submission receipts served by the monitoring server.
+const RECENT_SUBMISSION_RECEIPTS: usize = 100;
+
+/// How often the fallback orderbook fetches new events while it is not used.
+const FALLBACK_ORDERBOOK_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "driver",
@@ -48,6 +87,8 @@ struct Options {
 
     /// The Ethereum node URL to connect to. Make sure that the node allows for
     /// queries without a gas limit to be able to fetch the orderbook.
+    /// `ws://` and `ipc://` URLs connect over a WebSocket or IPC socket, which
+    /// lets the node push new blocks instead of being polled for them.
     #[structopt(short, long, env = "NODE_URL")]
     node_url: Url,
 
@@ -56,7 +97,9 @@ struct Options {
     /// 'StandardSolver' for mixed integer programming solver;
     /// 'FallbackSolver' for a more conservative solver than the standard solver;
     /// 'BestRingSolver' for a solver searching only for the best ring;
-    /// 'OpenSolver' for the open-source solver
+    /// 'OpenSolver' for the open-source solver;
+    /// 'PluginSolver' for a solver plugin loaded from a shared library;
+    /// 'GrpcSolver' for a remote solver served over gRPC
     #[structopt(
         long,
         env = "SOLVER_TYPE",
@@ -77,7 +120,67 @@ struct Options {
     )]
     solver_internal_optimizer: InternalOptimizer,
 
+    /// The maximum relative deviation, in [0, 1), of token prices in solutions
+    /// from their prices of external sources (e.g. 0.5 allows prices between
+    /// half and one and a half times the external price). The bounds are
+    /// passed to the solver as `priceBounds`. Prices are not bounded if unset.
+    #[structopt(long, env = "SOLVER_PRICE_BOUNDS_DEVIATION")]
+    solver_price_bounds_deviation: Option<f64>,
+
+    /// Path to the shared library of the solver plugin that is run by the
+    /// "PluginSolver" solver type. The plugin is run in a separate process for
+    /// every batch, which is killed once the time limit is exceeded.
+    #[structopt(long, env = "SOLVER_PLUGIN", parse(from_os_str))]
+    solver_plugin: Option<PathBuf>,
+
+    /// The memory limit in MiB of the solver plugin process, which is also
+    /// passed to the solver plugin.
+    #[structopt(long, env = "SOLVER_PLUGIN_MEMORY_LIMIT", default_value = "4096")]
+    solver_plugin_memory_limit: u64,
+
+    /// URL of the remote solver that is used by the "GrpcSolver" solver type.
+    #[structopt(long, env = "SOLVER_GRPC_URL")]
+    solver_grpc_url: Option<Url>,
+
+    /// Comma separated solver types that race the solver for every batch. The
+    /// solution to submit is selected among the valid solutions of all solvers
+    /// with the solution ranking policy.
+    #[structopt(
+        long,
+        env = "RACING_SOLVER_TYPES",
+        possible_values = SolverType::variant_names(),
+        case_insensitive = true,
+        use_delimiter = true
+    )]
+    racing_solver_types: Vec<SolverType>,
+
+    /// How the solution to submit is selected among the solutions of racing
+    /// solvers. Can be one of:
+    /// 'ObjectiveValue' for the highest objective value;
+    /// 'FewestTouchedOrders' for the fewest touched orders among the solutions
+    /// with an objective value within the solution ranking tolerance.
+    #[structopt(
+        long,
+        env = "SOLUTION_RANKING_POLICY",
+        default_value = "ObjectiveValue",
+        possible_values = SolutionRankingPolicy::variant_names(),
+        case_insensitive = true,
+    )]
+    solution_ranking_policy: SolutionRankingPolicy,
+
+    /// The relative tolerance below the highest objective value of the
+    /// 'FewestTouchedOrders' solution ranking policy.
+    #[structopt(long, env = "SOLUTION_RANKING_TOLERANCE", default_value = "0.01")]
+    solution_ranking_tolerance: f64,
+
+    /// Comma separated token ids that submitted solutions must not touch. This
+    /// applies with and without racing solvers.
+    #[structopt(long, env = "SOLUTION_RANKING_FLAGGED_TOKENS", use_delimiter = true)]
+    solution_ranking_flagged_tokens: Vec<u16>,
+
     /// JSON encoded backup token information to provide to the solver.
+    /// The optional `priceSources` restrict which price sources are used for a
+    /// token (any of hardcoded, pricegraph, kraken, dexag and oneinch).
     ///
     /// For example: '{
     ///   "T0001": {
@@ -85,6 +188,7 @@ struct Options {
     ///     "alias": "WETH",
     ///     "decimals": 18,
     ///     "externalPrice": 200000000000000000000,
+    ///     "priceSources": ["kraken", "pricegraph"],
     ///   },
     ///   "T0004": {
     ///     "address": "0x0000000000000000000000000000000000000000",
@@ -96,27 +200,19 @@ struct Options {
     #[structopt(long, env = "TOKEN_DATA", default_value = "{}")]
     token_data: TokenData,
 
-    /// JSON encoded object of which tokens/orders to ignore.
-    ///
-    /// For example: '{
-    ///   "tokens": {"Whitelist": [1, 2]},
-    ///   "users": {
-    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0A": { "OrderIds": [0, 1] },
-    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0B": "All"
-    ///   }
-    ///  }'
-    /// More examples can be found in the tests of orderbook/filtered_orderboook.rs
-    #[structopt(long, env = "ORDERBOOK_FILTER", default_value = "{}")]
-    orderbook_filter: OrderbookFilter,
+    #[structopt(flatten)]
+    private_key: PrivateKeyOptions,
 
-    /// The private key used by the driver to sign transactions.
-    #[structopt(short = "k", long, env = "PRIVATE_KEY", hide_env_values = true)]
-    private_key: PrivateKey,
+    /// The URL of an external signer that transactions are sent to unsigned
+    /// with `eth_sendTransaction`. The signer signs them for the external
+    /// signer account and forwards them to the node. No private key is needed
+    /// when this is set.
+    #[structopt(long, env = "EXTERNAL_SIGNER_URL")]
+    external_signer_url: Option<Url>,
 
-    /// Specify the number of blocks to fetch events for at a time for
-    /// constructing the orderbook for the solver.
-    #[structopt(long, env = "AUCTION_DATA_PAGE_SIZE", default_value = "500")]
-    auction_data_page_size: usize,
+    /// The account whose key the external signer signs transactions with.
+    #[structopt(long, env = "EXTERNAL_SIGNER_ACCOUNT")]
+    external_signer_account: Option<Address>,
 
     /// The timeout in milliseconds of web3 JSON RPC calls, defaults to 10000ms
     #[structopt(
@@ -127,6 +223,37 @@ struct Options {
     )]
     rpc_timeout: Duration,
 
+    /// The maximum number of attempts for web3 JSON RPC calls that fail with
+    /// a transient error such as a timeout or an HTTP 429 or 5xx status. A
+    /// value of 1 disables retries.
+    #[structopt(long, env = "RPC_MAX_ATTEMPTS", default_value = "3")]
+    rpc_max_attempts: u32,
+
+    /// The backoff in milliseconds before retrying a failed web3 JSON RPC
+    /// call for the first time. The backoff doubles for every further retry.
+    #[structopt(
+        long,
+        env = "RPC_INITIAL_BACKOFF",
+        default_value = "200",
+        parse(try_from_str = duration_millis),
+    )]
+    rpc_initial_backoff: Duration,
+
+    /// The maximum backoff in milliseconds between retries of a failed web3
+    /// JSON RPC call.
+    #[structopt(
+        long,
+        env = "RPC_MAX_BACKOFF",
+        default_value = "5000",
+        parse(try_from_str = duration_millis),
+    )]
+    rpc_max_backoff: Duration,
+
+    /// The maximum number of web3 JSON RPC retries per batch. Once exhausted,
+    /// transient errors are no longer retried until the next batch starts.
+    #[structopt(long, env = "RPC_RETRY_BUDGET_PER_BATCH", default_value = "50")]
+    rpc_retry_budget_per_batch: usize,
+
     /// The default timeout in milliseconds of HTTP requests to remote services
     /// such as the Gnosis Safe gas station and exchange REST APIs for fetching
     /// price estimates.
@@ -138,6 +265,22 @@ struct Options {
     )]
     http_timeout: Duration,
 
+    #[structopt(flatten)]
+    http_pool: HttpPoolOptions,
+
+    #[structopt(flatten)]
+    http_circuit_breaker: CircuitBreakerOptions,
+
+    /// JSON encoded HTTP client profiles of the remote services (`node`,
+    /// `gasStation`, `priceApi` and `alerting`) with an optional timeout in
+    /// seconds, number of retries, headers and proxy URL, for example
+    /// '{"gasStation": {"timeout": 2, "retries": 1}}'.
+    #[structopt(long, env = "HTTP_PROFILES", default_value = "{}")]
+    http_profiles: HttpProfiles,
+
+    #[structopt(flatten)]
+    http_proxy: HttpProxyOptions,
+
     /// The offset from the start of a batch in seconds at which point we
     /// should start solving.
     #[structopt(
@@ -175,36 +318,8 @@ struct Options {
     #[structopt(long, env = "ECONOMIC_VIABILITY_SUBSIDY_FACTOR", default_value = "1.0")]
     economic_viability_subsidy_factor: f64,
 
-    /// We multiply the economically viable min average fee by this amount to ensure that if a
-    /// solution has this minimum amount it will still be end up economically viable even when the
-    /// gas or native token price moves slightly between solution computation and submission.
-    #[structopt(
-        long,
-        env = "ECONOMIC_VIABILITY_MIN_AVG_FEE_FACTOR",
-        default_value = "1.1"
-    )]
-    economic_viability_min_avg_fee_factor: f64,
-
-    /// The static minimum average fee per order used for the Static strategy.
-    #[structopt(long, env = "STATIC_MIN_AVG_FEE_PER_ORDER")]
-    static_min_avg_fee_per_order: Option<u128>,
-
-    /// The static max gas price fee per order used for the Static strategy.
-    #[structopt(long, env = "STATIC_MAX_GAS_PRICE")]
-    static_max_gas_price: Option<u128>,
-
-    /// How to calculate the economic viability constraints.
-    /// `Static`: Use fallback_min_avg_fee_per_order and fallback_max_gas_price.
-    /// `Dynamic`: Use current native token price, gas price and subsidy factor.
-    /// `Combined`: Use the better (lower min-avg-fee) of the above.
-    #[structopt(
-        long,
-        env = "ECONOMIC_VIABILITY_STRATEGY",
-        default_value = "Dynamic",
-        possible_values = EconomicViabilityStrategy::variant_names(),
-        case_insensitive = true,
-    )]
-    economic_viability_strategy: EconomicViabilityStrategy,
+    #[structopt(flatten)]
+    economic_viability: EconomicViabilityOptions,
 
     /// The kind of scheduler to use.
     #[structopt(
@@ -216,6 +331,39 @@ struct Options {
     )]
     scheduler: SchedulerKind,
 
+    /// What to do when the orderbook for a batch cannot be fetched: retry with
+    /// backoff while there is time left for solving, read the orderbook from
+    /// the node at `--orderbook-fallback-node-url` or skip the batch. If not
+    /// set the batch fails and is retried by the scheduler.
+    #[structopt(
+        long,
+        env = "SKIP_BATCH_POLICY",
+        possible_values = SkipBatchPolicy::variant_names(),
+        case_insensitive = true,
+    )]
+    skip_batch_policy: Option<SkipBatchPolicy>,
+
+    /// The number of consecutive batches failing at the same processing stage,
+    /// for example because solution verification reverts, after which the
+    /// driver skips batches for a cool-down period and reports itself as not
+    /// ready. Disabled if not set.
+    #[structopt(long, env = "CIRCUIT_BREAKER_THRESHOLD")]
+    circuit_breaker_threshold: Option<usize>,
+
+    /// The number of batches skipped when the circuit breaker opens.
+    #[structopt(long, env = "CIRCUIT_BREAKER_COOLDOWN", default_value = "1")]
+    circuit_breaker_cooldown: u32,
+
+    /// The maximum number of batches skipped by the circuit breaker. The
+    /// cool-down doubles every time the first batch after a cool-down fails.
+    #[structopt(long, env = "CIRCUIT_BREAKER_MAX_COOLDOWN", default_value = "12")]
+    circuit_breaker_max_cooldown: u32,
+
+    /// The Ethereum node URL used for building the fallback orderbook for the
+    /// `Fallback` skip batch policy.
+    #[structopt(long, env = "ORDERBOOK_FALLBACK_NODE_URL")]
+    orderbook_fallback_node_url: Option<Url>,
+
     /// Time interval in seconds in which price sources should be updated.
     #[structopt(
         long,
@@ -225,10 +373,8 @@ struct Options {
     )]
     price_source_update_interval: Duration,
 
-    /// Use an orderbook file for persisting an event cache in order to speed up
-    /// the startup time.
-    #[structopt(long, env = "ORDERBOOK_FILE", parse(from_os_str))]
-    orderbook_file: Option<PathBuf>,
+    #[structopt(flatten)]
+    orderbook: OrderbookOptions,
 
     /// ID for the token which is used to pay network transaction fees on the
     /// target chain (e.g. WETH on mainnet, DAI on xDAI).
@@ -245,12 +391,25 @@ struct Options {
     )]
     use_external_price_source: bool,
 
+    /// Whether to keep Kraken prices up to date through a WebSocket ticker
+    /// subscription instead of only polling the REST API every price source
+    /// update interval.
+    #[structopt(
+        long,
+        env = "USE_KRAKEN_WEBSOCKET",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    use_kraken_websocket: bool,
+
     /// Which gas estimators to use. Multiple estimators are used in sequence if a previous one
     /// fails. Individual estimators support different networks.
     /// `EthGasStation`: supports mainnet.
     /// `GasNow`: supports mainnet.
+    /// `GasNowWebSocket`: supports mainnet, keeps streamed GasNow prices in memory.
     /// `GnosisSafe`: supports mainnet and rinkeby.
     /// `Web3`: supports every network.
+    /// `BlockPercentile`: supports every network, uses the gas prices in recent blocks.
     #[structopt(
         long,
         env = "GAS_ESTIMATORS",
@@ -279,119 +438,625 @@ struct Options {
         default_value = "[]"
     )]
     custom_benign_errors: CustomBenignErrors,
+
+    /// The minimum time in seconds between replacements of a pending solution
+    /// transaction with a higher gas price.
+    #[structopt(
+        long,
+        env = "MIN_REPLACEMENT_INTERVAL",
+        default_value = "0",
+        parse(try_from_str = duration_secs),
+    )]
+    min_replacement_interval: Duration,
+
+    /// The minimum relative increase of the estimated gas price, for example
+    /// 0.1 for 10%, before a pending solution transaction is replaced.
+    #[structopt(long, env = "MIN_REPLACEMENT_GAS_PRICE_INCREASE", default_value = "0")]
+    min_replacement_gas_price_increase: f64,
+
+    /// The relative margin, for example 0.2 for 20%, that is added to the gas
+    /// used by the simulated submission of a solution for the gas limit of the
+    /// submission transaction.
+    #[structopt(long, env = "SOLUTION_GAS_LIMIT_MARGIN", default_value = "0.5")]
+    solution_gas_limit_margin: f64,
+
+    /// The minimum balance in wei of the native token each submitting account
+    /// should have. The driver reports itself as not ready and logs warnings
+    /// while the balance of any of them is below this threshold.
+    #[structopt(
+        long,
+        env = "MIN_ACCOUNT_BALANCE",
+        default_value = "100000000000000000"
+    )]
+    min_account_balance: u128,
+
+    /// Time interval in seconds in which the balances of the submitting
+    /// accounts are checked.
+    #[structopt(
+        long,
+        env = "ACCOUNT_BALANCE_CHECK_INTERVAL",
+        default_value = "60",
+        parse(try_from_str = duration_secs),
+    )]
+    account_balance_check_interval: Duration,
+
+    /// The maximum drift in seconds of the system time relative to the chain
+    /// time. Beyond that the system scheduler follows the chain time and the
+    /// driver reports itself as not ready.
+    #[structopt(
+        long,
+        env = "MAX_CLOCK_DRIFT",
+        default_value = "30",
+        parse(try_from_str = duration_secs),
+    )]
+    max_clock_drift: Duration,
+
+    /// Time interval in seconds in which the drift of the system time is
+    /// measured.
+    #[structopt(
+        long,
+        env = "CLOCK_DRIFT_CHECK_INTERVAL",
+        default_value = "60",
+        parse(try_from_str = duration_secs),
+    )]
+    clock_drift_check_interval: Duration,
+
+    /// The number of randomly sampled orderbook balances that are compared to
+    /// the balances stored in the exchange contract in order to detect bugs in
+    /// applying events. Set to 0 to disable the reconciliation.
+    #[structopt(long, env = "BALANCE_RECONCILIATION_SAMPLE_SIZE", default_value = "20")]
+    balance_reconciliation_sample_size: usize,
+
+    /// Time interval in seconds in which orderbook balances are reconciled
+    /// with the on-chain state.
+    #[structopt(
+        long,
+        env = "BALANCE_RECONCILIATION_INTERVAL",
+        default_value = "600",
+        parse(try_from_str = duration_secs),
+    )]
+    balance_reconciliation_interval: Duration,
+
+    /// The fraction of the earned fee token balance that is periodically sold
+    /// for the wrapped native token on the exchange, withdrawn and unwrapped,
+    /// so that the submitting account stays funded. Disabled if not set.
+    #[structopt(long, env = "FEE_COMPOUNDING_PORTION")]
+    fee_compounding_portion: Option<f64>,
+
+    /// Time interval in seconds in which earned fees are compounded.
+    #[structopt(
+        long,
+        env = "FEE_COMPOUNDING_INTERVAL",
+        default_value = "3600",
+        parse(try_from_str = duration_secs),
+    )]
+    fee_compounding_interval: Duration,
+
+    /// Path to a file containing the hex encoded private key of the account
+    /// that relays withdrawals for users. The relayer is disabled if not set.
+    /// It uses a separate account so that relayed transactions do not
+    /// interfere with the nonces of solution submissions.
+    #[structopt(long, env = "RELAYER_PRIVATE_KEY_FILE", parse(from_os_str))]
+    relayer_private_key_file: Option<PathBuf>,
+
+    /// The port on which the relayer accepts requests.
+    #[structopt(long, env = "RELAYER_PORT", default_value = "8081")]
+    relayer_port: u16,
+
+    /// The minimum time in seconds between two transactions relayed for the
+    /// same user.
+    #[structopt(
+        long,
+        env = "RELAYER_RATE_LIMIT",
+        default_value = "3600",
+        parse(try_from_str = duration_secs),
+    )]
+    relayer_rate_limit: Duration,
+
+    /// The maximum gas price in wei that relayed transactions are sent with.
+    /// The gas price of a pending transaction is increased up to this price
+    /// until it is mined.
+    #[structopt(long, env = "RELAYER_MAX_GAS_PRICE", default_value = "500000000000")]
+    relayer_max_gas_price: u128,
+
+    /// The URL of a webhook to which alerts are posted as JSON. Alerting is
+    /// disabled if it is not set.
+    #[structopt(long, env = "ALERT_WEBHOOK_URL")]
+    alert_webhook_url: Option<Url>,
+
+    /// JSON encoded object of the alert rules that are evaluated after every
+    /// batch.
+    ///
+    /// For example: '{
+    ///   "maxBatchesWithoutSolution": 12,
+    ///   "maxObjectiveValueDrop": 0.5,
+    ///   "trailingSolutions": 20,
+    ///   "maxGasCost": 0.1
+    /// }'
+    /// The maximum gas cost is in units of the native token.
+    #[structopt(long, env = "ALERT_RULES", default_value = "{}")]
+    alert_rules: AlertRules,
+
+    /// Path of a file to which the receipts of submitted solutions are
+    /// appended as JSON lines. The most recent receipts are always served on
+    /// the `/submissions` monitoring endpoint.
+    #[structopt(long, env = "SUBMISSION_RECEIPTS_FILE", parse(from_os_str))]
+    submission_receipts_file: Option<PathBuf>,
+
+    /// Print the orderbook state recovered from the orderbook file as JSON and
+    /// exit. This is useful for debugging the event based orderbook.
+    #[structopt(long)]
+    dump_state: bool,
+
+    #[structopt(flatten)]
+    config: ConfigOptions,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Withdraws the fee token earned by the solver account from the exchange.
+    /// Every run either claims a matured withdrawal or requests the withdrawal
+    /// of the current fee token balance.
+    FeeFunds {
+        /// Only print the current balances and the action that would be taken
+        /// without sending any transactions.
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// The number of batches after which a requested withdrawal can be
+        /// claimed. The fees remain available for trading until then.
+        #[structopt(long, default_value = "0")]
+        withdraw_delay_batches: u32,
+    },
 }
 
 fn main() {
-    let options = Options::from_args();
+    price_finding::solver_plugin::run_host_if_requested();
+    let options: Options = config::from_args();
+    if options.config.print_config {
+        println!("{:#?}", options);
+        return;
+    }
+    if options.dump_state {
+        dump_state(&options);
+        return;
+    }
     let (_, _guard) = logging::init(&options.log_filter);
+    if let Some(Command::FeeFunds {
+        dry_run,
+        withdraw_delay_batches,
+    }) = options.command
+    {
+        manage_fee_funds(&options, withdraw_delay_batches, dry_run);
+        return;
+    }
     info!("Starting driver with runtime options: {:#?}", options);
 
+    // The orderbook file is shared with the monitoring server, which serves
+    // it as a checkpoint.
+    let checkpoint_file = options.orderbook.checkpoint_file();
+
     // Set up metrics and health monitoring and serve in separate thread.
-    let (stablex_metrics, http_metrics, solver_metrics, health) = setup_monitoring();
-
-    // Set up shared HTTP client and HTTP services.
-    let http_factory = HttpFactory::new(options.http_timeout, http_metrics);
-    let (web3, gas_station) = setup_http_services(&http_factory, &options).wait();
-
-    // Set up connection to exchange contract
-    let contract = Arc::new(
-        StableXContractImpl::new(
-            &web3,
-            options.private_key.clone(),
-            options.use_solution_submitter,
-        )
-        .wait()
-        .unwrap(),
-    );
+    let (
+        stablex_metrics,
+        http_metrics,
+        solver_metrics,
+        health,
+        startup_progress,
+        submission_receipts,
+    ) = setup_monitoring(&options, checkpoint_file.clone());
+    startup_progress
+        .clone()
+        .start_logging(STARTUP_PROGRESS_LOG_INTERVAL);
+
+    // Set up shared HTTP client.
+    let http_factory = HttpFactory::new(
+        options.http_timeout,
+        options.http_pool,
+        options.http_circuit_breaker,
+        http_metrics,
+    )
+    .with_profiles(options.http_profiles.clone())
+    .with_proxy(options.http_proxy.clone());
+
+    // The setup runs as a single future so that independent steps are
+    // performed concurrently. The scheduler blocks so it is started afterwards.
+    let mut scheduler = setup_scheduler(
+        options,
+        http_factory,
+        stablex_metrics,
+        solver_metrics,
+        health,
+        startup_progress,
+        submission_receipts,
+        checkpoint_file,
+    )
+    .wait();
+    scheduler.start();
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn setup_scheduler(
+    options: Options,
+    http_factory: HttpFactory,
+    stablex_metrics: Arc<StableXMetrics>,
+    solver_metrics: SolverMetrics,
+    health: Arc<dyn HealthReporting>,
+    startup_progress: Arc<StartupProgress>,
+    submission_receipts: Arc<SubmissionReceipts>,
+    checkpoint_file: Option<Arc<CheckpointFile>>,
+) -> Box<dyn Scheduler> {
+    let web3 = web3_provider(
+        &http_factory,
+        options.node_url.as_str(),
+        options.rpc_timeout,
+        retry_policy(&options),
+    )
+    .unwrap();
+
+    // The gas estimators and the exchange contract only depend on the node.
+    let (gas_station, contract) =
+        startup_progress
+            .step("connect to node", async {
+                futures::join!(
+                    gas_price::create_priority_estimator(
+                        &http_factory,
+                        &web3,
+                        &options.gas_estimators,
+                    ),
+                    StableXContractImpl::new(
+                        &web3,
+                        setup_signer(&http_factory, &options),
+                        options.use_solution_submitter,
+                    ),
+                )
+            })
+            .await;
+    let gas_station = gas_station.unwrap();
+    let gas_estimate_feedback = Arc::new(GasEstimateFeedback::new(
+        stablex_metrics.clone(),
+        gas_station.clone(),
+    ));
+    let gas_station: Arc<dyn GasPriceEstimating> = gas_station;
+    let contract = Arc::new(contract.unwrap());
     info!("Using contract at {:?}", contract.address());
     info!("Using account {:?}", contract.account());
+    info!(
+        "Using batch duration of {}s",
+        contract.batch_duration().as_secs()
+    );
 
-    info!("Orderbook filter: {:?}", options.orderbook_filter);
-    let orderbook = Arc::new(FilteredOrderbookReader::new(
-        Box::new(EventBasedOrderbook::new(
-            contract.clone(),
-            web3,
-            options.auction_data_page_size,
-            options.orderbook_file,
-        )),
-        options.orderbook_filter.clone(),
+    let batch_clock = Arc::new(BatchClock::new(
+        contract.clone(),
+        options.max_clock_drift,
+        health.clone(),
+        stablex_metrics.clone(),
     ));
+    batch_clock
+        .clone()
+        .start_in_background(options.clock_drift_check_interval);
+
+    let cached_contract = Arc::new(CachedStableXContract::new(contract.clone()));
+    let mut event_based_orderbook = EventBasedOrderbook::new(
+        contract.clone(),
+        web3.clone(),
+        options.orderbook.auction_data_page_size,
+        options.orderbook.orderbook_file.clone(),
+        options.orderbook.orderbook_reindex_from_block,
+    )
+    .with_event_buffer_size(options.orderbook.orderbook_event_buffer_size)
+    .with_metrics(stablex_metrics.clone())
+    .with_startup_progress(startup_progress.clone())
+    .with_cached_contract(cached_contract.clone());
+    if let Some(event_sink) = options
+        .orderbook
+        .event_sink()
+        .expect("failed to create event sink")
+    {
+        event_based_orderbook = event_based_orderbook.with_event_sink(event_sink);
+    }
+    if let Some(checkpoint_source) = options
+        .orderbook
+        .checkpoint_source(&http_factory)
+        .expect("failed to create orderbook checkpoint source")
+    {
+        event_based_orderbook = event_based_orderbook.with_checkpoint_source(checkpoint_source);
+    }
+    if let Some(checkpoint_file) = checkpoint_file {
+        event_based_orderbook = event_based_orderbook.with_checkpoint_file(checkpoint_file);
+    }
+    let event_based_orderbook = Arc::new(event_based_orderbook);
+    if options.balance_reconciliation_sample_size > 0 {
+        event_based_orderbook.clone().start_balance_reconciliation(
+            options.balance_reconciliation_interval,
+            options.balance_reconciliation_sample_size,
+            stablex_metrics.clone(),
+        );
+    }
+
+    info!("Orderbook filter: {:?}", options.orderbook.orderbook_filter);
+    let orderbook = Arc::new(
+        filtered_orderbook(Box::new(event_based_orderbook.clone()), &options)
+            .with_metrics(stablex_metrics.clone()),
+    );
 
     let price_oracle = Arc::new(
         PriceOracle::new(
             &http_factory,
             orderbook.clone(),
-            contract.clone(),
-            options.token_data,
+            cached_contract,
+            options.token_data.clone(),
             options.price_source_update_interval,
             options.native_token_id.into(),
             options.use_external_price_source,
+            options.use_kraken_websocket,
         )
         .unwrap(),
     );
 
+    if let Some(portion) = options.fee_compounding_portion {
+        let client = ExchangeClientImpl::new(&web3, setup_signer(&http_factory, &options))
+            .await
+            .unwrap();
+        let native_token = client
+            .token_address(options.native_token_id)
+            .await
+            .expect("failed to get native token address");
+        let wrapped_native_token =
+            WrappedNativeTokenImpl::new(&web3, setup_signer(&http_factory, &options), native_token)
+                .await
+                .unwrap();
+        FeeCompounding::new(
+            Arc::new(client),
+            event_based_orderbook,
+            Arc::new(wrapped_native_token),
+            price_oracle.clone(),
+            options.native_token_id.into(),
+            portion,
+        )
+        .start_in_background(options.fee_compounding_interval);
+    }
+
+    if let Some(path) = &options.relayer_private_key_file {
+        let private_key = read_private_key(path).expect("failed to load relayer private key");
+        let client = ExchangeClientImpl::new(&web3, private_key.into())
+            .await
+            .unwrap();
+        info!(
+            "Relaying withdrawals from account {:?} on port {}",
+            client.account(),
+            options.relayer_port
+        );
+        RouilleServer::new(Relayer::new(
+            Arc::new(client),
+            gas_station.clone(),
+            options.relayer_max_gas_price as f64,
+            options.relayer_rate_limit,
+        ))
+        .start_in_background_on_port(options.relayer_port);
+    }
+
     let economic_viability = options
-        .economic_viability_strategy
-        .from_arguments(
+        .economic_viability
+        .create(
             options.economic_viability_subsidy_factor,
-            options.economic_viability_min_avg_fee_factor,
-            options.static_min_avg_fee_per_order,
-            options.static_max_gas_price,
             price_oracle.clone(),
             gas_station.clone(),
         )
         .unwrap();
 
     // Setup price.
-    let price_finder = price_finding::create_price_finder(
-        Some(Fee::default()),
-        options.solver_type,
-        price_oracle,
-        options.solver_internal_optimizer,
-        solver_metrics,
-        stablex_metrics.clone(),
+    let create_price_finder = |solver_type, solver_metrics: SolverMetrics| {
+        let solver_plugin = match (solver_type, &options.solver_plugin) {
+            (SolverType::PluginSolver, Some(path)) => Some(
+                SolverPlugin::load(path, options.solver_plugin_memory_limit * 1024 * 1024)
+                    .expect("failed to load solver plugin"),
+            ),
+            _ => None,
+        };
+        let grpc_solver = match (solver_type, &options.solver_grpc_url) {
+            (SolverType::GrpcSolver, Some(url)) => {
+                Some(GrpcSolver::new(url).expect("failed to create gRPC solver"))
+            }
+            _ => None,
+        };
+        price_finding::create_price_finder(
+            Some(Fee::default()),
+            solver_type,
+            price_oracle.clone(),
+            options.solver_internal_optimizer,
+            solver_metrics,
+            stablex_metrics.clone(),
+            options.solver_price_bounds_deviation,
+            solver_plugin,
+            grpc_solver,
+            BatchTiming::with_batch_duration(contract.batch_duration()),
+        )
+    };
+    let price_finder = create_price_finder(options.solver_type, solver_metrics.clone());
+    let racing_price_finders = options
+        .racing_solver_types
+        .iter()
+        .enumerate()
+        .map(|(index, solver_type)| {
+            let solver_metrics =
+                solver_metrics.for_solver(format!("racing_{}_{}", index, solver_type));
+            create_price_finder(*solver_type, solver_metrics)
+        })
+        .collect::<Vec<_>>();
+
+    // Initializing the orderbook fetches the event history, which takes the
+    // longest, so the submitting accounts are set up in the meantime.
+    let fallback_orderbook = match options.skip_batch_policy {
+        Some(SkipBatchPolicy::Fallback) => {
+            Some(setup_fallback_orderbook(&http_factory, &options).await)
+        }
+        _ => None,
+    };
+    let (orderbook_initialization, fallback_orderbook_initialization, solution_submitter) = futures::join!(
+        startup_progress.step("initialize orderbook", orderbook.initialize()),
+        async {
+            match &fallback_orderbook {
+                Some(fallback_orderbook) => {
+                    startup_progress
+                        .step(
+                            "initialize fallback orderbook",
+                            fallback_orderbook.initialize(),
+                        )
+                        .await
+                }
+                None => Ok(()),
+            }
+        },
+        startup_progress.step(
+            "set up solution submitters",
+            setup_solution_submitter(
+                &web3,
+                contract.clone(),
+                gas_station,
+                &gas_estimate_feedback,
+                &stablex_metrics,
+                &options,
+            ),
+        ),
     );
+    let (solution_submitter, submitting_accounts) = solution_submitter;
+    BalanceMonitor::new(
+        Arc::new(web3.clone()),
+        submitting_accounts,
+        options.min_account_balance.into(),
+        health.clone(),
+        stablex_metrics.clone(),
+    )
+    .start_in_background(options.account_balance_check_interval);
 
-    // Set up solution submitter.
-    let solution_submitter = Arc::new(StableXSolutionSubmitter::new(
-        contract.clone(),
-        gas_station,
-        options.custom_benign_errors,
-    ));
+    orderbook_initialization.expect("primary orderbook initialization failed");
+    if let Err(err) = fallback_orderbook_initialization {
+        warn!(
+            "fallback orderbook initialization failed, retrying on next update: {:?}",
+            err
+        );
+    }
 
-    // Set up the driver and start the run-loop.
+    // Set up the driver and the scheduler running it.
     let driver = StableXDriverImpl::new(
         price_finder,
-        orderbook.clone(),
+        orderbook,
         solution_submitter,
         economic_viability,
         stablex_metrics,
     );
+    let driver = match options.skip_batch_policy {
+        Some(skip_batch_policy) => {
+            driver.with_skip_batch_policy(skip_batch_policy, fallback_orderbook)
+        }
+        None => driver,
+    };
+    let driver = driver
+        .with_submission_receipts(submission_receipts)
+        .with_racing_price_finders(
+            racing_price_finders,
+            solution_ranking::create(
+                options.solution_ranking_policy,
+                options.solution_ranking_tolerance,
+                &options.solution_ranking_flagged_tokens,
+            ),
+        );
+    let alerting = options.alert_webhook_url.as_ref().map(|url| {
+        let sender = WebhookAlertSender::new(&http_factory, url.clone())
+            .expect("failed to create alert webhook client");
+        Arc::new(Alerting::new(options.alert_rules.clone(), Arc::new(sender)))
+    });
+    let driver = match &alerting {
+        Some(alerting) => driver.with_alerting(alerting.clone()),
+        None => driver,
+    };
+    let driver = match options.circuit_breaker_threshold {
+        Some(failure_threshold) => driver.with_circuit_breaker(
+            CircuitBreakerConfig {
+                failure_threshold,
+                cooldown: options.circuit_breaker_cooldown,
+                max_cooldown: options.circuit_breaker_max_cooldown,
+            },
+            health.clone(),
+        ),
+        None => driver,
+    };
 
     let scheduler_config = AuctionTimingConfiguration::new(
+        BatchTiming::with_batch_duration(contract.batch_duration()),
         options.target_start_solve_time,
         options.latest_solution_submit_time,
         options.earliest_solution_submit_time,
     );
 
-    let mut scheduler =
-        options
-            .scheduler
-            .create(contract, Arc::new(driver), scheduler_config, health);
-    orderbook
-        .initialize()
+    let scheduler = options.scheduler.create(
+        contract,
+        Arc::new(driver),
+        scheduler_config,
+        health,
+        batch_clock,
+        alerting,
+    );
+    startup_progress.finished();
+    scheduler
+}
+
+/// Prints the state of the event based orderbook stored in the orderbook file.
+fn dump_state(options: &Options) {
+    let path = options
+        .orderbook
+        .orderbook_file
+        .as_ref()
+        .expect("dumping the state requires an orderbook file");
+    let events = EventRegistry::try_from(path.as_path()).expect("failed to read orderbook file");
+    let state = events.state().expect("failed to apply orderbook events");
+    println!("{}", state.to_debug_json().unwrap());
+}
+
+/// Prints the fee token balances of the solver account and withdraws earned
+/// fees.
+fn manage_fee_funds(options: &Options, withdraw_delay_batches: u32, dry_run: bool) {
+    let http_factory = HttpFactory::default().with_proxy(options.http_proxy.clone());
+    let web3 = web3_provider(
+        &http_factory,
+        options.node_url.as_str(),
+        options.rpc_timeout,
+        retry_policy(options),
+    )
+    .unwrap();
+    let client = ExchangeClientImpl::new(&web3, setup_signer(&http_factory, options))
         .wait()
-        .expect("primary orderbook initialization failed");
-    scheduler.start();
+        .expect("failed to connect to the exchange");
+    let manager = FeeFundsManager::new(Arc::new(client), withdraw_delay_batches, dry_run);
+
+    let status = manager.status().wait().expect("failed to fetch fee funds");
+    println!("{:#?}", status);
+    let action = manager.manage().wait().expect("failed to manage fee funds");
+    println!("{:?}", action);
 }
 
-fn setup_monitoring() -> (
+fn setup_monitoring(
+    options: &Options,
+    checkpoint_file: Option<Arc<CheckpointFile>>,
+) -> (
     Arc<StableXMetrics>,
     HttpMetrics,
     SolverMetrics,
     Arc<dyn HealthReporting>,
+    Arc<StartupProgress>,
+    Arc<SubmissionReceipts>,
 ) {
     let health = Arc::new(HttpHealthEndpoint::new());
+    let startup_progress = Arc::new(StartupProgress::new(health.clone()));
+    let submission_receipts = SubmissionReceipts::new(RECENT_SUBMISSION_RECEIPTS);
+    let submission_receipts = Arc::new(match &options.submission_receipts_file {
+        Some(path) => submission_receipts.with_archive(path.clone()),
+        None => submission_receipts,
+    });
 
     let prometheus_registry = Arc::new(Registry::new());
     let stablex_metrics = Arc::new(StableXMetrics::new(prometheus_registry.clone()));
@@ -402,28 +1067,160 @@ fn setup_monitoring() -> (
     RouilleServer::new(DefaultRouter {
         metrics: Arc::new(metric_handler),
         health_readiness: health.clone(),
+        startup_progress: Some(startup_progress.clone()),
+        submission_receipts: Some(submission_receipts.clone()),
+        orderbook_snapshot: options.orderbook.snapshot_handler(checkpoint_file),
     })
     .start_in_background();
 
-    (stablex_metrics, http_metrics, solver_metrics, health)
+    (
+        stablex_metrics,
+        http_metrics,
+        solver_metrics,
+        health,
+        startup_progress,
+        submission_receipts,
+    )
 }
 
-async fn setup_http_services(
+/// Creates an orderbook that is built from the events of a separate node.
+async fn setup_fallback_orderbook(
     http_factory: &HttpFactory,
     options: &Options,
-) -> (Web3, Arc<dyn GasPriceEstimating>) {
-    let web3 = web3_provider(http_factory, options.node_url.as_str(), options.rpc_timeout).unwrap();
-    let gas_station =
-        gas_price::create_priority_estimator(&http_factory, &web3, &options.gas_estimators)
-            .await
-            .unwrap();
-    (web3, gas_station)
+) -> Arc<dyn StableXOrderBookReading> {
+    let node_url = options
+        .orderbook_fallback_node_url
+        .as_ref()
+        .expect("the fallback skip batch policy requires an orderbook fallback node url");
+    let web3 = web3_provider(
+        http_factory,
+        node_url.as_str(),
+        options.rpc_timeout,
+        retry_policy(options),
+    )
+    .unwrap();
+    let contract = StableXContractImpl::new(&web3, setup_signer(http_factory, options), false)
+        .await
+        .unwrap();
+    let orderbook = Arc::new(
+        EventBasedOrderbook::new(
+            Arc::new(contract),
+            web3,
+            options.orderbook.auction_data_page_size,
+            None,
+            None,
+        )
+        .with_event_buffer_size(options.orderbook.orderbook_event_buffer_size),
+    );
+    orderbook
+        .clone()
+        .start_updating(FALLBACK_ORDERBOOK_UPDATE_INTERVAL);
+    Arc::new(filtered_orderbook(Box::new(orderbook), options))
+}
+
+/// Applies the orderbook filter to an orderbook.
+fn filtered_orderbook(
+    orderbook: Box<dyn StableXOrderBookReading>,
+    options: &Options,
+) -> FilteredOrderbookReader {
+    // The price oracle reads the filtered orderbook, so there are no prices to
+    // value orders with while filtering it.
+    assert!(
+        !options.orderbook.orderbook_filter.requires_prices(),
+        "the driver does not support sell_value orderbook filter expressions"
+    );
+    FilteredOrderbookReader::new(orderbook, options.orderbook.orderbook_filter.clone())
 }
 
-fn duration_millis(s: &str) -> Result<Duration, ParseIntError> {
-    Ok(Duration::from_millis(s.parse()?))
+async fn setup_solution_submitter(
+    web3: &Web3,
+    contract: Arc<StableXContractImpl>,
+    gas_station: Arc<dyn GasPriceEstimating>,
+    gas_estimate_feedback: &Arc<GasEstimateFeedback>,
+    metrics: &Arc<StableXMetrics>,
+    options: &Options,
+) -> (
+    Arc<dyn StableXSolutionSubmitting + Send + Sync>,
+    Vec<Address>,
+) {
+    let additional_keys = options
+        .private_key
+        .load_additional()
+        .expect("failed to load additional private keys");
+    let additional_contracts =
+        future::join_all(additional_keys.into_iter().map(|key| async move {
+            let contract =
+                StableXContractImpl::new(web3, key.into(), options.use_solution_submitter)
+                    .await
+                    .unwrap();
+            info!("Using additional account {:?}", contract.account());
+            Arc::new(contract)
+        }))
+        .await;
+
+    let mut submitters: Vec<(_, Box<dyn StableXSolutionSubmitting + Send + Sync>)> = Vec::new();
+    for contract in std::iter::once(contract).chain(additional_contracts) {
+        let submitter = StableXSolutionSubmitter::new(
+            contract.clone(),
+            gas_station.clone(),
+            options.custom_benign_errors.clone(),
+        )
+        .with_gas_estimate_feedback(gas_estimate_feedback.clone())
+        .with_replacement_policy(ReplacementPolicy {
+            min_interval: options.min_replacement_interval,
+            min_increase: options.min_replacement_gas_price_increase,
+        })
+        .with_gas_limit_margin(options.solution_gas_limit_margin);
+        if let Err(err) = submitter.recover_stuck_transactions().await {
+            error!(
+                "failed to recover stuck transactions of account {:?}: {:?}",
+                contract.account(),
+                err
+            );
+        }
+        submitters.push((contract.account().address(), Box::new(submitter)));
+    }
+
+    let accounts = submitters.iter().map(|(account, _)| *account).collect();
+    if submitters.len() == 1 {
+        let (_, submitter) = submitters.pop().unwrap();
+        return (Arc::from(submitter), accounts);
+    }
+    let submitter = Arc::new(RoundRobinSolutionSubmitter::new(
+        submitters,
+        metrics.clone(),
+    ));
+    (submitter, accounts)
+}
+
+fn setup_signer(http_factory: &HttpFactory, options: &Options) -> Signer {
+    match &options.external_signer_url {
+        Some(url) => {
+            let account = options
+                .external_signer_account
+                .expect("external signer requires an external signer account");
+            let web3 = web3_provider(
+                http_factory,
+                url.as_str(),
+                options.rpc_timeout,
+                retry_policy(options),
+            )
+            .unwrap();
+            Signer::External { web3, account }
+        }
+        None => options
+            .private_key
+            .load()
+            .expect("failed to load private key")
+            .into(),
+    }
 }
 
-fn duration_secs(s: &str) -> Result<Duration, ParseIntError> {
-    Ok(Duration::from_secs(s.parse()?))
+fn retry_policy(options: &Options) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: options.rpc_max_attempts,
+        initial_backoff: options.rpc_initial_backoff,
+        max_backoff: options.rpc_max_backoff,
+        retry_budget_per_batch: options.rpc_retry_budget_per_batch,
+    }
 }
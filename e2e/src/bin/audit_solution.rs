@@ -0,0 +1,115 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use prometheus::Registry;
+use services_core::{
+    history::{audit, ExchangeHistory},
+    metrics::{self, MetricsPushOptions},
+    models::SubmitSolutionCall,
+    price_finding::optimization_price_finder,
+};
+use std::{fs, path::PathBuf};
+use structopt::StructOpt;
+
+/// Options for auditing a solution.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "audit_solution",
+    about = "Utility for re-verifying a solution against an archived orderbook.",
+    rename_all = "kebab"
+)]
+struct Options {
+    /// The events registry file store containing past exchange events.
+    #[structopt(long, env = "ORDERBOOK_FILE", parse(from_os_str))]
+    orderbook_file: PathBuf,
+
+    /// The file containing the solution, either as solver output JSON or as
+    /// hex encoded calldata of the `submitSolution` transaction.
+    #[structopt(long, parse(from_os_str))]
+    solution: PathBuf,
+
+    /// The batch solved by the solution. Required for solver output, which
+    /// unlike calldata does not contain the batch.
+    #[structopt(long)]
+    batch_id: Option<u32>,
+
+    #[structopt(flatten)]
+    metrics_push: MetricsPushOptions,
+}
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+    metrics::run_job(&options.metrics_push, "audit_solution", |registry| {
+        run(&options, registry)
+    })
+}
+
+fn run(options: &Options, registry: &Registry) -> Result<()> {
+    let history = ExchangeHistory::from_filestore(&options.orderbook_file)?;
+    let content = fs::read_to_string(&options.solution)
+        .with_context(|| format!("error reading {}", options.solution.display()))?;
+    let content = content.trim();
+
+    let call = match content.strip_prefix("0x") {
+        Some(calldata) => Some(SubmitSolutionCall::decode(&decode_hex(calldata)?)?),
+        None => None,
+    };
+    let batch_id = match (&call, options.batch_id) {
+        (Some(call), Some(batch_id)) => {
+            ensure!(
+                batch_id == call.batch_id,
+                "calldata submits a solution for batch {} instead of {}",
+                call.batch_id,
+                batch_id
+            );
+            batch_id
+        }
+        (Some(call), None) => call.batch_id,
+        (None, Some(batch_id)) => batch_id,
+        (None, None) => return Err(anyhow!("the batch ID is required for solver output")),
+    };
+
+    let (accounts, orders) = history.auction_state_for_batch(batch_id)?;
+    let solution = match &call {
+        Some(call) => audit::solution_from_call(call, &orders),
+        None => optimization_price_finder::parse_solver_output(content)?,
+    };
+    let audit = audit::audit_solution(&orders, &accounts, &solution);
+    println!("Audit of solution for batch {}", batch_id);
+    print!("{}", audit);
+    metrics::record_gauge(
+        registry,
+        "audit_solution_trades",
+        "The number of trades of the audited solution.",
+        audit.trades as f64,
+    )?;
+    metrics::record_gauge(
+        registry,
+        "audit_solution_findings",
+        "The number of checks of the exchange the audited solution fails.",
+        audit.findings.len() as f64,
+    )?;
+    if let Some(call) = call {
+        if Some(call.claimed_objective_value) == audit.objective_value() {
+            println!("claimed objective value matches");
+        } else {
+            println!(
+                "claimed objective value {} does not match",
+                call.claimed_objective_value
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    ensure!(hex.len() % 2 == 0, "hex string has an odd length");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            let byte = hex
+                .get(i..i + 2)
+                .ok_or_else(|| anyhow!("hex string is not ASCII"))?;
+            Ok(u8::from_str_radix(byte, 16)?)
+        })
+        .collect()
+}
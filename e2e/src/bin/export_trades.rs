@@ -0,0 +1,62 @@
+use anyhow::Result;
+use prometheus::Registry;
+use services_core::{
+    history::{export, ExchangeHistory},
+    metrics::{self, MetricsPushOptions},
+    models::BatchId,
+};
+use std::{fs::File, io::BufWriter, path::PathBuf};
+use structopt::StructOpt;
+
+/// Options for exporting historic trades.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "export_trades",
+    about = "Utility for exporting historic trades and clearing prices to CSV.",
+    rename_all = "kebab"
+)]
+struct Options {
+    /// The events registry file store containing past exchange events.
+    #[structopt(long, env = "ORDERBOOK_FILE", parse(from_os_str))]
+    orderbook_file: PathBuf,
+
+    /// The first batch to export trades for.
+    #[structopt(long, default_value = "0")]
+    from_batch: u64,
+
+    /// The last batch to export trades for. Defaults to the current batch.
+    #[structopt(long)]
+    to_batch: Option<u64>,
+
+    /// The output file for the exported trades.
+    #[structopt(long, default_value = "target/trades-export.csv", parse(from_os_str))]
+    output: PathBuf,
+
+    #[structopt(flatten)]
+    metrics_push: MetricsPushOptions,
+}
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+    metrics::run_job(&options.metrics_push, "export_trades", |registry| {
+        run(&options, registry)
+    })
+}
+
+fn run(options: &Options, registry: &Registry) -> Result<()> {
+    let history = ExchangeHistory::from_filestore(&options.orderbook_file)?;
+
+    let batches =
+        BatchId(options.from_batch)..=options.to_batch.map(BatchId).unwrap_or_else(BatchId::now);
+    let records = history.trade_records(batches);
+    println!("Exporting {} trades.", records.len());
+    metrics::record_gauge(
+        registry,
+        "export_trades_trades",
+        "The number of exported trades.",
+        records.len() as f64,
+    )?;
+    export::write_trades_csv(BufWriter::new(File::create(&options.output)?), records)?;
+
+    Ok(())
+}
@@ -1,7 +1,12 @@
 use anyhow::Result;
 use e2e::cmd::{self, Reporting, SampleChannel};
 use pricegraph::{Element, Pricegraph, TokenId};
-use services_core::{history::Settlement, models::BatchId};
+use prometheus::Registry;
+use services_core::{
+    history::Settlement,
+    metrics::{self, MetricsPushOptions},
+    models::BatchId,
+};
 use std::{fs::File, io::Write, path::PathBuf};
 use structopt::StructOpt;
 
@@ -24,11 +29,23 @@ struct Options {
     /// The output directory for the computed results.
     #[structopt(long, env = "OUTPUT_DIR", default_value = "target", parse(from_os_str))]
     output_dir: PathBuf,
+
+    #[structopt(flatten)]
+    metrics_push: MetricsPushOptions,
 }
 
 fn main() -> Result<()> {
     let options = Options::from_args();
-    let mut report = Report::new(File::create(&options.output_dir.join("prices.csv"))?);
+    metrics::run_job(&options.metrics_push, "historic_prices", |registry| {
+        run(&options, registry)
+    })
+}
+
+fn run(options: &Options, registry: &Registry) -> Result<()> {
+    let mut report = Report::new(
+        File::create(&options.output_dir.join("prices.csv"))?,
+        registry.clone(),
+    );
 
     report.header()?;
     cmd::for_each_batch(
@@ -96,6 +113,7 @@ struct Row {
 
 struct Report<T> {
     output: T,
+    registry: Registry,
     samples: usize,
     total_error: f64,
     bad_estimates: usize,
@@ -106,9 +124,10 @@ impl<T> Report<T>
 where
     T: Write,
 {
-    fn new(output: T) -> Self {
+    fn new(output: T, registry: Registry) -> Self {
         Report {
             output,
+            registry,
             samples: 0,
             total_error: 0.0,
             bad_estimates: 0,
@@ -169,6 +188,26 @@ where
             100.0 * self.bad_estimates as f64 / self.samples as f64,
             100.0 * self.missed_estimates as f64 / self.samples as f64,
         );
+
+        for (name, help, value) in &[
+            (
+                "historic_prices_samples",
+                "The number of processed token prices.",
+                self.samples,
+            ),
+            (
+                "historic_prices_bad_estimates",
+                "The number of token price estimates that are off by an order of magnitude.",
+                self.bad_estimates,
+            ),
+            (
+                "historic_prices_missed_estimates",
+                "The number of token prices without estimate.",
+                self.missed_estimates,
+            ),
+        ] {
+            metrics::record_gauge(&self.registry, name, help, *value as f64)?;
+        }
         Ok(())
     }
 }
@@ -2,7 +2,12 @@ use anyhow::Result;
 use contracts::batch_exchange::event_data::Trade;
 use e2e::cmd::{self, Reporting};
 use pricegraph::{Element, Pricegraph, TokenPair, FEE_FACTOR, U256};
-use services_core::{history::Settlement, models::BatchId};
+use prometheus::Registry;
+use services_core::{
+    history::Settlement,
+    metrics::{self, MetricsPushOptions},
+    models::BatchId,
+};
 use std::{fs::File, io::Write, path::PathBuf};
 use structopt::StructOpt;
 
@@ -24,12 +29,23 @@ struct Options {
     /// The output directory for the computed results.
     #[structopt(long, env = "OUTPUT_DIR", default_value = "target", parse(from_os_str))]
     output_dir: PathBuf,
+
+    #[structopt(flatten)]
+    metrics_push: MetricsPushOptions,
 }
 
 fn main() -> Result<()> {
     let options = Options::from_args();
+    metrics::run_job(&options.metrics_push, "historic_trades", |registry| {
+        run(&options, registry)
+    })
+}
 
-    let mut report = Report::new(File::create(options.output_dir.join("trades.csv"))?);
+fn run(options: &Options, registry: &Registry) -> Result<()> {
+    let mut report = Report::new(
+        File::create(options.output_dir.join("trades.csv"))?,
+        registry.clone(),
+    );
     report.header()?;
 
     cmd::for_each_batch(
@@ -257,6 +273,7 @@ struct Row {
 
 struct Report<T> {
     output: T,
+    registry: Registry,
     total: usize,
     success: usize,
     skipped: usize,
@@ -268,9 +285,10 @@ impl<T> Report<T>
 where
     T: Write,
 {
-    fn new(output: T) -> Self {
+    fn new(output: T, registry: Registry) -> Self {
         Report {
             output,
+            registry,
             total: 0,
             success: 0,
             skipped: 0,
@@ -347,6 +365,36 @@ where
             skipped = percent(self.skipped),
         );
 
+        for (name, help, value) in &[
+            (
+                "historic_trades_orders",
+                "The number of processed orders.",
+                self.total,
+            ),
+            (
+                "historic_trades_correct",
+                "The number of orders that were traded as their price estimate suggests.",
+                self.success,
+            ),
+            (
+                "historic_trades_missed",
+                "The number of orders with a pessimistic price estimate that were traded.",
+                self.missed,
+            ),
+            (
+                "historic_trades_failed",
+                "The number of reasonably priced orders that were not fully matched.",
+                self.failed,
+            ),
+            (
+                "historic_trades_skipped",
+                "The number of matchable orders that the solver skipped.",
+                self.skipped,
+            ),
+        ] {
+            metrics::record_gauge(&self.registry, name, help, *value as f64)?;
+        }
+
         Ok(())
     }
 }
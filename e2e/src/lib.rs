@@ -1,4 +1,5 @@
 pub mod cmd;
 pub mod common;
 pub mod docker_logs;
+pub mod local_network;
 pub mod stablex;
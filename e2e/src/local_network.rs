@@ -0,0 +1,288 @@
+//! Module for running e2e tests against a local test network without
+//! docker-compose. The test node is spawned as a child process, the exchange
+//! contracts are deployed to it and the node is stopped again when the network
+//! is dropped.
+//!
+//! Both `ganache-cli` and `anvil` are supported. The node is started with the
+//! same network id and block gas limit as the docker-compose setup, and the
+//! contracts are deployed like the `deploy` script of the `contracts` crate
+//! does, so that a fresh deterministic ganache node ends up with the same
+//! contract addresses as the ones used by the build script.
+
+use crate::common::{FutureWaitExt as _, MAX_GAS};
+use anyhow::{bail, Context as _, Result};
+use contracts::{
+    deployment::Deployment,
+    ethcontract::dyns::{DynTransport, DynWeb3},
+};
+use services_core::{
+    contracts::{web3_provider, Web3},
+    http::HttpFactory,
+    transport::RetryPolicy,
+};
+use std::{
+    env,
+    error::Error,
+    fmt,
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The network id of the local test network.
+pub const NETWORK_ID: u64 = 5777;
+
+/// The block gas limit of the local test network.
+const BLOCK_GAS_LIMIT: u64 = 8_000_000;
+
+/// The gas used for deploying each of the contracts.
+const DEPLOY_GAS: u64 = 8_000_000;
+
+const NODE_READY_TIMEOUT: Duration = Duration::from_secs(30);
+const NODE_READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often a test node is started on a different free port when it exits
+/// before becoming ready.
+const NODE_START_ATTEMPTS: usize = 3;
+
+/// The test node implementation to spawn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+    Ganache,
+    Anvil,
+}
+
+impl NodeKind {
+    fn default_binary(self) -> &'static str {
+        match self {
+            NodeKind::Ganache => "ganache-cli",
+            NodeKind::Anvil => "anvil",
+        }
+    }
+
+    fn args(self, port: u16) -> Vec<String> {
+        match self {
+            NodeKind::Ganache => vec![
+                "--deterministic".to_owned(),
+                "--port".to_owned(),
+                port.to_string(),
+                "--networkId".to_owned(),
+                NETWORK_ID.to_string(),
+                "--gasLimit".to_owned(),
+                BLOCK_GAS_LIMIT.to_string(),
+            ],
+            NodeKind::Anvil => vec![
+                "--port".to_owned(),
+                port.to_string(),
+                "--chain-id".to_owned(),
+                NETWORK_ID.to_string(),
+                "--gas-limit".to_owned(),
+                BLOCK_GAS_LIMIT.to_string(),
+            ],
+        }
+    }
+}
+
+impl FromStr for NodeKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "ganache" => Ok(NodeKind::Ganache),
+            "anvil" => Ok(NodeKind::Anvil),
+            _ => bail!("unknown test node kind '{}'", value),
+        }
+    }
+}
+
+/// Configuration of the spawned test node.
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    pub kind: NodeKind,
+    /// The path of the node binary. Defaults to looking up the binary of the
+    /// node kind in `PATH`.
+    pub binary: Option<PathBuf>,
+    /// The port the node listens on. Defaults to a free port.
+    pub port: Option<u16>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            kind: NodeKind::Ganache,
+            binary: None,
+            port: None,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Reads the configuration from the `E2E_NODE` (`ganache` or `anvil`) and
+    /// `E2E_NODE_BIN` environment variables.
+    pub fn from_env() -> Result<Self> {
+        let kind = match env::var("E2E_NODE") {
+            Ok(kind) => kind.parse()?,
+            Err(_) => NodeKind::Ganache,
+        };
+        let binary = env::var_os("E2E_NODE_BIN").map(PathBuf::from);
+        Ok(Self {
+            kind,
+            binary,
+            port: None,
+        })
+    }
+}
+
+/// A local test node running as a child process of the test. The node is
+/// killed when this is dropped.
+pub struct LocalNode {
+    process: Child,
+    url: String,
+    web3: Web3,
+}
+
+impl LocalNode {
+    /// Spawns a test node and waits for it to accept requests.
+    pub fn start(config: &NodeConfig) -> Result<Self> {
+        if let Some(port) = config.port {
+            return Self::start_on_port(config, port);
+        }
+
+        // NOTE: The free port can be taken by another process before the node
+        // binds to it, in which case the node exits and is started again on a
+        // different port.
+        let mut attempt = 1;
+        loop {
+            match Self::start_on_port(config, free_port()?) {
+                Err(err) if attempt < NODE_START_ATTEMPTS && err.is::<NodeExited>() => {
+                    eprintln!("{:?}, restarting on a different port", err);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn start_on_port(config: &NodeConfig, port: u16) -> Result<Self> {
+        let binary = config
+            .binary
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(config.kind.default_binary()));
+        let process = Command::new(&binary)
+            .args(config.kind.args(port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to spawn test node {}", binary.display()))?;
+
+        let url = format!("http://127.0.0.1:{}", port);
+        let web3 = web3_provider(
+            &HttpFactory::default(),
+            &url,
+            Duration::from_secs(10),
+            RetryPolicy::default(),
+        )?;
+
+        // NOTE: Create the node before waiting for it, so that the process is
+        // killed if it does not become ready.
+        let mut node = Self { process, url, web3 };
+        node.wait_until_ready()?;
+        Ok(node)
+    }
+
+    /// The URL of the node's JSON RPC endpoint.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn web3(&self) -> &Web3 {
+        &self.web3
+    }
+
+    /// Deploys the exchange contracts and their dependencies to the node.
+    pub fn deploy_contracts(&self) -> Result<Deployment> {
+        deploy_contracts(&self.web3)
+    }
+
+    fn wait_until_ready(&mut self) -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < NODE_READY_TIMEOUT {
+            if let Some(status) = self.process.try_wait()? {
+                return Err(NodeExited(status).into());
+            }
+            if self.web3.eth().accounts().wait().is_ok() {
+                return Ok(());
+            }
+            thread::sleep(NODE_READY_POLL_INTERVAL);
+        }
+
+        bail!(
+            "timed out waiting for test node after {}s",
+            NODE_READY_TIMEOUT.as_secs(),
+        )
+    }
+}
+
+impl Drop for LocalNode {
+    fn drop(&mut self) {
+        if let Err(err) = self.process.kill().and_then(|_| self.process.wait()) {
+            eprintln!("failed to stop test node: {:?}", err);
+        }
+    }
+}
+
+/// The error of a test node that exited before becoming ready, for example
+/// because its port is already in use.
+#[derive(Debug)]
+struct NodeExited(ExitStatus);
+
+impl fmt::Display for NodeExited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "test node exited before becoming ready: {}", self.0)
+    }
+}
+
+impl Error for NodeExited {}
+
+/// A local test network with deployed exchange contracts.
+pub struct LocalNetwork {
+    pub node: LocalNode,
+    pub deployment: Deployment,
+}
+
+impl LocalNetwork {
+    /// Spawns a test node configured from the environment and deploys the
+    /// exchange contracts to it.
+    pub fn start() -> Result<Self> {
+        Self::start_with(&NodeConfig::from_env()?)
+    }
+
+    pub fn start_with(config: &NodeConfig) -> Result<Self> {
+        let node = LocalNode::start(config)?;
+        let deployment = node.deploy_contracts()?;
+        Ok(Self { node, deployment })
+    }
+
+    pub fn web3(&self) -> &Web3 {
+        self.node.web3()
+    }
+}
+
+/// Deploys the exchange contracts and their dependencies like the `deploy`
+/// script of the `contracts` crate does.
+pub fn deploy_contracts(web3: &Web3) -> Result<Deployment> {
+    let web3 = DynWeb3::new(DynTransport::new(web3.transport().clone()));
+    let mut deployment = Deployment::deploy(&web3, DEPLOY_GAS.into()).wait()?;
+    deployment.batch_exchange.defaults_mut().gas = Some(MAX_GAS.into());
+    Ok(deployment)
+}
+
+/// Finds a port that is currently not in use by binding to port 0. The port is
+/// released again, so it may be taken by another process before it is used.
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
@@ -1,22 +1,36 @@
 use crate::common::{
-    approve, create_accounts_with_funded_tokens, wait_for, FutureBuilderExt as _,
-    FutureWaitExt as _, MAX_GAS,
+    approve, create_accounts_with_funded_tokens, wait_for, wait_for_condition,
+    FutureBuilderExt as _, FutureWaitExt as _, MAX_GAS,
 };
 use contracts::{BatchExchange, TokenOWL, IERC20};
 use ethcontract::{Account, Address, U256};
 use services_core::contracts::Web3;
+use std::time::{Duration, Instant};
 
 pub fn setup_stablex(
     web3: &Web3,
     num_tokens: usize,
     num_users: usize,
     token_minted: u32,
+) -> (BatchExchange, Vec<Address>, Vec<IERC20>) {
+    let instance =
+        BatchExchange::deployed(&web3).wait_and_expect("Cannot get deployed BatchExchange");
+    setup_stablex_instance(web3, instance, num_tokens, num_users, token_minted)
+}
+
+/// Sets up tokens and funded accounts for an exchange instance that was not
+/// necessarily deployed by the `deploy` script, for example one deployed to a
+/// `local_network::LocalNetwork`.
+pub fn setup_stablex_instance(
+    web3: &Web3,
+    mut instance: BatchExchange,
+    num_tokens: usize,
+    num_users: usize,
+    token_minted: u32,
 ) -> (BatchExchange, Vec<Address>, Vec<IERC20>) {
     // Get all tokens but OWL in a generic way
     let (accounts, mut tokens) =
         create_accounts_with_funded_tokens(&web3, num_tokens - 1, num_users, token_minted);
-    let mut instance =
-        BatchExchange::deployed(&web3).wait_and_expect("Cannot get deployed BatchExchange");
     instance.defaults_mut().gas = Some(MAX_GAS.into());
     approve(&tokens, instance.address(), &accounts, token_minted);
 
@@ -45,9 +59,48 @@ pub fn setup_stablex(
     (instance, accounts, tokens)
 }
 
-pub fn close_auction(web3: &Web3, instance: &BatchExchange) {
+/// Advances the time of the test chain to the start of the next batch and mines
+/// a block, closing the auction of the current batch. Returns the id of the
+/// closed batch.
+pub fn close_auction(web3: &Web3, instance: &BatchExchange) -> u32 {
+    let batch_id = current_batch_id(instance);
     let seconds_remaining = instance
         .get_seconds_remaining_in_batch()
         .wait_and_expect("Cannot get seconds remaining in batch");
     wait_for(web3, seconds_remaining.as_u32());
+
+    let next_batch_id = current_batch_id(instance);
+    assert!(
+        next_batch_id > batch_id,
+        "batch {} still collecting orders after advancing time",
+        batch_id,
+    );
+    batch_id
+}
+
+/// Waits for the driver to submit a non-trivial solution for the specified
+/// batch and panics if none is submitted within the timeout.
+pub fn assert_solution_submitted(instance: &BatchExchange, batch_id: u32, timeout: Duration) {
+    wait_for_condition(
+        || {
+            let (solution_batch_id, _, _, objective_value) = instance
+                .latest_solution()
+                .wait_and_expect("Cannot get latest solution");
+            solution_batch_id == batch_id && objective_value > U256::zero()
+        },
+        Instant::now() + timeout,
+    )
+    .unwrap_or_else(|_| {
+        panic!(
+            "No non-trivial solution submitted for batch {} within {}s",
+            batch_id,
+            timeout.as_secs(),
+        )
+    });
+}
+
+fn current_batch_id(instance: &BatchExchange) -> u32 {
+    instance
+        .get_current_batch_id()
+        .wait_and_expect("Cannot get current batch id")
 }
@@ -1,20 +1,25 @@
 use contracts::{BatchExchange, IERC20};
 use e2e::{
-    common::{wait_for_condition, FutureBuilderExt as _, FutureWaitExt as _},
+    common::{FutureBuilderExt as _, FutureWaitExt as _},
     docker_logs,
-    stablex::{close_auction, setup_stablex},
+    local_network::LocalNetwork,
+    stablex::{assert_solution_submitted, close_auction, setup_stablex, setup_stablex_instance},
 };
 use ethcontract::{Account, PrivateKey, U256};
 use futures::future::{join_all, FutureExt as _};
-use services_core::{contracts::Web3, http::HttpFactory};
-use std::{
-    env,
-    time::{Duration, Instant},
+use services_core::{
+    contracts::Web3, http::HttpFactory, models::batch_id::BATCH_DURATION, transport::RetryPolicy,
 };
+use std::{env, time::Duration};
 
 fn web3(url: &str) -> Web3 {
-    services_core::contracts::web3_provider(&HttpFactory::default(), url, Duration::from_secs(10))
-        .expect("transport failed")
+    services_core::contracts::web3_provider(
+        &HttpFactory::default(),
+        url,
+        Duration::from_secs(10),
+        RetryPolicy::default(),
+    )
+    .expect("transport failed")
 }
 
 #[test]
@@ -71,19 +76,8 @@ fn test_with_ganache() {
         )
         .from(Account::Local(accounts[1], None))
         .wait_and_expect("Cannot place first order");
-    close_auction(&web3, &instance);
-
-    // wait for solver to submit solution
-    wait_for_condition(
-        || {
-            instance
-                .get_current_objective_value()
-                .wait_and_expect("Cannot get objective value")
-                > U256::zero()
-        },
-        Instant::now() + Duration::from_secs(30),
-    )
-    .expect("No non-trivial solution submitted");
+    let batch = close_auction(&web3, &instance);
+    assert_solution_submitted(&instance, batch, Duration::from_secs(30));
 
     instance
         .request_withdraw(tokens[1].address(), (999 * usd_price_in_fee).into())
@@ -111,6 +105,26 @@ fn test_with_ganache() {
     assert!(difference < allowed_difference as i128);
 }
 
+#[test]
+fn test_with_local_network() {
+    let network = LocalNetwork::start().expect("Cannot start local network");
+    let web3 = network.web3();
+    let (instance, accounts, tokens) =
+        setup_stablex_instance(web3, network.deployment.batch_exchange.clone(), 3, 3, 100);
+
+    let amount = U256::exp10(18) * 3000;
+    instance
+        .deposit(tokens[1].address(), amount)
+        .from(Account::Local(accounts[0], None))
+        .wait_and_expect("Failed to deposit");
+    close_auction(web3, &instance);
+
+    let balance = instance
+        .get_balance(accounts[0], tokens[1].address())
+        .wait_and_expect("Cannot get balance");
+    assert_eq!(balance, amount);
+}
+
 #[test]
 fn test_rinkeby() {
     // Setup instance and default tx params
@@ -201,15 +215,21 @@ fn test_rinkeby() {
         result.unwrap_or_else(|_| panic!("Tx #{} failed", index));
     }
 
-    // Wait for solution to be applied
-    let sleep_time = instance
+    // All orders are valid in the current batch, so wait for the driver to
+    // solve it once it closes.
+    let batch = instance
+        .get_current_batch_id()
+        .wait_and_expect("Cannot get batchId");
+    let seconds_remaining = instance
         .get_seconds_remaining_in_batch()
         .wait_and_expect("Cannot get seconds remaining in batch")
-        .low_u64()
-        + 60;
-
-    println!("Sleeping {} seconds...", sleep_time);
-    std::thread::sleep(Duration::from_secs(sleep_time));
+        .low_u64();
+    println!("Waiting for solution of batch {}...", batch);
+    assert_solution_submitted(
+        &instance,
+        batch,
+        Duration::from_secs(seconds_remaining) + BATCH_DURATION,
+    );
 
     docker_logs::assert_no_errors_logged("dex-services_stablex_1");
 }
@@ -0,0 +1,329 @@
+//! Invariants that every settlement of the exchange must satisfy. They are
+//! checked from the emitted events alone, so they hold independently of the
+//! solver that submitted the solution.
+
+use contracts::batch_exchange::event_data::SolutionSubmission;
+use ethcontract::U256;
+use services_core::{
+    contracts::stablex_contract::LatestSolution,
+    history::{
+        invariants::{self, TokenViolation, TokenVolumes},
+        Settlement,
+    },
+};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+};
+
+/// The invariant that the balances of the local orderbook match the balances
+/// stored by the exchange.
+pub const ORDERBOOK_BALANCES: &str = "orderbook_balances";
+
+/// A violated invariant.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// The tokens traded by a settlement violate an invariant of the
+    /// exchange.
+    Token(TokenViolation),
+    /// The burnt fees of the solution are not half of the fee token imbalance.
+    BurntFees { emitted: U256, expected: U256 },
+    /// The disregarded utility of the solution exceeds its total utility, so
+    /// it cannot have a valid objective value.
+    ObjectiveValueUnderflow,
+    /// The solution stored by the exchange does not belong to the settled
+    /// batch.
+    StoredSolutionBatch { settled: u32, stored: u32 },
+    /// The objective value stored by the exchange does not match the one
+    /// computed from the solution event.
+    ObjectiveValue { emitted: U256, stored: U256 },
+    /// The fee reward stored by the exchange does not match the burnt fees of
+    /// the solution event.
+    FeeReward { emitted: U256, stored: U256 },
+}
+
+impl Violation {
+    /// The names of all invariants. This includes the orderbook balances,
+    /// which are reconciled with the exchange instead of being checked for
+    /// every settlement.
+    pub const INVARIANTS: &'static [&'static str] = &[
+        "token_conservation",
+        "fee_burn",
+        "prices",
+        "objective_value",
+        "stored_solution",
+        ORDERBOOK_BALANCES,
+    ];
+
+    /// The name of the violated invariant, used as the metric label.
+    pub fn invariant(&self) -> &'static str {
+        match self {
+            Violation::Token(TokenViolation::TokenNotConserved { .. }) => "token_conservation",
+            Violation::Token(TokenViolation::NegativeFeeImbalance { .. })
+            | Violation::BurntFees { .. } => "fee_burn",
+            Violation::Token(TokenViolation::UnpricedToken { .. }) => "prices",
+            Violation::ObjectiveValueUnderflow | Violation::ObjectiveValue { .. } => {
+                "objective_value"
+            }
+            Violation::StoredSolutionBatch { .. } | Violation::FeeReward { .. } => {
+                "stored_solution"
+            }
+        }
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Violation::Token(violation) => violation.fmt(f),
+            Violation::BurntFees { emitted, expected } => write!(
+                f,
+                "burnt fees {} are not half of the fee token imbalance {}",
+                emitted, expected
+            ),
+            Violation::ObjectiveValueUnderflow => {
+                write!(f, "disregarded utility exceeds the total utility")
+            }
+            Violation::StoredSolutionBatch { settled, stored } => write!(
+                f,
+                "stored solution is for batch {} instead of the settled batch {}",
+                stored, settled
+            ),
+            Violation::ObjectiveValue { emitted, stored } => write!(
+                f,
+                "stored objective value {} does not match the emitted objective value {}",
+                stored, emitted
+            ),
+            Violation::FeeReward { emitted, stored } => write!(
+                f,
+                "stored fee reward {} does not match the emitted burnt fees {}",
+                stored, emitted
+            ),
+        }
+    }
+}
+
+/// Checks the trades of a settlement against the solution event that
+/// settled them.
+pub fn check_settlement(settlement: &Settlement) -> Vec<Violation> {
+    let mut volumes = TokenVolumes::default();
+    for trade in &settlement.trades {
+        volumes.add_trade(
+            trade.sell_token,
+            trade.buy_token,
+            trade.executed_sell_amount.into(),
+            trade.executed_buy_amount.into(),
+        );
+    }
+
+    let priced_tokens = settlement
+        .solution
+        .token_ids_for_price
+        .iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    let mut violations = volumes
+        .check_conservation()
+        .into_iter()
+        .chain(volumes.check_prices(|token| priced_tokens.contains(&token)))
+        .map(Violation::Token)
+        .collect::<Vec<_>>();
+
+    match volumes.fee_imbalance() {
+        Ok(imbalance) => {
+            let expected = imbalance / 2;
+            if settlement.solution.burnt_fees != expected {
+                violations.push(Violation::BurntFees {
+                    emitted: settlement.solution.burnt_fees,
+                    expected,
+                });
+            }
+        }
+        Err(violation) => violations.push(Violation::Token(violation)),
+    }
+
+    if objective_value(&settlement.solution).is_none() {
+        violations.push(Violation::ObjectiveValueUnderflow);
+    }
+
+    violations
+}
+
+/// Checks the solution stored by the exchange at the end of the batch in
+/// which the settled batch was solved against the solution event.
+pub fn check_stored_solution(
+    batch: u32,
+    solution: &SolutionSubmission,
+    stored: &LatestSolution,
+) -> Vec<Violation> {
+    if stored.batch_id != batch {
+        return vec![Violation::StoredSolutionBatch {
+            settled: batch,
+            stored: stored.batch_id,
+        }];
+    }
+
+    let mut violations = Vec::new();
+    if let Some(emitted) = objective_value(solution) {
+        if stored.objective_value != emitted {
+            violations.push(Violation::ObjectiveValue {
+                emitted,
+                stored: stored.objective_value,
+            });
+        }
+    }
+    if stored.fee_reward != solution.burnt_fees {
+        violations.push(Violation::FeeReward {
+            emitted: solution.burnt_fees,
+            stored: stored.fee_reward,
+        });
+    }
+    violations
+}
+
+/// The objective value of a solution as computed by the exchange, or `None`
+/// if it underflows.
+pub fn objective_value(solution: &SolutionSubmission) -> Option<U256> {
+    invariants::objective_value(
+        solution.utility,
+        solution.disregarded_utility,
+        solution.burnt_fees,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contracts::batch_exchange::event_data::Trade;
+    use ethcontract::Address;
+
+    fn trade(sell_token: u16, buy_token: u16, sell_amount: u128, buy_amount: u128) -> Trade {
+        Trade {
+            owner: Address::from_low_u64_be(1),
+            order_id: 0,
+            sell_token,
+            buy_token,
+            executed_sell_amount: sell_amount,
+            executed_buy_amount: buy_amount,
+        }
+    }
+
+    fn settlement(trades: Vec<Trade>, burnt_fees: u64) -> Settlement {
+        Settlement {
+            trades,
+            solution: SolutionSubmission {
+                utility: 1000.into(),
+                disregarded_utility: 100.into(),
+                burnt_fees: burnt_fees.into(),
+                prices: vec![1, 2],
+                token_ids_for_price: vec![1, 2],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn valid_settlement() {
+        let settlement = settlement(
+            vec![
+                trade(0, 1, 1_000, 400),
+                trade(1, 2, 400, 300),
+                trade(2, 0, 300, 990),
+            ],
+            5,
+        );
+        assert_eq!(check_settlement(&settlement), vec![]);
+    }
+
+    #[test]
+    fn detects_tokens_that_are_not_conserved() {
+        let settlement = settlement(vec![trade(1, 2, 100, 50), trade(2, 1, 50, 99)], 0);
+        assert_eq!(
+            check_settlement(&settlement),
+            vec![Violation::Token(TokenViolation::TokenNotConserved {
+                token: 1,
+                sold: 100.into(),
+                bought: 99.into(),
+            })]
+        );
+    }
+
+    #[test]
+    fn detects_unpriced_tokens() {
+        let settlement = settlement(vec![trade(1, 3, 100, 50), trade(3, 1, 50, 100)], 0);
+        assert_eq!(
+            check_settlement(&settlement),
+            vec![Violation::Token(TokenViolation::UnpricedToken { token: 3 })]
+        );
+    }
+
+    #[test]
+    fn detects_wrong_burnt_fees() {
+        let settlement = settlement(vec![trade(0, 1, 1_000, 100), trade(1, 0, 100, 980)], 9);
+        assert_eq!(
+            check_settlement(&settlement),
+            vec![Violation::BurntFees {
+                emitted: 9.into(),
+                expected: 10.into(),
+            }]
+        );
+
+        let settlement = settlement(vec![trade(0, 1, 980, 100), trade(1, 0, 100, 1_000)], 0);
+        assert_eq!(
+            check_settlement(&settlement),
+            vec![Violation::Token(TokenViolation::NegativeFeeImbalance {
+                sold: 980.into(),
+                bought: 1_000.into(),
+            })]
+        );
+    }
+
+    #[test]
+    fn detects_objective_value_underflow() {
+        let mut settlement = settlement(vec![], 0);
+        settlement.solution.disregarded_utility = 1001.into();
+        assert_eq!(
+            check_settlement(&settlement),
+            vec![Violation::ObjectiveValueUnderflow]
+        );
+    }
+
+    #[test]
+    fn compares_stored_solution() {
+        let solution = settlement(vec![], 10).solution;
+        let stored = LatestSolution {
+            batch_id: 42,
+            submitter: Address::zero(),
+            fee_reward: 10.into(),
+            objective_value: 910.into(),
+        };
+        assert_eq!(check_stored_solution(42, &solution, &stored), vec![]);
+
+        assert_eq!(
+            check_stored_solution(41, &solution, &stored),
+            vec![Violation::StoredSolutionBatch {
+                settled: 41,
+                stored: 42,
+            }]
+        );
+
+        let stored = LatestSolution {
+            fee_reward: 11.into(),
+            objective_value: 900.into(),
+            ..stored
+        };
+        assert_eq!(
+            check_stored_solution(42, &solution, &stored),
+            vec![
+                Violation::ObjectiveValue {
+                    emitted: 910.into(),
+                    stored: 900.into(),
+                },
+                Violation::FeeReward {
+                    emitted: 10.into(),
+                    stored: 11.into(),
+                },
+            ]
+        );
+    }
+}
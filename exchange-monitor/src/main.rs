@@ -0,0 +1,200 @@
+//! A standalone watcher of the exchange. It does not solve or submit solutions
+//! but keeps the event based orderbook up to date, checks every settlement for
+//! violations of the exchange invariants and reconciles orderbook balances with
+//! the exchange. Violations are logged as errors and exported as metrics to
+//! alert on.
+
+mod invariants;
+mod metrics;
+mod monitor;
+
+use metrics::Metrics;
+use monitor::Monitor;
+use prometheus::Registry;
+use services_core::{
+    config::{self, duration_secs, ConfigOptions, OrderbookOptions},
+    contracts::{stablex_contract::StableXContractImpl, web3_provider},
+    health::{HealthReporting, HttpHealthEndpoint},
+    http::{CircuitBreakerOptions, HttpFactory, HttpPoolOptions, HttpProfiles, HttpProxyOptions},
+    http_server::{DefaultRouter, RouilleServer, Serving},
+    logging,
+    metrics::{HttpMetrics, MetricsHandler, StableXMetrics},
+    orderbook::{EventBasedOrderbook, StableXOrderBookReading as _},
+    transport::RetryPolicy,
+};
+use std::{sync::Arc, time::Duration};
+use structopt::StructOpt;
+use tokio::runtime;
+use url::Url;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "exchange monitor", rename_all = "kebab")]
+struct Options {
+    /// The log filter to use.
+    ///
+    /// This follows the `slog-envlogger` syntax (e.g. 'info,exchange_monitor=debug').
+    #[structopt(
+        long,
+        env = "LOG_FILTER",
+        default_value = "warn,exchange_monitor=info,services_core=info"
+    )]
+    log_filter: String,
+
+    /// The Ethereum node URL to connect to. `ws://` and `ipc://` URLs connect
+    /// over a WebSocket or IPC socket instead of HTTP.
+    #[structopt(long, env = "NODE_URL")]
+    node_url: Url,
+
+    /// The timeout in seconds of web3 JSON RPC calls.
+    #[structopt(
+        long,
+        env = "RPC_TIMEOUT",
+        default_value = "10",
+        parse(try_from_str = duration_secs),
+    )]
+    rpc_timeout: Duration,
+
+    #[structopt(flatten)]
+    http_pool: HttpPoolOptions,
+
+    #[structopt(flatten)]
+    http_circuit_breaker: CircuitBreakerOptions,
+
+    /// JSON encoded HTTP client profiles of the remote services (`node`,
+    /// `gasStation`, `priceApi` and `alerting`) with an optional timeout in
+    /// seconds, number of retries, headers and proxy URL, for example
+    /// '{"gasStation": {"timeout": 2, "retries": 1}}'.
+    #[structopt(long, env = "HTTP_PROFILES", default_value = "{}")]
+    http_profiles: HttpProfiles,
+
+    #[structopt(flatten)]
+    http_proxy: HttpProxyOptions,
+
+    #[structopt(flatten)]
+    orderbook: OrderbookOptions,
+
+    /// Time interval in seconds in which the monitor checks for new
+    /// settlements and reconciles orderbook balances.
+    #[structopt(
+        long,
+        env = "CHECK_INTERVAL",
+        default_value = "30",
+        parse(try_from_str = duration_secs),
+    )]
+    check_interval: Duration,
+
+    /// The number of orderbook balances compared with the on-chain balances
+    /// on every check. Set to 0 to disable the reconciliation.
+    #[structopt(long, env = "BALANCE_RECONCILIATION_SAMPLE_SIZE", default_value = "20")]
+    balance_reconciliation_sample_size: usize,
+
+    #[structopt(flatten)]
+    config: ConfigOptions,
+}
+
+fn main() {
+    let options: Options = config::from_args();
+    if options.config.print_config {
+        println!("{:#?}", options);
+        return;
+    }
+    let (_, _guard) = logging::init(&options.log_filter);
+    log::info!(
+        "Starting exchange monitor with runtime options: {:#?}",
+        options
+    );
+
+    let (metrics, stablex_metrics, http_metrics, health) = setup_monitoring();
+    let http_factory = HttpFactory::new(
+        options.rpc_timeout,
+        options.http_pool,
+        options.http_circuit_breaker,
+        http_metrics,
+    )
+    .with_profiles(options.http_profiles.clone())
+    .with_proxy(options.http_proxy.clone());
+    let mut runtime = runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let web3 = web3_provider(
+        &http_factory,
+        options.node_url.as_str(),
+        options.rpc_timeout,
+        RetryPolicy::default(),
+    )
+    .unwrap();
+    let contract = Arc::new(
+        runtime
+            .block_on(StableXContractImpl::read_only(&web3))
+            .unwrap(),
+    );
+
+    let event_sink = options
+        .orderbook
+        .event_sink()
+        .expect("failed to create event sink");
+    let checkpoint_source = options
+        .orderbook
+        .checkpoint_source(&http_factory)
+        .expect("failed to create orderbook checkpoint source");
+    let mut orderbook = EventBasedOrderbook::new(
+        contract.clone(),
+        web3,
+        options.orderbook.auction_data_page_size,
+        options.orderbook.orderbook_file,
+        options.orderbook.orderbook_reindex_from_block,
+    )
+    .with_event_buffer_size(options.orderbook.orderbook_event_buffer_size)
+    .with_metrics(stablex_metrics.clone());
+    if let Some(event_sink) = event_sink {
+        orderbook = orderbook.with_event_sink(event_sink);
+    }
+    if let Some(checkpoint_source) = checkpoint_source {
+        orderbook = orderbook.with_checkpoint_source(checkpoint_source);
+    }
+    let orderbook = Arc::new(orderbook);
+
+    runtime
+        .block_on(orderbook.initialize())
+        .expect("failed to initialize orderbook");
+    log::info!("Orderbook initialized.");
+
+    let monitor = Monitor {
+        contract,
+        orderbook,
+        metrics,
+        stablex_metrics,
+        balance_sample_size: options.balance_reconciliation_sample_size,
+    };
+    health.notify_ready();
+    runtime.block_on(monitor.run_forever(options.check_interval));
+}
+
+fn setup_monitoring() -> (
+    Arc<Metrics>,
+    Arc<StableXMetrics>,
+    HttpMetrics,
+    Arc<dyn HealthReporting>,
+) {
+    let health = Arc::new(HttpHealthEndpoint::new());
+    let prometheus_registry = Arc::new(Registry::new());
+
+    let metric_handler = MetricsHandler::new(prometheus_registry.clone());
+    RouilleServer::new(DefaultRouter {
+        metrics: Arc::new(metric_handler),
+        health_readiness: health.clone(),
+        startup_progress: None,
+        submission_receipts: None,
+        orderbook_snapshot: None,
+    })
+    .start_in_background();
+
+    let metrics = Arc::new(Metrics::new(prometheus_registry.as_ref()).unwrap());
+    let stablex_metrics = Arc::new(StableXMetrics::new(prometheus_registry.clone()));
+    let http_metrics = HttpMetrics::new(&prometheus_registry).unwrap();
+
+    (metrics, stablex_metrics, http_metrics, health)
+}
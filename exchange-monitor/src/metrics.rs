@@ -0,0 +1,85 @@
+use crate::invariants::Violation;
+use anyhow::Result;
+use ethcontract::U256;
+use prometheus::{Counter, IntCounter, IntCounterVec, IntGauge, Opts, Registry};
+
+/// Metrics of the monitored exchange. Alerts are defined on the invariant
+/// violations, which should never increase.
+pub struct Metrics {
+    invariant_violations: IntCounterVec,
+    checked_batch: IntGauge,
+    settled_batches: IntCounter,
+    settled_trades: IntCounter,
+    burnt_fees: Counter,
+}
+
+impl Metrics {
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let opts = Opts::new(
+            "dfusion_monitor_invariant_violations",
+            "The number of violations of exchange invariants, per invariant.",
+        );
+        let invariant_violations = IntCounterVec::new(opts, &["invariant"]).unwrap();
+        registry.register(Box::new(invariant_violations.clone()))?;
+
+        let opts = Opts::new(
+            "dfusion_monitor_checked_batch",
+            "The last batch whose settlement was checked.",
+        );
+        let checked_batch = IntGauge::with_opts(opts).unwrap();
+        registry.register(Box::new(checked_batch.clone()))?;
+
+        let opts = Opts::new(
+            "dfusion_monitor_settled_batches",
+            "The number of checked batches that were settled by a solution.",
+        );
+        let settled_batches = IntCounter::with_opts(opts).unwrap();
+        registry.register(Box::new(settled_batches.clone()))?;
+
+        let opts = Opts::new(
+            "dfusion_monitor_settled_trades",
+            "The number of trades of checked settlements.",
+        );
+        let settled_trades = IntCounter::with_opts(opts).unwrap();
+        registry.register(Box::new(settled_trades.clone()))?;
+
+        let opts = Opts::new(
+            "dfusion_monitor_burnt_fees",
+            "The total fees in fee token atoms burnt by checked settlements.",
+        );
+        let burnt_fees = Counter::with_opts(opts).unwrap();
+        registry.register(Box::new(burnt_fees.clone()))?;
+
+        // Initialize the violations so that alerts can be defined on their
+        // increase before the first violation.
+        for invariant in Violation::INVARIANTS {
+            invariant_violations
+                .with_label_values(&[invariant])
+                .inc_by(0);
+        }
+
+        Ok(Self {
+            invariant_violations,
+            checked_batch,
+            settled_batches,
+            settled_trades,
+            burnt_fees,
+        })
+    }
+
+    pub fn invariant_violated(&self, invariant: &str, count: usize) {
+        self.invariant_violations
+            .with_label_values(&[invariant])
+            .inc_by(count as _);
+    }
+
+    pub fn batch_checked(&self, batch: u32) {
+        self.checked_batch.set(batch as _);
+    }
+
+    pub fn batch_settled(&self, trades: usize, burnt_fees: U256) {
+        self.settled_batches.inc();
+        self.settled_trades.inc_by(trades as _);
+        self.burnt_fees.inc_by(burnt_fees.to_f64_lossy());
+    }
+}
@@ -0,0 +1,134 @@
+//! Continuously checks the settlements of the exchange and the consistency of
+//! the local orderbook.
+
+use crate::{
+    invariants::{self, Violation, ORDERBOOK_BALANCES},
+    metrics::Metrics,
+};
+use anyhow::Result;
+use services_core::{
+    contracts::stablex_contract::{StableXContract as _, StableXContractImpl},
+    metrics::StableXMetrics,
+    models::batch_id::BatchTiming,
+    orderbook::{EventBasedOrderbook, StableXOrderBookReading as _},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::time;
+
+pub struct Monitor {
+    pub contract: Arc<StableXContractImpl>,
+    pub orderbook: Arc<EventBasedOrderbook>,
+    pub metrics: Arc<Metrics>,
+    pub stablex_metrics: Arc<StableXMetrics>,
+    /// The number of orderbook balances compared with the exchange on every
+    /// check, or 0 to not compare balances.
+    pub balance_sample_size: usize,
+}
+
+impl Monitor {
+    /// Checks the settlement of every batch once it is final and reconciles a
+    /// sample of orderbook balances every `check_interval`.
+    ///
+    /// Batches whose check fails are retried on the next check, along with all
+    /// batches that became final in the meantime, so that no batch is skipped.
+    pub async fn run_forever(&self, check_interval: Duration) -> ! {
+        let timing = BatchTiming::with_batch_duration(self.contract.batch_duration());
+        let mut next_batch = None;
+        loop {
+            time::delay_for(check_interval).await;
+
+            if self.balance_sample_size > 0 {
+                if let Err(err) = self.check_balances().await {
+                    log::warn!("failed to reconcile orderbook balances: {:?}", err);
+                }
+            }
+
+            // The solution for a batch is submitted in the following batch, so
+            // the settlement is final once that batch is over.
+            let final_batch = match timing.current_batch(SystemTime::now()) {
+                Ok(current_batch) => current_batch.0 as u32 - 2,
+                Err(err) => {
+                    log::error!("system time is before the unix epoch: {:?}", err);
+                    continue;
+                }
+            };
+            let mut batch = next_batch.unwrap_or(final_batch);
+            while batch <= final_batch {
+                if let Err(err) = self.check_batch(batch).await {
+                    log::warn!("failed to check batch {}: {:?}", batch, err);
+                    break;
+                }
+                batch += 1;
+            }
+            next_batch = Some(batch);
+        }
+    }
+
+    async fn check_batch(&self, batch: u32) -> Result<()> {
+        let settlement = match self.orderbook.settlement_for_batch(batch).await? {
+            Some(settlement) => settlement,
+            None => {
+                log::info!("batch {} was not settled", batch);
+                self.metrics.batch_checked(batch);
+                return Ok(());
+            }
+        };
+
+        let mut violations = invariants::check_settlement(&settlement);
+        // The stored solution is read at the end of the batch in which the
+        // settled batch was solved, as it is replaced by the solution for the
+        // next batch afterwards.
+        let last_block = self.contract.get_last_block_for_batch(batch + 1).await?;
+        let stored = self
+            .contract
+            .latest_solution(Some(last_block.into()))
+            .await?;
+        violations.extend(invariants::check_stored_solution(
+            batch,
+            &settlement.solution,
+            &stored,
+        ));
+
+        log::info!(
+            "checked batch {} with {} trades and {} burnt fees",
+            batch,
+            settlement.trades.len(),
+            settlement.solution.burnt_fees,
+        );
+        self.report_violations(batch, &violations);
+        self.metrics
+            .batch_settled(settlement.trades.len(), settlement.solution.burnt_fees);
+        self.metrics.batch_checked(batch);
+        Ok(())
+    }
+
+    async fn check_balances(&self) -> Result<()> {
+        let mismatches = self
+            .orderbook
+            .reconcile_balances(self.balance_sample_size, &self.stablex_metrics)
+            .await?;
+        if mismatches > 0 {
+            log::error!(
+                "{} sampled orderbook balances do not match the exchange",
+                mismatches
+            );
+            self.metrics
+                .invariant_violated(ORDERBOOK_BALANCES, mismatches);
+        }
+        Ok(())
+    }
+
+    fn report_violations(&self, batch: u32, violations: &[Violation]) {
+        for violation in violations {
+            log::error!(
+                "settlement of batch {} violates an invariant: {}",
+                batch,
+                violation
+            );
+            self.metrics.invariant_violated(violation.invariant(), 1);
+        }
+    }
+}
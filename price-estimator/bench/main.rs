@@ -0,0 +1,387 @@
+//! Load test for a running price estimator. It replays a recorded or generated
+//! mix of requests with a fixed number of concurrent clients and reports
+//! latency percentiles per endpoint.
+//!
+//! Estimates for the current orderbook are tagged with the version of the
+//! orderbook they were computed from, so the first response with a new version
+//! marks an orderbook update of the price estimator. Latencies of requests that
+//! were in flight during an update are reported separately, which shows whether
+//! updates pause request handling.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use env_logger::Env;
+use futures::future;
+use isahc::{config::Configurable, HttpClient, ResponseExt as _};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "price-estimator-bench",
+    about = "Load test measuring the latencies of a running price estimator.",
+    rename_all = "kebab"
+)]
+struct Options {
+    /// The URL of the price estimator, without the API path.
+    #[structopt(long, default_value = "http://localhost:8080")]
+    url: String,
+
+    /// A file with the requests to replay in order. Every line is either a
+    /// request path like `/api/v1/markets/1-7/estimated-best-ask-price` or a
+    /// `GET` request log line of the price estimator, other lines are skipped.
+    /// A request mix is generated when no file is specified.
+    #[structopt(long, parse(from_os_str))]
+    requests: Option<PathBuf>,
+
+    /// The tokens of the markets of generated requests.
+    #[structopt(long, use_delimiter = true, default_value = "1,4,7")]
+    tokens: Vec<u16>,
+
+    /// The number of distinct requests to generate.
+    #[structopt(long, default_value = "1000")]
+    generated_requests: usize,
+
+    /// The seed of the random number generator for generating requests.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// The number of concurrent clients, each sending one request at a time.
+    #[structopt(long, default_value = "50")]
+    concurrency: usize,
+
+    /// The duration of the load test in seconds.
+    #[structopt(long, default_value = "60")]
+    duration: u64,
+
+    /// The timeout of a single request in seconds. Requests that time out are
+    /// counted as errors.
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init_from_env(Env::default().default_filter_or("warn,price_estimator_bench=info"));
+
+    if let Err(err) = run(Options::from_args()).await {
+        log::error!("Error running load test: {:?}", err);
+        std::process::exit(-1);
+    }
+}
+
+async fn run(options: Options) -> Result<()> {
+    if options.concurrency == 0 {
+        bail!("at least one concurrent client is required");
+    }
+
+    let requests = match &options.requests {
+        Some(path) => read_requests(path)?,
+        None => generate_requests(&options.tokens, options.generated_requests, options.seed)?,
+    };
+    log::info!(
+        "sending {} distinct requests with {} clients for {}s",
+        requests.len(),
+        options.concurrency,
+        options.duration,
+    );
+
+    let client = HttpClient::builder()
+        .timeout(Duration::from_secs(options.timeout))
+        .max_connections_per_host(options.concurrency)
+        .build()?;
+    let load_test = Arc::new(LoadTest {
+        client,
+        url: options.url.trim_end_matches('/').to_owned(),
+        requests,
+        next_request: AtomicUsize::new(0),
+        start: Instant::now(),
+        deadline: Instant::now() + Duration::from_secs(options.duration),
+    });
+
+    let clients = (0..options.concurrency).map(|_| tokio::spawn(load_test.clone().run_client()));
+    let mut samples = Vec::new();
+    for client_samples in future::join_all(clients).await {
+        samples.extend(client_samples?);
+    }
+
+    print_report(&samples);
+    Ok(())
+}
+
+/// Reads the request paths to replay from a file.
+fn read_requests(path: &Path) -> Result<Vec<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let lines = content.lines().filter(|line| !line.trim().is_empty());
+    let requests = lines.clone().filter_map(request_path).collect::<Vec<_>>();
+
+    let skipped = lines.count() - requests.len();
+    if skipped > 0 {
+        log::info!("skipped {} lines that are not GET requests", skipped);
+    }
+    if requests.is_empty() {
+        bail!("no requests found in {}", path.display());
+    }
+    Ok(requests)
+}
+
+/// Extracts the request path from a line of a request file. Request log lines
+/// have the format `<address> "GET <path> HTTP/1.1" <status> ...`.
+fn request_path(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.starts_with('/') {
+        return Some(line.to_owned());
+    }
+
+    const METHOD: &str = "\"GET ";
+    let start = line.find(METHOD)? + METHOD.len();
+    let path = line[start..].split_whitespace().next()?;
+    Some(path.to_owned())
+}
+
+/// Generates a mix of requests for the markets between the specified tokens,
+/// weighted by how often the frontend sends them.
+fn generate_requests(tokens: &[u16], count: usize, seed: u64) -> Result<Vec<String>> {
+    let tokens = tokens.iter().copied().collect::<BTreeSet<_>>();
+    if tokens.len() < 2 {
+        bail!("at least two distinct tokens are required to generate requests");
+    }
+    let markets = tokens
+        .iter()
+        .flat_map(|base| tokens.iter().map(move |quote| (*base, *quote)))
+        .filter(|(base, quote)| base != quote)
+        .collect::<Vec<_>>();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let requests = (0..count)
+        .map(|_| {
+            let (base, quote) = *markets.choose(&mut rng).unwrap();
+            let market = format!("/api/v1/markets/{}-{}", base, quote);
+            match rng.gen_range(0..10) {
+                0..=4 => format!(
+                    "{}/estimated-buy-amount/{}?atoms=true",
+                    market,
+                    10f64.powf(rng.gen_range(15.0..22.0)).floor(),
+                ),
+                5..=6 => format!("{}/estimated-best-ask-price?atoms=true", market),
+                7 => format!(
+                    "{}/estimated-amounts-at-price/{}?atoms=true",
+                    market,
+                    10f64.powf(rng.gen_range(-3.0..3.0)),
+                ),
+                8 => market,
+                _ => format!("/api/v1/prices/{}", base),
+            }
+        })
+        .collect();
+    Ok(requests)
+}
+
+struct LoadTest {
+    client: HttpClient,
+    url: String,
+    requests: Vec<String>,
+    next_request: AtomicUsize,
+    start: Instant,
+    deadline: Instant,
+}
+
+/// The outcome of a single request.
+struct Sample {
+    endpoint: String,
+    /// The time at which the request was sent relative to the start of the
+    /// load test.
+    sent: Duration,
+    latency: Duration,
+    /// The response status or `None` if the request failed.
+    status: Option<u16>,
+    /// The orderbook update the response was computed from, if it is tagged.
+    orderbook_update: Option<u64>,
+}
+
+impl Sample {
+    fn received(&self) -> Duration {
+        self.sent + self.latency
+    }
+
+    fn is_error(&self) -> bool {
+        !matches!(self.status, Some(status) if status < 400)
+    }
+}
+
+impl LoadTest {
+    /// Sends requests one at a time until the load test is over.
+    async fn run_client(self: Arc<Self>) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        while Instant::now() < self.deadline {
+            let index = self.next_request.fetch_add(1, Ordering::Relaxed);
+            let path = &self.requests[index % self.requests.len()];
+            samples.push(self.send(path).await);
+        }
+        samples
+    }
+
+    async fn send(&self, path: &str) -> Sample {
+        let sent = Instant::now();
+        let (status, orderbook_update) =
+            match self.client.get_async(format!("{}{}", self.url, path)).await {
+                Ok(mut response) => {
+                    // NOTE: Read the body so that the connection can be reused
+                    // and the latency includes the transfer of the response.
+                    if let Err(err) = response.text() {
+                        log::debug!("failed to read response body: {}", err);
+                    }
+                    let orderbook_update = response
+                        .headers()
+                        .get("etag")
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|tag| orderbook_update(tag).ok());
+                    (Some(response.status().as_u16()), orderbook_update)
+                }
+                Err(err) => {
+                    log::debug!("request to {} failed: {}", path, err);
+                    (None, None)
+                }
+            };
+
+        Sample {
+            endpoint: endpoint(path),
+            sent: sent - self.start,
+            latency: sent.elapsed(),
+            status,
+            orderbook_update,
+        }
+    }
+}
+
+/// Parses the orderbook update counter from an entity tag of the form
+/// `"<epoch>-<update>"`.
+fn orderbook_update(entity_tag: &str) -> Result<u64> {
+    let tag = entity_tag.trim_start_matches("W/").trim_matches('"');
+    let update = tag
+        .rsplit('-')
+        .next()
+        .ok_or_else(|| anyhow!("invalid entity tag {}", entity_tag))?;
+    Ok(update.parse()?)
+}
+
+/// The endpoint of a request path, for example `estimated-buy-amount` for
+/// `/api/v1/markets/1-7/estimated-buy-amount/1000`.
+fn endpoint(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    let mut segments = path
+        .trim_start_matches("/api/v1/")
+        .split('/')
+        .filter(|segment| !segment.is_empty());
+    match segments.next() {
+        Some("markets") => segments.nth(1).unwrap_or("markets").to_owned(),
+        Some(segment) => segment.to_owned(),
+        None => "/".to_owned(),
+    }
+}
+
+/// The times at which new orderbook versions were first observed, excluding
+/// the version that was current when the load test started.
+fn orderbook_updates(samples: &[Sample]) -> Vec<Duration> {
+    let mut first_received = BTreeMap::new();
+    for sample in samples {
+        if let Some(update) = sample.orderbook_update {
+            let received = first_received
+                .entry(update)
+                .or_insert_with(|| sample.received());
+            *received = (*received).min(sample.received());
+        }
+    }
+    first_received.values().skip(1).copied().collect()
+}
+
+fn print_report(samples: &[Sample]) {
+    let updates = orderbook_updates(samples);
+    let during_update = |sample: &Sample| {
+        updates
+            .iter()
+            .any(|update| sample.sent < *update && *update <= sample.received())
+    };
+
+    let mut by_endpoint = BTreeMap::<&str, Vec<&Sample>>::new();
+    for sample in samples {
+        by_endpoint
+            .entry(&sample.endpoint)
+            .or_default()
+            .push(sample);
+    }
+
+    println!(
+        "{:<30} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+        "endpoint", "requests", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms",
+    );
+    for (endpoint, samples) in &by_endpoint {
+        print_row(endpoint, samples.iter().copied());
+    }
+    print_row("all", samples.iter());
+    print_row(
+        "all during orderbook updates",
+        samples.iter().filter(|sample| during_update(sample)),
+    );
+    print_row(
+        "all between orderbook updates",
+        samples.iter().filter(|sample| !during_update(sample)),
+    );
+
+    let mut statuses = BTreeMap::<String, usize>::new();
+    for sample in samples {
+        let status = match sample.status {
+            Some(status) => status.to_string(),
+            None => "failed".to_owned(),
+        };
+        *statuses.entry(status).or_default() += 1;
+    }
+    println!();
+    println!("orderbook updates observed: {}", updates.len());
+    for (status, count) in statuses {
+        println!("status {}: {}", status, count);
+    }
+}
+
+fn print_row<'a>(label: &str, samples: impl Iterator<Item = &'a Sample>) {
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for sample in samples {
+        latencies.push(sample.latency);
+        if sample.is_error() {
+            errors += 1;
+        }
+    }
+    latencies.sort();
+
+    let millis = |quantile: f64| match percentile(&latencies, quantile) {
+        Some(latency) => format!("{:.1}", latency.as_secs_f64() * 1000.0),
+        None => "-".to_owned(),
+    };
+    println!(
+        "{:<30} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+        label,
+        latencies.len(),
+        errors,
+        millis(0.5),
+        millis(0.9),
+        millis(0.99),
+        millis(1.0),
+    );
+}
+
+/// The nearest-rank percentile of sorted latencies.
+fn percentile(sorted: &[Duration], quantile: f64) -> Option<Duration> {
+    let rank = (quantile * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
@@ -0,0 +1,98 @@
+//! Tracks how well price estimates predicted the prices at which batches were
+//! actually settled.
+
+use crate::{
+    metrics::Metrics,
+    models::{EstimationTime, RoundingBuffer},
+    orderbook::Orderbook,
+};
+use anyhow::Result;
+use pricegraph::TokenPair;
+use services_core::models::BatchId;
+use std::{sync::Arc, time::Duration};
+use tokio::time;
+
+/// How often to check whether another batch has been settled.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Compares the price estimates for every settled batch with the exchange
+/// rates of its trades.
+pub async fn track_accuracy_forever(orderbook: Arc<Orderbook>, metrics: Arc<Metrics>) -> ! {
+    let mut last_tracked_batch = None;
+    loop {
+        time::delay_for(CHECK_INTERVAL).await;
+        // The solution for a batch is submitted in the following batch, so the
+        // settlement is final once that batch is over.
+        let batch = BatchId(BatchId::now().0 - 2);
+        if last_tracked_batch == Some(batch) {
+            continue;
+        }
+        match track_batch(&orderbook, &metrics, batch).await {
+            Ok(()) => last_tracked_batch = Some(batch),
+            Err(err) => log::warn!(
+                "failed to track estimate accuracy for batch {}: {:?}",
+                batch,
+                err
+            ),
+        }
+    }
+}
+
+async fn track_batch(orderbook: &Orderbook, metrics: &Metrics, batch: BatchId) -> Result<()> {
+    let settlement = match orderbook.settlement_for_batch(batch).await? {
+        Some(settlement) => settlement,
+        None => return Ok(()),
+    };
+    let pricegraph = orderbook
+        .pricegraph(EstimationTime::Batch(batch), &[], RoundingBuffer::Disabled)
+        .await?;
+
+    let errors = orderbook
+        .run_blocking(move || {
+            settlement
+                .trades
+                .iter()
+                .filter_map(|trade| {
+                    let pair = TokenPair {
+                        buy: trade.buy_token,
+                        sell: trade.sell_token,
+                    };
+                    let sell_amount = trade.executed_sell_amount as f64;
+                    let estimate = pricegraph
+                        .estimate_limit_price(pair.into_unbounded_range(), sell_amount)
+                        .ok()??;
+                    let settled = trade.executed_buy_amount as f64 / sell_amount;
+                    Some((pair, relative_error(estimate, settled)?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+    for (pair, error) in errors {
+        metrics.estimate_error(pair, error);
+    }
+    Ok(())
+}
+
+/// The error of an estimated exchange rate relative to the settled one.
+/// Positive errors mean that the estimate promised a better rate than the
+/// trade was settled at.
+fn relative_error(estimated: f64, settled: f64) -> Option<f64> {
+    if !settled.is_finite() || settled <= 0.0 {
+        return None;
+    }
+    Some(estimated / settled - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_error_of_estimates() {
+        assert_eq!(relative_error(2.0, 2.0), Some(0.0));
+        assert_eq!(relative_error(3.0, 2.0), Some(0.5));
+        assert_eq!(relative_error(1.0, 2.0), Some(-0.5));
+        assert_eq!(relative_error(1.0, 0.0), None);
+        assert_eq!(relative_error(1.0, f64::NAN), None);
+    }
+}
@@ -0,0 +1,60 @@
+//! Module for running cpu heavy pricegraph computations without blocking the
+//! threads driving the HTTP server and the orderbook updates.
+
+use anyhow::Result;
+use tokio::sync::Semaphore;
+
+/// Runs computations on tokio's blocking thread pool while bounding how many
+/// of them run at the same time.
+pub struct BlockingPool {
+    permits: Semaphore,
+}
+
+impl BlockingPool {
+    pub fn new(max_concurrent_computations: usize) -> Self {
+        Self {
+            permits: Semaphore::new(max_concurrent_computations),
+        }
+    }
+
+    /// Runs the computation once one of the computation slots is free.
+    pub async fn run<T, F>(&self, computation: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.permits.acquire().await;
+        Ok(tokio::task::spawn_blocking(computation).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn bounds_concurrent_computations() {
+        let pool = BlockingPool::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let computation = || {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            pool.run(move || {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        };
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let results = runtime.block_on(futures::future::join_all((0..8).map(|_| computation())));
+        assert!(results.into_iter().all(|result| result.is_ok()));
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+    }
+}
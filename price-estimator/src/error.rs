@@ -1,5 +1,6 @@
 //! Module implementing a custom warp rejection for internal service errors.
 
+use crate::{models::ErrorResult, orderbook::OrderbookNotInitialized};
 use anyhow::Error;
 use warp::{
     http::StatusCode,
@@ -14,25 +15,95 @@ pub enum RejectionReason {
     NoTokenInfo,
     /// The token symbol or address was not found.
     TokenNotFound,
+    /// A request parameter is outside of the range of values it accepts.
+    InvalidParameter {
+        parameter: &'static str,
+        allowed: &'static str,
+    },
+    /// The orderbook cannot match any part of a requested order.
+    NoLiquidity,
+    /// The orderbook has not been loaded yet so no estimates can be made.
+    NotInitialized,
+    /// Too many requests are being handled concurrently.
+    TooManyRequests,
+    /// Computing the response took too long.
+    Timeout,
     /// Internal server error.
     InternalError(Error),
+    /// The client already has the response for the orderbook version with
+    /// this entity tag.
+    NotModified(String),
 }
 
 impl RejectionReason {
-    /// Retrieve an HTTP status code and error message for the given rejection
+    /// Converts an error from retrieving the orderbook into a rejection
+    /// reason, distinguishing an orderbook that is still being loaded from
+    /// other internal errors.
+    pub fn orderbook_error(err: Error) -> Self {
+        if err.is::<OrderbookNotInitialized>() {
+            RejectionReason::NotInitialized
+        } else {
+            RejectionReason::InternalError(err)
+        }
+    }
+
+    /// Retrieve an HTTP status code and error body for the given rejection
     /// reason.
-    pub fn as_http_error(&self) -> (StatusCode, &'static str) {
+    pub fn as_http_error(&self) -> (StatusCode, ErrorResult) {
         match self {
             RejectionReason::NoTokenInfo => (
                 StatusCode::BAD_REQUEST,
-                "requested base units for token with missing ERC20 info",
-            ),
-            RejectionReason::TokenNotFound => {
-                (StatusCode::BAD_REQUEST, "token symbol or address not found")
-            }
-            RejectionReason::InternalError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
-            }
+                ErrorResult::new(
+                    "noTokenInfo",
+                    "requested base units for token with missing ERC20 info",
+                ),
+            ),
+            RejectionReason::TokenNotFound => (
+                StatusCode::BAD_REQUEST,
+                ErrorResult::new("tokenNotFound", "token symbol or address not found"),
+            ),
+            RejectionReason::InvalidParameter { parameter, allowed } => (
+                StatusCode::BAD_REQUEST,
+                ErrorResult {
+                    parameter: Some(*parameter),
+                    allowed: Some(*allowed),
+                    ..ErrorResult::new("invalidParameter", "invalid request parameter")
+                },
+            ),
+            RejectionReason::NoLiquidity => (
+                StatusCode::BAD_REQUEST,
+                ErrorResult::new("noLiquidity", "not enough liquidity to match the order"),
+            ),
+            RejectionReason::NotInitialized => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResult::new("notInitialized", "orderbook has not been initialized yet"),
+            ),
+            RejectionReason::TooManyRequests => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorResult::new("tooManyRequests", "too many concurrent requests"),
+            ),
+            RejectionReason::Timeout => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResult::new("timeout", "computing the response took too long"),
+            ),
+            RejectionReason::InternalError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResult::new("internalError", "internal server error"),
+            ),
+            RejectionReason::NotModified(_) => (
+                StatusCode::NOT_MODIFIED,
+                ErrorResult::new("notModified", "response has not been modified"),
+            ),
+        }
+    }
+
+    /// The label used for the rejected requests metric, if this rejection is
+    /// caused by load shedding.
+    pub fn load_shedding_label(&self) -> Option<&'static str> {
+        match self {
+            RejectionReason::TooManyRequests => Some("concurrency_limit"),
+            RejectionReason::Timeout => Some("timeout"),
+            _ => None,
         }
     }
 }
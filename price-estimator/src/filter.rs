@@ -1,14 +1,45 @@
 use crate::{
-    amounts_at_price, error::RejectionReason, metrics::Metrics, models::*, orderbook::Orderbook,
+    amounts_at_price,
+    error::RejectionReason,
+    metrics::Metrics,
+    models::*,
+    openapi,
+    order_placement::{self, PlaceableOrder},
+    orderbook::Orderbook,
+    request_limits::{self, ConcurrencyLimit, ConcurrencyPermit, RequestLimits},
 };
 use pricegraph::{Market, OrderbookError, Pricegraph, TokenPairRange, TransitiveOrder};
+use serde::de::DeserializeOwned;
 use services_core::{
     economic_viability::EconomicViabilityComputing,
-    models::TokenId,
+    models::{BatchId, TokenId},
     token_info::{TokenBaseInfo, TokenInfoFetching},
 };
-use std::{convert::Infallible, sync::Arc, time::Instant};
-use warp::{http::StatusCode, reply::Json, Filter, Rejection, Reply};
+use std::{
+    convert::Infallible,
+    future::{self, Future},
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
+use warp::{
+    http::{header, HeaderValue, StatusCode},
+    reply::{Json, Response},
+    Filter, Rejection, Reply,
+};
+
+/// The exchange charges a fee of `1 / FEE_DENOMINATOR` of the sell amount of
+/// every order.
+const FEE_DENOMINATOR: u128 = 1000;
+
+/// The number of seconds clients are asked to wait before retrying a request
+/// that failed because the service is overloaded or the orderbook has not been
+/// initialized yet.
+const RETRY_AFTER_SECS: u64 = 10;
+
+/// The maximum number of batches an average price can be computed over. Every
+/// batch requires building a separate pricegraph from its historic orderbook.
+pub const MAX_AVERAGE_PRICE_BATCHES: u32 = 10;
 
 /// Handles all supported requests under a `/api/v1` root path.
 pub fn all(
@@ -16,12 +47,24 @@ pub fn all(
     token_info: Arc<dyn TokenInfoFetching>,
     metrics: Arc<Metrics>,
     economic_viability: Arc<dyn EconomicViabilityComputing>,
+    limits: RequestLimits,
+    swagger_ui_dir: Option<PathBuf>,
 ) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone + Send {
-    let markets = markets(orderbook.clone(), token_info.clone());
-    let estimated_buy_amount = estimated_buy_amount(orderbook.clone(), token_info.clone());
+    let markets = markets(orderbook.clone(), token_info.clone(), limits);
+    let estimated_buy_amount = estimated_buy_amount(orderbook.clone(), token_info.clone(), limits);
     let estimated_amounts_at_price =
-        estimated_amounts_at_price(orderbook.clone(), token_info.clone());
-    let estimated_best_ask_price = estimated_best_ask_price(orderbook, token_info);
+        estimated_amounts_at_price(orderbook.clone(), token_info.clone(), limits);
+    let estimated_best_ask_price =
+        estimated_best_ask_price(orderbook.clone(), token_info.clone(), limits);
+    let average_price = average_price(orderbook.clone(), token_info.clone(), limits);
+    let placeable_order = placeable_order(orderbook.clone(), token_info.clone(), limits);
+    let estimated_fee = estimated_fee(
+        orderbook.clone(),
+        token_info.clone(),
+        economic_viability.clone(),
+        limits,
+    );
+    let price = price(orderbook, token_info, limits);
     let minimum_order_size_owl = minimum_order_size_owl(economic_viability);
 
     let label = |label: &'static str| warp::any().map(move || label);
@@ -33,64 +76,114 @@ pub fn all(
             .unify()
             .or(label("estimated-best-ask-price").and(estimated_best_ask_price))
             .unify()
+            .or(label("average-price").and(average_price))
+            .unify()
+            .or(label("placeable-order").and(placeable_order))
+            .unify()
+            .or(label("estimated-fee").and(estimated_fee))
+            .unify()
             .or(label("minimum-order-size-owl").and(minimum_order_size_owl))
+            .unify()
+            .or(label("prices").and(price))
             .unify(),
     );
 
+    let concurrency_limit = Arc::new(ConcurrencyLimit::new(limits.max_concurrent_requests));
+    let permit = warp::any().and_then(move || {
+        future::ready(
+            concurrency_limit
+                .try_acquire()
+                .ok_or_else(|| Rejection::from(RejectionReason::TooManyRequests)),
+        )
+    });
+
     let start_time = warp::any().map(Instant::now);
-    let handle_metrics = move |start, route, reply| {
+    let rejection_metrics = metrics.clone();
+    // The permit is only dropped once the response has been computed.
+    let handle_metrics = move |start, _: ConcurrencyPermit, route, reply| {
         metrics.handle_successful_response(route, start);
         (reply,)
     };
 
-    start_time
+    // The documentation is served without taking a permit so that it stays
+    // available while the estimates are overloaded.
+    openapi::routes(swagger_ui_dir).or(start_time
+        .and(permit)
         .and(routes_with_labels)
         .map(handle_metrics)
-        .recover(handle_rejection)
+        .recover(move |err| handle_rejection(err, rejection_metrics.clone())))
 }
 
-async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
-    let (code, message) = if let Some(reason) = err.find::<RejectionReason>() {
+async fn handle_rejection(err: Rejection, metrics: Arc<Metrics>) -> Result<impl Reply, Infallible> {
+    if let Some(RejectionReason::NotModified(entity_tag)) = err.find() {
+        let response =
+            warp::reply::with_header(StatusCode::NOT_MODIFIED, header::ETAG, entity_tag.as_str());
+        return Ok(response.into_response());
+    }
+
+    let (code, body) = if let Some(reason) = err.find::<RejectionReason>() {
         log::warn!("rejection reason: {:?}", reason);
+        if let Some(label) = reason.load_shedding_label() {
+            metrics.request_shed(label);
+        }
         reason.as_http_error()
     } else if err.is_not_found() {
-        (StatusCode::NOT_FOUND, "invalid url path")
+        (
+            StatusCode::NOT_FOUND,
+            ErrorResult::new("notFound", "invalid url path"),
+        )
     } else if let Some(warp::reject::InvalidQuery { .. }) = err.find() {
-        (StatusCode::BAD_REQUEST, "invalid url query")
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorResult::new("invalidQuery", "invalid url query"),
+        )
     } else {
         log::warn!("unhandled rejection: {:?}", err);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "unexpected internal error",
+            ErrorResult::new("internalError", "unexpected internal error"),
         )
     };
 
-    let json = warp::reply::json(&ErrorResult { message });
-    Ok(warp::reply::with_status(json, code))
+    let json = warp::reply::json(&body);
+    let mut response = warp::reply::with_status(json, code).into_response();
+    if code == StatusCode::SERVICE_UNAVAILABLE || code == StatusCode::TOO_MANY_REQUESTS {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    }
+    Ok(response)
 }
 
 /// Validate a request of the form
 /// `/minimum-order-size-owl` and answer it.
 fn minimum_order_size_owl(
     economic_viability: Arc<dyn EconomicViabilityComputing>,
-) -> impl Filter<Extract = (Json,), Error = Rejection> + Clone {
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
     warp::path!("minimum-order-size-owl")
         .and(warp::any().map(move || economic_viability.clone()))
         .and_then(get_minimum_order_size_owl)
+        .map(Reply::into_response)
 }
 
 async fn get_minimum_order_size_owl(
     economic_viability: Arc<dyn EconomicViabilityComputing>,
 ) -> Result<Json, Rejection> {
-    let fee_ratio = 1000;
+    let result = get_minimum_fee_owl(economic_viability.as_ref()).await? * FEE_DENOMINATOR;
+    Result::<Json, Rejection>::Ok(warp::reply::json(&result))
+}
+
+/// The minimum total fee in OWL atoms an order needs to generate to be
+/// considered by the solver.
+async fn get_minimum_fee_owl(
+    economic_viability: &dyn EconomicViabilityComputing,
+) -> Result<u128, Rejection> {
     // Multiply by 2 because economic viability returns earned fee while we want generated fee.
-    let result = economic_viability
+    Ok(economic_viability
         .min_average_fee()
         .await
         .map_err(RejectionReason::InternalError)?
-        * 2
-        * fee_ratio;
-    Result::<Json, Rejection>::Ok(warp::reply::json(&result))
+        * 2)
 }
 
 /// Validate a request of the form
@@ -99,12 +192,22 @@ async fn get_minimum_order_size_owl(
 fn markets(
     orderbook: Arc<Orderbook>,
     token_info: Arc<dyn TokenInfoFetching>,
-) -> impl Filter<Extract = (Json,), Error = Rejection> + Clone {
-    markets_filter()
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    markets_filter(limits.max_hops)
         .and(warp::get())
+        .and(entity_tag(orderbook.clone()))
         .and(warp::any().map(move || orderbook.clone()))
         .and(warp::any().map(move || token_info.clone()))
-        .and_then(get_markets)
+        .and_then(move |pair, query, entity_tag, orderbook, token_info| {
+            with_entity_tag(
+                entity_tag,
+                request_limits::with_timeout(
+                    limits.timeout,
+                    get_markets(pair, query, orderbook, token_info),
+                ),
+            )
+        })
 }
 
 /// Validate a request of the form
@@ -113,11 +216,23 @@ fn markets(
 fn estimated_buy_amount(
     orderbook: Arc<Orderbook>,
     token_info: Arc<dyn TokenInfoFetching>,
-) -> impl Filter<Extract = (Json,), Error = Rejection> + Clone {
-    estimated_buy_amount_filter()
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    estimated_buy_amount_filter(limits.max_hops)
+        .and(entity_tag(orderbook.clone()))
         .and(warp::any().map(move || orderbook.clone()))
         .and(warp::any().map(move || token_info.clone()))
-        .and_then(estimate_buy_amount)
+        .and_then(
+            move |pair, amount, query, entity_tag, orderbook, token_info| {
+                with_entity_tag(
+                    entity_tag,
+                    request_limits::with_timeout(
+                        limits.timeout,
+                        estimate_buy_amount(pair, amount, query, orderbook, token_info),
+                    ),
+                )
+            },
+        )
 }
 
 /// Validate a request of the form:
@@ -126,11 +241,23 @@ fn estimated_buy_amount(
 fn estimated_amounts_at_price(
     orderbook: Arc<Orderbook>,
     token_info: Arc<dyn TokenInfoFetching>,
-) -> impl Filter<Extract = (Json,), Error = Rejection> + Clone {
-    estimated_amounts_at_price_filter()
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    estimated_amounts_at_price_filter(limits.max_hops)
+        .and(entity_tag(orderbook.clone()))
         .and(warp::any().map(move || orderbook.clone()))
         .and(warp::any().map(move || token_info.clone()))
-        .and_then(estimate_amounts_at_price)
+        .and_then(
+            move |pair, price, query, entity_tag, orderbook, token_info| {
+                with_entity_tag(
+                    entity_tag,
+                    request_limits::with_timeout(
+                        limits.timeout,
+                        estimate_amounts_at_price(pair, price, query, orderbook, token_info),
+                    ),
+                )
+            },
+        )
 }
 
 /// Validate a request of the form:
@@ -139,47 +266,360 @@ fn estimated_amounts_at_price(
 fn estimated_best_ask_price(
     orderbook: Arc<Orderbook>,
     token_infos: Arc<dyn TokenInfoFetching>,
-) -> impl Filter<Extract = (Json,), Error = Rejection> + Clone {
-    estimated_best_ask_price_filter()
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    estimated_best_ask_price_filter(limits.max_hops)
+        .and(entity_tag(orderbook.clone()))
+        .and(warp::any().map(move || orderbook.clone()))
+        .and(warp::any().map(move || token_infos.clone()))
+        .and_then(move |pair, query, entity_tag, orderbook, token_infos| {
+            with_entity_tag(
+                entity_tag,
+                request_limits::with_timeout(
+                    limits.timeout,
+                    estimate_best_ask_price(pair, query, orderbook, token_infos),
+                ),
+            )
+        })
+}
+
+/// Validate a request of the form:
+/// `/markets/<baseTokenId>-<quoteTokenId>/average-price/<batches>`
+/// and answer it.
+fn average_price(
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    average_price_filter(limits.max_hops)
+        .and(entity_tag(orderbook.clone()))
+        .and(warp::any().map(move || orderbook.clone()))
+        .and(warp::any().map(move || token_infos.clone()))
+        .and_then(
+            move |pair, batches, query, entity_tag, orderbook, token_infos| {
+                with_entity_tag(
+                    entity_tag,
+                    request_limits::with_timeout(
+                        limits.timeout,
+                        get_average_price(pair, batches, query, orderbook, token_infos),
+                    ),
+                )
+            },
+        )
+}
+
+/// Validate a request of the form:
+/// `/markets/<baseTokenId>-<quoteTokenId>/placeable-order/<sellAmountInQuoteToken>`
+/// and answer it.
+fn placeable_order(
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    placeable_order_filter(limits.max_hops)
+        .and(batch_entity_tag(orderbook.clone()))
+        .and(warp::any().map(move || orderbook.clone()))
+        .and(warp::any().map(move || token_infos.clone()))
+        .and_then(
+            move |pair, amount, query, batch_id, entity_tag, orderbook, token_infos| {
+                with_entity_tag(
+                    entity_tag,
+                    request_limits::with_timeout(
+                        limits.timeout,
+                        get_placeable_order(pair, amount, query, batch_id, orderbook, token_infos),
+                    ),
+                )
+            },
+        )
+}
+
+/// Validate a request of the form:
+/// `/markets/<baseTokenId>-<quoteTokenId>/estimated-fee/<sellAmountInQuoteToken>`
+/// and answer it.
+fn estimated_fee(
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+    economic_viability: Arc<dyn EconomicViabilityComputing>,
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    estimated_fee_filter()
+        .and(warp::any().map(move || orderbook.clone()))
+        .and(warp::any().map(move || token_infos.clone()))
+        .and(warp::any().map(move || economic_viability.clone()))
+        .and_then(
+            move |pair, amount, query, orderbook, token_infos, economic_viability| {
+                request_limits::with_timeout(
+                    limits.timeout,
+                    estimate_fee(
+                        pair,
+                        amount,
+                        query,
+                        orderbook,
+                        token_infos,
+                        economic_viability,
+                    ),
+                )
+            },
+        )
+        .map(Reply::into_response)
+}
+
+/// Validate a request of the form:
+/// `/prices/<tokenId>` or `/prices/native`
+/// and answer it.
+fn price(
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+    limits: RequestLimits,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    price_filter()
         .and(warp::any().map(move || orderbook.clone()))
         .and(warp::any().map(move || token_infos.clone()))
-        .and_then(estimate_best_ask_price)
+        .and_then(move |token, orderbook, token_infos| {
+            request_limits::with_timeout(limits.timeout, get_price(token, orderbook, token_infos))
+        })
+        .map(Reply::into_response)
+}
+
+/// Extracts the entity tag of the current orderbook version, which estimates
+/// are tagged with, or rejects the request with `304 Not Modified` if the
+/// client already has a response for this version. Estimates are not tagged
+/// before the orderbook has been initialized.
+fn entity_tag(
+    orderbook: Arc<Orderbook>,
+) -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match").and_then(
+        move |if_none_match: Option<String>| {
+            let entity_tag = orderbook
+                .version()
+                .map(|version| format!("\"{}\"", version));
+            future::ready(unless_modified(entity_tag, if_none_match))
+        },
+    )
+}
+
+/// Like `entity_tag` but for responses that also depend on the current batch,
+/// like placeable orders whose validity starts at it. The batch id is part of
+/// the entity tag and extracted alongside it so that the response is computed
+/// for the batch it is tagged with.
+fn batch_entity_tag(
+    orderbook: Arc<Orderbook>,
+) -> impl Filter<Extract = (BatchId, Option<String>), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and_then(move |if_none_match: Option<String>| {
+            let batch_id = BatchId::now();
+            let entity_tag = orderbook
+                .version()
+                .map(|version| format!("\"{}-{}\"", version, batch_id));
+            future::ready(
+                unless_modified(entity_tag, if_none_match).map(|entity_tag| (batch_id, entity_tag)),
+            )
+        })
+        .untuple_one()
+}
+
+/// Rejects the request with `304 Not Modified` if the `If-None-Match` header
+/// matches the entity tag.
+fn unless_modified(
+    entity_tag: Option<String>,
+    if_none_match: Option<String>,
+) -> Result<Option<String>, Rejection> {
+    match (entity_tag, if_none_match) {
+        (Some(entity_tag), Some(if_none_match))
+            if matches_entity_tag(&if_none_match, &entity_tag) =>
+        {
+            Err(Rejection::from(RejectionReason::NotModified(entity_tag)))
+        }
+        (entity_tag, _) => Ok(entity_tag),
+    }
+}
+
+/// Whether an `If-None-Match` header value matches the entity tag. Weak
+/// comparison is used as required for this header.
+fn matches_entity_tag(if_none_match: &str, entity_tag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == entity_tag)
+}
+
+/// Adds the entity tag to a successfully computed estimate.
+async fn with_entity_tag(
+    entity_tag: Option<String>,
+    estimate: impl Future<Output = Result<Json, Rejection>>,
+) -> Result<Response, Rejection> {
+    let mut response = estimate.await?.into_response();
+    if let Some(value) = entity_tag.and_then(|tag| HeaderValue::from_str(&tag).ok()) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    Ok(response)
 }
 
 fn markets_prefix() -> impl Filter<Extract = (CurrencyPair,), Error = Rejection> + Copy {
-    warp::path!("markets" / CurrencyPair / ..)
+    warp::path!("markets" / CurrencyPair / ..).and_then(|pair: CurrencyPair| {
+        future::ready(
+            validate_token(&pair.base)
+                .and_then(|_| validate_token(&pair.quote))
+                .map(|_| pair),
+        )
+    })
+}
+
+/// Rejects numeric token references that are not valid token ids. These would
+/// otherwise be interpreted as token symbols.
+fn validate_token(token: &TokenRef) -> Result<(), Rejection> {
+    match token {
+        TokenRef::Symbol(symbol) if symbol.chars().all(|c| c.is_ascii_digit()) => {
+            Err(RejectionReason::InvalidParameter {
+                parameter: "token id",
+                allowed: "integer between 0 and 65535",
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects amounts that cannot be converted into token atoms.
+async fn validate_amount(amount: f64) -> Result<f64, Rejection> {
+    if amount.is_finite() && amount >= 0.0 {
+        Ok(amount)
+    } else {
+        Err(RejectionReason::InvalidParameter {
+            parameter: "sell amount in quote",
+            allowed: "finite non-negative number",
+        }
+        .into())
+    }
+}
+
+/// Rejects prices for which no limit price can be computed.
+async fn validate_price(price: f64) -> Result<f64, Rejection> {
+    if price.is_finite() && price > 0.0 {
+        Ok(price)
+    } else {
+        Err(RejectionReason::InvalidParameter {
+            parameter: "price",
+            allowed: "finite positive number",
+        }
+        .into())
+    }
+}
+
+/// Rejects average price windows that are empty or too expensive to compute.
+async fn validate_batches(batches: u32) -> Result<u32, Rejection> {
+    if batches > 0 && batches <= MAX_AVERAGE_PRICE_BATCHES {
+        Ok(batches)
+    } else {
+        Err(RejectionReason::InvalidParameter {
+            parameter: "batches",
+            allowed: "integer between 1 and 10",
+        }
+        .into())
+    }
+}
+
+/// Extracts the query parameters with the number of hops limited to the
+/// maximum configured for the server, which is also used as the default for
+/// requests that don't specify the number of hops.
+fn query_with_hops_limit<Q>(
+    max_hops: Option<usize>,
+) -> impl Filter<Extract = (Q,), Error = Rejection> + Copy
+where
+    Q: HopsQuery + DeserializeOwned + Send + 'static,
+{
+    warp::query::<Q>().and_then(move |mut query: Q| {
+        let result = limit_hops(*query.hops_mut(), max_hops).map(move |hops| {
+            *query.hops_mut() = hops;
+            query
+        });
+        future::ready(result)
+    })
+}
+
+/// Rejects requested hops exceeding the maximum and applies the default.
+fn limit_hops(hops: Option<usize>, max_hops: Option<usize>) -> Result<Option<usize>, Rejection> {
+    match (hops, max_hops) {
+        (Some(hops), Some(max_hops)) if hops > max_hops => Err(RejectionReason::InvalidParameter {
+            parameter: "hops",
+            allowed: "integer up to the maximum hops configured for the server",
+        }
+        .into()),
+        (None, max_hops) => Ok(max_hops.map(|max_hops| max_hops.min(MAX_HOPS))),
+        (hops, _) => Ok(hops),
+    }
 }
 
 fn markets_filter(
+    max_hops: Option<usize>,
 ) -> impl Filter<Extract = (CurrencyPair, QueryParameters), Error = Rejection> + Copy {
     markets_prefix()
         .and(warp::path::end())
         .and(warp::get())
-        .and(warp::query::<QueryParameters>())
+        .and(query_with_hops_limit::<QueryParameters>(max_hops))
 }
 
 fn estimated_buy_amount_filter(
+    max_hops: Option<usize>,
 ) -> impl Filter<Extract = (CurrencyPair, f64, QueryParameters), Error = Rejection> + Copy {
     markets_prefix()
-        .and(warp::path!("estimated-buy-amount" / f64))
+        .and(warp::path!("estimated-buy-amount" / f64).and_then(validate_amount))
         .and(warp::get())
-        .and(warp::query::<QueryParameters>())
+        .and(query_with_hops_limit::<QueryParameters>(max_hops))
 }
 
 fn estimated_amounts_at_price_filter(
+    max_hops: Option<usize>,
 ) -> impl Filter<Extract = (CurrencyPair, f64, QueryParameters), Error = Rejection> + Copy {
     markets_prefix()
-        .and(warp::path!("estimated-amounts-at-price" / f64))
+        .and(warp::path!("estimated-amounts-at-price" / f64).and_then(validate_price))
         .and(warp::get())
-        .and(warp::query::<QueryParameters>())
+        .and(query_with_hops_limit::<QueryParameters>(max_hops))
 }
 
 fn estimated_best_ask_price_filter(
+    max_hops: Option<usize>,
 ) -> impl Filter<Extract = (CurrencyPair, QueryParameters), Error = Rejection> + Copy {
     markets_prefix()
         .and(warp::path!("estimated-best-ask-price"))
         .and(warp::get())
-        .and(warp::query::<QueryParameters>())
+        .and(query_with_hops_limit::<QueryParameters>(max_hops))
+}
+
+fn average_price_filter(
+    max_hops: Option<usize>,
+) -> impl Filter<Extract = (CurrencyPair, u32, QueryParameters), Error = Rejection> + Copy {
+    markets_prefix()
+        .and(warp::path!("average-price" / u32).and_then(validate_batches))
+        .and(warp::get())
+        .and(query_with_hops_limit::<QueryParameters>(max_hops))
+}
+
+fn placeable_order_filter(
+    max_hops: Option<usize>,
+) -> impl Filter<Extract = (CurrencyPair, f64, OrderQueryParameters), Error = Rejection> + Copy {
+    markets_prefix()
+        .and(warp::path!("placeable-order" / f64).and_then(validate_amount))
+        .and(warp::get())
+        .and(query_with_hops_limit::<OrderQueryParameters>(max_hops))
+}
+
+fn estimated_fee_filter(
+) -> impl Filter<Extract = (CurrencyPair, f64, UnitQueryParameters), Error = Rejection> + Copy {
+    markets_prefix()
+        .and(warp::path!("estimated-fee" / f64).and_then(validate_amount))
+        .and(warp::get())
+        .and(warp::query::<UnitQueryParameters>())
+}
+
+/// Extracts the requested token, `None` meaning the native token.
+fn price_filter() -> impl Filter<Extract = (Option<TokenRef>,), Error = Rejection> + Copy {
+    warp::path!("prices" / "native")
+        .map(|| None)
+        .or(warp::path!("prices" / TokenRef)
+            .and_then(|token: TokenRef| future::ready(validate_token(&token).map(|_| Some(token)))))
+        .unify()
+        .and(warp::get())
 }
 
 async fn get_token_info(
@@ -201,6 +641,19 @@ async fn get_market(
         .map_err(|_| RejectionReason::TokenNotFound.into())
 }
 
+/// Runs a pricegraph computation on the orderbook's blocking thread pool.
+async fn compute<T, F>(orderbook: &Orderbook, computation: F) -> Result<T, Rejection>
+where
+    F: FnOnce() -> Result<T, OrderbookError> + Send + 'static,
+    T: Send + 'static,
+{
+    orderbook
+        .run_blocking(computation)
+        .await
+        .map_err(RejectionReason::InternalError)?
+        .map_err(|err| RejectionReason::InternalError(err.into()).into())
+}
+
 async fn get_markets(
     pair: CurrencyPair,
     query: QueryParameters,
@@ -210,16 +663,19 @@ async fn get_markets(
     let market = get_market(pair, &*token_infos).await?;
     // This route intentionally uses the raw pricegraph without rounding buffer so that orders are
     // unmodified.
-    let transitive_orderbook = orderbook
+    let pricegraph = orderbook
         .pricegraph(
             query.time,
             &query.ignore_addresses,
             RoundingBuffer::Disabled,
         )
         .await
-        .map_err(RejectionReason::InternalError)?
-        .transitive_orderbook(market, query.hops, None)
-        .map_err(|err| RejectionReason::InternalError(err.into()))?;
+        .map_err(RejectionReason::orderbook_error)?;
+    let hops = query.hops;
+    let transitive_orderbook = compute(&orderbook, move || {
+        pricegraph.transitive_orderbook(market, hops, None)
+    })
+    .await?;
     let result = MarketsResult::from(&transitive_orderbook);
     let result = match query.unit {
         Unit::Atoms => result,
@@ -255,24 +711,15 @@ async fn estimate_buy_amount(
             (amount, amount.as_atoms(&token_info) as _)
         }
     };
-    let pricegraph = orderbook
-        .pricegraph(query.time, &query.ignore_addresses, query.rounding_buffer)
-        .await
-        .map_err(RejectionReason::InternalError)?;
-    // This reduced sell amount is what the solver would see after applying the rounding buffer.
-    let sell_amount_in_quote_atoms = match query.rounding_buffer {
-        RoundingBuffer::Enabled => f64::max(
-            sell_amount_in_quote_atoms - orderbook.rounding_buffer(token_pair_range.pair).await,
-            0.0,
-        ),
-        RoundingBuffer::Disabled => sell_amount_in_quote_atoms,
-    };
-    let transitive_order = pricegraph
-        .order_for_sell_amount(token_pair_range, sell_amount_in_quote_atoms)
-        .map_err(|err| RejectionReason::InternalError(err.into()))?;
+    let buy_amount_in_base_atoms = estimate_buy_amount_atoms(
+        token_pair_range,
+        sell_amount_in_quote_atoms,
+        &query,
+        &orderbook,
+    )
+    .await?;
 
-    let mut buy_amount_in_base =
-        Amount::Atoms(transitive_order.map(|order| order.buy).unwrap_or_default() as _);
+    let mut buy_amount_in_base = Amount::Atoms(buy_amount_in_base_atoms as _);
     if query.unit == Unit::BaseUnits {
         let token_info = get_token_info(token_pair_range.pair.buy, token_infos.as_ref()).await?;
         buy_amount_in_base = buy_amount_in_base.into_base_units(&token_info)
@@ -287,6 +734,78 @@ async fn estimate_buy_amount(
     Ok(warp::reply::json(&result))
 }
 
+/// Estimates the buy amount in atoms an order selling the specified amount of
+/// atoms can set while still expecting to be completely matched.
+async fn estimate_buy_amount_atoms(
+    token_pair_range: TokenPairRange,
+    sell_amount_atoms: f64,
+    query: &QueryParameters,
+    orderbook: &Orderbook,
+) -> Result<f64, Rejection> {
+    let pricegraph = orderbook
+        .pricegraph(query.time, &query.ignore_addresses, query.rounding_buffer)
+        .await
+        .map_err(RejectionReason::orderbook_error)?;
+    // This reduced sell amount is what the solver would see after applying the rounding buffer.
+    let sell_amount_atoms = match query.rounding_buffer {
+        RoundingBuffer::Enabled => f64::max(
+            sell_amount_atoms - orderbook.rounding_buffer(token_pair_range.pair),
+            0.0,
+        ),
+        RoundingBuffer::Disabled => sell_amount_atoms,
+    };
+    let transitive_order = compute(orderbook, move || {
+        pricegraph.order_for_sell_amount(token_pair_range, sell_amount_atoms)
+    })
+    .await?;
+    Ok(transitive_order.map(|order| order.buy).unwrap_or_default())
+}
+
+async fn get_placeable_order(
+    pair: CurrencyPair,
+    sell_amount_in_quote: f64,
+    query: OrderQueryParameters,
+    batch_id: BatchId,
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+) -> Result<Json, Rejection> {
+    let token_pair_range = TokenPairRange {
+        pair: get_market(pair, &*token_infos).await?.bid_pair(),
+        hops: query.hops,
+    };
+    let sell_amount = match query.unit {
+        Unit::Atoms => sell_amount_in_quote as u128,
+        Unit::BaseUnits => {
+            let token_info =
+                get_token_info(token_pair_range.pair.sell, token_infos.as_ref()).await?;
+            Amount::BaseUnits(sell_amount_in_quote).as_atoms(&token_info)
+        }
+    };
+    let estimated_buy_amount = estimate_buy_amount_atoms(
+        token_pair_range,
+        sell_amount as f64,
+        &query.estimation_query(),
+        &orderbook,
+    )
+    .await?;
+    let buy_amount =
+        order_placement::buy_amount_with_slippage(estimated_buy_amount, query.slippage);
+    // An order without buy amount would give away the sold tokens.
+    if buy_amount == 0 {
+        return Err(RejectionReason::NoLiquidity.into());
+    }
+
+    let order = PlaceableOrder::new(
+        token_pair_range.pair.buy,
+        token_pair_range.pair.sell,
+        buy_amount,
+        sell_amount,
+        batch_id,
+        query.valid_for,
+    );
+    Ok(warp::reply::json(&OrderPlacementResult::from(order)))
+}
+
 async fn estimate_amounts_at_price(
     pair: CurrencyPair,
     price_in_quote: f64,
@@ -301,41 +820,41 @@ async fn estimate_amounts_at_price(
     let pricegraph = orderbook
         .pricegraph(query.time, &query.ignore_addresses, query.rounding_buffer)
         .await
-        .map_err(RejectionReason::InternalError)?;
+        .map_err(RejectionReason::orderbook_error)?;
     let rounding_buffer = match query.rounding_buffer {
-        RoundingBuffer::Enabled => Some(orderbook.rounding_buffer(token_pair_range.pair).await),
+        RoundingBuffer::Enabled => Some(orderbook.rounding_buffer(token_pair_range.pair)),
         RoundingBuffer::Disabled => None,
     };
-    let result = match query.unit {
-        Unit::Atoms => estimate_amounts_at_price_atoms(
+    let base_unit_token_infos = match query.unit {
+        Unit::Atoms => None,
+        Unit::BaseUnits => Some((
+            get_token_info(token_pair_range.pair.buy, token_infos.as_ref()).await?,
+            get_token_info(token_pair_range.pair.sell, token_infos.as_ref()).await?,
+        )),
+    };
+    let price_in_quote_atoms = match &base_unit_token_infos {
+        None => price_in_quote,
+        Some((buy_token_info, sell_token_info)) => {
+            price_in_quote
+                * (sell_token_info.base_unit_in_atoms().get() as f64
+                    / buy_token_info.base_unit_in_atoms().get() as f64)
+        }
+    };
+    let mut result = compute(&orderbook, move || {
+        estimate_amounts_at_price_atoms(
             token_pair_range,
-            price_in_quote,
+            price_in_quote_atoms,
             &pricegraph,
             rounding_buffer,
         )
-        .map_err(|err| RejectionReason::InternalError(err.into()))?,
-        Unit::BaseUnits => {
-            let buy_token_info =
-                get_token_info(token_pair_range.pair.buy, token_infos.as_ref()).await?;
-            let sell_token_info =
-                get_token_info(token_pair_range.pair.sell, token_infos.as_ref()).await?;
-            let price_in_quote_atoms = price_in_quote
-                * (sell_token_info.base_unit_in_atoms().get() as f64
-                    / buy_token_info.base_unit_in_atoms().get() as f64);
-            let mut result = estimate_amounts_at_price_atoms(
-                token_pair_range,
-                price_in_quote_atoms,
-                &pricegraph,
-                rounding_buffer,
-            )
-            .map_err(|err| RejectionReason::InternalError(err.into()))?;
-            result.buy_amount_in_base = result.buy_amount_in_base.into_base_units(&buy_token_info);
-            result.sell_amount_in_quote = result
-                .sell_amount_in_quote
-                .into_base_units(&sell_token_info);
-            result
-        }
-    };
+    })
+    .await?;
+    if let Some((buy_token_info, sell_token_info)) = base_unit_token_infos {
+        result.buy_amount_in_base = result.buy_amount_in_base.into_base_units(&buy_token_info);
+        result.sell_amount_in_quote = result
+            .sell_amount_in_quote
+            .into_base_units(&sell_token_info);
+    }
     Ok(warp::reply::json(&result))
 }
 
@@ -374,13 +893,15 @@ async fn estimate_best_ask_price(
     token_infos: Arc<dyn TokenInfoFetching>,
 ) -> Result<Json, Rejection> {
     let market = get_market(pair, &*token_infos).await?;
-    let price = orderbook
+    let pricegraph = orderbook
         .pricegraph(query.time, &query.ignore_addresses, query.rounding_buffer)
         .await
-        .map_err(RejectionReason::InternalError)?
-        .best_ask_transitive_order(market)
-        .map_err(|err| RejectionReason::InternalError(err.into()))?
-        .map(|order| order.overlapping_exchange_rate().recip());
+        .map_err(RejectionReason::orderbook_error)?;
+    let price = compute(&orderbook, move || {
+        pricegraph.best_ask_transitive_order(market)
+    })
+    .await?
+    .map(|order| order.overlapping_exchange_rate().recip());
 
     let result = PriceEstimateResult(price);
     let result = match query.unit {
@@ -394,15 +915,132 @@ async fn estimate_best_ask_price(
     Ok(warp::reply::json(&result))
 }
 
+async fn get_average_price(
+    pair: CurrencyPair,
+    batches: u32,
+    query: QueryParameters,
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+) -> Result<Json, Rejection> {
+    let market = get_market(pair, &*token_infos).await?;
+    // The window ends with the requested batch, or the current one when no
+    // batch is specified.
+    let last_batch = match query.time {
+        EstimationTime::Now => BatchId::now(),
+        EstimationTime::Batch(batch_id) => batch_id,
+        EstimationTime::Block(_) | EstimationTime::Timestamp(_) => {
+            return Err(RejectionReason::InvalidParameter {
+                parameter: "time",
+                allowed: "batchId or none",
+            }
+            .into())
+        }
+    };
+    let mut pricegraphs = Vec::new();
+    for batch_id in last_batch.0.saturating_sub(batches as u64 - 1)..=last_batch.0 {
+        let pricegraph = orderbook
+            .pricegraph(
+                EstimationTime::Batch(BatchId(batch_id)),
+                &query.ignore_addresses,
+                query.rounding_buffer,
+            )
+            .await
+            .map_err(RejectionReason::orderbook_error)?;
+        pricegraphs.push(pricegraph);
+    }
+    let price = compute(&orderbook, move || {
+        Pricegraph::volume_weighted_average_price(pricegraphs.iter().map(|p| p.as_ref()), market)
+    })
+    .await?;
+
+    let result = PriceEstimateResult(price);
+    let result = match query.unit {
+        Unit::Atoms => result,
+        Unit::BaseUnits => {
+            let base_token_info = get_token_info(market.base, token_infos.as_ref()).await?;
+            let quote_token_info = get_token_info(market.quote, token_infos.as_ref()).await?;
+            result.into_base_units(&base_token_info, &quote_token_info)
+        }
+    };
+    Ok(warp::reply::json(&result))
+}
+
+async fn estimate_fee(
+    pair: CurrencyPair,
+    sell_amount_in_quote: f64,
+    query: UnitQueryParameters,
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+    economic_viability: Arc<dyn EconomicViabilityComputing>,
+) -> Result<Json, Rejection> {
+    let quote_token = get_market(pair, &*token_infos).await?.quote;
+    let minimum_fee_in_owl = get_minimum_fee_owl(economic_viability.as_ref()).await?;
+    // Token prices are given in OWL atoms per 10^18 atoms of the token.
+    let quote_token_price = orderbook.token_price(TokenId(quote_token)).get();
+    let minimum_fee_in_quote = minimum_fee_in_owl as f64 * 1e18 / quote_token_price as f64;
+
+    let mut result = EstimatedFeeResult {
+        quote_token_id: quote_token,
+        sell_amount_in_quote: Amount::Atoms(sell_amount_in_quote as _),
+        fee_in_quote: Amount::Atoms((sell_amount_in_quote / FEE_DENOMINATOR as f64) as _),
+        minimum_fee_in_quote: Amount::Atoms(minimum_fee_in_quote as _),
+        minimum_fee_in_owl: Amount::Atoms(minimum_fee_in_owl),
+    };
+    if query.unit == Unit::BaseUnits {
+        let quote_token_info = get_token_info(quote_token, token_infos.as_ref()).await?;
+        let owl_token_info = get_token_info(0, token_infos.as_ref()).await?;
+        let sell_amount = Amount::BaseUnits(sell_amount_in_quote);
+        result.sell_amount_in_quote = sell_amount;
+        result.fee_in_quote =
+            Amount::Atoms(sell_amount.as_atoms(&quote_token_info) / FEE_DENOMINATOR)
+                .into_base_units(&quote_token_info);
+        result.minimum_fee_in_quote = result
+            .minimum_fee_in_quote
+            .into_base_units(&quote_token_info);
+        result.minimum_fee_in_owl = result.minimum_fee_in_owl.into_base_units(&owl_token_info);
+    }
+    Ok(warp::reply::json(&result))
+}
+
+async fn get_price(
+    token: Option<TokenRef>,
+    orderbook: Arc<Orderbook>,
+    token_infos: Arc<dyn TokenInfoFetching>,
+) -> Result<Json, Rejection> {
+    let token_id = match token {
+        Some(token) => token
+            .as_token_id(token_infos.as_ref())
+            .await
+            .map_err(|_| RejectionReason::TokenNotFound)?,
+        None => orderbook.native_token().0,
+    };
+    let price_in_owl = orderbook.token_price(TokenId(token_id)).get();
+    let price_in_usd = get_token_info(token_id, token_infos.as_ref())
+        .await
+        .ok()
+        .map(|token_info| token_info.get_usd_price(price_in_owl));
+    let result = PriceResult {
+        token_id,
+        price_in_owl,
+        price_in_usd,
+    };
+    Ok(warp::reply::json(&result))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::infallible_price_source::PriceCacheUpdater;
     use anyhow::{anyhow, Result};
     use futures::future::FutureExt as _;
+    use serde_json::Value;
     use services_core::{
         economic_viability::FixedEconomicViabilityComputer, orderbook::NoopOrderbook,
     };
+    use std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    };
 
     fn empty_token_info() -> impl TokenInfoFetching {
         struct TokenInfoFetcher {}
@@ -421,54 +1059,595 @@ mod tests {
         TokenInfoFetcher {}
     }
 
+    /// Routes with a timeout need to be run in a tokio runtime.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    fn test_limits() -> RequestLimits {
+        RequestLimits {
+            max_concurrent_requests: 10,
+            timeout: Duration::from_secs(10),
+            max_hops: None,
+        }
+    }
+
     fn all_filter() -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+        all_filter_with_swagger_ui(None)
+    }
+
+    fn all_filter_with_swagger_ui(
+        swagger_ui_dir: Option<PathBuf>,
+    ) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
         let token_info = Arc::new(empty_token_info());
         let orderbook = Arc::new(Orderbook::new(
             Box::new(NoopOrderbook),
-            PriceCacheUpdater::new(token_info.clone(), Vec::new()),
+            Arc::new(PriceCacheUpdater::new(
+                token_info.clone(),
+                Default::default(),
+                Vec::new(),
+            )),
             1.0,
             TokenId(1),
+            1,
         ));
+        block_on(orderbook.update()).unwrap();
         let metrics = Arc::new(Metrics::new(&prometheus::Registry::new()).unwrap());
         let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
-        all(orderbook, token_info, metrics, economic_viability)
+        all(
+            orderbook,
+            token_info,
+            metrics,
+            economic_viability,
+            test_limits(),
+            swagger_ui_dir,
+        )
     }
 
     #[test]
     fn error_unhandled_path() {
-        let response = warp::test::request()
-            .path("/")
-            .reply(&all_filter())
-            .now_or_never()
-            .unwrap();
+        let response = block_on(warp::test::request().path("/").reply(&all_filter()));
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn serves_openapi_spec_and_docs() {
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/openapi.json")
+                .reply(&all_filter()),
+        );
+        assert_eq!(response.status(), 200);
+        let spec: Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(spec, openapi::spec());
+
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/docs")
+                .reply(&all_filter()),
+        );
         assert_eq!(response.status(), 404);
+
+        let swagger_ui_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/docs")
+                .reply(&all_filter_with_swagger_ui(Some(swagger_ui_dir))),
+        );
+        assert_eq!(response.status(), 200);
+    }
+
+    /// A value for every query parameter that is accepted by any route.
+    const QUERY_PARAMETER_VALUES: &[(&str, &str)] = &[
+        ("atoms", "true"),
+        ("unit", "atoms"),
+        ("hops", "1"),
+        ("batchId", "1"),
+        ("blockNumber", "1"),
+        ("timestamp", "1"),
+        ("ignoreAddresses", ""),
+        ("roundingBuffer", "enabled"),
+        ("slippage", "0.1"),
+        ("validFor", "1"),
+    ];
+
+    /// Returns the fields of a type that is deserialized as a struct, like the
+    /// raw query parameters the query parameter types are converted from.
+    fn struct_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+        use serde::de::{self, Visitor};
+
+        struct FieldRecorder(&'static [&'static str]);
+        impl<'de> de::Deserializer<'de> for &mut FieldRecorder {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
+            }
+
+            fn deserialize_struct<V: Visitor<'de>>(
+                self,
+                _: &'static str,
+                fields: &'static [&'static str],
+                _: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.0 = fields;
+                Err(de::Error::custom("fields recorded"))
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+                bytes byte_buf option unit unit_struct newtype_struct seq tuple
+                tuple_struct map enum identifier ignored_any
+            }
+        }
+
+        let mut recorder = FieldRecorder(&[]);
+        assert!(T::deserialize(&mut recorder).is_err());
+        recorder.0
+    }
+
+    /// Replaces the path parameters of a documented path with valid values.
+    fn request_path(path: &str) -> String {
+        let request_path = path
+            .replace("{market}", "0-1")
+            .replace("{sell amount in quote}", "1")
+            .replace("{price}", "1")
+            .replace("{batches}", "1")
+            .replace("{token}", "1");
+        assert!(!request_path.contains('{'), "unknown parameter in {}", path);
+        request_path
+    }
+
+    /// Returns the names of the documented query parameters of a path and
+    /// whether they are required.
+    fn documented_query_parameters(spec: &Value, path: &str) -> Vec<(String, bool)> {
+        spec["paths"][path]["get"]["parameters"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|parameter| resolve(spec, parameter))
+            .filter(|parameter| parameter["in"] == "query")
+            .map(|parameter| {
+                (
+                    parameter["name"].as_str().unwrap().to_string(),
+                    parameter["required"] == true,
+                )
+            })
+            .collect()
+    }
+
+    /// Follows a reference to the components of the specification.
+    fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+        match value["$ref"].as_str() {
+            Some(reference) => {
+                let pointer = reference.strip_prefix('#').unwrap();
+                resolve(spec, spec.pointer(pointer).unwrap())
+            }
+            None => value,
+        }
+    }
+
+    fn get<F>(filter: &F, path: &str, query: &[(&str, &str)]) -> (StatusCode, Value)
+    where
+        F: Filter + 'static,
+        F::Extract: Reply + Send,
+    {
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+        let response = block_on(
+            warp::test::request()
+                .path(&format!("{}?{}", path, query))
+                .reply(filter),
+        );
+        let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), body)
+    }
+
+    /// Whether the request was rejected because of its query parameters.
+    fn rejects_query((status, body): &(StatusCode, Value)) -> bool {
+        *status == StatusCode::BAD_REQUEST
+            && (body["code"] == "invalidQuery" || body["code"] == "invalidParameter")
+    }
+
+    #[test]
+    fn openapi_paths_are_handled() {
+        let spec = openapi::spec();
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(!paths.is_empty());
+        for path in paths.keys() {
+            let response = block_on(
+                warp::test::request()
+                    .path(&request_path(path))
+                    .reply(&all_filter()),
+            );
+            assert_ne!(response.status(), 404, "{} is not handled", path);
+        }
+    }
+
+    #[test]
+    fn openapi_query_parameters_are_accepted() {
+        let values = QUERY_PARAMETER_VALUES
+            .iter()
+            .copied()
+            .collect::<HashMap<_, _>>();
+        let fields = struct_fields::<QueryParameters>()
+            .iter()
+            .chain(struct_fields::<OrderQueryParameters>())
+            .chain(struct_fields::<UnitQueryParameters>())
+            .copied()
+            .collect::<HashSet<_>>();
+        assert_eq!(fields, values.keys().copied().collect());
+
+        let spec = openapi::spec();
+        let filter = all_filter();
+        for path in spec["paths"].as_object().unwrap().keys() {
+            let request_path = request_path(path);
+            let documented = documented_query_parameters(&spec, path);
+            let required = documented
+                .iter()
+                .filter(|(_, required)| *required)
+                .map(|(name, _)| (name.as_str(), values[name.as_str()]))
+                .collect::<Vec<_>>();
+            assert!(
+                !rejects_query(&get(&filter, &request_path, &required)),
+                "{} rejects its required query parameters",
+                path
+            );
+
+            let with_parameter = |name: &'static str, value: &'static str| {
+                let mut query = required.clone();
+                query.push((name, value));
+                get(&filter, &request_path, &query)
+            };
+            if !rejects_query(&with_parameter("unknownParameter", "1")) {
+                assert!(
+                    documented.is_empty(),
+                    "{} documents query parameters but does not parse its query",
+                    path
+                );
+                continue;
+            }
+            for field in &fields {
+                if required.iter().any(|(name, _)| name == field) {
+                    continue;
+                }
+                let accepted = !rejects_query(&with_parameter(*field, values[field]));
+                let is_documented = documented.iter().any(|(name, _)| name == field);
+                assert_eq!(
+                    accepted, is_documented,
+                    "query parameter {} of {} is accepted: {}, documented: {}",
+                    field, path, accepted, is_documented
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn openapi_responses_have_documented_properties() {
+        let spec = openapi::spec();
+        let filter = all_filter();
+        let mut checked_responses = 0;
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            let documented = documented_query_parameters(&spec, path);
+            let mut query = documented
+                .iter()
+                .filter(|(_, required)| *required)
+                .map(|(name, _)| {
+                    let value = QUERY_PARAMETER_VALUES
+                        .iter()
+                        .find(|(field, _)| field == name)
+                        .unwrap()
+                        .1;
+                    (name.as_str(), value)
+                })
+                .collect::<Vec<_>>();
+            // Amounts in base units require token infos, which the test does
+            // not have.
+            if documented.iter().any(|(name, _)| name == "unit") {
+                query.push(("unit", "atoms"));
+            }
+            let (status, body) = get(&filter, &request_path(path), &query);
+            if status != StatusCode::OK {
+                continue;
+            }
+            let schema = resolve(
+                &spec,
+                &item["get"]["responses"]["200"]["content"]["application/json"]["schema"],
+            );
+            let properties = match schema["properties"].as_object() {
+                Some(properties) => properties,
+                None => continue,
+            };
+            assert_eq!(
+                body.as_object().unwrap().keys().collect::<HashSet<_>>(),
+                properties.keys().collect::<HashSet<_>>(),
+                "response of {}",
+                path
+            );
+            checked_responses += 1;
+        }
+        assert!(checked_responses > 0);
     }
 
     #[test]
     fn error_no_token_info() {
-        let response = warp::test::request()
-            .path("/api/v1/markets/0-1/estimated-buy-amount/2?atoms=false&hops=3")
-            .reply(&all_filter())
-            .now_or_never()
-            .unwrap();
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/markets/0-1/estimated-buy-amount/2?atoms=false&hops=3")
+                .reply(&all_filter()),
+        );
         assert_eq!(response.status(), 400);
     }
 
+    #[test]
+    fn error_invalid_parameter() {
+        for path in &[
+            "/api/v1/markets/0-70000/estimated-buy-amount/2",
+            "/api/v1/markets/0-1/estimated-buy-amount/-2",
+            "/api/v1/markets/0-1/estimated-amounts-at-price/0",
+            "/api/v1/prices/70000",
+        ] {
+            let response = block_on(warp::test::request().path(path).reply(&all_filter()));
+            assert_eq!(response.status(), 400);
+            let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(json["code"], "invalidParameter");
+            assert!(json["parameter"].is_string());
+            assert!(json["allowed"].is_string());
+        }
+    }
+
+    #[test]
+    fn error_orderbook_not_initialized() {
+        let token_info = Arc::new(empty_token_info());
+        let orderbook = Arc::new(Orderbook::new(
+            Box::new(NoopOrderbook),
+            Arc::new(PriceCacheUpdater::new(
+                token_info.clone(),
+                Default::default(),
+                Vec::new(),
+            )),
+            1.0,
+            TokenId(1),
+            1,
+        ));
+        let metrics = Arc::new(Metrics::new(&prometheus::Registry::new()).unwrap());
+        let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
+        let filter = all(
+            orderbook,
+            token_info,
+            metrics,
+            economic_viability,
+            test_limits(),
+            None,
+        );
+
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/markets/0-1/estimated-buy-amount/2?atoms=true")
+                .reply(&filter),
+        );
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "10");
+    }
+
+    #[test]
+    fn error_too_many_requests() {
+        let token_info = Arc::new(empty_token_info());
+        let orderbook = Arc::new(Orderbook::new(
+            Box::new(NoopOrderbook),
+            Arc::new(PriceCacheUpdater::new(
+                token_info.clone(),
+                Default::default(),
+                Vec::new(),
+            )),
+            1.0,
+            TokenId(1),
+            1,
+        ));
+        let metrics = Arc::new(Metrics::new(&prometheus::Registry::new()).unwrap());
+        let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
+        let limits = RequestLimits {
+            max_concurrent_requests: 0,
+            ..test_limits()
+        };
+        let filter = all(
+            orderbook,
+            token_info,
+            metrics,
+            economic_viability,
+            limits,
+            None,
+        );
+
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/minimum-order-size-owl")
+                .reply(&filter),
+        );
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "10");
+    }
+
+    #[test]
+    fn error_timeout() {
+        struct SlowTokenInfoFetcher;
+        #[async_trait::async_trait]
+        impl TokenInfoFetching for SlowTokenInfoFetcher {
+            async fn get_token_info(
+                &self,
+                _: TokenId,
+            ) -> Result<services_core::token_info::TokenBaseInfo> {
+                tokio::time::delay_for(Duration::from_secs(1)).await;
+                Err(anyhow!(""))
+            }
+            async fn all_ids(&self) -> Result<Vec<TokenId>> {
+                tokio::time::delay_for(Duration::from_secs(1)).await;
+                Ok(Default::default())
+            }
+        }
+
+        let orderbook = Arc::new(Orderbook::new(
+            Box::new(NoopOrderbook),
+            Arc::new(PriceCacheUpdater::new(
+                Arc::new(empty_token_info()),
+                Default::default(),
+                Vec::new(),
+            )),
+            1.0,
+            TokenId(1),
+            1,
+        ));
+        block_on(orderbook.update()).unwrap();
+        let metrics = Arc::new(Metrics::new(&prometheus::Registry::new()).unwrap());
+        let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
+        let limits = RequestLimits {
+            timeout: Duration::from_millis(1),
+            ..test_limits()
+        };
+        let filter = all(
+            orderbook,
+            Arc::new(SlowTokenInfoFetcher),
+            metrics,
+            economic_viability,
+            limits,
+            None,
+        );
+
+        for path in &[
+            "/api/v1/markets/WETH-DAI?atoms=true",
+            "/api/v1/markets/WETH-DAI/estimated-buy-amount/2?atoms=true",
+            "/api/v1/markets/WETH-DAI/estimated-fee/2?atoms=true",
+            "/api/v1/prices/WETH",
+        ] {
+            let response = block_on(warp::test::request().path(path).reply(&filter));
+            assert_eq!(response.status(), 503, "{}", path);
+            let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(json["code"], "timeout", "{}", path);
+        }
+    }
+
     #[test]
     fn all_filter_ok() {
-        let response = warp::test::request()
-            .path("/api/v1/markets/0-1/estimated-buy-amount/2?atoms=true&hops=3")
-            .reply(&all_filter())
-            .now_or_never()
-            .unwrap();
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/markets/0-1/estimated-buy-amount/2?atoms=true&hops=3")
+                .reply(&all_filter()),
+        );
+        assert_eq!(response.status(), 200);
+    }
+
+    #[test]
+    fn all_filter_estimated_fee_ok() {
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/markets/0-1/estimated-fee/2000?atoms=true")
+                .reply(&all_filter()),
+        );
         assert_eq!(response.status(), 200);
+        let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(json["quoteTokenId"], 1);
+        assert_eq!(json["feeInQuote"], "2");
+    }
+
+    #[test]
+    fn all_filter_native_token_price_ok() {
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/prices/native")
+                .reply(&all_filter()),
+        );
+        assert_eq!(response.status(), 200);
+        let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(json["tokenId"], 1);
+        assert_eq!(json["priceInOwl"], "1000000000000000000");
+        assert!(json["priceInUsd"].is_null());
+    }
+
+    #[test]
+    fn estimates_are_not_modified_for_matching_entity_tag() {
+        let filter = all_filter();
+        let path = "/api/v1/markets/0-1/estimated-buy-amount/2?atoms=true";
+        let response = block_on(warp::test::request().path(path).reply(&filter));
+        assert_eq!(response.status(), 200);
+        let entity_tag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let response = block_on(
+            warp::test::request()
+                .path(path)
+                .header("if-none-match", &entity_tag)
+                .reply(&filter),
+        );
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()[header::ETAG], entity_tag.as_str());
+        assert!(response.body().is_empty());
+
+        let response = block_on(
+            warp::test::request()
+                .path(path)
+                .header("if-none-match", "\"0-0\"")
+                .reply(&filter),
+        );
+        assert_eq!(response.status(), 200);
+    }
+
+    #[test]
+    fn batch_entity_tag_includes_batch_id() {
+        let orderbook = Arc::new(Orderbook::new(
+            Box::new(NoopOrderbook),
+            Arc::new(PriceCacheUpdater::new(
+                Arc::new(empty_token_info()),
+                Default::default(),
+                Vec::new(),
+            )),
+            1.0,
+            TokenId(1),
+            1,
+        ));
+        block_on(orderbook.update()).unwrap();
+        let filter = batch_entity_tag(orderbook);
+
+        let (batch_id, entity_tag) = block_on(warp::test::request().filter(&filter)).unwrap();
+        let entity_tag = entity_tag.unwrap();
+        assert!(entity_tag.ends_with(&format!("-{}\"", batch_id)));
+
+        let stale_batch_tag = entity_tag.replace(
+            &format!("-{}\"", batch_id),
+            &format!("-{}\"", batch_id.prev()),
+        );
+        assert!(block_on(
+            warp::test::request()
+                .header("if-none-match", &stale_batch_tag)
+                .filter(&filter)
+        )
+        .is_ok());
+        assert!(block_on(
+            warp::test::request()
+                .header("if-none-match", &entity_tag)
+                .filter(&filter)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn matches_entity_tags() {
+        assert!(matches_entity_tag("\"a-1\"", "\"a-1\""));
+        assert!(matches_entity_tag("\"a-0\", W/\"a-1\"", "\"a-1\""));
+        assert!(matches_entity_tag("*", "\"a-1\""));
+        assert!(!matches_entity_tag("\"a-0\"", "\"a-1\""));
     }
 
     #[test]
     fn token_by_symbol_and_address() {
         let (pair, _) = warp::test::request()
             .path("/markets/WETH-0x1A5F9352Af8aF974bFC03399e3767DF6370d82e4?atoms=false")
-            .filter(&markets_filter())
+            .filter(&markets_filter(None))
             .now_or_never()
             .unwrap()
             .unwrap();
@@ -488,7 +1667,7 @@ mod tests {
     fn estimated_buy_amount_ok() {
         let (pair, volume, query) = warp::test::request()
             .path("/markets/0-65535/estimated-buy-amount/1?atoms=true&hops=2")
-            .filter(&estimated_buy_amount_filter())
+            .filter(&estimated_buy_amount_filter(None))
             .now_or_never()
             .unwrap()
             .unwrap();
@@ -499,11 +1678,39 @@ mod tests {
         assert_eq!(query.hops, Some(2));
     }
 
+    #[test]
+    fn placeable_order_ok() {
+        let (pair, volume, query) = warp::test::request()
+            .path("/markets/0-1/placeable-order/1?atoms=true&slippage=0.01&validFor=2")
+            .filter(&placeable_order_filter(None))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(pair.base, TokenRef::Id(0));
+        assert_eq!(pair.quote, TokenRef::Id(1));
+        assert_eq!(volume, 1.0);
+        assert_eq!(query.unit, Unit::Atoms);
+        assert_eq!(query.slippage, 0.01);
+        assert_eq!(query.valid_for, 2);
+    }
+
+    #[test]
+    fn placeable_order_without_liquidity() {
+        let response = block_on(
+            warp::test::request()
+                .path("/api/v1/markets/0-1/placeable-order/2000?atoms=true&slippage=0.01")
+                .reply(&all_filter()),
+        );
+        assert_eq!(response.status(), 400);
+        let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(json["code"], "noLiquidity");
+    }
+
     #[test]
     fn markets_ok() {
         let (pair, query) = warp::test::request()
             .path("/markets/1-2?atoms=true&hops=3&batchId=123")
-            .filter(&markets_filter())
+            .filter(&markets_filter(None))
             .now_or_never()
             .unwrap()
             .unwrap();
@@ -514,6 +1721,33 @@ mod tests {
         assert_eq!(query.time, EstimationTime::Batch(123.into()));
     }
 
+    #[test]
+    fn markets_hops_limited() {
+        let filter = markets_filter(Some(3));
+        let (_, query) = warp::test::request()
+            .path("/markets/1-2")
+            .filter(&filter)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(query.hops, Some(3));
+
+        let (_, query) = warp::test::request()
+            .path("/markets/1-2?hops=2")
+            .filter(&filter)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(query.hops, Some(2));
+
+        assert!(warp::test::request()
+            .path("/markets/1-2?hops=4")
+            .filter(&filter)
+            .now_or_never()
+            .unwrap()
+            .is_err());
+    }
+
     #[test]
     fn estimated_buy_amount_too_few_tokens() {
         for path in &[
@@ -522,7 +1756,7 @@ mod tests {
         ] {
             assert!(warp::test::request()
                 .path(path)
-                .filter(&estimated_buy_amount_filter())
+                .filter(&estimated_buy_amount_filter(None))
                 .now_or_never()
                 .unwrap()
                 .is_err());
@@ -539,7 +1773,7 @@ mod tests {
         ] {
             assert!(warp::test::request()
                 .path(path)
-                .filter(&estimated_buy_amount_filter())
+                .filter(&estimated_buy_amount_filter(None))
                 .now_or_never()
                 .unwrap()
                 .is_err());
@@ -554,7 +1788,7 @@ mod tests {
         ] {
             assert!(warp::test::request()
                 .path(path)
-                .filter(&estimated_buy_amount_filter())
+                .filter(&estimated_buy_amount_filter(None))
                 .now_or_never()
                 .unwrap()
                 .is_err());
@@ -565,7 +1799,7 @@ mod tests {
     fn estimated_amounts_at_price_ok() {
         let (pair, volume, query) = warp::test::request()
             .path("/markets/0-65535/estimated-amounts-at-price/0.5?atoms=true")
-            .filter(&estimated_amounts_at_price_filter())
+            .filter(&estimated_amounts_at_price_filter(None))
             .now_or_never()
             .unwrap()
             .unwrap();
@@ -580,7 +1814,7 @@ mod tests {
     fn estimated_best_ask_xrate_ok() {
         let (pair, query) = warp::test::request()
             .path("/markets/0-65535/estimated-best-ask-price?atoms=true")
-            .filter(&estimated_best_ask_price_filter())
+            .filter(&estimated_best_ask_price_filter(None))
             .now_or_never()
             .unwrap()
             .unwrap();
@@ -589,4 +1823,64 @@ mod tests {
         assert_eq!(query.unit, Unit::Atoms);
         assert_eq!(query.hops, None);
     }
+
+    #[test]
+    fn average_price_ok() {
+        let (pair, batches, query) = warp::test::request()
+            .path("/markets/0-1/average-price/5?batchId=42")
+            .filter(&average_price_filter(None))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(pair.base, TokenRef::Id(0));
+        assert_eq!(pair.quote, TokenRef::Id(1));
+        assert_eq!(batches, 5);
+        assert_eq!(query.time, EstimationTime::Batch(BatchId(42)));
+    }
+
+    #[test]
+    fn average_price_invalid_batches() {
+        for path in &[
+            "/markets/0-1/average-price/0",
+            "/markets/0-1/average-price/11",
+        ] {
+            assert!(warp::test::request()
+                .path(path)
+                .filter(&average_price_filter(None))
+                .now_or_never()
+                .unwrap()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn estimated_fee_ok() {
+        let (pair, sell_amount, query) = warp::test::request()
+            .path("/markets/0-1/estimated-fee/100?atoms=false")
+            .filter(&estimated_fee_filter())
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(pair.base, TokenRef::Id(0));
+        assert_eq!(pair.quote, TokenRef::Id(1));
+        assert!((sell_amount - 100.0).abs() < f64::EPSILON);
+        assert_eq!(query.unit, Unit::BaseUnits);
+    }
+
+    #[test]
+    fn price_ok() {
+        for (path, expected) in &[
+            ("/prices/native", None),
+            ("/prices/7", Some(TokenRef::Id(7))),
+            ("/prices/WETH", Some(TokenRef::Symbol("WETH".to_owned()))),
+        ] {
+            let token = warp::test::request()
+                .path(path)
+                .filter(&price_filter())
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            assert_eq!(token, *expected);
+        }
+    }
 }
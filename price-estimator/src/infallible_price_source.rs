@@ -1,15 +1,19 @@
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use pricegraph::Pricegraph;
 use services_core::{
     models::TokenId,
-    price_estimation::{average_price_source, price_source::PriceSource},
-    token_info::{TokenBaseInfo, TokenInfoFetching},
+    price_estimation::{
+        average_price_source,
+        price_source::{InfalliblePriceSource, PriceSource, PriceSourceKind},
+        restricted_price_source::RestrictedPriceSource,
+    },
+    token_info::{hardcoded::TokenData, TokenBaseInfo, TokenInfoFetching},
 };
 use std::{collections::HashMap, num::NonZeroU128, sync::Arc};
-use tokio::sync::{RwLock, RwLockReadGuard};
 
 /// Roughly like `PriceSource` but is updated externally and cannot fail.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct PriceCache {
     tokens: HashMap<TokenId, TokenBaseInfo>,
     prices: HashMap<TokenId, NonZeroU128>,
@@ -43,53 +47,77 @@ impl PriceCache {
 }
 
 /// Infallible price source that is updated with the average of external price sources and the
-/// pricegraph price source.
+/// pricegraph price source. The token data restricts which sources are used for each token.
+///
+/// Updates swap in a new cache so reading prices never waits for an update.
 pub struct PriceCacheUpdater {
     token_info: Arc<dyn TokenInfoFetching>,
+    token_data: Arc<TokenData>,
     external_price_sources: Vec<Box<dyn PriceSource + Send + Sync>>,
-    inner: RwLock<PriceCache>,
+    inner: ArcSwap<PriceCache>,
 }
 
 impl PriceCacheUpdater {
     pub fn new(
         token_info: Arc<dyn TokenInfoFetching>,
+        token_data: Arc<TokenData>,
         external_price_sources: Vec<Box<dyn PriceSource + Send + Sync>>,
     ) -> Self {
         Self {
             token_info,
+            token_data,
             external_price_sources,
             inner: Default::default(),
         }
     }
 
-    pub async fn inner(&self) -> RwLockReadGuard<'_, PriceCache> {
-        self.inner.read().await
+    pub fn inner(&self) -> Arc<PriceCache> {
+        self.inner.load_full()
     }
 
     pub async fn update_tokens(&self) -> Result<()> {
         let all_tokens = self.token_info.all_ids().await?;
         let tokens = self.token_info.get_token_infos(&all_tokens).await?;
-        self.inner.write().await.update_tokens(tokens);
+        self.inner.rcu(|cache| {
+            let mut cache = PriceCache::clone(cache);
+            cache.update_tokens(tokens.clone());
+            cache
+        });
         Ok(())
     }
 
     pub async fn update_prices(&self, pricegraph: &Pricegraph) -> Result<()> {
         let all_tokens = self.token_info.all_ids().await?;
+        let pricegraph = RestrictedPriceSource::new(
+            PriceSourceKind::Pricegraph,
+            self.token_data.clone(),
+            pricegraph,
+        );
         let prices = average_price_source::average_price_sources(
             self.external_price_sources
                 .iter()
                 .map(|source| source.as_ref() as &(dyn PriceSource + Send + Sync))
                 .chain(std::iter::once(
-                    pricegraph as &(dyn PriceSource + Send + Sync),
+                    &pricegraph as &(dyn PriceSource + Send + Sync),
                 )),
             &all_tokens,
         )
         .await?;
-        self.inner.write().await.update_prices(&prices);
+        self.inner.rcu(|cache| {
+            let mut cache = PriceCache::clone(cache);
+            cache.update_prices(&prices);
+            cache
+        });
         Ok(())
     }
 }
 
+impl InfalliblePriceSource for PriceCacheUpdater {
+    fn price(&self, token_id: TokenId) -> NonZeroU128 {
+        self.inner().price(token_id)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,37 +1,44 @@
+mod accuracy;
 mod amounts_at_price;
+mod blocking;
 mod error;
 mod filter;
 mod infallible_price_source;
 mod metrics;
 mod models;
+mod openapi;
+mod order_placement;
 mod orderbook;
+mod request_limits;
 mod solver_rounding_buffer;
+mod warmup;
 
-use ethcontract::PrivateKey;
 use infallible_price_source::PriceCacheUpdater;
 use metrics::Metrics;
 use orderbook::Orderbook;
 use prometheus::Registry;
+use request_limits::RequestLimits;
 use services_core::{
-    contracts::{stablex_contract::StableXContractImpl, web3_provider},
-    economic_viability::EconomicViabilityStrategy,
+    config::{self, duration_secs, ConfigOptions, EconomicViabilityOptions, OrderbookOptions},
+    contracts::{
+        stablex_contract::{CachedStableXContract, StableXContractImpl},
+        web3_provider,
+    },
     gas_price::{self, GasEstimatorType},
     health::{HealthReporting, HttpHealthEndpoint},
-    http::HttpFactory,
-    http_server::{DefaultRouter, RouilleServer, Serving},
+    http::{CircuitBreakerOptions, HttpFactory, HttpPoolOptions, HttpProfiles, HttpProxyOptions},
+    http_server::{DefaultRouter, Handler, RouilleServer, Serving},
     logging,
     metrics::{HttpMetrics, MetricsHandler},
-    orderbook::{EventBasedOrderbook, FilteredOrderbookReader, OrderbookFilter},
+    orderbook::{EventBasedOrderbook, FilteredOrderbookReader},
     token_info::{cached::TokenInfoCache, hardcoded::TokenData},
-    util::FutureWaitExt as _,
-};
-use std::{
-    collections::HashMap, net::SocketAddr, num::ParseIntError, path::PathBuf, sync::Arc,
-    time::Duration,
+    transport::RetryPolicy,
 };
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use structopt::StructOpt;
 use tokio::{runtime, time};
 use url::Url;
+use warmup::{Warmup, WarmupEstimate};
 use warp::Filter;
 
 #[derive(Debug, StructOpt)]
@@ -52,6 +59,8 @@ struct Options {
 
     /// The Ethereum node URL to connect to. Make sure that the node allows for
     /// queries without a gas limit to be able to fetch the orderbook.
+    /// `ws://` and `ipc://` URLs connect over a WebSocket or IPC socket, which
+    /// lets the node push new blocks instead of being polled for them.
     #[structopt(long, env = "NODE_URL")]
     node_url: Url,
 
@@ -64,8 +73,21 @@ struct Options {
     )]
     rpc_timeout: Duration,
 
-    #[structopt(long, env = "ORDERBOOK_FILE", parse(from_os_str))]
-    orderbook_file: Option<PathBuf>,
+    #[structopt(flatten)]
+    http_pool: HttpPoolOptions,
+
+    #[structopt(flatten)]
+    http_circuit_breaker: CircuitBreakerOptions,
+
+    /// JSON encoded HTTP client profiles of the remote services (`node`,
+    /// `gasStation`, `priceApi` and `alerting`) with an optional timeout in
+    /// seconds, number of retries, headers and proxy URL, for example
+    /// '{"gasStation": {"timeout": 2, "retries": 1}}'.
+    #[structopt(long, env = "HTTP_PROFILES", default_value = "{}")]
+    http_profiles: HttpProfiles,
+
+    #[structopt(flatten)]
+    http_proxy: HttpProxyOptions,
 
     #[structopt(
         long,
@@ -84,29 +106,25 @@ struct Options {
     )]
     price_source_update_interval: Duration,
 
+    /// Whether to keep Kraken prices up to date through a WebSocket ticker
+    /// subscription instead of only polling the REST API every price source
+    /// update interval.
+    #[structopt(
+        long,
+        env = "USE_KRAKEN_WEBSOCKET",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    use_kraken_websocket: bool,
+
     /// JSON encoded backup token information like in the driver. Used as an override to the ERC20
     /// information we fetch from the block chain in case that information is wrong or unavailable
     /// which can happen for example when tokens do not implement the standard properly.
     #[structopt(long, env = "TOKEN_DATA", default_value = "{}")]
     token_data: TokenData,
 
-    /// JSON encoded object of which tokens/orders to ignore.
-    ///
-    /// For example: '{
-    ///   "tokens": {"Whitelist": [1, 2]},
-    ///   "users": {
-    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0A": { "OrderIds": [0, 1] },
-    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0B": "All"
-    ///   }
-    ///  }'
-    /// More examples can be found in the tests of orderbook/filtered_orderboook.rs
-    #[structopt(long, env = "ORDERBOOK_FILTER", default_value = "{}")]
-    orderbook_filter: OrderbookFilter,
-
-    /// The number of blocks to fetch events for at a time for constructing the
-    /// orderbook.
-    #[structopt(long, env = "AUCTION_DATA_PAGE_SIZE", default_value = "500")]
-    auction_data_page_size: usize,
+    #[structopt(flatten)]
+    orderbook: OrderbookOptions,
 
     /// An extra factor to multiply calculated rounding buffers with. Setting this to >1 protects
     /// makes prices mores conservative protecting against changes between the time a user requested
@@ -114,33 +132,17 @@ struct Options {
     #[structopt(long, env = "EXTRA_ROUNDING_BUFFER_FACTOR", default_value = "2.0")]
     extra_rounding_buffer_factor: f64,
 
-    // These are copies of the same arguments used in the driver for economic viability.
-    // It would be nice if we could avoid copy pasting these. Maybe structopt/clap has a feature
-    // like serde's flatten that would allow us to do this.
+    /// Subsidy factor used to compute the minimum average fee per order in a
+    /// solution as well as the gas cap for economically viable solution.
     #[structopt(
         long,
         env = "ECONOMIC_VIABILITY_SUBSIDY_FACTOR",
         default_value = "10.0"
     )]
     economic_viability_subsidy_factor: f64,
-    #[structopt(
-        long,
-        env = "ECONOMIC_VIABILITY_MIN_AVG_FEE_FACTOR",
-        default_value = "1.1"
-    )]
-    economic_viability_min_avg_fee_factor: f64,
-    #[structopt(long, env = "STATIC_MIN_AVG_FEE_PER_ORDER")]
-    static_min_avg_fee_per_order: Option<u128>,
-    #[structopt(long, env = "STATIC_MAX_GAS_PRICE")]
-    static_max_gas_price: Option<u128>,
-    #[structopt(
-        long,
-        env = "ECONOMIC_VIABILITY_STRATEGY",
-        default_value = "Dynamic",
-        possible_values = EconomicViabilityStrategy::variant_names(),
-        case_insensitive = true,
-    )]
-    economic_viability_strategy: EconomicViabilityStrategy,
+
+    #[structopt(flatten)]
+    economic_viability: EconomicViabilityOptions,
 
     /// ID for the token which is used to pay network transaction fees on the
     /// target chain (e.g. WETH on mainnet, DAI on xDAI).
@@ -156,107 +158,216 @@ struct Options {
         use_delimiter = true
     )]
     gas_estimators: Vec<GasEstimatorType>,
+
+    /// The maximum number of requests that are handled concurrently. Further
+    /// requests are rejected with status 429.
+    #[structopt(long, env = "MAX_CONCURRENT_REQUESTS", default_value = "64")]
+    max_concurrent_requests: usize,
+
+    /// The maximum time in seconds spent handling a single request before it
+    /// is rejected with status 503.
+    #[structopt(
+        long,
+        env = "REQUEST_TIMEOUT",
+        default_value = "10",
+        parse(try_from_str = duration_secs),
+    )]
+    request_timeout: Duration,
+
+    /// The maximum number of hops (i.e. maximum ring trade length) of the
+    /// `pricegraph` search a request can ask for. Requests that don't specify
+    /// the number of hops use this maximum. If unset, the search is unbounded
+    /// by default.
+    #[structopt(long, env = "MAX_HOPS")]
+    max_hops: Option<usize>,
+
+    /// Comma separated estimates of popular markets that are computed after
+    /// every orderbook update, so that the first requests after an update
+    /// don't pay for the full graph search. Estimates have the format
+    /// `<sell token id>-<buy token id>:<sell amount in atoms>`.
+    #[structopt(long, env = "WARMUP_ESTIMATES", use_delimiter = true)]
+    warmup_estimates: Vec<WarmupEstimate>,
+
+    /// The maximum number of cpu heavy pricegraph computations that run
+    /// concurrently on the blocking thread pool.
+    #[structopt(long, env = "MAX_CONCURRENT_COMPUTATIONS", default_value = "4")]
+    max_concurrent_computations: usize,
+
+    /// The directory containing the `swagger-ui.css` and `swagger-ui-bundle.js`
+    /// assets of the `swagger-ui-dist` package, which are served with the
+    /// interactive documentation at `/api/v1/docs`. The documentation is not
+    /// served if it is not set.
+    #[structopt(long, env = "SWAGGER_UI_DIR")]
+    swagger_ui_dir: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    config: ConfigOptions,
 }
 
 fn main() {
-    let options = Options::from_args();
+    let options: Options = config::from_args();
+    if options.config.print_config {
+        println!("{:#?}", options);
+        return;
+    }
     let (_, _guard) = logging::init(&options.log_filter);
     log::info!(
         "Starting price estimator with runtime options: {:#?}",
         options
     );
 
-    let (metrics, driver_http_metrics, health) = setup_monitoring();
+    let checkpoint_file = options.orderbook.checkpoint_file();
+    let (metrics, driver_http_metrics, health) =
+        setup_monitoring(options.orderbook.snapshot_handler(checkpoint_file.clone()));
     let metrics = Arc::new(metrics);
-    let http_factory = HttpFactory::new(options.rpc_timeout, driver_http_metrics);
+    let http_factory = HttpFactory::new(
+        options.rpc_timeout,
+        options.http_pool,
+        options.http_circuit_breaker,
+        driver_http_metrics,
+    )
+    .with_profiles(options.http_profiles.clone())
+    .with_proxy(options.http_proxy.clone());
+    let mut runtime = runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+
     let web3 = web3_provider(
         &http_factory,
         options.node_url.as_str(),
         options.rpc_timeout,
+        RetryPolicy::default(),
     )
     .unwrap();
-    // The private key is not actually used but StableXContractImpl requires it.
-    let private_key = PrivateKey::from_raw([1u8; 32]).unwrap();
-    let contract = Arc::new(
-        StableXContractImpl::new(&web3, private_key, false)
-            .wait()
-            .unwrap(),
-    );
-    let gas_station =
-        gas_price::create_priority_estimator(&http_factory, &web3, &options.gas_estimators)
-            .wait()
-            .unwrap();
+    // The exchange contract and the gas estimators only depend on the node.
+    let (contract, gas_station) = runtime.block_on(async {
+        futures::join!(
+            StableXContractImpl::read_only(&web3),
+            gas_price::create_priority_estimator(&http_factory, &web3, &options.gas_estimators),
+        )
+    });
+    let contract = Arc::new(contract.unwrap());
+    let gas_station = gas_station.unwrap();
 
+    let cached_contract = Arc::new(CachedStableXContract::new(contract.clone()));
     let cache: HashMap<_, _> = options.token_data.clone().into();
-    let token_info = TokenInfoCache::with_cache(contract.clone(), cache);
-    token_info
-        .cache_all()
-        .wait()
-        .expect("failed to cache token infos");
-    let token_info = Arc::new(token_info);
-
-    let orderbook = Box::new(FilteredOrderbookReader::new(
-        Box::new(EventBasedOrderbook::new(
-            contract,
-            web3,
-            options.auction_data_page_size,
-            options.orderbook_file,
-        )),
-        options.orderbook_filter.clone(),
-    ));
+    let token_info = Arc::new(TokenInfoCache::with_cache(cached_contract.clone(), cache));
+
+    let event_sink = options
+        .orderbook
+        .event_sink()
+        .expect("failed to create event sink");
+    let checkpoint_source = options
+        .orderbook
+        .checkpoint_source(&http_factory)
+        .expect("failed to create orderbook checkpoint source");
+    let mut event_based_orderbook = EventBasedOrderbook::new(
+        contract,
+        web3,
+        options.orderbook.auction_data_page_size,
+        options.orderbook.orderbook_file,
+        options.orderbook.orderbook_reindex_from_block,
+    )
+    .with_event_buffer_size(options.orderbook.orderbook_event_buffer_size)
+    .with_cached_contract(cached_contract);
+    if let Some(event_sink) = event_sink {
+        event_based_orderbook = event_based_orderbook.with_event_sink(event_sink);
+    }
+    if let Some(checkpoint_source) = checkpoint_source {
+        event_based_orderbook = event_based_orderbook.with_checkpoint_source(checkpoint_source);
+    }
+    if let Some(checkpoint_file) = checkpoint_file {
+        event_based_orderbook = event_based_orderbook.with_checkpoint_file(checkpoint_file);
+    }
 
+    let token_data = Arc::new(options.token_data.clone());
     let external_price_sources = services_core::price_estimation::external_price_sources(
         &http_factory,
         token_info.clone(),
+        token_data.clone(),
         options.price_source_update_interval,
+        options.use_kraken_websocket,
     )
     .expect("failed to create external price sources");
-    let infallible_price_source =
-        PriceCacheUpdater::new(token_info.clone(), external_price_sources);
+    let infallible_price_source = Arc::new(PriceCacheUpdater::new(
+        token_info.clone(),
+        token_data,
+        external_price_sources,
+    ));
+
+    let orderbook = Box::new(
+        FilteredOrderbookReader::new(
+            Box::new(event_based_orderbook),
+            options.orderbook.orderbook_filter.clone(),
+        )
+        .with_price_source(infallible_price_source.clone()),
+    );
 
     let orderbook = Arc::new(Orderbook::new(
         orderbook,
         infallible_price_source,
         options.extra_rounding_buffer_factor,
         options.native_token_id.into(),
+        options.max_concurrent_computations,
     ));
-    let _ = orderbook.update().wait();
+
+    // Filling the token info cache and the initial orderbook update are
+    // independent of each other. The update needs to run on the runtime because
+    // it uses its blocking thread pool.
+    let (token_info_result, _) =
+        runtime.block_on(async { futures::join!(token_info.cache_all(), orderbook.update()) });
+    token_info_result.expect("failed to cache token infos");
     log::info!("Orderbook initialized.");
 
     let economic_viability = options
-        .economic_viability_strategy
-        .from_arguments(
+        .economic_viability
+        .create(
             options.economic_viability_subsidy_factor,
-            options.economic_viability_min_avg_fee_factor,
-            options.static_min_avg_fee_per_order,
-            options.static_max_gas_price,
             orderbook.clone(),
             gas_station.clone(),
         )
         .unwrap();
 
-    let mut runtime = runtime::Builder::new()
-        .threaded_scheduler()
-        .enable_all()
-        .build()
-        .unwrap();
-
+    let warmup = Arc::new(Warmup::new(
+        options.warmup_estimates.clone(),
+        options.max_hops,
+        metrics.clone(),
+    ));
     let orderbook_task = runtime.spawn(update_orderbook_forever(
         orderbook.clone(),
         options.orderbook_update_interval,
+        warmup,
+    ));
+    runtime.spawn(accuracy::track_accuracy_forever(
+        orderbook.clone(),
+        metrics.clone(),
     ));
 
     // We add the allow origin header so that requests from the interactive openapi documentation
     // go through to locally running instance. This does mean we set the header for non openapi
     // requests too. This doesn't have security implications because this is a public,
     // unauthenticated api anyway.
-    let filter = filter::all(orderbook, token_info, metrics.clone(), economic_viability)
-        .with(warp::log::custom(move |info| metrics.handle_response(info)))
-        .with(warp::log("price_estimator"))
-        .with(warp::reply::with::header(
-            "Access-Control-Allow-Origin",
-            "*",
-        ));
+    let limits = RequestLimits {
+        max_concurrent_requests: options.max_concurrent_requests,
+        timeout: options.request_timeout,
+        max_hops: options.max_hops,
+    };
+    let filter = filter::all(
+        orderbook,
+        token_info,
+        metrics.clone(),
+        economic_viability,
+        limits,
+        options.swagger_ui_dir.clone(),
+    )
+    .with(warp::log::custom(move |info| metrics.handle_response(info)))
+    .with(warp::log("price_estimator"))
+    .with(warp::reply::with::header(
+        "Access-Control-Allow-Origin",
+        "*",
+    ));
     let serve_task = runtime.spawn(warp::serve(filter).run(options.bind_address));
 
     log::info!("Server ready.");
@@ -269,20 +380,25 @@ fn main() {
     });
 }
 
-async fn update_orderbook_forever(orderbook: Arc<Orderbook>, update_interval: Duration) -> ! {
+async fn update_orderbook_forever(
+    orderbook: Arc<Orderbook>,
+    update_interval: Duration,
+    warmup: Arc<Warmup>,
+) -> ! {
+    // The initial update happens before the server is started.
+    warmup.spawn(orderbook.clone());
     loop {
         time::delay_for(update_interval).await;
-        if let Err(err) = orderbook.update().await {
-            log::error!("error updating orderbook: {:?}", err);
+        match orderbook.update().await {
+            Ok(()) => warmup.spawn(orderbook.clone()),
+            Err(err) => log::error!("error updating orderbook: {:?}", err),
         }
     }
 }
 
-fn duration_secs(s: &str) -> Result<Duration, ParseIntError> {
-    Ok(Duration::from_secs(s.parse()?))
-}
-
-fn setup_monitoring() -> (Metrics, HttpMetrics, Arc<dyn HealthReporting>) {
+fn setup_monitoring(
+    orderbook_snapshot: Option<Arc<dyn Handler>>,
+) -> (Metrics, HttpMetrics, Arc<dyn HealthReporting>) {
     let health = Arc::new(HttpHealthEndpoint::new());
     let prometheus_registry = Arc::new(Registry::new());
 
@@ -290,6 +406,9 @@ fn setup_monitoring() -> (Metrics, HttpMetrics, Arc<dyn HealthReporting>) {
     RouilleServer::new(DefaultRouter {
         metrics: Arc::new(metric_handler),
         health_readiness: health.clone(),
+        startup_progress: None,
+        submission_receipts: None,
+        orderbook_snapshot,
     })
     .start_in_background();
 
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use pricegraph::TokenPair;
 use prometheus::{Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
 use std::time::Instant;
 use warp::log::Info;
@@ -14,6 +15,9 @@ pub struct Metrics {
     response_status: IntCounterVec,
     response_time: Histogram,
     response_time_per_route: HistogramVec,
+    shed_requests: IntCounterVec,
+    estimate_errors: HistogramVec,
+    warmup_time: Histogram,
 }
 
 impl Metrics {
@@ -39,10 +43,37 @@ impl Metrics {
         let response_time_per_route = HistogramVec::new(opts, &["route"]).unwrap();
         registry.register(Box::new(response_time_per_route.clone()))?;
 
+        let opts = Opts::new(
+            "price_estimator_shed_requests",
+            "The number of requests rejected because of the concurrency limit or timeout.",
+        );
+        let shed_requests = IntCounterVec::new(opts, &["reason"]).unwrap();
+        registry.register(Box::new(shed_requests.clone()))?;
+
+        let opts = HistogramOpts::new(
+            "price_estimator_estimate_relative_error",
+            "The error of estimated exchange rates relative to the rates at which trades were settled, per market.",
+        )
+        .buckets(vec![
+            -0.5, -0.2, -0.1, -0.05, -0.02, -0.01, 0.0, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5,
+        ]);
+        let estimate_errors = HistogramVec::new(opts, &["market"]).unwrap();
+        registry.register(Box::new(estimate_errors.clone()))?;
+
+        let opts = HistogramOpts::new(
+            "price_estimator_warmup_time",
+            "The duration it takes to warm up the estimates of popular markets after an orderbook update.",
+        );
+        let warmup_time = Histogram::with_opts(opts).unwrap();
+        registry.register(Box::new(warmup_time.clone()))?;
+
         Ok(Self {
             response_status,
             response_time,
             response_time_per_route,
+            shed_requests,
+            estimate_errors,
+            warmup_time,
         })
     }
 
@@ -53,6 +84,21 @@ impl Metrics {
             .observe(response_time);
     }
 
+    pub fn request_shed(&self, reason: &str) {
+        self.shed_requests.with_label_values(&[reason]).inc();
+    }
+
+    pub fn estimate_error(&self, pair: TokenPair, relative_error: f64) {
+        let market = format!("{}-{}", pair.sell, pair.buy);
+        self.estimate_errors
+            .with_label_values(&[&market])
+            .observe(relative_error);
+    }
+
+    pub fn warmup_finished(&self, start: Instant) {
+        self.warmup_time.observe(start.elapsed().as_secs_f64());
+    }
+
     pub fn handle_response(&self, info: Info<'_>) {
         let status = info.status();
         self.response_status
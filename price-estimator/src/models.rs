@@ -3,9 +3,11 @@ mod markets_results;
 mod query;
 
 pub use self::{currency_pair::*, markets_results::*, query::*};
+use crate::order_placement::PlaceableOrder;
+use ethcontract::web3::types::Bytes;
 use serde::Serialize;
 use serde_with::rust::display_fromstr;
-use services_core::token_info::TokenBaseInfo;
+use services_core::{models::BatchId, token_info::TokenBaseInfo};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -16,6 +18,63 @@ pub struct EstimatedOrderResult {
     pub sell_amount_in_quote: Amount,
 }
 
+/// An order that is ready to be signed and placed on the exchange. Amounts are
+/// always in atoms because that is what the contract expects.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPlacementResult {
+    pub buy_token_id: u16,
+    pub sell_token_id: u16,
+    pub valid_from: BatchId,
+    pub valid_until: BatchId,
+    #[serde(with = "display_fromstr")]
+    pub buy_amount: u128,
+    #[serde(with = "display_fromstr")]
+    pub sell_amount: u128,
+    /// The encoded `placeOrder` call for the order.
+    pub calldata: Bytes,
+}
+
+impl From<PlaceableOrder> for OrderPlacementResult {
+    fn from(order: PlaceableOrder) -> Self {
+        Self {
+            buy_token_id: order.buy_token,
+            sell_token_id: order.sell_token,
+            valid_from: order.valid_from,
+            valid_until: order.valid_until,
+            buy_amount: order.buy_amount,
+            sell_amount: order.sell_amount,
+            calldata: order.calldata(),
+        }
+    }
+}
+
+/// The fee an order would pay compared to the minimum fee it needs to pay in
+/// order to be considered by the solver.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimatedFeeResult {
+    pub quote_token_id: u16,
+    pub sell_amount_in_quote: Amount,
+    pub fee_in_quote: Amount,
+    pub minimum_fee_in_quote: Amount,
+    pub minimum_fee_in_owl: Amount,
+}
+
+/// The price estimate for a token that is used by the solver.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceResult {
+    pub token_id: u16,
+    /// The price in OWL atoms for 10^18 atoms of the token, this is the
+    /// format the smart contract expects.
+    #[serde(with = "display_fromstr")]
+    pub price_in_owl: u128,
+    /// The price in USD for one base unit of the token. This is `None` if
+    /// the ERC20 info of the token is not known.
+    pub price_in_usd: Option<f64>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct TransitiveOrder {
     pub price: f64,
@@ -81,8 +140,28 @@ impl PriceEstimateResult {
 }
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ErrorResult {
+    /// Machine readable identifier of the kind of error.
+    pub code: &'static str,
     pub message: &'static str,
+    /// The request parameter that caused the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<&'static str>,
+    /// Description of the values accepted for the parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<&'static str>,
+}
+
+impl ErrorResult {
+    pub fn new(code: &'static str, message: &'static str) -> Self {
+        Self {
+            code,
+            message,
+            parameter: None,
+            allowed: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -111,6 +190,19 @@ mod tests {
         assert_eq!(json, expected);
     }
 
+    #[test]
+    fn order_placement_serialization() {
+        let order = PlaceableOrder::new(1, 2, 3, 4, BatchId(5), 1);
+        let json = serde_json::to_value(OrderPlacementResult::from(order)).unwrap();
+        assert_eq!(json["buyTokenId"], 1);
+        assert_eq!(json["sellTokenId"], 2);
+        assert_eq!(json["validFrom"], 5);
+        assert_eq!(json["validUntil"], 5);
+        assert_eq!(json["buyAmount"], "3");
+        assert_eq!(json["sellAmount"], "4");
+        assert!(json["calldata"].as_str().unwrap().starts_with("0x26c3d394"));
+    }
+
     #[test]
     fn amount_unit_conversion() {
         let owl = TokenBaseInfo {
@@ -8,7 +8,11 @@ use std::convert::TryFrom;
 
 // It never makes sense to have more than 30 hops because we cannot have more orders in one batch.
 // A large number of hops is also a DOS attack vector because we allocate memory proportionally.
-const MAX_HOPS: usize = 30;
+pub const MAX_HOPS: usize = 30;
+
+/// The number of batches orders created by the order placement route are
+/// valid for if not specified otherwise.
+const DEFAULT_ORDER_VALIDITY_BATCHES: u32 = 6;
 
 /// Common query parameters shared across all price estimation routes.
 #[derive(Clone, Debug, Deserialize)]
@@ -26,6 +30,62 @@ pub struct QueryParameters {
     pub rounding_buffer: RoundingBuffer,
 }
 
+/// Query parameters of the order placement route. Orders are always created
+/// for the current orderbook with the rounding buffer applied, as this is what
+/// the solver sees.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawOrderQuery")]
+pub struct OrderQueryParameters {
+    /// The unit of the sell amount.
+    pub unit: Unit,
+    pub hops: Option<usize>,
+    pub ignore_addresses: Vec<Address>,
+    /// The fraction by which the estimated buy amount is reduced so that the
+    /// order still gets matched if prices move against it.
+    pub slippage: f64,
+    /// The number of batches, starting with the current one, the order is
+    /// valid for.
+    pub valid_for: u32,
+}
+
+/// Query parameters of routes that only convert amounts between units.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "RawUnitQuery")]
+pub struct UnitQueryParameters {
+    /// The unit of the token amounts.
+    pub unit: Unit,
+}
+
+/// Query parameters containing the number of hops of the `pricegraph` search.
+pub trait HopsQuery {
+    fn hops_mut(&mut self) -> &mut Option<usize>;
+}
+
+impl HopsQuery for QueryParameters {
+    fn hops_mut(&mut self) -> &mut Option<usize> {
+        &mut self.hops
+    }
+}
+
+impl HopsQuery for OrderQueryParameters {
+    fn hops_mut(&mut self) -> &mut Option<usize> {
+        &mut self.hops
+    }
+}
+
+impl OrderQueryParameters {
+    /// The parameters used to estimate the buy amount of the order.
+    pub fn estimation_query(&self) -> QueryParameters {
+        QueryParameters {
+            unit: self.unit,
+            hops: self.hops,
+            time: EstimationTime::Now,
+            ignore_addresses: self.ignore_addresses.clone(),
+            rounding_buffer: RoundingBuffer::Enabled,
+        }
+    }
+}
+
 /// Units for token amounts.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -85,25 +145,33 @@ struct RawQuery {
     rounding_buffer: Option<RoundingBuffer>,
 }
 
+/// Intermediate raw order query parameters used for parsing.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct RawOrderQuery {
+    atoms: Option<bool>,
+    unit: Option<Unit>,
+    hops: Option<usize>,
+    ignore_addresses: Option<String>,
+    slippage: f64,
+    valid_for: Option<u32>,
+}
+
+/// Intermediate raw unit query parameters used for parsing.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawUnitQuery {
+    atoms: Option<bool>,
+    unit: Option<Unit>,
+}
+
 impl TryFrom<RawQuery> for QueryParameters {
     type Error = Error;
 
     fn try_from(raw: RawQuery) -> Result<Self> {
-        if let Some(hops) = raw.hops {
-            anyhow::ensure!(
-                hops <= MAX_HOPS,
-                "hops parameter is limited to {}",
-                MAX_HOPS
-            );
-        }
+        validate_hops(raw.hops)?;
         Ok(QueryParameters {
-            unit: match (raw.atoms, raw.unit) {
-                (Some(true), None) => Unit::Atoms,
-                (Some(false), None) => Unit::BaseUnits,
-                (None, Some(unit)) => unit,
-                (None, None) => Unit::default(),
-                _ => bail!("only one of 'atoms' or 'unit' parameters can be specified"),
-            },
+            unit: parse_unit(raw.atoms, raw.unit)?,
             hops: raw.hops,
             time: match (raw.batch_id, raw.block_number, raw.timestamp) {
                 (None, None, None) => EstimationTime::Now,
@@ -118,6 +186,63 @@ impl TryFrom<RawQuery> for QueryParameters {
     }
 }
 
+impl TryFrom<RawOrderQuery> for OrderQueryParameters {
+    type Error = Error;
+
+    fn try_from(raw: RawOrderQuery) -> Result<Self> {
+        validate_hops(raw.hops)?;
+        anyhow::ensure!(
+            (0.0..1.0).contains(&raw.slippage),
+            "slippage must be at least 0 and less than 1"
+        );
+        let valid_for = raw.valid_for.unwrap_or(DEFAULT_ORDER_VALIDITY_BATCHES);
+        anyhow::ensure!(valid_for > 0, "orders must be valid for at least one batch");
+        Ok(OrderQueryParameters {
+            unit: parse_unit(raw.atoms, raw.unit)?,
+            hops: raw.hops,
+            ignore_addresses: raw
+                .ignore_addresses
+                .as_deref()
+                .map(parse_addresses)
+                .transpose()?
+                .unwrap_or_default(),
+            slippage: raw.slippage,
+            valid_for,
+        })
+    }
+}
+
+impl TryFrom<RawUnitQuery> for UnitQueryParameters {
+    type Error = Error;
+
+    fn try_from(raw: RawUnitQuery) -> Result<Self> {
+        Ok(UnitQueryParameters {
+            unit: parse_unit(raw.atoms, raw.unit)?,
+        })
+    }
+}
+
+fn validate_hops(hops: Option<usize>) -> Result<()> {
+    if let Some(hops) = hops {
+        anyhow::ensure!(
+            hops <= MAX_HOPS,
+            "hops parameter is limited to {}",
+            MAX_HOPS
+        );
+    }
+    Ok(())
+}
+
+fn parse_unit(atoms: Option<bool>, unit: Option<Unit>) -> Result<Unit> {
+    Ok(match (atoms, unit) {
+        (Some(true), None) => Unit::Atoms,
+        (Some(false), None) => Unit::BaseUnits,
+        (None, Some(unit)) => unit,
+        (None, None) => Unit::default(),
+        _ => bail!("only one of 'atoms' or 'unit' parameters can be specified"),
+    })
+}
+
 fn parse_addresses(string: &str) -> Result<Vec<Address>> {
     string.split(',').map(parse_address).collect()
 }
@@ -240,10 +365,59 @@ mod tests {
         assert!(query_params("?unit=atoms&atoms=true").is_err());
     }
 
+    fn order_query_params(params: &str) -> Result<OrderQueryParameters, Rejection> {
+        warp::test::request()
+            .path(&format!("/{}", params))
+            .filter(&warp::query::<OrderQueryParameters>())
+            .now_or_never()
+            .unwrap()
+    }
+
+    #[test]
+    fn order_query_parameters() {
+        let query = order_query_params("?slippage=0.01").unwrap();
+        assert_eq!(query.unit, Unit::BaseUnits);
+        assert_eq!(query.slippage, 0.01);
+        assert_eq!(query.valid_for, DEFAULT_ORDER_VALIDITY_BATCHES);
+        assert_eq!(query.estimation_query().time, EstimationTime::Now);
+        assert_eq!(
+            query.estimation_query().rounding_buffer,
+            RoundingBuffer::Enabled
+        );
+
+        let query = order_query_params("?atoms=true&slippage=0&validFor=1").unwrap();
+        assert_eq!(query.unit, Unit::Atoms);
+        assert_eq!(query.valid_for, 1);
+    }
+
+    #[test]
+    fn invalid_order_query_parameters() {
+        assert!(order_query_params("").is_err());
+        assert!(order_query_params("?slippage=1").is_err());
+        assert!(order_query_params("?slippage=-0.1").is_err());
+        assert!(order_query_params("?slippage=0.01&validFor=0").is_err());
+        assert!(order_query_params("?slippage=0.01&batchId=1").is_err());
+    }
+
     #[test]
     fn mutually_exclusive_generation_parameter() {
         assert!(query_params("?batchId=123&blockNumber=456").is_err());
         assert!(query_params("?blockNumber=123&timestamp=456").is_err());
         assert!(query_params("?timestamp=123&batchId=456").is_err());
     }
+
+    #[test]
+    fn unit_query_parameters() {
+        let unit_query_params = |params: &str| {
+            warp::test::request()
+                .path(&format!("/{}", params))
+                .filter(&warp::query::<UnitQueryParameters>())
+                .now_or_never()
+                .unwrap()
+        };
+        assert_eq!(unit_query_params("").unwrap().unit, Unit::BaseUnits);
+        assert_eq!(unit_query_params("?unit=atoms").unwrap().unit, Unit::Atoms);
+        assert_eq!(unit_query_params("?atoms=true").unwrap().unit, Unit::Atoms);
+        assert!(unit_query_params("?hops=1").is_err());
+    }
 }
@@ -0,0 +1,639 @@
+//! The OpenAPI specification of the routes in `filter`. It is built in code
+//! next to the filters and the tests check it against the filters: every
+//! documented path is handled, exactly the documented query parameters are
+//! accepted and responses have the documented properties, so that the
+//! documentation does not drift from the API.
+
+use serde_json::{json, Value};
+use std::{future, path::PathBuf};
+use warp::{reply::Html, Filter, Rejection, Reply};
+
+/// Routes serving the specification as JSON at `/api/v1/openapi.json` and an
+/// interactive Swagger UI for it at `/api/v1/docs`. The Swagger UI is only
+/// served if the directory containing its `swagger-ui.css` and
+/// `swagger-ui-bundle.js` assets is configured, so that browsers do not load
+/// scripts from a third party.
+pub fn routes(
+    swagger_ui_dir: Option<PathBuf>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let spec = warp::path!("api" / "v1" / "openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&spec()));
+    let swagger_ui_configured = {
+        let configured = swagger_ui_dir.is_some();
+        warp::any()
+            .and_then(move || {
+                future::ready(if configured {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                })
+            })
+            .untuple_one()
+    };
+    let docs = warp::path!("api" / "v1" / "docs")
+        .and(warp::get())
+        .and(swagger_ui_configured.clone())
+        .map(|| Html(SWAGGER_UI));
+    let assets = warp::path!("api" / "v1" / "docs" / "assets" / ..)
+        .and(warp::get())
+        .and(swagger_ui_configured)
+        .and(warp::fs::dir(swagger_ui_dir.unwrap_or_default()));
+    spec.map(Reply::into_response)
+        .or(docs.map(Reply::into_response))
+        .unify()
+        .or(assets.map(Reply::into_response))
+        .unify()
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Price Estimator API</title>
+    <link rel="stylesheet" href="docs/assets/swagger-ui.css">
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="docs/assets/swagger-ui-bundle.js"></script>
+    <script>
+      SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>
+"##;
+
+/// Builds the OpenAPI specification.
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "title": "Price Estimator OpenAPI",
+        },
+        "servers": [
+            { "url": "https://price-estimate-mainnet.dev.gnosisdev.com", "description": "Staging" },
+            { "url": "https://dex-price-estimator.gnosis.io", "description": "Production" },
+            { "url": "http://localhost:8080", "description": "Local" },
+        ],
+        "paths": {
+            "/api/v1/markets/{market}/estimated-buy-amount/{sell amount in quote}": get(
+                "Estimated Buy Amount",
+                "Estimate the buy amount (in buy tokens) a user can set as a limit order while \
+                 still expecting to be completely matched when selling the given amount of quote \
+                 token.",
+                schema_ref("AmountResponse"),
+                with_query_parameters(
+                    vec![market(), number_parameter("sell amount in quote", 1)],
+                    &[
+                        "Unit",
+                        "Atoms",
+                        "Hops",
+                        "BatchId",
+                        "IgnoreAddresses",
+                        "BlockNumber",
+                        "Timestamp",
+                        "RoundingBuffer",
+                    ],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}/estimated-amounts-at-price/{price}": get(
+                "Estimated Amounts At Price",
+                "Estimate largest buy and sell amounts for a limit at given price that would get \
+                 matched. Note that it might be possible to use a higher buy amount for the same \
+                 returned sell amount and still likely get completely matched by the solver. This \
+                 buy amount can be computed with a subsequent estimate buy amount API call using \
+                 the returned sell amount in quote value.",
+                schema_ref("AmountResponse"),
+                with_query_parameters(
+                    vec![market(), number_parameter("price", 400)],
+                    &[
+                        "Unit",
+                        "Atoms",
+                        "Hops",
+                        "BatchId",
+                        "IgnoreAddresses",
+                        "BlockNumber",
+                        "Timestamp",
+                        "RoundingBuffer",
+                    ],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}/estimated-best-ask-price": get(
+                "Estimated Best Ask Price",
+                "Estimates the exchange rate for the market. Note that the true exchange rate \
+                 depends on the buy amount whereas this exchange rate is for a theoretical 0 \
+                 amount. In the example we can exchange ~300 units of the sell token for 1 unit \
+                 of the buy token.",
+                nullable_price(),
+                with_query_parameters(
+                    vec![market()],
+                    &[
+                        "Unit",
+                        "Atoms",
+                        "Hops",
+                        "BatchId",
+                        "IgnoreAddresses",
+                        "BlockNumber",
+                        "Timestamp",
+                        "RoundingBuffer",
+                    ],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}/average-price/{batches}": get(
+                "Average Price",
+                "Volume weighted average of the best ask and bid prices of the market over the \
+                 last batches, ending with the batch specified by batchId or the current batch. \
+                 It changes more smoothly than the best ask price of a single orderbook.",
+                nullable_price(),
+                with_query_parameters(
+                    vec![
+                        market(),
+                        json!({
+                            "name": "batches",
+                            "required": true,
+                            "in": "path",
+                            "schema": {
+                                "type": "integer",
+                                "minimum": 1,
+                                "maximum": crate::filter::MAX_AVERAGE_PRICE_BATCHES,
+                            },
+                            "example": 5,
+                        }),
+                    ],
+                    &["Unit", "Atoms", "Hops", "BatchId", "IgnoreAddresses", "RoundingBuffer"],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}/placeable-order/{sell amount in quote}": get(
+                "Placeable Order",
+                "An order selling the given amount of quote token that is ready to be signed and \
+                 placed with the `placeOrder` function of the exchange. Its buy amount is the \
+                 estimated buy amount with the rounding buffer applied, reduced by the slippage \
+                 tolerance. The order is valid from the current batch for the given number of \
+                 batches. Note that the contract makes orders valid from the batch in which \
+                 they are placed.",
+                schema_ref("PlaceableOrderResponse"),
+                with_query_parameters(
+                    vec![market(), number_parameter("sell amount in quote", 1)],
+                    &["Unit", "Atoms", "Hops", "IgnoreAddresses", "Slippage", "ValidFor"],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}": get(
+                "Market",
+                "The transitive orderbook (containing bids and asks) for the given base and quote \
+                 token.",
+                schema_ref("MarketsResponse"),
+                with_query_parameters(
+                    vec![
+                        market(),
+                        json!({
+                            "name": "roundingBuffer",
+                            "in": "query",
+                            "description": "Ignored, the market is always computed without \
+                                rounding buffer so that it shows the orders as they were \
+                                placed.",
+                            "required": false,
+                            "deprecated": true,
+                            "schema": schema_ref("RoundingBuffer"),
+                        }),
+                    ],
+                    &[
+                        "Unit",
+                        "Atoms",
+                        "Hops",
+                        "BatchId",
+                        "IgnoreAddresses",
+                        "BlockNumber",
+                        "Timestamp",
+                    ],
+                ),
+                true,
+            ),
+            "/api/v1/markets/{market}/estimated-fee/{sell amount in quote}": get(
+                "Estimated Fee",
+                "The fee an order selling the given amount of quote token pays and the minimum \
+                 fee (in quote token and in OWL) an order needs to pay for it to be considered by \
+                 the solver according to current economic viability constraints and token prices.",
+                schema_ref("EstimatedFeeResponse"),
+                with_query_parameters(
+                    vec![market(), number_parameter("sell amount in quote", 1)],
+                    &["Unit", "Atoms"],
+                ),
+                false,
+            ),
+            "/api/v1/minimum-order-size-owl": {
+                "get": {
+                    "summary": "Minimum Order Size Owl",
+                    "description": "The minimum size of an order in owl atoms for it to be \
+                        considered by the solver according to current economic viability \
+                        constraints.",
+                    "responses": {
+                        "200": ok(schema_ref("MinimumOrderSizeOwlResponse")),
+                    },
+                },
+            },
+            "/api/v1/prices/{token}": get(
+                "Token Price",
+                "The price estimate of a token that is used by the backend, for example to \
+                 compute fees. The token can be given by id, address or symbol or be `native` for \
+                 the configured native token. The price in USD is `null` if the ERC20 info of the \
+                 token is not available.",
+                schema_ref("PriceResponse"),
+                vec![json!({
+                    "name": "token",
+                    "required": true,
+                    "in": "path",
+                    "schema": { "type": "string" },
+                    "example": "native",
+                })],
+                false,
+            ),
+        },
+        "components": components(),
+    })
+}
+
+/// A GET operation that can fail with invalid parameters and, if it reads
+/// the orderbook, because the orderbook is not available yet. Responses
+/// computed from the orderbook are tagged with its version.
+fn get(
+    summary: &str,
+    description: &str,
+    schema: Value,
+    mut parameters: Vec<Value>,
+    reads_orderbook: bool,
+) -> Value {
+    let mut responses = json!({
+        "200": ok(schema),
+        "400": response_ref("BadRequest"),
+    });
+    if reads_orderbook {
+        responses["200"]["headers"] = json!({ "ETag": { "$ref": "#/components/headers/ETag" } });
+        responses["304"] = response_ref("NotModified");
+        responses["503"] = response_ref("ServiceUnavailable");
+        parameters.push(json!({ "$ref": "#/components/parameters/IfNoneMatch" }));
+    }
+    json!({
+        "get": {
+            "summary": summary,
+            "description": description,
+            "responses": responses,
+            "parameters": parameters,
+        },
+    })
+}
+
+fn ok(schema: Value) -> Value {
+    json!({
+        "description": "OK",
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn response_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/responses/{}", name) })
+}
+
+fn with_query_parameters(mut parameters: Vec<Value>, names: &[&str]) -> Vec<Value> {
+    parameters.extend(
+        names
+            .iter()
+            .map(|name| json!({ "$ref": format!("#/components/parameters/{}", name) })),
+    );
+    parameters
+}
+
+fn market() -> Value {
+    json!({ "$ref": "#/components/parameters/Market" })
+}
+
+fn number_parameter(name: &str, example: u32) -> Value {
+    json!({
+        "name": name,
+        "required": true,
+        "in": "path",
+        "schema": schema_ref("NumberParameter"),
+        "example": example,
+    })
+}
+
+fn nullable_price() -> Value {
+    json!({ "type": "number", "nullable": true, "example": 297.8 })
+}
+
+fn components() -> Value {
+    json!({
+        "schemas": schemas(),
+        "responses": responses(),
+        "parameters": parameters(),
+        "headers": {
+            "ETag": {
+                "description": "Identifies the version of the orderbook the response was \
+                    computed from. It changes whenever the orderbook is updated.",
+                "schema": { "type": "string" },
+            },
+        },
+    })
+}
+
+fn schemas() -> Value {
+    json!({
+        "NumberParameter": { "type": "number" },
+        "Unit": {
+            "type": "string",
+            "enum": ["baseunits", "atoms"],
+            "default": "baseunits",
+            "example": "baseunits",
+        },
+        "RoundingBuffer": {
+            "type": "string",
+            "enum": ["enabled", "disabled"],
+            "default": "enabled",
+            "example": "enabled",
+        },
+        "IgnoreAddressesParameter": { "type": "string", "default": "" },
+        "AmountResponse": {
+            "type": "object",
+            "properties": {
+                "baseTokenId": { "type": "integer" },
+                "quoteTokenId": { "type": "integer" },
+                "buyAmountInBase": { "type": "string" },
+                "sellAmountInQuote": { "type": "string" },
+            },
+            "example": {
+                "baseTokenId": 1,
+                "quoteTokenId": 7,
+                "buyAmountInBase": "0.0025",
+                "sellAmountInQuote": "1",
+            },
+        },
+        "PlaceableOrderResponse": {
+            "type": "object",
+            "properties": {
+                "buyTokenId": { "type": "integer" },
+                "sellTokenId": { "type": "integer" },
+                "validFrom": { "type": "integer" },
+                "validUntil": { "type": "integer" },
+                "buyAmount": { "type": "string" },
+                "sellAmount": { "type": "string" },
+                "calldata": { "type": "string" },
+            },
+            "example": {
+                "buyTokenId": 1,
+                "sellTokenId": 7,
+                "validFrom": 5_340_000,
+                "validUntil": 5_340_005,
+                "buyAmount": "2500000000000000",
+                "sellAmount": "1000000000000000000",
+                "calldata": "0x26c3d394\
+                    0000000000000000000000000000000000000000000000000000000000000001\
+                    0000000000000000000000000000000000000000000000000000000000000007\
+                    0000000000000000000000000000000000000000000000000000000000517b65\
+                    0000000000000000000000000000000000000000000000000008e1bc9bf04000\
+                    0000000000000000000000000000000000000000000000000de0b6b3a7640000",
+            },
+        },
+        "TransitiveOrder": {
+            "type": "object",
+            "properties": {
+                "price": { "type": "number" },
+                "volume": { "type": "number" },
+            },
+        },
+        "MarketsResponse": {
+            "type": "object",
+            "properties": {
+                "asks": { "type": "array", "items": schema_ref("TransitiveOrder") },
+                "bids": { "type": "array", "items": schema_ref("TransitiveOrder") },
+            },
+            "example": {
+                "asks": [{ "price": 407.6755405630054, "volume": 9.389082650375993 }],
+                "bids": [{ "price": 5508028446685.359, "volume": 3.2264600472733105 }],
+            },
+        },
+        "EstimatedFeeResponse": {
+            "type": "object",
+            "properties": {
+                "quoteTokenId": { "type": "integer" },
+                "sellAmountInQuote": { "type": "string" },
+                "feeInQuote": { "type": "string" },
+                "minimumFeeInQuote": { "type": "string" },
+                "minimumFeeInOwl": { "type": "string" },
+            },
+            "example": {
+                "quoteTokenId": 7,
+                "sellAmountInQuote": "100",
+                "feeInQuote": "0.1",
+                "minimumFeeInQuote": "0.05",
+                "minimumFeeInOwl": "0.05",
+            },
+        },
+        "PriceResponse": {
+            "type": "object",
+            "properties": {
+                "tokenId": { "type": "integer" },
+                "priceInOwl": {
+                    "type": "string",
+                    "description": "OWL atoms per 10^18 atoms of the token.",
+                },
+                "priceInUsd": {
+                    "type": "number",
+                    "nullable": true,
+                    "description": "USD per base unit of the token.",
+                },
+            },
+            "example": {
+                "tokenId": 1,
+                "priceInOwl": "400000000000000000000",
+                "priceInUsd": 400.0,
+            },
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "properties": {
+                "code": {
+                    "type": "string",
+                    "description": "Machine readable identifier of the kind of error.",
+                },
+                "message": { "type": "string" },
+                "parameter": {
+                    "type": "string",
+                    "description": "The request parameter that caused the error, only set \
+                        for invalid parameters.",
+                },
+                "allowed": {
+                    "type": "string",
+                    "description": "The values accepted for the parameter, only set for \
+                        invalid parameters.",
+                },
+            },
+            "example": {
+                "code": "invalidParameter",
+                "message": "invalid request parameter",
+                "parameter": "sell amount in quote",
+                "allowed": "finite non-negative number",
+            },
+        },
+        "MinimumOrderSizeOwlResponse": { "type": "number" },
+    })
+}
+
+fn responses() -> Value {
+    json!({
+        "NotModified": {
+            "description": "The orderbook has not changed since the response with the entity \
+                tag given in the `If-None-Match` header was computed.",
+            "headers": { "ETag": { "$ref": "#/components/headers/ETag" } },
+        },
+        "BadRequest": {
+            "description": "The request contains invalid parameters.",
+            "content": { "application/json": { "schema": schema_ref("ErrorResponse") } },
+        },
+        "ServiceUnavailable": {
+            "description": "The orderbook has not been initialized yet. The `Retry-After` \
+                header contains the number of seconds after which the request can be \
+                retried.",
+            "content": { "application/json": { "schema": schema_ref("ErrorResponse") } },
+        },
+    })
+}
+
+fn parameters() -> Value {
+    json!({
+        "Market": {
+            "name": "market",
+            "in": "path",
+            "description": "token pair of the form `<buy token>-<sell token>`",
+            "required": true,
+            "schema": { "type": "string" },
+            "examples": {
+                "token_ids": { "summary": "Token Ids", "value": "1-7" },
+                "symbols": { "summary": "Symbols", "value": "WETH-DAI" },
+                "addresses": {
+                    "value": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2-\
+                        0x6b175474e89094c44da98b954eedeac495271d0f",
+                },
+            },
+        },
+        "Unit": {
+            "name": "unit",
+            "in": "query",
+            "description": "If `baseunits` (the default) all amounts are denominated in the \
+                \"natural\" unit of the respective token given by the number of decimals \
+                specified through the ERC20 interface. If set to `atoms` all amounts are \
+                denominated in the smallest available unit (atom) of the token.",
+            "required": false,
+            "schema": schema_ref("Unit"),
+        },
+        "Atoms": {
+            "name": "atoms",
+            "in": "query",
+            "description": "Deprecated, use `unit` instead. If `true` all amounts are \
+                denominated in atoms, if `false` in base units. This parameter cannot be \
+                specified together with the \"unit\" query parameter.",
+            "required": false,
+            "deprecated": true,
+            "schema": { "type": "boolean" },
+        },
+        "Hops": {
+            "name": "hops",
+            "in": "query",
+            "description": "The maximum number of hops (i.e. maximum ring trade length) used \
+                when searching the orderbook. Fewer hops trade accuracy for latency. The \
+                server may be configured with a lower maximum, which is also used when the \
+                parameter is not specified.",
+            "required": false,
+            "schema": { "type": "integer", "minimum": 0, "maximum": crate::models::MAX_HOPS },
+        },
+        "RoundingBuffer": {
+            "name": "roundingBuffer",
+            "in": "query",
+            "description": "If `enabled` (the default) the orderbook is adjusted by a \
+                rounding buffer that is used by the solvers internally. This makes the \
+                results more accurately reflect how the solvers see the orderbook. If set to \
+                `disabled` no adjustments take place which can lead to for example prices \
+                for orders that would not actually get matched by the solver.",
+            "required": false,
+            "schema": schema_ref("RoundingBuffer"),
+        },
+        "BatchId": {
+            "name": "batchId",
+            "in": "query",
+            "description": "The batch ID to compute the estimate for, only accounting orders \
+                that are valid at the specified batch. If no batch ID is specified, the \
+                current batch that is collecting orders will be used.",
+            "required": false,
+            "schema": { "type": "integer" },
+        },
+        "IgnoreAddresses": {
+            "name": "ignoreAddresses",
+            "in": "query",
+            "description": "Comma separated list of addresses in hex notation whose orders \
+                should be ignored. Capitalization of letters does not matter. No space after \
+                the commas.",
+            "required": false,
+            "schema": schema_ref("IgnoreAddressesParameter"),
+            "examples": {
+                "empty": { "summary": "Empty", "value": "" },
+                "list": {
+                    "summary": "Two Addresses",
+                    "value": "0x00000000000000000000000000000000000000a0,\
+                        0x00000000000000000000000000000000000000A1",
+                },
+            },
+        },
+        "BlockNumber": {
+            "name": "blockNumber",
+            "in": "query",
+            "description": "The block number to compute the estimate for. This will use the \
+                open orderbook at that block (i.e. orders that will be considered for solving \
+                the current batch at the block number). This parameter cannot be specified \
+                together with the \"batchId\" query parameter.",
+            "required": false,
+            "schema": { "type": "integer" },
+        },
+        "Timestamp": {
+            "name": "timestamp",
+            "in": "query",
+            "description": "The unix timestamp in seconds to compute the estimate for, using \
+                the orderbook of the events up to and including that time. This parameter \
+                cannot be specified together with the \"batchId\" or \"blockNumber\" query \
+                parameters.",
+            "required": false,
+            "schema": { "type": "integer" },
+        },
+        "Slippage": {
+            "name": "slippage",
+            "in": "query",
+            "description": "The fraction by which the estimated buy amount is reduced so that \
+                the order still gets matched if prices move against it.",
+            "required": true,
+            "schema": { "type": "number", "minimum": 0, "exclusiveMaximum": true, "maximum": 1 },
+            "example": 0.005,
+        },
+        "ValidFor": {
+            "name": "validFor",
+            "in": "query",
+            "description": "The number of batches, starting with the current one, the order \
+                is valid for.",
+            "required": false,
+            "schema": { "type": "integer", "minimum": 1, "default": 6 },
+        },
+        "IfNoneMatch": {
+            "name": "If-None-Match",
+            "in": "header",
+            "description": "The `ETag` of a previous response. If the orderbook has not been \
+                updated since, the response is `304 Not Modified` without a body.",
+            "required": false,
+            "schema": { "type": "string" },
+        },
+    })
+}
@@ -0,0 +1,110 @@
+//! Module implementing the creation of orders that are ready to be placed on
+//! the exchange, so that integrators do not need to reimplement the rounding
+//! and batch computations of the price estimator.
+
+use ethcontract::{
+    common::abi::{encode, short_signature, ParamType, Token},
+    web3::types::Bytes,
+};
+use services_core::models::BatchId;
+
+/// An order that can be placed with the `placeOrder` function of the exchange
+/// contract.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaceableOrder {
+    pub buy_token: u16,
+    pub sell_token: u16,
+    /// The batch that is currently collecting orders. The contract makes
+    /// orders valid from the batch in which they are placed, which is later if
+    /// the transaction is not mined in the current batch.
+    pub valid_from: BatchId,
+    pub valid_until: BatchId,
+    pub buy_amount: u128,
+    pub sell_amount: u128,
+}
+
+impl PlaceableOrder {
+    /// Creates an order valid for the specified number of batches, starting
+    /// with the current batch.
+    pub fn new(
+        buy_token: u16,
+        sell_token: u16,
+        buy_amount: u128,
+        sell_amount: u128,
+        valid_from: BatchId,
+        valid_for: u32,
+    ) -> Self {
+        Self {
+            buy_token,
+            sell_token,
+            valid_from,
+            valid_until: BatchId(valid_from.0 + u64::from(valid_for) - 1),
+            buy_amount,
+            sell_amount,
+        }
+    }
+
+    /// The encoded call of
+    /// `placeOrder(uint16 buyToken, uint16 sellToken, uint32 validUntil, uint128 buyAmount, uint128 sellAmount)`.
+    pub fn calldata(&self) -> Bytes {
+        let selector = short_signature(
+            "placeOrder",
+            &[
+                ParamType::Uint(16),
+                ParamType::Uint(16),
+                ParamType::Uint(32),
+                ParamType::Uint(128),
+                ParamType::Uint(128),
+            ],
+        );
+        let arguments = encode(&[
+            Token::Uint(self.buy_token.into()),
+            Token::Uint(self.sell_token.into()),
+            Token::Uint(self.valid_until.0.into()),
+            Token::Uint(self.buy_amount.into()),
+            Token::Uint(self.sell_amount.into()),
+        ]);
+        Bytes([&selector[..], &arguments].concat())
+    }
+}
+
+/// Reduces an estimated buy amount by the slippage tolerance of the order.
+pub fn buy_amount_with_slippage(estimated_buy_amount: f64, slippage: f64) -> u128 {
+    (estimated_buy_amount * (1.0 - slippage)).floor() as _
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethcontract::U256;
+
+    #[test]
+    fn valid_for_number_of_batches() {
+        let order = PlaceableOrder::new(1, 2, 3, 4, BatchId(10), 1);
+        assert_eq!(order.valid_from, BatchId(10));
+        assert_eq!(order.valid_until, BatchId(10));
+        let order = PlaceableOrder::new(1, 2, 3, 4, BatchId(10), 6);
+        assert_eq!(order.valid_until, BatchId(15));
+    }
+
+    #[test]
+    fn encodes_place_order_calldata() {
+        let order = PlaceableOrder::new(1, 7, 1000, 2000, BatchId(42), 1);
+        let calldata = order.calldata().0;
+        assert_eq!(calldata.len(), 4 + 5 * 32);
+        assert_eq!(&calldata[..4], &[0x26, 0xc3, 0xd3, 0x94]);
+        let word = |index: usize| U256::from_big_endian(&calldata[4 + 32 * index..][..32]);
+        assert_eq!(word(0), 1.into());
+        assert_eq!(word(1), 7.into());
+        assert_eq!(word(2), 42.into());
+        assert_eq!(word(3), 1000.into());
+        assert_eq!(word(4), 2000.into());
+    }
+
+    #[test]
+    fn applies_slippage_to_buy_amount() {
+        assert_eq!(buy_amount_with_slippage(1000.0, 0.0), 1000);
+        assert_eq!(buy_amount_with_slippage(1000.0, 0.005), 995);
+        assert_eq!(buy_amount_with_slippage(999.9, 0.0), 999);
+    }
+}
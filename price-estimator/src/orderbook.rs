@@ -1,49 +1,93 @@
 use crate::{
+    blocking::BlockingPool,
     infallible_price_source::PriceCacheUpdater,
     models::{EstimationTime, RoundingBuffer},
     solver_rounding_buffer,
 };
 use anyhow::{bail, Result};
+use arc_swap::ArcSwapOption;
 use ethcontract::Address;
 use futures::future;
 use pricegraph::{Pricegraph, TokenPair};
 use services_core::{
     economic_viability::NativeTokenPricing,
+    history::Settlement,
     models::{AccountState, BatchId, Order, TokenId},
     orderbook::StableXOrderBookReading,
 };
-use tokio::sync::RwLock;
+use std::{
+    fmt::{self, Display, Formatter},
+    num::NonZeroU128,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// Error returned when estimating with the current orderbook before it was
+/// successfully loaded for the first time.
+#[derive(Debug, thiserror::Error)]
+#[error("orderbook has not been initialized yet")]
+pub struct OrderbookNotInitialized;
 
-struct PricegraphCache {
-    pricegraph_raw: Pricegraph,
-    pricegraph_with_rounding_buffer: Pricegraph,
+/// Identifies the orderbook estimates for the current batch are computed
+/// from. It changes with every update and, since it includes the time the
+/// service was started, is not reused after a restart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OrderbookVersion {
+    epoch: u64,
+    update: u64,
+}
+
+impl Display for OrderbookVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:x}-{}", self.epoch, self.update)
+    }
+}
+
+/// Immutable pricegraphs for the current orderbook. Updates build a new
+/// snapshot and atomically swap it in so that readers never wait for them.
+struct PricegraphSnapshot {
+    version: OrderbookVersion,
+    pricegraph_raw: Arc<Pricegraph>,
+    pricegraph_with_rounding_buffer: Arc<Pricegraph>,
 }
 
 /// Access and update the pricegraph orderbook.
 pub struct Orderbook {
     orderbook_reading: Box<dyn StableXOrderBookReading>,
-    pricegraph_cache: RwLock<PricegraphCache>,
+    /// `None` until the first successful update.
+    pricegraph_cache: ArcSwapOption<PricegraphSnapshot>,
+    /// Milliseconds since the unix epoch at which the orderbook was created.
+    epoch: u64,
+    updates: AtomicU64,
     extra_rounding_buffer_factor: f64,
-    infallible_price_source: PriceCacheUpdater,
+    infallible_price_source: Arc<PriceCacheUpdater>,
     native_token: TokenId,
+    blocking_pool: BlockingPool,
 }
 
 impl Orderbook {
     pub fn new(
         orderbook_reading: Box<dyn StableXOrderBookReading>,
-        infallible_price_source: PriceCacheUpdater,
+        infallible_price_source: Arc<PriceCacheUpdater>,
         extra_rounding_buffer_factor: f64,
         native_token: TokenId,
+        max_concurrent_computations: usize,
     ) -> Self {
         Self {
             orderbook_reading,
-            pricegraph_cache: RwLock::new(PricegraphCache {
-                pricegraph_raw: Pricegraph::new(std::iter::empty()),
-                pricegraph_with_rounding_buffer: Pricegraph::new(std::iter::empty()),
-            }),
+            pricegraph_cache: ArcSwapOption::empty(),
+            epoch: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default(),
+            updates: AtomicU64::new(0),
             infallible_price_source,
             extra_rounding_buffer_factor,
             native_token,
+            blocking_pool: BlockingPool::new(max_concurrent_computations),
         }
     }
 
@@ -52,45 +96,72 @@ impl Orderbook {
         time: EstimationTime,
         ignore_addresses: &[Address],
         rounding_buffer: RoundingBuffer,
-    ) -> Result<Pricegraph> {
+    ) -> Result<Arc<Pricegraph>> {
         if time == EstimationTime::Now && ignore_addresses.is_empty() {
-            Ok(self.cached_pricegraph(rounding_buffer).await)
+            self.cached_pricegraph(rounding_buffer)
         } else {
             let mut auction_data = self.auction_data(time).await?;
             if matches!(rounding_buffer, RoundingBuffer::Enabled) {
-                self.apply_rounding_buffer_to_auction_data(&mut auction_data)
-                    .await?;
+                self.apply_rounding_buffer_to_auction_data(&mut auction_data);
             }
 
-            Ok(pricegraph_from_auction_data(
-                &auction_data,
-                ignore_addresses,
-            ))
+            let ignore_addresses = ignore_addresses.to_vec();
+            self.run_blocking(move || {
+                Arc::new(pricegraph_from_auction_data(
+                    &auction_data,
+                    &ignore_addresses,
+                ))
+            })
+            .await
         }
     }
 
     /// Recreate the pricegraph orderbook and update the infallible price source.
     pub async fn update(&self) -> Result<()> {
-        let mut auction_data = self.auction_data(EstimationTime::Now).await?;
+        let auction_data = self.auction_data(EstimationTime::Now).await?;
 
-        // TODO: Move this cpu heavy computation out of the async function using spawn_blocking.
-        let pricegraph = pricegraph_from_auction_data(&auction_data, &[]);
+        let (mut auction_data, pricegraph) = self
+            .run_blocking(move || {
+                let pricegraph = pricegraph_from_auction_data(&auction_data, &[]);
+                (auction_data, pricegraph)
+            })
+            .await?;
         self.update_infallible_price_source(&pricegraph).await;
-        self.pricegraph_cache.write().await.pricegraph_raw = pricegraph;
 
-        self.apply_rounding_buffer_to_auction_data(&mut auction_data)
+        self.apply_rounding_buffer_to_auction_data(&mut auction_data);
+        let pricegraph_with_rounding_buffer = self
+            .run_blocking(move || pricegraph_from_auction_data(&auction_data, &[]))
             .await?;
-        let pricegraph = pricegraph_from_auction_data(&auction_data, &[]);
+        let version = OrderbookVersion {
+            epoch: self.epoch,
+            update: self.updates.fetch_add(1, Ordering::SeqCst) + 1,
+        };
         self.pricegraph_cache
-            .write()
-            .await
-            .pricegraph_with_rounding_buffer = pricegraph;
+            .store(Some(Arc::new(PricegraphSnapshot {
+                version,
+                pricegraph_raw: Arc::new(pricegraph),
+                pricegraph_with_rounding_buffer: Arc::new(pricegraph_with_rounding_buffer),
+            })));
         Ok(())
     }
 
-    pub async fn rounding_buffer(&self, token_pair: TokenPair) -> f64 {
-        let price_source = self.infallible_price_source.inner().await;
-        solver_rounding_buffer::rounding_buffer(
+    /// Returns true once the orderbook has been successfully updated.
+    pub fn is_initialized(&self) -> bool {
+        self.pricegraph_cache.load().is_some()
+    }
+
+    /// The version of the current orderbook or `None` if it has not been
+    /// initialized yet.
+    pub fn version(&self) -> Option<OrderbookVersion> {
+        self.pricegraph_cache
+            .load()
+            .as_ref()
+            .map(|snapshot| snapshot.version)
+    }
+
+    pub fn rounding_buffer(&self, token_pair: TokenPair) -> f64 {
+        let price_source = self.infallible_price_source.inner();
+        pricegraph::rounding_buffer(
             price_source.price(TokenId(0)).get() as f64,
             price_source.price(TokenId(token_pair.sell)).get() as f64,
             price_source.price(TokenId(token_pair.buy)).get() as f64,
@@ -98,6 +169,35 @@ impl Orderbook {
         )
     }
 
+    /// Runs a cpu heavy computation, like building or querying a pricegraph,
+    /// on a bounded blocking thread pool so that it does not stall the async
+    /// runtime.
+    pub async fn run_blocking<T, F>(&self, computation: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.blocking_pool.run(computation).await
+    }
+
+    /// The final settlement of a past batch, if a solution was submitted.
+    pub async fn settlement_for_batch(&self, batch_id: BatchId) -> Result<Option<Settlement>> {
+        self.orderbook_reading
+            .settlement_for_batch(batch_id.into())
+            .await
+    }
+
+    /// The token whose price is used to convert gas costs into OWL.
+    pub fn native_token(&self) -> TokenId {
+        self.native_token
+    }
+
+    /// The estimated price of a token in OWL atoms per 10^18 atoms of the
+    /// token, as used by the solver.
+    pub fn token_price(&self, token: TokenId) -> NonZeroU128 {
+        self.infallible_price_source.inner().price(token)
+    }
+
     /// Update the infallible price source with the averaged prices of the external price sources
     /// and the pricegraph prices.
     async fn update_infallible_price_source(&self, pricegraph: &Pricegraph) {
@@ -135,20 +235,18 @@ impl Orderbook {
         }
     }
 
-    async fn cached_pricegraph(&self, pricegraph_type: RoundingBuffer) -> Pricegraph {
-        let cache = self.pricegraph_cache.read().await;
-        match pricegraph_type {
-            RoundingBuffer::Disabled => &cache.pricegraph_raw,
-            RoundingBuffer::Enabled => &cache.pricegraph_with_rounding_buffer,
+    fn cached_pricegraph(&self, pricegraph_type: RoundingBuffer) -> Result<Arc<Pricegraph>> {
+        let snapshot = self.pricegraph_cache.load();
+        let snapshot = snapshot.as_ref().ok_or(OrderbookNotInitialized)?;
+        Ok(match pricegraph_type {
+            RoundingBuffer::Disabled => &snapshot.pricegraph_raw,
+            RoundingBuffer::Enabled => &snapshot.pricegraph_with_rounding_buffer,
         }
-        .clone()
+        .clone())
     }
 
-    async fn apply_rounding_buffer_to_auction_data(
-        &self,
-        auction_data: &mut AuctionData,
-    ) -> Result<()> {
-        let price_source = self.infallible_price_source.inner().await;
+    fn apply_rounding_buffer_to_auction_data(&self, auction_data: &mut AuctionData) {
+        let price_source = self.infallible_price_source.inner();
         let prices = |token_id| price_source.price(token_id);
         solver_rounding_buffer::apply_rounding_buffer(
             prices,
@@ -156,19 +254,13 @@ impl Orderbook {
             &mut auction_data.0,
             self.extra_rounding_buffer_factor,
         );
-        Ok(())
     }
 }
 
 #[async_trait::async_trait]
 impl NativeTokenPricing for Orderbook {
-    async fn get_native_token_price(&self) -> Option<std::num::NonZeroU128> {
-        Some(
-            self.infallible_price_source
-                .inner()
-                .await
-                .price(self.native_token),
-        )
+    async fn get_native_token_price(&self) -> Option<NonZeroU128> {
+        Some(self.token_price(self.native_token))
     }
 }
 
@@ -190,7 +282,6 @@ fn pricegraph_from_auction_data(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::FutureExt as _;
     use services_core::{
         models::TokenId, orderbook::NoopOrderbook, price_estimation::price_source::PriceSource,
         token_info::hardcoded::TokenData,
@@ -214,24 +305,42 @@ mod tests {
         }
 
         let token_info = Arc::new(TokenData::default());
-        let infallible = PriceCacheUpdater::new(token_info, vec![Box::new(PriceSource_ {})]);
-        let orderbook = Orderbook::new(Box::new(NoopOrderbook), infallible, 2.0, TokenId(1));
-        let price = || {
-            orderbook
-                .infallible_price_source
-                .inner()
-                .now_or_never()
-                .unwrap()
-                .price(TokenId(1))
-        };
+        let infallible = Arc::new(PriceCacheUpdater::new(
+            token_info,
+            Default::default(),
+            vec![Box::new(PriceSource_ {})],
+        ));
+        let orderbook = Orderbook::new(Box::new(NoopOrderbook), infallible, 2.0, TokenId(1), 1);
+        let price = || orderbook.infallible_price_source.inner().price(TokenId(1));
 
         let before_update_price = price();
-        orderbook.update().now_or_never().unwrap().unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(orderbook.update()).unwrap();
         let after_update_price = price();
         assert!(before_update_price != after_update_price);
         assert_eq!(after_update_price.get(), 3);
     }
 
+    #[test]
+    fn version_changes_on_update() {
+        let token_info = Arc::new(TokenData::default());
+        let infallible = Arc::new(PriceCacheUpdater::new(
+            token_info,
+            Default::default(),
+            Vec::new(),
+        ));
+        let orderbook = Orderbook::new(Box::new(NoopOrderbook), infallible, 2.0, TokenId(1), 1);
+        assert_eq!(orderbook.version(), None);
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(orderbook.update()).unwrap();
+        let first = orderbook.version().unwrap();
+        runtime.block_on(orderbook.update()).unwrap();
+        let second = orderbook.version().unwrap();
+        assert_ne!(first, second);
+        assert_eq!(orderbook.version(), Some(second));
+    }
+
     #[test]
     fn uses_ignored_addresses() {
         let mut account_state = AccountState::default();
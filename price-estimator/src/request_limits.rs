@@ -0,0 +1,104 @@
+//! Module implementing limits that keep expensive requests from starving the
+//! price estimator.
+
+use crate::error::RejectionReason;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use warp::Rejection;
+
+/// Limits applied to all requests.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLimits {
+    /// The maximum number of requests that are handled concurrently. Requests
+    /// exceeding this limit are rejected.
+    pub max_concurrent_requests: usize,
+    /// The maximum time spent computing the response of a single request.
+    pub timeout: Duration,
+    /// The maximum number of hops of the `pricegraph` search a request can
+    /// ask for, which is also the default for requests that don't specify
+    /// the number of hops. `None` leaves the search unbounded by default.
+    pub max_hops: Option<usize>,
+}
+
+/// Keeps track of the number of requests that are currently being handled.
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+    max: usize,
+    active: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a permit to handle a request or `None` if too many requests
+    /// are already being handled. The request counts as active until the
+    /// permit is dropped.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConcurrencyPermit> {
+        if self.active.fetch_add(1, Ordering::SeqCst) >= self.max {
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(ConcurrencyPermit(self.clone()))
+    }
+}
+
+/// A request that is being handled.
+#[derive(Debug)]
+pub struct ConcurrencyPermit(Arc<ConcurrencyLimit>);
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Rejects the request if computing the response takes longer than `timeout`.
+pub async fn with_timeout<T>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, Rejection>>,
+) -> Result<T, Rejection> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| RejectionReason::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permits_are_limited_until_dropped() {
+        let limit = Arc::new(ConcurrencyLimit::new(2));
+        let first = limit.try_acquire().unwrap();
+        let _second = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+
+        drop(first);
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_slow_requests() {
+        let result = with_timeout(Duration::from_millis(1), async {
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+            Ok(())
+        })
+        .await;
+        let rejection = result.unwrap_err();
+        assert!(matches!(
+            rejection.find::<RejectionReason>(),
+            Some(RejectionReason::Timeout)
+        ));
+    }
+}
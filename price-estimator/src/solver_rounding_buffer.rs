@@ -1,33 +1,10 @@
 use ethcontract::{Address, U256};
+use pricegraph::rounding_buffer;
 use services_core::models::{AccountState, Order, TokenId};
 use std::{collections::HashMap, num::NonZeroU128};
 
-// This code is closely related to dex-solver/src/opt/process/Rounding.py .
-// Discussion of motivation happened in https://github.com/gnosis/dex-services/issues/970 .
-
-const MAX_ROUNDING_VOLUME: f64 = 100_000_000_000.0;
-const PRICE_ESTIMATION_ERROR: f64 = 10.0;
-
-fn max_rounding_amount(token_price: f64, fee_token_price: f64) -> f64 {
-    let estimated_price_in_fee_token = token_price / fee_token_price;
-    let max_rounding_amount = MAX_ROUNDING_VOLUME / estimated_price_in_fee_token;
-    max_rounding_amount.max(1.0)
-}
-
-/// Calculate a single rounding buffer like the solver does. This amount is subtracted from the
-/// denominator of orders selling the sell token and buying the buy token.
-pub fn rounding_buffer(
-    fee_token_price: f64,
-    sell_token_price: f64,
-    buy_token_price: f64,
-    extra_factor: f64,
-) -> f64 {
-    let estimated_xrate = buy_token_price / sell_token_price;
-    max_rounding_amount(buy_token_price, fee_token_price)
-        * estimated_xrate
-        * PRICE_ESTIMATION_ERROR.powi(2)
-        * extra_factor
-}
+// The rounding buffer of a single token pair is computed by `pricegraph` so
+// that it can be shared with the WASM bindings.
 
 /// Perform the same rounding buffer calculation as our solvers in order to increase the correctness
 /// of our estimates.
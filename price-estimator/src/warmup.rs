@@ -0,0 +1,125 @@
+//! Warms up the estimates of popular markets after every orderbook update.
+//!
+//! Orderbooks share their shortest path trees with their clones, so computing
+//! an estimate on the current pricegraph makes the graph search of later
+//! requests for the same sell token and number of hops free.
+
+use crate::{
+    metrics::Metrics,
+    models::{EstimationTime, RoundingBuffer},
+    orderbook::Orderbook,
+};
+use anyhow::{anyhow, Context as _, Error, Result};
+use pricegraph::{TokenPair, TokenPairRange};
+use std::{str::FromStr, sync::Arc, time::Instant};
+
+/// An estimate computed after every orderbook update, in the format
+/// `<sell token id>-<buy token id>:<sell amount in atoms>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WarmupEstimate {
+    pub pair: TokenPair,
+    pub sell_amount: f64,
+}
+
+impl FromStr for WarmupEstimate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || -> Result<Self> {
+            let mut parts = s.splitn(2, ':');
+            let pair = parts.next().unwrap_or_default();
+            let sell_amount = parts
+                .next()
+                .ok_or_else(|| anyhow!("missing sell amount"))?
+                .parse()?;
+            let mut tokens = pair.splitn(2, '-');
+            let sell = tokens.next().unwrap_or_default().parse()?;
+            let buy = tokens
+                .next()
+                .ok_or_else(|| anyhow!("missing buy token"))?
+                .parse()?;
+            Ok(WarmupEstimate {
+                pair: TokenPair { buy, sell },
+                sell_amount,
+            })
+        };
+        parse().with_context(|| format!("invalid warmup estimate {:?}", s))
+    }
+}
+
+/// Computes the configured estimates for the current orderbook.
+pub struct Warmup {
+    estimates: Vec<WarmupEstimate>,
+    /// The number of hops requests use by default, as shortest path trees are
+    /// only shared between searches with the same number of hops.
+    hops: Option<usize>,
+    metrics: Arc<Metrics>,
+}
+
+impl Warmup {
+    pub fn new(estimates: Vec<WarmupEstimate>, hops: Option<usize>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            estimates,
+            hops,
+            metrics,
+        }
+    }
+
+    /// Warms up the estimates in the background, logging errors.
+    pub fn spawn(self: &Arc<Self>, orderbook: Arc<Orderbook>) {
+        if self.estimates.is_empty() {
+            return;
+        }
+        let warmup = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = warmup.run(&orderbook).await {
+                log::warn!("failed to warm up estimates: {:?}", err);
+            }
+        });
+    }
+
+    async fn run(&self, orderbook: &Orderbook) -> Result<()> {
+        let start = Instant::now();
+        let pricegraph = orderbook
+            .pricegraph(EstimationTime::Now, &[], RoundingBuffer::Enabled)
+            .await?;
+        let estimates = self.estimates.clone();
+        let hops = self.hops;
+        orderbook
+            .run_blocking(move || {
+                for estimate in estimates {
+                    let pair_range = TokenPairRange {
+                        pair: estimate.pair,
+                        hops,
+                    };
+                    if let Err(err) =
+                        pricegraph.estimate_limit_price(pair_range, estimate.sell_amount)
+                    {
+                        log::debug!("failed to warm up estimate {:?}: {:?}", estimate, err);
+                    }
+                }
+            })
+            .await?;
+        self.metrics.warmup_finished(start);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_warmup_estimate() {
+        assert_eq!(
+            "7-1:1e18".parse::<WarmupEstimate>().unwrap(),
+            WarmupEstimate {
+                pair: TokenPair { buy: 1, sell: 7 },
+                sell_amount: 1e18,
+            }
+        );
+        for invalid in &["", "7-1", "7:1e18", "7-1:", "a-1:1", "7-1:a"] {
+            assert!(invalid.parse::<WarmupEstimate>().is_err());
+        }
+    }
+}
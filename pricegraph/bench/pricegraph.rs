@@ -1,6 +1,8 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 use itertools::Itertools;
-use pricegraph::{Market, Pricegraph, TokenPair};
+use pricegraph::{
+    Element, Market, Orderbook, PriceFraction, Pricegraph, TokenId, TokenPair, Validity, H160, U256,
+};
 use pricegraph_data::DEFAULT_ORDERBOOK;
 use std::time::Duration;
 
@@ -8,6 +10,86 @@ fn read_default_pricegraph() -> Pricegraph {
     Pricegraph::read(&*DEFAULT_ORDERBOOK).expect("error reading orderbook")
 }
 
+/// The sell amount of the orders of synthetic orderbooks.
+const SYNTHETIC_SELL_AMOUNT: f64 = 1e20;
+
+/// Creates an orderbook element for a synthetic order selling the full
+/// balance of its own user at the specified limit price.
+fn synthetic_order(user: u64, sell: TokenId, buy: TokenId, price: f64) -> Element {
+    Element {
+        user: H160::from_low_u64_be(user),
+        balance: U256::from(SYNTHETIC_SELL_AMOUNT as u128),
+        pair: TokenPair { buy, sell },
+        valid: Validity {
+            from: 0,
+            to: u32::max_value(),
+        },
+        price: PriceFraction {
+            numerator: (SYNTHETIC_SELL_AMOUNT * price) as _,
+            denominator: SYNTHETIC_SELL_AMOUNT as _,
+        },
+        remaining_sell_amount: SYNTHETIC_SELL_AMOUNT as _,
+        id: 0,
+    }
+}
+
+/// A synthetic orderbook with an order between every pair of tokens. Limit
+/// prices deviate from a consistent set of token prices by up to 2% in both
+/// directions, which results in many overlapping ring trades.
+fn complete_orderbook(tokens: u16) -> Orderbook {
+    let token_price = |token: TokenId| f64::from(token) + 1.0;
+    let elements = (0..tokens)
+        .cartesian_product(0..tokens)
+        .filter(|(sell, buy)| sell != buy)
+        .enumerate()
+        .map(|(user, (sell, buy))| {
+            let deviation = (usize::from(sell) * 31 + usize::from(buy) * 17) % 41;
+            let factor = 0.98 + 0.04 * deviation as f64 / 40.0;
+            let price = token_price(sell) / token_price(buy) * factor;
+            synthetic_order(user as _, sell, buy, price)
+        });
+    Orderbook::from_elements(elements)
+}
+
+/// A synthetic orderbook with orders in both directions between consecutive
+/// tokens and a single order closing the chain into one long overlapping ring
+/// trade over all tokens.
+fn chain_orderbook(tokens: u16) -> Orderbook {
+    let links =
+        (1..tokens).flat_map(|token| vec![(token - 1, token, 0.99), (token, token - 1, 1.02)]);
+    let elements = links
+        .chain(Some((tokens - 1, 0, 0.99)))
+        .enumerate()
+        .map(|(user, (sell, buy, price))| synthetic_order(user as _, sell, buy, price));
+    Orderbook::from_elements(elements)
+}
+
+/// The synthetic orderbooks with their names, and the market spanning the
+/// graph for filling market orders.
+fn synthetic_orderbooks() -> Vec<(String, Orderbook, Market)> {
+    let complete = [10, 25, 50].iter().map(|&tokens| {
+        (
+            format!("complete:{}", tokens),
+            complete_orderbook(tokens),
+            Market {
+                base: tokens - 1,
+                quote: 0,
+            },
+        )
+    });
+    let chain = [1000, 2500, 5000].iter().map(|&tokens| {
+        (
+            format!("chain:{}", tokens),
+            chain_orderbook(tokens),
+            Market {
+                base: tokens / 2,
+                quote: 0,
+            },
+        )
+    });
+    complete.chain(chain).collect()
+}
+
 pub fn read(c: &mut Criterion) {
     c.bench_function("Pricegraph::read", |b| b.iter(read_default_pricegraph));
 }
@@ -69,6 +151,49 @@ pub fn order_for_limit_price(c: &mut Criterion) {
     group.finish();
 }
 
+pub fn synthetic_reduce_overlapping_orders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Orderbook::reduce_overlapping_orders");
+    for (name, orderbook, _) in synthetic_orderbooks() {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &orderbook,
+            |b, orderbook| {
+                b.iter_batched(
+                    || orderbook.clone(),
+                    |orderbook| orderbook.reduce_overlapping_orders(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+pub fn synthetic_fill_market_ring_trade(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Orderbook::fill_market_ring_trade");
+    for (name, orderbook, market) in synthetic_orderbooks() {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &(orderbook, market),
+            |b, (orderbook, market)| {
+                b.iter_batched(
+                    || orderbook.clone(),
+                    |mut orderbook| {
+                        while orderbook
+                            .fill_market_ring_trade(*market)
+                            .expect("error filling ring trade")
+                            .is_some()
+                        {}
+                        orderbook
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
 criterion_group!(
     name = overlapping;
     config = Criterion::default().measurement_time(Duration::from_secs(60));
@@ -79,4 +204,11 @@ criterion_group!(
     config = Criterion::default().measurement_time(Duration::from_secs(10));
     targets =  estimate_limit_price, order_for_limit_price
 );
-criterion_main!(overlapping, reduced);
+criterion_group!(
+    name = synthetic;
+    config = Criterion::default()
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(30));
+    targets = synthetic_reduce_overlapping_orders, synthetic_fill_market_ring_trade
+);
+criterion_main!(overlapping, reduced, synthetic);
@@ -0,0 +1,255 @@
+use anyhow::{anyhow, bail, Result};
+use env_logger::Env;
+use pricegraph::{Element, PriceFraction, TokenId, TokenPair, Validity, H160, U256};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "pricegraph-data-generate",
+    about = "Generates a synthetic orderbook in the viewer encoding for load testing."
+)]
+struct Options {
+    /// The file to write the orderbook to.
+    #[structopt(name = "OUTPUT")]
+    output: PathBuf,
+
+    /// The number of tokens including the fee token.
+    #[structopt(long, default_value = "20")]
+    tokens: u16,
+
+    /// The number of users placing orders.
+    #[structopt(long, default_value = "100")]
+    users: usize,
+
+    /// The number of orders.
+    #[structopt(long, default_value = "1000")]
+    orders: usize,
+
+    /// The share of orders with a limit price that is better than the
+    /// reference price of its token pair, and thus overlaps with orders in the
+    /// opposite direction. Must be between 0 and 1.
+    #[structopt(long, default_value = "0.1")]
+    overlap: f64,
+
+    /// The seed of the random number generator, generating the same orderbook
+    /// for the same seed and options.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// The output format, either `hex` for the permissive hex format of the
+    /// orderbooks in the `data` directory, or `binary` for the raw encoding
+    /// returned by `BatchExchangeViewer::getFilteredOrdersPaginated`.
+    #[structopt(long, default_value = "hex")]
+    format: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum OutputFormat {
+    Hex,
+    Binary,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "hex" => Ok(OutputFormat::Hex),
+            "binary" => Ok(OutputFormat::Binary),
+            _ => bail!("unknown output format '{}'", value),
+        }
+    }
+}
+
+fn main() {
+    env_logger::init_from_env(Env::default().default_filter_or("warn,generate=debug"));
+
+    if let Err(err) = run(Options::from_args()) {
+        log::error!("Error generating orderbook: {:?}", err);
+        std::process::exit(-1);
+    }
+}
+
+fn run(options: Options) -> Result<()> {
+    if options.tokens < 2 {
+        bail!("at least two tokens are required");
+    }
+    if options.users == 0 {
+        bail!("at least one user is required");
+    }
+    if !(0.0..=1.0).contains(&options.overlap) {
+        bail!("overlap must be between 0 and 1");
+    }
+
+    log::info!(
+        "generating {} orders of {} users over {} tokens",
+        options.orders,
+        options.users,
+        options.tokens,
+    );
+    let elements = generate_elements(&options)?;
+
+    let mut output = BufWriter::new(File::create(&options.output)?);
+    let bytes = Element::write_all(&elements);
+    match options.format {
+        OutputFormat::Hex => write_hex(&mut output, &bytes)?,
+        OutputFormat::Binary => output.write_all(&bytes)?,
+    }
+    output.flush()?;
+
+    log::info!("wrote orderbook to `{}`", options.output.display());
+    Ok(())
+}
+
+/// The range of the order values in OWL as powers of 10.
+const MIN_ORDER_VALUE_EXPONENT: f64 = 16.0;
+const MAX_ORDER_VALUE_EXPONENT: f64 = 22.0;
+
+/// The range of the token prices in OWL as powers of 10.
+const MIN_TOKEN_PRICE_EXPONENT: f64 = -6.0;
+const MAX_TOKEN_PRICE_EXPONENT: f64 = 6.0;
+
+/// The maximum deviation of limit prices from the reference price of their
+/// token pair.
+const MAX_OVERLAPPING_DEVIATION: f64 = 0.02;
+const MAX_SPREAD_DEVIATION: f64 = 0.2;
+
+/// The share of orders that were partially filled in previous batches.
+const PARTIALLY_FILLED_ORDERS: f64 = 0.2;
+
+/// The share of users that have a smaller balance than the sell amounts of
+/// their orders.
+const UNDERFUNDED_USERS: f64 = 0.2;
+
+/// Generates orderbook elements sorted by user and order ID, in the same order
+/// as the viewer contract returns them.
+///
+/// Every token has a reference price in OWL and limit prices deviate from the
+/// reference price of their token pair, so that the generated orderbook has
+/// a realistic mix of overlapping orders and orders with a spread.
+///
+/// Returns an error if a user ends up with more orders than there are order
+/// IDs.
+fn generate_elements(options: &Options) -> Result<Vec<Element>> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let token_prices = (0..options.tokens)
+        .map(|token| {
+            if token == 0 {
+                1.0
+            } else {
+                10f64.powf(rng.gen_range(MIN_TOKEN_PRICE_EXPONENT..MAX_TOKEN_PRICE_EXPONENT))
+            }
+        })
+        .collect::<Vec<_>>();
+    let users = (0..options.users)
+        .map(|_| (H160(rng.gen()), rng.gen_bool(UNDERFUNDED_USERS)))
+        .collect::<Vec<_>>();
+
+    let mut orders = Vec::with_capacity(options.orders);
+    let mut sell_amounts = BTreeMap::<(usize, TokenId), u128>::new();
+    for _ in 0..options.orders {
+        let user = rng.gen_range(0..users.len());
+        let sell = rng.gen_range(0..options.tokens);
+        let buy = ((u32::from(sell) + rng.gen_range(1..u32::from(options.tokens)))
+            % u32::from(options.tokens)) as TokenId;
+
+        let value = 10f64.powf(rng.gen_range(MIN_ORDER_VALUE_EXPONENT..MAX_ORDER_VALUE_EXPONENT));
+        let sell_amount = value / token_prices[usize::from(sell)];
+        let deviation = if rng.gen_bool(options.overlap) {
+            -rng.gen_range(0.0..MAX_OVERLAPPING_DEVIATION)
+        } else {
+            rng.gen_range(0.0..MAX_SPREAD_DEVIATION)
+        };
+        let buy_amount = value / token_prices[usize::from(buy)] * (1.0 + deviation);
+
+        let price = PriceFraction {
+            numerator: (buy_amount as u128).max(1),
+            denominator: (sell_amount as u128).max(1),
+        };
+        let remaining_sell_amount = if rng.gen_bool(PARTIALLY_FILLED_ORDERS) {
+            (price.denominator as f64 * rng.gen_range(0.0..1.0)) as u128
+        } else {
+            price.denominator
+        };
+
+        *sell_amounts.entry((user, sell)).or_default() += remaining_sell_amount;
+        orders.push((user, TokenPair { buy, sell }, price, remaining_sell_amount));
+    }
+    orders.sort_by_key(|(user, ..)| *user);
+
+    // NOTE: Balances are computed from a `BTreeMap` so that the random numbers
+    // are drawn in a deterministic order for a given seed.
+    let balances = sell_amounts
+        .into_iter()
+        .map(|((user, token), sell_amount)| {
+            let (_, underfunded) = users[user];
+            let balance = if underfunded {
+                (sell_amount as f64 * rng.gen_range(0.0..1.0)) as u128
+            } else {
+                sell_amount
+            };
+            ((user, token), balance)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut next_order_ids = HashMap::<usize, u32>::new();
+    orders
+        .into_iter()
+        .map(|(user, pair, price, remaining_sell_amount)| {
+            let next_id = next_order_ids.entry(user).or_default();
+            let id = u16::try_from(*next_id).map_err(|_| {
+                anyhow!(
+                    "user {} has more than {} orders, use more users",
+                    user,
+                    u32::from(u16::max_value()) + 1,
+                )
+            })?;
+            *next_id += 1;
+            Ok(Element {
+                user: users[user].0,
+                balance: U256::from(balances[&(user, pair.sell)]),
+                pair,
+                valid: Validity {
+                    from: 0,
+                    to: u32::max_value(),
+                },
+                price,
+                remaining_sell_amount,
+                id,
+            })
+        })
+        .collect()
+}
+
+/// Writes the encoded elements in the permissive hex format with lines to
+/// separate orders and spaces to separate fields.
+fn write_hex(mut output: impl Write, bytes: &[u8]) -> Result<()> {
+    const SECTIONS: &[usize] = &[20, 32, 2, 2, 4, 4, 16, 16, 16, 2];
+
+    for element in bytes.chunks(pricegraph::ELEMENT_STRIDE) {
+        let encoded = SECTIONS
+            .iter()
+            .scan(element, |remaining, &section| {
+                let (bytes, rest) = remaining.split_at(section);
+                *remaining = rest;
+                Some(hex::encode(bytes))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        output.write_all(encoded.as_bytes())?;
+        output.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
@@ -1,13 +1,9 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use pricegraph::Element;
 
-// Fuzz Element::read_all .
-// This is unlikely to find any panic because the implementation is simple.
+// Fuzz Element::read_all and reducing the orderbook built from the decoded
+// elements.
 
 fuzz_target!(|data: &[u8]| {
-    if let Ok(elements) = Element::read_all(data) {
-        // Iterate to consume the iterator.
-        for _ in elements {}
-    }
+    pricegraph::fuzz::read_and_reduce(data);
 });
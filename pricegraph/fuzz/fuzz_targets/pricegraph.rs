@@ -1,12 +1,7 @@
 #![no_main]
 use arbitrary::Arbitrary;
 use libfuzzer_sys::fuzz_target;
-use pricegraph::{Element, Market, Pricegraph, TokenId, TokenPairRange};
-use std::iter::once;
-
-// Limit the maximum token id that is allowed to appear in the generated orders. Without this we can
-// make orderbook creation too slow if one order contains a large buy or sell token id.
-const MAX_TOKEN_ID: u16 = 16;
+use pricegraph::{fuzz, Element, Market, Pricegraph, TokenPairRange};
 
 // Fuzz creation and usage of Orderbook.
 
@@ -29,15 +24,8 @@ struct Arguments {
     operation: Operation,
 }
 
-fn largest_token_id(elements: &[Element]) -> Option<TokenId> {
-    elements
-        .iter()
-        .flat_map(|e| once(e.pair.buy).chain(once(e.pair.sell)))
-        .max()
-}
-
 fuzz_target!(|arguments: Arguments| {
-    if largest_token_id(&arguments.elements).unwrap_or(0) > MAX_TOKEN_ID {
+    if !fuzz::is_within_limits(&arguments.elements) {
         return;
     }
 
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use pricegraph::Element;
+
+// Fuzz Orderbook::reduce_overlapping_orders with arbitrary orders.
+
+fuzz_target!(|elements: Vec<Element>| {
+    pricegraph::fuzz::reduce_overlapping_orders(elements);
+});
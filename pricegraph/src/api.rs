@@ -1,5 +1,6 @@
 //! Module containing the high-level `Pricegraph` API operation implementations.
 
+mod average_price;
 mod price_estimation;
 mod price_source;
 mod transitive_orderbook;
@@ -0,0 +1,113 @@
+//! Module containing the implementation for computing volume weighted average
+//! prices over multiple orderbook snapshots.
+
+use crate::api::{Market, TransitiveOrderbook};
+use crate::orderbook::OrderbookError;
+use crate::Pricegraph;
+
+impl Pricegraph {
+    /// Computes a volume weighted average price for the specified market over
+    /// multiple orderbook snapshots, for example the orderbooks of the last few
+    /// batches. Returns `None` if none of the snapshots have any transitive
+    /// orders for the market.
+    ///
+    /// Each snapshot contributes the price of its best ask and best bid
+    /// transitive orders, weighted by their volume in the base token. This
+    /// gives a reference price that changes more smoothly than an estimate
+    /// computed from a single orderbook.
+    ///
+    /// Note that the price is expressed in the quote token and includes fees.
+    pub fn volume_weighted_average_price<'a>(
+        snapshots: impl IntoIterator<Item = &'a Pricegraph>,
+        market: Market,
+    ) -> Result<Option<f64>, OrderbookError> {
+        let mut weighted_price_sum = 0.0;
+        let mut total_volume = 0.0;
+        for pricegraph in snapshots {
+            let best_orders = TransitiveOrderbook {
+                asks: pricegraph
+                    .best_ask_transitive_order(market)?
+                    .into_iter()
+                    .collect(),
+                bids: pricegraph
+                    .best_bid_transitive_order(market)?
+                    .into_iter()
+                    .collect(),
+            };
+            for (price, volume) in best_orders.ask_prices().chain(best_orders.bid_prices()) {
+                weighted_price_sum += price * volume;
+                total_volume += volume;
+            }
+        }
+
+        if total_volume > 0.0 {
+            Ok(Some(weighted_price_sum / total_volume))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::prelude::*;
+    use crate::FEE_FACTOR;
+
+    #[test]
+    fn average_price_weighs_snapshots_by_volume() {
+        let base: u128 = 1_000_000_000_000;
+        let first = pricegraph! {
+            users {
+                @0 {
+                    token 1 => 2 * base,
+                }
+            }
+            orders {
+                owner @0 buying 0 [2 * base] selling 1 [base],
+            }
+        };
+        let second = pricegraph! {
+            users {
+                @0 {
+                    token 1 => 3 * base,
+                }
+            }
+            orders {
+                owner @0 buying 0 [3 * base] selling 1 [3 * base],
+            }
+        };
+
+        let market = Market { base: 1, quote: 0 };
+        assert_approx_eq!(
+            Pricegraph::volume_weighted_average_price(vec![&first], market)
+                .unwrap()
+                .unwrap(),
+            2.0 * FEE_FACTOR
+        );
+        assert_approx_eq!(
+            Pricegraph::volume_weighted_average_price(vec![&first, &second], market)
+                .unwrap()
+                .unwrap(),
+            1.25 * FEE_FACTOR
+        );
+    }
+
+    #[test]
+    fn average_price_without_orders_is_none() {
+        let empty = Pricegraph::new(std::iter::empty());
+        assert_eq!(
+            Pricegraph::volume_weighted_average_price(
+                vec![&empty, &empty],
+                Market { base: 1, quote: 0 }
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            Pricegraph::volume_weighted_average_price(vec![], Market { base: 1, quote: 0 })
+                .unwrap(),
+            None
+        );
+    }
+}
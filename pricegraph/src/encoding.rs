@@ -9,6 +9,10 @@ use thiserror::Error;
 /// The stride of an orderbook element in bytes.
 pub const ELEMENT_STRIDE: usize = 114;
 
+/// The stride of an orderbook element in bytes in the packed format, which
+/// does not include order IDs.
+pub const PACKED_ELEMENT_STRIDE: usize = 112;
+
 /// A type alias for a batch ID.
 pub type BatchId = u32;
 
@@ -116,13 +120,62 @@ pub struct Element {
     pub id: OrderId,
 }
 
+/// The formats in which orderbooks are encoded by the smart contracts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EncodingFormat {
+    /// The format of `BatchExchangeViewer::getFilteredOrdersPaginated` where
+    /// every order includes its ID.
+    Indexed,
+    /// The format of `BatchExchange::getEncodedOrders` without order IDs.
+    Packed,
+}
+
+impl EncodingFormat {
+    /// Returns the stride of an orderbook element in bytes for this format.
+    pub fn stride(self) -> usize {
+        match self {
+            EncodingFormat::Indexed => ELEMENT_STRIDE,
+            EncodingFormat::Packed => PACKED_ELEMENT_STRIDE,
+        }
+    }
+
+    /// Detects the format of an encoded orderbook by its byte length.
+    ///
+    /// Note that some lengths are valid for both formats, in which case the
+    /// indexed format is assumed. Callers that know the format of the encoded
+    /// orderbook should specify it explicitly instead.
+    pub fn detect(len: usize) -> Result<Self, InvalidLength> {
+        if len % ELEMENT_STRIDE == 0 {
+            Ok(EncodingFormat::Indexed)
+        } else if len % PACKED_ELEMENT_STRIDE == 0 {
+            Ok(EncodingFormat::Packed)
+        } else {
+            Err(InvalidLength(len))
+        }
+    }
+}
+
 impl Element {
+    /// Reads all elements from an orderbook in the indexed format.
     pub fn read_all(bytes: &[u8]) -> Result<impl Iterator<Item = Self> + '_, InvalidLength> {
-        if bytes.len() % ELEMENT_STRIDE != 0 {
+        Element::read_all_with_format(bytes, EncodingFormat::Indexed)
+    }
+
+    /// Reads all elements from an orderbook in the specified format.
+    ///
+    /// The packed format does not include order IDs, they are instead derived
+    /// from the position of the order in the encoded orderbook. This relies on
+    /// the contract encoding all orders of a user consecutively and in order.
+    pub fn read_all_with_format(
+        bytes: &[u8],
+        format: EncodingFormat,
+    ) -> Result<impl Iterator<Item = Self> + '_, InvalidLength> {
+        if bytes.len() % format.stride() != 0 {
             return Err(InvalidLength(bytes.len()));
         }
 
-        Ok(bytes.chunks(ELEMENT_STRIDE).map(|mut chunk| {
+        let mut previous_order: Option<(UserId, OrderId)> = None;
+        Ok(bytes.chunks(format.stride()).map(move |mut chunk| {
             macro_rules! read {
                 (u16) => {
                     u16::from_be_bytes(read!(2))
@@ -148,7 +201,7 @@ impl Element {
             }
 
             #[allow(unused_assignments, clippy::eval_order_dependence)]
-            Element {
+            let mut element = Element {
                 user: read!(H160),
                 balance: read!(U256),
                 pair: TokenPair {
@@ -164,10 +217,54 @@ impl Element {
                     denominator: read!(u128),
                 },
                 remaining_sell_amount: read!(u128),
-                id: read!(u16),
+                id: match format {
+                    EncodingFormat::Indexed => read!(u16),
+                    EncodingFormat::Packed => 0,
+                },
+            };
+            if format == EncodingFormat::Packed {
+                element.id = match previous_order {
+                    Some((user, id)) if user == element.user => id.wrapping_add(1),
+                    _ => 0,
+                };
             }
+            previous_order = Some((element.user, element.id));
+            element
         }))
     }
+
+    /// Writes elements in the indexed format, the inverse of `read_all`.
+    pub fn write_all<'a>(elements: impl IntoIterator<Item = &'a Element>) -> Vec<u8> {
+        Element::write_all_with_format(elements, EncodingFormat::Indexed)
+    }
+
+    /// Writes elements in the specified format, the inverse of
+    /// `read_all_with_format`.
+    pub fn write_all_with_format<'a>(
+        elements: impl IntoIterator<Item = &'a Element>,
+        format: EncodingFormat,
+    ) -> Vec<u8> {
+        let elements = elements.into_iter();
+        let mut bytes = Vec::with_capacity(elements.size_hint().0 * format.stride());
+        for element in elements {
+            let mut balance = [0u8; 32];
+            element.balance.to_big_endian(&mut balance);
+
+            bytes.extend_from_slice(element.user.as_bytes());
+            bytes.extend_from_slice(&balance);
+            bytes.extend_from_slice(&element.pair.buy.to_be_bytes());
+            bytes.extend_from_slice(&element.pair.sell.to_be_bytes());
+            bytes.extend_from_slice(&element.valid.from.to_be_bytes());
+            bytes.extend_from_slice(&element.valid.to.to_be_bytes());
+            bytes.extend_from_slice(&element.price.numerator.to_be_bytes());
+            bytes.extend_from_slice(&element.price.denominator.to_be_bytes());
+            bytes.extend_from_slice(&element.remaining_sell_amount.to_be_bytes());
+            if format == EncodingFormat::Indexed {
+                bytes.extend_from_slice(&element.id.to_be_bytes());
+            }
+        }
+        bytes
+    }
 }
 
 #[cfg(feature = "fuzz")]
@@ -249,4 +346,65 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn read_all_packed_elements() {
+        let user = |byte: u8| {
+            let mut bytes = vec![byte; 20];
+            bytes.extend_from_slice(&[0u8; 92]);
+            bytes
+        };
+        let bytes = [user(1), user(1), user(2), user(1)].concat();
+
+        let elements = Element::read_all_with_format(&bytes, EncodingFormat::Packed)
+            .unwrap()
+            .map(|element| (element.user, element.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            elements,
+            vec![
+                (H160::repeat_byte(1), 0),
+                (H160::repeat_byte(1), 1),
+                (H160::repeat_byte(2), 0),
+                (H160::repeat_byte(1), 0),
+            ]
+        );
+        assert!(Element::read_all_with_format(&bytes, EncodingFormat::Indexed).is_err());
+    }
+
+    #[test]
+    fn write_all_round_trips() {
+        let bytes = (0u8..228).collect::<Vec<_>>();
+        let elements = Element::read_all(&bytes).unwrap().collect::<Vec<_>>();
+        assert_eq!(Element::write_all(&elements), bytes);
+
+        let packed = (0u8..224).collect::<Vec<_>>();
+        let elements = Element::read_all_with_format(&packed, EncodingFormat::Packed)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Element::write_all_with_format(&elements, EncodingFormat::Packed),
+            packed
+        );
+    }
+
+    #[test]
+    fn detects_encoding_format() {
+        assert_eq!(
+            EncodingFormat::detect(3 * ELEMENT_STRIDE).unwrap(),
+            EncodingFormat::Indexed
+        );
+        assert_eq!(
+            EncodingFormat::detect(3 * PACKED_ELEMENT_STRIDE).unwrap(),
+            EncodingFormat::Packed
+        );
+        // 6384 bytes are 56 indexed or 57 packed elements.
+        assert_eq!(6384 % ELEMENT_STRIDE, 0);
+        assert_eq!(6384 % PACKED_ELEMENT_STRIDE, 0);
+        assert_eq!(
+            EncodingFormat::detect(6384).unwrap(),
+            EncodingFormat::Indexed
+        );
+        assert!(EncodingFormat::detect(113).is_err());
+    }
 }
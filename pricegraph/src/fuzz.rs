@@ -0,0 +1,51 @@
+//! Entry points for the `cargo fuzz` targets in the `fuzz` directory.
+//!
+//! The targets only need to assert that these functions don't panic. Inputs
+//! that would make a single run too slow are skipped, so that the fuzzer
+//! spends its time on inputs that can actually find panics: the orderbook
+//! graph has a node for every token ID up to the largest one, and reducing
+//! an orderbook fills at most one ring trade per order.
+
+use crate::encoding::{Element, TokenId};
+use crate::orderbook::Orderbook;
+use std::iter;
+
+/// The largest token ID that is allowed to appear in fuzzed orders.
+pub const MAX_TOKEN_ID: TokenId = 16;
+
+/// The maximum number of orders in a fuzzed orderbook.
+pub const MAX_ELEMENTS: usize = 64;
+
+/// Returns whether an orderbook with the specified elements is small enough to
+/// be fuzzed.
+pub fn is_within_limits(elements: &[Element]) -> bool {
+    elements.len() <= MAX_ELEMENTS
+        && elements
+            .iter()
+            .flat_map(|element| iter::once(element.pair.buy).chain(iter::once(element.pair.sell)))
+            .all(|token| token <= MAX_TOKEN_ID)
+}
+
+/// Decodes elements from arbitrary bytes with `Element::read_all` and builds
+/// and reduces an orderbook from them. Also checks that encoding the elements
+/// again results in the original bytes.
+pub fn read_and_reduce(bytes: &[u8]) {
+    let elements = match Element::read_all(bytes) {
+        Ok(elements) => elements.take(MAX_ELEMENTS + 1).collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    if elements.len() <= MAX_ELEMENTS {
+        assert_eq!(Element::write_all(&elements), bytes);
+    }
+    reduce_overlapping_orders(elements);
+}
+
+/// Builds an orderbook from the specified elements and reduces it. Errors are
+/// expected for orderbooks that cannot be reduced because of floating point
+/// imprecision and are ignored.
+pub fn reduce_overlapping_orders(elements: Vec<Element>) {
+    if !is_within_limits(&elements) {
+        return;
+    }
+    let _ = Orderbook::from_elements(elements).reduce_overlapping_orders();
+}
@@ -3,7 +3,7 @@
 use petgraph::visit::NodeIndexable;
 use std::ops::Deref;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 /// A path of nodes connected by a (directed) edge.
 pub struct Path<N>(pub Vec<N>);
 
@@ -21,6 +21,12 @@ impl<N> From<NegativeCycle<N>> for Path<N> {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Error for a path that loops because of a negative cycle that was not
+/// detected by the shortest path search, for example because of rounding
+/// errors.
+pub struct UndetectedNegativeCycle;
+
 #[derive(Clone, Debug)]
 /// An ordered collection of nodes that form a cycle of negative weight.
 /// The first node of the cycle coincides with the last.
@@ -2,7 +2,7 @@
 //! implementation of the Bellman-Ford graph search algorigthm that returns the
 //! detected negative cycle on error.
 
-use super::path::{NegativeCycle, Path};
+use super::path::{NegativeCycle, Path, UndetectedNegativeCycle};
 use bounded::Bounded;
 use petgraph::algo::FloatMeasure;
 use petgraph::visit::{
@@ -31,7 +31,13 @@ pub trait PredecessorStoring<G: GraphBase + Data> {
     fn update_predecessor(&mut self, node_index: usize, updated_predecessor: Option<G::NodeId>);
 
     /// Returns shortest path from source to destination node, if a path exists.
-    fn path_to(&self, source: G::NodeId, dest: G::NodeId, graph: G) -> Option<Path<G::NodeId>>;
+    /// Returns an error if the path runs into an undetected negative cycle.
+    fn path_to(
+        &self,
+        source: G::NodeId,
+        dest: G::NodeId,
+        graph: G,
+    ) -> Result<Option<Path<G::NodeId>>, UndetectedNegativeCycle>;
 
     /// Lists all nodes that can be reached from the source (excluding the source itself).
     fn connected_nodes(&self, graph: G) -> Vec<G::NodeId>;
@@ -59,7 +65,20 @@ pub trait ShortestPathGraph<G: GraphBase + IntoEdges> {
     fn update_predecessor(&mut self, node: G::NodeId, updated_predecessor: Option<G::NodeId>);
 
     /// Returns shortest path from source to destination node, if a path exists.
-    fn path_to(&self, dest: G::NodeId) -> Option<Path<G::NodeId>>;
+    ///
+    /// # Panics
+    ///
+    /// Panics if the path runs into an undetected negative cycle.
+    fn path_to(&self, dest: G::NodeId) -> Option<Path<G::NodeId>> {
+        self.try_path_to(dest).expect("undetected negative cycle")
+    }
+
+    /// Returns shortest path from source to destination node, if a path exists.
+    /// Returns an error if the path runs into an undetected negative cycle.
+    fn try_path_to(
+        &self,
+        dest: G::NodeId,
+    ) -> Result<Option<Path<G::NodeId>>, UndetectedNegativeCycle>;
 
     /// Lists all nodes that can be reached from the source (including the source itself).
     fn connected_nodes(&self) -> Vec<G::NodeId>;
@@ -104,7 +123,10 @@ where
     }
 
     /// Returns shortest path from source to destination node, if a path exists.
-    fn path_to(&self, dest: G::NodeId) -> Option<Path<G::NodeId>> {
+    fn try_path_to(
+        &self,
+        dest: G::NodeId,
+    ) -> Result<Option<Path<G::NodeId>>, UndetectedNegativeCycle> {
         self.predecessor_store
             .path_to(self.source, dest, self.graph)
     }
@@ -1,4 +1,4 @@
-use super::super::path::{NegativeCycle, Path};
+use super::super::path::{NegativeCycle, Path, UndetectedNegativeCycle};
 use super::{nodes_from_predecessors, Distances, PredecessorStoring, PredecessorVec};
 use petgraph::algo::FloatMeasure;
 use petgraph::visit::{Data, IntoNodeIdentifiers, NodeIndexable};
@@ -54,7 +54,12 @@ where
             updated_predecessor;
     }
 
-    fn path_to(&self, source: G::NodeId, dest: G::NodeId, graph: G) -> Option<Path<G::NodeId>> {
+    fn path_to(
+        &self,
+        source: G::NodeId,
+        dest: G::NodeId,
+        graph: G,
+    ) -> Result<Option<Path<G::NodeId>>, UndetectedNegativeCycle> {
         let mut path;
         let mut current = dest;
         let max_path_len = self.predecessors_at_step.len();
@@ -71,13 +76,13 @@ where
             }
         }
         match found {
-            false => None,
+            false => Ok(None),
             true => {
                 path.push(source);
                 // NOTE: `path` is in reverse order, since it was built by walking the path
                 // backwards, so reverse it and done!
                 path.reverse();
-                Some(Path(path))
+                Ok(Some(Path(path)))
             }
         }
     }
@@ -1,7 +1,7 @@
 //! The module contains the implementation of bellman-ford where the shortest path may
 //! contain of arbitrarily many edges
 
-use super::super::path::{find_cycle, NegativeCycle, Path};
+use super::super::path::{find_cycle, NegativeCycle, Path, UndetectedNegativeCycle};
 use super::{nodes_from_predecessors, Distances, PredecessorStoring, PredecessorVec};
 use petgraph::algo::FloatMeasure;
 use petgraph::visit::{Data, EdgeRef, IntoEdges, IntoNodeIdentifiers, NodeIndexable};
@@ -37,20 +37,30 @@ where
         self.predecessors[node_index] = updated_predecessor;
     }
 
-    fn path_to(&self, source: G::NodeId, dest: G::NodeId, graph: G) -> Option<Path<G::NodeId>> {
+    fn path_to(
+        &self,
+        source: G::NodeId,
+        dest: G::NodeId,
+        graph: G,
+    ) -> Result<Option<Path<G::NodeId>>, UndetectedNegativeCycle> {
         let max_path_len = self.predecessors.len();
         let mut path = Vec::with_capacity(max_path_len);
         let mut current = dest;
         while current != source {
-            assert!(path.len() <= max_path_len, "undetected negative cycle");
+            if path.len() > max_path_len {
+                return Err(UndetectedNegativeCycle);
+            }
             path.push(current);
-            current = self.predecessors[graph.to_index(current)]?;
+            current = match self.predecessors[graph.to_index(current)] {
+                Some(predecessor) => predecessor,
+                None => return Ok(None),
+            };
         }
         path.push(source);
         // NOTE: `path` is in reverse order, since it was built by walking the path
         // backwards, so reverse it and done!
         path.reverse();
-        Some(Path(path))
+        Ok(Some(Path(path)))
     }
 
     fn connected_nodes(&self, graph: G) -> Vec<G::NodeId> {
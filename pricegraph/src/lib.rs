@@ -6,13 +6,17 @@ mod test;
 
 mod api;
 mod encoding;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 mod graph;
 pub mod num;
 mod orderbook;
+mod rounding_buffer;
 
 pub use self::api::*;
 pub use self::encoding::*;
 pub use self::orderbook::*;
+pub use self::rounding_buffer::*;
 
 /// The fee factor that is applied to each order's buy price.
 pub const FEE_FACTOR: f64 = 1.0 / 0.999;
@@ -60,8 +64,23 @@ impl Pricegraph {
     /// - `16` bytes: price denominator
     /// - `16` bytes: remaining order sell amount
     /// - `2` bytes: order ID
+    ///
+    /// Orderbooks in the packed format of `BatchExchange::getEncodedOrders`,
+    /// which is the same without order IDs and has a `112` byte stride, are
+    /// also accepted. The format is detected by the byte length, see
+    /// `EncodingFormat::detect` for details.
     pub fn read(bytes: impl AsRef<[u8]>) -> Result<Self, InvalidLength> {
-        let elements = Element::read_all(bytes.as_ref())?;
+        let bytes = bytes.as_ref();
+        Pricegraph::read_with_format(bytes, EncodingFormat::detect(bytes.len())?)
+    }
+
+    /// Create a new `Pricegraph` instance from auction elements encoded in the
+    /// specified format.
+    pub fn read_with_format(
+        bytes: impl AsRef<[u8]>,
+        format: EncodingFormat,
+    ) -> Result<Self, InvalidLength> {
+        let elements = Element::read_all_with_format(bytes.as_ref(), format)?;
         Ok(Pricegraph::new(elements))
     }
 
@@ -11,6 +11,7 @@ mod map;
 mod order;
 mod reduced;
 mod scalar;
+mod shortest_paths;
 mod user;
 mod weight;
 
@@ -19,6 +20,7 @@ pub use self::iter::TransitiveOrders;
 use self::order::{Amount, Order, OrderCollector, OrderMap};
 pub use self::reduced::ReducedOrderbook;
 pub use self::scalar::{ExchangeRate, LimitPrice};
+use self::shortest_paths::ShortestPathCache;
 use self::user::{User, UserMap};
 pub use self::weight::Weight;
 use crate::api::Market;
@@ -49,6 +51,9 @@ pub struct Orderbook {
     /// A projection of the orderbook onto a graph with nodes as tokens and
     /// edges as the lowest order exchange rate between token pairs.
     projection: OrderbookGraph,
+    /// Shortest path trees of the projection graph for finding transitive
+    /// orders. The cache is replaced whenever the projection graph changes.
+    shortest_paths: ShortestPathCache,
 }
 
 impl Orderbook {
@@ -97,6 +102,7 @@ impl Orderbook {
             orders,
             users,
             projection,
+            shortest_paths: ShortestPathCache::default(),
         }
     }
 
@@ -255,6 +261,7 @@ impl Orderbook {
     /// order or removing the edge entirely if no orders remain for the given
     /// token pair.
     fn update_projection_graph_edge(&mut self, pair: TokenPair) {
+        self.shortest_paths = ShortestPathCache::default();
         while let Some(true) = self.orders.best_order_for_pair(pair).map(|order| {
             num::is_dust_amount(num::u256_to_u128_saturating(
                 order.get_effective_amount(&self.users),
@@ -291,11 +298,21 @@ impl Orderbook {
         end: NodeIndex,
         hops: Option<usize>,
     ) -> Result<Option<(Path<NodeIndex>, Flow)>, OrderbookError> {
-        let shortest_path_graph =
-            shortest_path(&self.projection, start, hops).map_err(OrderbookError::OverlapError)?;
-        let path = match shortest_path_graph.path_to(end) {
-            Some(path) => path,
-            None => return Ok(None),
+        let shortest_paths = self
+            .shortest_paths
+            .get_or_compute(start, hops, || {
+                let shortest_path_graph = shortest_path(&self.projection, start, hops)?;
+                Ok(self
+                    .projection
+                    .node_indices()
+                    .map(|node| shortest_path_graph.try_path_to(node))
+                    .collect())
+            })
+            .map_err(OrderbookError::OverlapError)?;
+        let path = match &shortest_paths[end.index()] {
+            Ok(Some(path)) => path.clone(),
+            Ok(None) => return Ok(None),
+            Err(_) => panic!("undetected negative cycle"),
         };
 
         let flow = self.find_path_flow(&path)?;
@@ -841,4 +858,52 @@ mod tests {
             .transitive_orders(TokenPair { buy: 0, sell: 10 }.into_unbounded_range())
             .is_err());
     }
+
+    #[test]
+    fn shares_shortest_paths_between_clones_until_modified() {
+        // 0 --1.0--> 1 --1.0--> 2
+        //  \                    ^
+        //   \-------2.0--------/
+        let orderbook = orderbook! {
+            users {
+                @1 {
+                    token 1 => 100_000_000,
+                }
+                @2 {
+                    token 2 => 100_000_000,
+                }
+            }
+            orders {
+                owner @1 buying 0 [1_000_000] selling 1 [1_000_000],
+                owner @2 buying 1 [1_000_000] selling 2 [1_000_000],
+                owner @2 buying 0 [2_000_000] selling 2 [1_000_000],
+            }
+        };
+        let pair = TokenPair { buy: 0, sell: 2 }.into_unbounded_range();
+        let is_cached = |orderbook: &Orderbook| {
+            orderbook
+                .shortest_paths
+                .0
+                .lock()
+                .unwrap()
+                .contains_key(&(node_index(0), None))
+        };
+        let transitive_orders = |orderbook: &Orderbook| {
+            orderbook
+                .clone()
+                .transitive_orders(pair)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        let first = transitive_orders(&orderbook);
+        assert!(is_cached(&orderbook));
+        assert_eq!(transitive_orders(&orderbook), first);
+
+        let mut modified = orderbook.clone();
+        modified.update_projection_graph_edge(TokenPair { buy: 0, sell: 1 });
+        assert!(!is_cached(&modified));
+        assert!(is_cached(&orderbook));
+    }
 }
@@ -0,0 +1,86 @@
+//! Module containing a cache of shortest path trees for the projection graph
+//! of an orderbook.
+
+use crate::graph::path::{NegativeCycle, Path, UndetectedNegativeCycle};
+use petgraph::graph::NodeIndex;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The shortest path from a starting token to a token in the projection graph.
+/// Paths running into a negative cycle that went undetected because of
+/// rounding errors are stored as errors so that they only fail when queried.
+pub type ShortestPathEntry = Result<Option<Path<NodeIndex>>, UndetectedNegativeCycle>;
+
+/// The shortest paths from a starting token to every token in the projection
+/// graph, indexed by node index.
+pub type ShortestPathTree = Arc<Vec<ShortestPathEntry>>;
+
+/// Shortest path trees by starting token and maximum number of hops.
+type ShortestPathTrees = HashMap<(NodeIndex, Option<usize>), ShortestPathTree>;
+
+/// A cache of shortest path trees per starting token and maximum number of
+/// hops.
+///
+/// Clones of an orderbook share the cache of the orderbook they were cloned
+/// from, so that queries on clones of an unmodified orderbook can reuse each
+/// other's results. This means that an orderbook must replace its cache with
+/// a new one instead of clearing it when its projection graph changes.
+#[derive(Clone, Debug, Default)]
+pub struct ShortestPathCache(pub(super) Arc<Mutex<ShortestPathTrees>>);
+
+impl ShortestPathCache {
+    /// Returns the cached shortest path tree for the starting token, computing
+    /// it if it is not cached. Negative cycles are not cached.
+    pub fn get_or_compute(
+        &self,
+        start: NodeIndex,
+        hops: Option<usize>,
+        compute: impl FnOnce() -> Result<Vec<ShortestPathEntry>, NegativeCycle<NodeIndex>>,
+    ) -> Result<ShortestPathTree, NegativeCycle<NodeIndex>> {
+        let key = (start, hops);
+        if let Some(tree) = self.0.lock().unwrap().get(&key) {
+            return Ok(tree.clone());
+        }
+
+        // NOTE: The tree is computed without holding the lock so that queries
+        // for other tokens are not blocked.
+        let tree = Arc::new(compute()?);
+        self.0.lock().unwrap().insert(key, tree.clone());
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_tree_once_per_start_and_hops() {
+        let cache = ShortestPathCache::default();
+        let tree = || Ok(vec![Ok(None), Ok(Some(Path(vec![NodeIndex::new(0)])))]);
+        let start = NodeIndex::new(0);
+
+        let first = cache.get_or_compute(start, None, tree).unwrap();
+        let second = cache
+            .get_or_compute(start, None, || panic!("tree computed twice"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let bounded = cache.get_or_compute(start, Some(1), tree).unwrap();
+        assert!(!Arc::ptr_eq(&first, &bounded));
+    }
+
+    #[test]
+    fn does_not_cache_negative_cycles() {
+        let cache = ShortestPathCache::default();
+        let start = NodeIndex::new(0);
+
+        let cycle = NegativeCycle(vec![start, NodeIndex::new(1), start]);
+        assert!(cache
+            .get_or_compute(start, None, || Err(cycle.clone()))
+            .is_err());
+        assert!(cache.get_or_compute(start, None, || Ok(vec![])).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 //! Module contains implementation of the weight used by order in graph path
 //! finding algorightms.
 
+use crate::num;
 use petgraph::algo::FloatMeasure;
 use std::{fmt, ops};
 
@@ -59,21 +60,69 @@ pub struct Weight(Fixed24x104);
 
 impl Weight {
     /// Creates a new graph weight from a floating point number.
+    ///
+    /// The base-2 logarithm is computed with integer arithmetic instead of
+    /// `f64::log2` so that weights are identical on every platform. This is
+    /// required since `f64::log2` is not guaranteed to be correctly rounded,
+    /// and its result differs between the native and the WebAssembly builds
+    /// of the pricegraph.
     pub fn new(value: f64) -> Self {
-        // TODO(nlordell): In the future, it might be nice to compute the `log2`
-        // already as a fixed point value in order to have more precision.
-        // Currently, since it is computed as a `f64` it is limited to 53 bits
-        // of precision instead of the full `104 + 7` that is possible given
-        // the range of the `log2` values and size of the fixed point number.
-
-        let weight = value.log2() * FIXED_24X104_SCALING_FACTOR;
-        debug_assert!(
-            (-128.0 * FIXED_24X104_SCALING_FACTOR..114.72 * FIXED_24X104_SCALING_FACTOR)
-                .contains(&weight)
-        );
+        let weight = log2(value);
+        debug_assert!((-128 << 104..115 << 104).contains(&weight));
+
+        Weight(weight)
+    }
+}
+
+/// The number of fractional bits used for computing the fractional part of
+/// a base-2 logarithm. This is chosen so that the square of a number in the
+/// range `[1, 2)` fits in a `u128`.
+const LOG2_FRACTIONAL_BITS: u32 = 63;
+
+/// Computes the base-2 logarithm of a strictly positive and finite `f64` as a
+/// fixed point number.
+///
+/// The integer part of the logarithm is the exponent of the `f64`, and the
+/// fractional part is the logarithm of its mantissa in the range `[1, 2)`,
+/// computed one bit at a time by repeatedly squaring the mantissa: whenever
+/// the square is at least `2`, the next bit of the logarithm is set and the
+/// square is halved. The result is accurate to about 60 fractional bits which
+/// is more than the 53 bits of precision of the `f64`.
+fn log2(value: f64) -> Fixed24x104 {
+    debug_assert!(num::is_strictly_positive_and_finite(value));
+
+    const MANTISSA_BITS: u32 = 52;
+    const MANTISSA_MASK: u64 = (1 << MANTISSA_BITS) - 1;
+    const EXPONENT_BIAS: i32 = 1023;
+
+    let bits = value.to_bits();
+    let biased_exponent = (bits >> MANTISSA_BITS) as i32;
+    let (exponent, mantissa) = if biased_exponent == 0 {
+        // NOTE: Subnormal numbers don't have an implicit leading bit, so
+        // normalize them by shifting the mantissa.
+        let shift = (bits & MANTISSA_MASK).leading_zeros() - (63 - MANTISSA_BITS);
+        (
+            1 - EXPONENT_BIAS - shift as i32,
+            (bits & MANTISSA_MASK) << shift,
+        )
+    } else {
+        (
+            biased_exponent - EXPONENT_BIAS,
+            (bits & MANTISSA_MASK) | (1 << MANTISSA_BITS),
+        )
+    };
 
-        Weight(weight as _)
+    let mut x = u128::from(mantissa) << (LOG2_FRACTIONAL_BITS - MANTISSA_BITS);
+    let mut fraction = 0;
+    for bit in (0..LOG2_FRACTIONAL_BITS).rev() {
+        x = (x * x) >> LOG2_FRACTIONAL_BITS;
+        if x >= 2 << LOG2_FRACTIONAL_BITS {
+            x >>= 1;
+            fraction |= 1 << bit;
+        }
     }
+
+    (Fixed24x104::from(exponent) << 104) + (fraction << (104 - LOG2_FRACTIONAL_BITS))
 }
 
 impl FloatMeasure for Weight {
@@ -124,9 +173,18 @@ impl fmt::Debug for Weight {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{num, MIN_AMOUNT};
+    use crate::{FEE_FACTOR, MIN_AMOUNT};
+    use assert_approx_eq::assert_approx_eq;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::cmp;
 
+    /// Returns random values in the range of exchange rates, distributed
+    /// uniformly in the logarithmic scale.
+    fn random_exchange_rates() -> impl Iterator<Item = f64> {
+        let mut rng = StdRng::seed_from_u64(42);
+        (0..10_000).map(move |_| 2.0f64.powf(rng.gen_range(-128.0..114.72)))
+    }
+
     #[test]
     fn weight_range_fits_in_fixed_point_number() {
         // NOTE: This test relies on float to integer conversion being
@@ -179,4 +237,70 @@ mod tests {
             ),
         );
     }
+
+    #[test]
+    fn log2_matches_f64_log2() {
+        for value in random_exchange_rates().chain(vec![1.0, FEE_FACTOR, 0.999, 3.0]) {
+            let expected = value.log2();
+            let actual = log2(value) as f64 / FIXED_24X104_SCALING_FACTOR;
+            assert_approx_eq!(
+                actual,
+                expected,
+                2.0 * num::max_rounding_error_with_epsilon(expected.abs()) + 2.0f64.powi(-58)
+            );
+        }
+    }
+
+    #[test]
+    fn log2_is_exact_for_powers_of_two() {
+        for exponent in -1022..=1023 {
+            assert_eq!(
+                log2(2.0f64.powi(exponent)),
+                Fixed24x104::from(exponent) << 104
+            );
+        }
+        assert_eq!(log2(f64::from_bits(1)), -1074 << 104);
+    }
+
+    #[test]
+    fn log2_of_product_with_power_of_two_is_sum() {
+        for value in random_exchange_rates() {
+            for exponent in &[-64, -1, 1, 64] {
+                assert_eq!(
+                    log2(value * 2.0f64.powi(*exponent)),
+                    log2(value) + (Fixed24x104::from(*exponent) << 104)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn log2_is_monotonic() {
+        let mut values = random_exchange_rates().collect::<Vec<_>>();
+        values.sort_by(|a, b| num::compare(*a, *b));
+        for pair in values.windows(2) {
+            assert!(log2(pair[0]) <= log2(pair[1]));
+        }
+
+        // NOTE: Also check adjacent `f64`s close to `1.0` where the `f64`
+        // logarithm has the least absolute precision.
+        let one = 1.0f64.to_bits();
+        for bits in one - 1000..one + 1000 {
+            assert!(log2(f64::from_bits(bits)) < log2(f64::from_bits(bits + 1)));
+        }
+    }
+
+    #[test]
+    fn weight_orders_like_f64_weight() {
+        let values = random_exchange_rates().collect::<Vec<_>>();
+        for pair in values.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if num::compare(a.log2(), b.log2()) != cmp::Ordering::Equal {
+                assert_eq!(
+                    Weight::new(a).cmp(&Weight::new(b)),
+                    num::compare(a.log2(), b.log2())
+                );
+            }
+        }
+    }
 }
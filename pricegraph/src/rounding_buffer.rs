@@ -0,0 +1,53 @@
+//! Module implementing the rounding buffer that the solvers apply to orders.
+//! It is shared between the price estimator and the WASM bindings so that
+//! estimates and orders constructed client-side use the same amounts.
+//!
+//! This code is closely related to dex-solver/src/opt/process/Rounding.py .
+//! Discussion of motivation happened in https://github.com/gnosis/dex-services/issues/970 .
+
+const MAX_ROUNDING_VOLUME: f64 = 100_000_000_000.0;
+const PRICE_ESTIMATION_ERROR: f64 = 10.0;
+
+fn max_rounding_amount(token_price: f64, fee_token_price: f64) -> f64 {
+    let estimated_price_in_fee_token = token_price / fee_token_price;
+    let max_rounding_amount = MAX_ROUNDING_VOLUME / estimated_price_in_fee_token;
+    max_rounding_amount.max(1.0)
+}
+
+/// Calculate a single rounding buffer like the solver does. This amount is
+/// subtracted from the denominator of orders selling the sell token and buying
+/// the buy token.
+///
+/// Token prices are in fee token atoms for `10^18` atoms of the token, as used
+/// by the solver. The rounding buffer is multiplied by an extra factor which
+/// the solver doesn't use, as added safety in case the prices move.
+pub fn rounding_buffer(
+    fee_token_price: f64,
+    sell_token_price: f64,
+    buy_token_price: f64,
+    extra_factor: f64,
+) -> f64 {
+    let estimated_xrate = buy_token_price / sell_token_price;
+    max_rounding_amount(buy_token_price, fee_token_price)
+        * estimated_xrate
+        * PRICE_ESTIMATION_ERROR.powi(2)
+        * extra_factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn rounding_buffer_scales_with_exchange_rate() {
+        assert_approx_eq!(rounding_buffer(1.0, 2.0, 1.0, 1.0), 5e12);
+        assert_approx_eq!(rounding_buffer(1.0, 1.0, 2.0, 1.0), 1e13);
+        assert_approx_eq!(rounding_buffer(1.0, 1.0, 2.0, 2.0), 2e13);
+    }
+
+    #[test]
+    fn rounding_amount_is_at_least_one_atom() {
+        assert_approx_eq!(rounding_buffer(1.0, 1e12, 1e12, 1.0), 100.0);
+    }
+}
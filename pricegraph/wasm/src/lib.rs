@@ -1,5 +1,6 @@
 //! This crate provides a thin WASM-compatible wrapper around the `pricegraph`
-//! crate and can be used for estimating prices for a given orderbook.
+//! crate and can be used for estimating prices for a given orderbook and for
+//! computing the rounding buffers the solver applies to orders.
 
 use pricegraph::{Pricegraph, TokenId, TokenPair};
 use wasm_bindgen::prelude::*;
@@ -51,3 +52,25 @@ impl PriceEstimator {
             .map_err(|err| JsValue::from(err.to_string()))
     }
 }
+
+/// Computes the rounding buffer the solver subtracts from the sell amount of
+/// orders selling the sell token for the buy token, in sell token atoms.
+///
+/// Token prices are in fee token atoms for `10^18` atoms of the token, as
+/// returned by the price estimator's `prices` route. The extra factor should
+/// match the one the price estimator is configured with to get the same
+/// results as its estimates.
+#[wasm_bindgen(js_name = "roundingBuffer")]
+pub fn rounding_buffer(
+    fee_token_price: f64,
+    sell_token_price: f64,
+    buy_token_price: f64,
+    extra_factor: f64,
+) -> f64 {
+    pricegraph::rounding_buffer(
+        fee_token_price,
+        sell_token_price,
+        buy_token_price,
+        extra_factor,
+    )
+}
@@ -1,6 +1,6 @@
 extern crate wasm_bindgen_test;
 
-use dex_pricegraph::PriceEstimator;
+use dex_pricegraph::{rounding_buffer, PriceEstimator};
 use pricegraph_data::DEFAULT_ORDERBOOK;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_test::*;
@@ -29,3 +29,15 @@ fn estimate_price() {
         estimate_time,
     );
 }
+
+#[wasm_bindgen_test]
+fn compute_rounding_buffer() {
+    let [fee_token_price, weth_price, dai_price] = [1e18, 400e18, 1e18];
+    let buffer = rounding_buffer(fee_token_price, dai_price, weth_price, 1.0);
+
+    console_log!(
+        "rounding buffer for orders selling DAI for WETH: {} DAI",
+        buffer / 1e18,
+    );
+    assert!(buffer > 0.0);
+}
@@ -0,0 +1,159 @@
+//! Benchmarks for building the event based orderbook state and retrieving the
+//! orderbook of a batch from it.
+//!
+//! By default the event stream is reconstructed from the mainnet orderbook in
+//! `pricegraph/data`. A recorded event stream can be benchmarked instead by
+//! setting `ORDERBOOK_FILE` to an orderbook file written by the driver.
+
+use contracts::batch_exchange::{
+    event_data::{Deposit, OrderPlacement, TokenListing},
+    Event,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ethcontract::{Address, U256};
+use pricegraph::Element;
+use pricegraph_data::{DEFAULT_BATCH_ID, DEFAULT_ORDERBOOK};
+use services_core::{history::events::EventRegistry, orderbook::streamed::State};
+use std::{
+    collections::{BTreeSet, HashSet},
+    convert::TryFrom,
+    env,
+    path::Path,
+    time::Duration,
+};
+
+/// The events to apply together with the batch of their block, and the batch
+/// to retrieve the orderbook for.
+struct EventStream {
+    name: String,
+    events: Vec<(Event, u32)>,
+    batch_id: u32,
+}
+
+fn read_event_stream() -> EventStream {
+    match env::var("ORDERBOOK_FILE") {
+        Ok(path) => recorded_event_stream(Path::new(&path)),
+        Err(_) => reconstructed_event_stream(),
+    }
+}
+
+fn recorded_event_stream(path: &Path) -> EventStream {
+    let registry = EventRegistry::try_from(path).expect("error reading orderbook file");
+    let events = registry
+        .into_events()
+        .map(|(event, batch_id)| (event, batch_id.into()))
+        .collect::<Vec<_>>();
+    let batch_id = events.last().map(|(_, batch_id)| batch_id + 1).unwrap_or(0);
+    EventStream {
+        name: path.display().to_string(),
+        events,
+        batch_id,
+    }
+}
+
+/// Creates the token listings, deposits and order placements that result in
+/// the orders and balances of the default mainnet orderbook.
+fn reconstructed_event_stream() -> EventStream {
+    let elements = Element::read_all(&*DEFAULT_ORDERBOOK)
+        .expect("error reading orderbook")
+        .collect::<Vec<_>>();
+    let batch_id = *DEFAULT_BATCH_ID;
+    let event_batch_id = batch_id - 1;
+    let token = |id: u16| Address::from_low_u64_be(u64::from(id) + 1);
+    let user = |element: &Element| Address::from_slice(element.user.as_bytes());
+
+    let tokens = elements
+        .iter()
+        .flat_map(|element| vec![element.pair.buy, element.pair.sell])
+        .chain(Some(0))
+        .collect::<BTreeSet<_>>();
+    let token_listings = tokens.into_iter().map(|id| {
+        Event::TokenListing(TokenListing {
+            token: token(id),
+            id,
+        })
+    });
+
+    let mut deposited = HashSet::new();
+    let deposits = elements
+        .iter()
+        .filter(|element| deposited.insert((element.user, element.pair.sell)))
+        .map(|element| {
+            let mut amount = [0u8; 32];
+            element.balance.to_big_endian(&mut amount);
+            Event::Deposit(Deposit {
+                user: user(element),
+                token: token(element.pair.sell),
+                amount: U256::from_big_endian(&amount),
+                batch_id: event_batch_id,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let order_placements = elements.iter().map(|element| {
+        Event::OrderPlacement(OrderPlacement {
+            owner: user(element),
+            index: element.id,
+            buy_token: element.pair.buy,
+            sell_token: element.pair.sell,
+            valid_from: element.valid.from,
+            valid_until: element.valid.to,
+            price_numerator: element.price.numerator,
+            price_denominator: element.price.denominator,
+        })
+    });
+
+    EventStream {
+        name: batch_id.to_string(),
+        events: token_listings
+            .chain(deposits)
+            .chain(order_placements)
+            .map(|event| (event, event_batch_id))
+            .collect(),
+        batch_id,
+    }
+}
+
+fn build_state(events: &[(Event, u32)]) -> State {
+    State::from_events(events.iter().map(|(event, batch_id)| (event, *batch_id)))
+        .expect("error applying events")
+}
+
+pub fn apply_events(c: &mut Criterion) {
+    let stream = read_event_stream();
+
+    let mut group = c.benchmark_group("State::apply_event");
+    group.throughput(Throughput::Elements(stream.events.len() as _));
+    group.bench_with_input(
+        BenchmarkId::from_parameter(&stream.name),
+        &stream.events,
+        |b, events| b.iter(|| build_state(events)),
+    );
+    group.finish();
+}
+
+pub fn orderbook_for_batch(c: &mut Criterion) {
+    let stream = read_event_stream();
+    let state = build_state(&stream.events);
+
+    let mut group = c.benchmark_group("State::canonicalized_auction_state_at_beginning_of_batch");
+    group.bench_with_input(
+        BenchmarkId::from_parameter(&stream.name),
+        &(&state, stream.batch_id),
+        |b, &(state, batch_id)| {
+            b.iter(|| {
+                state
+                    .canonicalized_auction_state_at_beginning_of_batch(batch_id)
+                    .expect("error retrieving orderbook")
+            })
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(
+    name = orderbook;
+    config = Criterion::default().measurement_time(Duration::from_secs(30));
+    targets = apply_events, orderbook_for_batch
+);
+criterion_main!(orderbook);
@@ -0,0 +1,7 @@
+fn main() {
+    // The solver protocol is only used by the gRPC solver.
+    #[cfg(feature = "solver")]
+    tonic_build::configure()
+        .compile(&["proto/solver.proto"], &["proto"])
+        .expect("failed to compile solver protocol");
+}
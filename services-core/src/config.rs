@@ -0,0 +1,544 @@
+//! Module for the command line options shared by the binaries and for layering
+//! a configuration file below environment variables and command line
+//! arguments.
+//!
+//! Every key in the configuration file corresponds to the long name of a
+//! command line option, for example:
+//!
+//! ```toml
+//! node-url = "http://localhost:8545"
+//! gas-estimators = ["GasNow", "Web3"]
+//!
+//! [orderbook-filter]
+//! tokens = { Whitelist = [1, 2] }
+//! ```
+//!
+//! Values from the file are only used for options that are neither passed on
+//! the command line nor set in the environment.
+
+use crate::{
+    economic_viability::{
+        EconomicViabilityComputing, EconomicViabilityStrategy, NativeTokenPricing,
+        ThresholdSmoothing,
+    },
+    event_export::{nats::NatsEventSink, EventSink},
+    gas_price::GasPriceEstimating,
+    http::HttpFactory,
+    http_server::Handler,
+    orderbook::{
+        streamed::checkpoint::{
+            CheckpointFile, CheckpointSecret, CheckpointSource, SnapshotHandler,
+        },
+        OrderbookFilter,
+    },
+};
+use anyhow::{anyhow, Context as _, Result};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use structopt::{
+    clap::{Error, ErrorKind},
+    StructOpt,
+};
+use toml::Value;
+use url::Url;
+
+const CONFIG_FILE_ARG: &str = "--config-file";
+const CONFIG_FILE_ENV: &str = "CONFIG_FILE";
+
+/// Options controlling how the configuration is loaded.
+#[derive(Debug, StructOpt)]
+pub struct ConfigOptions {
+    /// Path to a TOML file with default values for any of the other options.
+    /// Keys are the long option names. Environment variables and command line
+    /// arguments take precedence over values from the file.
+    #[structopt(long, env = "CONFIG_FILE", parse(from_os_str))]
+    pub config_file: Option<PathBuf>,
+
+    /// Print the resolved configuration and exit.
+    #[structopt(long)]
+    pub print_config: bool,
+}
+
+/// Options for reading the orderbook from the exchange contract events.
+#[derive(Debug, StructOpt)]
+pub struct OrderbookOptions {
+    /// JSON encoded object of which tokens/orders to ignore.
+    ///
+    /// For example: '{
+    ///   "tokens": {"Whitelist": [1, 2]},
+    ///   "users": {
+    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0A": { "OrderIds": [0, 1] },
+    ///     "0x7b60655Ca240AC6c76dD29c13C45BEd969Ee6F0B": "All"
+    ///   },
+    ///   "orders": ["sell_amount >= 1000000", "valid_for >= 2", "pair in [1/2, 1/7]"]
+    ///  }'
+    /// Order expressions support the fields `sell_amount`, `balance`,
+    /// `valid_for` (in batches) and `sell_value` (in OWL atoms) with
+    /// comparison operators as well as a token pair allowlist, which can be
+    /// used to only solve the markets a solver is specialized on. `sell_value`
+    /// needs an infallible price source, which currently only the price
+    /// estimator has, so other services refuse to start with it.
+    /// More examples can be found in the tests of orderbook/filtered_orderboook.rs
+    #[structopt(long, env = "ORDERBOOK_FILTER", default_value = "{}")]
+    pub orderbook_filter: OrderbookFilter,
+
+    /// Specify the number of blocks to fetch events for at a time for
+    /// constructing the orderbook.
+    #[structopt(long, env = "AUCTION_DATA_PAGE_SIZE", default_value = "500")]
+    pub auction_data_page_size: usize,
+
+    /// The number of fetched event pages that are kept in memory while they
+    /// wait to be applied to the orderbook. Fetching pauses once this many pages
+    /// are buffered, which bounds the memory used when syncing long histories.
+    #[structopt(long, env = "ORDERBOOK_EVENT_BUFFER_SIZE", default_value = "4")]
+    pub orderbook_event_buffer_size: usize,
+
+    /// Use an orderbook file for persisting an event cache in order to speed up
+    /// the startup time.
+    #[structopt(long, env = "ORDERBOOK_FILE", parse(from_os_str))]
+    pub orderbook_file: Option<PathBuf>,
+
+    /// Discard the events recovered from the orderbook file starting at this
+    /// block and fetch them again from the node. This is useful for controlled
+    /// reprocessing after fixing a bug in event handling.
+    #[structopt(long, env = "ORDERBOOK_REINDEX_FROM_BLOCK")]
+    pub orderbook_reindex_from_block: Option<u64>,
+
+    /// The URL of the orderbook snapshot endpoint of a trusted driver or price
+    /// estimator. When no orderbook could be recovered from the orderbook file,
+    /// it is bootstrapped from a checkpoint downloaded from there instead of
+    /// from all events since the deployment of the contract. The checkpoint is
+    /// only imported if its orders and balances match those of the exchange
+    /// contract at its block, which requires the node to serve that state.
+    #[structopt(long, env = "ORDERBOOK_CHECKPOINT_URL")]
+    pub orderbook_checkpoint_url: Option<Url>,
+
+    /// The secret shared with the peer serving checkpoints, which
+    /// authenticates the download and signs the checkpoint. Together with an
+    /// orderbook file it also enables serving the file as a checkpoint on the
+    /// `/orderbook/snapshot` endpoint of the monitoring server, for other
+    /// instances and new replicas to import.
+    #[structopt(long, env = "ORDERBOOK_CHECKPOINT_SECRET")]
+    pub orderbook_checkpoint_secret: Option<CheckpointSecret>,
+
+    /// The URL of a NATS server to which the decoded exchange events are
+    /// published, for example `nats://localhost:4222`.
+    #[structopt(long, env = "EVENT_EXPORT_NATS_URL")]
+    pub event_export_nats_url: Option<Url>,
+
+    /// The NATS subject the exchange events are published to.
+    #[structopt(
+        long,
+        env = "EVENT_EXPORT_SUBJECT",
+        default_value = "batch-exchange.events"
+    )]
+    pub event_export_subject: String,
+}
+
+impl OrderbookOptions {
+    /// Creates the sink for exporting exchange events if one is configured.
+    pub fn event_sink(&self) -> Result<Option<Arc<dyn EventSink>>> {
+        let url = match &self.event_export_nats_url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let sink = NatsEventSink::new(url, self.event_export_subject.as_str())?;
+        Ok(Some(Arc::new(sink)))
+    }
+
+    /// Creates the source for importing orderbook checkpoints if one is
+    /// configured.
+    pub fn checkpoint_source(
+        &self,
+        http_factory: &HttpFactory,
+    ) -> Result<Option<CheckpointSource>> {
+        let url = match &self.orderbook_checkpoint_url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let secret = self
+            .orderbook_checkpoint_secret
+            .clone()
+            .ok_or_else(|| anyhow!("importing orderbook checkpoints requires a secret"))?;
+        let source = CheckpointSource::new(http_factory, url.clone(), secret)?;
+        Ok(Some(source))
+    }
+
+    /// Creates the orderbook file that is served as a checkpoint if both the
+    /// file and the secret are configured. It is shared by the orderbook
+    /// writing it and the handler serving it.
+    pub fn checkpoint_file(&self) -> Option<Arc<CheckpointFile>> {
+        match (&self.orderbook_file, &self.orderbook_checkpoint_secret) {
+            (Some(path), Some(_)) => Some(Arc::new(CheckpointFile::new(path.clone()))),
+            _ => None,
+        }
+    }
+
+    /// Creates the handler serving the checkpoint file.
+    pub fn snapshot_handler(
+        &self,
+        checkpoint_file: Option<Arc<CheckpointFile>>,
+    ) -> Option<Arc<dyn Handler>> {
+        match (checkpoint_file, &self.orderbook_checkpoint_secret) {
+            (Some(checkpoint_file), Some(secret)) => Some(Arc::new(SnapshotHandler::new(
+                checkpoint_file,
+                secret.clone(),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Options for computing the economic viability constraints of solutions. The
+/// subsidy factor is not part of these options because its default differs
+/// between the binaries.
+#[derive(Debug, StructOpt)]
+pub struct EconomicViabilityOptions {
+    /// We multiply the economically viable min average fee by this amount to ensure that if a
+    /// solution has this minimum amount it will still be end up economically viable even when the
+    /// gas or native token price moves slightly between solution computation and submission.
+    #[structopt(
+        long,
+        env = "ECONOMIC_VIABILITY_MIN_AVG_FEE_FACTOR",
+        default_value = "1.1"
+    )]
+    pub economic_viability_min_avg_fee_factor: f64,
+
+    /// The weight of a new sample when smoothing the dynamic min average fee
+    /// with an exponential moving average, in the range (0, 1]. Lower values
+    /// make the fee follow gas price spikes more slowly. 1 disables smoothing.
+    #[structopt(
+        long,
+        env = "ECONOMIC_VIABILITY_SMOOTHING_FACTOR",
+        default_value = "1.0"
+    )]
+    pub economic_viability_smoothing_factor: f64,
+
+    /// The relative change of the smoothed dynamic min average fee that is
+    /// needed before it gets updated, for example 0.1 for a band of 10%.
+    /// 0 disables the hysteresis band.
+    #[structopt(long, env = "ECONOMIC_VIABILITY_HYSTERESIS", default_value = "0.0")]
+    pub economic_viability_hysteresis: f64,
+
+    /// The static minimum average fee per order used for the Static strategy.
+    #[structopt(long, env = "STATIC_MIN_AVG_FEE_PER_ORDER")]
+    pub static_min_avg_fee_per_order: Option<u128>,
+
+    /// The static max gas price fee per order used for the Static strategy.
+    #[structopt(long, env = "STATIC_MAX_GAS_PRICE")]
+    pub static_max_gas_price: Option<u128>,
+
+    /// How to calculate the economic viability constraints.
+    /// `Static`: Use fallback_min_avg_fee_per_order and fallback_max_gas_price.
+    /// `Dynamic`: Use current native token price, gas price and subsidy factor.
+    /// `Combined`: Use the better (lower min-avg-fee) of the above.
+    #[structopt(
+        long,
+        env = "ECONOMIC_VIABILITY_STRATEGY",
+        default_value = "Dynamic",
+        possible_values = EconomicViabilityStrategy::variant_names(),
+        case_insensitive = true,
+    )]
+    pub economic_viability_strategy: EconomicViabilityStrategy,
+}
+
+impl EconomicViabilityOptions {
+    /// Create the economic viability computer configured by these options.
+    pub fn create(
+        &self,
+        subsidy_factor: f64,
+        native_token_price: Arc<dyn NativeTokenPricing + Send + Sync>,
+        gas_station: Arc<dyn GasPriceEstimating>,
+    ) -> Result<Arc<dyn EconomicViabilityComputing>> {
+        self.economic_viability_strategy.from_arguments(
+            subsidy_factor,
+            self.economic_viability_min_avg_fee_factor,
+            ThresholdSmoothing {
+                smoothing_factor: self.economic_viability_smoothing_factor,
+                hysteresis: self.economic_viability_hysteresis,
+            },
+            self.static_min_avg_fee_per_order,
+            self.static_max_gas_price,
+            native_token_price,
+            gas_station,
+        )
+    }
+}
+
+/// Parses the options from the command line arguments, the environment and
+/// the configuration file, in this order of precedence. Exits the process with
+/// a usage error if the configuration file is invalid.
+pub fn from_args<T: StructOpt>() -> T {
+    let mut args = env::args_os();
+    let bin = args.next();
+    let args = args.collect::<Vec<_>>();
+    let config_file = config_file_path(args.iter().cloned())
+        .or_else(|| env::var_os(CONFIG_FILE_ENV).map(PathBuf::from));
+    let config_args = match &config_file {
+        Some(path) => load_config_file(path)
+            .map(|config| config_args(config, &args, |name| env::var_os(name).is_some()))
+            .unwrap_or_else(|err| config_file_error(path, err).exit()),
+        None => Vec::new(),
+    };
+
+    let all_args = bin
+        .into_iter()
+        .chain(config_args.iter().cloned())
+        .chain(args);
+    match T::from_iter_safe(all_args) {
+        Ok(options) => options,
+        Err(err) => match (&config_file, unknown_config_key(&err, &config_args)) {
+            (Some(path), Some(key)) => {
+                config_file_error(path, anyhow!("unknown option '{}'", key)).exit()
+            }
+            _ => err.exit(),
+        },
+    }
+}
+
+fn config_file_error(path: &Path, err: anyhow::Error) -> Error {
+    Error::with_description(
+        &format!("invalid config file {}: {:#}", path.display(), err),
+        ErrorKind::InvalidValue,
+    )
+}
+
+/// Finds the path of the configuration file in the command line arguments.
+/// The arguments must be scanned before the options are parsed because the
+/// values from the file are passed to the parser along with them.
+fn config_file_path(args: impl IntoIterator<Item = OsString>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    let prefix = format!("{}=", CONFIG_FILE_ARG);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        } else if arg == CONFIG_FILE_ARG {
+            return args.next().map(PathBuf::from);
+        } else if let Some(path) = arg.strip_prefix(&prefix) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Reads the configuration file into a map from option names to the string
+/// values that would be passed on the command line.
+fn load_config_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content = fs::read_to_string(path).context("failed to read file")?;
+    let config: BTreeMap<String, Value> = toml::from_str(&content)?;
+    config
+        .into_iter()
+        .map(|(key, value)| {
+            let value = arg_value(&value).with_context(|| format!("option '{}'", key))?;
+            Ok((key.replace('_', "-"), value))
+        })
+        .collect()
+}
+
+/// Converts the configuration to command line arguments for the options that
+/// are neither passed on the command line nor set in their environment
+/// variable, which is named after the option.
+fn config_args(
+    config: BTreeMap<String, String>,
+    args: &[OsString],
+    is_env_var_set: impl Fn(&str) -> bool,
+) -> Vec<OsString> {
+    config
+        .into_iter()
+        .filter(|(key, _)| !is_env_var_set(&env_var_name(key)) && !has_arg(args, key))
+        .map(|(key, value)| format!("--{}={}", key, value).into())
+        .collect()
+}
+
+/// Returns whether the long option is passed in the command line arguments.
+fn has_arg(args: &[OsString], key: &str) -> bool {
+    let name = format!("--{}", key);
+    let prefix = format!("{}=", name);
+    args.iter()
+        .map(|arg| arg.to_string_lossy())
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == name || arg.starts_with(&prefix))
+}
+
+/// Returns the key of the configuration file the parser did not recognize as
+/// an option, if that is what it failed on.
+fn unknown_config_key(err: &Error, config_args: &[OsString]) -> Option<String> {
+    if err.kind != ErrorKind::UnknownArgument {
+        return None;
+    }
+    let name = err.info.as_ref()?.first()?;
+    let prefix = format!("{}=", name);
+    if config_args
+        .iter()
+        .any(|arg| arg.to_string_lossy().starts_with(&prefix))
+    {
+        Some(name.trim_start_matches('-').to_string())
+    } else {
+        None
+    }
+}
+
+/// Option names in the configuration file may be either in kebab or snake
+/// case.
+fn env_var_name(key: &str) -> String {
+    key.replace('-', "_").to_uppercase()
+}
+
+/// Converts a configuration value to the format expected by the option's
+/// parser. Arrays become comma separated lists and tables become JSON objects
+/// which is how options like the orderbook filter are specified.
+fn arg_value(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Datetime(value) => value.to_string(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Array(_) | Value::Table(_) => Err(anyhow!("nested lists are not supported")),
+                value => arg_value(value),
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        Value::Table(_) => serde_json::to_string(value)?,
+    })
+}
+
+pub fn duration_millis(s: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_millis(s.parse()?))
+}
+
+pub fn duration_secs(s: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(s.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_config_file_in_args() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            config_file_path(args(&["--node-url", "x", "--config-file", "a.toml"])),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            config_file_path(args(&["--config-file=b.toml"])),
+            Some(PathBuf::from("b.toml"))
+        );
+        assert_eq!(
+            config_file_path(args(&["--", "--config-file", "a.toml"])),
+            None
+        );
+        assert_eq!(config_file_path(args(&["--node-url", "x"])), None);
+    }
+
+    #[test]
+    fn converts_keys_to_env_var_names() {
+        assert_eq!(env_var_name("node-url"), "NODE_URL");
+        assert_eq!(
+            env_var_name("auction_data_page_size"),
+            "AUCTION_DATA_PAGE_SIZE"
+        );
+    }
+
+    #[test]
+    fn converts_values_to_arg_values() {
+        let config: BTreeMap<String, Value> = toml::from_str(
+            r#"
+                log-filter = "info"
+                page-size = 500
+                factor = 1.5
+                flag = true
+                gas-estimators = ["GasNow", "Web3"]
+
+                [orderbook-filter]
+                tokens = { Whitelist = [1, 2] }
+            "#,
+        )
+        .unwrap();
+        let value = |key: &str| arg_value(&config[key]).unwrap();
+
+        assert_eq!(value("log-filter"), "info");
+        assert_eq!(value("page-size"), "500");
+        assert_eq!(value("factor"), "1.5");
+        assert_eq!(value("flag"), "true");
+        assert_eq!(value("gas-estimators"), "GasNow,Web3");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&value("orderbook-filter")).unwrap(),
+            serde_json::json!({ "tokens": { "Whitelist": [1, 2] } })
+        );
+    }
+
+    #[test]
+    fn rejects_nested_lists() {
+        let config: BTreeMap<String, Value> = toml::from_str("list = [[1], [2]]").unwrap();
+        assert!(arg_value(&config["list"]).is_err());
+    }
+
+    #[test]
+    fn command_line_and_environment_take_precedence_over_config() {
+        let config = vec![
+            ("node-url", "http://file"),
+            ("page-size", "500"),
+            ("log-filter", "info"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let args = ["--node-url=http://cli"]
+            .iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            config_args(config, &args, |name| name == "LOG_FILTER"),
+            vec![OsString::from("--page-size=500")]
+        );
+    }
+
+    #[derive(Debug, StructOpt)]
+    struct TestOptions {
+        #[structopt(long, env = "CONFIG_TEST_SOME_OPTION", default_value = "1")]
+        some_option: i32,
+        #[structopt(long, env = "CONFIG_TEST_LIST", use_delimiter = true)]
+        list: Vec<u32>,
+    }
+
+    #[test]
+    fn parses_config_args() {
+        let args = ["test", "--some-option=-2", "--list=1,2"];
+        let options = TestOptions::from_iter_safe(args.iter()).unwrap();
+        assert_eq!(options.some_option, -2);
+        assert_eq!(options.list, vec![1, 2]);
+    }
+
+    #[test]
+    fn finds_unknown_config_key() {
+        let config_args = vec![OsString::from("--unknown-option=1")];
+        let args = ["test"]
+            .iter()
+            .map(OsString::from)
+            .chain(config_args.iter().cloned());
+        let err = TestOptions::from_iter_safe(args).unwrap_err();
+        assert_eq!(
+            unknown_config_key(&err, &config_args),
+            Some("unknown-option".to_string())
+        );
+
+        let err = TestOptions::from_iter_safe(&["test", "--other-option"]).unwrap_err();
+        assert_eq!(unknown_config_key(&err, &config_args), None);
+    }
+}
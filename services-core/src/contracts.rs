@@ -1,24 +1,68 @@
+mod call_cache;
+pub mod exchange_client;
+pub mod private_key;
 pub mod stablex_auction_element;
 pub mod stablex_contract;
+pub mod wrapped_native_token;
 
 use crate::http::HttpFactory;
-use crate::transport::HttpTransport;
+use crate::transport::{NodeTransport, RetryPolicy};
 use anyhow::Result;
 use ethcontract::contract::MethodDefaults;
-use ethcontract::{Account, PrivateKey};
+use ethcontract::{Account, Address, PrivateKey};
 use std::time::Duration;
 
-pub type Web3 = ethcontract::web3::api::Web3<HttpTransport>;
+pub type Web3 = ethcontract::web3::api::Web3<NodeTransport>;
 
-pub fn web3_provider(http_factory: &HttpFactory, url: &str, timeout: Duration) -> Result<Web3> {
-    let http = HttpTransport::new(http_factory, url, timeout)?;
-    let web3 = Web3::new(http);
+pub fn web3_provider(
+    http_factory: &HttpFactory,
+    url: &str,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+) -> Result<Web3> {
+    let transport = NodeTransport::new(http_factory, url, timeout, retry_policy)?;
+    let web3 = Web3::new(transport);
 
     Ok(web3)
 }
 
-fn account(key: PrivateKey, chain_id: u64) -> Account {
-    Account::Offline(key, Some(chain_id))
+/// How transactions sent to the exchange contracts are signed.
+#[derive(Clone)]
+pub enum Signer {
+    /// Transactions are signed locally with the private key.
+    PrivateKey(PrivateKey),
+    /// Transactions are sent unsigned with `eth_sendTransaction` to an external
+    /// signer (e.g. EthSigner backed by Vault) which signs them with the key of
+    /// the account and forwards them to the node. This way the key never has to
+    /// be available to the service. Read-only calls still go to the node.
+    External { web3: Web3, account: Address },
+    /// No transactions are sent, for services that only read from the
+    /// exchange. Transactions sent anyway are rejected by the node.
+    ReadOnly,
+}
+
+impl From<PrivateKey> for Signer {
+    fn from(key: PrivateKey) -> Self {
+        Signer::PrivateKey(key)
+    }
+}
+
+impl Signer {
+    /// The connection over which transactions have to be sent.
+    fn transaction_web3<'a>(&'a self, node: &'a Web3) -> &'a Web3 {
+        match self {
+            Signer::PrivateKey(_) | Signer::ReadOnly => node,
+            Signer::External { web3, .. } => web3,
+        }
+    }
+}
+
+fn account(signer: &Signer, chain_id: u64) -> Account {
+    match signer {
+        Signer::PrivateKey(key) => Account::Offline(key.clone(), Some(chain_id)),
+        Signer::External { account, .. } => Account::Local(*account, None),
+        Signer::ReadOnly => Account::Local(Address::zero(), None),
+    }
 }
 
 fn method_defaults(account: Account) -> MethodDefaults {
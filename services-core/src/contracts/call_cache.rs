@@ -0,0 +1,138 @@
+//! Module implementing a cache for the results of contract calls that return
+//! values that never or only rarely change.
+
+use anyhow::Result;
+use futures::future::Future;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A cache of call results by the arguments of the call. Errors are not
+/// cached so that failed calls are retried on their next use.
+pub struct CallCache<K, V> {
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+    max_age: Option<Duration>,
+}
+
+impl<K, V> CallCache<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    /// Creates a cache for values that expire after the maximum age, or
+    /// never expire if it is `None`.
+    pub fn new(max_age: Option<Duration>) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_age,
+        }
+    }
+
+    /// Returns the cached value for the key, or awaits the call and caches
+    /// its result if there is no value or it expired. The call is only polled
+    /// if the value is not cached.
+    pub async fn get_or_fetch(&self, key: K, call: impl Future<Output = Result<V>>) -> Result<V> {
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(fetched, _)| {
+                self.max_age
+                    .map_or(true, |max_age| fetched.elapsed() < max_age)
+            })
+            .map(|(_, value)| value.clone());
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+
+        // NOTE: The call is made without holding the lock, so concurrent
+        // lookups of a missing value can both query the node, which is fine
+        // since they return the same value.
+        let value = call.await?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), value.clone()));
+        Ok(value)
+    }
+
+    pub fn invalidate(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use futures::future::{self, FutureExt as _};
+
+    #[test]
+    fn caches_values_by_key() {
+        let cache = CallCache::new(None);
+        let value = |key| {
+            cache
+                .get_or_fetch(key, future::ready(Ok(key * 2)))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(value(1), 2);
+        assert_eq!(value(2), 4);
+
+        let cached = cache
+            .get_or_fetch(1, future::ready(Ok(42)))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached, 2);
+    }
+
+    #[test]
+    fn does_not_cache_errors() {
+        let cache = CallCache::new(None);
+        assert!(cache
+            .get_or_fetch((), future::ready(Err(anyhow!("node unavailable"))))
+            .now_or_never()
+            .unwrap()
+            .is_err());
+
+        let value = cache
+            .get_or_fetch((), future::ready(Ok(1)))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn fetches_expired_and_invalidated_values_again() {
+        let cache = CallCache::new(Some(Duration::from_secs(0)));
+        let fetch = |value| {
+            cache
+                .get_or_fetch((), future::ready(Ok(value)))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(fetch(1), 1);
+        assert_eq!(fetch(2), 2);
+
+        let cache = CallCache::new(None);
+        let fetch = |value| {
+            cache
+                .get_or_fetch((), future::ready(Ok(value)))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(fetch(1), 1);
+        assert_eq!(fetch(2), 1);
+        cache.invalidate();
+        assert_eq!(fetch(3), 3);
+    }
+}
@@ -0,0 +1,375 @@
+//! Typed access to the user facing functions of the exchange contract, such as
+//! placing and cancelling orders and managing deposits and withdrawals, for
+//! the account of a signer.
+
+use crate::contracts::{self, call_cache::CallCache, Signer};
+use ::contracts::BatchExchange;
+use anyhow::{Error, Result};
+use ethcontract::{
+    errors::ExecutionError,
+    transaction::{
+        confirm::ConfirmParams, Account, GasPrice, ResolveCondition, TransactionBuilder,
+    },
+    Address, H256, U256,
+};
+
+/// An order to be placed on the exchange.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NewOrder {
+    pub buy_token: u16,
+    pub sell_token: u16,
+    /// The first batch in which the order can be traded.
+    pub valid_from: u32,
+    /// The last batch in which the order can be traded.
+    pub valid_until: u32,
+    pub buy_amount: u128,
+    pub sell_amount: u128,
+}
+
+/// A withdrawal that was requested but not yet claimed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PendingWithdraw {
+    pub amount: U256,
+    /// The batch from which on the withdrawal can be claimed.
+    pub batch_id: u32,
+}
+
+impl PendingWithdraw {
+    /// Whether the withdrawal can be claimed in the current batch.
+    pub fn has_matured(&self, current_batch_id: u32) -> bool {
+        !self.amount.is_zero() && self.batch_id < current_batch_id
+    }
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait ExchangeClient: Send + Sync {
+    /// The address of the account that sends the transactions.
+    fn account(&self) -> Address;
+
+    /// Retrieve the number of transactions sent from the account, which is the
+    /// nonce of its next transaction.
+    async fn transaction_count(&self) -> Result<U256>;
+
+    /// Retrieve the current batch ID that is accepting orders.
+    async fn current_batch_id(&self) -> Result<u32>;
+
+    /// Retrieve the exchange token ID of a token address.
+    async fn token_id(&self, token: Address) -> Result<u16>;
+
+    /// Retrieve the token address of an exchange token ID.
+    async fn token_address(&self, token_id: u16) -> Result<Address>;
+
+    /// Retrieve the balance of the account for a token that is available for
+    /// trading.
+    async fn balance(&self, token: Address) -> Result<U256>;
+
+    /// Retrieve the withdrawal of a token that was requested by the account.
+    async fn pending_withdraw(&self, token: Address) -> Result<PendingWithdraw>;
+
+    /// Retrieve the withdrawal of a token that was requested by any user.
+    async fn pending_withdraw_of(&self, user: Address, token: Address) -> Result<PendingWithdraw>;
+
+    /// Deposits an amount of a token into the exchange. The exchange needs to
+    /// be approved to transfer the amount beforehand.
+    async fn deposit(&self, token: Address, amount: U256) -> Result<H256>;
+
+    /// Places orders in a single transaction.
+    async fn place_orders(&self, orders: Vec<NewOrder>) -> Result<H256>;
+
+    /// Cancels orders so that they can no longer be traded from the current
+    /// batch on. Orders that have already expired are deleted from the
+    /// contract storage instead, which refunds gas.
+    async fn cancel_orders(&self, order_ids: Vec<u16>) -> Result<H256>;
+
+    /// Requests the withdrawal of an amount of a token. The withdrawal can be
+    /// claimed with `withdraw` once the current batch has been finalized.
+    async fn request_withdraw(&self, token: Address, amount: U256) -> Result<H256>;
+
+    /// Requests the withdrawal of an amount of a token that can be claimed
+    /// once the specified batch has been finalized. The amount stays available
+    /// for trading until then.
+    async fn request_future_withdraw(
+        &self,
+        token: Address,
+        amount: U256,
+        batch_id: u32,
+    ) -> Result<H256>;
+
+    /// Claims a matured withdrawal of a token.
+    async fn withdraw(&self, token: Address) -> Result<H256>;
+
+    /// Claims a matured withdrawal of a token for any user with the specified
+    /// gas price and nonce, and waits for the transaction to be mined. The
+    /// exchange transfers the funds to the user, so the claim does not need to
+    /// be sent by the user.
+    async fn withdraw_for(
+        &self,
+        user: Address,
+        token: Address,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<H256, ExecutionError>;
+
+    /// Sends a transaction without effect to the account itself with the
+    /// specified gas price and nonce, which replaces a pending transaction with
+    /// the same nonce, and waits for it to be mined.
+    async fn send_noop_transaction(
+        &self,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<(), ExecutionError>;
+}
+
+pub struct ExchangeClientImpl {
+    instance: BatchExchange,
+    /// The exchange contract bound to the connection transactions are sent
+    /// over, which differs from `instance` when using an external signer.
+    transaction_instance: BatchExchange,
+    account: Account,
+    /// Token addresses never change once a token is listed, but are looked up
+    /// whenever fee funds are managed.
+    token_addresses: CallCache<u16, Address>,
+}
+
+impl ExchangeClientImpl {
+    pub async fn new(web3: &contracts::Web3, signer: Signer) -> Result<Self> {
+        let chain_id = web3.eth().chain_id().await?.as_u64();
+        let account = contracts::account(&signer, chain_id);
+        let defaults = contracts::method_defaults(account.clone());
+
+        let mut instance = BatchExchange::deployed(&web3).await?;
+        *instance.defaults_mut() = defaults.clone();
+        let mut transaction_instance =
+            BatchExchange::at(signer.transaction_web3(web3), instance.address());
+        *transaction_instance.defaults_mut() = defaults;
+
+        Ok(ExchangeClientImpl {
+            instance,
+            transaction_instance,
+            account,
+            token_addresses: CallCache::new(None),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ExchangeClient for ExchangeClientImpl {
+    fn account(&self) -> Address {
+        self.account.address()
+    }
+
+    async fn transaction_count(&self) -> Result<U256> {
+        self.instance
+            .raw_instance()
+            .web3()
+            .eth()
+            .transaction_count(self.account.address(), None)
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn current_batch_id(&self) -> Result<u32> {
+        self.instance
+            .get_current_batch_id()
+            .call()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn token_id(&self, token: Address) -> Result<u16> {
+        self.instance
+            .token_address_to_id_map(token)
+            .call()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn token_address(&self, token_id: u16) -> Result<Address> {
+        let call = self.instance.token_id_to_address_map(token_id).call();
+        self.token_addresses
+            .get_or_fetch(token_id, async { call.await.map_err(Error::from) })
+            .await
+    }
+
+    async fn balance(&self, token: Address) -> Result<U256> {
+        self.instance
+            .get_balance(self.account.address(), token)
+            .call()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn pending_withdraw(&self, token: Address) -> Result<PendingWithdraw> {
+        self.pending_withdraw_of(self.account.address(), token)
+            .await
+    }
+
+    async fn pending_withdraw_of(&self, user: Address, token: Address) -> Result<PendingWithdraw> {
+        let (amount, batch_id) = self
+            .instance
+            .get_pending_withdraw(user, token)
+            .call()
+            .await?;
+        Ok(PendingWithdraw { amount, batch_id })
+    }
+
+    async fn deposit(&self, token: Address, amount: U256) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .deposit(token, amount)
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn place_orders(&self, orders: Vec<NewOrder>) -> Result<H256> {
+        let (buy_tokens, sell_tokens, valid_froms, valid_untils, buy_amounts, sell_amounts) =
+            encode_orders_for_contract(&orders);
+        Ok(self
+            .transaction_instance
+            .place_valid_from_orders(
+                buy_tokens,
+                sell_tokens,
+                valid_froms,
+                valid_untils,
+                buy_amounts,
+                sell_amounts,
+            )
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn cancel_orders(&self, order_ids: Vec<u16>) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .cancel_orders(order_ids)
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn request_withdraw(&self, token: Address, amount: U256) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .request_withdraw(token, amount)
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn request_future_withdraw(
+        &self,
+        token: Address,
+        amount: U256,
+        batch_id: u32,
+    ) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .request_future_withdraw(token, amount, batch_id)
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn withdraw(&self, token: Address) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .withdraw(self.account.address(), token)
+            .send()
+            .await?
+            .hash())
+    }
+
+    async fn withdraw_for(
+        &self,
+        user: Address,
+        token: Address,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<H256, ExecutionError> {
+        Ok(self
+            .transaction_instance
+            .withdraw(user, token)
+            .gas_price(GasPrice::Value(gas_price))
+            .nonce(nonce)
+            .send()
+            .await
+            .map_err(|err| err.inner)?
+            .hash())
+    }
+
+    async fn send_noop_transaction(
+        &self,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<(), ExecutionError> {
+        let web3 = self.transaction_instance.raw_instance().web3();
+        TransactionBuilder::new(web3)
+            .from(self.account.clone())
+            .to(self.account.address())
+            .gas(21_000.into())
+            .gas_price(GasPrice::Value(gas_price))
+            .nonce(nonce)
+            .value(U256::zero())
+            .resolve(ResolveCondition::Confirmed(ConfirmParams::mined()))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+type EncodedOrders = (Vec<u16>, Vec<u16>, Vec<u32>, Vec<u32>, Vec<u128>, Vec<u128>);
+
+/// Encodes orders into the parallel arrays expected by the contract.
+fn encode_orders_for_contract(orders: &[NewOrder]) -> EncodedOrders {
+    let mut encoded = EncodedOrders::default();
+    for order in orders {
+        encoded.0.push(order.buy_token);
+        encoded.1.push(order.sell_token);
+        encoded.2.push(order.valid_from);
+        encoded.3.push(order.valid_until);
+        encoded.4.push(order.buy_amount);
+        encoded.5.push(order.sell_amount);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_orders_as_parallel_arrays() {
+        let orders = [
+            NewOrder {
+                buy_token: 1,
+                sell_token: 2,
+                valid_from: 3,
+                valid_until: 4,
+                buy_amount: 5,
+                sell_amount: 6,
+            },
+            NewOrder {
+                buy_token: 7,
+                sell_token: 8,
+                valid_from: 9,
+                valid_until: 10,
+                buy_amount: 11,
+                sell_amount: 12,
+            },
+        ];
+        assert_eq!(
+            encode_orders_for_contract(&orders),
+            (
+                vec![1, 7],
+                vec![2, 8],
+                vec![3, 9],
+                vec![4, 10],
+                vec![5, 11],
+                vec![6, 12]
+            )
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, bail, Context as _, Result};
+use ethcontract::PrivateKey;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+/// Options for loading the private key used to sign transactions. Exactly one
+/// of the key sources has to be specified.
+#[derive(Debug, StructOpt)]
+pub struct PrivateKeyOptions {
+    /// The private key used by the driver to sign transactions. Prefer one of
+    /// the file based options since environment variables and arguments can
+    /// leak into process listings and deployment manifests.
+    #[structopt(short = "k", long, env = "PRIVATE_KEY", hide_env_values = true)]
+    pub private_key: Option<PrivateKey>,
+
+    /// Path to a file containing the hex encoded private key used to sign
+    /// transactions.
+    #[structopt(long, env = "PRIVATE_KEY_FILE", parse(from_os_str))]
+    pub private_key_file: Option<PathBuf>,
+
+    /// Path to an encrypted JSON keystore containing the private key used to
+    /// sign transactions. Requires a keystore password file.
+    #[structopt(long, env = "KEYSTORE_FILE", parse(from_os_str))]
+    pub keystore_file: Option<PathBuf>,
+
+    /// Path to a file containing the password of the JSON keystore.
+    #[structopt(long, env = "KEYSTORE_PASSWORD_FILE", parse(from_os_str))]
+    pub keystore_password_file: Option<PathBuf>,
+
+    /// Paths to files containing hex encoded private keys of additional
+    /// accounts used to submit solutions. Submissions rotate between all
+    /// accounts, skipping accounts whose previous transactions are pending.
+    #[structopt(
+        long,
+        env = "ADDITIONAL_PRIVATE_KEY_FILES",
+        parse(from_os_str),
+        use_delimiter = true
+    )]
+    pub additional_private_key_files: Vec<PathBuf>,
+}
+
+impl PrivateKeyOptions {
+    /// Loads the private key from the configured source.
+    pub fn load(&self) -> Result<PrivateKey> {
+        match (
+            &self.private_key,
+            &self.private_key_file,
+            &self.keystore_file,
+        ) {
+            (Some(key), None, None) => Ok(key.clone()),
+            (None, Some(path), None) => read_private_key(path),
+            (None, None, Some(path)) => {
+                let password_path = self
+                    .keystore_password_file
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("keystore file requires a keystore password file"))?;
+                let password = fs::read_to_string(password_path)
+                    .with_context(|| format!("failed to read {}", password_path.display()))?;
+                let key = eth_keystore::decrypt_key(path, trim_line_ending(&password))
+                    .map_err(|err| anyhow!("failed to decrypt keystore: {}", err))?;
+                PrivateKey::from_slice(&key).map_err(|err| anyhow!("invalid private key: {}", err))
+            }
+            (None, None, None) => bail!(
+                "no private key specified, use one of private key, private key file or keystore file"
+            ),
+            _ => bail!("only one of private key, private key file or keystore file can be used"),
+        }
+    }
+
+    /// Loads the private keys of the additional submission accounts.
+    pub fn load_additional(&self) -> Result<Vec<PrivateKey>> {
+        self.additional_private_key_files
+            .iter()
+            .map(|path| read_private_key(path))
+            .collect()
+    }
+}
+
+/// Reads a hex encoded private key from a file.
+pub fn read_private_key(path: &Path) -> Result<PrivateKey> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_private_key(&content)
+}
+
+/// Parses a hex encoded private key ignoring surrounding whitespace, which
+/// usually ends up in files written by hand or by `echo`.
+fn parse_private_key(content: &str) -> Result<PrivateKey> {
+    content
+        .trim()
+        .parse()
+        .map_err(|err| anyhow!("invalid private key: {}", err))
+}
+
+/// Passwords may contain whitespace so only the final line ending is removed.
+fn trim_line_ending(password: &str) -> &str {
+    password
+        .strip_suffix('\n')
+        .map(|password| password.strip_suffix('\r').unwrap_or(password))
+        .unwrap_or(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    #[test]
+    fn parses_private_key_with_whitespace() {
+        let key = parse_private_key(&format!("  {}\n", KEY)).unwrap();
+        assert_eq!(
+            key.public_address(),
+            KEY.parse::<PrivateKey>().unwrap().public_address()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_private_key() {
+        assert!(parse_private_key("1234").is_err());
+    }
+
+    #[test]
+    fn trims_only_final_line_ending() {
+        assert_eq!(trim_line_ending(" password \n"), " password ");
+        assert_eq!(trim_line_ending("password\r\n"), "password");
+        assert_eq!(trim_line_ending("password\n\n"), "password\n");
+        assert_eq!(trim_line_ending("password"), "password");
+    }
+
+    #[test]
+    fn requires_exactly_one_key_source() {
+        let options = PrivateKeyOptions {
+            private_key: None,
+            private_key_file: None,
+            keystore_file: None,
+            keystore_password_file: None,
+            additional_private_key_files: Vec::new(),
+        };
+        assert!(options.load().is_err());
+
+        let options = PrivateKeyOptions {
+            private_key: Some(KEY.parse().unwrap()),
+            private_key_file: Some(PathBuf::from("key")),
+            ..options
+        };
+        assert!(options.load().is_err());
+
+        let options = PrivateKeyOptions {
+            private_key_file: None,
+            ..options
+        };
+        assert!(options.load().is_ok());
+    }
+}
@@ -1,28 +1,41 @@
 // NOTE: Required for automock.
 #![cfg_attr(test, allow(clippy::ptr_arg))]
 
+mod cached;
+mod event_decoding;
 mod search_batches;
 
+pub use self::{
+    cached::CachedStableXContract,
+    event_decoding::{EventAbiVersion, ExchangeEvent},
+};
+
 use crate::{
-    contracts,
-    models::{ExecutedOrder, Solution},
+    contracts::{self, Signer},
+    models::{batch_id::BatchTiming, Solution, SubmitSolutionCall},
+    util::AsyncSleeping,
 };
-use ::contracts::{batch_exchange, BatchExchange, BatchExchangeViewer, SolutionSubmitter};
-use anyhow::{Error, Result};
+use ::contracts::{BatchExchange, BatchExchangeViewer, SolutionSubmitter};
+use anyhow::{ensure, Error, Result};
 use ethcontract::{
-    contract::Event,
+    contract::MethodBuilder,
     errors::{ExecutionError, MethodError},
     transaction::{confirm::ConfirmParams, Account, GasPrice, ResolveCondition, TransactionResult},
-    Address, BlockId, BlockNumber, PrivateKey, U256,
+    transport::DynTransport,
+    web3::types::FilterBuilder,
+    Address, BlockId, BlockNumber, U256,
 };
-use futures::stream::{BoxStream, StreamExt};
+use futures::stream::{self, BoxStream, StreamExt};
 
 use lazy_static::lazy_static;
-use std::collections::HashMap;
 use std::time::Duration;
 
 pub const SOLUTION_SUBMISSION_GAS_LIMIT: u32 = 6_000_000;
 
+/// The interval at which the current batch is polled once the end of a batch
+/// has been reached.
+const BATCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 lazy_static! {
     // In the BatchExchange smart contract, the objective value will be multiplied by
     // 1 + IMPROVEMENT_DENOMINATOR = 101. Hence, the maximal possible objective value is:
@@ -32,41 +45,67 @@ lazy_static! {
 #[derive(Clone)]
 pub struct StableXContractImpl {
     instance: BatchExchange,
+    /// The exchange contract bound to the connection transactions are sent
+    /// over, which differs from `instance` when using an external signer.
+    transaction_instance: BatchExchange,
     viewer: BatchExchangeViewer,
     solution_submitter: Option<SolutionSubmitter>,
     account: Account,
+    event_abi_version: EventAbiVersion,
+    batch_duration: Duration,
 }
 
 impl StableXContractImpl {
     pub async fn new(
         web3: &contracts::Web3,
-        key: PrivateKey,
+        signer: Signer,
         use_solution_submitter: bool,
     ) -> Result<Self> {
         let chain_id = web3.eth().chain_id().await?.as_u64();
-        let account = contracts::account(key, chain_id);
+        let account = contracts::account(&signer, chain_id);
         let defaults = contracts::method_defaults(account.clone());
+        let transaction_web3 = signer.transaction_web3(web3);
 
         let viewer = BatchExchangeViewer::deployed(&web3).await?;
         let mut instance = BatchExchange::deployed(&web3).await?;
         *instance.defaults_mut() = defaults.clone();
+        let mut transaction_instance = BatchExchange::at(transaction_web3, instance.address());
+        *transaction_instance.defaults_mut() = defaults.clone();
 
         let solution_submitter = if use_solution_submitter {
-            let mut instance = SolutionSubmitter::deployed(&web3).await?;
+            let deployed = SolutionSubmitter::deployed(&web3).await?;
+            let mut instance = SolutionSubmitter::at(transaction_web3, deployed.address());
             *instance.defaults_mut() = defaults;
             Some(instance)
         } else {
             None
         };
 
+        let event_abi_version = EventAbiVersion::for_contract(chain_id, instance.address());
+        let batch_duration = Duration::from_secs(instance.batch_time().call().await?.into());
+        ensure!(
+            batch_duration.as_secs() > 0,
+            "exchange contract has a batch time of zero"
+        );
+        web3.transport()
+            .set_batch_timing(BatchTiming::with_batch_duration(batch_duration));
         Ok(StableXContractImpl {
             instance,
+            transaction_instance,
             viewer,
             solution_submitter,
             account,
+            event_abi_version,
+            batch_duration,
         })
     }
 
+    /// Creates an exchange contract that is only used to read from the
+    /// exchange and never sends transactions.
+    pub async fn read_only(web3: &contracts::Web3) -> Result<Self> {
+        Self::new(web3, Signer::ReadOnly, false).await
+    }
+
     pub fn account(&self) -> Account {
         self.account.clone()
     }
@@ -86,6 +125,65 @@ impl StableXContractImpl {
             .await
             .map_err(Error::from)
     }
+
+    /// The latest solution stored by the exchange at the specified block,
+    /// which is the solution that is currently winning the batch it was
+    /// submitted for.
+    pub async fn latest_solution(
+        &self,
+        block_number: Option<BlockNumber>,
+    ) -> Result<LatestSolution> {
+        let mut builder = self.instance.latest_solution();
+        builder.block = block_number.map(BlockId::Number);
+        let (batch_id, submitter, fee_reward, objective_value) = builder.call().await?;
+        Ok(LatestSolution {
+            batch_id,
+            submitter,
+            fee_reward,
+            objective_value,
+        })
+    }
+
+    /// The method submitting the solution, sent from the solution submitter
+    /// contract if it is used.
+    fn submit_solution_method(
+        &self,
+        batch_index: u32,
+        solution: &Solution,
+        claimed_objective_value: U256,
+    ) -> MethodBuilder<DynTransport, U256> {
+        let call = SubmitSolutionCall::new(batch_index, claimed_objective_value, solution);
+        match &self.solution_submitter {
+            Some(submitter) => submitter.submit_solution(
+                call.batch_id,
+                call.claimed_objective_value,
+                call.owners,
+                call.order_ids,
+                call.buy_volumes,
+                call.prices,
+                call.token_ids_for_price,
+            ),
+            None => self.transaction_instance.submit_solution(
+                call.batch_id,
+                call.claimed_objective_value,
+                call.owners,
+                call.order_ids,
+                call.buy_volumes,
+                call.prices,
+                call.token_ids_for_price,
+            ),
+        }
+    }
+}
+
+/// The solution stored by the exchange as `latestSolution`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LatestSolution {
+    pub batch_id: u32,
+    pub submitter: Address,
+    /// The fees burnt by the solution, which are rewarded to the solver.
+    pub fee_reward: U256,
+    pub objective_value: U256,
 }
 
 /// Information about an order page that where filtered
@@ -107,6 +205,10 @@ pub trait StableXContract: Send + Sync {
     /// Retrieve the time remaining in the batch.
     async fn get_current_auction_remaining_time(&self) -> Result<Duration>;
 
+    /// The duration of a batch as set by the `BATCH_TIME` constant of the
+    /// deployed contract, which is read once when connecting to it.
+    fn batch_duration(&self) -> Duration;
+
     /// Searches for the block number of the last block of the given batch. If
     /// the batch has not yet been finalized, then the block number for the
     /// `"latest"` block is returned.
@@ -136,6 +238,15 @@ pub trait StableXContract: Send + Sync {
         block_number: Option<BlockNumber>,
     ) -> Result<Vec<u8>>;
 
+    /// Retrieve the balance of a user for a token as used for solving the
+    /// current batch at the given block.
+    async fn get_balance(
+        &self,
+        user: Address,
+        token: Address,
+        block_number: Option<BlockNumber>,
+    ) -> Result<U256>;
+
     async fn get_solution_objective_value(
         &self,
         batch_index: u32,
@@ -143,21 +254,36 @@ pub trait StableXContract: Send + Sync {
         block_number: Option<BlockNumber>,
     ) -> Result<U256>;
 
+    /// Simulates submitting the solution with the claimed objective value and
+    /// returns the gas used by the submission.
+    async fn estimate_solution_gas(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256>;
+
+    /// Submits the solution with the specified gas limit and waits until the
+    /// transaction is mined.
     async fn submit_solution(
         &self,
         batch_index: u32,
         solution: Solution,
         claimed_objective_value: U256,
+        gas_limit: U256,
         gas_price: U256,
         nonce: U256,
-    ) -> Result<(), MethodError>;
+    ) -> Result<TransactionResult, MethodError>;
 
+    /// Retrieve the exchange events between the specified blocks, inclusive,
+    /// fetching logs for `block_page_size` blocks at a time. Events are decoded
+    /// with the event ABI version of the deployed contract.
     async fn past_events<'a>(
         &'a self,
-        from_block: BlockNumber,
-        to_block: BlockNumber,
+        from_block: u64,
+        to_block: u64,
         block_page_size: u64,
-    ) -> Result<BoxStream<'a, Result<Event<batch_exchange::Event>, ExecutionError>>, ExecutionError>;
+    ) -> Result<BoxStream<'a, Result<ExchangeEvent>>>;
 
     /// Create a noop transaction. Useful to cancel a previous transaction that is stuck due to
     /// low gas price.
@@ -169,6 +295,10 @@ pub trait StableXContract: Send + Sync {
 
     /// The current nonce aka transaction_count.
     async fn get_transaction_count(&self) -> Result<U256>;
+
+    /// The nonce including transactions that are still pending. If this is
+    /// larger than the current nonce then there are unconfirmed transactions.
+    async fn get_pending_transaction_count(&self) -> Result<U256>;
 }
 
 #[async_trait::async_trait]
@@ -190,9 +320,16 @@ impl StableXContract for StableXContractImpl {
         Ok(Duration::from_secs(seconds.as_u64()))
     }
 
+    fn batch_duration(&self) -> Duration {
+        self.batch_duration
+    }
+
     async fn get_last_block_for_batch(&self, batch_id: u32) -> Result<u64> {
-        let web3 = self.instance.raw_instance().web3();
-        search_batches::search_last_block_for_batch(&web3, batch_id).await
+        let batch_id_retriever = search_batches::NodeBatchIdRetriever {
+            web3: self.instance.raw_instance().web3(),
+            batch_timing: BatchTiming::with_batch_duration(self.batch_duration),
+        };
+        search_batches::search_last_block_for_batch(&batch_id_retriever, batch_id).await
     }
 
     async fn get_filtered_auction_data_paginated(
@@ -243,85 +380,105 @@ impl StableXContract for StableXContractImpl {
         orders_builder.call().await.map_err(Error::from)
     }
 
+    async fn get_balance(
+        &self,
+        user: Address,
+        token: Address,
+        block_number: Option<BlockNumber>,
+    ) -> Result<U256> {
+        let mut builder = self.instance.get_balance(user, token);
+        builder.block = block_number.map(BlockId::Number);
+        builder.call().await.map_err(Error::from)
+    }
+
     async fn get_solution_objective_value(
         &self,
         batch_index: u32,
         solution: Solution,
         block_number: Option<BlockNumber>,
     ) -> Result<U256> {
-        let (prices, token_ids_for_price) = encode_prices_for_contract(&solution.prices);
-        let (owners, order_ids, volumes) = encode_execution_for_contract(&solution.executed_orders);
+        let call = SubmitSolutionCall::new(batch_index, *MAX_OBJECTIVE_VALUE, &solution);
         let mut builder = self
             .instance
             .submit_solution(
-                batch_index,
-                *MAX_OBJECTIVE_VALUE,
-                owners,
-                order_ids,
-                volumes,
-                prices,
-                token_ids_for_price,
+                call.batch_id,
+                call.claimed_objective_value,
+                call.owners,
+                call.order_ids,
+                call.buy_volumes,
+                call.prices,
+                call.token_ids_for_price,
             )
             .view();
         builder.block = block_number.map(BlockId::Number);
         builder.call().await.map_err(Error::from)
     }
 
+    async fn estimate_solution_gas(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256> {
+        self.submit_solution_method(batch_index, &solution, claimed_objective_value)
+            .estimate_gas()
+            .await
+            .map_err(Error::from)
+    }
+
     async fn submit_solution(
         &self,
         batch_index: u32,
         solution: Solution,
         claimed_objective_value: U256,
+        gas_limit: U256,
         gas_price: U256,
         nonce: U256,
-    ) -> Result<(), MethodError> {
-        let (prices, token_ids_for_price) = encode_prices_for_contract(&solution.prices);
-        let (owners, order_ids, volumes) = encode_execution_for_contract(&solution.executed_orders);
-        let mut method = match &self.solution_submitter {
-            Some(submitter) => submitter.submit_solution(
-                batch_index,
-                claimed_objective_value,
-                owners,
-                order_ids,
-                volumes,
-                prices,
-                token_ids_for_price,
-            ),
-            None => self.instance.submit_solution(
-                batch_index,
-                claimed_objective_value,
-                owners,
-                order_ids,
-                volumes,
-                prices,
-                token_ids_for_price,
-            ),
-        }
-        .gas_price(GasPrice::Value(gas_price))
-        // NOTE: Gas estimate might be off, as we race with other solution
-        //   submissions and thus might have to revert trades which costs
-        //   more gas than expected.
-        .gas(SOLUTION_SUBMISSION_GAS_LIMIT.into())
-        .nonce(nonce);
+    ) -> Result<TransactionResult, MethodError> {
+        let mut method = self
+            .submit_solution_method(batch_index, &solution, claimed_objective_value)
+            .gas_price(GasPrice::Value(gas_price))
+            .gas(gas_limit)
+            .nonce(nonce);
         method.tx.resolve = Some(ResolveCondition::Confirmed(ConfirmParams::mined()));
-        method.send().await.map(|_| ())
+        method.send().await
     }
 
     async fn past_events<'a>(
         &'a self,
-        from_block: BlockNumber,
-        to_block: BlockNumber,
+        from_block: u64,
+        to_block: u64,
         block_page_size: u64,
-    ) -> Result<BoxStream<'a, Result<Event<batch_exchange::Event>, ExecutionError>>, ExecutionError>
-    {
-        let stream = self
-            .instance
-            .all_events()
-            .from_block(from_block)
-            .to_block(to_block)
-            .block_page_size(block_page_size)
-            .query_paginated()
-            .await?;
+    ) -> Result<BoxStream<'a, Result<ExchangeEvent>>> {
+        ensure!(block_page_size > 0, "block page size must be positive");
+        let web3 = self.instance.raw_instance().web3();
+        let address = self.instance.address();
+        let event_abi_version = self.event_abi_version;
+        let pages = (from_block..=to_block)
+            .step_by(block_page_size as usize)
+            .map(move |page_start| {
+                let page_end = page_start.saturating_add(block_page_size - 1);
+                (page_start, page_end.min(to_block))
+            });
+        let stream = stream::iter(pages)
+            .then(move |(page_start, page_end)| {
+                let filter = FilterBuilder::default()
+                    .address(vec![address])
+                    .from_block(BlockNumber::Number(page_start.into()))
+                    .to_block(BlockNumber::Number(page_end.into()))
+                    .build();
+                web3.eth().logs(filter)
+            })
+            .flat_map(move |logs| {
+                let events: Vec<Result<ExchangeEvent>> = match logs {
+                    Ok(logs) => logs
+                        .into_iter()
+                        .filter_map(|log| event_abi_version.decode(log).transpose())
+                        .collect(),
+                    Err(err) => vec![Err(err.into())],
+                };
+                stream::iter(events)
+            });
         Ok(stream.boxed())
     }
 
@@ -330,7 +487,7 @@ impl StableXContract for StableXContractImpl {
         gas_price: U256,
         nonce: U256,
     ) -> Result<TransactionResult, ExecutionError> {
-        let web3 = self.instance.raw_instance().web3();
+        let web3 = self.transaction_instance.raw_instance().web3();
         let account = self.account.clone();
         let address = account.address();
         let transaction = ethcontract::transaction::TransactionBuilder::new(web3)
@@ -352,100 +509,98 @@ impl StableXContract for StableXContractImpl {
             .await
             .map_err(From::from)
     }
-}
 
-fn encode_prices_for_contract(price_map: &HashMap<u16, u128>) -> (Vec<u128>, Vec<u16>) {
-    // Representing the solution's price vector as:
-    // sorted_touched_token_ids, non_zero_prices (excluding price at token with id 0)
-    let mut token_ids: Vec<u16> = price_map
-        .keys()
-        .copied()
-        .filter(|t| *t > 0 && price_map[t] > 0)
-        .collect();
-    token_ids.sort_unstable();
-    let prices = token_ids
-        .iter()
-        .map(|token_id| price_map[token_id])
-        .collect();
-    (prices, token_ids)
+    async fn get_pending_transaction_count(&self) -> Result<U256> {
+        let web3 = self.instance.raw_instance().web3();
+        let address = self.account.address();
+        web3.eth()
+            .transaction_count(address, Some(BlockNumber::Pending))
+            .await
+            .map_err(From::from)
+    }
 }
 
-fn encode_execution_for_contract(
-    executed_orders: &[ExecutedOrder],
-) -> (Vec<Address>, Vec<u16>, Vec<u128>) {
-    let mut owners = vec![];
-    let mut order_ids = vec![];
-    let mut volumes = vec![];
-    for order in executed_orders {
-        if order.buy_amount > 0 {
-            // order was touched!
-            // Note that above condition is only holds for sell orders.
-            owners.push(order.account_id);
-            order_ids.push(order.order_id);
-            volumes.push(order.buy_amount);
+/// Waits until the exchange accepts orders for at least the specified batch
+/// and returns the ID of the batch currently accepting orders.
+///
+/// Instead of polling at a fixed interval this sleeps until the end of the
+/// current batch as reported by the contract. Batches only change with the
+/// first block mined after the end of a batch, so once the end is reached the
+/// batch is polled every `BATCH_POLL_INTERVAL`.
+pub async fn wait_for_batch(
+    contract: &dyn StableXContract,
+    sleep: &dyn AsyncSleeping,
+    batch_id: u32,
+) -> Result<u32> {
+    loop {
+        let current_batch = contract.get_current_auction_index().await?;
+        if current_batch >= batch_id {
+            return Ok(current_batch);
         }
+        let remaining_time = contract.get_current_auction_remaining_time().await?;
+        let remaining_batches = batch_id - current_batch - 1;
+        let wait_time = remaining_time + contract.batch_duration() * remaining_batches;
+        sleep.sleep(wait_time.max(BATCH_POLL_INTERVAL)).await;
     }
-    (owners, order_ids, volumes)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::util::test_util::map_from_slice;
+    use crate::util::MockAsyncSleeping;
+    use futures::FutureExt as _;
+    use mockall::{predicate::eq, Sequence};
 
     #[test]
-    fn generic_encode_execution_test() {
-        let address_1 = Address::from_low_u64_be(1);
-        let address_2 = Address::from_low_u64_be(2);
-
-        let order_1 = ExecutedOrder {
-            order_id: 0,
-            account_id: address_1,
-            sell_amount: 1,
-            buy_amount: 1,
-        };
-        let order_2 = ExecutedOrder {
-            order_id: 1,
-            account_id: address_2,
-            sell_amount: 0,
-            buy_amount: 0,
-        };
-
-        let expected_owners = vec![address_1];
-        let expected_order_ids = vec![0];
-        let expected_volumes = vec![1];
-
-        let expected_results = (expected_owners, expected_order_ids, expected_volumes);
-
-        assert_eq!(
-            encode_execution_for_contract(&[order_1, order_2]),
-            expected_results
-        );
-    }
-
-    #[test]
-    fn generic_price_encoding() {
-        let price_map = map_from_slice(&[(0, u128::max_value()), (1, 0), (2, 1), (3, 2)]);
-        // Only contain non fee-tokens and non zero prices
-        let expected_prices = vec![1, 2];
-        let expected_token_ids = vec![2, 3];
-
-        assert_eq!(
-            encode_prices_for_contract(&price_map),
-            (expected_prices, expected_token_ids)
-        );
-    }
-
-    #[test]
-    fn unsorted_price_encoding() {
-        let unordered_price_map = map_from_slice(&[(4, 2), (1, 3), (5, 0), (0, 2), (3, 1)]);
-
-        // Only contain non fee-token and non zero prices
-        let expected_prices = vec![3, 1, 2];
-        let expected_token_ids = vec![1, 3, 4];
-        assert_eq!(
-            encode_prices_for_contract(&unordered_price_map),
-            (expected_prices, expected_token_ids)
-        );
+    fn wait_for_batch_sleeps_until_batch_end() {
+        let mut sequence = Sequence::new();
+        let mut contract = MockStableXContract::new();
+        let mut sleep = MockAsyncSleeping::new();
+        contract
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
+        contract
+            .expect_get_current_auction_index()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(40));
+        contract
+            .expect_get_current_auction_remaining_time()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(Duration::from_secs(100)));
+        sleep
+            .expect_sleep()
+            .with(eq(Duration::from_secs(400)))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| immediate!(()));
+        contract
+            .expect_get_current_auction_index()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(41));
+        contract
+            .expect_get_current_auction_remaining_time()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(Duration::from_secs(0)));
+        sleep
+            .expect_sleep()
+            .with(eq(BATCH_POLL_INTERVAL))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| immediate!(()));
+        contract
+            .expect_get_current_auction_index()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(43));
+
+        let batch = wait_for_batch(&contract, &sleep, 42)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch, 43);
     }
 }
@@ -0,0 +1,198 @@
+//! Module implementing a wrapper around the exchange contract that caches the
+//! number of listed tokens, which would otherwise be queried from the node
+//! over and over again.
+
+use super::{ExchangeEvent, FilteredOrderPage, StableXContract, StableXContractImpl};
+use crate::{contracts::call_cache::CallCache, models::Solution};
+use anyhow::Result;
+use ethcontract::{
+    errors::{ExecutionError, MethodError},
+    transaction::TransactionResult,
+    Address, BlockNumber, U256,
+};
+use futures::stream::BoxStream;
+use std::{sync::Arc, time::Duration};
+
+/// The default time after which values that rarely change, like the number
+/// of listed tokens, are queried again.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// The exchange contract with a cached number of listed tokens, which only
+/// changes when a token is listed but is needed by every lookup of all token
+/// infos.
+///
+/// The number of tokens is queried again once it is older than the maximum
+/// age or after the cache was invalidated. All other calls are forwarded to
+/// the contract. Token infos are cached by `TokenInfoCache` instead.
+pub struct CachedStableXContract {
+    inner: Arc<StableXContractImpl>,
+    num_tokens: CallCache<(), u16>,
+}
+
+impl CachedStableXContract {
+    pub fn new(inner: Arc<StableXContractImpl>) -> Self {
+        Self::with_max_age(inner, DEFAULT_MAX_AGE)
+    }
+
+    /// Creates a cached contract for which the number of tokens is queried
+    /// again once it is older than the specified maximum age.
+    pub fn with_max_age(inner: Arc<StableXContractImpl>, max_age: Duration) -> Self {
+        Self {
+            inner,
+            num_tokens: CallCache::new(Some(max_age)),
+        }
+    }
+
+    /// The wrapped contract.
+    pub fn inner(&self) -> &Arc<StableXContractImpl> {
+        &self.inner
+    }
+
+    /// Drops the cached number of tokens, so that it is queried again on its
+    /// next use. This is called when a token was listed.
+    pub fn invalidate(&self) {
+        self.num_tokens.invalidate();
+    }
+
+    pub async fn num_tokens(&self) -> Result<u16> {
+        self.num_tokens
+            .get_or_fetch((), self.inner.num_tokens())
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl StableXContract for CachedStableXContract {
+    async fn get_current_auction_index(&self) -> Result<u32> {
+        self.inner.get_current_auction_index().await
+    }
+
+    async fn get_current_auction_remaining_time(&self) -> Result<Duration> {
+        self.inner.get_current_auction_remaining_time().await
+    }
+
+    fn batch_duration(&self) -> Duration {
+        self.inner.batch_duration()
+    }
+
+    async fn get_last_block_for_batch(&self, batch_id: u32) -> Result<u64> {
+        self.inner.get_last_block_for_batch(batch_id).await
+    }
+
+    async fn get_filtered_auction_data_paginated(
+        &self,
+        batch_index: u32,
+        token_whitelist: Vec<u16>,
+        page_size: u16,
+        previous_page_user: Address,
+        previous_page_user_offset: u16,
+        block_number: Option<BlockNumber>,
+    ) -> Result<FilteredOrderPage> {
+        self.inner
+            .get_filtered_auction_data_paginated(
+                batch_index,
+                token_whitelist,
+                page_size,
+                previous_page_user,
+                previous_page_user_offset,
+                block_number,
+            )
+            .await
+    }
+
+    async fn get_auction_data_paginated(
+        &self,
+        page_size: u16,
+        previous_page_user: Address,
+        previous_page_user_offset: u16,
+        block_number: Option<BlockNumber>,
+    ) -> Result<Vec<u8>> {
+        self.inner
+            .get_auction_data_paginated(
+                page_size,
+                previous_page_user,
+                previous_page_user_offset,
+                block_number,
+            )
+            .await
+    }
+
+    async fn get_balance(
+        &self,
+        user: Address,
+        token: Address,
+        block_number: Option<BlockNumber>,
+    ) -> Result<U256> {
+        self.inner.get_balance(user, token, block_number).await
+    }
+
+    async fn get_solution_objective_value(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        block_number: Option<BlockNumber>,
+    ) -> Result<U256> {
+        self.inner
+            .get_solution_objective_value(batch_index, solution, block_number)
+            .await
+    }
+
+    async fn estimate_solution_gas(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256> {
+        self.inner
+            .estimate_solution_gas(batch_index, solution, claimed_objective_value)
+            .await
+    }
+
+    async fn submit_solution(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+        gas_limit: U256,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<TransactionResult, MethodError> {
+        self.inner
+            .submit_solution(
+                batch_index,
+                solution,
+                claimed_objective_value,
+                gas_limit,
+                gas_price,
+                nonce,
+            )
+            .await
+    }
+
+    async fn past_events<'a>(
+        &'a self,
+        from_block: u64,
+        to_block: u64,
+        block_page_size: u64,
+    ) -> Result<BoxStream<'a, Result<ExchangeEvent>>> {
+        self.inner
+            .past_events(from_block, to_block, block_page_size)
+            .await
+    }
+
+    async fn send_noop_transaction(
+        &self,
+        gas_price: U256,
+        nonce: U256,
+    ) -> Result<TransactionResult, ExecutionError> {
+        self.inner.send_noop_transaction(gas_price, nonce).await
+    }
+
+    async fn get_transaction_count(&self) -> Result<U256> {
+        self.inner.get_transaction_count().await
+    }
+
+    async fn get_pending_transaction_count(&self) -> Result<U256> {
+        self.inner.get_pending_transaction_count().await
+    }
+}
@@ -0,0 +1,164 @@
+//! Versioned decoding of exchange contract event logs.
+//!
+//! The orderbook is built from the events of the current `BatchExchange` ABI.
+//! Deployments with a different event ABI are registered by chain id and
+//! contract address and decode their logs into those events, so that contract
+//! upgrades only need a new version here instead of changes to the orderbook.
+
+use ::contracts::{batch_exchange, BatchExchange};
+use anyhow::{anyhow, Result};
+use ethcontract::{common::abi::RawLog, contract::ParseLog, web3::types::Log, Address, H256};
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+
+lazy_static! {
+    /// Exchange contract deployments that use an event ABI other than `V1`,
+    /// by chain id and contract address.
+    static ref CONTRACT_VERSIONS: HashMap<(u64, Address), EventAbiVersion> = HashMap::new();
+
+    /// The topics of all events of the current exchange contract ABI.
+    static ref V1_TOPICS: HashSet<H256> = BatchExchange::raw_contract()
+        .abi
+        .events()
+        .map(|event| event.signature())
+        .collect();
+}
+
+/// A decoded exchange contract event together with its position in the chain.
+#[derive(Clone, Debug)]
+pub struct ExchangeEvent {
+    pub data: batch_exchange::Event,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub log_index: usize,
+}
+
+/// The versions of the exchange contract event ABI.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EventAbiVersion {
+    /// The ABI the `BatchExchange` bindings were generated from.
+    V1,
+}
+
+impl EventAbiVersion {
+    /// Selects the event ABI version of an exchange contract deployment.
+    pub fn for_contract(chain_id: u64, address: Address) -> Self {
+        CONTRACT_VERSIONS
+            .get(&(chain_id, address))
+            .copied()
+            .unwrap_or(EventAbiVersion::V1)
+    }
+
+    /// Decodes a log emitted by the exchange contract. Logs with topics that
+    /// are unknown to this version are skipped by returning `None`, so that
+    /// new events do not prevent updating the orderbook.
+    pub fn decode(self, log: Log) -> Result<Option<ExchangeEvent>> {
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => return Ok(None),
+        };
+        let data = match self {
+            EventAbiVersion::V1 => {
+                if !V1_TOPICS.contains(&topic) {
+                    log::warn!("skipping exchange event with unknown topic {:?}", topic);
+                    return Ok(None);
+                }
+                batch_exchange::Event::parse_log(RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.0.clone(),
+                })?
+            }
+        };
+
+        Ok(Some(ExchangeEvent {
+            data,
+            block_number: log
+                .block_number
+                .ok_or_else(|| anyhow!("event without block number: {:?}", log))?
+                .as_u64(),
+            block_hash: log
+                .block_hash
+                .ok_or_else(|| anyhow!("event without block hash: {:?}", log))?,
+            log_index: log
+                .log_index
+                .ok_or_else(|| anyhow!("event without log index: {:?}", log))?
+                .as_usize(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batch_exchange::event_data::Deposit;
+    use ethcontract::{
+        common::abi::{encode, Token},
+        web3::types::Bytes,
+        U256,
+    };
+    use serde_json::json;
+
+    fn log(topics: Vec<H256>, data: Vec<u8>) -> Log {
+        serde_json::from_value(json!({
+            "address": Address::zero(),
+            "topics": topics,
+            "data": Bytes(data),
+            "blockHash": H256::repeat_byte(1),
+            "blockNumber": "0x2a",
+            "transactionHash": H256::zero(),
+            "transactionIndex": "0x0",
+            "logIndex": "0x3",
+            "transactionLogIndex": "0x0",
+            "removed": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn decodes_known_events() {
+        let signature = BatchExchange::raw_contract()
+            .abi
+            .event("Deposit")
+            .unwrap()
+            .signature();
+        let user = Address::from_low_u64_be(1);
+        let token = Address::from_low_u64_be(2);
+        let log = log(
+            vec![signature, user.into(), token.into()],
+            encode(&[Token::Uint(10.into()), Token::Uint(5.into())]),
+        );
+
+        let event = EventAbiVersion::V1.decode(log).unwrap().unwrap();
+        match event.data {
+            batch_exchange::Event::Deposit(Deposit {
+                user: event_user,
+                token: event_token,
+                amount,
+                batch_id,
+            }) => {
+                assert_eq!(event_user, user);
+                assert_eq!(event_token, token);
+                assert_eq!(amount, U256::from(10));
+                assert_eq!(batch_id, 5);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(event.block_number, 42);
+        assert_eq!(event.block_hash, H256::repeat_byte(1));
+        assert_eq!(event.log_index, 3);
+    }
+
+    #[test]
+    fn skips_unknown_events() {
+        let log = log(vec![H256::repeat_byte(0xff)], vec![]);
+        assert!(EventAbiVersion::V1.decode(log).unwrap().is_none());
+    }
+
+    #[test]
+    fn unknown_deployments_use_current_version() {
+        assert_eq!(
+            EventAbiVersion::for_contract(1, Address::zero()),
+            EventAbiVersion::V1
+        );
+    }
+}
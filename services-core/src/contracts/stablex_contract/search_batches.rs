@@ -1,9 +1,11 @@
+use crate::models::batch_id::BatchTiming;
 use anyhow::{anyhow, Result};
 use ethcontract::{prelude::Web3, transport::DynTransport, web3::types::Block, BlockNumber, H256};
 
-fn get_block_batch_id<T>(block: &Block<T>) -> u32 {
-    const BATCH_DURATION: u64 = 300;
-    (block.timestamp.as_u64() / BATCH_DURATION) as _
+fn get_block_batch_id<T>(block: &Block<T>, batch_timing: &BatchTiming) -> u32 {
+    batch_timing
+        .batch_at_timestamp(block.timestamp.as_u64())
+        .into()
 }
 
 async fn get_block(
@@ -39,16 +41,22 @@ pub trait BatchIdRetrieving {
     async fn current_batch_id_and_block_number(&self) -> Result<(u32, u64)>;
 }
 
+/// Retrieves the batch ids of blocks from the node based on their timestamp.
+pub struct NodeBatchIdRetriever {
+    pub web3: Web3<DynTransport>,
+    pub batch_timing: BatchTiming,
+}
+
 #[async_trait::async_trait]
-impl BatchIdRetrieving for Web3<DynTransport> {
+impl BatchIdRetrieving for NodeBatchIdRetriever {
     async fn batch_id_from_block(&self, block_number: BlockNumber) -> Result<u32> {
-        let current_block = get_block(&self, block_number).await?;
-        Ok(get_block_batch_id(&current_block))
+        let current_block = get_block(&self.web3, block_number).await?;
+        Ok(get_block_batch_id(&current_block, &self.batch_timing))
     }
 
     async fn current_batch_id_and_block_number(&self) -> Result<(u32, u64)> {
-        let current_block = get_block(&self, BlockNumber::Latest).await?;
-        let batch_id = get_block_batch_id(&current_block);
+        let current_block = get_block(&self.web3, BlockNumber::Latest).await?;
+        let batch_id = get_block_batch_id(&current_block, &self.batch_timing);
         let block_number = current_block
             .number
             .ok_or_else(|| anyhow!("latest block {:?} has no block number", current_block.hash))?
@@ -0,0 +1,66 @@
+//! Access to the wrapped native token (e.g. WETH on mainnet or WXDAI on xDAI)
+//! held by the account of a signer, which has to be unwrapped before it can
+//! pay for gas.
+
+use crate::contracts::{self, Signer};
+use ::contracts::WETH9;
+use anyhow::{Error, Result};
+use ethcontract::{Address, H256, U256};
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait WrappedNativeToken: Send + Sync {
+    /// Retrieve the wrapped native token balance of the account.
+    async fn balance(&self) -> Result<U256>;
+
+    /// Unwraps an amount of the account's wrapped native token into the
+    /// native token.
+    async fn unwrap(&self, amount: U256) -> Result<H256>;
+}
+
+pub struct WrappedNativeTokenImpl {
+    instance: WETH9,
+    /// The token contract bound to the connection transactions are sent over,
+    /// which differs from `instance` when using an external signer.
+    transaction_instance: WETH9,
+    account: Address,
+}
+
+impl WrappedNativeTokenImpl {
+    pub async fn new(web3: &contracts::Web3, signer: Signer, token: Address) -> Result<Self> {
+        let chain_id = web3.eth().chain_id().await?.as_u64();
+        let account = contracts::account(&signer, chain_id);
+        let defaults = contracts::method_defaults(account.clone());
+
+        let mut instance = WETH9::at(web3, token);
+        *instance.defaults_mut() = defaults.clone();
+        let mut transaction_instance = WETH9::at(signer.transaction_web3(web3), token);
+        *transaction_instance.defaults_mut() = defaults;
+
+        Ok(WrappedNativeTokenImpl {
+            instance,
+            transaction_instance,
+            account: account.address(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl WrappedNativeToken for WrappedNativeTokenImpl {
+    async fn balance(&self) -> Result<U256> {
+        self.instance
+            .balance_of(self.account)
+            .call()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn unwrap(&self, amount: U256) -> Result<H256> {
+        Ok(self
+            .transaction_instance
+            .withdraw(amount)
+            .send()
+            .await?
+            .hash())
+    }
+}
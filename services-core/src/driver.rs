@@ -1,2 +1,10 @@
+pub mod alerting;
+pub mod balance_monitor;
+pub mod batch_clock;
+pub mod fee_compounding;
+pub mod fee_funds;
+pub mod relayer;
 pub mod scheduler;
+pub mod solution_ranking;
 pub mod stablex_driver;
+pub mod submission_receipts;
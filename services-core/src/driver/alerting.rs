@@ -0,0 +1,333 @@
+//! Alerting on the outcome of batches. Configurable rules are evaluated after
+//! every batch the scheduler processed and for every submitted solution, and
+//! alerts are sent to a webhook in the background, so that the conditions
+//! under which the solver is considered unhealthy are defined in a single
+//! place.
+
+use crate::http::{HttpClient, HttpFactory, HttpLabel, HttpService};
+use crate::models::BatchId;
+use anyhow::{Error, Result};
+use async_std::task::{self, JoinHandle};
+use ethcontract::U256;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, str::FromStr, sync::Arc, sync::Mutex};
+use url::Url;
+
+/// The rules that are evaluated after every batch. Rules that are not set
+/// are disabled.
+///
+/// For example: '{
+///   "maxBatchesWithoutSolution": 12,
+///   "maxObjectiveValueDrop": 0.5,
+///   "trailingSolutions": 20,
+///   "maxGasCost": 0.1
+/// }'
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AlertRules {
+    /// Alert once no solution was submitted for this many batches in a row.
+    pub max_batches_without_solution: Option<u64>,
+    /// Alert when the objective value of a submitted solution is lower than
+    /// the average of the previous solutions by more than this fraction.
+    pub max_objective_value_drop: Option<f64>,
+    /// The number of previous solutions the average objective value is
+    /// computed over.
+    #[serde(default = "default_trailing_solutions")]
+    pub trailing_solutions: usize,
+    /// Alert when the estimated maximum gas cost of a submitted solution
+    /// exceeds this amount of the native token, for example ETH.
+    pub max_gas_cost: Option<f64>,
+}
+
+fn default_trailing_solutions() -> usize {
+    10
+}
+
+impl FromStr for AlertRules {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+/// A solution that was submitted for a batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SubmittedSolution {
+    pub objective_value: U256,
+    /// The estimated gas of the solution multiplied with the gas price cap
+    /// of its submission, in wei.
+    pub max_gas_cost: f64,
+}
+
+/// An alert raised by one of the rules.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "rule", rename_all = "camelCase")]
+pub enum Alert {
+    #[serde(rename_all = "camelCase")]
+    NoSolution { batches: u64 },
+    #[serde(rename_all = "camelCase")]
+    ObjectiveValueDrop {
+        objective_value: f64,
+        trailing_average: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    GasCost { max_gas_cost: f64 },
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait AlertSending: Send + Sync {
+    async fn send_alert(&self, batch: BatchId, alert: &Alert) -> Result<()>;
+}
+
+/// Sends alerts as JSON to a webhook URL.
+pub struct WebhookAlertSender {
+    client: HttpClient,
+    url: Url,
+}
+
+impl WebhookAlertSender {
+    pub fn new(http_factory: &HttpFactory, url: Url) -> Result<Self> {
+        Ok(Self {
+            client: http_factory
+                .create_for(HttpService::Alerting)?
+                .without_circuit_breaker(),
+            url,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    batch: BatchId,
+    alert: &'a Alert,
+}
+
+#[async_trait::async_trait]
+impl AlertSending for WebhookAlertSender {
+    async fn send_alert(&self, batch: BatchId, alert: &Alert) -> Result<()> {
+        let payload = serde_json::to_string(&WebhookPayload { batch, alert })?;
+        self.client
+            .post_raw_json_async(self.url.as_str(), payload, HttpLabel::AlertWebhook)
+            .await?;
+        Ok(())
+    }
+}
+
+pub struct Alerting {
+    rules: AlertRules,
+    sender: Arc<dyn AlertSending>,
+    state: Mutex<AlertingState>,
+}
+
+#[derive(Default)]
+struct AlertingState {
+    /// The last batch for which a solution was submitted. Initialized to the
+    /// batch before the first processed batch.
+    last_solution_batch: Option<BatchId>,
+    /// Whether the current streak of batches without solution was alerted.
+    no_solution_alerted: bool,
+    /// The objective values of the most recent solutions.
+    objective_values: VecDeque<f64>,
+}
+
+impl Alerting {
+    pub fn new(rules: AlertRules, sender: Arc<dyn AlertSending>) -> Self {
+        Self {
+            rules,
+            sender,
+            state: Mutex::new(AlertingState::default()),
+        }
+    }
+
+    /// Evaluates the rules for a solution that was submitted for a batch and
+    /// sends the resulting alerts in the background.
+    pub fn solution_submitted(&self, batch: BatchId, solution: SubmittedSolution) {
+        self.send(batch, self.evaluate(batch, Some(solution)));
+    }
+
+    /// Evaluates the rules after the scheduler processed a batch, whether or
+    /// not a solution was submitted for it, and sends the resulting alerts in
+    /// the background.
+    pub fn batch_processed(&self, batch: BatchId) {
+        self.send(batch, self.evaluate(batch, None));
+    }
+
+    /// Sends alerts without blocking the caller. Failing to send an alert is
+    /// logged.
+    fn send(&self, batch: BatchId, alerts: Vec<Alert>) -> Option<JoinHandle<()>> {
+        if alerts.is_empty() {
+            return None;
+        }
+        let sender = self.sender.clone();
+        Some(task::spawn(async move {
+            for alert in alerts {
+                log::warn!("alert for batch {}: {:?}", batch, alert);
+                if let Err(err) = sender.send_alert(batch, &alert).await {
+                    log::error!("failed to send alert: {:?}", err);
+                }
+            }
+        }))
+    }
+
+    fn evaluate(&self, batch: BatchId, solution: Option<SubmittedSolution>) -> Vec<Alert> {
+        let mut state = self.state.lock().unwrap();
+        let mut alerts = Vec::new();
+        let last_solution_batch = *state.last_solution_batch.get_or_insert(batch.prev());
+
+        let solution = match solution {
+            Some(solution) => solution,
+            None => {
+                // The streak is computed from the last solution, so that
+                // batches the scheduler skipped are counted as well.
+                let batches = batch.0.saturating_sub(last_solution_batch.0);
+                if let Some(max_batches) = self.rules.max_batches_without_solution {
+                    if batches >= max_batches && !state.no_solution_alerted {
+                        state.no_solution_alerted = true;
+                        alerts.push(Alert::NoSolution { batches });
+                    }
+                }
+                return alerts;
+            }
+        };
+        state.last_solution_batch = Some(batch);
+        state.no_solution_alerted = false;
+
+        let objective_value = solution.objective_value.to_f64_lossy();
+        if let (Some(max_drop), false) = (
+            self.rules.max_objective_value_drop,
+            state.objective_values.is_empty(),
+        ) {
+            let trailing_average =
+                state.objective_values.iter().sum::<f64>() / state.objective_values.len() as f64;
+            if objective_value < trailing_average * (1.0 - max_drop) {
+                alerts.push(Alert::ObjectiveValueDrop {
+                    objective_value,
+                    trailing_average,
+                });
+            }
+        }
+        state.objective_values.push_back(objective_value);
+        while state.objective_values.len() > self.rules.trailing_solutions {
+            state.objective_values.pop_front();
+        }
+
+        if let Some(max_gas_cost) = self.rules.max_gas_cost {
+            if solution.max_gas_cost / 1e18 > max_gas_cost {
+                alerts.push(Alert::GasCost {
+                    max_gas_cost: solution.max_gas_cost,
+                });
+            }
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alerting(rules: &str) -> Alerting {
+        Alerting::new(rules.parse().unwrap(), Arc::new(MockAlertSending::new()))
+    }
+
+    fn solution(objective_value: u64) -> Option<SubmittedSolution> {
+        Some(SubmittedSolution {
+            objective_value: objective_value.into(),
+            max_gas_cost: 0.0,
+        })
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(
+            "{\"maxBatchesWithoutSolution\": 12, \"maxGasCost\": 0.1}"
+                .parse::<AlertRules>()
+                .unwrap(),
+            AlertRules {
+                max_batches_without_solution: Some(12),
+                max_objective_value_drop: None,
+                trailing_solutions: 10,
+                max_gas_cost: Some(0.1),
+            }
+        );
+        assert!("{\"unknownRule\": 1}".parse::<AlertRules>().is_err());
+    }
+
+    #[test]
+    fn alerts_once_per_streak_without_solution() {
+        let alerting = alerting("{\"maxBatchesWithoutSolution\": 2}");
+        assert!(alerting.evaluate(BatchId(10), solution(1)).is_empty());
+        assert!(alerting.evaluate(BatchId(11), None).is_empty());
+        assert_eq!(
+            alerting.evaluate(BatchId(12), None),
+            vec![Alert::NoSolution { batches: 2 }]
+        );
+        assert!(alerting.evaluate(BatchId(13), None).is_empty());
+        assert!(alerting.evaluate(BatchId(14), solution(1)).is_empty());
+        assert_eq!(
+            alerting.evaluate(BatchId(17), None),
+            vec![Alert::NoSolution { batches: 3 }]
+        );
+    }
+
+    #[test]
+    fn alerts_on_objective_value_drop() {
+        let alerting = alerting("{\"maxObjectiveValueDrop\": 0.5, \"trailingSolutions\": 2}");
+        assert!(alerting.evaluate(BatchId(1), solution(100)).is_empty());
+        assert!(alerting.evaluate(BatchId(2), solution(300)).is_empty());
+        assert!(alerting.evaluate(BatchId(3), solution(100)).is_empty());
+        assert_eq!(
+            alerting.evaluate(BatchId(4), solution(50)),
+            vec![Alert::ObjectiveValueDrop {
+                objective_value: 50.0,
+                trailing_average: 200.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn alerts_on_gas_cost() {
+        let alerting = alerting("{\"maxGasCost\": 0.1}");
+        let solution = |max_gas_cost| {
+            Some(SubmittedSolution {
+                objective_value: 1.into(),
+                max_gas_cost,
+            })
+        };
+        assert!(alerting.evaluate(BatchId(1), solution(1e17)).is_empty());
+        assert_eq!(
+            alerting.evaluate(BatchId(2), solution(2e17)),
+            vec![Alert::GasCost { max_gas_cost: 2e17 }]
+        );
+    }
+
+    #[test]
+    fn sends_alerts() {
+        let mut sender = MockAlertSending::new();
+        sender
+            .expect_send_alert()
+            .withf(|batch, alert| {
+                *batch == BatchId(2) && *alert == Alert::NoSolution { batches: 1 }
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let alerting = Alerting::new(
+            "{\"maxBatchesWithoutSolution\": 1}".parse().unwrap(),
+            Arc::new(sender),
+        );
+        assert!(alerting
+            .send(BatchId(1), alerting.evaluate(BatchId(1), solution(1)))
+            .is_none());
+        assert!(alerting
+            .send(BatchId(1), alerting.evaluate(BatchId(1), None))
+            .is_none());
+        task::block_on(
+            alerting
+                .send(BatchId(2), alerting.evaluate(BatchId(2), None))
+                .unwrap(),
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{contracts::Web3, health::HealthReporting, metrics::StableXMetrics};
+use anyhow::Result;
+use async_std::task::{self, JoinHandle};
+use ethcontract::{Address, U256};
+use futures::future;
+use std::{sync::Arc, time::Duration};
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait BalanceReading: Send + Sync {
+    /// Retrieves the native token balance of an account.
+    async fn balance(&self, account: Address) -> Result<U256>;
+}
+
+#[async_trait::async_trait]
+impl BalanceReading for Web3 {
+    async fn balance(&self, account: Address) -> Result<U256> {
+        Ok(self.eth().balance(account, None).await?)
+    }
+}
+
+/// Periodically checks the balances of the accounts submitting solutions so
+/// that an empty wallet is noticed before a solution submission fails.
+pub struct BalanceMonitor {
+    balance_reader: Arc<dyn BalanceReading>,
+    accounts: Vec<Address>,
+    threshold: U256,
+    health: Arc<dyn HealthReporting>,
+    metrics: Arc<StableXMetrics>,
+}
+
+impl BalanceMonitor {
+    pub fn new(
+        balance_reader: Arc<dyn BalanceReading>,
+        accounts: Vec<Address>,
+        threshold: U256,
+        health: Arc<dyn HealthReporting>,
+        metrics: Arc<StableXMetrics>,
+    ) -> Self {
+        Self {
+            balance_reader,
+            accounts,
+            threshold,
+            health,
+            metrics,
+        }
+    }
+
+    /// Fetches the current balances, exports them as metrics and reports the
+    /// service as unhealthy if any of them is below the threshold.
+    pub async fn check(&self) -> Result<Vec<U256>> {
+        let balances = future::try_join_all(
+            self.accounts
+                .iter()
+                .map(|account| self.balance_reader.balance(*account)),
+        )
+        .await?;
+
+        let mut sufficient = true;
+        for (account, balance) in self.accounts.iter().zip(&balances) {
+            self.metrics.account_balance_fetched(*account, *balance);
+            if *balance < self.threshold {
+                log::warn!(
+                    "balance {} of account {:?} is below the threshold of {}",
+                    balance,
+                    account,
+                    self.threshold,
+                );
+                sufficient = false;
+            }
+        }
+        self.health.notify_balance_sufficient(sufficient);
+
+        Ok(balances)
+    }
+
+    /// Spawns a background task that checks the balance every
+    /// `update_interval`.
+    pub fn start_in_background(self, update_interval: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                if let Err(err) = self.check().await {
+                    log::warn!("failed to check account balance: {:?}", err);
+                }
+                task::sleep(update_interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::MockHealthReporting;
+    use futures::FutureExt as _;
+    use mockall::predicate::eq;
+
+    fn monitor(balances: &[u64], expected_sufficient: bool) -> BalanceMonitor {
+        let accounts = (1..=balances.len() as u64)
+            .map(Address::from_low_u64_be)
+            .collect::<Vec<_>>();
+
+        let mut balance_reader = MockBalanceReading::new();
+        for (account, balance) in accounts.iter().zip(balances) {
+            let balance = U256::from(*balance);
+            balance_reader
+                .expect_balance()
+                .with(eq(*account))
+                .returning(move |_| Ok(balance));
+        }
+
+        let mut health = MockHealthReporting::new();
+        health
+            .expect_notify_balance_sufficient()
+            .with(eq(expected_sufficient))
+            .times(1)
+            .return_const(());
+
+        BalanceMonitor::new(
+            Arc::new(balance_reader),
+            accounts,
+            U256::from(100),
+            Arc::new(health),
+            Arc::new(StableXMetrics::default()),
+        )
+    }
+
+    #[test]
+    fn reports_sufficient_balance() {
+        let monitor = monitor(&[100], true);
+        let balances = monitor.check().now_or_never().unwrap().unwrap();
+        assert_eq!(balances, vec![U256::from(100)]);
+    }
+
+    #[test]
+    fn reports_insufficient_balance() {
+        let monitor = monitor(&[99], false);
+        let balances = monitor.check().now_or_never().unwrap().unwrap();
+        assert_eq!(balances, vec![U256::from(99)]);
+    }
+
+    #[test]
+    fn reports_insufficient_balance_of_any_account() {
+        let monitor = monitor(&[100, 99], false);
+        let balances = monitor.check().now_or_never().unwrap().unwrap();
+        assert_eq!(balances, vec![U256::from(100), U256::from(99)]);
+    }
+}
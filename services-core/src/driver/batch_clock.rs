@@ -0,0 +1,205 @@
+use crate::{
+    contracts::stablex_contract::StableXContract,
+    health::HealthReporting,
+    metrics::StableXMetrics,
+    models::batch_id::BatchTiming,
+    util::{self, Now},
+};
+use anyhow::{ensure, Result};
+use async_std::task::{self, JoinHandle};
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A clock for scheduling batches that follows the chain time when the system
+/// clock drifted. Batches are based on the timestamps of the blocks so a
+/// drifting host clock leads to solving and submitting too late.
+///
+/// The chain time is the timestamp of the latest block as seen by the exchange
+/// contract. It lags behind the real time by up to a block time, so the system
+/// time is used as long as it is within `max_drift` of the chain time. Beyond
+/// that the system clock is assumed to be wrong, the chain time is used instead
+/// and the service is reported as not ready.
+pub struct BatchClock {
+    contract: Arc<dyn StableXContract>,
+    now: Box<dyn Now>,
+    max_drift: Duration,
+    health: Arc<dyn HealthReporting>,
+    metrics: Arc<StableXMetrics>,
+    /// The correction in milliseconds that is subtracted from the system time.
+    /// This is zero while the clock is in sync.
+    correction_millis: AtomicI64,
+}
+
+impl BatchClock {
+    pub fn new(
+        contract: Arc<dyn StableXContract>,
+        max_drift: Duration,
+        health: Arc<dyn HealthReporting>,
+        metrics: Arc<StableXMetrics>,
+    ) -> Self {
+        Self::with_now(
+            contract,
+            Box::new(util::default_now()),
+            max_drift,
+            health,
+            metrics,
+        )
+    }
+
+    fn with_now(
+        contract: Arc<dyn StableXContract>,
+        now: Box<dyn Now>,
+        max_drift: Duration,
+        health: Arc<dyn HealthReporting>,
+        metrics: Arc<StableXMetrics>,
+    ) -> Self {
+        Self {
+            contract,
+            now,
+            max_drift,
+            health,
+            metrics,
+            correction_millis: AtomicI64::new(0),
+        }
+    }
+
+    /// Measures the drift of the system time relative to the chain time in
+    /// seconds, exports it as a metric and updates the correction applied to
+    /// the system time.
+    pub async fn update(&self) -> Result<f64> {
+        let batch_id = self.contract.get_current_auction_index().await?;
+        let remaining_time = self.contract.get_current_auction_remaining_time().await?;
+        let system_time = self.now.system_now();
+        ensure!(
+            self.contract.get_current_auction_index().await? == batch_id,
+            "batch changed while measuring the chain time"
+        );
+
+        let batch_end = BatchTiming::with_batch_duration(self.contract.batch_duration())
+            .solve_start_time(batch_id.into());
+        let chain_time = unix_seconds(batch_end) - remaining_time.as_secs_f64();
+        let drift = unix_seconds(system_time) - chain_time;
+        self.metrics.clock_drift_measured(drift);
+
+        let in_sync = drift.abs() <= self.max_drift.as_secs_f64();
+        if !in_sync {
+            log::warn!(
+                "system time drifted {}s from the chain time, using chain time instead",
+                drift
+            );
+        }
+        let correction = if in_sync { 0.0 } else { drift };
+        self.correction_millis
+            .store((correction * 1000.0) as i64, Ordering::SeqCst);
+        self.health.notify_clock_in_sync(in_sync);
+
+        Ok(drift)
+    }
+
+    /// Spawns a background task that measures the drift every
+    /// `update_interval`.
+    pub fn start_in_background(self: Arc<Self>, update_interval: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                if let Err(err) = self.update().await {
+                    log::warn!("failed to measure clock drift: {:?}", err);
+                }
+                task::sleep(update_interval).await;
+            }
+        })
+    }
+}
+
+impl Now for BatchClock {
+    fn system_now(&self) -> SystemTime {
+        let now = self.now.system_now();
+        let correction = self.correction_millis.load(Ordering::SeqCst);
+        if correction >= 0 {
+            now - Duration::from_millis(correction as u64)
+        } else {
+            now + Duration::from_millis((-correction) as u64)
+        }
+    }
+
+    fn instant_now(&self) -> Instant {
+        self.now.instant_now()
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        contracts::stablex_contract::MockStableXContract, health::MockHealthReporting,
+        util::MockNow,
+    };
+    use futures::FutureExt as _;
+    use mockall::predicate::eq;
+
+    /// Creates a clock with a chain time of 1000s in batch 3.
+    fn clock(system_time: u64, expected_in_sync: bool) -> BatchClock {
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
+        contract
+            .expect_get_current_auction_index()
+            .returning(|| Ok(3));
+        contract
+            .expect_get_current_auction_remaining_time()
+            .returning(|| Ok(Duration::from_secs(200)));
+
+        let mut now = MockNow::new();
+        now.expect_system_now()
+            .returning(move || SystemTime::UNIX_EPOCH + Duration::from_secs(system_time));
+
+        let mut health = MockHealthReporting::new();
+        health
+            .expect_notify_clock_in_sync()
+            .with(eq(expected_in_sync))
+            .times(1)
+            .return_const(());
+
+        BatchClock::with_now(
+            Arc::new(contract),
+            Box::new(now),
+            Duration::from_secs(30),
+            Arc::new(health),
+            Arc::new(StableXMetrics::default()),
+        )
+    }
+
+    #[test]
+    fn uses_system_time_while_in_sync() {
+        let clock = clock(1010, true);
+        let drift = clock.update().now_or_never().unwrap().unwrap();
+        assert_eq!(drift, 10.0);
+        assert_eq!(
+            clock.system_now(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1010)
+        );
+    }
+
+    #[test]
+    fn uses_chain_time_when_clock_drifted() {
+        let clock = clock(940, false);
+        let drift = clock.update().now_or_never().unwrap().unwrap();
+        assert_eq!(drift, -60.0);
+        assert_eq!(
+            clock.system_now(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000)
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Keeps the solver account funded by selling part of the earned fees for the
+//! wrapped native token on the exchange itself, withdrawing the proceeds and
+//! unwrapping them so they can pay for gas.
+
+use crate::contracts::exchange_client::{ExchangeClient, NewOrder};
+use crate::contracts::wrapped_native_token::WrappedNativeToken;
+use crate::driver::fee_funds::FeeFundsManager;
+use crate::economic_viability::NativeTokenPricing;
+use crate::models::{Order, TokenId};
+use crate::orderbook::StableXOrderBookReading;
+use anyhow::{anyhow, Result};
+use async_std::task::{self, JoinHandle};
+use ethcontract::Address;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The number of batches the sell orders stay valid for.
+const ORDER_VALIDITY_BATCHES: u32 = 12;
+
+/// How much worse than the estimated native token price the sell orders are
+/// allowed to trade at, so that they get matched despite small price moves.
+const PRICE_TOLERANCE: f64 = 0.02;
+
+pub struct FeeCompounding {
+    client: Arc<dyn ExchangeClient>,
+    /// The orderbook used to find the previously placed order, so that only
+    /// one order is open at a time.
+    orderbook: Arc<dyn StableXOrderBookReading>,
+    wrapped_native_token: Arc<dyn WrappedNativeToken>,
+    price_oracle: Arc<dyn NativeTokenPricing + Send + Sync>,
+    native_token: TokenId,
+    /// The fraction of the fee token balance that is sold at a time.
+    portion: f64,
+    /// Withdraws the bought native token from the exchange.
+    native_funds: FeeFundsManager,
+}
+
+impl FeeCompounding {
+    /// Creates a new fee compounding task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the portion is not in the range `(0, 1]`.
+    pub fn new(
+        client: Arc<dyn ExchangeClient>,
+        orderbook: Arc<dyn StableXOrderBookReading>,
+        wrapped_native_token: Arc<dyn WrappedNativeToken>,
+        price_oracle: Arc<dyn NativeTokenPricing + Send + Sync>,
+        native_token: TokenId,
+        portion: f64,
+    ) -> Self {
+        assert!(portion > 0.0 && portion <= 1.0, "invalid fee portion");
+        Self {
+            native_funds: FeeFundsManager::new(client.clone(), 0, false)
+                .with_token_id(native_token.0),
+            client,
+            orderbook,
+            wrapped_native_token,
+            price_oracle,
+            native_token,
+            portion,
+        }
+    }
+
+    /// Sells a portion of the fee token balance for the native token unless a
+    /// previously placed order is still open, and withdraws and unwraps the
+    /// native token that was bought. Returns the placed order.
+    pub async fn compound(&self) -> Result<Option<NewOrder>> {
+        self.native_funds.manage().await?;
+        self.unwrap_native_token().await?;
+
+        let current_batch = self.client.current_batch_id().await?;
+        if self.has_open_order(current_batch).await? {
+            return Ok(None);
+        }
+
+        let fee_token = self.client.token_address(TokenId::reference().0).await?;
+        let balance = self.client.balance(fee_token).await?;
+        let sell_amount = (balance.to_f64_lossy() * self.portion) as u128;
+        if sell_amount == 0 {
+            return Ok(None);
+        }
+        let price = self
+            .price_oracle
+            .get_native_token_price()
+            .await
+            .ok_or_else(|| anyhow!("no native token price"))?;
+        let order = sell_order(sell_amount, price.get(), self.native_token, current_batch);
+
+        let hash = self.client.place_orders(vec![order]).await?;
+        log::info!(
+            "placed order {:?} to sell fees in transaction {:?}",
+            order,
+            hash
+        );
+        Ok(Some(order))
+    }
+
+    /// Unwraps the whole wrapped native token balance of the account.
+    async fn unwrap_native_token(&self) -> Result<()> {
+        let balance = self.wrapped_native_token.balance().await?;
+        if balance.is_zero() {
+            return Ok(());
+        }
+        let hash = self.wrapped_native_token.unwrap(balance).await?;
+        log::info!(
+            "unwrapping {} native token atoms in transaction {:?}",
+            balance,
+            hash
+        );
+        Ok(())
+    }
+
+    /// Checks whether the orderbook contains an order of the account selling
+    /// fees for the native token that can still be matched in the current
+    /// batch.
+    async fn has_open_order(&self, current_batch: u32) -> Result<bool> {
+        let (_, orders) = self
+            .orderbook
+            .get_auction_data_for_batch(current_batch)
+            .await?;
+        let account = self.client.account();
+        Ok(orders
+            .iter()
+            .any(|order| self.is_open_fee_order(order, account)))
+    }
+
+    fn is_open_fee_order(&self, order: &Order, account: Address) -> bool {
+        order.account_id == account
+            && order.sell_token == TokenId::reference().0
+            && order.buy_token == self.native_token.0
+            && order.remaining_sell_amount > 0
+    }
+
+    /// Spawns a background task that compounds fees every `interval`.
+    pub fn start_in_background(self, interval: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                if let Err(err) = self.compound().await {
+                    log::warn!("failed to compound fees: {:?}", err);
+                }
+                task::sleep(interval).await;
+            }
+        })
+    }
+}
+
+/// Creates an order selling fee token atoms for the native token, where the
+/// native token price is the amount of fee token atoms for `10^18` native
+/// token atoms.
+fn sell_order(
+    sell_amount: u128,
+    native_token_price: u128,
+    native_token: TokenId,
+    current_batch: u32,
+) -> NewOrder {
+    let buy_amount =
+        sell_amount as f64 * 1e18 / native_token_price as f64 * (1.0 - PRICE_TOLERANCE);
+    NewOrder {
+        buy_token: native_token.0,
+        sell_token: TokenId::reference().0,
+        valid_from: current_batch,
+        valid_until: current_batch + ORDER_VALIDITY_BATCHES,
+        buy_amount: buy_amount as u128,
+        sell_amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::exchange_client::{MockExchangeClient, PendingWithdraw};
+    use crate::contracts::wrapped_native_token::MockWrappedNativeToken;
+    use crate::economic_viability::MockNativeTokenPricing;
+    use crate::orderbook::MockStableXOrderBookReading;
+    use ethcontract::{H256, U256};
+    use futures::FutureExt as _;
+    use mockall::predicate::eq;
+
+    #[test]
+    fn sell_order_applies_price_tolerance() {
+        let order = sell_order(1_000, 2 * 10u128.pow(18), TokenId(1), 10);
+        assert_eq!(order.sell_token, 0);
+        assert_eq!(order.buy_token, 1);
+        assert_eq!(order.sell_amount, 1_000);
+        assert_eq!(order.buy_amount, 490);
+        assert_eq!(order.valid_from, 10);
+        assert_eq!(order.valid_until, 10 + ORDER_VALIDITY_BATCHES);
+    }
+
+    fn client(fee_balance: u128) -> MockExchangeClient {
+        let mut client = MockExchangeClient::new();
+        client.expect_account().returning(Address::zero);
+        client
+            .expect_token_address()
+            .returning(|token_id| Ok(Address::from_low_u64_be(token_id as u64)));
+        client.expect_current_batch_id().returning(|| Ok(10));
+        client
+            .expect_balance()
+            .returning(move |token| Ok(U256::from(if token.is_zero() { fee_balance } else { 0 })));
+        client
+            .expect_pending_withdraw()
+            .returning(|_| Ok(PendingWithdraw::default()));
+        client
+    }
+
+    fn price_oracle() -> MockNativeTokenPricing {
+        let mut price_oracle = MockNativeTokenPricing::new();
+        price_oracle
+            .expect_get_native_token_price()
+            .returning(|| Some(nonzero!(10u128.pow(18))));
+        price_oracle
+    }
+
+    fn wrapped_native_token(balance: u128) -> MockWrappedNativeToken {
+        let mut wrapped_native_token = MockWrappedNativeToken::new();
+        wrapped_native_token
+            .expect_balance()
+            .returning(move || Ok(balance.into()));
+        wrapped_native_token
+    }
+
+    #[test]
+    fn places_order_when_none_is_open() {
+        let mut client = client(1_000);
+        client
+            .expect_place_orders()
+            .times(1)
+            .returning(|_| Ok(H256::zero()));
+        let mut orderbook = MockStableXOrderBookReading::default();
+        orderbook
+            .expect_get_auction_data_for_batch()
+            .with(eq(10))
+            .returning(|_| Ok(Default::default()));
+
+        let compounding = FeeCompounding::new(
+            Arc::new(client),
+            Arc::new(orderbook),
+            Arc::new(wrapped_native_token(0)),
+            Arc::new(price_oracle()),
+            TokenId(1),
+            0.5,
+        );
+        let order = compounding.compound().now_or_never().unwrap().unwrap();
+        assert_eq!(order.unwrap().sell_amount, 500);
+    }
+
+    #[test]
+    fn does_not_place_order_while_one_is_open() {
+        let mut client = client(1_000);
+        client.expect_place_orders().never();
+        let mut orderbook = MockStableXOrderBookReading::default();
+        orderbook
+            .expect_get_auction_data_for_batch()
+            .returning(|_| {
+                let order = Order {
+                    account_id: Address::zero(),
+                    sell_token: 0,
+                    buy_token: 1,
+                    remaining_sell_amount: 1,
+                    ..Default::default()
+                };
+                Ok((Default::default(), vec![order]))
+            });
+
+        let compounding = FeeCompounding::new(
+            Arc::new(client),
+            Arc::new(orderbook),
+            Arc::new(wrapped_native_token(0)),
+            Arc::new(price_oracle()),
+            TokenId(1),
+            0.5,
+        );
+        assert_eq!(
+            compounding.compound().now_or_never().unwrap().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn unwraps_native_token_balance() {
+        let client = client(0);
+        let mut orderbook = MockStableXOrderBookReading::default();
+        orderbook
+            .expect_get_auction_data_for_batch()
+            .returning(|_| Ok(Default::default()));
+        let mut wrapped_native_token = wrapped_native_token(42);
+        wrapped_native_token
+            .expect_unwrap()
+            .with(eq(U256::from(42)))
+            .times(1)
+            .returning(|_| Ok(H256::zero()));
+
+        let compounding = FeeCompounding::new(
+            Arc::new(client),
+            Arc::new(orderbook),
+            Arc::new(wrapped_native_token),
+            Arc::new(price_oracle()),
+            TokenId(1),
+            0.5,
+        );
+        assert_eq!(
+            compounding.compound().now_or_never().unwrap().unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Management of the fee token that the solver account earns with its
+//! solutions. Earned fees are credited to the account's exchange balance and
+//! need to be withdrawn in two steps: a withdrawal is requested and can be
+//! claimed once the batch it was requested for has been finalized. Other
+//! tokens that the account acquires on the exchange, such as the native token
+//! bought with fees, are withdrawn the same way.
+
+use crate::contracts::exchange_client::{ExchangeClient, PendingWithdraw};
+use crate::models::TokenId;
+use anyhow::Result;
+use ethcontract::{Address, U256};
+use std::sync::Arc;
+
+/// The exchange balances of a token for the solver account.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FeeFundsStatus {
+    pub token: Address,
+    pub current_batch_id: u32,
+    pub balance: U256,
+    pub pending_withdraw: PendingWithdraw,
+}
+
+impl FeeFundsStatus {
+    fn has_matured_withdraw(&self) -> bool {
+        self.pending_withdraw.has_matured(self.current_batch_id)
+    }
+}
+
+/// The action taken, or that would have been taken in a dry run, to manage
+/// the fee funds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeeFundsAction {
+    /// There was nothing to do.
+    None,
+    /// A withdrawal of the amount was requested for the batch.
+    RequestWithdraw { amount: U256, batch_id: u32 },
+    /// A matured withdrawal of the amount was claimed.
+    Withdraw { amount: U256 },
+}
+
+pub struct FeeFundsManager {
+    client: Arc<dyn ExchangeClient>,
+    /// The exchange ID of the managed token.
+    token_id: u16,
+    /// The number of batches after which requested withdrawals can be claimed.
+    /// Until then the fees stay available for trading.
+    withdraw_delay_batches: u32,
+    /// Whether to only determine the action without sending transactions.
+    dry_run: bool,
+}
+
+impl FeeFundsManager {
+    pub fn new(
+        client: Arc<dyn ExchangeClient>,
+        withdraw_delay_batches: u32,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            client,
+            token_id: TokenId::reference().0,
+            withdraw_delay_batches,
+            dry_run,
+        }
+    }
+
+    /// Manages the funds of the specified token instead of the fee token.
+    pub fn with_token_id(mut self, token_id: u16) -> Self {
+        self.token_id = token_id;
+        self
+    }
+
+    /// Retrieves the exchange balances of the managed token.
+    pub async fn status(&self) -> Result<FeeFundsStatus> {
+        let token = self.client.token_address(self.token_id).await?;
+        let (current_batch_id, balance, pending_withdraw) = futures::try_join!(
+            self.client.current_batch_id(),
+            self.client.balance(token),
+            self.client.pending_withdraw(token),
+        )?;
+        Ok(FeeFundsStatus {
+            token,
+            current_batch_id,
+            balance,
+            pending_withdraw,
+        })
+    }
+
+    /// Claims a matured withdrawal or, if no withdrawal is pending, requests
+    /// the withdrawal of the token balance.
+    pub async fn manage(&self) -> Result<FeeFundsAction> {
+        let status = self.status().await?;
+        let action = if status.has_matured_withdraw() {
+            FeeFundsAction::Withdraw {
+                amount: status.pending_withdraw.amount,
+            }
+        } else if status.pending_withdraw.amount.is_zero() && !status.balance.is_zero() {
+            FeeFundsAction::RequestWithdraw {
+                amount: status.balance,
+                batch_id: status.current_batch_id + self.withdraw_delay_batches,
+            }
+        } else {
+            FeeFundsAction::None
+        };
+
+        if self.dry_run {
+            log::info!("dry run, not executing fee funds action {:?}", action);
+            return Ok(action);
+        }
+        match action {
+            FeeFundsAction::None => (),
+            FeeFundsAction::RequestWithdraw { amount, batch_id } => {
+                let hash = self
+                    .client
+                    .request_future_withdraw(status.token, amount, batch_id)
+                    .await?;
+                log::info!(
+                    "requested withdrawal of {} of token {} for batch {} in transaction {:?}",
+                    amount,
+                    self.token_id,
+                    batch_id,
+                    hash
+                );
+            }
+            FeeFundsAction::Withdraw { amount } => {
+                let hash = self.client.withdraw(status.token).await?;
+                log::info!(
+                    "withdrew {} of token {} in transaction {:?}",
+                    amount,
+                    self.token_id,
+                    hash
+                );
+            }
+        }
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::exchange_client::MockExchangeClient;
+    use ethcontract::H256;
+    use futures::FutureExt as _;
+    use mockall::predicate::eq;
+
+    fn client(balance: u64, pending_withdraw: PendingWithdraw) -> MockExchangeClient {
+        let mut client = MockExchangeClient::new();
+        client
+            .expect_token_address()
+            .returning(|token_id| Ok(Address::from_low_u64_be(token_id as u64 + 1)));
+        client.expect_current_batch_id().returning(|| Ok(10));
+        client
+            .expect_balance()
+            .returning(move |_| Ok(balance.into()));
+        client
+            .expect_pending_withdraw()
+            .returning(move |_| Ok(pending_withdraw));
+        client
+    }
+
+    #[test]
+    fn requests_withdraw_of_balance() {
+        let mut client = client(100, PendingWithdraw::default());
+        client
+            .expect_request_future_withdraw()
+            .with(eq(Address::from_low_u64_be(1)), eq(U256::from(100)), eq(12))
+            .times(1)
+            .returning(|_, _, _| Ok(H256::zero()));
+
+        let manager = FeeFundsManager::new(Arc::new(client), 2, false);
+        assert_eq!(
+            manager.manage().now_or_never().unwrap().unwrap(),
+            FeeFundsAction::RequestWithdraw {
+                amount: 100.into(),
+                batch_id: 12
+            }
+        );
+    }
+
+    #[test]
+    fn claims_matured_withdraw() {
+        let pending_withdraw = PendingWithdraw {
+            amount: 50.into(),
+            batch_id: 9,
+        };
+        let mut client = client(100, pending_withdraw);
+        client
+            .expect_withdraw()
+            .times(1)
+            .returning(|_| Ok(H256::zero()));
+
+        let manager = FeeFundsManager::new(Arc::new(client), 0, false);
+        assert_eq!(
+            manager.manage().now_or_never().unwrap().unwrap(),
+            FeeFundsAction::Withdraw { amount: 50.into() }
+        );
+    }
+
+    #[test]
+    fn waits_for_pending_withdraw() {
+        let pending_withdraw = PendingWithdraw {
+            amount: 50.into(),
+            batch_id: 10,
+        };
+        let manager = FeeFundsManager::new(Arc::new(client(100, pending_withdraw)), 0, false);
+        assert_eq!(
+            manager.manage().now_or_never().unwrap().unwrap(),
+            FeeFundsAction::None
+        );
+    }
+
+    #[test]
+    fn withdraws_other_tokens() {
+        let mut client = client(100, PendingWithdraw::default());
+        client
+            .expect_request_future_withdraw()
+            .with(eq(Address::from_low_u64_be(3)), eq(U256::from(100)), eq(10))
+            .times(1)
+            .returning(|_, _, _| Ok(H256::zero()));
+
+        let manager = FeeFundsManager::new(Arc::new(client), 0, false).with_token_id(2);
+        manager.manage().now_or_never().unwrap().unwrap();
+    }
+
+    #[test]
+    fn dry_run_does_not_send_transactions() {
+        let manager =
+            FeeFundsManager::new(Arc::new(client(100, PendingWithdraw::default())), 0, true);
+        assert_eq!(
+            manager.manage().now_or_never().unwrap().unwrap(),
+            FeeFundsAction::RequestWithdraw {
+                amount: 100.into(),
+                batch_id: 10
+            }
+        );
+    }
+}
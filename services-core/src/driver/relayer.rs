@@ -0,0 +1,524 @@
+//! Module implementing an opt-in relayer that claims matured withdrawals on
+//! behalf of users, so that users without ether can still get their funds out
+//! of the exchange.
+//!
+//! Only `withdraw` can be relayed since the exchange transfers the funds to the
+//! user regardless of who sends the transaction. `requestWithdraw` is
+//! authorized by the sender of the transaction and the exchange has no entry
+//! point for signed meta-transactions, so requests to relay it are rejected.
+
+use crate::{
+    contracts::exchange_client::ExchangeClient,
+    gas_price::GasPriceEstimating,
+    http_server::Handler,
+    solution_submission::{gas_price_stream, is_transaction_error, ReplacementPolicy},
+    util::{AsyncSleep, AsyncSleeping, FutureWaitExt as _},
+};
+use anyhow::{anyhow, Error, Result};
+use ethcontract::{errors::ExecutionError, Address, H256, U256};
+use futures::{future::FutureExt as _, lock::Mutex as AsyncMutex};
+use rouille::{router, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use transaction_retry::{RetryResult, TransactionResult, TransactionSending};
+
+/// The gas limit that the gas price of relayed withdrawals is estimated for.
+const WITHDRAW_GAS_LIMIT: f64 = 100_000.0;
+/// The time in which relayed withdrawals should be mined. The gas price is
+/// increased while the transaction is pending, and it is cancelled if it was
+/// not mined by then.
+const TARGET_CONFIRM_TIME: Duration = Duration::from_secs(120);
+
+/// The reasons for not relaying a request, which are reported to the user.
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum RelayError {
+    #[error("requestWithdraw is authorized by the transaction sender and cannot be relayed")]
+    NotRelayable,
+    #[error("the user has no matured withdrawal of the token")]
+    NoMaturedWithdraw,
+    #[error("a transaction was already relayed for the user in the last {0} seconds")]
+    RateLimited(u64),
+}
+
+impl RelayError {
+    fn status_code(&self) -> u16 {
+        match self {
+            RelayError::NotRelayable | RelayError::NoMaturedWithdraw => 422,
+            RelayError::RateLimited(_) => 429,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawRequest {
+    user: Address,
+    token: Address,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RelayedTransaction {
+    transaction_hash: H256,
+}
+
+/// Relays withdrawals from the account of the exchange client with the gas
+/// price of the driver's gas estimators, which is increased until the
+/// transaction is mined. Every user can have at most one transaction relayed
+/// per rate limit interval.
+///
+/// The relayer is meant to be served on its own port, so that it can be
+/// exposed publicly without exposing the monitoring endpoints.
+pub struct Relayer {
+    client: Arc<dyn ExchangeClient>,
+    gas_station: Arc<dyn GasPriceEstimating>,
+    max_gas_price: f64,
+    rate_limit: Duration,
+    async_sleep: Box<dyn AsyncSleeping>,
+    last_relayed: Mutex<HashMap<Address, Instant>>,
+    /// Held while a withdrawal is relayed, from reading its nonce until it is
+    /// mined or cancelled, so that concurrent relays for different users do
+    /// not share a nonce and replace each other.
+    nonce_lock: AsyncMutex<()>,
+}
+
+impl Relayer {
+    pub fn new(
+        client: Arc<dyn ExchangeClient>,
+        gas_station: Arc<dyn GasPriceEstimating>,
+        max_gas_price: f64,
+        rate_limit: Duration,
+    ) -> Self {
+        Self::with_sleep(client, gas_station, max_gas_price, rate_limit, AsyncSleep)
+    }
+
+    fn with_sleep(
+        client: Arc<dyn ExchangeClient>,
+        gas_station: Arc<dyn GasPriceEstimating>,
+        max_gas_price: f64,
+        rate_limit: Duration,
+        async_sleep: impl AsyncSleeping,
+    ) -> Self {
+        Self {
+            client,
+            gas_station,
+            max_gas_price,
+            rate_limit,
+            async_sleep: Box::new(async_sleep),
+            last_relayed: Mutex::new(HashMap::new()),
+            nonce_lock: AsyncMutex::new(()),
+        }
+    }
+
+    /// Claims the matured withdrawal of a token for the user and returns the
+    /// hash of the mined transaction. The user can request the withdrawal to
+    /// be relayed again right away if it could not be relayed.
+    pub async fn relay_withdraw(&self, user: Address, token: Address) -> Result<H256> {
+        let (current_batch_id, pending_withdraw) = futures::try_join!(
+            self.client.current_batch_id(),
+            self.client.pending_withdraw_of(user, token),
+        )?;
+        if !pending_withdraw.has_matured(current_batch_id) {
+            return Err(RelayError::NoMaturedWithdraw.into());
+        }
+        let now = Instant::now();
+        self.reserve(user, now)?;
+
+        let transaction_hash = match self.send_withdraw(user, token).await {
+            Ok(transaction_hash) => transaction_hash,
+            Err(err) => {
+                self.release(user, now);
+                return Err(err);
+            }
+        };
+        log::info!(
+            "relayed withdrawal of {} of token {:?} for user {:?} in transaction {:?}",
+            pending_withdraw.amount,
+            token,
+            user,
+            transaction_hash,
+        );
+        Ok(transaction_hash)
+    }
+
+    /// Sends the withdrawal with an increasing gas price until it is mined, or
+    /// cancels it once the target confirmation time has passed.
+    async fn send_withdraw(&self, user: Address, token: Address) -> Result<H256> {
+        let _nonce_guard = self.nonce_lock.lock().await;
+        let nonce = self.client.transaction_count().await?;
+        let withdraw_sender = WithdrawSender {
+            client: self.client.as_ref(),
+            user,
+            token,
+            nonce,
+        };
+        let cancellation_sender = CancellationSender {
+            client: self.client.as_ref(),
+            nonce,
+        };
+        let cancel_future = async {
+            self.async_sleep.sleep(TARGET_CONFIRM_TIME).await;
+            cancellation_sender
+        };
+        let stream = gas_price_stream::gas_price_stream(
+            Instant::now() + TARGET_CONFIRM_TIME,
+            WITHDRAW_GAS_LIMIT,
+            self.max_gas_price,
+            ReplacementPolicy::default(),
+            self.gas_station.as_ref(),
+            self.async_sleep.as_ref(),
+        );
+
+        match transaction_retry::retry(withdraw_sender, cancel_future.boxed(), stream).await {
+            Some(RetryResult::Submitted(result)) => result
+                .0
+                .map_err(|err| Error::from(err).context("failed to relay withdrawal")),
+            Some(RetryResult::Cancelled(result)) => Err(match result.0 {
+                Ok(()) => anyhow!("relayed withdrawal was not mined in time and was cancelled"),
+                Err(err) => Error::from(err).context("failed to cancel relayed withdrawal"),
+            }),
+            None => Err(anyhow!("relayed withdrawal was never sent")),
+        }
+    }
+
+    /// Records a relayed transaction for the user unless one was already
+    /// relayed within the rate limit interval.
+    fn reserve(&self, user: Address, now: Instant) -> Result<(), RelayError> {
+        let mut last_relayed = self.last_relayed.lock().unwrap();
+        last_relayed.retain(|_, relayed| now.duration_since(*relayed) < self.rate_limit);
+        if last_relayed.contains_key(&user) {
+            return Err(RelayError::RateLimited(self.rate_limit.as_secs()));
+        }
+        last_relayed.insert(user, now);
+        Ok(())
+    }
+
+    /// Removes the reservation made at the specified time for a transaction
+    /// that could not be relayed.
+    fn release(&self, user: Address, reserved_at: Instant) {
+        let mut last_relayed = self.last_relayed.lock().unwrap();
+        if last_relayed.get(&user) == Some(&reserved_at) {
+            last_relayed.remove(&user);
+        }
+    }
+}
+
+struct WithdrawResult(Result<H256, ExecutionError>);
+impl TransactionResult for WithdrawResult {
+    fn was_mined(&self) -> bool {
+        match &self.0 {
+            Ok(_) => true,
+            Err(err) => !is_transaction_error(err),
+        }
+    }
+}
+
+struct WithdrawSender<'a> {
+    client: &'a dyn ExchangeClient,
+    user: Address,
+    token: Address,
+    nonce: U256,
+}
+#[async_trait::async_trait]
+impl<'a> TransactionSending for WithdrawSender<'a> {
+    type Output = WithdrawResult;
+    async fn send(&self, gas_price: f64) -> Self::Output {
+        log::info!("relaying withdrawal transaction at gas price {}", gas_price);
+        let result = self
+            .client
+            .withdraw_for(
+                self.user,
+                self.token,
+                U256::from_f64_lossy(gas_price),
+                self.nonce,
+            )
+            .await;
+        WithdrawResult(result)
+    }
+}
+
+struct CancellationResult(Result<(), ExecutionError>);
+impl TransactionResult for CancellationResult {
+    fn was_mined(&self) -> bool {
+        match &self.0 {
+            Ok(()) => true,
+            Err(err) => !is_transaction_error(err),
+        }
+    }
+}
+
+struct CancellationSender<'a> {
+    client: &'a dyn ExchangeClient,
+    nonce: U256,
+}
+#[async_trait::async_trait]
+impl<'a> TransactionSending for CancellationSender<'a> {
+    type Output = CancellationResult;
+    async fn send(&self, gas_price: f64) -> Self::Output {
+        log::info!("cancelling relayed withdrawal at gas price {}", gas_price);
+        let result = self
+            .client
+            .send_noop_transaction(U256::from_f64_lossy(gas_price), self.nonce)
+            .await;
+        CancellationResult(result)
+    }
+}
+
+impl Handler for Relayer {
+    #[allow(clippy::manual_strip)]
+    fn handle_request(&self, request: &Request) -> Result<Response> {
+        let result = router!(request,
+            (POST) (/relayer/withdraw) => {
+                let body = match rouille::input::json_input::<WithdrawRequest>(request) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        return Ok(Response::text(format!("invalid request: {}", err))
+                            .with_status_code(400))
+                    }
+                };
+                self.relay_withdraw(body.user, body.token).wait()
+            },
+            (POST) (/relayer/request-withdraw) => { Err(RelayError::NotRelayable.into()) },
+            _ => return Ok(Response::empty_404()),
+        );
+
+        match result {
+            Ok(transaction_hash) => Ok(Response::json(&RelayedTransaction { transaction_hash })),
+            Err(err) => match err.downcast_ref::<RelayError>() {
+                Some(err) => {
+                    Ok(Response::text(err.to_string()).with_status_code(err.status_code()))
+                }
+                None => Err(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        contracts::exchange_client::{MockExchangeClient, PendingWithdraw},
+        gas_price::MockGasPriceEstimating,
+        util::MockAsyncSleeping,
+    };
+    use futures::{future, FutureExt as _};
+    use mockall::predicate::{always, eq};
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
+    };
+
+    fn client(pending_withdraw: PendingWithdraw) -> MockExchangeClient {
+        let mut client = MockExchangeClient::new();
+        client.expect_current_batch_id().returning(|| Ok(10));
+        client
+            .expect_pending_withdraw_of()
+            .returning(move |_, _| Ok(pending_withdraw));
+        client
+            .expect_transaction_count()
+            .returning(|| Ok(U256::from(7)));
+        client
+    }
+
+    fn relayer(client: MockExchangeClient) -> Relayer {
+        let mut gas_station = MockGasPriceEstimating::new();
+        gas_station
+            .expect_estimate_with_limits()
+            .returning(|_, _| Ok(20e9));
+        let mut sleep = MockAsyncSleeping::new();
+        sleep
+            .expect_sleep()
+            .returning(|_| future::pending().boxed());
+        Relayer::with_sleep(
+            Arc::new(client),
+            Arc::new(gas_station),
+            100e9,
+            Duration::from_secs(60),
+            sleep,
+        )
+    }
+
+    #[test]
+    fn relays_matured_withdrawals_once_per_interval() {
+        let user = Address::from_low_u64_be(1);
+        let token = Address::from_low_u64_be(2);
+        let mut client = client(PendingWithdraw {
+            amount: 50.into(),
+            batch_id: 9,
+        });
+        client
+            .expect_withdraw_for()
+            .with(
+                eq(user),
+                eq(token),
+                eq(U256::from(20_000_000_000u64)),
+                eq(U256::from(7)),
+            )
+            .times(1)
+            .returning(|_, _, _, _| Ok(H256::from_low_u64_be(3)));
+
+        let relayer = relayer(client);
+        assert_eq!(
+            relayer
+                .relay_withdraw(user, token)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            H256::from_low_u64_be(3)
+        );
+        let err = relayer
+            .relay_withdraw(user, token)
+            .now_or_never()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RelayError>(),
+            Some(&RelayError::RateLimited(60))
+        );
+    }
+
+    #[test]
+    fn releases_reservation_of_failed_withdrawals() {
+        let user = Address::from_low_u64_be(1);
+        let token = Address::from_low_u64_be(2);
+        let mut client = client(PendingWithdraw {
+            amount: 50.into(),
+            batch_id: 9,
+        });
+        let mut sequence = mockall::Sequence::new();
+        client
+            .expect_withdraw_for()
+            .with(eq(user), eq(token), always(), always())
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _, _| Err(ExecutionError::Revert(None)));
+        client
+            .expect_withdraw_for()
+            .with(eq(user), eq(token), always(), always())
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _, _| Ok(H256::from_low_u64_be(3)));
+
+        let relayer = relayer(client);
+        assert!(relayer
+            .relay_withdraw(user, token)
+            .now_or_never()
+            .unwrap()
+            .is_err());
+        assert_eq!(
+            relayer
+                .relay_withdraw(user, token)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            H256::from_low_u64_be(3)
+        );
+    }
+
+    #[test]
+    fn concurrent_relays_use_distinct_nonces() {
+        let transaction_count = Arc::new(AtomicU64::new(7));
+        let nonces = Arc::new(Mutex::new(Vec::new()));
+        let mut client = MockExchangeClient::new();
+        client.expect_current_batch_id().returning(|| Ok(10));
+        client.expect_pending_withdraw_of().returning(|_, _| {
+            Ok(PendingWithdraw {
+                amount: 50.into(),
+                batch_id: 9,
+            })
+        });
+        client.expect_transaction_count().returning({
+            let transaction_count = transaction_count.clone();
+            move || Ok(transaction_count.load(Ordering::SeqCst).into())
+        });
+        client.expect_withdraw_for().returning({
+            let nonces = nonces.clone();
+            move |_, _, _, nonce| {
+                nonces.lock().unwrap().push(nonce);
+                // Give the other relay time to read the transaction count
+                // before this one is mined.
+                thread::sleep(Duration::from_millis(50));
+                transaction_count.fetch_add(1, Ordering::SeqCst);
+                Ok(H256::from_low_u64_be(nonce.as_u64()))
+            }
+        });
+
+        let relayer = Arc::new(relayer(client));
+        let relays = (1..=2)
+            .map(|user| {
+                let relayer = relayer.clone();
+                thread::spawn(move || {
+                    relayer
+                        .relay_withdraw(Address::from_low_u64_be(user), Address::zero())
+                        .wait()
+                })
+            })
+            .collect::<Vec<_>>();
+        for relay in relays {
+            assert!(relay.join().unwrap().is_ok());
+        }
+
+        let mut nonces = nonces.lock().unwrap().clone();
+        nonces.sort();
+        assert_eq!(nonces, vec![U256::from(7), U256::from(8)]);
+    }
+
+    #[test]
+    fn does_not_relay_pending_withdrawals() {
+        let client = client(PendingWithdraw {
+            amount: 50.into(),
+            batch_id: 10,
+        });
+        let err = relayer(client)
+            .relay_withdraw(Address::zero(), Address::zero())
+            .now_or_never()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RelayError>(),
+            Some(&RelayError::NoMaturedWithdraw)
+        );
+    }
+
+    #[test]
+    fn rate_limit_expires() {
+        let relayer = relayer(MockExchangeClient::new());
+        let user = Address::from_low_u64_be(1);
+        let now = Instant::now();
+        assert_eq!(relayer.reserve(user, now), Ok(()));
+        assert_eq!(
+            relayer.reserve(user, now + Duration::from_secs(30)),
+            Err(RelayError::RateLimited(60))
+        );
+        assert_eq!(
+            relayer.reserve(Address::from_low_u64_be(2), now + Duration::from_secs(30)),
+            Ok(())
+        );
+        assert_eq!(relayer.reserve(user, now + Duration::from_secs(60)), Ok(()));
+    }
+
+    #[test]
+    fn rejects_withdraw_requests_and_unknown_urls() {
+        let relayer = relayer(MockExchangeClient::new());
+        let response = relayer
+            .handle_request(&Request::fake_http(
+                "POST",
+                "/relayer/request-withdraw",
+                vec![],
+                vec![],
+            ))
+            .unwrap();
+        assert_eq!(response.status_code, 422);
+
+        let response = relayer
+            .handle_request(&Request::fake_http("GET", "/metrics", vec![], vec![]))
+            .unwrap();
+        assert_eq!(response.status_code, 404);
+    }
+}
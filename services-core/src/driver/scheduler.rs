@@ -3,8 +3,11 @@ mod system;
 
 use self::{evm::EvmScheduler, system::SystemScheduler};
 use crate::{
-    contracts::stablex_contract::StableXContract, driver::stablex_driver::StableXDriver,
-    health::HealthReporting, models::batch_id::SOLVING_WINDOW,
+    contracts::stablex_contract::StableXContract,
+    driver::{alerting::Alerting, stablex_driver::StableXDriver},
+    health::HealthReporting,
+    models::batch_id::BatchTiming,
+    util::Now,
 };
 use std::{sync::Arc, time::Duration};
 
@@ -16,6 +19,9 @@ pub trait Scheduler {
 
 #[derive(Clone, Copy, Debug)]
 pub struct AuctionTimingConfiguration {
+    /// The timing of the batches of the exchange.
+    batch_timing: BatchTiming,
+
     /// The offset from the start of a batch at which point we should start
     /// solving.
     target_start_solve_time: Duration,
@@ -37,21 +43,22 @@ impl AuctionTimingConfiguration {
     /// Panics if the configuration is invalid. Specifically the following
     /// invariants must hold:
     /// - `target_start_solve_time < solver_time_limit`
-    /// - `solver_time_limit < SOLVING_WINDOW`
-    /// - `min_solution_submit_time < SOLVING_WINDOW`
+    /// - `solver_time_limit < solving_window`
+    /// - `min_solution_submit_time < solving_window`
     ///
-    /// Where `SOLVING_WINDOW` represents the amount of time within a batch in
-    /// which a solution is accepted. There is an amount of time at the end of a
+    /// Where `solving_window` is the amount of time within a batch of the
+    /// specified timing in which a solution is accepted. There is an amount of time at the end of a
     /// batch where solutions are no longer accepted, this is done to allow
     /// traders time to make decisions after the previous batch has already
     /// finalized.
     pub fn new(
+        batch_timing: BatchTiming,
         target_start_solve_time: Duration,
         solver_time_limit: Duration,
         min_solution_submit_time: Duration,
     ) -> Self {
         assert!(
-            solver_time_limit < SOLVING_WINDOW,
+            solver_time_limit < batch_timing.solving_window,
             "The solver time limit must be within the solving window",
         );
         assert!(
@@ -59,11 +66,12 @@ impl AuctionTimingConfiguration {
             "the target solve start time must be earlier than the solver time limit",
         );
         assert!(
-            min_solution_submit_time < SOLVING_WINDOW,
+            min_solution_submit_time < batch_timing.solving_window,
             "The min solution submit time must be within the solving window",
         );
 
         AuctionTimingConfiguration {
+            batch_timing,
             target_start_solve_time,
             latest_solution_submit_time: solver_time_limit,
             earliest_solution_submit_time: min_solution_submit_time,
@@ -74,6 +82,7 @@ impl AuctionTimingConfiguration {
 impl Default for AuctionTimingConfiguration {
     fn default() -> Self {
         AuctionTimingConfiguration::new(
+            BatchTiming::default(),
             Duration::from_secs(30),
             Duration::from_secs(180),
             Duration::from_secs(0),
@@ -85,7 +94,8 @@ arg_enum! {
     /// The different kinds of schedulers.
     #[derive(Debug)]
     pub enum SchedulerKind {
-        /// A system based scheduler that uses system time to run the driver.
+        /// A system based scheduler that uses system time, corrected by the
+        /// batch clock, to run the driver.
         System,
         /// An EVM based scheduler that queries block-chain state to run the driver.
         Evm,
@@ -93,19 +103,25 @@ arg_enum! {
 }
 
 impl SchedulerKind {
-    /// Creates a new scheduler based on the parameters.
+    /// Creates a new scheduler based on the parameters. The alert rules are
+    /// evaluated after every batch if alerting is configured.
     pub fn create(
         &self,
         exchange: Arc<dyn StableXContract>,
         driver: Arc<dyn StableXDriver>,
         config: AuctionTimingConfiguration,
         health: Arc<dyn HealthReporting>,
+        clock: Arc<dyn Now>,
+        alerting: Option<Arc<Alerting>>,
     ) -> Box<dyn Scheduler> {
         match self {
-            SchedulerKind::System => {
-                Box::new(SystemScheduler::new(exchange, driver, health, config))
-            }
-            SchedulerKind::Evm => Box::new(EvmScheduler::new(exchange, driver, health, config)),
+            SchedulerKind::System => Box::new(
+                SystemScheduler::new(exchange, driver, health, config, clock)
+                    .with_alerting(alerting),
+            ),
+            SchedulerKind::Evm => Box::new(
+                EvmScheduler::new(exchange, driver, health, config).with_alerting(alerting),
+            ),
         }
     }
 }
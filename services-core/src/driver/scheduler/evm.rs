@@ -3,10 +3,13 @@
 
 use super::{AuctionTimingConfiguration, Scheduler};
 use crate::{
-    contracts::stablex_contract::StableXContract,
-    driver::stablex_driver::{DriverError, StableXDriver},
+    contracts::stablex_contract::{self, StableXContract},
+    driver::{
+        alerting::Alerting,
+        stablex_driver::{DriverError, StableXDriver},
+    },
+    error::ErrorCode,
     health::HealthReporting,
-    models::batch_id::BATCH_DURATION,
     models::Solution,
     util::{AsyncSleep, AsyncSleeping, FutureWaitExt as _},
 };
@@ -14,7 +17,8 @@ use anyhow::Result;
 use log::{error, info, warn};
 use std::{sync::Arc, thread, time::Duration};
 
-/// The amount of time the scheduler should wait between polling.
+/// The amount of time the scheduler should wait between polling while waiting
+/// for the earliest solution submit time.
 const POLL_TIMEOUT: Duration = Duration::from_secs(5);
 /// The amount of time to wait between retries after errors.
 const RETRY_SLEEP_DURATION: Duration = Duration::from_secs(5);
@@ -26,6 +30,7 @@ pub struct EvmScheduler {
     config: AuctionTimingConfiguration,
     sleep: Box<dyn AsyncSleeping>,
     health: Arc<dyn HealthReporting>,
+    alerting: Option<Arc<Alerting>>,
 }
 
 impl EvmScheduler {
@@ -42,9 +47,16 @@ impl EvmScheduler {
             config,
             sleep: Box::new(AsyncSleep),
             health,
+            alerting: None,
         }
     }
 
+    /// Evaluates the alert rules after every batch.
+    pub fn with_alerting(mut self, alerting: Option<Arc<Alerting>>) -> Self {
+        self.alerting = alerting;
+        self
+    }
+
     /// Creates a new scheduler with the default configuration.
     #[cfg(test)]
     pub fn with_defaults_and_sleep(
@@ -59,6 +71,7 @@ impl EvmScheduler {
             config: AuctionTimingConfiguration::default(),
             sleep,
             health,
+            alerting: None,
         }
     }
 
@@ -71,13 +84,14 @@ impl EvmScheduler {
     }
 
     async fn wait_for_batch_to_change(&self, batch: u32) -> Result<u32> {
-        loop {
-            let current_batch = self.current_solving_batch().await?;
-            if current_batch != batch {
-                return Ok(current_batch);
-            }
-            self.sleep.sleep(POLL_TIMEOUT).await;
-        }
+        // The batch accepting orders is always one ahead of the solving batch.
+        let current_batch = stablex_contract::wait_for_batch(
+            self.exchange.as_ref(),
+            self.sleep.as_ref(),
+            batch + 2,
+        )
+        .await?;
+        Ok(current_batch - 1)
     }
 
     async fn batch_time(&self, batch_id: u32) -> Result<Option<Duration>> {
@@ -87,7 +101,10 @@ impl EvmScheduler {
         if self.current_solving_batch().await? != batch_id {
             return Ok(None);
         }
-        let batch_time = BATCH_DURATION
+        let batch_time = self
+            .config
+            .batch_timing
+            .batch_duration
             .checked_sub(time_remaining)
             .expect("time remaining greater than batch duration");
         Ok(Some(batch_time))
@@ -116,10 +133,20 @@ impl EvmScheduler {
                     return Ok(Some(solution));
                 }
                 Err(DriverError::Retry(err)) => {
-                    error!("driver retryable error for batch {}: {:?}", batch_id, err);
+                    error!(
+                        "driver retryable error for batch {} ({}): {:?}",
+                        batch_id,
+                        ErrorCode::of(&err, ErrorCode::OrderbookFetch),
+                        err
+                    );
                 }
                 Err(DriverError::Skip(err)) => {
-                    error!("driver error for batch {}: {:?}", batch_id, err);
+                    error!(
+                        "driver error for batch {} ({}): {:?}",
+                        batch_id,
+                        ErrorCode::of(&err, ErrorCode::SolverFailed),
+                        err
+                    );
                     return Ok(None);
                 }
             }
@@ -142,8 +169,10 @@ impl EvmScheduler {
         match self.driver.submit_solution(batch_id.into(), solution).await {
             Ok(()) => info!("successfully completed batch {}", batch_id),
             Err(err) => error!(
-                "failed to submit solution for batch {}: {:?}",
-                batch_id, err
+                "failed to submit solution for batch {} ({}): {:?}",
+                batch_id,
+                ErrorCode::of(&err, ErrorCode::SubmissionFailed),
+                err
             ),
         }
         Ok(())
@@ -157,11 +186,17 @@ impl EvmScheduler {
         };
         let new_batch = self.wait_for_batch_to_change(last_batch).await?;
         self.health.notify_ready();
-        let solution = self.solve(new_batch).await?;
-        if let Some(solution) = solution {
-            self.submit(new_batch, solution).await?;
+        let result: Result<u32> = async {
+            if let Some(solution) = self.solve(new_batch).await? {
+                self.submit(new_batch, solution).await?;
+            }
+            Ok(new_batch)
         }
-        Ok(new_batch)
+        .await;
+        if let Some(alerting) = &self.alerting {
+            alerting.batch_processed(new_batch.into());
+        }
+        result
     }
 }
 
@@ -243,6 +278,9 @@ mod tests {
     #[test]
     fn scheduler_only_runs_next_batch_after_previous_has_finished() {
         let mut exchange = MockStableXContract::new();
+        exchange
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
         exchange
             .expect_get_current_auction_index()
             .times(2)
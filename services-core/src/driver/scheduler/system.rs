@@ -1,10 +1,14 @@
 use super::{AuctionTimingConfiguration, Scheduler};
 use crate::{
-    contracts::stablex_contract::StableXContract,
-    driver::stablex_driver::{DriverError, StableXDriver},
+    contracts::stablex_contract::{self, StableXContract},
+    driver::{
+        alerting::Alerting,
+        stablex_driver::{DriverError, StableXDriver},
+    },
+    error::ErrorCode,
     health::HealthReporting,
-    models::{BatchId, Solution},
-    util::{self, AsyncSleep, AsyncSleeping, Now},
+    models::{batch_id::BatchTiming, BatchId, Solution},
+    util::{AsyncSleep, AsyncSleeping, Now},
 };
 use anyhow::{Context, Result};
 use std::{
@@ -14,14 +18,15 @@ use std::{
 };
 
 const RETRY_SLEEP_DURATION: Duration = Duration::from_secs(1);
-const CONTRACT_BATCH_ID_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct SystemScheduler {
     contract: Arc<dyn StableXContract>,
     driver: Arc<dyn StableXDriver>,
     health: Arc<dyn HealthReporting>,
     auction_timing_configuration: AuctionTimingConfiguration,
+    now: Arc<dyn Now>,
     last_solved_batch: Option<BatchId>,
+    alerting: Option<Arc<Alerting>>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -36,46 +41,64 @@ impl SystemScheduler {
         driver: Arc<dyn StableXDriver>,
         health: Arc<dyn HealthReporting>,
         auction_timing_configuration: AuctionTimingConfiguration,
+        now: Arc<dyn Now>,
     ) -> Self {
         Self {
             contract,
             driver,
             health,
             auction_timing_configuration,
+            now,
             last_solved_batch: None,
+            alerting: None,
         }
     }
 
+    /// Evaluates the alert rules after every batch.
+    pub fn with_alerting(mut self, alerting: Option<Arc<Alerting>>) -> Self {
+        self.alerting = alerting;
+        self
+    }
+
     fn start_solving_in_background(&self, batch_id: BatchId, solver_deadline: Instant) {
         let driver = self.driver.clone();
         let contract = self.contract.clone();
+        let now = self.now.clone();
+        let alerting = self.alerting.clone();
+        let batch_timing = self.auction_timing_configuration.batch_timing;
         let earliest_solution_submit_time = self
             .auction_timing_configuration
             .earliest_solution_submit_time;
         async_std::task::spawn(async move {
             solve_and_submit(
                 batch_id,
+                batch_timing,
                 solver_deadline,
                 earliest_solution_submit_time,
                 driver.as_ref(),
                 contract.as_ref(),
-                &util::default_now(),
+                now.as_ref(),
                 &AsyncSleep {},
             )
             .await;
+            if let Some(alerting) = alerting {
+                alerting.batch_processed(batch_id);
+            }
         });
     }
 
     /// Return current batch id and what to do with it.
     fn determine_action(&self, now: SystemTime) -> Result<Action> {
-        let solving_batch = BatchId::currently_being_solved(now)
+        let batch_timing = &self.auction_timing_configuration.batch_timing;
+        let solving_batch = batch_timing
+            .batch_being_solved(now)
             .context("failed to get batch id currently being solved")?;
-        let intended_solve_start_time = solving_batch.solve_start_time()
+        let intended_solve_start_time = batch_timing.solve_start_time(solving_batch)
             + self.auction_timing_configuration.target_start_solve_time;
         // unwrap here because this cannot fail because the `solving_batch`'s
         // start time is always before `now`.
         let elapsed_time = now
-            .duration_since(solving_batch.solve_start_time())
+            .duration_since(batch_timing.solve_start_time(solving_batch))
             .unwrap();
 
         let action = if self.last_solved_batch == Some(solving_batch)
@@ -85,7 +108,7 @@ impl SystemScheduler {
                     .latest_solution_submit_time
         {
             let next = solving_batch.next();
-            let duration = (next.solve_start_time()
+            let duration = (batch_timing.solve_start_time(next)
                 + self.auction_timing_configuration.target_start_solve_time)
                 .duration_since(now)
                 .unwrap();
@@ -107,6 +130,7 @@ impl SystemScheduler {
 
 async fn solve_and_submit(
     batch_id: BatchId,
+    batch_timing: BatchTiming,
     solver_deadline: Instant,
     earliest_solution_submit_time: Duration,
     driver: &(dyn StableXDriver),
@@ -124,6 +148,7 @@ async fn solve_and_submit(
                 }
                 return submit(
                     batch_id,
+                    batch_timing,
                     earliest_solution_submit_time,
                     solution,
                     driver,
@@ -145,24 +170,26 @@ async fn wait_for_batch_id(
     contract: &dyn StableXContract,
     sleep: &dyn AsyncSleeping,
 ) -> Result<()> {
-    // NOTE: Compare with `>=` as the exchange's current batch index is the
-    //   one accepting orders and does not yet accept solutions.
-    while batch_id.0 as u32 >= contract.get_current_auction_index().await? {
+    // NOTE: The exchange's current batch index is the one accepting orders
+    //   and does not yet accept solutions.
+    let accepting_solutions = batch_id.0 as u32 + 1;
+    if contract.get_current_auction_index().await? < accepting_solutions {
         log::info!("Solved batch is not yet accepting solutions, waiting for next batch.");
-        sleep.sleep(CONTRACT_BATCH_ID_POLL_INTERVAL).await;
+        stablex_contract::wait_for_batch(contract, sleep, accepting_solutions).await?;
     }
     Ok(())
 }
 
 async fn submit(
     batch_id: BatchId,
+    batch_timing: BatchTiming,
     earliest_solution_submit_time: Duration,
     solution: Solution,
     driver: &(dyn StableXDriver),
     now: &dyn Now,
     sleep: &dyn AsyncSleeping,
 ) {
-    if let Ok(duration) = (batch_id.solve_start_time() + earliest_solution_submit_time)
+    if let Ok(duration) = (batch_timing.solve_start_time(batch_id) + earliest_solution_submit_time)
         .duration_since(now.system_now())
     {
         log::info!(
@@ -178,12 +205,16 @@ async fn submit(
 fn log_solve_result(batch_id: BatchId, driver_result: &Result<Solution, DriverError>) {
     match driver_result {
         Ok(_) => log::info!("Batch {} solved successfully.", batch_id),
-        Err(DriverError::Retry(err)) => {
-            log::error!("Batch {} failed with retryable error: {:?}", batch_id, err)
-        }
+        Err(DriverError::Retry(err)) => log::error!(
+            "Batch {} failed with retryable error ({}): {:?}",
+            batch_id,
+            ErrorCode::of(err, ErrorCode::OrderbookFetch),
+            err
+        ),
         Err(DriverError::Skip(err)) => log::error!(
-            "Batch {} failed with unretryable error: {:?}",
+            "Batch {} failed with unretryable error ({}): {:?}",
             batch_id,
+            ErrorCode::of(err, ErrorCode::SolverFailed),
             err
         ),
     }
@@ -192,25 +223,30 @@ fn log_solve_result(batch_id: BatchId, driver_result: &Result<Solution, DriverEr
 fn log_submit_result(batch_id: BatchId, result: &Result<()>) {
     match result {
         Ok(_) => log::info!("Batch {} solution submitted successfully.", batch_id),
-        Err(err) => log::error!("Batch {} solution submission failed: {:?}", batch_id, err),
+        Err(err) => log::error!(
+            "Batch {} solution submission failed ({}): {:?}",
+            batch_id,
+            ErrorCode::of(err, ErrorCode::SubmissionFailed),
+            err
+        ),
     }
 }
 
-fn duration_until_healthy(now: SystemTime) -> Duration {
+fn duration_until_healthy(now: SystemTime, batch_timing: &BatchTiming) -> Duration {
     // We don't use the target_start_solve_time as an extra safety buffer in case the time between
     // this pod and the other one is out of sync.
-    let next_start_solve_time = BatchId::current(now).unwrap().solve_start_time();
-    next_start_solve_time
-        .duration_since(now)
-        .unwrap_or_default()
+    batch_timing.time_until_next_batch(now).unwrap()
 }
 
 impl Scheduler for SystemScheduler {
     fn start(&mut self) -> ! {
-        thread::sleep(duration_until_healthy(SystemTime::now()));
+        thread::sleep(duration_until_healthy(
+            self.now.system_now(),
+            &self.auction_timing_configuration.batch_timing,
+        ));
         self.health.notify_ready();
         loop {
-            match self.determine_action(SystemTime::now()) {
+            match self.determine_action(self.now.system_now()) {
                 Ok(Action::Sleep(duration)) => {
                     log::info!("Sleeping {}s.", duration.as_secs());
                     thread::sleep(duration);
@@ -236,7 +272,7 @@ mod tests {
         contracts::stablex_contract::MockStableXContract,
         driver::stablex_driver::MockStableXDriver,
         health::MockHealthReporting,
-        util::{MockAsyncSleeping, MockNow},
+        util::{self, MockAsyncSleeping, MockNow},
     };
     use anyhow::anyhow;
     use futures::future::FutureExt as _;
@@ -247,13 +283,19 @@ mod tests {
         let driver = Arc::new(MockStableXDriver::new());
         let contract = Arc::new(MockStableXContract::new());
         let auction_timing_configuration = AuctionTimingConfiguration {
+            batch_timing: BatchTiming::default(),
             target_start_solve_time: Duration::from_secs(10),
             latest_solution_submit_time: Duration::from_secs(20),
             earliest_solution_submit_time: Duration::from_secs(0),
         };
         let health = Arc::new(MockHealthReporting::new());
-        let scheduler =
-            SystemScheduler::new(contract, driver, health, auction_timing_configuration);
+        let scheduler = SystemScheduler::new(
+            contract,
+            driver,
+            health,
+            auction_timing_configuration,
+            Arc::new(util::default_now()),
+        );
 
         let base_time = SystemTime::UNIX_EPOCH + Duration::from_secs(300);
 
@@ -305,13 +347,19 @@ mod tests {
         let driver = Arc::new(MockStableXDriver::new());
         let contract = Arc::new(MockStableXContract::new());
         let auction_timing_configuration = AuctionTimingConfiguration {
+            batch_timing: BatchTiming::default(),
             target_start_solve_time: Duration::from_secs(10),
             latest_solution_submit_time: Duration::from_secs(20),
             earliest_solution_submit_time: Duration::from_secs(0),
         };
         let health = Arc::new(MockHealthReporting::new());
-        let mut scheduler =
-            SystemScheduler::new(contract, driver, health, auction_timing_configuration);
+        let mut scheduler = SystemScheduler::new(
+            contract,
+            driver,
+            health,
+            auction_timing_configuration,
+            Arc::new(util::default_now()),
+        );
         scheduler.last_solved_batch = Some(BatchId(0));
 
         let base_time = SystemTime::UNIX_EPOCH + Duration::from_secs(300);
@@ -385,6 +433,7 @@ mod tests {
 
         assert!(solve_and_submit(
             BatchId(0),
+            BatchTiming::default(),
             *EPOCH + Duration::from_secs(5),
             Duration::from_secs(0),
             &driver,
@@ -431,6 +480,12 @@ mod tests {
             .times(1)
             .in_sequence(&mut sequence)
             .returning(|| Ok(1));
+        contract
+            .expect_get_current_auction_remaining_time()
+            .returning(|| Ok(Duration::from_secs(0)));
+        contract
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
         driver
             .expect_submit_solution()
             .times(1)
@@ -439,6 +494,7 @@ mod tests {
 
         assert!(solve_and_submit(
             BatchId(0),
+            BatchTiming::default(),
             *EPOCH + Duration::from_secs(1),
             Duration::from_secs(0),
             &driver,
@@ -475,6 +531,7 @@ mod tests {
 
         assert!(submit(
             BatchId(0),
+            BatchTiming::default(),
             Duration::from_secs(5),
             Solution::trivial(),
             &driver,
@@ -524,6 +581,7 @@ mod tests {
         });
 
         let auction_timing_configuration = AuctionTimingConfiguration {
+            batch_timing: BatchTiming::default(),
             target_start_solve_time: Duration::from_secs(10),
             latest_solution_submit_time: Duration::from_secs(20),
             earliest_solution_submit_time: Duration::from_secs(0),
@@ -534,6 +592,7 @@ mod tests {
             Arc::new(driver),
             health,
             auction_timing_configuration,
+            Arc::new(util::default_now()),
         );
 
         scheduler.start();
@@ -542,17 +601,53 @@ mod tests {
     #[test]
     fn duration_until_healthy_returns_next_start_solve_time() {
         let time = |duration| SystemTime::UNIX_EPOCH + duration;
+        let timing = BatchTiming::default();
         assert_eq!(
-            duration_until_healthy(time(Duration::from_secs(301))),
+            duration_until_healthy(time(Duration::from_secs(301)), &timing),
             Duration::from_secs(299)
         );
         assert_eq!(
-            duration_until_healthy(time(Duration::from_secs(350))),
+            duration_until_healthy(time(Duration::from_secs(350)), &timing),
             Duration::from_secs(250)
         );
         assert_eq!(
-            duration_until_healthy(time(Duration::from_secs(599))),
+            duration_until_healthy(time(Duration::from_secs(599)), &timing),
             Duration::from_secs(1)
         );
     }
+
+    #[test]
+    fn determine_action_with_shorter_batches() {
+        let auction_timing_configuration = AuctionTimingConfiguration::new(
+            BatchTiming::with_batch_duration(Duration::from_secs(60)),
+            Duration::from_secs(5),
+            Duration::from_secs(30),
+            Duration::from_secs(0),
+        );
+        let scheduler = SystemScheduler::new(
+            Arc::new(MockStableXContract::new()),
+            Arc::new(MockStableXDriver::new()),
+            Arc::new(MockHealthReporting::new()),
+            auction_timing_configuration,
+            Arc::new(util::default_now()),
+        );
+
+        let base_time = SystemTime::UNIX_EPOCH + Duration::from_secs(120);
+        assert_eq!(
+            scheduler.determine_action(base_time).unwrap(),
+            Action::Sleep(Duration::from_secs(5))
+        );
+        assert_eq!(
+            scheduler
+                .determine_action(base_time + Duration::from_secs(10))
+                .unwrap(),
+            Action::Solve(BatchId(1), Duration::from_secs(20))
+        );
+        assert_eq!(
+            scheduler
+                .determine_action(base_time + Duration::from_secs(40))
+                .unwrap(),
+            Action::Sleep(Duration::from_secs(25))
+        );
+    }
 }
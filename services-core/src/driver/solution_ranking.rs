@@ -0,0 +1,200 @@
+//! Module implementing the policies for selecting the solution to submit when
+//! multiple solvers race for a batch.
+
+use crate::models::Solution;
+use ethcontract::U256;
+use std::collections::HashSet;
+
+arg_enum! {
+    /// The policy for selecting among candidate solutions.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum SolutionRankingPolicy {
+        /// Select the solution with the highest objective value.
+        ObjectiveValue,
+        /// Select the solution touching the fewest orders among the solutions
+        /// whose objective value is within the tolerance of the best one.
+        FewestTouchedOrders,
+    }
+}
+
+/// A verified solution with the objective value computed by the exchange.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub solution: Solution,
+    pub objective_value: U256,
+}
+
+/// A policy for selecting the solution to submit among candidate solutions.
+pub trait SolutionRanking: Send + Sync {
+    /// Returns the preferred candidate, or `None` if no candidate should be
+    /// submitted.
+    fn select(&self, candidates: Vec<Candidate>) -> Option<Candidate>;
+}
+
+/// Creates the ranking for the policy. Candidates with solutions touching any
+/// of the flagged tokens are never selected.
+pub fn create(
+    policy: SolutionRankingPolicy,
+    tolerance: f64,
+    flagged_tokens: &[u16],
+) -> Box<dyn SolutionRanking> {
+    let ranking: Box<dyn SolutionRanking> = match policy {
+        SolutionRankingPolicy::ObjectiveValue => Box::new(ObjectiveValue),
+        SolutionRankingPolicy::FewestTouchedOrders => Box::new(FewestTouchedOrders { tolerance }),
+    };
+    if flagged_tokens.is_empty() {
+        ranking
+    } else {
+        Box::new(AvoidFlaggedTokens {
+            flagged_tokens: flagged_tokens.iter().copied().collect(),
+            ranking,
+        })
+    }
+}
+
+/// Selects the candidate with the highest objective value.
+pub struct ObjectiveValue;
+
+impl SolutionRanking for ObjectiveValue {
+    fn select(&self, candidates: Vec<Candidate>) -> Option<Candidate> {
+        candidates
+            .into_iter()
+            .max_by_key(|candidate| candidate.objective_value)
+    }
+}
+
+/// Selects the candidate touching the fewest orders among the candidates
+/// whose objective value is at most the relative tolerance below the highest
+/// one. Ties are broken by the objective value.
+pub struct FewestTouchedOrders {
+    pub tolerance: f64,
+}
+
+impl SolutionRanking for FewestTouchedOrders {
+    fn select(&self, candidates: Vec<Candidate>) -> Option<Candidate> {
+        let best = candidates
+            .iter()
+            .map(|candidate| candidate.objective_value)
+            .max()?;
+        let threshold = best.to_f64_lossy() * (1.0 - self.tolerance);
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.objective_value.to_f64_lossy() >= threshold)
+            .min_by(|a, b| {
+                touched_orders(&a.solution)
+                    .cmp(&touched_orders(&b.solution))
+                    .then(b.objective_value.cmp(&a.objective_value))
+            })
+    }
+}
+
+fn touched_orders(solution: &Solution) -> usize {
+    solution
+        .executed_orders
+        .iter()
+        .filter(|order| order.sell_amount > 0)
+        .count()
+}
+
+/// Excludes candidates with solutions pricing any of the flagged tokens, which
+/// are all tokens traded by the solution, before ranking the remaining ones.
+pub struct AvoidFlaggedTokens {
+    pub flagged_tokens: HashSet<u16>,
+    pub ranking: Box<dyn SolutionRanking>,
+}
+
+impl SolutionRanking for AvoidFlaggedTokens {
+    fn select(&self, candidates: Vec<Candidate>) -> Option<Candidate> {
+        let candidates = candidates
+            .into_iter()
+            .filter(|candidate| {
+                let flagged = candidate
+                    .solution
+                    .prices
+                    .keys()
+                    .find(|token| self.flagged_tokens.contains(token));
+                if let Some(token) = flagged {
+                    log::info!(
+                        "not selecting solution with objective value {} touching flagged token {}",
+                        candidate.objective_value,
+                        token
+                    );
+                }
+                flagged.is_none()
+            })
+            .collect();
+        self.ranking.select(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ExecutedOrder;
+    use ethcontract::Address;
+
+    fn candidate(objective_value: u64, touched_orders: usize, tokens: &[u16]) -> Candidate {
+        Candidate {
+            solution: Solution {
+                prices: tokens.iter().map(|token| (*token, 1)).collect(),
+                executed_orders: (0..touched_orders)
+                    .map(|order_id| ExecutedOrder {
+                        account_id: Address::zero(),
+                        order_id: order_id as _,
+                        sell_amount: 1,
+                        buy_amount: 1,
+                    })
+                    .collect(),
+            },
+            objective_value: objective_value.into(),
+        }
+    }
+
+    #[test]
+    fn selects_highest_objective_value() {
+        let candidates = vec![
+            candidate(90, 2, &[0, 1]),
+            candidate(100, 4, &[0, 1]),
+            candidate(95, 3, &[0, 1]),
+        ];
+        assert_eq!(
+            ObjectiveValue.select(candidates.clone()),
+            Some(candidates[1].clone())
+        );
+        assert_eq!(ObjectiveValue.select(vec![]), None);
+    }
+
+    #[test]
+    fn selects_fewest_touched_orders_within_tolerance() {
+        let candidates = vec![
+            candidate(100, 4, &[0, 1]),
+            candidate(96, 3, &[0, 1]),
+            candidate(95, 3, &[0, 1]),
+            candidate(80, 2, &[0, 1]),
+        ];
+        let ranking = FewestTouchedOrders { tolerance: 0.05 };
+        assert_eq!(
+            ranking.select(candidates.clone()),
+            Some(candidates[1].clone())
+        );
+
+        let ranking = FewestTouchedOrders { tolerance: 0.0 };
+        assert_eq!(
+            ranking.select(candidates.clone()),
+            Some(candidates[0].clone())
+        );
+    }
+
+    #[test]
+    fn avoids_flagged_tokens() {
+        let candidates = vec![candidate(100, 2, &[0, 1, 2]), candidate(90, 2, &[0, 1])];
+        let ranking = create(SolutionRankingPolicy::ObjectiveValue, 0.0, &[2]);
+        assert_eq!(
+            ranking.select(candidates.clone()),
+            Some(candidates[1].clone())
+        );
+
+        let ranking = create(SolutionRankingPolicy::ObjectiveValue, 0.0, &[1]);
+        assert_eq!(ranking.select(candidates), None);
+    }
+}
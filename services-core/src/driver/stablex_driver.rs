@@ -1,18 +1,72 @@
 use crate::{
+    driver::{
+        alerting::{Alerting, SubmittedSolution},
+        solution_ranking::{Candidate, ObjectiveValue, SolutionRanking},
+        submission_receipts::SubmissionReceipts,
+    },
     economic_viability::EconomicViabilityComputing,
+    error::ErrorCode,
+    health::HealthReporting,
     metrics::StableXMetrics,
     models::{account_state::AccountState, order::Order, BatchId, Solution},
     orderbook::StableXOrderBookReading,
     price_finding::PriceFinding,
     solution_submission::{SolutionSubmissionError, StableXSolutionSubmitting},
+    util::{AsyncSleep, AsyncSleeping},
 };
 use anyhow::{Error, Result};
-use log::{info, warn};
+use futures::future;
+use log::{error, info, warn};
 use std::{
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
+/// The minimal time the solver gets to have a chance for a solution.
+const MIN_SOLVER_TIME: Duration = Duration::from_secs(1);
+
+/// The delay before retrying to fetch the orderbook for the first time. It is
+/// doubled for every further retry.
+const INITIAL_ORDERBOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+arg_enum! {
+    /// What the driver does when the orderbook for a batch cannot be fetched.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum SkipBatchPolicy {
+        /// Retry fetching the orderbook with exponential backoff as long as
+        /// there is time left for solving.
+        Retry,
+        /// Fetch the orderbook from the fallback reader.
+        Fallback,
+        /// Skip the batch by using the trivial solution.
+        Trivial,
+    }
+}
+
+/// Configuration of the circuit breaker that pauses the driver when batches
+/// repeatedly fail at the same processing stage, instead of wasting gas on
+/// transactions that are likely to fail again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive batches failing at the same stage after
+    /// which the circuit breaker opens.
+    pub failure_threshold: usize,
+    /// The number of batches that are skipped when the circuit breaker opens.
+    pub cooldown: u32,
+    /// The maximum number of batches that are skipped. The cool-down doubles
+    /// every time the first batch processed after a cool-down fails again.
+    pub max_cooldown: u32,
+}
+
+/// The processing stage at which a batch failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FailureStage {
+    Orderbook,
+    Solver,
+    Verification,
+    Submission,
+}
+
 #[derive(Debug)]
 pub enum DriverError {
     Retry(Error),
@@ -31,19 +85,45 @@ pub trait StableXDriver: Send + Sync {
     async fn submit_solution(&self, batch_to_solve: BatchId, solution: Solution) -> Result<()>;
 }
 
-pub struct StableXDriverImpl {
-    price_finder: Arc<dyn PriceFinding + Send + Sync>,
-    orderbook_reader: Arc<dyn StableXOrderBookReading>,
-    solution_submitter: Arc<dyn StableXSolutionSubmitting + Send + Sync>,
+/// The driver with its components behind trait objects, as it is configured
+/// at runtime.
+pub type StableXDriverImpl = StableXDriverCore<
+    dyn PriceFinding + Send + Sync,
+    dyn StableXOrderBookReading,
+    dyn StableXSolutionSubmitting + Send + Sync,
+>;
+
+/// The driver, generic over the types of its price finder, orderbook reader
+/// and solution submitter so that it is monomorphized for concrete components.
+pub struct StableXDriverCore<P: ?Sized, R: ?Sized, S: ?Sized> {
+    price_finder: Arc<P>,
+    orderbook_reader: Arc<R>,
+    solution_submitter: Arc<S>,
     economic_viability: Arc<dyn EconomicViabilityComputing>,
     metrics: Arc<StableXMetrics>,
+    skip_batch_policy: Option<SkipBatchPolicy>,
+    fallback_orderbook_reader: Option<Arc<dyn StableXOrderBookReading>>,
+    alerting: Option<Arc<Alerting>>,
+    submission_receipts: Option<Arc<SubmissionReceipts>>,
+    circuit_breaker: Option<(Mutex<CircuitBreaker>, Arc<dyn HealthReporting>)>,
+    racing_price_finders: Vec<Arc<dyn PriceFinding + Send + Sync>>,
+    solution_ranking: Box<dyn SolutionRanking>,
+    /// The candidate selected when solving the last batch, so that it is
+    /// submitted without being evaluated again.
+    selected_candidate: Mutex<Option<(BatchId, Candidate)>>,
+    sleep: Box<dyn AsyncSleeping>,
 }
 
-impl StableXDriverImpl {
+impl<P, R, S> StableXDriverCore<P, R, S>
+where
+    P: PriceFinding + Send + Sync + ?Sized,
+    R: StableXOrderBookReading + ?Sized,
+    S: StableXSolutionSubmitting + Send + Sync + ?Sized,
+{
     pub fn new(
-        price_finder: Arc<dyn PriceFinding + Send + Sync>,
-        orderbook_reader: Arc<dyn StableXOrderBookReading>,
-        solution_submitter: Arc<dyn StableXSolutionSubmitting + Send + Sync>,
+        price_finder: Arc<P>,
+        orderbook_reader: Arc<R>,
+        solution_submitter: Arc<S>,
         economic_viability: Arc<dyn EconomicViabilityComputing>,
         metrics: Arc<StableXMetrics>,
     ) -> Self {
@@ -53,6 +133,107 @@ impl StableXDriverImpl {
             solution_submitter,
             economic_viability,
             metrics,
+            skip_batch_policy: None,
+            fallback_orderbook_reader: None,
+            alerting: None,
+            submission_receipts: None,
+            circuit_breaker: None,
+            racing_price_finders: Vec::new(),
+            solution_ranking: Box::new(ObjectiveValue),
+            selected_candidate: Mutex::new(None),
+            sleep: Box::new(AsyncSleep),
+        }
+    }
+
+    /// Sets what to do when the orderbook for a batch cannot be fetched. The
+    /// fallback reader is only used with `SkipBatchPolicy::Fallback`. Without
+    /// a policy the batch fails so that the scheduler retries it.
+    pub fn with_skip_batch_policy(
+        mut self,
+        skip_batch_policy: SkipBatchPolicy,
+        fallback_orderbook_reader: Option<Arc<dyn StableXOrderBookReading>>,
+    ) -> Self {
+        self.skip_batch_policy = Some(skip_batch_policy);
+        self.fallback_orderbook_reader = fallback_orderbook_reader;
+        self
+    }
+
+    /// Evaluates the alert rules for every submitted solution. The rules for
+    /// batches without solution are evaluated by the scheduler.
+    pub fn with_alerting(mut self, alerting: Arc<Alerting>) -> Self {
+        self.alerting = Some(alerting);
+        self
+    }
+
+    /// Records the receipts of submitted solutions.
+    pub fn with_submission_receipts(
+        mut self,
+        submission_receipts: Arc<SubmissionReceipts>,
+    ) -> Self {
+        self.submission_receipts = Some(submission_receipts);
+        self
+    }
+
+    /// Skips batches for a cool-down period after consecutive batches failed
+    /// at the same stage. The service is reported as not ready while batches
+    /// are skipped.
+    pub fn with_circuit_breaker(
+        mut self,
+        config: CircuitBreakerConfig,
+        health: Arc<dyn HealthReporting>,
+    ) -> Self {
+        self.circuit_breaker = Some((Mutex::new(CircuitBreaker::new(config)), health));
+        self
+    }
+
+    /// Runs the racing price finders concurrently with the price finder. The
+    /// solution to submit is selected among the valid solutions with the
+    /// ranking, which also applies without racing price finders.
+    pub fn with_racing_price_finders(
+        mut self,
+        racing_price_finders: Vec<Arc<dyn PriceFinding + Send + Sync>>,
+        solution_ranking: Box<dyn SolutionRanking>,
+    ) -> Self {
+        self.racing_price_finders = racing_price_finders;
+        self.solution_ranking = solution_ranking;
+        self
+    }
+
+    /// Returns whether the batch should be processed or skipped because the
+    /// circuit breaker is open.
+    fn circuit_breaker_allows(&self, batch: BatchId) -> bool {
+        match &self.circuit_breaker {
+            Some((circuit_breaker, _)) => circuit_breaker.lock().unwrap().allows(batch),
+            None => true,
+        }
+    }
+
+    fn batch_failed(&self, batch: BatchId, stage: FailureStage) {
+        let (circuit_breaker, health) = match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker,
+            None => return,
+        };
+        let opened = circuit_breaker.lock().unwrap().record_failure(batch, stage);
+        if let Some(cooldown) = opened {
+            error!(
+                "Circuit breaker opened after batch {} failed at {:?} stage, skipping {} batches",
+                batch, stage, cooldown
+            );
+            self.metrics.circuit_breaker_changed(true);
+            health.notify_circuit_breaker_open(true);
+        }
+    }
+
+    fn batch_succeeded(&self, batch: BatchId) {
+        let (circuit_breaker, health) = match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker,
+            None => return,
+        };
+        let closed = circuit_breaker.lock().unwrap().record_success(batch);
+        if closed {
+            info!("Closing circuit breaker after batch {} succeeded", batch);
+            self.metrics.circuit_breaker_changed(false);
+            health.notify_circuit_breaker_open(false);
         }
     }
 
@@ -66,6 +247,53 @@ impl StableXDriverImpl {
         get_auction_data_result
     }
 
+    /// Handles a failure to fetch the orderbook according to the skip batch
+    /// policy. Returns `None` if the batch should be skipped.
+    async fn recover_orderbook(
+        &self,
+        batch_to_solve: BatchId,
+        deadline: Instant,
+        err: Error,
+    ) -> Result<Option<(AccountState, Vec<Order>)>, DriverError> {
+        let skip_batch_policy = match self.skip_batch_policy {
+            Some(skip_batch_policy) => skip_batch_policy,
+            None => return Err(DriverError::Retry(err)),
+        };
+        warn!(
+            "failed to fetch orderbook for batch {}, applying {} policy: {:?}",
+            batch_to_solve, skip_batch_policy, err
+        );
+        self.metrics
+            .orderbook_fetch_degraded(&skip_batch_policy.to_string());
+        match skip_batch_policy {
+            SkipBatchPolicy::Retry => {
+                let mut err = err;
+                let mut delay = INITIAL_ORDERBOOK_RETRY_DELAY;
+                while Instant::now() + delay + MIN_SOLVER_TIME < deadline {
+                    self.sleep.sleep(delay).await;
+                    match self.get_orderbook(batch_to_solve.into()).await {
+                        Ok(orderbook) => return Ok(Some(orderbook)),
+                        Err(retry_err) => {
+                            warn!("retrying to fetch orderbook failed: {:?}", retry_err);
+                            err = retry_err;
+                        }
+                    }
+                    delay *= 2;
+                }
+                Err(DriverError::Retry(err))
+            }
+            SkipBatchPolicy::Fallback => match &self.fallback_orderbook_reader {
+                Some(reader) => reader
+                    .get_auction_data_for_batch(batch_to_solve.into())
+                    .await
+                    .map(Some)
+                    .map_err(DriverError::Retry),
+                None => Err(DriverError::Retry(err)),
+            },
+            SkipBatchPolicy::Trivial => Ok(None),
+        }
+    }
+
     async fn solve(
         &self,
         batch_to_solve: BatchId,
@@ -79,9 +307,22 @@ impl StableXDriverImpl {
         }
         let min_avg_fee = self.economic_viability.min_average_fee().await?;
         let price_finder_result = self
-            .price_finder
-            .find_prices(&orders, &account_state, deadline, min_avg_fee)
-            .await;
+            .race(
+                batch_to_solve,
+                &orders,
+                &account_state,
+                deadline,
+                min_avg_fee,
+            )
+            .await
+            .map(|candidate| {
+                let solution = candidate
+                    .as_ref()
+                    .map_or_else(Solution::trivial, |candidate| candidate.solution.clone());
+                *self.selected_candidate.lock().unwrap() =
+                    candidate.map(|candidate| (batch_to_solve, candidate));
+                solution
+            });
         self.metrics
             .auction_solution_computed(batch_to_solve.into(), &price_finder_result);
 
@@ -93,50 +334,236 @@ impl StableXDriverImpl {
         Ok(solution)
     }
 
-    async fn submit(&self, batch_to_solve: BatchId, solution: Solution) -> Result<()> {
-        let verified = if solution.is_non_trivial() {
-            // NOTE: in retrieving the objective value from the reader the
-            //   solution gets validated, ensured that it is better than the
-            //   latest submitted solution, and that solutions are still being
-            //   accepted for this batch ID.
-            let verification_result = self
-                .solution_submitter
-                .get_solution_objective_value(batch_to_solve.into(), solution.clone())
-                .await;
-            self.metrics
-                .auction_solution_verified(batch_to_solve.into(), &verification_result);
-
-            match verification_result {
-                Ok(objective_value) => {
-                    info!(
-                        "Verified solution with objective value: {}",
-                        objective_value
-                    );
-                    Some(objective_value)
-                }
-                Err(err) => match err {
-                    SolutionSubmissionError::Benign(reason) => {
-                        info!("Benign failure while verifying solution: {}", reason);
-                        None
+    /// Runs the price finder and the racing price finders concurrently and
+    /// selects among their valid solutions with the ranking, so that the
+    /// ranking also applies to the solution of a single price finder. Returns
+    /// `None` if there is no solution to submit.
+    async fn race(
+        &self,
+        batch_to_solve: BatchId,
+        orders: &[Order],
+        account_state: &AccountState,
+        deadline: Duration,
+        min_avg_fee: u128,
+    ) -> Result<Option<Candidate>> {
+        let mut searches =
+            vec![self
+                .price_finder
+                .find_prices(orders, account_state, deadline, min_avg_fee)];
+        searches.extend(self.racing_price_finders.iter().map(|price_finder| {
+            price_finder.find_prices(orders, account_state, deadline, min_avg_fee)
+        }));
+        let mut results = future::join_all(searches).await;
+
+        let mut candidates = Vec::new();
+        let mut rejected = false;
+        for (index, result) in results.iter().enumerate() {
+            match result {
+                Ok(solution) if solution.is_non_trivial() => {
+                    if !self.verify_locally(batch_to_solve, solution).await {
+                        rejected = true;
+                        continue;
                     }
-                    SolutionSubmissionError::Unexpected(err) => {
-                        // Return from entire function with the unexpected error
-                        return Err(err);
+                    match self.evaluate(batch_to_solve, solution).await {
+                        Ok(Some(candidate)) => candidates.push(candidate),
+                        Ok(None) => (),
+                        Err(err) => {
+                            warn!(
+                                "Rejecting solution for batch {} that failed verification: {:?}",
+                                batch_to_solve, err
+                            );
+                            rejected = true;
+                        }
                     }
-                },
+                }
+                Ok(_) => (),
+                // The error of the price finder is returned if there are no
+                // candidates and logged below otherwise.
+                Err(_) if index == 0 => (),
+                Err(err) => warn!(
+                    "Racing price finder failed for batch {}: {:?}",
+                    batch_to_solve, err
+                ),
             }
-        } else {
+        }
+        if candidates.is_empty() {
+            // Without valid solutions the result of the price finder is
+            // handled as if there were no racing price finders.
+            if rejected {
+                self.batch_failed(batch_to_solve, FailureStage::Verification);
+            }
+            return results.swap_remove(0).map(|_| None);
+        }
+        if let Err(err) = &results[0] {
+            warn!(
+                "Price finder failed for batch {}, selecting among racing solutions: {:?}",
+                batch_to_solve, err
+            );
+        }
+
+        let num_candidates = candidates.len();
+        match self.solution_ranking.select(candidates) {
+            Some(candidate) => {
+                info!(
+                    "Selected solution with objective value {} among {} candidates for batch {}",
+                    candidate.objective_value, num_candidates, batch_to_solve
+                );
+                Ok(Some(candidate))
+            }
+            None => {
+                info!(
+                    "None of the {} candidates for batch {} was selected",
+                    num_candidates, batch_to_solve
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Computes the objective value of a locally verified solution. Returns
+    /// `None` if the solution cannot be submitted for a benign reason.
+    async fn evaluate(
+        &self,
+        batch_to_solve: BatchId,
+        solution: &Solution,
+    ) -> Result<Option<Candidate>> {
+        // NOTE: in retrieving the objective value from the reader the
+        //   solution gets validated, ensured that it is better than the
+        //   latest submitted solution, and that solutions are still being
+        //   accepted for this batch ID.
+        let verification_result = self
+            .solution_submitter
+            .get_solution_objective_value(batch_to_solve.into(), solution.clone())
+            .await;
+        self.metrics
+            .auction_solution_verified(batch_to_solve.into(), &verification_result);
+        match verification_result {
+            Ok(objective_value) => {
+                info!(
+                    "Verified solution for batch {} with objective value: {}",
+                    batch_to_solve, objective_value
+                );
+                Ok(Some(Candidate {
+                    solution: solution.clone(),
+                    objective_value,
+                }))
+            }
+            Err(SolutionSubmissionError::Benign(reason)) => {
+                info!(
+                    "Benign failure while verifying solution for batch {}: {}",
+                    batch_to_solve, reason
+                );
+                Ok(None)
+            }
+            Err(SolutionSubmissionError::Unexpected(err)) => Err(err),
+        }
+    }
+
+    /// Takes the candidate selected when solving the batch if its solution is
+    /// the one to submit.
+    fn take_selected_candidate(
+        &self,
+        batch_to_solve: BatchId,
+        solution: &Solution,
+    ) -> Option<Candidate> {
+        let mut selected_candidate = self.selected_candidate.lock().unwrap();
+        match &*selected_candidate {
+            Some((batch, candidate))
+                if *batch == batch_to_solve && candidate.solution == *solution =>
+            {
+                selected_candidate.take().map(|(_, candidate)| candidate)
+            }
+            _ => None,
+        }
+    }
+
+    /// Replays the solution against the local orderbook so that invalid solver
+    /// output is rejected before spending gas on a failing transaction.
+    async fn verify_locally(&self, batch_to_solve: BatchId, solution: &Solution) -> bool {
+        match self
+            .orderbook_reader
+            .verify_solution(batch_to_solve.into(), solution)
+            .await
+        {
+            Ok(()) => true,
+            Err(err) => {
+                let code = ErrorCode::of(&err, ErrorCode::OrderbookVerification);
+                warn!(
+                    "Rejecting solution for batch {} that failed local verification ({}): {:?}",
+                    batch_to_solve, code, err
+                );
+                self.metrics
+                    .error_occurred(&err, ErrorCode::OrderbookVerification);
+                false
+            }
+        }
+    }
+
+    /// Submits the solution, which is only verified if it is not the candidate
+    /// selected when solving the batch. Returns the submitted solution or
+    /// `None` if it was not submitted.
+    async fn submit(
+        &self,
+        batch_to_solve: BatchId,
+        solution: Solution,
+    ) -> Result<Option<SubmittedSolution>> {
+        let candidate = if !solution.is_non_trivial() {
             info!(
                 "Not submitting trivial solution for batch {}",
                 batch_to_solve
             );
             None
+        } else if let Some(candidate) = self.take_selected_candidate(batch_to_solve, &solution) {
+            Some(candidate)
+        } else if !self.verify_locally(batch_to_solve, &solution).await {
+            self.batch_failed(batch_to_solve, FailureStage::Verification);
+            None
+        } else {
+            match self.evaluate(batch_to_solve, &solution).await {
+                Ok(candidate) => candidate,
+                Err(err) => {
+                    self.batch_failed(batch_to_solve, FailureStage::Verification);
+                    return Err(err);
+                }
+            }
         };
 
-        let submitted = if let Some(objective_value) = verified {
+        // NOTE: The submission of the verified solution is simulated once
+        //   so that every transaction submitting it uses the same gas
+        //   limit, instead of estimating it for every transaction.
+        let verified = match candidate {
+            Some(candidate) => match self
+                .solution_submitter
+                .estimate_gas_limit(
+                    batch_to_solve.into(),
+                    solution.clone(),
+                    candidate.objective_value,
+                )
+                .await
+            {
+                Ok(gas_limit) => {
+                    info!("Simulated solution submission with gas limit {}", gas_limit);
+                    Some((candidate.objective_value, gas_limit))
+                }
+                Err(SolutionSubmissionError::Benign(reason)) => {
+                    info!("Benign failure while simulating solution: {}", reason);
+                    None
+                }
+                Err(SolutionSubmissionError::Unexpected(err)) => {
+                    // Return from entire function with the unexpected error
+                    self.batch_failed(batch_to_solve, FailureStage::Verification);
+                    return Err(err);
+                }
+            },
+            None => None,
+        };
+
+        let submitted = if let Some((objective_value, gas_limit)) = verified {
+            let economic_viability_info = solution.economic_viability_info();
+            let estimated_gas = economic_viability_info.estimated_gas;
             let gas_price_cap = self
                 .economic_viability
-                .max_gas_price(solution.economic_viability_info())
+                .max_gas_price(economic_viability_info)
                 .await?;
             let submission_result = self
                 .solution_submitter
@@ -144,58 +571,87 @@ impl StableXDriverImpl {
                     batch_to_solve.into(),
                     solution,
                     objective_value,
+                    gas_limit,
                     gas_price_cap,
                 )
                 .await;
             self.metrics
                 .auction_solution_submitted(batch_to_solve.into(), &submission_result);
             match submission_result {
-                Ok(_) => {
-                    info!("Successfully applied solution to batch {}", batch_to_solve);
-                    true
+                Ok(receipt) => {
+                    info!(
+                        "Successfully applied solution to batch {} in transaction {:?}",
+                        batch_to_solve, receipt.transaction_hash
+                    );
+                    if let Some(submission_receipts) = &self.submission_receipts {
+                        submission_receipts.record(receipt);
+                    }
+                    Some(SubmittedSolution {
+                        objective_value,
+                        max_gas_cost: estimated_gas as f64 * gas_price_cap,
+                    })
                 }
                 Err(err) => match err {
                     SolutionSubmissionError::Benign(reason) => {
                         info!("Benign failure while submitting solution: {}", reason);
-                        false
+                        None
                     }
                     SolutionSubmissionError::Unexpected(err) => return Err(err),
                 },
             }
         } else {
-            false
+            None
         };
 
-        if !submitted {
+        if submitted.is_none() {
             self.metrics
                 .auction_processed_but_not_submitted(batch_to_solve.into());
         };
 
-        Ok(())
+        Ok(submitted)
     }
 }
 
 #[async_trait::async_trait]
-impl StableXDriver for StableXDriverImpl {
+impl<P, R, S> StableXDriver for StableXDriverCore<P, R, S>
+where
+    P: PriceFinding + Send + Sync + ?Sized,
+    R: StableXOrderBookReading + ?Sized,
+    S: StableXSolutionSubmitting + Send + Sync + ?Sized,
+{
     async fn solve_batch(
         &self,
         batch_to_solve: BatchId,
         deadline: Duration,
     ) -> Result<Solution, DriverError> {
         let deadline = Instant::now() + deadline;
+        if !self.circuit_breaker_allows(batch_to_solve) {
+            warn!(
+                "Skipping batch {} because the circuit breaker is open",
+                batch_to_solve
+            );
+            return Ok(Solution::trivial());
+        }
 
         self.metrics
             .auction_processing_started(&Ok(batch_to_solve.into()));
-        let (account_state, orders) = self
-            .get_orderbook(batch_to_solve.into())
-            .await
-            .map_err(DriverError::Retry)?;
+        let (account_state, orders) = match self.get_orderbook(batch_to_solve.into()).await {
+            Ok(orderbook) => orderbook,
+            Err(err) => match self.recover_orderbook(batch_to_solve, deadline, err).await {
+                Ok(Some(orderbook)) => orderbook,
+                Ok(None) => return Ok(Solution::trivial()),
+                Err(err) => {
+                    self.batch_failed(batch_to_solve, FailureStage::Orderbook);
+                    return Err(err);
+                }
+            },
+        };
 
         // Make sure the solver has at least some minimal time to run to have a chance for a
         // solution. This also fixes an assert where the solver fails if the timelimit gets rounded
         // to 0.
         let deadline = match deadline.checked_duration_since(Instant::now()) {
-            Some(duration) if duration > Duration::from_secs(1) => duration,
+            Some(duration) if duration > MIN_SOLVER_TIME => duration,
             _ => {
                 warn!("orderbook retrieval exceeded time limit");
                 return Ok(Solution::trivial());
@@ -204,11 +660,115 @@ impl StableXDriver for StableXDriverImpl {
 
         self.solve(batch_to_solve, deadline, account_state, orders)
             .await
-            .map_err(DriverError::Skip)
+            .map_err(|err| {
+                self.batch_failed(batch_to_solve, FailureStage::Solver);
+                DriverError::Skip(err)
+            })
     }
 
     async fn submit_solution(&self, batch_to_solve: BatchId, solution: Solution) -> Result<()> {
-        self.submit(batch_to_solve, solution).await
+        let submitted = self.submit(batch_to_solve, solution).await;
+        match &submitted {
+            Ok(_) => self.batch_succeeded(batch_to_solve),
+            Err(_) => self.batch_failed(batch_to_solve, FailureStage::Submission),
+        }
+        if let (Some(alerting), Ok(Some(solution))) = (&self.alerting, &submitted) {
+            alerting.solution_submitted(batch_to_solve, *solution);
+        }
+        submitted.map(|_| ())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum CircuitState {
+    /// Batches are processed.
+    Closed,
+    /// Batches before the specified batch are skipped.
+    Open { until: BatchId },
+    /// The cool-down is over and the next batch decides whether the circuit
+    /// breaker closes or opens again with a longer cool-down.
+    HalfOpen,
+}
+
+/// The state machine of the circuit breaker. A batch counts as failed if any
+/// of its processing stages failed, even if retrying the stage succeeded, and
+/// only the first failure of a batch is taken into account.
+#[derive(Debug)]
+struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: CircuitState,
+    /// The last failed batch along with the stage it failed at and the number
+    /// of consecutive failed batches that failed at that stage.
+    last_failure: Option<(BatchId, FailureStage, usize)>,
+    cooldown: u32,
+}
+
+impl CircuitBreaker {
+    fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            state: CircuitState::Closed,
+            last_failure: None,
+            cooldown: config.cooldown,
+        }
+    }
+
+    /// Returns whether the batch should be processed, ending the cool-down
+    /// once it is over.
+    fn allows(&mut self, batch: BatchId) -> bool {
+        match self.state {
+            CircuitState::Open { until } if batch < until => false,
+            CircuitState::Open { .. } => {
+                self.state = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+        }
+    }
+
+    /// Records a failed batch. Returns the number of batches that will be
+    /// skipped if the circuit breaker opened.
+    fn record_failure(&mut self, batch: BatchId, stage: FailureStage) -> Option<u32> {
+        if matches!(self.last_failure, Some((last_batch, ..)) if last_batch == batch) {
+            return None;
+        }
+        let failures = match self.last_failure {
+            Some((last_batch, last_stage, failures)) if last_stage == stage => failures + 1,
+            _ => 1,
+        };
+        self.last_failure = Some((batch, stage, failures));
+
+        match self.state {
+            CircuitState::HalfOpen => {
+                self.cooldown = self
+                    .cooldown
+                    .saturating_mul(2)
+                    .min(self.config.max_cooldown);
+            }
+            CircuitState::Closed if failures >= self.config.failure_threshold => (),
+            _ => return None,
+        }
+        self.state = CircuitState::Open {
+            until: BatchId(batch.0 + 1 + u64::from(self.cooldown)),
+        };
+        Some(self.cooldown)
+    }
+
+    /// Records a successfully processed batch. Returns whether the circuit
+    /// breaker closed.
+    fn record_success(&mut self, batch: BatchId) -> bool {
+        if matches!(self.last_failure, Some((last_batch, ..)) if last_batch == batch) {
+            return false;
+        }
+        self.last_failure = None;
+        match self.state {
+            CircuitState::HalfOpen => {
+                self.state = CircuitState::Closed;
+                self.cooldown = self.config.cooldown;
+                true
+            }
+            CircuitState::Closed | CircuitState::Open { .. } => false,
+        }
     }
 }
 
@@ -216,15 +776,17 @@ impl StableXDriver for StableXDriverImpl {
 mod tests {
     use super::*;
     use crate::{
+        driver::solution_ranking::{self, SolutionRankingPolicy},
         economic_viability::{FixedEconomicViabilityComputer, MockEconomicViabilityComputing},
+        health::MockHealthReporting,
         models::{
             order::test_util::{create_order_for_test, order_to_executed_order},
             AccountState,
         },
         orderbook::MockStableXOrderBookReading,
         price_finding::price_finder_interface::MockPriceFinding,
-        solution_submission::MockStableXSolutionSubmitting,
-        util::test_util::map_from_slice,
+        solution_submission::{MockStableXSolutionSubmitting, SubmissionReceipt},
+        util::{test_util::map_from_slice, MockAsyncSleeping},
     };
     use anyhow::anyhow;
     use ethcontract::U256;
@@ -235,7 +797,7 @@ mod tests {
     #[test]
     fn invokes_solver_with_reader_data_for_unprocessed_auction() {
         let mut reader = MockStableXOrderBookReading::default();
-        let submitter = MockStableXSolutionSubmitting::default();
+        let mut submitter = MockStableXSolutionSubmitting::default();
         let mut pf = MockPriceFinding::default();
         let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
         let metrics = StableXMetrics::default();
@@ -266,6 +828,10 @@ mod tests {
                 o == orders.as_slice() && *s == state && *t <= latest_solution_submit_time
             })
             .return_once(move |_, _, _, _| Ok(solution));
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
+        submitter
+            .expect_get_solution_objective_value()
+            .returning(|_, _| Ok(1.into()));
 
         let driver = StableXDriverImpl::new(
             Arc::new(pf),
@@ -281,6 +847,32 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn solves_with_concrete_components() {
+        let mut reader = MockStableXOrderBookReading::default();
+        reader
+            .expect_get_auction_data_for_batch()
+            .returning(|_| Ok(Default::default()));
+        let driver: StableXDriverCore<
+            MockPriceFinding,
+            MockStableXOrderBookReading,
+            MockStableXSolutionSubmitting,
+        > = StableXDriverCore::new(
+            Arc::new(MockPriceFinding::default()),
+            Arc::new(reader),
+            Arc::new(MockStableXSolutionSubmitting::default()),
+            Arc::new(MockEconomicViabilityComputing::new()),
+            Arc::new(StableXMetrics::default()),
+        );
+
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
     #[test]
     fn test_errors_on_failing_reader() {
         let mut reader = MockStableXOrderBookReading::default();
@@ -310,6 +902,100 @@ mod tests {
         ));
     }
 
+    fn driver_with_failing_reader(
+        skip_batch_policy: SkipBatchPolicy,
+        fallback_reader: Option<MockStableXOrderBookReading>,
+    ) -> StableXDriverImpl {
+        let mut reader = MockStableXOrderBookReading::default();
+        reader
+            .expect_get_auction_data_for_batch()
+            .returning(|_| Err(anyhow!("Error")));
+        StableXDriverImpl::new(
+            Arc::new(MockPriceFinding::default()),
+            Arc::new(reader),
+            Arc::new(MockStableXSolutionSubmitting::default()),
+            Arc::new(MockEconomicViabilityComputing::new()),
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_skip_batch_policy(
+            skip_batch_policy,
+            fallback_reader.map(|reader| Arc::new(reader) as Arc<dyn StableXOrderBookReading>),
+        )
+    }
+
+    #[test]
+    fn retries_failing_reader_with_backoff() {
+        let mut reader = MockStableXOrderBookReading::default();
+        let mut seq = mockall::Sequence::new();
+        reader
+            .expect_get_auction_data_for_batch()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|_| Err(anyhow!("Error")));
+        reader
+            .expect_get_auction_data_for_batch()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Ok(Default::default()));
+
+        let mut sleep = MockAsyncSleeping::new();
+        let mut sleep_seq = mockall::Sequence::new();
+        for delay in &[1, 2] {
+            sleep
+                .expect_sleep()
+                .with(eq(Duration::from_secs(*delay)))
+                .times(1)
+                .in_sequence(&mut sleep_seq)
+                .returning(|_| immediate!(()));
+        }
+
+        let mut driver = StableXDriverImpl::new(
+            Arc::new(MockPriceFinding::default()),
+            Arc::new(reader),
+            Arc::new(MockStableXSolutionSubmitting::default()),
+            Arc::new(MockEconomicViabilityComputing::new()),
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_skip_batch_policy(SkipBatchPolicy::Retry, None);
+        driver.sleep = Box::new(sleep);
+
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
+    #[test]
+    fn falls_back_to_secondary_reader() {
+        let mut fallback = MockStableXOrderBookReading::default();
+        fallback
+            .expect_get_auction_data_for_batch()
+            .with(eq(42))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let driver = driver_with_failing_reader(SkipBatchPolicy::Fallback, Some(fallback));
+
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
+    #[test]
+    fn skips_batch_with_trivial_solution() {
+        let driver = driver_with_failing_reader(SkipBatchPolicy::Trivial, None);
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
     #[test]
     fn test_errors_on_failing_price_finder() {
         let mut reader = MockStableXOrderBookReading::default();
@@ -411,7 +1097,8 @@ mod tests {
 
     #[test]
     fn test_does_not_submit_solution_for_which_validation_failed() {
-        let reader = MockStableXOrderBookReading::default();
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
         let mut submitter = MockStableXSolutionSubmitting::default();
         let pf = MockPriceFinding::default();
         let economic_viability = Arc::new(MockEconomicViabilityComputing::new());
@@ -452,9 +1139,50 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn does_not_submit_solution_that_fails_local_verification() {
+        let mut reader = MockStableXOrderBookReading::default();
+        let mut submitter = MockStableXSolutionSubmitting::default();
+        let pf = MockPriceFinding::default();
+        let economic_viability = Arc::new(MockEconomicViabilityComputing::new());
+        let metrics = StableXMetrics::default();
+
+        let orders = vec![create_order_for_test(), create_order_for_test()];
+        let batch = 42;
+
+        reader
+            .expect_verify_solution()
+            .with(eq(batch), always())
+            .returning(|_, _| Err(anyhow!("solution overdraws balance")));
+        submitter.expect_get_solution_objective_value().times(0);
+        submitter.expect_submit_solution().times(0);
+
+        let solution = Solution {
+            prices: map_from_slice(&[(0, 1), (1, 2)]),
+            executed_orders: vec![
+                order_to_executed_order(&orders[0], 0, 0),
+                order_to_executed_order(&orders[1], 2, 2),
+            ],
+        };
+
+        let driver = StableXDriverImpl::new(
+            Arc::new(pf),
+            Arc::new(reader),
+            Arc::new(submitter),
+            economic_viability,
+            Arc::new(metrics),
+        );
+        assert!(driver
+            .submit_solution(BatchId::from(batch), solution)
+            .now_or_never()
+            .unwrap()
+            .is_ok());
+    }
+
     #[test]
     fn test_do_not_fail_on_benign_verification_error() {
-        let reader = MockStableXOrderBookReading::default();
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
         let mut submitter = MockStableXSolutionSubmitting::default();
         let pf = MockPriceFinding::default();
         let economic_viability = Arc::new(MockEconomicViabilityComputing::new());
@@ -493,7 +1221,8 @@ mod tests {
 
     #[test]
     fn test_do_not_fail_on_benign_submission_error() {
-        let reader = MockStableXOrderBookReading::default();
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
         let mut submitter = MockStableXSolutionSubmitting::default();
         let pf = MockPriceFinding::default();
         let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
@@ -506,10 +1235,20 @@ mod tests {
             .expect_get_solution_objective_value()
             .with(eq(batch), always())
             .returning(|_, _| Ok(42.into()));
+        submitter
+            .expect_estimate_gas_limit()
+            .with(eq(batch), always(), eq(U256::from(42)))
+            .returning(|_, _, _| Ok(300_000.into()));
         submitter
             .expect_submit_solution()
-            .with(eq(batch), always(), eq(U256::from(42)), always())
-            .returning(|_, _, _, _| {
+            .with(
+                eq(batch),
+                always(),
+                eq(U256::from(42)),
+                eq(U256::from(300_000)),
+                always(),
+            )
+            .returning(|_, _, _, _, _| {
                 Err(SolutionSubmissionError::Benign("Benign Error".to_owned()))
             });
 
@@ -535,6 +1274,65 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn records_receipt_of_submitted_solution() {
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
+        let mut submitter = MockStableXSolutionSubmitting::default();
+        let economic_viability = Arc::new(FixedEconomicViabilityComputer::new(0, 0.into()));
+
+        let orders = vec![create_order_for_test(), create_order_for_test()];
+        let batch = 42;
+
+        submitter
+            .expect_get_solution_objective_value()
+            .returning(|_, _| Ok(42.into()));
+        submitter
+            .expect_estimate_gas_limit()
+            .returning(|_, _, _| Ok(300_000.into()));
+        submitter
+            .expect_submit_solution()
+            .returning(|batch_index, _, _, _, _| {
+                Ok(SubmissionReceipt {
+                    batch_index,
+                    gas_used: Some(100_000.into()),
+                    ..Default::default()
+                })
+            });
+
+        let solution = Solution {
+            prices: map_from_slice(&[(0, 1), (1, 2)]),
+            executed_orders: vec![
+                order_to_executed_order(&orders[0], 0, 0),
+                order_to_executed_order(&orders[1], 2, 2),
+            ],
+        };
+
+        let submission_receipts = Arc::new(SubmissionReceipts::new(10));
+        let driver = StableXDriverImpl::new(
+            Arc::new(MockPriceFinding::default()),
+            Arc::new(reader),
+            Arc::new(submitter),
+            economic_viability,
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_submission_receipts(submission_receipts.clone());
+        driver
+            .submit_solution(BatchId::from(batch), solution)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            submission_receipts.recent(),
+            vec![SubmissionReceipt {
+                batch_index: batch,
+                gas_used: Some(100_000.into()),
+                ..Default::default()
+            }]
+        );
+    }
+
     #[test]
     fn does_not_invoke_price_finder_when_orderbook_retrieval_exceedes_latest_solution_submit_time()
     {
@@ -576,4 +1374,291 @@ mod tests {
             .unwrap()
             .is_ok());
     }
+
+    const CIRCUIT_BREAKER_CONFIG: CircuitBreakerConfig = CircuitBreakerConfig {
+        failure_threshold: 2,
+        cooldown: 1,
+        max_cooldown: 2,
+    };
+
+    #[test]
+    fn circuit_breaker_opens_after_consecutive_failures_at_same_stage() {
+        let mut circuit_breaker = CircuitBreaker::new(CIRCUIT_BREAKER_CONFIG);
+
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(1), FailureStage::Verification),
+            None
+        );
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(2), FailureStage::Submission),
+            None
+        );
+        assert!(!circuit_breaker.record_success(BatchId(3)));
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(4), FailureStage::Submission),
+            None
+        );
+        // Retries of the same batch only count once.
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(4), FailureStage::Submission),
+            None
+        );
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(5), FailureStage::Submission),
+            Some(1)
+        );
+
+        assert!(!circuit_breaker.allows(BatchId(6)));
+        assert!(circuit_breaker.allows(BatchId(7)));
+        assert!(circuit_breaker.record_success(BatchId(7)));
+        assert_eq!(circuit_breaker.state, CircuitState::Closed);
+    }
+
+    #[test]
+    fn circuit_breaker_escalates_cooldown_when_failing_after_cooldown() {
+        let mut circuit_breaker = CircuitBreaker::new(CIRCUIT_BREAKER_CONFIG);
+        circuit_breaker.record_failure(BatchId(1), FailureStage::Solver);
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(2), FailureStage::Solver),
+            Some(1)
+        );
+
+        assert!(circuit_breaker.allows(BatchId(4)));
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(4), FailureStage::Orderbook),
+            Some(2)
+        );
+        assert!(!circuit_breaker.allows(BatchId(6)));
+        assert!(circuit_breaker.allows(BatchId(7)));
+        assert_eq!(
+            circuit_breaker.record_failure(BatchId(7), FailureStage::Orderbook),
+            Some(2)
+        );
+
+        assert!(circuit_breaker.allows(BatchId(10)));
+        assert!(circuit_breaker.record_success(BatchId(10)));
+        assert_eq!(circuit_breaker.cooldown, 1);
+    }
+
+    #[test]
+    fn skips_batches_while_circuit_breaker_is_open() {
+        let mut reader = MockStableXOrderBookReading::default();
+        reader
+            .expect_get_auction_data_for_batch()
+            .times(2)
+            .returning(|_| Ok((AccountState::default(), vec![create_order_for_test()])));
+        let mut economic_viability = MockEconomicViabilityComputing::new();
+        economic_viability
+            .expect_min_average_fee()
+            .returning(|| Ok(0));
+        let mut pf = MockPriceFinding::default();
+        pf.expect_find_prices()
+            .times(2)
+            .returning(|_, _, _, _| Err(anyhow!("solver crashed")));
+        let mut health = MockHealthReporting::new();
+        health
+            .expect_notify_circuit_breaker_open()
+            .with(eq(true))
+            .times(1)
+            .return_const(());
+
+        let driver = StableXDriverImpl::new(
+            Arc::new(pf),
+            Arc::new(reader),
+            Arc::new(MockStableXSolutionSubmitting::default()),
+            Arc::new(economic_viability),
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_circuit_breaker(CIRCUIT_BREAKER_CONFIG, Arc::new(health));
+
+        for batch in 1..=2 {
+            assert!(matches!(
+                driver
+                    .solve_batch(BatchId(batch), Duration::from_secs(60))
+                    .now_or_never()
+                    .unwrap(),
+                Err(DriverError::Skip(_))
+            ));
+        }
+        let solution = driver
+            .solve_batch(BatchId(3), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
+    #[test]
+    fn selects_among_racing_solutions() {
+        let orders = vec![create_order_for_test(), create_order_for_test()];
+        let state = AccountState::with_balance_for(&orders);
+        let solution = |amount| Solution {
+            prices: map_from_slice(&[(0, 1), (1, 2)]),
+            executed_orders: vec![order_to_executed_order(&orders[0], amount, amount)],
+        };
+        let (worse, better) = (solution(1), solution(2));
+
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_get_auction_data_for_batch().return_once({
+            let result = (state, orders.clone());
+            move |_| Ok(result)
+        });
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
+
+        let mut pf = MockPriceFinding::default();
+        pf.expect_find_prices().return_once({
+            let solution = worse.clone();
+            move |_, _, _, _| Ok(solution)
+        });
+        let mut racing_pf = MockPriceFinding::default();
+        racing_pf.expect_find_prices().return_once({
+            let solution = better.clone();
+            move |_, _, _, _| Ok(solution)
+        });
+        let mut failing_pf = MockPriceFinding::default();
+        failing_pf
+            .expect_find_prices()
+            .return_once(|_, _, _, _| Err(anyhow!("error")));
+
+        let mut submitter = MockStableXSolutionSubmitting::default();
+        submitter
+            .expect_get_solution_objective_value()
+            .returning(|_, solution| Ok(solution.executed_orders[0].sell_amount.into()));
+
+        let driver = StableXDriverImpl::new(
+            Arc::new(pf),
+            Arc::new(reader),
+            Arc::new(submitter),
+            Arc::new(FixedEconomicViabilityComputer::new(0, 0.into())),
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_racing_price_finders(
+            vec![
+                Arc::new(racing_pf) as Arc<dyn PriceFinding + Send + Sync>,
+                Arc::new(failing_pf),
+            ],
+            Box::new(ObjectiveValue),
+        );
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, better);
+    }
+
+    #[test]
+    fn ranks_solution_without_racing_price_finders() {
+        let orders = vec![create_order_for_test(), create_order_for_test()];
+        let state = AccountState::with_balance_for(&orders);
+        let solution = Solution {
+            prices: map_from_slice(&[(0, 1), (1, 2)]),
+            executed_orders: vec![order_to_executed_order(&orders[0], 1, 1)],
+        };
+
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_get_auction_data_for_batch().return_once({
+            let result = (state, orders.clone());
+            move |_| Ok(result)
+        });
+        reader.expect_verify_solution().returning(|_, _| Ok(()));
+
+        let mut pf = MockPriceFinding::default();
+        pf.expect_find_prices()
+            .return_once(move |_, _, _, _| Ok(solution));
+
+        let mut submitter = MockStableXSolutionSubmitting::default();
+        submitter
+            .expect_get_solution_objective_value()
+            .returning(|_, _| Ok(1.into()));
+
+        let driver = StableXDriverImpl::new(
+            Arc::new(pf),
+            Arc::new(reader),
+            Arc::new(submitter),
+            Arc::new(FixedEconomicViabilityComputer::new(0, 0.into())),
+            Arc::new(StableXMetrics::default()),
+        )
+        .with_racing_price_finders(
+            Vec::new(),
+            solution_ranking::create(SolutionRankingPolicy::ObjectiveValue, 0.0, &[1]),
+        );
+        let solution = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution, Solution::trivial());
+    }
+
+    #[test]
+    fn submits_selected_candidate_without_evaluating_it_again() {
+        let orders = vec![create_order_for_test(), create_order_for_test()];
+        let state = AccountState::with_balance_for(&orders);
+        let solution = Solution {
+            prices: map_from_slice(&[(0, 1), (1, 2)]),
+            executed_orders: vec![order_to_executed_order(&orders[0], 1, 1)],
+        };
+
+        let mut reader = MockStableXOrderBookReading::default();
+        reader.expect_get_auction_data_for_batch().return_once({
+            let result = (state, orders.clone());
+            move |_| Ok(result)
+        });
+        reader
+            .expect_verify_solution()
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let mut pf = MockPriceFinding::default();
+        pf.expect_find_prices().return_once({
+            let solution = solution.clone();
+            move |_, _, _, _| Ok(solution)
+        });
+
+        let mut submitter = MockStableXSolutionSubmitting::default();
+        submitter
+            .expect_get_solution_objective_value()
+            .times(1)
+            .returning(|_, _| Ok(42.into()));
+        submitter
+            .expect_estimate_gas_limit()
+            .with(eq(42), always(), eq(U256::from(42)))
+            .returning(|_, _, _| Ok(300_000.into()));
+        submitter
+            .expect_submit_solution()
+            .with(
+                eq(42),
+                eq(solution.clone()),
+                eq(U256::from(42)),
+                eq(U256::from(300_000)),
+                always(),
+            )
+            .times(1)
+            .returning(|batch_index, _, _, _, _| {
+                Ok(SubmissionReceipt {
+                    batch_index,
+                    ..Default::default()
+                })
+            });
+
+        let driver = StableXDriverImpl::new(
+            Arc::new(pf),
+            Arc::new(reader),
+            Arc::new(submitter),
+            Arc::new(FixedEconomicViabilityComputer::new(0, 0.into())),
+            Arc::new(StableXMetrics::default()),
+        );
+        let selected = driver
+            .solve_batch(BatchId(42), Duration::from_secs(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected, solution);
+        driver
+            .submit_solution(BatchId(42), selected)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -0,0 +1,119 @@
+//! Module keeping the receipts of submitted solutions so that operators can
+//! look up the transactions of the most recent batches without searching the
+//! chain.
+
+use crate::{http_server::Handler, solution_submission::SubmissionReceipt};
+use anyhow::{Context as _, Result};
+use rouille::{Request, Response};
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// The receipts of the most recent solution submissions, newest first.
+/// Receipts can additionally be archived by appending them as JSON lines to a
+/// file.
+pub struct SubmissionReceipts {
+    capacity: usize,
+    recent: Mutex<VecDeque<SubmissionReceipt>>,
+    archive: Option<PathBuf>,
+}
+
+impl SubmissionReceipts {
+    /// Creates a store keeping the specified number of recent receipts.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            archive: None,
+        }
+    }
+
+    /// Appends every recorded receipt to the file at the specified path.
+    pub fn with_archive(mut self, path: PathBuf) -> Self {
+        self.archive = Some(path);
+        self
+    }
+
+    /// Records the receipt of a submitted solution. Failing to archive the
+    /// receipt is logged.
+    pub fn record(&self, receipt: SubmissionReceipt) {
+        if let Some(path) = &self.archive {
+            if let Err(err) = archive_receipt(path, &receipt) {
+                log::error!("failed to archive submission receipt: {:?}", err);
+            }
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_front(receipt);
+        recent.truncate(self.capacity);
+    }
+
+    /// Returns the most recent receipts, newest first.
+    pub fn recent(&self) -> Vec<SubmissionReceipt> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn archive_receipt(path: &Path, receipt: &SubmissionReceipt) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    write_receipt(file, receipt)
+}
+
+/// Writes a receipt as a single JSON line.
+fn write_receipt(mut output: impl Write, receipt: &SubmissionReceipt) -> Result<()> {
+    let mut line = serde_json::to_vec(receipt)?;
+    line.push(b'\n');
+    output.write_all(&line)?;
+    Ok(())
+}
+
+impl Handler for SubmissionReceipts {
+    fn handle_request(&self, _: &Request) -> Result<Response> {
+        Ok(Response::json(&self.recent()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(batch_index: u32) -> SubmissionReceipt {
+        SubmissionReceipt {
+            batch_index,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_most_recent_receipts() {
+        let receipts = SubmissionReceipts::new(2);
+        for batch_index in 1..=3 {
+            receipts.record(receipt(batch_index));
+        }
+        assert_eq!(receipts.recent(), vec![receipt(3), receipt(2)]);
+    }
+
+    #[test]
+    fn writes_receipts_as_json_lines() {
+        let mut output = Vec::new();
+        write_receipt(&mut output, &receipt(1)).unwrap();
+        write_receipt(&mut output, &receipt(2)).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"batchIndex\":1,\
+              \"transactionHash\":\"0x0000000000000000000000000000000000000000000000000000000000000000\",\
+              \"blockNumber\":null,\"gasPrice\":\"0x0\",\"gasUsed\":null}\n\
+             {\"batchIndex\":2,\
+              \"transactionHash\":\"0x0000000000000000000000000000000000000000000000000000000000000000\",\
+              \"blockNumber\":null,\"gasPrice\":\"0x0\",\"gasUsed\":null}\n"
+        );
+    }
+}
@@ -1,14 +1,13 @@
 //! Module implementing minimum average fee computation based on reference token
 //! price estimates.
 
-use crate::models::solution::EconomicViabilityInfo;
-use anyhow::{anyhow, Context as _, Result};
+use crate::models::solution::{EconomicViabilityInfo, GAS_PER_TRADE};
+use anyhow::{anyhow, ensure, Context as _, Result};
 use gas_estimation::GasPriceEstimating;
-use std::{num::NonZeroU128, sync::Arc};
-
-/// The approximate amount of gas used in a solution per trade. In practice the value depends on how
-/// much gas is used in the reversion of the previous solution.
-const GAS_PER_TRADE: f64 = 120_000.0;
+use std::{
+    num::NonZeroU128,
+    sync::{Arc, Mutex},
+};
 
 arg_enum! {
     #[derive(Debug)]
@@ -27,11 +26,20 @@ impl EconomicViabilityStrategy {
         &self,
         subsidy_factor: f64,
         min_avg_fee_factor: f64,
+        smoothing: ThresholdSmoothing,
         static_min_avg_fee_per_order: Option<u128>,
         static_max_gas_price: Option<u128>,
         native_token_price: Arc<dyn NativeTokenPricing + Send + Sync>,
         gas_station: Arc<dyn GasPriceEstimating>,
     ) -> Result<Arc<dyn EconomicViabilityComputing>> {
+        ensure!(
+            smoothing.smoothing_factor > 0.0 && smoothing.smoothing_factor <= 1.0,
+            "smoothing factor must be in the range (0, 1]"
+        );
+        ensure!(
+            smoothing.hysteresis >= 0.0,
+            "hysteresis must not be negative"
+        );
         let make_dynamic = || {
             DynamicEconomicViabilityComputer::new(
                 native_token_price,
@@ -39,6 +47,7 @@ impl EconomicViabilityStrategy {
                 subsidy_factor,
                 min_avg_fee_factor,
             )
+            .with_smoothing(smoothing)
         };
         let make_fixed = || -> Result<_> {
             let min_avg_fee =
@@ -90,6 +99,8 @@ pub struct DynamicEconomicViabilityComputer {
     /// amount it will still be end up economically viable even when the gas or native token price moves
     /// slightly between solution computation and submission.
     min_avg_fee_factor: f64,
+    smoothing: ThresholdSmoothing,
+    smoothing_state: Mutex<SmoothingState>,
 }
 
 impl DynamicEconomicViabilityComputer {
@@ -104,9 +115,18 @@ impl DynamicEconomicViabilityComputer {
             gas_station,
             subsidy_factor,
             min_avg_fee_factor,
+            smoothing: ThresholdSmoothing::default(),
+            smoothing_state: Mutex::new(SmoothingState::default()),
         }
     }
 
+    /// Smooths the min average fee so that it changes gradually instead of
+    /// following every spike of the gas price.
+    pub fn with_smoothing(mut self, smoothing: ThresholdSmoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
     async fn native_token_price_in_owl(&self) -> Result<f64> {
         self.price_oracle
             .get_native_token_price()
@@ -132,6 +152,9 @@ impl EconomicViabilityComputing for DynamicEconomicViabilityComputer {
         let gas_price = self.gas_price().await?;
 
         let fee = min_average_fee(native_token_price, gas_price) * self.min_avg_fee_factor;
+        let fee = self
+            .smoothing
+            .update(&mut self.smoothing_state.lock().unwrap(), fee);
         let subsidized = fee / self.subsidy_factor;
         log::debug!(
                 "computed min average fee to be {}, subsidized to {} based on native token price {} gas price {}",
@@ -143,18 +166,64 @@ impl EconomicViabilityComputing for DynamicEconomicViabilityComputer {
 
     async fn max_gas_price(&self, economic_viability_info: EconomicViabilityInfo) -> Result<f64> {
         let earned_fee = economic_viability_info.earned_fee.to_f64_lossy();
-        let num_trades = economic_viability_info.num_executed_orders;
+        let estimated_gas = economic_viability_info.estimated_gas;
         let native_token_price = self.native_token_price_in_owl().await?;
-        let cap = gas_price_cap(native_token_price, earned_fee, num_trades);
+        let cap = gas_price_cap(native_token_price, earned_fee, estimated_gas);
         let subsidized = cap * self.subsidy_factor;
         log::debug!(
-                "computed max gas price to be {} subsidized to {} based on earned fee {} num trades {} native token price {}",
-                cap, subsidized, earned_fee, num_trades, native_token_price
+                "computed max gas price to be {} subsidized to {} based on earned fee {} estimated gas {} native token price {}",
+                cap, subsidized, earned_fee, estimated_gas, native_token_price
             );
         Ok(subsidized)
     }
 }
 
+/// Exponential smoothing with a hysteresis band for a dynamically computed
+/// threshold.
+#[derive(Clone, Copy, Debug)]
+pub struct ThresholdSmoothing {
+    /// The weight of a new sample in the exponential moving average, in the
+    /// range (0, 1]. A factor of 1 disables smoothing.
+    pub smoothing_factor: f64,
+    /// The relative change of the moving average that is needed before the
+    /// threshold is updated. A value of 0 disables the hysteresis band.
+    pub hysteresis: f64,
+}
+
+impl Default for ThresholdSmoothing {
+    fn default() -> Self {
+        ThresholdSmoothing {
+            smoothing_factor: 1.0,
+            hysteresis: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SmoothingState {
+    average: Option<f64>,
+    threshold: Option<f64>,
+}
+
+impl ThresholdSmoothing {
+    /// Adds a sample to the moving average and returns the current threshold.
+    fn update(&self, state: &mut SmoothingState, sample: f64) -> f64 {
+        let average = match state.average {
+            Some(average) => average + self.smoothing_factor * (sample - average),
+            None => sample,
+        };
+        let threshold = match state.threshold {
+            Some(threshold) if (average - threshold).abs() <= threshold * self.hysteresis => {
+                threshold
+            }
+            _ => average,
+        };
+        state.average = Some(average);
+        state.threshold = Some(threshold);
+        threshold
+    }
+}
+
 /// Computes the min average fee per order based on the current native token price in
 /// reference token and a gas price estimate. Returns the minimum average fee
 /// in reference token that must be accumulated per order in order for a
@@ -162,14 +231,13 @@ impl EconomicViabilityComputing for DynamicEconomicViabilityComputer {
 fn min_average_fee(native_token_price: f64, gas_price: f64) -> f64 {
     let owl_per_eth = native_token_price / 1e18;
     let gas_price_in_owl = owl_per_eth * gas_price;
-    GAS_PER_TRADE * gas_price_in_owl
+    GAS_PER_TRADE as f64 * gas_price_in_owl
 }
 
 /// The gas price cap is selected so that submitting solution is still roughly profitable.
-fn gas_price_cap(native_token_price: f64, earned_fee: f64, num_trades: usize) -> f64 {
+fn gas_price_cap(native_token_price: f64, earned_fee: f64, estimated_gas: u64) -> f64 {
     let owl_per_eth = native_token_price / 1e18;
-    let gas_use = GAS_PER_TRADE * (num_trades as f64);
-    earned_fee / (owl_per_eth * gas_use)
+    earned_fee / (owl_per_eth * estimated_gas as f64)
 }
 
 /// Fixed values.
@@ -251,7 +319,7 @@ mod tests {
     #[test]
     fn computes_gas_price_cap() {
         // 50 owl fee, ~600 gwei gas price cap
-        assert_approx_eq!(gas_price_cap(240e18, 50e18, 3), 578703703703.7037);
+        assert_approx_eq!(gas_price_cap(240e18, 50e18, 360_000), 578703703703.7037);
     }
 
     #[test]
@@ -280,6 +348,7 @@ mod tests {
         let info = EconomicViabilityInfo {
             num_executed_orders: 3,
             earned_fee: U256::from(50e18 as u128),
+            estimated_gas: 360_000,
         };
         assert_approx_eq!(
             economic_viability.max_gas_price(info).wait().unwrap(),
@@ -287,6 +356,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn smoothing_dampens_spikes() {
+        let smoothing = ThresholdSmoothing {
+            smoothing_factor: 0.5,
+            hysteresis: 0.0,
+        };
+        let mut state = SmoothingState::default();
+        assert_approx_eq!(smoothing.update(&mut state, 100.0), 100.0);
+        assert_approx_eq!(smoothing.update(&mut state, 300.0), 200.0);
+        assert_approx_eq!(smoothing.update(&mut state, 100.0), 150.0);
+    }
+
+    #[test]
+    fn hysteresis_keeps_threshold_within_band() {
+        let smoothing = ThresholdSmoothing {
+            smoothing_factor: 1.0,
+            hysteresis: 0.1,
+        };
+        let mut state = SmoothingState::default();
+        assert_approx_eq!(smoothing.update(&mut state, 100.0), 100.0);
+        assert_approx_eq!(smoothing.update(&mut state, 109.0), 100.0);
+        assert_approx_eq!(smoothing.update(&mut state, 91.0), 100.0);
+        assert_approx_eq!(smoothing.update(&mut state, 111.0), 111.0);
+        assert_approx_eq!(smoothing.update(&mut state, 105.0), 111.0);
+    }
+
+    #[test]
+    fn dynamic_min_average_fee_is_smoothed() {
+        let mut price_oracle = MockNativeTokenPricing::new();
+        price_oracle
+            .expect_get_native_token_price()
+            .returning(|| Some(nonzero!(1e18 as u128)));
+        let mut gas_station = MockGasPriceEstimating::new();
+        let mut seq = mockall::Sequence::new();
+        for gas_price in &[10.0, 30.0] {
+            let gas_price = *gas_price;
+            gas_station
+                .expect_estimate()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(move || Ok(gas_price));
+        }
+        let economic_viability = DynamicEconomicViabilityComputer::new(
+            Arc::new(price_oracle),
+            Arc::new(gas_station),
+            1.0,
+            1.0,
+        )
+        .with_smoothing(ThresholdSmoothing {
+            smoothing_factor: 0.5,
+            hysteresis: 0.0,
+        });
+
+        let fee_per_gas_price = GAS_PER_TRADE as u128;
+        assert_eq!(
+            economic_viability.min_average_fee().wait().unwrap(),
+            10 * fee_per_gas_price
+        );
+        assert_eq!(
+            economic_viability.min_average_fee().wait().unwrap(),
+            20 * fee_per_gas_price
+        );
+    }
+
     #[test]
     fn combined_strategy_picks_min_min_average_fee() {
         for (fixed_fee, dynamic_fee, expected_fee) in &[(5, 10, 5), (5, 1, 1)] {
@@ -325,6 +458,7 @@ mod tests {
             let info = EconomicViabilityInfo {
                 num_executed_orders: 1,
                 earned_fee: U256::from(*earned_fee),
+                estimated_gas: GAS_PER_TRADE,
             };
             let result = combined
                 .max_gas_price(info)
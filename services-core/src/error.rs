@@ -0,0 +1,243 @@
+//! Module containing stable error codes for the failures of the services.
+//!
+//! Errors are propagated as `anyhow` errors which are only logged as opaque
+//! messages. Error codes classify these errors by the subsystem that failed and
+//! the cause of the failure, so that logs and metrics can be broken down by
+//! cause. Codes must not be renamed as dashboards and alerts depend on them.
+
+use crate::http::{CircuitOpenError, HttpStatusError};
+use anyhow::Error;
+use ethcontract::{
+    errors::{ExecutionError, MethodError},
+    web3::Error as Web3Error,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// The subsystems of the services errors can originate from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Subsystem {
+    /// Requests to the node and other remote APIs.
+    Rpc,
+    /// Retrieving the orderbook.
+    Orderbook,
+    /// Computing solutions.
+    Solver,
+    /// Verifying and submitting solutions.
+    Submission,
+}
+
+impl Subsystem {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Subsystem::Rpc => "rpc",
+            Subsystem::Orderbook => "orderbook",
+            Subsystem::Solver => "solver",
+            Subsystem::Submission => "submission",
+        }
+    }
+}
+
+/// A stable code identifying the cause of an error.
+///
+/// Codes can be attached to errors either as their root cause with
+/// `Error::new(code)` or as context with `Context::context(code)`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorCode {
+    /// A remote request timed out.
+    RpcTimeout,
+    /// A remote API could not be reached or its circuit breaker is open.
+    RpcUnavailable,
+    /// A remote API responded with an error status.
+    RpcHttpStatus,
+    /// The node responded with a JSON RPC error or an invalid response.
+    RpcNodeError,
+    /// Any other failure of the JSON RPC transport.
+    RpcTransport,
+    /// A contract call or transaction reverted.
+    ContractReverted,
+    /// The orderbook could not be fetched.
+    OrderbookFetch,
+    /// A solution failed the verification against the local orderbook.
+    OrderbookVerification,
+    /// The solver failed to produce a solution.
+    SolverFailed,
+    /// The solution transaction was not mined in time.
+    SubmissionTimeout,
+    /// Any other failure while verifying or submitting a solution.
+    SubmissionFailed,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::RpcTimeout,
+        ErrorCode::RpcUnavailable,
+        ErrorCode::RpcHttpStatus,
+        ErrorCode::RpcNodeError,
+        ErrorCode::RpcTransport,
+        ErrorCode::ContractReverted,
+        ErrorCode::OrderbookFetch,
+        ErrorCode::OrderbookVerification,
+        ErrorCode::SolverFailed,
+        ErrorCode::SubmissionTimeout,
+        ErrorCode::SubmissionFailed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::RpcTimeout => "rpc_timeout",
+            ErrorCode::RpcUnavailable => "rpc_unavailable",
+            ErrorCode::RpcHttpStatus => "rpc_http_status",
+            ErrorCode::RpcNodeError => "rpc_node_error",
+            ErrorCode::RpcTransport => "rpc_transport",
+            ErrorCode::ContractReverted => "contract_reverted",
+            ErrorCode::OrderbookFetch => "orderbook_fetch",
+            ErrorCode::OrderbookVerification => "orderbook_verification",
+            ErrorCode::SolverFailed => "solver_failed",
+            ErrorCode::SubmissionTimeout => "submission_timeout",
+            ErrorCode::SubmissionFailed => "submission_failed",
+        }
+    }
+
+    pub fn subsystem(self) -> Subsystem {
+        match self {
+            ErrorCode::RpcTimeout
+            | ErrorCode::RpcUnavailable
+            | ErrorCode::RpcHttpStatus
+            | ErrorCode::RpcNodeError
+            | ErrorCode::RpcTransport
+            | ErrorCode::ContractReverted => Subsystem::Rpc,
+            ErrorCode::OrderbookFetch | ErrorCode::OrderbookVerification => Subsystem::Orderbook,
+            ErrorCode::SolverFailed => Subsystem::Solver,
+            ErrorCode::SubmissionTimeout | ErrorCode::SubmissionFailed => Subsystem::Submission,
+        }
+    }
+
+    /// Classifies an error by the outermost cause in its chain that has a
+    /// known code, falling back to the specified code for errors without a
+    /// known cause.
+    pub fn of(err: &Error, fallback: ErrorCode) -> ErrorCode {
+        ErrorCode::classify(err).unwrap_or(fallback)
+    }
+
+    /// Classifies an error by the outermost cause in its chain that has a
+    /// known code.
+    pub fn classify(err: &Error) -> Option<ErrorCode> {
+        if let Some(code) = err.downcast_ref::<ErrorCode>() {
+            return Some(*code);
+        }
+        err.chain().find_map(|cause| {
+            if let Some(code) = cause.downcast_ref::<ErrorCode>() {
+                Some(*code)
+            } else if cause.is::<CircuitOpenError>() {
+                Some(ErrorCode::RpcUnavailable)
+            } else if cause.is::<HttpStatusError>() {
+                Some(ErrorCode::RpcHttpStatus)
+            } else if let Some(err) = cause.downcast_ref::<isahc::Error>() {
+                Some(classify_http_error(err))
+            } else if let Some(err) = cause.downcast_ref::<MethodError>() {
+                Some(classify_execution_error(&err.inner))
+            } else if let Some(err) = cause.downcast_ref::<ExecutionError>() {
+                Some(classify_execution_error(err))
+            } else {
+                cause.downcast_ref::<Web3Error>().map(classify_web3_error)
+            }
+        })
+    }
+}
+
+fn classify_http_error(err: &isahc::Error) -> ErrorCode {
+    match err {
+        isahc::Error::Timeout => ErrorCode::RpcTimeout,
+        isahc::Error::ConnectFailed
+        | isahc::Error::CouldntResolveHost
+        | isahc::Error::NoResponse => ErrorCode::RpcUnavailable,
+        _ => ErrorCode::RpcTransport,
+    }
+}
+
+fn classify_execution_error(err: &ExecutionError) -> ErrorCode {
+    match err {
+        ExecutionError::Web3(err) => classify_web3_error(err),
+        ExecutionError::Revert(_) | ExecutionError::Failure(_) => ErrorCode::ContractReverted,
+        _ => ErrorCode::RpcNodeError,
+    }
+}
+
+fn classify_web3_error(err: &Web3Error) -> ErrorCode {
+    match err {
+        Web3Error::Rpc(_) | Web3Error::InvalidResponse(_) | Web3Error::Decoder(_) => {
+            ErrorCode::RpcNodeError
+        }
+        _ => ErrorCode::RpcTransport,
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for ErrorCode {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context as _};
+    use ethcontract::jsonrpc::types::Error as RpcError;
+    use isahc::http::StatusCode;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes = ErrorCode::ALL
+            .iter()
+            .map(|code| code.as_str())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn classifies_attached_codes() {
+        let err = Error::new(ErrorCode::SolverFailed).context("solver execution failed");
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::SolverFailed));
+
+        let err = anyhow!("no response")
+            .context(ErrorCode::SubmissionTimeout)
+            .context("failed to submit");
+        assert_eq!(
+            ErrorCode::classify(&err),
+            Some(ErrorCode::SubmissionTimeout)
+        );
+    }
+
+    #[test]
+    fn classifies_rpc_errors() {
+        let err = Error::from(isahc::Error::Timeout).context("failed to fetch prices");
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::RpcTimeout));
+
+        let err = Error::from(HttpStatusError {
+            status: StatusCode::BAD_GATEWAY,
+            body: String::new(),
+        });
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::RpcHttpStatus));
+
+        let err = Error::from(Web3Error::Rpc(RpcError::internal_error()));
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::RpcNodeError));
+
+        let err = Error::from(ExecutionError::Web3(Web3Error::Transport("closed".into())));
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::RpcTransport));
+
+        let err = Error::from(ExecutionError::Revert(None)).context("call failed");
+        assert_eq!(ErrorCode::classify(&err), Some(ErrorCode::ContractReverted));
+    }
+
+    #[test]
+    fn falls_back_for_unknown_errors() {
+        let err = anyhow!("something went wrong");
+        assert_eq!(ErrorCode::classify(&err), None);
+        assert_eq!(
+            ErrorCode::of(&err, ErrorCode::OrderbookFetch),
+            ErrorCode::OrderbookFetch
+        );
+    }
+}
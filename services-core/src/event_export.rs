@@ -0,0 +1,427 @@
+//! Export of decoded exchange contract events to a message bus, so that other
+//! services can consume them without indexing the contract themselves.
+//!
+//! Events are published in a versioned JSON schema that is independent of the
+//! generated contract bindings. Since the orderbook fetches the most recent
+//! blocks again on every update to handle reorgs, the same event can be
+//! published more than once. Consumers identify events by their block hash
+//! and log index. Events that were published for blocks which are no longer
+//! part of the chain are retracted with a `Retraction` message.
+//!
+//! Messages are published in the background through a bounded queue, so that
+//! a slow or unavailable message bus does not stall the orderbook.
+
+pub mod nats;
+
+use crate::contracts::stablex_contract::ExchangeEvent;
+use anyhow::Result;
+use async_std::{future, task};
+use contracts::batch_exchange::Event;
+use ethcontract::{Address, H256, U256};
+use futures::{channel::mpsc, stream::StreamExt as _};
+use serde::Serialize;
+use serde_with::rust::display_fromstr;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The version of the exported event schema. It is increased on every change
+/// that is not backwards compatible.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The number of messages that are queued while the sink is slow or
+/// unavailable. Messages exported while the queue is full are dropped.
+const EXPORT_QUEUE_SIZE: usize = 10_000;
+
+/// The time after which publishing a single message is given up.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A destination for exported messages.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync {
+    async fn publish(&self, message: &ExportedMessage) -> Result<()>;
+}
+
+/// Queues messages and publishes them to a sink in a background task.
+pub struct EventExporter {
+    sender: Mutex<mpsc::Sender<ExportedMessage>>,
+}
+
+impl EventExporter {
+    pub fn new(sink: Arc<dyn EventSink>) -> Self {
+        let (sender, receiver) = mpsc::channel(EXPORT_QUEUE_SIZE);
+        task::spawn(publish_messages(sink, receiver, PUBLISH_TIMEOUT));
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Queues a message for publishing without waiting for it to be published.
+    pub fn export(&self, message: ExportedMessage) {
+        if let Err(err) = self.sender.lock().unwrap().try_send(message) {
+            log::warn!(
+                "dropping exported message {:?} because the export queue is full",
+                err.into_inner()
+            );
+        }
+    }
+}
+
+/// Publishes the queued messages in order, giving up on each one after the
+/// timeout.
+async fn publish_messages(
+    sink: Arc<dyn EventSink>,
+    mut receiver: mpsc::Receiver<ExportedMessage>,
+    timeout: Duration,
+) {
+    while let Some(message) = receiver.next().await {
+        match future::timeout(timeout, sink.publish(&message)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => log::warn!("failed to export {:?}: {:?}", message, err),
+            Err(_) => log::warn!("timed out exporting {:?}", message),
+        }
+    }
+}
+
+/// A message published to the sink.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ExportedMessage {
+    Event(ExportedEvent),
+    Retraction(RetractedEvent),
+}
+
+/// An exchange event together with its position in the chain.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedEvent {
+    pub version: u32,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub log_index: usize,
+    pub block_timestamp: u64,
+    #[serde(flatten)]
+    pub data: EventData,
+}
+
+impl ExportedEvent {
+    pub fn new(event: &ExchangeEvent, block_timestamp: u64) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            block_number: event.block_number,
+            block_hash: event.block_hash,
+            log_index: event.log_index,
+            block_timestamp,
+            data: EventData::from(&event.data),
+        }
+    }
+}
+
+/// An event that was previously exported for a block which is no longer part
+/// of the chain.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename = "Retraction", rename_all = "camelCase")]
+pub struct RetractedEvent {
+    pub version: u32,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub log_index: usize,
+}
+
+impl RetractedEvent {
+    pub fn new(block_number: u64, block_hash: H256, log_index: usize) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            block_number,
+            block_hash,
+            log_index,
+        }
+    }
+}
+
+/// The data of an exported event, tagged with its type. Amounts and prices
+/// are encoded as decimal strings because they do not fit into JSON numbers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum EventData {
+    #[serde(rename_all = "camelCase")]
+    Deposit {
+        user: Address,
+        token: Address,
+        #[serde(with = "display_fromstr")]
+        amount: U256,
+        batch_id: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    WithdrawRequest {
+        user: Address,
+        token: Address,
+        #[serde(with = "display_fromstr")]
+        amount: U256,
+        batch_id: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Withdraw {
+        user: Address,
+        token: Address,
+        #[serde(with = "display_fromstr")]
+        amount: U256,
+    },
+    #[serde(rename_all = "camelCase")]
+    TokenListing { token: Address, token_id: u16 },
+    #[serde(rename_all = "camelCase")]
+    OrderPlacement {
+        owner: Address,
+        order_id: u16,
+        buy_token: u16,
+        sell_token: u16,
+        valid_from: u32,
+        valid_until: u32,
+        #[serde(with = "display_fromstr")]
+        price_numerator: u128,
+        #[serde(with = "display_fromstr")]
+        price_denominator: u128,
+    },
+    #[serde(rename_all = "camelCase")]
+    OrderCancellation { owner: Address, order_id: u16 },
+    #[serde(rename_all = "camelCase")]
+    OrderDeletion { owner: Address, order_id: u16 },
+    #[serde(rename_all = "camelCase")]
+    Trade {
+        owner: Address,
+        order_id: u16,
+        sell_token: u16,
+        buy_token: u16,
+        #[serde(with = "display_fromstr")]
+        executed_sell_amount: u128,
+        #[serde(with = "display_fromstr")]
+        executed_buy_amount: u128,
+    },
+    #[serde(rename_all = "camelCase")]
+    TradeReversion {
+        owner: Address,
+        order_id: u16,
+        sell_token: u16,
+        buy_token: u16,
+        #[serde(with = "display_fromstr")]
+        executed_sell_amount: u128,
+        #[serde(with = "display_fromstr")]
+        executed_buy_amount: u128,
+    },
+    #[serde(rename_all = "camelCase")]
+    SolutionSubmission {
+        submitter: Address,
+        #[serde(with = "display_fromstr")]
+        utility: U256,
+        #[serde(with = "display_fromstr")]
+        disregarded_utility: U256,
+        #[serde(with = "display_fromstr")]
+        burnt_fees: U256,
+        #[serde(with = "display_fromstr")]
+        last_auction_burnt_fees: U256,
+        prices: Vec<TokenPrice>,
+    },
+}
+
+/// The price of a token in a submitted solution.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenPrice {
+    pub token_id: u16,
+    #[serde(with = "display_fromstr")]
+    pub price: u128,
+}
+
+impl From<&Event> for EventData {
+    fn from(event: &Event) -> Self {
+        match event {
+            Event::Deposit(event) => EventData::Deposit {
+                user: event.user,
+                token: event.token,
+                amount: event.amount,
+                batch_id: event.batch_id,
+            },
+            Event::WithdrawRequest(event) => EventData::WithdrawRequest {
+                user: event.user,
+                token: event.token,
+                amount: event.amount,
+                batch_id: event.batch_id,
+            },
+            Event::Withdraw(event) => EventData::Withdraw {
+                user: event.user,
+                token: event.token,
+                amount: event.amount,
+            },
+            Event::TokenListing(event) => EventData::TokenListing {
+                token: event.token,
+                token_id: event.id,
+            },
+            Event::OrderPlacement(event) => EventData::OrderPlacement {
+                owner: event.owner,
+                order_id: event.index,
+                buy_token: event.buy_token,
+                sell_token: event.sell_token,
+                valid_from: event.valid_from,
+                valid_until: event.valid_until,
+                price_numerator: event.price_numerator,
+                price_denominator: event.price_denominator,
+            },
+            Event::OrderCancellation(event) => EventData::OrderCancellation {
+                owner: event.owner,
+                order_id: event.id,
+            },
+            Event::OrderDeletion(event) => EventData::OrderDeletion {
+                owner: event.owner,
+                order_id: event.id,
+            },
+            Event::Trade(event) => EventData::Trade {
+                owner: event.owner,
+                order_id: event.order_id,
+                sell_token: event.sell_token,
+                buy_token: event.buy_token,
+                executed_sell_amount: event.executed_sell_amount,
+                executed_buy_amount: event.executed_buy_amount,
+            },
+            Event::TradeReversion(event) => EventData::TradeReversion {
+                owner: event.owner,
+                order_id: event.order_id,
+                sell_token: event.sell_token,
+                buy_token: event.buy_token,
+                executed_sell_amount: event.executed_sell_amount,
+                executed_buy_amount: event.executed_buy_amount,
+            },
+            Event::SolutionSubmission(event) => EventData::SolutionSubmission {
+                submitter: event.submitter,
+                utility: event.utility,
+                disregarded_utility: event.disregarded_utility,
+                burnt_fees: event.burnt_fees,
+                last_auction_burnt_fees: event.last_auction_burnt_fees,
+                prices: event
+                    .token_ids_for_price
+                    .iter()
+                    .zip(&event.prices)
+                    .map(|(&token_id, &price)| TokenPrice { token_id, price })
+                    .collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use contracts::batch_exchange::event_data::{Deposit, SolutionSubmission};
+    use serde_json::json;
+
+    #[test]
+    fn exports_deposit_in_stable_schema() {
+        let event = ExchangeEvent {
+            data: Event::Deposit(Deposit {
+                user: Address::from_low_u64_be(1),
+                token: Address::from_low_u64_be(2),
+                amount: U256::from(10).pow(U256::from(30)),
+                batch_id: 42,
+            }),
+            block_number: 7,
+            block_hash: H256::from_low_u64_be(8),
+            log_index: 3,
+        };
+        assert_eq!(
+            serde_json::to_value(ExportedEvent::new(&event, 1_600_000_000)).unwrap(),
+            json!({
+                "version": 1,
+                "blockNumber": 7,
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000008",
+                "logIndex": 3,
+                "blockTimestamp": 1_600_000_000,
+                "type": "Deposit",
+                "user": "0x0000000000000000000000000000000000000001",
+                "token": "0x0000000000000000000000000000000000000002",
+                "amount": "1000000000000000000000000000000",
+                "batchId": 42,
+            })
+        );
+    }
+
+    #[test]
+    fn exports_solution_prices_by_token() {
+        let event = Event::SolutionSubmission(SolutionSubmission {
+            submitter: Address::zero(),
+            utility: 1.into(),
+            disregarded_utility: 2.into(),
+            burnt_fees: 3.into(),
+            last_auction_burnt_fees: 4.into(),
+            prices: vec![10, 20],
+            token_ids_for_price: vec![1, 2],
+        });
+        assert_eq!(
+            serde_json::to_value(EventData::from(&event)).unwrap()["prices"],
+            json!([
+                { "tokenId": 1, "price": "10" },
+                { "tokenId": 2, "price": "20" },
+            ])
+        );
+    }
+
+    #[test]
+    fn exports_retraction() {
+        let retraction =
+            ExportedMessage::Retraction(RetractedEvent::new(7, H256::from_low_u64_be(8), 3));
+        assert_eq!(
+            serde_json::to_value(retraction).unwrap(),
+            json!({
+                "type": "Retraction",
+                "version": 1,
+                "blockNumber": 7,
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000008",
+                "logIndex": 3,
+            })
+        );
+    }
+
+    /// A sink that never finishes publishing the first message.
+    #[derive(Default)]
+    struct StallingSink {
+        published: Mutex<Vec<ExportedMessage>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventSink for StallingSink {
+        async fn publish(&self, message: &ExportedMessage) -> Result<()> {
+            let first = {
+                let mut published = self.published.lock().unwrap();
+                published.push(message.clone());
+                published.len() == 1
+            };
+            if first {
+                futures::future::pending::<()>().await;
+            }
+            Err(anyhow!("error"))
+        }
+    }
+
+    #[test]
+    fn publishing_continues_after_timeouts_and_errors() {
+        let sink = Arc::new(StallingSink::default());
+        let (mut sender, receiver) = mpsc::channel(10);
+        let messages = (0..3)
+            .map(|log_index| {
+                ExportedMessage::Retraction(RetractedEvent::new(0, H256::zero(), log_index))
+            })
+            .collect::<Vec<_>>();
+        for message in &messages {
+            sender.try_send(message.clone()).unwrap();
+        }
+        drop(sender);
+
+        task::block_on(publish_messages(
+            sink.clone(),
+            receiver,
+            Duration::from_millis(10),
+        ));
+        assert_eq!(*sink.published.lock().unwrap(), messages);
+    }
+}
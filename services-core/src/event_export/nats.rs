@@ -0,0 +1,150 @@
+//! Publishing of exported events to a NATS subject using the plain text NATS
+//! client protocol.
+
+use super::{EventSink, ExportedMessage};
+use anyhow::{anyhow, bail, Context as _, Result};
+use async_std::{
+    io::BufReader,
+    net::{Shutdown, TcpStream},
+    prelude::*,
+    task,
+};
+use futures::lock::Mutex;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use url::Url;
+
+const DEFAULT_PORT: u16 = 4222;
+
+/// Publishes events to a NATS subject. The connection is established on the
+/// first publish and again after it was lost. Events published while the
+/// server is unavailable are lost.
+pub struct NatsEventSink {
+    address: String,
+    subject: String,
+    connection: Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    /// Shared with the task that answers server pings.
+    stream: Arc<Mutex<TcpStream>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl NatsEventSink {
+    /// Creates a sink for a server URL of the form `nats://host:port`.
+    pub fn new(url: &Url, subject: impl Into<String>) -> Result<Self> {
+        let subject = subject.into();
+        if subject.is_empty() || subject.contains(char::is_whitespace) {
+            bail!("invalid NATS subject {:?}", subject);
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("NATS URL {} has no host", url))?;
+        Ok(Self {
+            address: format!("{}:{}", host, url.port().unwrap_or(DEFAULT_PORT)),
+            subject,
+            connection: Mutex::new(None),
+        })
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .with_context(|| format!("failed to connect to NATS server {}", self.address))?;
+        let mut lines = BufReader::new(stream.clone()).lines();
+        match lines.next().await {
+            Some(Ok(line)) if line.starts_with("INFO") => (),
+            _ => bail!("NATS server {} did not send INFO", self.address),
+        }
+        let stream = Arc::new(Mutex::new(stream));
+        stream
+            .lock()
+            .await
+            .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")
+            .await?;
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let (ping_stream, ping_closed) = (stream.clone(), closed.clone());
+        task::spawn(async move {
+            while let Some(Ok(line)) = lines.next().await {
+                if line == "PING" {
+                    if ping_stream
+                        .lock()
+                        .await
+                        .write_all(b"PONG\r\n")
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                } else if line.starts_with("-ERR") {
+                    log::warn!("NATS server error: {}", line);
+                }
+            }
+            ping_closed.store(true, Ordering::SeqCst);
+        });
+        Ok(Connection { stream, closed })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for NatsEventSink {
+    async fn publish(&self, message: &ExportedMessage) -> Result<()> {
+        let payload = serde_json::to_vec(message)?;
+        let message = publish_message(&self.subject, &payload);
+        let mut connection = self.connection.lock().await;
+        let current = match connection.take() {
+            Some(current) if !current.closed.load(Ordering::SeqCst) => current,
+            _ => self.connect().await?,
+        };
+
+        let mut stream = current.stream.lock().await;
+        if let Err(err) = stream.write_all(&message).await {
+            // Stops the ping task so that the next publish reconnects.
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(err.into());
+        }
+        drop(stream);
+        *connection = Some(current);
+        Ok(())
+    }
+}
+
+/// Encodes a NATS `PUB` message.
+fn publish_message(subject: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+    message.extend_from_slice(payload);
+    message.extend_from_slice(b"\r\n");
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_publish_message() {
+        assert_eq!(
+            publish_message("exchange.events", b"{}"),
+            b"PUB exchange.events 2\r\n{}\r\n"
+        );
+    }
+
+    #[test]
+    fn parses_server_url() {
+        let sink = NatsEventSink::new(&"nats://localhost".parse().unwrap(), "events").unwrap();
+        assert_eq!(sink.address, "localhost:4222");
+        let sink = NatsEventSink::new(&"nats://10.0.0.1:4333".parse().unwrap(), "events").unwrap();
+        assert_eq!(sink.address, "10.0.0.1:4333");
+    }
+
+    #[test]
+    fn rejects_invalid_subject() {
+        let url = "nats://localhost".parse().unwrap();
+        assert!(NatsEventSink::new(&url, "").is_err());
+        assert!(NatsEventSink::new(&url, "exchange events").is_err());
+    }
+}
@@ -1,11 +1,23 @@
-use crate::{contracts::Web3, http::HttpClient, http::HttpFactory, metrics::HttpLabel};
-use anyhow::{anyhow, Result};
-use gas_estimation::{
-    EthGasStation, GasNowGasStation, GnosisSafeGasStation, PriorityGasPriceEstimating, Transport,
+mod block_percentile;
+mod feedback;
+mod gasnow_websocket;
+
+pub use self::{
+    block_percentile::BlockPercentileEstimator,
+    feedback::{AttributedGasPriceEstimator, Estimate, GasEstimateFeedback, SubmissionEstimator},
+    gasnow_websocket::{GasNowWebSocket, DEFAULT_URL as GASNOW_WEBSOCKET_URL},
 };
+
+use crate::{
+    contracts::Web3,
+    http::{HttpClient, HttpFactory, HttpService},
+    metrics::HttpLabel,
+};
+use anyhow::{anyhow, Result};
+use gas_estimation::{EthGasStation, GasNowGasStation, GnosisSafeGasStation, Transport};
 use isahc::http::uri::Uri;
 use serde::de::DeserializeOwned;
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 pub use gas_estimation::GasPriceEstimating;
 
@@ -20,8 +32,10 @@ impl Transport for HttpClient {
 arg_enum! {
     #[derive(Debug)]
     pub enum GasEstimatorType {
+        BlockPercentile,
         EthGasStation,
         GasNow,
+        GasNowWebSocket,
         GnosisSafe,
         Web3,
     }
@@ -31,38 +45,69 @@ pub async fn create_priority_estimator(
     http_factory: &HttpFactory,
     web3: &Web3,
     estimator_types: &[GasEstimatorType],
-) -> Result<Arc<dyn GasPriceEstimating>> {
+) -> Result<Arc<AttributedGasPriceEstimator>> {
     let network_id = web3.net().version().await?;
-    let mut estimators = Vec::<Box<dyn GasPriceEstimating>>::new();
+    let mut estimators = Vec::<(String, Box<dyn GasPriceEstimating>)>::new();
     for estimator_type in estimator_types {
-        match estimator_type {
+        let estimator: Box<dyn GasPriceEstimating> = match estimator_type {
             GasEstimatorType::EthGasStation => {
                 if !is_mainnet(&network_id) {
                     return Err(anyhow!("EthGasStation only supports mainnet"));
                 }
-                estimators.push(Box::new(EthGasStation::new(http_factory.create()?)))
+                Box::new(EthGasStation::new(
+                    http_factory.create_for(HttpService::GasStation)?,
+                ))
             }
             GasEstimatorType::GasNow => {
                 if !is_mainnet(&network_id) {
                     return Err(anyhow!("GasNow only supports mainnet"));
                 }
-                estimators.push(Box::new(GasNowGasStation::new(http_factory.create()?)))
+                Box::new(GasNowGasStation::new(
+                    http_factory.create_for(HttpService::GasStation)?,
+                ))
             }
-            GasEstimatorType::GnosisSafe => estimators.push(Box::new(
-                GnosisSafeGasStation::with_network_id(&network_id, http_factory.create()?)?,
-            )),
-            GasEstimatorType::Web3 => estimators.push(Box::new(web3.clone())),
-        }
+            GasEstimatorType::GasNowWebSocket => {
+                if !is_mainnet(&network_id) {
+                    return Err(anyhow!("GasNow only supports mainnet"));
+                }
+                Box::new(GasNowWebSocket::connect(GASNOW_WEBSOCKET_URL))
+            }
+            GasEstimatorType::GnosisSafe => Box::new(GnosisSafeGasStation::with_network_id(
+                &network_id,
+                http_factory.create_for(HttpService::GasStation)?,
+            )?),
+            GasEstimatorType::Web3 => Box::new(web3.clone()),
+            GasEstimatorType::BlockPercentile => {
+                Box::new(BlockPercentileEstimator::new(web3.clone()))
+            }
+        };
+        estimators.push((estimator_type.to_string(), estimator));
     }
-    Ok(Arc::new(PriorityGasPriceEstimating::new(estimators)))
+    Ok(Arc::new(AttributedGasPriceEstimator::new(estimators)))
 }
 
 fn is_mainnet(network_id: &str) -> bool {
     network_id == "1"
 }
 
-#[cfg(test)]
-use std::time::Duration;
+/// Linearly interpolates between points sorted by time limit, clamping to the
+/// first and last value outside of their range.
+fn linear_interpolation(points: &[(Duration, f64)], time_limit: Duration) -> f64 {
+    let (first_time, first_value) = points[0];
+    if time_limit <= first_time {
+        return first_value;
+    }
+    for window in points.windows(2) {
+        let ((lower_time, lower_value), (upper_time, upper_value)) = (window[0], window[1]);
+        if time_limit <= upper_time {
+            let progress =
+                (time_limit - lower_time).as_secs_f64() / (upper_time - lower_time).as_secs_f64();
+            return lower_value + (upper_value - lower_value) * progress;
+        }
+    }
+    points[points.len() - 1].1
+}
+
 #[cfg(test)]
 mockall::mock! {
     pub GasPriceEstimating {}
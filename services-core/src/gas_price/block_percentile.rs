@@ -0,0 +1,153 @@
+//! Gas price estimation from the gas prices of transactions in recent blocks.
+
+use super::linear_interpolation;
+use crate::contracts::Web3;
+use anyhow::{anyhow, Context as _, Result};
+use ethcontract::web3::types::{BlockId, BlockNumber};
+use futures::future;
+use gas_estimation::GasPriceEstimating;
+use std::{collections::BTreeMap, ops::RangeInclusive, sync::Mutex, time::Duration};
+
+/// The number of most recent blocks whose transactions are considered.
+const BLOCK_COUNT: u64 = 20;
+
+/// The percentile of recently included gas prices to pay by time limit.
+/// Shorter time limits need a gas price that outbids more transactions.
+const PERCENTILES: &[(Duration, f64)] = &[
+    (Duration::from_secs(15), 90.0),
+    (Duration::from_secs(60), 60.0),
+    (Duration::from_secs(180), 40.0),
+    (Duration::from_secs(600), 20.0),
+];
+
+/// A gas price estimator that only relies on the node. It interpolates the
+/// percentile of gas prices included in the last blocks by time limit, which
+/// reacts to congestion better than `eth_gasPrice` on chains without third
+/// party gas price APIs.
+pub struct BlockPercentileEstimator {
+    web3: Web3,
+    /// The gas prices of the transactions in the recent blocks by block
+    /// number.
+    blocks: Mutex<BTreeMap<u64, Vec<f64>>>,
+}
+
+impl BlockPercentileEstimator {
+    pub fn new(web3: Web3) -> Self {
+        BlockPercentileEstimator {
+            web3,
+            blocks: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the sorted gas prices of the transactions in the recent blocks,
+    /// only fetching the blocks that were mined since the previous call.
+    async fn recent_gas_prices(&self) -> Result<Vec<f64>> {
+        let latest_block = self
+            .web3
+            .eth()
+            .block_number()
+            .await
+            .context("failed to get latest block number")?
+            .as_u64();
+        let new_blocks = blocks_to_fetch(&self.blocks.lock().unwrap(), latest_block);
+        let fetched_blocks = future::try_join_all(new_blocks.clone().map(|number| {
+            self.web3
+                .eth()
+                .block_with_txs(BlockId::Number(BlockNumber::Number(number.into())))
+        }))
+        .await
+        .context("failed to get recent blocks")?;
+
+        let mut blocks = self.blocks.lock().unwrap();
+        for (number, block) in new_blocks.zip(fetched_blocks) {
+            // Blocks the node does not know yet are fetched on the next call.
+            if let Some(block) = block {
+                let gas_prices = block
+                    .transactions
+                    .iter()
+                    .map(|transaction| transaction.gas_price.to_f64_lossy())
+                    .collect();
+                blocks.insert(number, gas_prices);
+            }
+        }
+        *blocks = blocks.split_off(&first_recent_block(latest_block));
+
+        let mut gas_prices = blocks.values().flatten().copied().collect::<Vec<_>>();
+        gas_prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Ok(gas_prices)
+    }
+}
+
+#[async_trait::async_trait]
+impl GasPriceEstimating for BlockPercentileEstimator {
+    async fn estimate(&self) -> Result<f64> {
+        self.estimate_with_limits(21_000.0, PERCENTILES[1].0).await
+    }
+
+    async fn estimate_with_limits(&self, _gas_limit: f64, time_limit: Duration) -> Result<f64> {
+        let gas_prices = self.recent_gas_prices().await?;
+        let percentile = linear_interpolation(PERCENTILES, time_limit);
+        gas_price_at_percentile(&gas_prices, percentile)
+            .ok_or_else(|| anyhow!("no transactions in the last {} blocks", BLOCK_COUNT))
+    }
+}
+
+/// The oldest block that is considered for the specified latest block.
+fn first_recent_block(latest_block: u64) -> u64 {
+    latest_block.saturating_sub(BLOCK_COUNT - 1)
+}
+
+/// Returns the range of recent blocks that are newer than the blocks that
+/// were already fetched.
+fn blocks_to_fetch(blocks: &BTreeMap<u64, Vec<f64>>, latest_block: u64) -> RangeInclusive<u64> {
+    let next_block = blocks.keys().next_back().map_or(0, |block| block + 1);
+    next_block.max(first_recent_block(latest_block))..=latest_block
+}
+
+/// Returns the gas price at a percentile of sorted gas prices, interpolating
+/// linearly between the closest ranks.
+fn gas_price_at_percentile(sorted_gas_prices: &[f64], percentile: f64) -> Option<f64> {
+    let last = sorted_gas_prices.len().checked_sub(1)?;
+    let rank = percentile / 100.0 * last as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    Some(
+        sorted_gas_prices[lower] + (sorted_gas_prices[upper] - sorted_gas_prices[lower]) * fraction,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn computes_percentiles() {
+        let gas_prices = [10.0, 20.0, 30.0, 40.0, 50.0];
+        assert_approx_eq!(gas_price_at_percentile(&gas_prices, 0.0).unwrap(), 10.0);
+        assert_approx_eq!(gas_price_at_percentile(&gas_prices, 50.0).unwrap(), 30.0);
+        assert_approx_eq!(gas_price_at_percentile(&gas_prices, 60.0).unwrap(), 34.0);
+        assert_approx_eq!(gas_price_at_percentile(&gas_prices, 100.0).unwrap(), 50.0);
+        assert_eq!(gas_price_at_percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn only_fetches_new_blocks() {
+        let mut blocks = BTreeMap::new();
+        assert_eq!(blocks_to_fetch(&blocks, 100), 81..=100);
+
+        blocks.insert(99, vec![]);
+        assert_eq!(blocks_to_fetch(&blocks, 99), 100..=99);
+        assert_eq!(blocks_to_fetch(&blocks, 101), 100..=101);
+        assert_eq!(blocks_to_fetch(&blocks, 200), 181..=200);
+    }
+
+    #[test]
+    fn interpolates_percentile_by_time_limit() {
+        let percentile = |secs| linear_interpolation(PERCENTILES, Duration::from_secs(secs));
+        assert_approx_eq!(percentile(5), 90.0);
+        assert_approx_eq!(percentile(120), 50.0);
+        assert_approx_eq!(percentile(3600), 20.0);
+    }
+}
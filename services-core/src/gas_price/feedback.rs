@@ -0,0 +1,224 @@
+//! Feedback on how well gas price estimates worked for solution submissions.
+//!
+//! The configured estimators are queried in priority order and every estimate
+//! is returned together with the estimator that produced it. Once a submission
+//! completes the outcome is attributed to the estimator of the estimate its
+//! last transaction was sent with, which allows comparing the estimators based
+//! on how often transactions were mined within the requested time limit.
+
+use crate::metrics::StableXMetrics;
+use anyhow::{anyhow, Result};
+use gas_estimation::GasPriceEstimating;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A gas price estimate and the estimator that produced it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub estimator: String,
+    pub gas_price: f64,
+}
+
+/// Estimates gas prices with the first of several named estimators that
+/// succeeds.
+pub struct AttributedGasPriceEstimator {
+    estimators: Vec<(String, Box<dyn GasPriceEstimating>)>,
+}
+
+impl AttributedGasPriceEstimator {
+    pub fn new(estimators: Vec<(String, Box<dyn GasPriceEstimating>)>) -> Self {
+        Self { estimators }
+    }
+
+    /// Returns the estimate of the first estimator that succeeds together with
+    /// its name.
+    pub async fn attributed_estimate_with_limits(
+        &self,
+        gas_limit: f64,
+        time_limit: Duration,
+    ) -> Result<Estimate> {
+        let mut result = Err(anyhow!("no gas price estimators configured"));
+        for (name, estimator) in &self.estimators {
+            match estimator.estimate_with_limits(gas_limit, time_limit).await {
+                Ok(gas_price) => {
+                    return Ok(Estimate {
+                        estimator: name.clone(),
+                        gas_price,
+                    })
+                }
+                Err(err) => {
+                    log::warn!("gas price estimator {} failed: {:?}", name, err);
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl GasPriceEstimating for AttributedGasPriceEstimator {
+    async fn estimate(&self) -> Result<f64> {
+        let mut result = Err(anyhow!("no gas price estimators configured"));
+        for (name, estimator) in &self.estimators {
+            match estimator.estimate().await {
+                Ok(gas_price) => return Ok(gas_price),
+                Err(err) => {
+                    log::warn!("gas price estimator {} failed: {:?}", name, err);
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    async fn estimate_with_limits(&self, gas_limit: f64, time_limit: Duration) -> Result<f64> {
+        self.attributed_estimate_with_limits(gas_limit, time_limit)
+            .await
+            .map(|estimate| estimate.gas_price)
+    }
+}
+
+/// Records the outcome of submissions for the estimates they used.
+pub struct GasEstimateFeedback {
+    metrics: Arc<StableXMetrics>,
+    estimator: Arc<AttributedGasPriceEstimator>,
+}
+
+impl GasEstimateFeedback {
+    pub fn new(metrics: Arc<StableXMetrics>, estimator: Arc<AttributedGasPriceEstimator>) -> Self {
+        GasEstimateFeedback { metrics, estimator }
+    }
+
+    /// Returns an estimator for the gas prices of a single submission that
+    /// keeps the estimate its latest transaction was sent with.
+    pub fn submission_estimator(&self) -> SubmissionEstimator {
+        SubmissionEstimator {
+            estimator: self.estimator.clone(),
+            latest: Mutex::new(None),
+        }
+    }
+
+    /// Records the outcome of a submission that used the specified estimate.
+    /// `mined_after` is the time it took until the transaction was mined or
+    /// `None` if it was not mined.
+    pub fn submission_completed(
+        &self,
+        estimate: &Estimate,
+        time_limit: Duration,
+        mined_after: Option<Duration>,
+    ) {
+        log::info!(
+            "gas price {} estimated by {} with time limit {:?} was mined after {:?}",
+            estimate.gas_price,
+            estimate.estimator,
+            time_limit,
+            mined_after
+        );
+        self.metrics
+            .gas_estimate_outcome(&estimate.estimator, time_limit, mined_after);
+    }
+}
+
+/// The gas price estimator of a single submission.
+pub struct SubmissionEstimator {
+    estimator: Arc<AttributedGasPriceEstimator>,
+    latest: Mutex<Option<Estimate>>,
+}
+
+impl SubmissionEstimator {
+    /// Returns the latest estimate of this submission.
+    pub fn into_latest(self) -> Option<Estimate> {
+        self.latest.into_inner().unwrap()
+    }
+}
+
+#[async_trait::async_trait]
+impl GasPriceEstimating for SubmissionEstimator {
+    async fn estimate(&self) -> Result<f64> {
+        self.estimator.estimate().await
+    }
+
+    async fn estimate_with_limits(&self, gas_limit: f64, time_limit: Duration) -> Result<f64> {
+        let estimate = self
+            .estimator
+            .attributed_estimate_with_limits(gas_limit, time_limit)
+            .await?;
+        let gas_price = estimate.gas_price;
+        *self.latest.lock().unwrap() = Some(estimate);
+        Ok(gas_price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gas_price::MockGasPriceEstimating;
+    use futures::FutureExt as _;
+
+    #[test]
+    fn attributes_estimates_to_the_first_working_estimator() {
+        let mut failing = MockGasPriceEstimating::new();
+        failing
+            .expect_estimate_with_limits()
+            .returning(|_, _| Err(anyhow!("error")));
+        let mut working = MockGasPriceEstimating::new();
+        working
+            .expect_estimate_with_limits()
+            .returning(|_, _| Ok(42.0));
+        let estimator = AttributedGasPriceEstimator::new(vec![
+            ("failing".to_string(), Box::new(failing)),
+            ("working".to_string(), Box::new(working)),
+        ]);
+
+        let time_limit = Duration::from_secs(60);
+        let estimate = estimator
+            .attributed_estimate_with_limits(1.0, time_limit)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            estimate,
+            Estimate {
+                estimator: "working".to_string(),
+                gas_price: 42.0,
+            }
+        );
+    }
+
+    #[test]
+    fn submission_estimator_keeps_its_latest_estimate() {
+        let mut working = MockGasPriceEstimating::new();
+        working
+            .expect_estimate_with_limits()
+            .returning(|_, time_limit| Ok(time_limit.as_secs() as f64));
+        let feedback = GasEstimateFeedback::new(
+            Arc::new(StableXMetrics::default()),
+            Arc::new(AttributedGasPriceEstimator::new(vec![(
+                "working".to_string(),
+                Box::new(working),
+            )])),
+        );
+
+        let first = feedback.submission_estimator();
+        let second = feedback.submission_estimator();
+        for (estimator, time_limit) in &[(&first, 60), (&second, 30), (&first, 50)] {
+            estimator
+                .estimate_with_limits(1.0, Duration::from_secs(*time_limit))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let estimate = first.into_latest().unwrap();
+        assert_eq!(estimate.gas_price, 50.0);
+        assert_eq!(second.into_latest().unwrap().gas_price, 30.0);
+        feedback.submission_completed(
+            &estimate,
+            Duration::from_secs(50),
+            Some(Duration::from_secs(30)),
+        );
+    }
+}
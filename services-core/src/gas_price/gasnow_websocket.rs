@@ -0,0 +1,207 @@
+//! GasNow WebSocket subscription keeping the latest gas prices in memory.
+
+use super::linear_interpolation;
+use anyhow::{anyhow, Context, Result};
+use async_std::task;
+use async_tungstenite::{async_std::connect_async, tungstenite::Message};
+use futures::StreamExt as _;
+use gas_estimation::GasPriceEstimating;
+use serde::Deserialize;
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+/// The default GasNow WebSocket API URL.
+pub const DEFAULT_URL: &str = "wss://www.gasnow.org/ws/gasprice";
+
+/// Prices that were not updated for this long are considered stale, in which
+/// case estimating fails so that the next estimator is used.
+const MAX_PRICE_AGE: Duration = Duration::from_secs(60);
+
+/// The time to wait before reconnecting after the connection was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// The confirmation times GasNow targets with its price categories.
+const RAPID_TIME: Duration = Duration::from_secs(15);
+const FAST_TIME: Duration = Duration::from_secs(60);
+const STANDARD_TIME: Duration = Duration::from_secs(180);
+const SLOW_TIME: Duration = Duration::from_secs(600);
+
+/// The latest gas prices and when they were received.
+type SharedPrices = Mutex<Option<(GasPrices, Instant)>>;
+
+/// Gas prices in wei by the targeted confirmation time.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+struct GasPrices {
+    rapid: f64,
+    fast: f64,
+    standard: f64,
+    slow: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GasPriceMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    data: GasPriceData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GasPriceData {
+    gas_prices: GasPrices,
+}
+
+/// A gas price estimator based on the prices pushed by the GasNow WebSocket
+/// API. Unlike the HTTP API it is not rate limited since prices are only
+/// received when they change.
+#[derive(Clone)]
+pub struct GasNowWebSocket(Arc<SharedPrices>);
+
+impl GasNowWebSocket {
+    /// Connects to the WebSocket API in a background task. The connection is
+    /// reestablished when it is lost and closed when the estimator is dropped.
+    pub fn connect(url: impl Into<String>) -> Self {
+        let prices = Arc::new(Mutex::new(None));
+        task::spawn(run(url.into(), Arc::downgrade(&prices)));
+        GasNowWebSocket(prices)
+    }
+
+    /// Returns the latest gas prices unless they are stale.
+    fn prices(&self) -> Result<GasPrices> {
+        let prices = self.0.lock().unwrap();
+        let (prices, received) = prices
+            .as_ref()
+            .ok_or_else(|| anyhow!("no gas prices received from GasNow yet"))?;
+        if received.elapsed() > MAX_PRICE_AGE {
+            return Err(anyhow!(
+                "GasNow gas prices are stale, last update {:?} ago",
+                received.elapsed()
+            ));
+        }
+        Ok(*prices)
+    }
+}
+
+#[async_trait::async_trait]
+impl GasPriceEstimating for GasNowWebSocket {
+    async fn estimate(&self) -> Result<f64> {
+        Ok(self.prices()?.fast)
+    }
+
+    async fn estimate_with_limits(&self, _gas_limit: f64, time_limit: Duration) -> Result<f64> {
+        Ok(interpolate(&self.prices()?, time_limit))
+    }
+}
+
+/// Interpolates the gas price between the price categories for the specified
+/// time limit.
+fn interpolate(prices: &GasPrices, time_limit: Duration) -> f64 {
+    linear_interpolation(
+        &[
+            (RAPID_TIME, prices.rapid),
+            (FAST_TIME, prices.fast),
+            (STANDARD_TIME, prices.standard),
+            (SLOW_TIME, prices.slow),
+        ],
+        time_limit,
+    )
+}
+
+async fn run(url: String, prices: Weak<SharedPrices>) {
+    while prices.upgrade().is_some() {
+        if let Err(err) = connection(&url, &prices).await {
+            log::warn!("GasNow WebSocket connection failed: {:?}", err);
+        }
+        task::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Handles a single WebSocket connection until it fails or the estimator is
+/// dropped.
+async fn connection(url: &str, prices: &Weak<SharedPrices>) -> Result<()> {
+    let (mut socket, _) = connect_async(url)
+        .await
+        .context("failed to connect to GasNow WebSocket API")?;
+    while let Some(message) = socket.next().await {
+        let prices = match prices.upgrade() {
+            Some(prices) => prices,
+            None => return Ok(()),
+        };
+        if let Message::Text(text) = message? {
+            if let Some(update) = parse_message(&text) {
+                *prices.lock().unwrap() = Some((update, Instant::now()));
+            }
+        }
+    }
+    Err(anyhow!("GasNow WebSocket connection closed"))
+}
+
+/// Parses a gas price update. Other messages are only logged.
+fn parse_message(text: &str) -> Option<GasPrices> {
+    match serde_json::from_str::<GasPriceMessage>(text) {
+        Ok(message) if message.kind == "gasprice_s" => Some(message.data.gas_prices),
+        _ => {
+            log::debug!("received GasNow WebSocket message: {}", text);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use futures::FutureExt as _;
+
+    const PRICES: GasPrices = GasPrices {
+        rapid: 100e9,
+        fast: 80e9,
+        standard: 50e9,
+        slow: 20e9,
+    };
+
+    #[test]
+    fn parses_gas_price_messages() {
+        assert_eq!(
+            parse_message(
+                r#"{"type":"gasprice_s","data":{"gasPrices":{"rapid":86760000000,"fast":71000000000,"standard":61000000000,"slow":50000000000},"timestamp":1606996740960}}"#
+            ),
+            Some(GasPrices {
+                rapid: 86.76e9,
+                fast: 71e9,
+                standard: 61e9,
+                slow: 50e9,
+            })
+        );
+        assert_eq!(parse_message(r#"{"type":"other"}"#), None);
+        assert_eq!(
+            parse_message(
+                r#"{"type":"gasprice_s","data":{"rapid":100000000000,"fast":80000000000,"standard":50000000000,"slow":20000000000}}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn interpolates_between_categories() {
+        assert_approx_eq!(interpolate(&PRICES, Duration::from_secs(0)), 100e9);
+        assert_approx_eq!(interpolate(&PRICES, Duration::from_secs(60)), 80e9);
+        assert_approx_eq!(interpolate(&PRICES, Duration::from_secs(120)), 65e9);
+        assert_approx_eq!(interpolate(&PRICES, Duration::from_secs(390)), 35e9);
+        assert_approx_eq!(interpolate(&PRICES, Duration::from_secs(3600)), 20e9);
+    }
+
+    #[test]
+    fn fails_without_fresh_prices() {
+        let estimator = GasNowWebSocket(Arc::new(Mutex::new(None)));
+        assert!(estimator.estimate().now_or_never().unwrap().is_err());
+
+        *estimator.0.lock().unwrap() = Some((PRICES, Instant::now()));
+        assert_approx_eq!(estimator.estimate().now_or_never().unwrap().unwrap(), 80e9);
+
+        *estimator.0.lock().unwrap() = Some((PRICES, Instant::now() - MAX_PRICE_AGE * 2));
+        assert!(estimator.estimate().now_or_never().unwrap().is_err());
+    }
+}
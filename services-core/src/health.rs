@@ -12,12 +12,33 @@ pub trait HealthReporting: Send + Sync {
     /// We use this to signal readiness only at the start of a batch in order to not interrupt the
     /// still running kubernetes pod while it is handling a batch.
     fn notify_ready(&self);
+
+    /// Notify whether the account used by the service has sufficient funds.
+    /// The service is reported as not ready while its balance is too low.
+    fn notify_balance_sufficient(&self, sufficient: bool);
+
+    /// Notify whether the system clock is in sync with the chain time. The
+    /// service is reported as not ready while the clock drifted too far.
+    fn notify_clock_in_sync(&self, in_sync: bool);
+
+    /// Notify whether the service is still initializing. The service is
+    /// reported as initializing and not ready until its startup finished.
+    fn notify_initializing(&self, initializing: bool);
+
+    /// Notify whether the driver's circuit breaker is open. The service is
+    /// reported as not ready while it skips batches because of repeated
+    /// failures.
+    fn notify_circuit_breaker_open(&self, open: bool);
 }
 
 /// Implementation sharing health information over an HTTP endpoint.
 #[derive(Debug, Default)]
 pub struct HttpHealthEndpoint {
     ready: AtomicBool,
+    insufficient_balance: AtomicBool,
+    clock_out_of_sync: AtomicBool,
+    initializing: AtomicBool,
+    circuit_breaker_open: AtomicBool,
 }
 
 impl HttpHealthEndpoint {
@@ -29,6 +50,10 @@ impl HttpHealthEndpoint {
     /// Returns true if the service is ready, false otherwise.
     fn is_ready(&self) -> bool {
         self.ready.load(Ordering::SeqCst)
+            && !self.insufficient_balance.load(Ordering::SeqCst)
+            && !self.clock_out_of_sync.load(Ordering::SeqCst)
+            && !self.initializing.load(Ordering::SeqCst)
+            && !self.circuit_breaker_open.load(Ordering::SeqCst)
     }
 }
 
@@ -36,12 +61,33 @@ impl HealthReporting for HttpHealthEndpoint {
     fn notify_ready(&self) {
         self.ready.store(true, Ordering::SeqCst);
     }
+
+    fn notify_balance_sufficient(&self, sufficient: bool) {
+        self.insufficient_balance
+            .store(!sufficient, Ordering::SeqCst);
+    }
+
+    fn notify_clock_in_sync(&self, in_sync: bool) {
+        self.clock_out_of_sync.store(!in_sync, Ordering::SeqCst);
+    }
+
+    fn notify_initializing(&self, initializing: bool) {
+        self.initializing.store(initializing, Ordering::SeqCst);
+    }
+
+    fn notify_circuit_breaker_open(&self, open: bool) {
+        self.circuit_breaker_open.store(open, Ordering::SeqCst);
+    }
 }
 
 impl Handler for HttpHealthEndpoint {
     fn handle_request(&self, _: &Request) -> Result<Response> {
         Ok(if self.is_ready() {
             Response::empty_204()
+        } else if self.initializing.load(Ordering::SeqCst) {
+            Response::text("initializing").with_status_code(503)
+        } else if self.circuit_breaker_open.load(Ordering::SeqCst) {
+            Response::text("circuit breaker open").with_status_code(503)
         } else {
             Response::text("service unavailable").with_status_code(503)
         })
@@ -82,4 +128,64 @@ mod tests {
             .unwrap();
         assert_eq!(response.status_code, 503);
     }
+
+    #[test]
+    fn responds_with_503_when_balance_insufficient() {
+        let health = HttpHealthEndpoint::new();
+        health.notify_ready();
+        health.notify_balance_sufficient(false);
+
+        let request = Request::fake_http("GET", "/health/readiness", vec![], vec![]);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 503);
+
+        health.notify_balance_sufficient(true);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 204);
+    }
+
+    #[test]
+    fn responds_with_503_when_clock_out_of_sync() {
+        let health = HttpHealthEndpoint::new();
+        health.notify_ready();
+        health.notify_clock_in_sync(false);
+
+        let request = Request::fake_http("GET", "/health/readiness", vec![], vec![]);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 503);
+
+        health.notify_clock_in_sync(true);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 204);
+    }
+
+    #[test]
+    fn responds_with_503_while_initializing() {
+        let health = HttpHealthEndpoint::new();
+        health.notify_ready();
+        health.notify_initializing(true);
+
+        let request = Request::fake_http("GET", "/health/readiness", vec![], vec![]);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 503);
+
+        health.notify_initializing(false);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 204);
+    }
+
+    #[test]
+    fn responds_with_503_while_circuit_breaker_open() {
+        let health = HttpHealthEndpoint::new();
+        health.notify_ready();
+        health.notify_circuit_breaker_open(true);
+
+        let request = Request::fake_http("GET", "/health/readiness", vec![], vec![]);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 503);
+
+        health.notify_circuit_breaker_open(false);
+        let response = health.handle_request(&request).unwrap();
+        assert_eq!(response.status_code, 204);
+    }
 }
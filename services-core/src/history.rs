@@ -1,17 +1,17 @@
 //! This module contains an implementation for querying historic echange data by
 //! inspecting indexed events.
 
+pub mod audit;
 pub mod batches;
 pub mod events;
+pub mod export;
+pub mod invariants;
 
 use self::batches::Batches;
 use self::events::EventRegistry;
-use crate::models::BatchId;
+use crate::models::{AccountState, BatchId, Order};
 use anyhow::Result;
-use contracts::batch_exchange::{
-    event_data::{SolutionSubmission, Trade},
-    Event,
-};
+use contracts::batch_exchange::event_data::{SolutionSubmission, Trade};
 use pricegraph::Element;
 use std::{fs::File, io::Read, path::Path};
 
@@ -57,30 +57,18 @@ impl ExchangeHistory {
             .collect())
     }
 
+    /// Returns the balances and orders of the auction for the specified batch.
+    pub fn auction_state_for_batch(
+        &self,
+        batch: impl Into<BatchId>,
+    ) -> Result<(AccountState, Vec<Order>)> {
+        self.events.auction_state_for_batch(batch)
+    }
+
     /// Returns a batch settlement information for the specified batch. Returns
     /// `None` if no solution was sumbitted for the specified batch.
     pub fn settlement_for_batch(&self, batch: impl Into<BatchId>) -> Option<Settlement> {
-        // NOTE: Solution submission is done in the following batch.
-        let events = self.events.events_for_batch(batch.into().next());
-
-        let mut trades = Vec::new();
-        let mut solution = None;
-        for event in events {
-            match event {
-                Event::Trade(trade) => trades.push(trade.clone()),
-                Event::TradeReversion(_) => {
-                    trades.clear();
-                    solution = None;
-                }
-                Event::SolutionSubmission(solution_submission) => {
-                    solution = Some(solution_submission)
-                }
-                _ => {}
-            }
-        }
-
-        let solution = solution?.clone();
-        Some(Settlement { trades, solution })
+        self.events.settlement_for_batch(batch)
     }
 }
 
@@ -95,17 +83,13 @@ pub struct Settlement {
 mod tests {
     use super::*;
     use crate::models::BatchId;
-    use contracts::batch_exchange;
+    use contracts::batch_exchange::{self, Event};
     use ethcontract::{Address, H256};
 
     fn block_hash(block_number: u64) -> H256 {
         H256::from_low_u64_be(block_number)
     }
 
-    fn batch_timestamp(batch_id: impl Into<BatchId>) -> u64 {
-        batch_id.into().as_timestamp()
-    }
-
     fn token_listing(token: u16) -> Event {
         Event::TokenListing(batch_exchange::event_data::TokenListing {
             id: token,
@@ -128,10 +112,10 @@ mod tests {
     fn read_event_filestore() {
         let bincode = {
             let mut events = EventRegistry::default();
-            events.handle_event_data(token_listing(0), 1, 0, block_hash(1), batch_timestamp(41));
-            events.handle_event_data(token_listing(1), 2, 0, block_hash(2), batch_timestamp(41));
-            events.handle_event_data(token_listing(2), 2, 1, block_hash(2), batch_timestamp(41));
-            events.handle_event_data(token_listing(3), 4, 0, block_hash(4), batch_timestamp(42));
+            events.handle_event_data(token_listing(0), 1, 0, block_hash(1), BatchId(41));
+            events.handle_event_data(token_listing(1), 2, 0, block_hash(2), BatchId(41));
+            events.handle_event_data(token_listing(2), 2, 1, block_hash(2), BatchId(41));
+            events.handle_event_data(token_listing(3), 4, 0, block_hash(4), BatchId(42));
             events.to_bytes().unwrap()
         };
 
@@ -185,13 +169,7 @@ mod tests {
 
             let mut events = EventRegistry::default();
             for (i, event) in event_data.into_iter().enumerate() {
-                events.handle_event_data(
-                    event,
-                    1337,
-                    i,
-                    block_hash(0),
-                    batch.next().as_timestamp(),
-                );
+                events.handle_event_data(event, 1337, i, block_hash(0), batch.next());
             }
 
             ExchangeHistory { events }
@@ -277,13 +255,7 @@ mod tests {
 
             let mut events = EventRegistry::default();
             for (i, event) in event_data.into_iter().enumerate() {
-                events.handle_event_data(
-                    event,
-                    1337,
-                    i,
-                    block_hash(0),
-                    batch.next().as_timestamp(),
-                );
+                events.handle_event_data(event, 1337, i, block_hash(0), batch.next());
             }
 
             ExchangeHistory { events }
@@ -0,0 +1,528 @@
+//! Module implementing an offline audit of a solution against the auction
+//! state of the batch it solves. The audit repeats the checks of the exchange
+//! and recomputes the objective value, so that disputed settlements can be
+//! investigated from an archived orderbook without a node.
+
+use super::invariants::{self, TokenViolation, TokenVolumes, FEE_TOKEN};
+use crate::models::{AccountState, ExecutedOrder, Order, Solution, SubmitSolutionCall};
+use ethcontract::{Address, U256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+};
+
+/// The price of the fee token, which is fixed by the exchange.
+const FEE_TOKEN_PRICE: u128 = 1_000_000_000_000_000_000;
+
+/// Every trade pays `1 / FEE_DENOMINATOR` of its sell amount as fee.
+const FEE_DENOMINATOR: u128 = 1000;
+
+/// A check of the exchange that the solution fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Finding {
+    /// The solution trades an order that is not part of the auction.
+    UnknownOrder { user: Address, order_id: u16 },
+    /// The solution trades an order below its limit price.
+    LimitPriceViolated { user: Address, order_id: u16 },
+    /// The solution sells more than the remaining amount of an order.
+    RemainingAmountExceeded {
+        user: Address,
+        order_id: u16,
+        sell_amount: u128,
+        remaining: u128,
+    },
+    /// The sell amount of a trade differs from the amount the exchange
+    /// computes from the buy amount and the prices.
+    SellAmount {
+        user: Address,
+        order_id: u16,
+        expected: u128,
+        actual: u128,
+    },
+    /// The solution sells more of a token than a user holds, including the
+    /// amount the user buys in the same solution.
+    InsufficientBalance {
+        user: Address,
+        token: u16,
+        sold: U256,
+        available: U256,
+    },
+    /// The traded tokens violate an invariant of the exchange.
+    Token(TokenViolation),
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Finding::UnknownOrder { user, order_id } => write!(
+                f,
+                "order {} of user {:?} is not part of the auction",
+                order_id, user
+            ),
+            Finding::LimitPriceViolated { user, order_id } => write!(
+                f,
+                "order {} of user {:?} is traded below its limit price",
+                order_id, user
+            ),
+            Finding::RemainingAmountExceeded {
+                user,
+                order_id,
+                sell_amount,
+                remaining,
+            } => write!(
+                f,
+                "order {} of user {:?} sells {} but only {} remain",
+                order_id, user, sell_amount, remaining
+            ),
+            Finding::SellAmount {
+                user,
+                order_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "order {} of user {:?} sells {} instead of {} at the solution prices",
+                order_id, user, actual, expected
+            ),
+            Finding::InsufficientBalance {
+                user,
+                token,
+                sold,
+                available,
+            } => write!(
+                f,
+                "user {:?} sells {} of token {} but only has {}",
+                user, sold, token, available
+            ),
+            Finding::Token(violation) => violation.fmt(f),
+        }
+    }
+}
+
+/// The result of auditing a solution.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Audit {
+    pub trades: usize,
+    pub findings: Vec<Finding>,
+    pub utility: U256,
+    pub disregarded_utility: U256,
+    pub burnt_fees: U256,
+}
+
+impl Audit {
+    /// The objective value of the solution as computed by the exchange, or
+    /// `None` if the disregarded utility exceeds the total utility.
+    pub fn objective_value(&self) -> Option<U256> {
+        invariants::objective_value(self.utility, self.disregarded_utility, self.burnt_fees)
+    }
+
+    /// Whether the solution passes all checks.
+    pub fn is_valid(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Display for Audit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "trades:              {}", self.trades)?;
+        writeln!(f, "utility:             {}", self.utility)?;
+        writeln!(f, "disregarded utility: {}", self.disregarded_utility)?;
+        writeln!(f, "burnt fees:          {}", self.burnt_fees)?;
+        match self.objective_value() {
+            Some(objective_value) => writeln!(f, "objective value:     {}", objective_value)?,
+            None => writeln!(f, "objective value:     underflows")?,
+        }
+        if self.findings.is_empty() {
+            writeln!(f, "the solution passes all checks")
+        } else {
+            writeln!(f, "the solution fails {} checks:", self.findings.len())?;
+            for finding in &self.findings {
+                writeln!(f, "  - {}", finding)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Reconstructs the solution from a call of `submitSolution`. The exchange
+/// only receives the buy amounts, so the sell amounts are computed from the
+/// prices like the exchange does, which requires the tokens of the orders.
+/// Trades of unknown orders or unpriced tokens sell nothing.
+pub fn solution_from_call(call: &SubmitSolutionCall, orders: &[Order]) -> Solution {
+    let mut prices: HashMap<u16, u128> = call
+        .token_ids_for_price
+        .iter()
+        .copied()
+        .zip(call.prices.iter().copied())
+        .collect();
+    prices.insert(FEE_TOKEN, FEE_TOKEN_PRICE);
+
+    let orders = orders_by_key(orders);
+    let executed_orders = call
+        .owners
+        .iter()
+        .zip(&call.order_ids)
+        .zip(&call.buy_volumes)
+        .map(|((user, order_id), buy_amount)| {
+            let sell_amount = orders
+                .get(&(*user, *order_id))
+                .and_then(|order| {
+                    executed_sell_amount(
+                        *buy_amount,
+                        *prices.get(&order.buy_token)?,
+                        *prices.get(&order.sell_token)?,
+                    )
+                })
+                .unwrap_or(0);
+            ExecutedOrder {
+                account_id: *user,
+                order_id: *order_id,
+                sell_amount,
+                buy_amount: *buy_amount,
+            }
+        })
+        .collect();
+
+    Solution {
+        prices,
+        executed_orders,
+    }
+}
+
+/// Audits the solution against the orders and balances of the auction of the
+/// solved batch.
+pub fn audit_solution(orders: &[Order], accounts: &AccountState, solution: &Solution) -> Audit {
+    let orders = orders_by_key(orders);
+    let price = |token: u16| match token {
+        FEE_TOKEN => Some(FEE_TOKEN_PRICE),
+        token => solution
+            .prices
+            .get(&token)
+            .copied()
+            .filter(|price| *price > 0),
+    };
+
+    let mut audit = Audit::default();
+    // The amounts sold and bought per token and per user and token.
+    let mut volumes = TokenVolumes::default();
+    let mut balances = BTreeMap::<(Address, u16), (U256, U256)>::new();
+    let mut trades = Vec::new();
+    for executed_order in &solution.executed_orders {
+        // Like the exchange only trades with a buy amount are considered.
+        if executed_order.buy_amount == 0 {
+            continue;
+        }
+        audit.trades += 1;
+
+        let (user, order_id) = (executed_order.account_id, executed_order.order_id);
+        let order = match orders.get(&(user, order_id)) {
+            Some(order) => *order,
+            None => {
+                audit
+                    .findings
+                    .push(Finding::UnknownOrder { user, order_id });
+                continue;
+            }
+        };
+        let (sell_amount, buy_amount) = (executed_order.sell_amount, executed_order.buy_amount);
+
+        if U256::from(buy_amount) * U256::from(order.denominator)
+            < U256::from(sell_amount) * U256::from(order.numerator)
+        {
+            audit
+                .findings
+                .push(Finding::LimitPriceViolated { user, order_id });
+        }
+        if sell_amount > order.remaining_sell_amount {
+            audit.findings.push(Finding::RemainingAmountExceeded {
+                user,
+                order_id,
+                sell_amount,
+                remaining: order.remaining_sell_amount,
+            });
+        }
+        // Trades of unpriced tokens are reported for the traded tokens.
+        if let (Some(buy_price), Some(sell_price)) =
+            (price(order.buy_token), price(order.sell_token))
+        {
+            match executed_sell_amount(buy_amount, buy_price, sell_price) {
+                Some(expected) if expected == sell_amount => {
+                    trades.push((order, sell_amount, buy_amount, buy_price, sell_price))
+                }
+                expected => audit.findings.push(Finding::SellAmount {
+                    user,
+                    order_id,
+                    expected: expected.unwrap_or(u128::max_value()),
+                    actual: sell_amount,
+                }),
+            }
+        }
+
+        let (sell_amount, buy_amount) = (U256::from(sell_amount), U256::from(buy_amount));
+        volumes.add_trade(order.sell_token, order.buy_token, sell_amount, buy_amount);
+        balances.entry((user, order.sell_token)).or_default().0 += sell_amount;
+        balances.entry((user, order.buy_token)).or_default().1 += buy_amount;
+    }
+    audit.findings.extend(
+        volumes
+            .check_prices(|token| price(token).is_some())
+            .into_iter()
+            .map(Finding::Token),
+    );
+
+    for (&(user, token), &(sold, bought)) in &balances {
+        let available = accounts.read_balance(token, user).saturating_add(bought);
+        if sold > available {
+            audit.findings.push(Finding::InsufficientBalance {
+                user,
+                token,
+                sold,
+                available,
+            });
+        }
+    }
+
+    match volumes.fee_imbalance() {
+        Ok(imbalance) => audit.burnt_fees = imbalance / 2,
+        Err(violation) => audit.findings.push(Finding::Token(violation)),
+    }
+    audit
+        .findings
+        .extend(volumes.check_conservation().into_iter().map(Finding::Token));
+
+    // The objective value is only computed from the trades at the solution
+    // prices, the other trades are already reported as findings.
+    for (order, sell_amount, buy_amount, buy_price, sell_price) in trades {
+        // Orders without a sell amount cannot trade and are already reported
+        // for exceeding their remaining amount.
+        if order.denominator == 0 {
+            continue;
+        }
+        audit.utility =
+            audit
+                .utility
+                .saturating_add(utility(order, sell_amount, buy_amount, buy_price));
+
+        let (sold, bought) = balances[&(order.account_id, order.sell_token)];
+        let balance = accounts
+            .read_balance(order.sell_token, order.account_id)
+            .saturating_add(bought)
+            .saturating_sub(sold);
+        let leftover_sell_amount =
+            U256::from(order.remaining_sell_amount.saturating_sub(sell_amount)).min(balance);
+        audit.disregarded_utility = audit
+            .disregarded_utility
+            .saturating_add(disregarded_utility(
+                order,
+                leftover_sell_amount,
+                buy_price,
+                sell_price,
+            ));
+    }
+
+    audit
+}
+
+fn orders_by_key(orders: &[Order]) -> HashMap<(Address, u16), &Order> {
+    orders
+        .iter()
+        .map(|order| ((order.account_id, order.id), order))
+        .collect()
+}
+
+/// The sell amount of a trade with the specified buy amount at the prices,
+/// rounded like the exchange does. Returns `None` if the sell price is zero
+/// or the amount overflows.
+fn executed_sell_amount(buy_amount: u128, buy_price: u128, sell_price: u128) -> Option<u128> {
+    if sell_price == 0 {
+        return None;
+    }
+    let sell_amount = (U256::from(buy_amount).checked_mul(buy_price.into())?
+        / (FEE_DENOMINATOR - 1))
+        .checked_mul(FEE_DENOMINATOR.into())?
+        / sell_price;
+    if sell_amount > U256::from(u128::max_value()) {
+        return None;
+    }
+    Some(sell_amount.low_u128())
+}
+
+/// The utility of a trade in the fee token, rounded like the exchange does.
+fn utility(order: &Order, sell_amount: u128, buy_amount: u128, buy_price: u128) -> U256 {
+    let sell_times_limit_buy = U256::from(sell_amount) * U256::from(order.numerator);
+    let denominator = U256::from(order.denominator);
+    let rounded_utility = U256::from(buy_amount)
+        .saturating_sub(sell_times_limit_buy / denominator)
+        .saturating_mul(buy_price.into());
+    let utility_error = (sell_times_limit_buy % denominator) * U256::from(buy_price) / denominator;
+    rounded_utility.saturating_sub(utility_error)
+}
+
+/// The utility that the order would have gained by trading its leftover sell
+/// amount at the solution prices, rounded like the exchange does.
+fn disregarded_utility(
+    order: &Order,
+    leftover_sell_amount: U256,
+    buy_price: u128,
+    sell_price: u128,
+) -> U256 {
+    let limit_term_left = U256::from(sell_price) * U256::from(order.denominator);
+    let limit_term_right = (U256::from(order.numerator) * U256::from(buy_price))
+        .saturating_mul(FEE_DENOMINATOR.into())
+        / (FEE_DENOMINATOR - 1);
+    let limit_term = limit_term_left.saturating_sub(limit_term_right);
+    leftover_sell_amount.saturating_mul(limit_term) / U256::from(order.denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_util::map_from_slice;
+
+    const PRICE: u128 = 1_000_000_000_000_000_000;
+
+    fn user(id: u64) -> Address {
+        Address::from_low_u64_be(id)
+    }
+
+    fn order(user_id: u64, buy_token: u16, sell_token: u16, buy: u128, sell: u128) -> Order {
+        Order {
+            id: 0,
+            account_id: user(user_id),
+            buy_token,
+            sell_token,
+            numerator: buy,
+            denominator: sell,
+            remaining_sell_amount: sell,
+            valid_from: 0,
+            valid_until: u32::max_value(),
+        }
+    }
+
+    fn executed_order(user_id: u64, sell_amount: u128, buy_amount: u128) -> ExecutedOrder {
+        ExecutedOrder {
+            account_id: user(user_id),
+            order_id: 0,
+            sell_amount,
+            buy_amount,
+        }
+    }
+
+    /// A ring trade between two users selling all of their orders at equal
+    /// prices, which leaves a fee of 1999 atoms of the fee token.
+    fn ring_trade() -> (Vec<Order>, AccountState, Solution) {
+        let orders = vec![
+            order(1, 1, 0, 900_000, 1_000_000),
+            order(2, 0, 1, 900_000, 999_000),
+        ];
+        let accounts = AccountState(
+            vec![
+                ((user(1), 0), U256::from(1_000_000)),
+                ((user(2), 1), U256::from(999_000)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let solution = Solution {
+            prices: map_from_slice(&[(0, PRICE), (1, PRICE)]),
+            executed_orders: vec![
+                executed_order(1, 1_000_000, 999_000),
+                executed_order(2, 999_000, 998_001),
+            ],
+        };
+        (orders, accounts, solution)
+    }
+
+    #[test]
+    fn recomputes_objective_value_of_valid_solution() {
+        let (orders, accounts, solution) = ring_trade();
+        let audit = audit_solution(&orders, &accounts, &solution);
+        assert!(audit.is_valid(), "{}", audit);
+        assert_eq!(audit.trades, 2);
+        assert_eq!(audit.utility, U256::from(197_001 * PRICE));
+        assert_eq!(audit.disregarded_utility, U256::zero());
+        assert_eq!(audit.burnt_fees, U256::from(999));
+        assert_eq!(
+            audit.objective_value(),
+            Some(U256::from(197_001 * PRICE + 999))
+        );
+    }
+
+    #[test]
+    fn disregards_utility_of_leftover_amounts() {
+        let (mut orders, accounts, solution) = ring_trade();
+        orders[0].remaining_sell_amount = 2_000_000;
+        let audit = audit_solution(&orders, &accounts, &solution);
+        // The leftover amount is bounded by the remaining balance, which the
+        // solution used up entirely.
+        assert_eq!(audit.disregarded_utility, U256::zero());
+
+        let accounts = AccountState(
+            accounts
+                .into_iter()
+                .map(|(key, balance)| (key, balance * 2))
+                .collect(),
+        );
+        let audit = audit_solution(&orders, &accounts, &solution);
+        assert!(audit.is_valid(), "{}", audit);
+        assert_eq!(
+            audit.disregarded_utility,
+            U256::from(1_000_000)
+                * (U256::from(1_000_000 * PRICE) - U256::from(900_000 * PRICE) * 1000 / 999)
+                / 1_000_000
+        );
+    }
+
+    #[test]
+    fn reports_failed_checks() {
+        let (orders, mut accounts, mut solution) = ring_trade();
+        accounts.0.insert((user(1), 0), U256::from(500_000));
+        solution.executed_orders[1].sell_amount = 998_000;
+        solution.executed_orders.push(executed_order(3, 1, 1));
+        solution.prices.remove(&1);
+
+        let audit = audit_solution(&orders, &accounts, &solution);
+        assert_eq!(
+            audit.findings,
+            vec![
+                Finding::UnknownOrder {
+                    user: user(3),
+                    order_id: 0
+                },
+                Finding::Token(TokenViolation::UnpricedToken { token: 1 }),
+                Finding::InsufficientBalance {
+                    user: user(1),
+                    token: 0,
+                    sold: 1_000_000.into(),
+                    available: 500_000.into(),
+                },
+                Finding::Token(TokenViolation::TokenNotConserved {
+                    token: 1,
+                    sold: 998_000.into(),
+                    bought: 999_000.into(),
+                }),
+            ]
+        );
+
+        solution.prices.insert(1, PRICE);
+        solution.executed_orders[0].buy_amount = 800_000;
+        let audit = audit_solution(&orders, &accounts, &solution);
+        assert!(audit.findings.contains(&Finding::LimitPriceViolated {
+            user: user(1),
+            order_id: 0
+        }));
+        assert!(audit.findings.contains(&Finding::SellAmount {
+            user: user(2),
+            order_id: 0,
+            expected: 999_000,
+            actual: 998_000,
+        }));
+    }
+
+    #[test]
+    fn reconstructs_solution_from_call() {
+        let (orders, _, solution) = ring_trade();
+        let call = SubmitSolutionCall::new(1, U256::zero(), &solution);
+        assert_eq!(solution_from_call(&call, &orders), solution);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    models::{AccountState, BatchId, Order},
+    history::Settlement,
+    models::{AccountState, BatchId, Order, Solution},
     orderbook::streamed::State,
     serialization::Version,
 };
@@ -12,7 +13,7 @@ use std::{
     convert::TryFrom,
     fs::{self, File},
     io::{BufReader, BufWriter, Read, Write},
-    ops::Bound,
+    ops::{Bound, RangeInclusive},
     path::Path,
 };
 use typenum::U2;
@@ -23,12 +24,12 @@ use typenum::U2;
 // list of all events based on which the state is built.
 
 #[derive(Clone, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
-struct EventSortKey {
-    block_number: u64,
+pub struct EventSortKey {
+    pub block_number: u64,
     /// Is included to differentiate events from the same block number but different blocks which
     /// can happen during reorgs.
-    block_hash: H256,
-    log_index: usize,
+    pub block_hash: H256,
+    pub log_index: usize,
 }
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -45,6 +46,9 @@ pub struct EventRegistry {
 }
 
 impl EventRegistry {
+    /// The format version of serialized event registries.
+    pub const VERSION: u32 = Version::<U2>::VALUE;
+
     pub fn read(reader: impl Read) -> Result<Self> {
         Ok(bincode::deserialize_from(reader)?)
     }
@@ -55,9 +59,8 @@ impl EventRegistry {
         block_number: u64,
         log_index: usize,
         block_hash: H256,
-        block_timestamp: u64,
+        batch_id: BatchId,
     ) {
-        let batch_id = BatchId::from_timestamp(block_timestamp);
         let key = EventSortKey {
             block_number,
             block_hash,
@@ -66,16 +69,25 @@ impl EventRegistry {
         self.events.insert(key, Value { event, batch_id });
     }
 
-    pub fn delete_events_starting_at_block(&mut self, block_number: u64) {
-        self.events.split_off(&EventSortKey {
-            block_number,
-            block_hash: H256::zero(),
-            log_index: 0,
-        });
+    /// Deletes the events starting at the specified block and returns their
+    /// keys.
+    pub fn delete_events_starting_at_block(&mut self, block_number: u64) -> Vec<EventSortKey> {
+        self.events
+            .split_off(&EventSortKey {
+                block_number,
+                block_hash: H256::zero(),
+                log_index: 0,
+            })
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    pub fn contains_event(&self, key: &EventSortKey) -> bool {
+        self.events.contains_key(key)
     }
 
     /// Serializes an `EventRegistry` into its bincode representation.
-    #[cfg(test)]
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         Ok(bincode::serialize(self)?)
     }
@@ -148,6 +160,37 @@ impl EventRegistry {
             .map(|(event, _)| event)
     }
 
+    /// Returns the final settlement of the specified batch. Returns `None` if
+    /// no solution was submitted for the batch.
+    pub fn settlement_for_batch(&self, batch_id: impl Into<BatchId>) -> Option<Settlement> {
+        // NOTE: Solution submission is done in the following batch.
+        settlement_from_events(self.events_for_batch(batch_id.into().next()))
+    }
+
+    /// Returns the final settlements of all batches in the specified range
+    /// that had a solution submitted. Unlike calling `settlement_for_batch`
+    /// for every batch, this only iterates over the events once.
+    pub fn settlements(&self, batches: RangeInclusive<BatchId>) -> Vec<(BatchId, Settlement)> {
+        let (first, last) = (batches.start().next(), batches.end().next());
+        let mut events = self
+            .events()
+            .skip_while(|(_, batch_id)| *batch_id < first)
+            .take_while(|(_, batch_id)| *batch_id <= last)
+            .peekable();
+
+        let mut settlements = Vec::new();
+        let mut batch_events = Vec::new();
+        while let Some((event, batch_id)) = events.next() {
+            batch_events.push(event);
+            if events.peek().map(|(_, next_batch_id)| *next_batch_id) != Some(batch_id) {
+                if let Some(settlement) = settlement_from_events(batch_events.drain(..)) {
+                    settlements.push((batch_id.prev(), settlement));
+                }
+            }
+        }
+        settlements
+    }
+
     /// Create a new streamed orderbook auction state with events from batches
     /// up to and including the specified batch ID.
     pub fn auction_state_for_batch(
@@ -158,6 +201,35 @@ impl EventRegistry {
         auction_state_for_batch_from_events(batch_id, self.events_until_batch(batch_id))
     }
 
+    /// Replays a solution for the specified batch against the state from which
+    /// the auction for the batch was created. See `State::verify_solution`.
+    pub fn verify_solution(&self, batch_id: impl Into<BatchId>, solution: &Solution) -> Result<()> {
+        let batch_id = batch_id.into();
+        let state = State::from_events(
+            self.events_until_batch(batch_id)
+                .map(|(event, batch_id)| (event, batch_id.into())),
+        )?;
+        state.verify_solution(batch_id.next().into(), solution)
+    }
+
+    /// Create the streamed orderbook state with all events.
+    pub fn state(&self) -> Result<State> {
+        State::from_events(
+            self.events()
+                .map(|(event, batch_id)| (event, batch_id.into())),
+        )
+    }
+
+    /// Create the streamed orderbook state with events up to and including
+    /// the specified block number.
+    pub fn state_at_block(&self, block_number: u64) -> Result<State> {
+        State::from_events(
+            self.events
+                .range(bounds_until_end_of_block(block_number))
+                .map(|(_, Value { event, batch_id })| (event, (*batch_id).into())),
+        )
+    }
+
     /// Create a new orderbook auction state with events up to and including
     /// block number for solving the specified batch.
     pub fn auction_state_for_batch_at_block(
@@ -191,6 +263,31 @@ fn auction_state_for_batch_from_events<'a>(
     state.canonicalized_auction_state_at_beginning_of_batch(batch_id.next().into())
 }
 
+/// Computes the final settlement from the events of the batch in which the
+/// solution was submitted.
+fn settlement_from_events<'a>(
+    events: impl Iterator<Item = &'a batch_exchange::Event>,
+) -> Option<Settlement> {
+    let mut trades = Vec::new();
+    let mut solution = None;
+    for event in events {
+        match event {
+            batch_exchange::Event::Trade(trade) => trades.push(trade.clone()),
+            batch_exchange::Event::TradeReversion(_) => {
+                trades.clear();
+                solution = None;
+            }
+            batch_exchange::Event::SolutionSubmission(solution_submission) => {
+                solution = Some(solution_submission)
+            }
+            _ => {}
+        }
+    }
+
+    let solution = solution?.clone();
+    Some(Settlement { trades, solution })
+}
+
 impl TryFrom<File> for EventRegistry {
     type Error = anyhow::Error;
 
@@ -319,12 +416,12 @@ mod tests {
             token: Address::from_low_u64_be(0),
             id: 0,
         });
-        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), 0);
+        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), BatchId(0));
         let token_listing_1 = Event::TokenListing(TokenListing {
             token: Address::from_low_u64_be(1),
             id: 1,
         });
-        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), 0);
+        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), BatchId(0));
         let order_placement = Event::OrderPlacement(OrderPlacement {
             owner: Address::from_low_u64_be(2),
             index: 0,
@@ -335,7 +432,7 @@ mod tests {
             price_numerator: 10,
             price_denominator: 10,
         });
-        events.handle_event_data(order_placement, 0, 2, H256::zero(), 0);
+        events.handle_event_data(order_placement, 0, 2, H256::zero(), BatchId(0));
 
         let deposit_0 = Event::Deposit(Deposit {
             user: Address::from_low_u64_be(2),
@@ -343,28 +440,36 @@ mod tests {
             amount: 1.into(),
             batch_id: 0,
         });
-        events.handle_event_data(deposit_0, 0, 3, H256::zero(), 0);
+        events.handle_event_data(deposit_0, 0, 3, H256::zero(), BatchId(0));
         let deposit_1 = Event::Deposit(Deposit {
             user: Address::from_low_u64_be(2),
             token: Address::from_low_u64_be(0),
             amount: 1.into(),
             batch_id: 0,
         });
-        events.handle_event_data(deposit_1, 1, 0, H256::zero(), 0);
+        events.handle_event_data(deposit_1, 1, 0, H256::zero(), BatchId(0));
         let deposit_2 = Event::Deposit(Deposit {
             user: Address::from_low_u64_be(2),
             token: Address::from_low_u64_be(0),
             amount: 1.into(),
             batch_id: 0,
         });
-        events.handle_event_data(deposit_2, 2, 0, H256::zero(), 0);
+        events.handle_event_data(deposit_2, 2, 0, H256::zero(), BatchId(0));
 
         let auction_data = events.auction_state_for_batch(2).unwrap();
         assert_eq!(
             auction_data.0.read_balance(0, Address::from_low_u64_be(2)),
             U256::from(3)
         );
-        events.delete_events_starting_at_block(1);
+        let deleted = events.delete_events_starting_at_block(1);
+        assert_eq!(
+            deleted
+                .iter()
+                .map(|key| key.block_number)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(!events.contains_event(&deleted[0]));
         let auction_data = events.auction_state_for_batch(1).unwrap();
         assert_eq!(
             auction_data.0.read_balance(0, Address::from_low_u64_be(2)),
@@ -413,12 +518,12 @@ mod tests {
         });
 
         let mut events = EventRegistry::default();
-        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), 0);
-        events.handle_event_data(order_placement, 0, 2, H256::zero(), 0);
-        events.handle_event_data(withdraw, 2, 0, H256::zero(), BatchId(1).as_timestamp());
-        events.handle_event_data(deposit, 0, 3, H256::zero(), 0);
-        events.handle_event_data(withdraw_request, 1, 0, H256::zero(), 0);
-        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), 0);
+        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), BatchId(0));
+        events.handle_event_data(order_placement, 0, 2, H256::zero(), BatchId(0));
+        events.handle_event_data(withdraw, 2, 0, H256::zero(), BatchId(1));
+        events.handle_event_data(deposit, 0, 3, H256::zero(), BatchId(0));
+        events.handle_event_data(withdraw_request, 1, 0, H256::zero(), BatchId(0));
+        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), BatchId(0));
 
         let auction_data = events.auction_state_for_batch(2).unwrap();
         assert_eq!(
@@ -461,11 +566,11 @@ mod tests {
         });
 
         let mut events = EventRegistry::default();
-        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), 0);
-        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), 0);
-        events.handle_event_data(order_placement, 0, 2, H256::zero(), 0);
-        events.handle_event_data(deposit_0, 0, 3, H256::zero(), 0);
-        events.handle_event_data(deposit_1, 1, 0, H256::zero(), BatchId(2).as_timestamp());
+        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), BatchId(0));
+        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), BatchId(0));
+        events.handle_event_data(order_placement, 0, 2, H256::zero(), BatchId(0));
+        events.handle_event_data(deposit_0, 0, 3, H256::zero(), BatchId(0));
+        events.handle_event_data(deposit_1, 1, 0, H256::zero(), BatchId(2));
 
         let auction_data = events.auction_state_for_batch(0).unwrap();
         assert_eq!(
@@ -514,12 +619,12 @@ mod tests {
         });
 
         let mut events = EventRegistry::default();
-        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), 0);
-        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), 0);
-        events.handle_event_data(order_placement, 0, 2, H256::zero(), 0);
-        events.handle_event_data(deposit_0, 0, 3, H256::zero(), 0);
-        events.handle_event_data(deposit_1, 1, 0, H256::zero(), 0);
-        events.handle_event_data(deposit_2, 2, 0, H256::zero(), BatchId(1).as_timestamp());
+        events.handle_event_data(token_listing_0, 0, 0, H256::zero(), BatchId(0));
+        events.handle_event_data(token_listing_1, 0, 1, H256::zero(), BatchId(0));
+        events.handle_event_data(order_placement, 0, 2, H256::zero(), BatchId(0));
+        events.handle_event_data(deposit_0, 0, 3, H256::zero(), BatchId(0));
+        events.handle_event_data(deposit_1, 1, 0, H256::zero(), BatchId(0));
+        events.handle_event_data(deposit_2, 2, 0, H256::zero(), BatchId(1));
 
         let auction_data = events.auction_state_for_batch_at_block(1, 0).unwrap();
         assert_eq!(
@@ -542,13 +647,7 @@ mod tests {
         });
 
         let mut events = EventRegistry::default();
-        events.handle_event_data(
-            token_listing,
-            1,
-            0,
-            H256::zero(),
-            BatchId(1337).as_timestamp(),
-        );
+        events.handle_event_data(token_listing, 1, 0, H256::zero(), BatchId(1337));
 
         assert!(events
             .auction_state_for_batch_at_block(BatchId(42), 1)
@@ -565,48 +664,12 @@ mod tests {
         }
 
         let mut events = EventRegistry::default();
-        events.handle_event_data(
-            token_listing(0),
-            0,
-            0,
-            H256::zero(),
-            BatchId(0).as_timestamp(),
-        );
-        events.handle_event_data(
-            token_listing(1),
-            0,
-            1,
-            H256::zero(),
-            BatchId(0).as_timestamp(),
-        );
-        events.handle_event_data(
-            token_listing(2),
-            1,
-            0,
-            H256::zero(),
-            BatchId(1).as_timestamp(),
-        );
-        events.handle_event_data(
-            token_listing(3),
-            2,
-            0,
-            H256::zero(),
-            BatchId(1).as_timestamp(),
-        );
-        events.handle_event_data(
-            token_listing(4),
-            3,
-            0,
-            H256::zero(),
-            BatchId(2).as_timestamp(),
-        );
-        events.handle_event_data(
-            token_listing(5),
-            4,
-            0,
-            H256::zero(),
-            BatchId(5).as_timestamp(),
-        );
+        events.handle_event_data(token_listing(0), 0, 0, H256::zero(), BatchId(0));
+        events.handle_event_data(token_listing(1), 0, 1, H256::zero(), BatchId(0));
+        events.handle_event_data(token_listing(2), 1, 0, H256::zero(), BatchId(1));
+        events.handle_event_data(token_listing(3), 2, 0, H256::zero(), BatchId(1));
+        events.handle_event_data(token_listing(4), 3, 0, H256::zero(), BatchId(2));
+        events.handle_event_data(token_listing(5), 4, 0, H256::zero(), BatchId(5));
 
         assert_eq!(
             events.events_until_batch(1).collect::<Vec<_>>(),
@@ -0,0 +1,193 @@
+//! Module containing the export of historic trades to CSV files for offline
+//! analysis.
+
+use super::{ExchangeHistory, Settlement};
+use crate::models::BatchId;
+use anyhow::Result;
+use ethcontract::{Address, U256};
+use std::{io::Write, ops::RangeInclusive};
+
+/// The price of the fee token, which is not part of submitted solutions.
+const FEE_TOKEN_PRICE: u128 = 1_000_000_000_000_000_000;
+
+/// A settled trade together with the clearing prices of its batch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TradeRecord {
+    pub batch: BatchId,
+    pub owner: Address,
+    pub order_id: u16,
+    pub sell_token: u16,
+    pub buy_token: u16,
+    pub sell_amount: u128,
+    pub buy_amount: u128,
+    /// The price of the sell token in fee token atoms for `10^18` atoms.
+    pub sell_token_price: u128,
+    /// The price of the buy token in fee token atoms for `10^18` atoms.
+    pub buy_token_price: u128,
+    /// The fee paid for the trade in fee token atoms, which is the difference
+    /// between the values of the sold and bought amounts.
+    pub fee: U256,
+}
+
+impl ExchangeHistory {
+    /// Returns the trades of all batches in the specified range that had a
+    /// solution submitted, ordered by batch.
+    pub fn trade_records(&self, batches: RangeInclusive<BatchId>) -> Vec<TradeRecord> {
+        self.events
+            .settlements(batches)
+            .into_iter()
+            .flat_map(|(batch, settlement)| trade_records_for_settlement(batch, &settlement))
+            .collect()
+    }
+}
+
+fn trade_records_for_settlement(batch: BatchId, settlement: &Settlement) -> Vec<TradeRecord> {
+    let price = |token: u16| {
+        if token == 0 {
+            return FEE_TOKEN_PRICE;
+        }
+        let solution = &settlement.solution;
+        solution
+            .token_ids_for_price
+            .iter()
+            .position(|&token_id| token_id == token)
+            .and_then(|index| solution.prices.get(index).copied())
+            .unwrap_or_default()
+    };
+    let value = |amount: u128, price: u128| {
+        U256::from(amount) * U256::from(price) / U256::from(FEE_TOKEN_PRICE)
+    };
+
+    settlement
+        .trades
+        .iter()
+        .map(|trade| {
+            let sell_token_price = price(trade.sell_token);
+            let buy_token_price = price(trade.buy_token);
+            TradeRecord {
+                batch,
+                owner: trade.owner,
+                order_id: trade.order_id,
+                sell_token: trade.sell_token,
+                buy_token: trade.buy_token,
+                sell_amount: trade.executed_sell_amount,
+                buy_amount: trade.executed_buy_amount,
+                sell_token_price,
+                buy_token_price,
+                fee: value(trade.executed_sell_amount, sell_token_price)
+                    .saturating_sub(value(trade.executed_buy_amount, buy_token_price)),
+            }
+        })
+        .collect()
+}
+
+/// Writes trade records as CSV with a header row.
+pub fn write_trades_csv(
+    mut output: impl Write,
+    records: impl IntoIterator<Item = TradeRecord>,
+) -> Result<()> {
+    writeln!(
+        &mut output,
+        "batch,timestamp,owner,order_id,sell_token,buy_token,sell_amount,buy_amount,\
+         sell_token_price,buy_token_price,fee",
+    )?;
+    for record in records {
+        writeln!(
+            &mut output,
+            "{},{},{:?},{},{},{},{},{},{},{},{}",
+            record.batch,
+            record.batch.as_timestamp(),
+            record.owner,
+            record.order_id,
+            record.sell_token,
+            record.buy_token,
+            record.sell_amount,
+            record.buy_amount,
+            record.sell_token_price,
+            record.buy_token_price,
+            record.fee,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::events::EventRegistry;
+    use contracts::batch_exchange::{
+        event_data::{SolutionSubmission, Trade},
+        Event,
+    };
+    use ethcontract::H256;
+
+    fn history_with_settlements(batches: &[u64]) -> ExchangeHistory {
+        let mut events = EventRegistry::default();
+        for (block, &batch) in batches.iter().enumerate() {
+            let settlement = vec![
+                Event::Trade(Trade {
+                    owner: Address::from_low_u64_be(1),
+                    order_id: batch as _,
+                    sell_token: 0,
+                    buy_token: 1,
+                    executed_sell_amount: 2_000,
+                    executed_buy_amount: 990,
+                }),
+                Event::SolutionSubmission(SolutionSubmission {
+                    prices: vec![2 * FEE_TOKEN_PRICE],
+                    token_ids_for_price: vec![1],
+                    ..Default::default()
+                }),
+            ];
+            for (log_index, event) in settlement.into_iter().enumerate() {
+                events.handle_event_data(
+                    event,
+                    block as _,
+                    log_index,
+                    H256::from_low_u64_be(block as _),
+                    BatchId(batch).next(),
+                );
+            }
+        }
+        ExchangeHistory { events }
+    }
+
+    #[test]
+    fn trade_records_in_batch_range() {
+        let history = history_with_settlements(&[41, 42, 44]);
+        let records = history.trade_records(BatchId(42)..=BatchId(43));
+        assert_eq!(
+            records,
+            vec![TradeRecord {
+                batch: BatchId(42),
+                owner: Address::from_low_u64_be(1),
+                order_id: 42,
+                sell_token: 0,
+                buy_token: 1,
+                sell_amount: 2_000,
+                buy_amount: 990,
+                sell_token_price: FEE_TOKEN_PRICE,
+                buy_token_price: 2 * FEE_TOKEN_PRICE,
+                fee: 20.into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn writes_csv() {
+        let history = history_with_settlements(&[42]);
+        let mut output = Vec::new();
+        write_trades_csv(
+            &mut output,
+            history.trade_records(BatchId(0)..=BatchId(100)),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "batch,timestamp,owner,order_id,sell_token,buy_token,sell_amount,buy_amount,\
+             sell_token_price,buy_token_price,fee\n\
+             42,12600,0x0000000000000000000000000000000000000001,42,0,1,2000,990,\
+             1000000000000000000,2000000000000000000,20\n"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Invariants of the exchange on the tokens traded by a solution, shared by
+//! the exchange monitor checking settlements and the offline audit of
+//! solutions.
+
+use ethcontract::U256;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// The token in which fees are paid, which is the only token that does not
+/// need to be conserved by a solution.
+pub const FEE_TOKEN: u16 = 0;
+
+/// A violated invariant on the traded tokens.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TokenViolation {
+    /// The trades sell a different amount of a token than they buy.
+    TokenNotConserved {
+        token: u16,
+        sold: U256,
+        bought: U256,
+    },
+    /// The trades buy more of the fee token than they sell.
+    NegativeFeeImbalance { sold: U256, bought: U256 },
+    /// A token was traded without being priced by the solution.
+    UnpricedToken { token: u16 },
+}
+
+impl Display for TokenViolation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TokenViolation::TokenNotConserved {
+                token,
+                sold,
+                bought,
+            } => write!(
+                f,
+                "token {} is not conserved: sold {} but bought {}",
+                token, sold, bought
+            ),
+            TokenViolation::NegativeFeeImbalance { sold, bought } => write!(
+                f,
+                "fee token imbalance is negative: sold {} but bought {}",
+                sold, bought
+            ),
+            TokenViolation::UnpricedToken { token } => {
+                write!(f, "token {} is traded without a price", token)
+            }
+        }
+    }
+}
+
+/// The amounts sold and bought of every token traded by a solution.
+#[derive(Clone, Debug, Default)]
+pub struct TokenVolumes(BTreeMap<u16, (U256, U256)>);
+
+impl TokenVolumes {
+    /// Adds the amounts of a trade.
+    pub fn add_trade(&mut self, sell_token: u16, buy_token: u16, sold: U256, bought: U256) {
+        self.0.entry(sell_token).or_default().0 += sold;
+        self.0.entry(buy_token).or_default().1 += bought;
+    }
+
+    /// The amount of the fee token that is sold but not bought, half of which
+    /// is burnt by the exchange.
+    pub fn fee_imbalance(&self) -> Result<U256, TokenViolation> {
+        let (sold, bought) = self.0.get(&FEE_TOKEN).copied().unwrap_or_default();
+        sold.checked_sub(bought)
+            .ok_or(TokenViolation::NegativeFeeImbalance { sold, bought })
+    }
+
+    /// Checks that every token except the fee token is sold and bought in the
+    /// same amount.
+    pub fn check_conservation(&self) -> Vec<TokenViolation> {
+        self.0
+            .iter()
+            .filter(|(&token, &(sold, bought))| token != FEE_TOKEN && sold != bought)
+            .map(
+                |(&token, &(sold, bought))| TokenViolation::TokenNotConserved {
+                    token,
+                    sold,
+                    bought,
+                },
+            )
+            .collect()
+    }
+
+    /// Checks that every traded token except the fee token, whose price is
+    /// fixed, is priced.
+    pub fn check_prices(&self, is_priced: impl Fn(u16) -> bool) -> Vec<TokenViolation> {
+        self.0
+            .keys()
+            .copied()
+            .filter(|&token| token != FEE_TOKEN && !is_priced(token))
+            .map(|token| TokenViolation::UnpricedToken { token })
+            .collect()
+    }
+}
+
+/// The objective value of a solution as computed by the exchange, or `None`
+/// if the disregarded utility exceeds the total utility.
+pub fn objective_value(utility: U256, disregarded_utility: U256, burnt_fees: U256) -> Option<U256> {
+    utility
+        .checked_add(burnt_fees)?
+        .checked_sub(disregarded_utility)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_traded_tokens() {
+        let mut volumes = TokenVolumes::default();
+        volumes.add_trade(0, 1, 1_000.into(), 400.into());
+        volumes.add_trade(1, 2, 400.into(), 300.into());
+        volumes.add_trade(2, 0, 299.into(), 990.into());
+
+        assert_eq!(volumes.fee_imbalance(), Ok(10.into()));
+        assert_eq!(
+            volumes.check_conservation(),
+            vec![TokenViolation::TokenNotConserved {
+                token: 2,
+                sold: 299.into(),
+                bought: 300.into(),
+            }]
+        );
+        assert_eq!(
+            volumes.check_prices(|token| token == 1),
+            vec![TokenViolation::UnpricedToken { token: 2 }]
+        );
+
+        volumes.add_trade(1, 0, 0.into(), 11.into());
+        assert_eq!(
+            volumes.fee_imbalance(),
+            Err(TokenViolation::NegativeFeeImbalance {
+                sold: 1_000.into(),
+                bought: 1_001.into(),
+            })
+        );
+    }
+}
@@ -1,64 +1,246 @@
 //! Module contains the implementation for a shared HTTP client for various
 //! driver components.
 
+mod circuit_breaker;
+mod profile;
+mod proxy;
+
+use self::circuit_breaker::CircuitBreaker;
+pub use self::circuit_breaker::{CircuitBreakerOptions, CircuitOpenError};
+pub use self::profile::{HttpProfile, HttpProfiles, HttpService};
+pub use self::proxy::HttpProxyOptions;
 pub use crate::metrics::HttpLabel;
-use crate::metrics::HttpMetrics;
-use anyhow::{anyhow, Context, Result};
-use isahc::http::{Error as HttpError, Uri};
+use crate::{config::duration_secs, metrics::HttpMetrics};
+use anyhow::{Context, Result};
+use futures::io::AsyncReadExt as _;
+use isahc::config::VersionNegotiation;
+use isahc::http::{Error as HttpError, Response, StatusCode, Uri};
 use isahc::prelude::{Configurable, Request};
-use isahc::{HttpClientBuilder, ResponseExt};
+use isahc::{Body, HttpClientBuilder, ResponseExt};
 use serde::de::DeserializeOwned;
 use std::convert::TryFrom;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+/// Connection pool options shared by all HTTP clients created by a factory.
+/// Connections are kept alive and reused across requests so that bursts of
+/// requests to the same host do not each open a new TLS connection.
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct HttpPoolOptions {
+    /// The maximum number of concurrent connections per host. Further requests
+    /// wait for a connection to become available.
+    #[structopt(long, env = "HTTP_MAX_CONNECTIONS_PER_HOST", default_value = "8")]
+    pub http_max_connections_per_host: usize,
+
+    /// The maximum number of idle connections that are kept open for reuse.
+    #[structopt(long, env = "HTTP_CONNECTION_CACHE_SIZE", default_value = "64")]
+    pub http_connection_cache_size: usize,
+
+    /// The interval in seconds of TCP keep-alive probes on idle connections.
+    #[structopt(
+        long,
+        env = "HTTP_TCP_KEEPALIVE",
+        default_value = "60",
+        parse(try_from_str = duration_secs),
+    )]
+    pub http_tcp_keepalive: Duration,
+
+    /// Whether to prefer HTTP/2 which multiplexes concurrent requests to the
+    /// same host over a single connection.
+    #[structopt(
+        long,
+        env = "HTTP_PREFER_HTTP2",
+        parse(try_from_str),
+        default_value = "true"
+    )]
+    pub http_prefer_http2: bool,
+}
+
+impl Default for HttpPoolOptions {
+    fn default() -> Self {
+        HttpPoolOptions {
+            http_max_connections_per_host: 8,
+            http_connection_cache_size: 64,
+            http_tcp_keepalive: Duration::from_secs(60),
+            http_prefer_http2: true,
+        }
+    }
+}
 
 /// A factory type for creating HTTP clients.
 #[derive(Debug)]
 pub struct HttpFactory {
     default_timeout: Duration,
+    pool: HttpPoolOptions,
+    circuit_breaker: CircuitBreakerOptions,
+    profiles: HttpProfiles,
+    proxy: HttpProxyOptions,
     metrics: Arc<HttpMetrics>,
 }
 
 impl HttpFactory {
     /// Creates a new HTTP client factory.
-    pub fn new(default_timeout: Duration, metrics: HttpMetrics) -> Self {
+    pub fn new(
+        default_timeout: Duration,
+        pool: HttpPoolOptions,
+        circuit_breaker: CircuitBreakerOptions,
+        metrics: HttpMetrics,
+    ) -> Self {
+        metrics.pool_configured(pool.http_max_connections_per_host);
         HttpFactory {
             default_timeout,
+            pool,
+            circuit_breaker,
+            profiles: HttpProfiles::default(),
+            proxy: HttpProxyOptions::default(),
             metrics: Arc::new(metrics),
         }
     }
 
+    /// Configures the client profiles of the services.
+    pub fn with_profiles(mut self, profiles: HttpProfiles) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Routes the requests of all clients through the proxy.
+    pub fn with_proxy(mut self, proxy: HttpProxyOptions) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Creates a new HTTP client with the profile of the service.
+    pub fn create_for(&self, service: HttpService) -> Result<HttpClient> {
+        self.with_profile(service, |builder| builder)
+    }
+
+    /// Creates a new HTTP client with the profile of the service and the
+    /// given configuration, which is applied after the profile so it can
+    /// override it.
+    pub fn with_profile(
+        &self,
+        service: HttpService,
+        configure: impl FnOnce(HttpClientBuilder) -> HttpClientBuilder,
+    ) -> Result<HttpClient> {
+        let profile = self.profiles.get(service);
+        let mut client = self.build(|builder| {
+            Ok(configure(
+                profile.configure(builder.timeout(self.default_timeout))?,
+            ))
+        })?;
+        client.retries = profile.retries.unwrap_or_default();
+        Ok(client)
+    }
+
+    /// The proxy that connections to a host of the service are routed through
+    /// by clients other than HTTP clients, like node sockets. The proxy of the
+    /// service profile takes precedence and both obey the bypass rules.
+    pub fn proxy_for(&self, service: HttpService, host: &str) -> Result<Option<Uri>> {
+        if self.proxy.bypasses(host) {
+            return Ok(None);
+        }
+        match self.profiles.get(service).proxy {
+            Some(proxy) => {
+                Ok(Some(proxy.parse().with_context(|| {
+                    format!("invalid HTTP proxy URL {}", proxy)
+                })?))
+            }
+            None => Ok(self.proxy.http_proxy_url.clone()),
+        }
+    }
+
     /// Creates a new HTTP client with the default configuration.
     pub fn create(&self) -> Result<HttpClient> {
         self.with_config(|builder| builder.timeout(self.default_timeout))
     }
 
-    /// Creates a new HTTP Client with the given configuration.
+    /// Creates a new HTTP Client with the given configuration. The connection
+    /// pool and proxy options are applied first so they can be overridden.
+    ///
+    /// Each client has its own circuit breaker since clients are created per
+    /// remote API.
     pub fn with_config(
         &self,
         configure: impl FnOnce(HttpClientBuilder) -> HttpClientBuilder,
     ) -> Result<HttpClient> {
-        let inner = configure(isahc::HttpClient::builder()).build()?;
+        self.build(|builder| Ok(configure(builder)))
+    }
+
+    fn build(
+        &self,
+        configure: impl FnOnce(HttpClientBuilder) -> Result<HttpClientBuilder>,
+    ) -> Result<HttpClient> {
+        let version_negotiation = if self.pool.http_prefer_http2 {
+            VersionNegotiation::http2()
+        } else {
+            VersionNegotiation::http11()
+        };
+        let builder = isahc::HttpClient::builder()
+            .max_connections_per_host(self.pool.http_max_connections_per_host)
+            .connection_cache_size(self.pool.http_connection_cache_size)
+            .tcp_keepalive(self.pool.http_tcp_keepalive)
+            .version_negotiation(version_negotiation)
+            .metrics(true);
+        let builder = self.proxy.configure(builder);
+        let inner = configure(builder)?.build()?;
         let metrics = self.metrics.clone();
+        let circuit_breaker = Some(CircuitBreaker::new(self.circuit_breaker));
 
-        Ok(HttpClient { inner, metrics })
+        Ok(HttpClient {
+            inner,
+            metrics,
+            circuit_breaker,
+            retries: 0,
+        })
     }
 }
 
 impl Default for HttpFactory {
     fn default() -> Self {
-        HttpFactory::new(Duration::from_secs(10), HttpMetrics::default())
+        HttpFactory::new(
+            Duration::from_secs(10),
+            HttpPoolOptions::default(),
+            CircuitBreakerOptions::default(),
+            HttpMetrics::default(),
+        )
     }
 }
 
+/// An error indicating that an HTTP request completed with a non-success
+/// status code.
+#[derive(Debug, thiserror::Error)]
+#[error("HTTP error status {status}: '{body}'")]
+pub struct HttpStatusError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
 /// An HTTP client instance with metrics.
 #[derive(Debug)]
 pub struct HttpClient {
     inner: isahc::HttpClient,
     metrics: Arc<HttpMetrics>,
+    circuit_breaker: Option<CircuitBreaker>,
+    /// The number of times GET requests failing without a response are
+    /// retried.
+    retries: u32,
 }
 
 impl HttpClient {
+    /// Removes the circuit breaker from this client, so that requests are
+    /// always sent regardless of previous failures.
+    pub fn without_circuit_breaker(mut self) -> Self {
+        self.circuit_breaker = None;
+        self
+    }
+
+    /// Returns the metrics registry used by this client.
+    pub fn metrics(&self) -> &HttpMetrics {
+        &self.metrics
+    }
+
     /// Post raw JSON data and return a future that resolves once the HTTP
     /// request has been completed.
     pub async fn post_raw_json_async<U>(
@@ -75,18 +257,20 @@ impl HttpClient {
         let http_request = Request::post(url)
             .header("Content-Type", "application/json")
             .body(data.into())?;
-        let mut response = self.inner.send_async(http_request).await?;
+        let mut response = self
+            .send(label, || self.inner.send_async(http_request))
+            .await?;
         let content = response.text()?;
 
         if response.status().is_success() {
             self.metrics.request(label, start.elapsed(), content.len());
             Ok(content)
         } else {
-            Err(anyhow!(
-                "HTTP error status {}: '{}'",
-                response.status(),
-                content.trim()
-            ))
+            Err(HttpStatusError {
+                status: response.status(),
+                body: content.trim().to_owned(),
+            }
+            .into())
         }
     }
 
@@ -99,7 +283,18 @@ impl HttpClient {
     {
         let start = Instant::now();
 
-        let json = self.inner.get_async(url).await?.text()?;
+        let uri = Uri::try_from(url).map_err(Into::<HttpError>::into)?;
+        let mut retries = self.retries;
+        let mut response = loop {
+            match self.send(label, || self.inner.get_async(uri.clone())).await {
+                Err(err) if retries > 0 && !err.is::<CircuitOpenError>() => {
+                    log::debug!("retrying failed {:?} request: {:?}", label, err);
+                    retries -= 1;
+                }
+                result => break result?,
+            }
+        };
+        let json = response.text()?;
         let size = json.len();
         self.metrics.request(label, start.elapsed(), size);
 
@@ -107,4 +302,66 @@ impl HttpClient {
             .with_context(|| format!("failed to parse JSON '{}'", json))?;
         Ok(result)
     }
+
+    /// Sends a GET request and returns the response with its body read into
+    /// memory. Unlike `get_json_async` this allows setting headers and reading
+    /// the headers of the response.
+    pub async fn get_bytes_async(
+        &self,
+        request: Request<()>,
+        label: HttpLabel,
+    ) -> Result<Response<Vec<u8>>> {
+        let start = Instant::now();
+        let response = self.send(label, || self.inner.send_async(request)).await?;
+        let (parts, mut body) = response.into_parts();
+        let mut bytes = Vec::new();
+        body.read_to_end(&mut bytes).await?;
+
+        if !parts.status.is_success() {
+            return Err(HttpStatusError {
+                status: parts.status,
+                body: String::from_utf8_lossy(&bytes).trim().to_owned(),
+            }
+            .into());
+        }
+        self.metrics.request(label, start.elapsed(), bytes.len());
+        Ok(Response::from_parts(parts, bytes))
+    }
+
+    /// Sends a request unless the circuit breaker is open and records its
+    /// outcome. Server errors count as failures for the circuit breaker as
+    /// they usually indicate that the remote API is down.
+    async fn send<F>(&self, label: HttpLabel, send: impl FnOnce() -> F) -> Result<Response<Body>>
+    where
+        F: Future<Output = Result<Response<Body>, isahc::Error>>,
+    {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            if let Err(err) = circuit_breaker.check(Instant::now()) {
+                self.metrics.circuit_open(label);
+                return Err(err.into());
+            }
+        }
+
+        let result = {
+            let _in_flight = self.metrics.request_started(label);
+            send().await
+        };
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            let success = matches!(&result, Ok(response) if !response.status().is_server_error());
+            circuit_breaker.record(Instant::now(), success);
+        }
+
+        let response = result?;
+        self.record_connection(label, &response);
+        Ok(response)
+    }
+
+    /// Records whether a request had to open a new connection or reused one
+    /// from the connection pool.
+    fn record_connection(&self, label: HttpLabel, response: &Response<Body>) {
+        if let Some(metrics) = response.metrics() {
+            self.metrics
+                .connection(label, metrics.connect_time() == Duration::from_secs(0));
+        }
+    }
 }
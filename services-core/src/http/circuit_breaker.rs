@@ -0,0 +1,175 @@
+use crate::config::duration_secs;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+/// Options for the circuit breakers guarding requests to external APIs.
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct CircuitBreakerOptions {
+    /// The number of consecutive failed requests after which an external API
+    /// is considered down. Requests to it fail immediately until a probe
+    /// request succeeds. Set to 0 to disable the circuit breaker.
+    #[structopt(
+        long,
+        env = "HTTP_CIRCUIT_BREAKER_FAILURE_THRESHOLD",
+        default_value = "3"
+    )]
+    pub http_circuit_breaker_failure_threshold: u32,
+
+    /// The interval in seconds in which a single probe request is sent to an
+    /// external API that is considered down.
+    #[structopt(
+        long,
+        env = "HTTP_CIRCUIT_BREAKER_PROBE_INTERVAL",
+        default_value = "60",
+        parse(try_from_str = duration_secs),
+    )]
+    pub http_circuit_breaker_probe_interval: Duration,
+}
+
+impl Default for CircuitBreakerOptions {
+    fn default() -> Self {
+        CircuitBreakerOptions {
+            http_circuit_breaker_failure_threshold: 3,
+            http_circuit_breaker_probe_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// An error indicating that a request was not sent because the remote API is
+/// considered down.
+#[derive(Debug, thiserror::Error)]
+#[error("circuit breaker is open after {failures} consecutive failures")]
+pub struct CircuitOpenError {
+    pub failures: u32,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: u32,
+    /// Requests are rejected until this time while the circuit is open.
+    open_until: Option<Instant>,
+}
+
+/// A circuit breaker that stops sending requests to a remote API after
+/// consecutive failures, so that a dead API does not cost the full timeout
+/// for every request. While open, a single probe request is let through
+/// every probe interval to detect when the API recovers.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    options: CircuitBreakerOptions,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(options: CircuitBreakerOptions) -> Self {
+        CircuitBreaker {
+            options,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Checks whether a request may be sent at the specified time. Once the
+    /// probe interval passed the request is let through as a probe and the
+    /// circuit stays open for all other requests until it completes.
+    pub fn check(&self, now: Instant) -> Result<(), CircuitOpenError> {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) if now < open_until => Err(CircuitOpenError {
+                failures: state.consecutive_failures,
+            }),
+            Some(_) => {
+                state.open_until = Some(now + self.options.http_circuit_breaker_probe_interval);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Records the outcome of a request that was sent at the specified time.
+    pub fn record(&self, now: Instant, success: bool) {
+        let threshold = self.options.http_circuit_breaker_failure_threshold;
+        let mut state = self.state.lock().unwrap();
+        if success {
+            if state.open_until.is_some() {
+                log::info!("remote API recovered, closing circuit breaker");
+            }
+            *state = State::default();
+            return;
+        }
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if threshold > 0 && state.consecutive_failures >= threshold {
+            if state.open_until.is_none() {
+                log::warn!(
+                    "remote API failed {} consecutive times, opening circuit breaker",
+                    state.consecutive_failures
+                );
+            }
+            state.open_until = Some(now + self.options.http_circuit_breaker_probe_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerOptions {
+            http_circuit_breaker_failure_threshold: 2,
+            http_circuit_breaker_probe_interval: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = breaker();
+        let now = Instant::now();
+
+        breaker.record(now, false);
+        breaker.record(now, true);
+        breaker.record(now, false);
+        assert!(breaker.check(now).is_ok());
+
+        breaker.record(now, false);
+        assert!(breaker.check(now).is_err());
+        assert!(breaker.check(now + Duration::from_secs(9)).is_err());
+    }
+
+    #[test]
+    fn probes_once_per_interval() {
+        let breaker = breaker();
+        let now = Instant::now();
+        breaker.record(now, false);
+        breaker.record(now, false);
+
+        let probe_time = now + Duration::from_secs(10);
+        assert!(breaker.check(probe_time).is_ok());
+        assert!(breaker.check(probe_time).is_err());
+
+        breaker.record(probe_time, false);
+        assert!(breaker.check(probe_time + Duration::from_secs(5)).is_err());
+
+        let probe_time = probe_time + Duration::from_secs(10);
+        assert!(breaker.check(probe_time).is_ok());
+        breaker.record(probe_time, true);
+        assert!(breaker.check(probe_time).is_ok());
+        assert!(breaker.check(probe_time).is_ok());
+    }
+
+    #[test]
+    fn never_opens_when_disabled() {
+        let breaker = CircuitBreaker::new(CircuitBreakerOptions {
+            http_circuit_breaker_failure_threshold: 0,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        for _ in 0..10 {
+            breaker.record(now, false);
+        }
+        assert!(breaker.check(now).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Error, Result};
+use isahc::{http::Uri, prelude::Configurable, HttpClientBuilder};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+
+/// The kinds of remote services HTTP clients are created for. Every kind has
+/// its own client profile.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum HttpService {
+    /// The Ethereum node. Its timeout is the RPC timeout and retries are
+    /// handled by the transport.
+    Node,
+    GasStation,
+    PriceApi,
+    Alerting,
+}
+
+impl HttpService {
+    /// The profile used when none is configured. Gas prices are needed
+    /// quickly to submit solutions in time, whereas price APIs are only
+    /// polled in the background and tolerate slower responses.
+    fn default_profile(self) -> HttpProfile {
+        let timeout = match self {
+            HttpService::GasStation => Some(Duration::from_secs(2)),
+            HttpService::PriceApi => Some(Duration::from_secs(10)),
+            HttpService::Node | HttpService::Alerting => None,
+        };
+        HttpProfile {
+            timeout,
+            ..Default::default()
+        }
+    }
+}
+
+/// Configuration of the HTTP clients for one kind of remote service.
+#[derive(Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct HttpProfile {
+    /// The timeout in seconds, overriding the default timeout of the factory.
+    #[serde(default, deserialize_with = "deserialize_secs")]
+    pub timeout: Option<Duration>,
+    /// The number of times GET requests failing without a response are
+    /// retried. Requests are not retried by default.
+    pub retries: Option<u32>,
+    /// Headers sent with every request, for example API keys.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// The URL of the proxy requests are sent through.
+    pub proxy: Option<String>,
+}
+
+impl HttpProfile {
+    /// Completes the profile with the fields of another one, which are only
+    /// used where this profile does not specify them.
+    fn or(mut self, other: HttpProfile) -> HttpProfile {
+        for (name, value) in other.headers {
+            self.headers.entry(name).or_insert(value);
+        }
+        HttpProfile {
+            timeout: self.timeout.or(other.timeout),
+            retries: self.retries.or(other.retries),
+            headers: self.headers,
+            proxy: self.proxy.or(other.proxy),
+        }
+    }
+
+    /// Applies the timeout, headers and proxy of the profile to the builder.
+    pub fn configure(&self, mut builder: HttpClientBuilder) -> Result<HttpClientBuilder> {
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.default_header(name.as_str(), value.as_str());
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = proxy
+                .parse::<Uri>()
+                .with_context(|| format!("invalid HTTP proxy URL {}", proxy))?;
+            builder = builder.proxy(Some(proxy));
+        }
+        Ok(builder)
+    }
+}
+
+// NOTE: Header values are not printed since they often contain API keys and
+// options get logged on startup.
+impl Debug for HttpProfile {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("HttpProfile")
+            .field("timeout", &self.timeout)
+            .field("retries", &self.retries)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("proxy", &self.proxy)
+            .finish()
+    }
+}
+
+fn deserialize_secs<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
+/// JSON encoded client profiles by service, for example
+/// `{"gasStation": {"timeout": 2, "retries": 1}}`. Profiles complete the
+/// default profile of their service.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct HttpProfiles(HashMap<HttpService, HttpProfile>);
+
+impl HttpProfiles {
+    /// The profile of the clients for the service.
+    pub fn get(&self, service: HttpService) -> HttpProfile {
+        let default = service.default_profile();
+        match self.0.get(&service) {
+            Some(profile) => profile.clone().or(default),
+            None => default,
+        }
+    }
+}
+
+impl FromStr for HttpProfiles {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        serde_json::from_str(value).context("failed to parse HTTP profiles from JSON string")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles_completing_defaults() {
+        let profiles: HttpProfiles = r#"{
+            "gasStation": {"retries": 1},
+            "priceApi": {"timeout": 5, "headers": {"X-Api-Key": "secret"}},
+            "alerting": {"proxy": "http://proxy:8080"}
+        }"#
+        .parse()
+        .unwrap();
+
+        let gas_station = profiles.get(HttpService::GasStation);
+        assert_eq!(gas_station.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(gas_station.retries, Some(1));
+
+        let price_api = profiles.get(HttpService::PriceApi);
+        assert_eq!(price_api.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(price_api.headers["X-Api-Key"], "secret");
+        assert!(!format!("{:?}", price_api).contains("secret"));
+
+        let alerting = profiles.get(HttpService::Alerting);
+        assert_eq!(alerting.proxy.as_deref(), Some("http://proxy:8080"));
+        assert!(alerting.configure(isahc::HttpClient::builder()).is_ok());
+
+        assert_eq!(profiles.get(HttpService::Node), HttpProfile::default());
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!("{\"unknown\": {}}".parse::<HttpProfiles>().is_err());
+        assert!("{\"node\": {\"timeout\": -1}}"
+            .parse::<HttpProfiles>()
+            .is_err());
+        assert!("{\"node\": {\"timeouts\": 1}}"
+            .parse::<HttpProfiles>()
+            .is_err());
+
+        let profile = HttpProfile {
+            proxy: Some("not a url\n".to_owned()),
+            ..Default::default()
+        };
+        assert!(profile.configure(isahc::HttpClient::builder()).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use isahc::{http::Uri, prelude::Configurable, HttpClientBuilder};
+use structopt::StructOpt;
+
+/// Options for routing outbound HTTP requests, including JSON RPC requests to
+/// the node over HTTP or WebSocket, through a proxy.
+#[derive(Clone, Debug, Default, StructOpt)]
+pub struct HttpProxyOptions {
+    /// The URL of the proxy that HTTP requests are sent through, for example
+    /// `http://proxy:3128` or `socks5h://proxy:1080`. The proxy of a service
+    /// profile takes precedence.
+    #[structopt(long, env = "HTTP_PROXY_URL")]
+    pub http_proxy_url: Option<Uri>,
+
+    /// Comma separated hosts that are connected to directly instead of
+    /// through the proxy. An entry also matches all subdomains of the host
+    /// and `*` matches all hosts.
+    #[structopt(long, env = "HTTP_PROXY_BYPASS", use_delimiter = true)]
+    pub http_proxy_bypass: Vec<String>,
+}
+
+impl HttpProxyOptions {
+    /// Applies the proxy and its bypass rules to the builder.
+    pub fn configure(&self, mut builder: HttpClientBuilder) -> HttpClientBuilder {
+        if let Some(proxy) = &self.http_proxy_url {
+            builder = builder.proxy(Some(proxy.clone()));
+        }
+        let bypass = self.bypass().map(str::to_owned).collect::<Vec<_>>();
+        if !bypass.is_empty() {
+            builder = builder.proxy_blacklist(bypass);
+        }
+        builder
+    }
+
+    /// Whether connections to the host bypass the proxy. This follows the
+    /// rules of the HTTP clients for connections that are not made by them.
+    pub fn bypasses(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.bypass().any(|entry| {
+            let entry = entry.trim_start_matches('.').to_ascii_lowercase();
+            entry == "*"
+                || host == entry
+                || (host.ends_with(&entry) && host[..host.len() - entry.len()].ends_with('.'))
+        })
+    }
+
+    fn bypass(&self) -> impl Iterator<Item = &str> {
+        self.http_proxy_bypass
+            .iter()
+            .map(|host| host.trim())
+            .filter(|host| !host.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proxy_options() {
+        let options = HttpProxyOptions::from_iter_safe(&[
+            "test",
+            "--http-proxy-url",
+            "socks5h://proxy:1080",
+            "--http-proxy-bypass",
+            "localhost,node.internal",
+        ])
+        .unwrap();
+        assert_eq!(
+            options.http_proxy_url,
+            Some(Uri::from_static("socks5h://proxy:1080"))
+        );
+        assert_eq!(
+            options.http_proxy_bypass,
+            vec!["localhost", "node.internal"]
+        );
+        assert!(options
+            .configure(isahc::HttpClient::builder())
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn bypasses_hosts_and_their_subdomains() {
+        let options = HttpProxyOptions {
+            http_proxy_url: Some(Uri::from_static("http://proxy:3128")),
+            http_proxy_bypass: vec!["localhost".to_owned(), " node.internal".to_owned()],
+        };
+        assert!(options.bypasses("localhost"));
+        assert!(options.bypasses("eth.node.internal"));
+        assert!(options.bypasses("NODE.internal"));
+        assert!(!options.bypasses("mainnode.internal"));
+        assert!(!options.bypasses("infura.io"));
+
+        let options = HttpProxyOptions {
+            http_proxy_bypass: vec!["*".to_owned()],
+            ..options
+        };
+        assert!(options.bypasses("infura.io"));
+    }
+}
@@ -22,7 +22,15 @@ pub trait Serving {
     where
         Self: Sized + Send + 'static,
     {
-        let _ = thread::spawn(move || self.serve(DEFAULT_MONITOR_PORT));
+        self.start_in_background_on_port(DEFAULT_MONITOR_PORT);
+    }
+
+    /// Starts the HTTP server on a background thread with the specified port.
+    fn start_in_background_on_port(self, port: u16)
+    where
+        Self: Sized + Send + 'static,
+    {
+        let _ = thread::spawn(move || self.serve(port));
     }
 }
 
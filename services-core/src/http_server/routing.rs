@@ -8,6 +8,12 @@ use std::sync::Arc;
 pub struct DefaultRouter {
     pub metrics: Arc<dyn Handler>,
     pub health_readiness: Arc<dyn Handler>,
+    /// Reports the startup progress of services that track it.
+    pub startup_progress: Option<Arc<dyn Handler>>,
+    /// Reports the receipts of the most recent solution submissions.
+    pub submission_receipts: Option<Arc<dyn Handler>>,
+    /// Serves the orderbook file as a checkpoint for other instances.
+    pub orderbook_snapshot: Option<Arc<dyn Handler>>,
 }
 
 impl Handler for DefaultRouter {
@@ -16,6 +22,24 @@ impl Handler for DefaultRouter {
         let handler = router!(request,
             (GET) (/metrics) => { self.metrics.as_ref() },
             (GET) (/health/readiness) => { self.health_readiness.as_ref() },
+            (GET) (/health/startup) => {
+                match &self.startup_progress {
+                    Some(startup_progress) => startup_progress.as_ref(),
+                    None => &NotFound,
+                }
+            },
+            (GET) (/submissions) => {
+                match &self.submission_receipts {
+                    Some(submission_receipts) => submission_receipts.as_ref(),
+                    None => &NotFound,
+                }
+            },
+            (GET) (/orderbook/snapshot) => {
+                match &self.orderbook_snapshot {
+                    Some(orderbook_snapshot) => orderbook_snapshot.as_ref(),
+                    None => &NotFound,
+                }
+            },
             _ => &NotFound,
         );
         handler.handle_request(request)
@@ -51,6 +75,9 @@ mod tests {
         let router = DefaultRouter {
             metrics: Arc::new(metrics),
             health_readiness: Arc::new(health_readiness),
+            startup_progress: None,
+            submission_receipts: None,
+            orderbook_snapshot: None,
         };
 
         let response = router
@@ -74,11 +101,36 @@ mod tests {
         let router = DefaultRouter {
             metrics: Arc::new(MockHandler::new()),
             health_readiness: Arc::new(MockHandler::new()),
+            startup_progress: None,
+            submission_receipts: None,
+            orderbook_snapshot: None,
         };
 
         let response = router
             .handle_request(&Request::fake_http("GET", "/foo", vec![], vec![]))
             .unwrap();
         assert_eq!(response.status_code, 404);
+        let response = router
+            .handle_request(&Request::fake_http(
+                "GET",
+                "/health/startup",
+                vec![],
+                vec![],
+            ))
+            .unwrap();
+        assert_eq!(response.status_code, 404);
+        let response = router
+            .handle_request(&Request::fake_http("GET", "/submissions", vec![], vec![]))
+            .unwrap();
+        assert_eq!(response.status_code, 404);
+        let response = router
+            .handle_request(&Request::fake_http(
+                "GET",
+                "/orderbook/snapshot",
+                vec![],
+                vec![],
+            ))
+            .unwrap();
+        assert_eq!(response.status_code, 404);
     }
 }
@@ -6,9 +6,13 @@
 pub mod macros;
 
 pub mod bigint_u256;
+pub mod config;
 pub mod contracts;
+#[cfg(feature = "solver")]
 pub mod driver;
 pub mod economic_viability;
+pub mod error;
+pub mod event_export;
 pub mod gas_price;
 pub mod health;
 pub mod history;
@@ -19,9 +23,12 @@ pub mod metrics;
 pub mod models;
 pub mod orderbook;
 pub mod price_estimation;
+#[cfg(feature = "solver")]
 pub mod price_finding;
 pub mod serialization;
+#[cfg(feature = "solver")]
 pub mod solution_submission;
+pub mod startup;
 pub mod time;
 pub mod token_info;
 pub mod transport;
@@ -1,9 +1,11 @@
 mod http_metrics;
 mod metrics_handler;
+mod push_gateway;
 pub mod solver_metrics;
 mod stablex_metrics;
 
-pub use http_metrics::{HttpLabel, HttpMetrics};
+pub use http_metrics::{HttpLabel, HttpMetrics, InFlightRequest};
 pub use metrics_handler::MetricsHandler;
+pub use push_gateway::{push_metrics, record_gauge, run_job, MetricsPushOptions};
 pub use solver_metrics::SolverMetrics;
 pub use stablex_metrics::StableXMetrics;
@@ -1,6 +1,9 @@
 use anyhow::Result;
 use ethcontract::jsonrpc::types::{Call, Request};
-use prometheus::{HistogramOpts, HistogramVec, Registry, DEFAULT_BUCKETS};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    DEFAULT_BUCKETS,
+};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,6 +12,12 @@ use std::time::Duration;
 pub struct HttpMetrics {
     latency: HistogramVec,
     size: HistogramVec,
+    retries: IntCounterVec,
+    retry_budget_exhausted: IntCounter,
+    in_flight: IntGaugeVec,
+    connections: IntCounterVec,
+    max_connections_per_host: IntGauge,
+    circuit_open: IntCounterVec,
 }
 
 impl HttpMetrics {
@@ -27,7 +36,73 @@ impl HttpMetrics {
             prometheus::exponential_buckets(100.0, 10.0, 8)?,
         )?;
 
-        Ok(HttpMetrics { latency, size })
+        let retries = IntCounterVec::new(
+            Opts::new(
+                "dfusion_service_http_retries",
+                "Number of retried HTTP requests after a transient error",
+            ),
+            &["request"],
+        )?;
+        for label in HttpLabel::all_labels() {
+            retries.with_label_values(&label.values());
+        }
+        registry.register(Box::new(retries.clone()))?;
+
+        let retry_budget_exhausted = IntCounter::new(
+            "dfusion_service_http_retry_budget_exhausted",
+            "Number of transient HTTP errors not retried because the retry budget was used up",
+        )?;
+        registry.register(Box::new(retry_budget_exhausted.clone()))?;
+
+        let in_flight = IntGaugeVec::new(
+            Opts::new(
+                "dfusion_service_http_in_flight",
+                "Number of HTTP requests waiting for a response",
+            ),
+            &["request"],
+        )?;
+        for label in HttpLabel::all_labels() {
+            in_flight.with_label_values(&label.values());
+        }
+        registry.register(Box::new(in_flight.clone()))?;
+
+        let connections = IntCounterVec::new(
+            Opts::new(
+                "dfusion_service_http_connections",
+                "Number of HTTP requests by whether they reused a pooled connection",
+            ),
+            &["request", "reused"],
+        )?;
+        registry.register(Box::new(connections.clone()))?;
+
+        let max_connections_per_host = IntGauge::new(
+            "dfusion_service_http_max_connections_per_host",
+            "Configured maximum number of HTTP connections per host",
+        )?;
+        registry.register(Box::new(max_connections_per_host.clone()))?;
+
+        let circuit_open = IntCounterVec::new(
+            Opts::new(
+                "dfusion_service_http_circuit_open",
+                "Number of HTTP requests rejected because the remote API is considered down",
+            ),
+            &["request"],
+        )?;
+        for label in HttpLabel::all_labels() {
+            circuit_open.with_label_values(&label.values());
+        }
+        registry.register(Box::new(circuit_open.clone()))?;
+
+        Ok(HttpMetrics {
+            latency,
+            size,
+            retries,
+            retry_budget_exhausted,
+            in_flight,
+            connections,
+            max_connections_per_host,
+            circuit_open,
+        })
     }
 
     /// Initializes a histogram with for all the labels.
@@ -58,6 +133,55 @@ impl HttpMetrics {
             .with_label_values(&label.values())
             .observe(size as _);
     }
+
+    /// Records that a request for the specified label is being retried.
+    pub fn retry(&self, label: HttpLabel) {
+        self.retries.with_label_values(&label.values()).inc();
+    }
+
+    /// Records that a transient error was not retried because there is no
+    /// retry budget left.
+    pub fn retry_budget_exhausted(&self) {
+        self.retry_budget_exhausted.inc();
+    }
+
+    /// Records the connection pool limit so that the number of in flight
+    /// requests can be related to it.
+    pub fn pool_configured(&self, max_connections_per_host: usize) {
+        self.max_connections_per_host
+            .set(max_connections_per_host as _);
+    }
+
+    /// Records that a request for the specified label was sent. The request is
+    /// counted as in flight until the returned guard is dropped.
+    pub fn request_started(&self, label: HttpLabel) -> InFlightRequest {
+        let gauge = self.in_flight.with_label_values(&label.values());
+        gauge.inc();
+        InFlightRequest(gauge)
+    }
+
+    /// Records that a request was not sent because the circuit breaker of the
+    /// remote API is open.
+    pub fn circuit_open(&self, label: HttpLabel) {
+        self.circuit_open.with_label_values(&label.values()).inc();
+    }
+
+    /// Records whether a request opened a new connection or reused a pooled
+    /// one.
+    pub fn connection(&self, label: HttpLabel, reused: bool) {
+        self.connections
+            .with_label_values(&[label.values()[0], if reused { "true" } else { "false" }])
+            .inc();
+    }
+}
+
+/// Guard counting a request as in flight while it is alive.
+pub struct InFlightRequest(IntGauge);
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
 }
 
 impl Default for HttpMetrics {
@@ -110,6 +234,8 @@ labels! {
         Dexag => "dexag",
         Oneinch => "oneinch",
         GasStation => "gas_station",
+        AlertWebhook => "alert_webhook",
+        OrderbookCheckpoint => "orderbook_checkpoint",
     }
 }
 
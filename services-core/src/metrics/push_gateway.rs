@@ -0,0 +1,147 @@
+//! Pushing metrics to a Prometheus push gateway. Short-lived tools exit before
+//! they could be scraped, so they push the metrics of their run instead.
+
+use anyhow::{anyhow, ensure, Result};
+use isahc::prelude::{Request, RequestExt};
+use prometheus::{Encoder, Gauge, Registry, TextEncoder};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+use url::Url;
+
+/// Options for pushing the metrics of short-lived tools.
+#[derive(Clone, Debug, StructOpt)]
+pub struct MetricsPushOptions {
+    /// The URL of a Prometheus push gateway that the metrics of the run are
+    /// pushed to once it finishes. Metrics are not pushed if unset.
+    #[structopt(long, env = "METRICS_PUSH_URL")]
+    pub metrics_push_url: Option<Url>,
+}
+
+impl MetricsPushOptions {
+    /// Pushes the metrics of the registry for the job if a push gateway is
+    /// configured.
+    pub fn push(&self, job: &str, registry: &Registry) -> Result<()> {
+        match &self.metrics_push_url {
+            Some(url) => push_metrics(url, job, registry),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Pushes the metrics of the registry to the push gateway, replacing all
+/// metrics previously pushed for the job.
+pub fn push_metrics(url: &Url, job: &str, registry: &Registry) -> Result<()> {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&registry.gather(), &mut body)?;
+
+    let response = Request::put(job_url(url, job)?.as_str())
+        .header("Content-Type", encoder.format_type())
+        .body(body)?
+        .send()?;
+    ensure!(
+        response.status().is_success(),
+        "push gateway responded with status {}",
+        response.status()
+    );
+    Ok(())
+}
+
+/// Runs a short-lived job and pushes its metrics once it finishes. The job
+/// registers the metrics of its results in the registry it is passed, to which
+/// the duration of the run, whether it succeeded and when it finished are
+/// added.
+///
+/// Errors of the job take precedence over errors pushing its metrics.
+pub fn run_job<T>(
+    options: &MetricsPushOptions,
+    job: &str,
+    run: impl FnOnce(&Registry) -> Result<T>,
+) -> Result<T> {
+    let registry = Registry::new();
+    let start = Instant::now();
+    let result = run(&registry);
+
+    let push =
+        record_run(&registry, start, result.is_ok()).and_then(|_| options.push(job, &registry));
+    let value = result?;
+    push?;
+    Ok(value)
+}
+
+fn record_run(registry: &Registry, start: Instant, success: bool) -> Result<()> {
+    record_gauge(
+        registry,
+        "job_duration_seconds",
+        "The duration of the last run of the job.",
+        start.elapsed().as_secs_f64(),
+    )?;
+    record_gauge(
+        registry,
+        "job_success",
+        "Whether the last run of the job succeeded.",
+        if success { 1.0 } else { 0.0 },
+    )?;
+    record_gauge(
+        registry,
+        "job_last_completion_timestamp_seconds",
+        "The unix timestamp at which the last run of the job finished.",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64(),
+    )
+}
+
+/// Registers a gauge with the value of a job result, which is pushed with the
+/// metrics of the run.
+pub fn record_gauge(registry: &Registry, name: &str, help: &str, value: f64) -> Result<()> {
+    let gauge = Gauge::new(name, help)?;
+    gauge.set(value);
+    registry.register(Box::new(gauge))?;
+    Ok(())
+}
+
+fn job_url(url: &Url, job: &str) -> Result<Url> {
+    let mut url = url.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("push gateway URL cannot be a base"))?
+        .pop_if_empty()
+        .extend(&["metrics", "job", job]);
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_url_appends_job_path() {
+        for (base, expected) in &[
+            (
+                "http://localhost:9091",
+                "http://localhost:9091/metrics/job/audit_solution",
+            ),
+            (
+                "http://localhost:9091/prefix/",
+                "http://localhost:9091/prefix/metrics/job/audit_solution",
+            ),
+        ] {
+            let url = job_url(&base.parse().unwrap(), "audit_solution").unwrap();
+            assert_eq!(url.as_str(), *expected);
+        }
+        assert!(job_url(&"mailto:metrics@gnosis.io".parse().unwrap(), "job").is_err());
+    }
+
+    #[test]
+    fn run_job_without_push_url() {
+        let options = MetricsPushOptions {
+            metrics_push_url: None,
+        };
+        let value = run_job(&options, "job", |registry| {
+            assert!(registry.gather().is_empty());
+            record_gauge(registry, "job_result", "The result of the job.", 42.0)?;
+            Ok(42)
+        })
+        .unwrap();
+        assert_eq!(value, 42);
+        assert!(run_job(&options, "job", |_| -> Result<()> { Err(anyhow!("error")) }).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use prometheus::{Gauge, IntCounter, Registry};
+use prometheus::{GaugeVec, IntCounterVec, Opts, Registry};
 use serde::Deserialize;
 use serde_json::{json, Number, Value};
 use std::{collections::HashMap, sync::Arc};
@@ -19,27 +19,32 @@ pub struct SolverStats {
     pub solver: HashMap<String, Value>,
 }
 
+/// The metrics of the solver runs, labelled with the solver so that racing
+/// solvers do not overwrite each other's metrics.
+#[derive(Clone)]
 pub struct SolverMetrics {
-    volume: Gauge,
-    utility: Gauge,
-    utility_disreg: Gauge,
-    utility_disreg_touched: Gauge,
-    fees: Gauge,
-    orders_touched: Gauge,
-    runtime: Gauge,
-    runtime_preprocessing: Gauge,
-    runtime_solving: Gauge,
-    runtime_ring_finding: Gauge,
-    runtime_validation: Gauge,
-    nr_variables: Gauge,
-    nr_bool_variables: Gauge,
-    optimality_gap: Gauge,
-    obj_val: Gauge,
-    obj_val_sc: Gauge,
-    interrupted: IntCounter,
+    solver: String,
+    volume: GaugeVec,
+    utility: GaugeVec,
+    utility_disreg: GaugeVec,
+    utility_disreg_touched: GaugeVec,
+    fees: GaugeVec,
+    orders_touched: GaugeVec,
+    runtime: GaugeVec,
+    runtime_preprocessing: GaugeVec,
+    runtime_solving: GaugeVec,
+    runtime_ring_finding: GaugeVec,
+    runtime_validation: GaugeVec,
+    nr_variables: GaugeVec,
+    nr_bool_variables: GaugeVec,
+    optimality_gap: GaugeVec,
+    obj_val: GaugeVec,
+    obj_val_sc: GaugeVec,
+    interrupted: IntCounterVec,
 }
 
 impl SolverMetrics {
+    /// Creates the metrics for the primary solver.
     pub fn new(registry: Arc<Registry>) -> Self {
         let make_gauge = |name| {
             let name = format!("dfusion_solver_{}", name);
@@ -47,14 +52,17 @@ impl SolverMetrics {
             // repository instead of here. However the prometheus library requires a non empty help
             // string so we use a single space.
             let help = " ".to_string();
-            let gauge = Gauge::new(name, help).unwrap();
+            let gauge = GaugeVec::new(Opts::new(name, help), &["solver"]).unwrap();
             registry.register(Box::new(gauge.clone())).unwrap();
             gauge
         };
 
-        let interrupted = IntCounter::new(
-            "dfusion_solver_interrupted",
-            "Increments when solving ran out of time",
+        let interrupted = IntCounterVec::new(
+            Opts::new(
+                "dfusion_solver_interrupted",
+                "Increments when solving ran out of time",
+            ),
+            &["solver"],
         )
         .unwrap();
         registry.register(Box::new(interrupted.clone())).unwrap();
@@ -62,6 +70,7 @@ impl SolverMetrics {
         macro_rules! create {
             ($($name:ident),*) => {
                 Self {
+                    solver: "primary".to_string(),
                     $(
                         $name: make_gauge(stringify!($name))
                     ),*,
@@ -90,39 +99,45 @@ impl SolverMetrics {
         }
     }
 
+    /// Returns the metrics for another solver, sharing the registered metrics
+    /// with a different label.
+    pub fn for_solver(&self, solver: impl Into<String>) -> Self {
+        Self {
+            solver: solver.into(),
+            ..self.clone()
+        }
+    }
+
     /// If a metric is not found in the solution file or cannot be converted to a float, it is set
     /// to 0.
     pub fn handle_stats(&self, stats: &SolverStats) {
-        self.volume.set(f64_or_0(&stats.obj_vals, "volume"));
-        self.utility.set(f64_or_0(&stats.obj_vals, "utility"));
-        self.utility_disreg
-            .set(f64_or_0(&stats.obj_vals, "utility_disreg"));
-        self.utility_disreg_touched
-            .set(f64_or_0(&stats.obj_vals, "utility_disreg_touched"));
-        self.fees.set(f64_or_0(&stats.obj_vals, "fees"));
-        self.orders_touched
-            .set(f64_or_0(&stats.obj_vals, "orders_touched"));
+        let labels = [self.solver.as_str()];
+        let set = |gauge: &GaugeVec, values: &HashMap<String, Value>, key: &str| {
+            gauge.with_label_values(&labels).set(f64_or_0(values, key))
+        };
+        let set_obj_val = |gauge: &GaugeVec, key: &str| set(gauge, &stats.obj_vals, key);
+        let set_solver = |gauge: &GaugeVec, key: &str| set(gauge, &stats.solver, key);
+
+        set_obj_val(&self.volume, "volume");
+        set_obj_val(&self.utility, "utility");
+        set_obj_val(&self.utility_disreg, "utility_disreg");
+        set_obj_val(&self.utility_disreg_touched, "utility_disreg_touched");
+        set_obj_val(&self.fees, "fees");
+        set_obj_val(&self.orders_touched, "orders_touched");
 
-        self.runtime.set(f64_or_0(&stats.solver, "runtime"));
-        self.runtime_preprocessing
-            .set(f64_or_0(&stats.solver, "runtime_preprocessing"));
-        self.runtime_solving
-            .set(f64_or_0(&stats.solver, "runtime_solving"));
-        self.runtime_ring_finding
-            .set(f64_or_0(&stats.solver, "runtime_ring_finding"));
-        self.runtime_validation
-            .set(f64_or_0(&stats.solver, "runtime_validation"));
-        self.nr_variables
-            .set(f64_or_0(&stats.solver, "nr_variables"));
-        self.nr_bool_variables
-            .set(f64_or_0(&stats.solver, "nr_bool_variables"));
-        self.optimality_gap
-            .set(f64_or_0(&stats.solver, "optimality_gap"));
-        self.obj_val.set(f64_or_0(&stats.solver, "obj_val"));
-        self.obj_val_sc.set(f64_or_0(&stats.solver, "obj_val_sc"));
+        set_solver(&self.runtime, "runtime");
+        set_solver(&self.runtime_preprocessing, "runtime_preprocessing");
+        set_solver(&self.runtime_solving, "runtime_solving");
+        set_solver(&self.runtime_ring_finding, "runtime_ring_finding");
+        set_solver(&self.runtime_validation, "runtime_validation");
+        set_solver(&self.nr_variables, "nr_variables");
+        set_solver(&self.nr_bool_variables, "nr_bool_variables");
+        set_solver(&self.optimality_gap, "optimality_gap");
+        set_solver(&self.obj_val, "obj_val");
+        set_solver(&self.obj_val_sc, "obj_val_sc");
 
         if stats.solver.get("exit_status") == Some(&json!("interrupted")) {
-            self.interrupted.inc();
+            self.interrupted.with_label_values(&labels).inc();
         }
     }
 }
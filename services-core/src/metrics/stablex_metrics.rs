@@ -1,21 +1,47 @@
-use crate::models::{AccountState, Order, Solution};
-use crate::solution_submission::SolutionSubmissionError;
-use anyhow::Result;
+#[cfg(feature = "solver")]
+use crate::driver::stablex_driver::SkipBatchPolicy;
+use crate::error::ErrorCode;
+use crate::gas_price::GasEstimatorType;
+use crate::models::{AccountState, BatchId, Order, Solution};
+#[cfg(feature = "solver")]
+use crate::solution_submission::{SolutionSubmissionError, SubmissionReceipt};
+use anyhow::{Error, Result};
 use chrono::Utc;
-use ethcontract::U256;
-use prometheus::{Gauge, IntCounterVec, IntGaugeVec, Opts, Registry};
+use ethcontract::{Address, U256};
+use prometheus::{
+    Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry,
+};
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct StableXMetrics {
     processing_times: IntGaugeVec,
     failures: IntCounterVec,
+    errors: IntCounterVec,
     successes: IntCounterVec,
     orders: IntGaugeVec,
     tokens: IntGaugeVec,
     users: IntGaugeVec,
     min_avg_fee: Gauge,
+    account_balance: GaugeVec,
+    submission_accounts: IntCounterVec,
+    clock_drift: Gauge,
+    balance_checks: IntCounterVec,
+    orderbook_fetch_degradations: IntCounterVec,
+    gas_estimate_outcomes: IntCounterVec,
+    gas_estimate_inclusion_ratio: HistogramVec,
+    orderbook_buffered_events: IntGauge,
+    orderbook_buffered_events_peak: IntGauge,
+    orderbook_filtered_orders: IntGaugeVec,
+    market_allowlist_markets: IntGauge,
+    batch_timeline: HistogramVec,
+    solution_gas_used: Gauge,
+    solution_gas_price: Gauge,
+    solution_block: IntGauge,
+    circuit_breaker_open: IntGauge,
 }
 
 impl StableXMetrics {
@@ -35,6 +61,18 @@ impl StableXMetrics {
         ProcessingStage::initialize_counters(&failures);
         registry.register(Box::new(failures.clone())).unwrap();
 
+        let error_opts = Opts::new(
+            "dfusion_service_errors",
+            "number of errors by the subsystem that failed and the error code of the cause",
+        );
+        let errors = IntCounterVec::new(error_opts, &["subsystem", "code"]).unwrap();
+        for code in ErrorCode::ALL {
+            errors
+                .with_label_values(&[code.subsystem().as_str(), code.as_str()])
+                .inc_by(0);
+        }
+        registry.register(Box::new(errors.clone())).unwrap();
+
         let success_opts = Opts::new(
             "dfusion_service_success",
             "number of auctions successfully processed",
@@ -71,14 +109,191 @@ impl StableXMetrics {
         let min_avg_fee = Gauge::with_opts(min_avg_fee_opts).unwrap();
         registry.register(Box::new(min_avg_fee.clone())).unwrap();
 
+        let account_balance_opts = Opts::new(
+            "dfusion_service_account_balance",
+            "balance of the solution submitting accounts in native token units",
+        );
+        let account_balance = GaugeVec::new(account_balance_opts, &["account"]).unwrap();
+        registry
+            .register(Box::new(account_balance.clone()))
+            .unwrap();
+
+        let submission_accounts_opts = Opts::new(
+            "dfusion_service_submission_accounts",
+            "number of solution submissions per submitting account",
+        );
+        let submission_accounts =
+            IntCounterVec::new(submission_accounts_opts, &["account"]).unwrap();
+        registry
+            .register(Box::new(submission_accounts.clone()))
+            .unwrap();
+
+        let clock_drift_opts = Opts::new(
+            "dfusion_service_clock_drift",
+            "difference between the system time and the chain time in seconds",
+        );
+        let clock_drift = Gauge::with_opts(clock_drift_opts).unwrap();
+        registry.register(Box::new(clock_drift.clone())).unwrap();
+
+        let balance_checks_opts = Opts::new(
+            "dfusion_service_orderbook_balance_checks",
+            "number of orderbook balances compared to the on-chain balance",
+        );
+        let balance_checks = IntCounterVec::new(balance_checks_opts, &["result"]).unwrap();
+        for result in &["match", "mismatch"] {
+            balance_checks.with_label_values(&[result]).inc_by(0);
+        }
+        registry.register(Box::new(balance_checks.clone())).unwrap();
+
+        let orderbook_fetch_degradations_opts = Opts::new(
+            "dfusion_service_orderbook_fetch_degradations",
+            "number of failed orderbook fetches handled by the skip batch policy",
+        );
+        let orderbook_fetch_degradations =
+            IntCounterVec::new(orderbook_fetch_degradations_opts, &["policy"]).unwrap();
+        #[cfg(feature = "solver")]
+        for policy in SkipBatchPolicy::variant_names() {
+            orderbook_fetch_degradations
+                .with_label_values(&[policy])
+                .inc_by(0);
+        }
+        registry
+            .register(Box::new(orderbook_fetch_degradations.clone()))
+            .unwrap();
+
+        let gas_estimate_outcomes_opts = Opts::new(
+            "dfusion_service_gas_estimate_outcomes",
+            "number of submissions by the estimator of their gas price and whether they were mined within the time limit",
+        );
+        let gas_estimate_outcomes =
+            IntCounterVec::new(gas_estimate_outcomes_opts, &["estimator", "outcome"]).unwrap();
+        for estimator in GasEstimatorType::variant_names() {
+            for outcome in &["in_time", "late", "not_mined"] {
+                gas_estimate_outcomes
+                    .with_label_values(&[estimator, outcome])
+                    .inc_by(0);
+            }
+        }
+        registry
+            .register(Box::new(gas_estimate_outcomes.clone()))
+            .unwrap();
+
+        let gas_estimate_inclusion_ratio_opts = HistogramOpts::new(
+            "dfusion_service_gas_estimate_inclusion_ratio",
+            "time until a submission was mined relative to the time limit of its gas price estimate",
+        )
+        .buckets(vec![0.1, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 4.0]);
+        let gas_estimate_inclusion_ratio =
+            HistogramVec::new(gas_estimate_inclusion_ratio_opts, &["estimator"]).unwrap();
+        registry
+            .register(Box::new(gas_estimate_inclusion_ratio.clone()))
+            .unwrap();
+
+        let orderbook_buffered_events = IntGauge::with_opts(Opts::new(
+            "dfusion_service_orderbook_buffered_events",
+            "number of fetched orderbook events waiting to be applied",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(orderbook_buffered_events.clone()))
+            .unwrap();
+
+        let orderbook_buffered_events_peak = IntGauge::with_opts(Opts::new(
+            "dfusion_service_orderbook_buffered_events_peak",
+            "highest number of fetched orderbook events that were waiting to be applied",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(orderbook_buffered_events_peak.clone()))
+            .unwrap();
+
+        let orderbook_filtered_orders_opts = Opts::new(
+            "dfusion_service_orderbook_filtered_orders",
+            "number of orders of the last fetched orderbook before and after filtering",
+        );
+        let orderbook_filtered_orders =
+            IntGaugeVec::new(orderbook_filtered_orders_opts, &["stage"]).unwrap();
+        registry
+            .register(Box::new(orderbook_filtered_orders.clone()))
+            .unwrap();
+
+        let market_allowlist_markets = IntGauge::with_opts(Opts::new(
+            "dfusion_service_market_allowlist_markets",
+            "number of markets orders are restricted to, 0 if orders of all markets are considered",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(market_allowlist_markets.clone()))
+            .unwrap();
+
+        let batch_timeline_opts = HistogramOpts::new(
+            "dfusion_service_batch_timeline",
+            "seconds after the start of the solving window at which a batch reached a processing stage",
+        )
+        .buckets(prometheus::linear_buckets(0.0, 15.0, 21).unwrap());
+        let batch_timeline =
+            HistogramVec::new(batch_timeline_opts, &[ProcessingStage::LABEL]).unwrap();
+        registry.register(Box::new(batch_timeline.clone())).unwrap();
+
+        let solution_gas_used = Gauge::with_opts(Opts::new(
+            "dfusion_service_solution_gas_used",
+            "gas used by the last mined solution submission",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(solution_gas_used.clone()))
+            .unwrap();
+
+        let solution_gas_price = Gauge::with_opts(Opts::new(
+            "dfusion_service_solution_gas_price",
+            "gas price in wei of the last mined solution submission",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(solution_gas_price.clone()))
+            .unwrap();
+
+        let solution_block = IntGauge::with_opts(Opts::new(
+            "dfusion_service_solution_block",
+            "block number of the last mined solution submission",
+        ))
+        .unwrap();
+        registry.register(Box::new(solution_block.clone())).unwrap();
+
+        let circuit_breaker_open = IntGauge::with_opts(Opts::new(
+            "dfusion_service_circuit_breaker_open",
+            "1 if the driver skips batches because of repeated failures, 0 otherwise",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(circuit_breaker_open.clone()))
+            .unwrap();
+
         Self {
             processing_times,
             failures,
+            errors,
             successes,
             orders,
             tokens,
             users,
             min_avg_fee,
+            account_balance,
+            submission_accounts,
+            clock_drift,
+            balance_checks,
+            orderbook_fetch_degradations,
+            gas_estimate_outcomes,
+            gas_estimate_inclusion_ratio,
+            orderbook_buffered_events,
+            orderbook_buffered_events_peak,
+            orderbook_filtered_orders,
+            market_allowlist_markets,
+            batch_timeline,
+            solution_gas_used,
+            solution_gas_price,
+            solution_block,
+            circuit_breaker_open,
         }
     }
 
@@ -91,11 +306,7 @@ impl StableXMetrics {
         }
         let stage_label = &[ProcessingStage::Started.as_ref()];
         match res {
-            Ok(batch) => {
-                self.processing_times
-                    .with_label_values(stage_label)
-                    .set(time_elapsed_since_batch_start(*batch));
-            }
+            Ok(batch) => self.stage_reached(ProcessingStage::Started, *batch),
             Err(_) => self.failures.with_label_values(stage_label).inc(),
         };
     }
@@ -103,9 +314,7 @@ impl StableXMetrics {
     pub fn auction_orders_fetched(&self, batch: u32, res: &Result<(AccountState, Vec<Order>)>) {
         let stage_label = &[ProcessingStage::OrdersFetched.as_ref()];
         let book_label = &[BookType::Orderbook.as_ref()];
-        self.processing_times
-            .with_label_values(stage_label)
-            .set(time_elapsed_since_batch_start(batch));
+        self.stage_reached(ProcessingStage::OrdersFetched, batch);
         match res {
             Ok((_, orders)) => {
                 self.orders
@@ -118,16 +327,17 @@ impl StableXMetrics {
                     .with_label_values(book_label)
                     .set(users_from_orders(&orders));
             }
-            Err(_) => self.failures.with_label_values(stage_label).inc(),
+            Err(err) => {
+                self.failures.with_label_values(stage_label).inc();
+                self.error_occurred(err, ErrorCode::OrderbookFetch);
+            }
         }
     }
 
     pub fn auction_solution_computed(&self, batch: u32, res: &Result<Solution>) {
         let stage_label = &[ProcessingStage::Solved.as_ref()];
         let book_label = &[BookType::Solution.as_ref()];
-        self.processing_times
-            .with_label_values(stage_label)
-            .set(time_elapsed_since_batch_start(batch));
+        self.stage_reached(ProcessingStage::Solved, batch);
         match res {
             Ok(solution) => {
                 self.orders.with_label_values(book_label).set(
@@ -144,45 +354,59 @@ impl StableXMetrics {
                     .with_label_values(book_label)
                     .set(users_from_solution(solution));
             }
-            Err(_) => self.failures.with_label_values(stage_label).inc(),
+            Err(err) => {
+                self.failures.with_label_values(stage_label).inc();
+                self.error_occurred(err, ErrorCode::SolverFailed);
+            }
         }
     }
 
+    #[cfg(feature = "solver")]
     pub fn auction_solution_verified(
         &self,
         batch: u32,
         res: &Result<U256, SolutionSubmissionError>,
     ) {
         let stage_label = &[ProcessingStage::Verified.as_ref()];
-        self.processing_times
-            .with_label_values(stage_label)
-            .set(time_elapsed_since_batch_start(batch));
+        self.stage_reached(ProcessingStage::Verified, batch);
         match res {
             Ok(_) => (),
             Err(err) => match err {
                 SolutionSubmissionError::Benign(_) => (),
-                SolutionSubmissionError::Unexpected(_) => {
-                    self.failures.with_label_values(stage_label).inc()
+                SolutionSubmissionError::Unexpected(err) => {
+                    self.failures.with_label_values(stage_label).inc();
+                    self.error_occurred(err, ErrorCode::SubmissionFailed);
                 }
             },
         }
     }
 
+    #[cfg(feature = "solver")]
     pub fn auction_solution_submitted(
         &self,
         batch: u32,
-        res: &Result<(), SolutionSubmissionError>,
+        res: &Result<SubmissionReceipt, SolutionSubmissionError>,
     ) {
         let stage_label = &[ProcessingStage::Submitted.as_ref()];
-        self.processing_times
-            .with_label_values(stage_label)
-            .set(time_elapsed_since_batch_start(batch));
+        self.stage_reached(ProcessingStage::Submitted, batch);
         match res {
-            Ok(_) => self.successes.with_label_values(stage_label).inc(),
+            Ok(receipt) => {
+                self.successes.with_label_values(stage_label).inc();
+                self.solution_gas_price
+                    .set(receipt.gas_price.to_f64_lossy());
+                if let Some(gas_used) = receipt.gas_used {
+                    self.solution_gas_used.set(gas_used.to_f64_lossy());
+                }
+                if let Some(block_number) = receipt.block_number {
+                    self.solution_block
+                        .set(block_number.try_into().unwrap_or(std::i64::MAX));
+                }
+            }
             Err(err) => match err {
                 SolutionSubmissionError::Benign(_) => (),
-                SolutionSubmissionError::Unexpected(_) => {
-                    self.failures.with_label_values(stage_label).inc()
+                SolutionSubmissionError::Unexpected(err) => {
+                    self.failures.with_label_values(stage_label).inc();
+                    self.error_occurred(err, ErrorCode::SubmissionFailed);
                 }
             },
         }
@@ -190,22 +414,118 @@ impl StableXMetrics {
 
     pub fn auction_processed_but_not_submitted(&self, batch: u32) {
         let stage_label = &[ProcessingStage::SolutionNotSubmitted.as_ref()];
-        self.processing_times
-            .with_label_values(stage_label)
-            .set(time_elapsed_since_batch_start(batch));
+        self.stage_reached(ProcessingStage::SolutionNotSubmitted, batch);
         self.successes.with_label_values(stage_label).inc();
     }
 
+    /// Records an error by its error code, using the fallback code for errors
+    /// without a known cause.
+    pub fn error_occurred(&self, err: &Error, fallback: ErrorCode) {
+        let code = ErrorCode::of(err, fallback);
+        self.errors
+            .with_label_values(&[code.subsystem().as_str(), code.as_str()])
+            .inc();
+    }
+
+    /// Records the time after the start of the solving window at which the
+    /// batch reached a processing stage.
+    fn stage_reached(&self, stage: ProcessingStage, batch: u32) {
+        let elapsed = time_elapsed_since_batch_start(batch);
+        self.processing_times
+            .with_label_values(&[stage.as_ref()])
+            .set(elapsed);
+        self.batch_timeline
+            .with_label_values(&[stage.as_ref()])
+            .observe(elapsed as f64);
+    }
+
     pub fn min_avg_fee_calculated(&self, min_avg_fee: u128) {
         self.min_avg_fee.set(min_avg_fee as f64);
     }
+
+    pub fn account_balance_fetched(&self, account: Address, balance: U256) {
+        self.account_balance
+            .with_label_values(&[&format!("{:?}", account)])
+            .set(balance.to_f64_lossy() / 1e18);
+    }
+
+    pub fn clock_drift_measured(&self, drift_seconds: f64) {
+        self.clock_drift.set(drift_seconds);
+    }
+
+    pub fn orderbook_balance_checked(&self, matches: bool) {
+        let result = if matches { "match" } else { "mismatch" };
+        self.balance_checks.with_label_values(&[result]).inc();
+    }
+
+    pub fn orderbook_fetch_degraded(&self, policy: &str) {
+        self.orderbook_fetch_degradations
+            .with_label_values(&[policy])
+            .inc();
+    }
+
+    pub fn orderbook_events_buffered(&self, count: usize) {
+        let count = count as i64;
+        self.orderbook_buffered_events.set(count);
+        if count > self.orderbook_buffered_events_peak.get() {
+            self.orderbook_buffered_events_peak.set(count);
+        }
+    }
+
+    pub fn orderbook_filtered(&self, unfiltered_orders: usize, filtered_orders: usize) {
+        self.orderbook_filtered_orders
+            .with_label_values(&["unfiltered"])
+            .set(unfiltered_orders.try_into().unwrap_or(std::i64::MAX));
+        self.orderbook_filtered_orders
+            .with_label_values(&["filtered"])
+            .set(filtered_orders.try_into().unwrap_or(std::i64::MAX));
+    }
+
+    pub fn market_allowlist_configured(&self, markets: usize) {
+        self.market_allowlist_markets
+            .set(markets.try_into().unwrap_or(std::i64::MAX));
+    }
+
+    pub fn gas_estimate_outcome(
+        &self,
+        estimator: &str,
+        time_limit: Duration,
+        mined_after: Option<Duration>,
+    ) {
+        let outcome = match mined_after {
+            Some(mined_after) => {
+                self.gas_estimate_inclusion_ratio
+                    .with_label_values(&[estimator])
+                    .observe(mined_after.as_secs_f64() / time_limit.as_secs_f64());
+                if mined_after <= time_limit {
+                    "in_time"
+                } else {
+                    "late"
+                }
+            }
+            None => "not_mined",
+        };
+        self.gas_estimate_outcomes
+            .with_label_values(&[estimator, outcome])
+            .inc();
+    }
+
+    pub fn circuit_breaker_changed(&self, open: bool) {
+        self.circuit_breaker_open.set(open as i64);
+    }
+
+    pub fn solution_submission_account_selected(&self, account: Address) {
+        self.submission_accounts
+            .with_label_values(&[&format!("{:?}", account)])
+            .inc();
+    }
 }
 
 fn time_elapsed_since_batch_start(batch: u32) -> i64 {
     let now = Utc::now().timestamp();
-    // A new batch is created every 5 minutes and becomes solvable one batch later
-    let batch_start = (batch as i64 + 1) * 300;
-    now - batch_start
+    // A batch becomes solvable once the next batch starts collecting orders
+    let batch_start = BatchId::from(batch).next().as_timestamp();
+    now - batch_start as i64
 }
 
 fn tokens_from_orders(orders: &[Order]) -> i64 {
@@ -1,11 +1,15 @@
 pub mod account_state;
+pub mod amount;
 pub mod batch_id;
 pub mod order;
 pub mod solution;
+pub mod submit_solution;
 pub mod tokens;
 
 pub use self::account_state::AccountState;
+pub use self::amount::{Amount, AmountError};
 pub use self::batch_id::BatchId;
 pub use self::order::Order;
 pub use self::solution::{ExecutedOrder, Solution};
+pub use self::submit_solution::SubmitSolutionCall;
 pub use self::tokens::{TokenId, TokenInfo};
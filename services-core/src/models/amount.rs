@@ -0,0 +1,127 @@
+use ethcontract::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An error in checked amount arithmetic.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum AmountError {
+    #[error("amount overflow")]
+    Overflow,
+    #[error("amount underflow")]
+    Underflow,
+    #[error("amount division by zero")]
+    DivisionByZero,
+}
+
+/// A token amount as used by orders and trades in the smart contract.
+///
+/// All arithmetic is checked and products are computed in U256 before being
+/// converted back, so that results that do not fit into an amount are reported
+/// as errors instead of wrapping or saturating.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(transparent)]
+pub struct Amount(pub u128);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(std::u128::MAX);
+
+    /// Converts a U256 into an amount, failing if it does not fit.
+    pub fn from_u256(value: U256) -> Result<Self, AmountError> {
+        if value > U256::from(std::u128::MAX) {
+            return Err(AmountError::Overflow);
+        }
+        Ok(Amount(value.low_u128()))
+    }
+
+    pub fn as_u256(self) -> U256 {
+        U256::from(self.0)
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Self, AmountError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Result<Self, AmountError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Underflow)
+    }
+
+    /// Computes `self * numerator / denominator` rounding down.
+    pub fn mul_div(self, numerator: u128, denominator: u128) -> Result<Self, AmountError> {
+        if denominator == 0 {
+            return Err(AmountError::DivisionByZero);
+        }
+        Self::from_u256(self.as_u256() * U256::from(numerator) / U256::from(denominator))
+    }
+
+    /// Computes `self * numerator / denominator` rounding up.
+    pub fn mul_div_ceil(self, numerator: u128, denominator: u128) -> Result<Self, AmountError> {
+        if denominator == 0 {
+            return Err(AmountError::DivisionByZero);
+        }
+        let denominator = U256::from(denominator);
+        // The product of two u128 is at most 2^256 - 2^129 + 1, so adding a
+        // u128 cannot overflow.
+        Self::from_u256(
+            (self.as_u256() * U256::from(numerator) + denominator - U256::one()) / denominator,
+        )
+    }
+}
+
+impl From<u128> for Amount {
+    fn from(value: u128) -> Self {
+        Amount(value)
+    }
+}
+
+impl From<Amount> for u128 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_arithmetic_reports_errors() {
+        assert_eq!(Amount(1).checked_add(Amount(2)), Ok(Amount(3)));
+        assert_eq!(
+            Amount::MAX.checked_add(Amount(1)),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(Amount(3).checked_sub(Amount(2)), Ok(Amount(1)));
+        assert_eq!(
+            Amount(2).checked_sub(Amount(3)),
+            Err(AmountError::Underflow)
+        );
+    }
+
+    #[test]
+    fn mul_div_uses_wide_intermediate_results() {
+        assert_eq!(Amount::MAX.mul_div(3, 3), Ok(Amount::MAX));
+        assert_eq!(
+            Amount::MAX.mul_div_ceil(std::u128::MAX, std::u128::MAX),
+            Ok(Amount::MAX)
+        );
+        assert_eq!(Amount(10).mul_div(1, 3), Ok(Amount(3)));
+        assert_eq!(Amount(10).mul_div_ceil(1, 3), Ok(Amount(4)));
+        assert_eq!(Amount::MAX.mul_div(2, 1), Err(AmountError::Overflow));
+        assert_eq!(Amount(1).mul_div(1, 0), Err(AmountError::DivisionByZero));
+    }
+}
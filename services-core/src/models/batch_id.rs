@@ -16,7 +16,7 @@ pub struct BatchId(pub u64);
 
 impl BatchId {
     pub fn from_timestamp(timestamp: u64) -> Self {
-        Self(timestamp / BATCH_DURATION.as_secs())
+        BatchTiming::default().batch_at_timestamp(timestamp)
     }
 
     /// Creates a new batch ID for the current point in time.
@@ -29,38 +29,130 @@ impl BatchId {
     }
 
     pub fn current(now: SystemTime) -> Result<Self, SystemTimeError> {
-        Ok(Self::from_timestamp(now.as_timestamp()?))
+        BatchTiming::default().current_batch(now)
     }
 
     pub fn currently_being_solved(now: SystemTime) -> Result<Self, SystemTimeError> {
-        Self::current(now).map(|batch_id| batch_id.prev())
+        BatchTiming::default().batch_being_solved(now)
     }
 
     pub fn as_timestamp(self) -> u64 {
-        self.0 * BATCH_DURATION.as_secs()
+        BatchTiming::default().timestamp(self)
     }
 
     pub fn order_collection_start_time(self) -> SystemTime {
-        SystemTime::from_timestamp(self.as_timestamp())
+        BatchTiming::default().order_collection_start_time(self)
     }
 
     pub fn solve_start_time(self) -> SystemTime {
-        self.order_collection_start_time() + BATCH_DURATION
+        BatchTiming::default().solve_start_time(self)
     }
 
     pub fn solve_end_time(self) -> SystemTime {
-        self.solve_start_time() + SOLVING_WINDOW
+        BatchTiming::default().solve_end_time(self)
+    }
+
+    /// The time that passed since the current batch started collecting orders.
+    pub fn time_into_batch(now: SystemTime) -> Result<Duration, SystemTimeError> {
+        BatchTiming::default().time_into_batch(now)
+    }
+
+    /// The time until the next batch starts collecting orders.
+    pub fn time_until_next_batch(now: SystemTime) -> Result<Duration, SystemTimeError> {
+        BatchTiming::default().time_until_next_batch(now)
     }
 
     pub fn next(self) -> BatchId {
         self.0.checked_add(1).map(BatchId).unwrap()
     }
 
-    fn prev(self) -> BatchId {
+    pub fn prev(self) -> BatchId {
         self.0.checked_sub(1).map(BatchId).unwrap()
     }
 }
 
+/// The timing of the batches of an exchange deployment. The default timing is
+/// the one of the production deployment, test deployments can use shorter
+/// batches.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BatchTiming {
+    /// The total time in a batch.
+    pub batch_duration: Duration,
+    /// The time in a batch where a solution may be submitted.
+    pub solving_window: Duration,
+}
+
+impl BatchTiming {
+    /// Creates the timing for batches of the specified duration. The solving
+    /// window takes up the same share of the batch as on the production
+    /// deployment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch duration is shorter than a second.
+    pub fn with_batch_duration(batch_duration: Duration) -> Self {
+        assert!(
+            batch_duration.as_secs() > 0,
+            "batch duration must be at least one second",
+        );
+        Self {
+            batch_duration,
+            solving_window: batch_duration * SOLVING_WINDOW.as_secs() as u32
+                / BATCH_DURATION.as_secs() as u32,
+        }
+    }
+
+    pub fn batch_at_timestamp(&self, timestamp: u64) -> BatchId {
+        BatchId(timestamp / self.batch_duration.as_secs())
+    }
+
+    pub fn current_batch(&self, now: SystemTime) -> Result<BatchId, SystemTimeError> {
+        Ok(self.batch_at_timestamp(now.as_timestamp()?))
+    }
+
+    pub fn batch_being_solved(&self, now: SystemTime) -> Result<BatchId, SystemTimeError> {
+        self.current_batch(now).map(BatchId::prev)
+    }
+
+    pub fn timestamp(&self, batch_id: BatchId) -> u64 {
+        batch_id.0 * self.batch_duration.as_secs()
+    }
+
+    pub fn order_collection_start_time(&self, batch_id: BatchId) -> SystemTime {
+        SystemTime::from_timestamp(self.timestamp(batch_id))
+    }
+
+    pub fn solve_start_time(&self, batch_id: BatchId) -> SystemTime {
+        self.order_collection_start_time(batch_id) + self.batch_duration
+    }
+
+    pub fn solve_end_time(&self, batch_id: BatchId) -> SystemTime {
+        self.solve_start_time(batch_id) + self.solving_window
+    }
+
+    /// The time that passed since the current batch started collecting orders.
+    pub fn time_into_batch(&self, now: SystemTime) -> Result<Duration, SystemTimeError> {
+        let elapsed = now.duration_since(SystemTime::UNIX_EPOCH)?;
+        let batch_start = Duration::from_secs(self.timestamp(self.current_batch(now)?));
+        Ok(elapsed - batch_start)
+    }
+
+    /// The time until the next batch starts collecting orders, which is also
+    /// when the current batch can start being solved.
+    pub fn time_until_next_batch(&self, now: SystemTime) -> Result<Duration, SystemTimeError> {
+        Ok(self.batch_duration - self.time_into_batch(now)?)
+    }
+}
+
+impl Default for BatchTiming {
+    fn default() -> Self {
+        Self {
+            batch_duration: BATCH_DURATION,
+            solving_window: SOLVING_WINDOW,
+        }
+    }
+}
+
 impl From<u32> for BatchId {
     fn from(value: u32) -> Self {
         BatchId(value as _)
@@ -168,4 +260,69 @@ mod tests {
             batch_id.next().order_collection_start_time()
         );
     }
+
+    #[test]
+    fn time_into_batch_and_until_next_batch() {
+        let batch_start = SystemTime::UNIX_EPOCH + Duration::from_secs(300);
+        assert_eq!(
+            BatchId::time_into_batch(batch_start).unwrap(),
+            Duration::from_secs(0)
+        );
+        assert_eq!(
+            BatchId::time_until_next_batch(batch_start).unwrap(),
+            Duration::from_secs(300)
+        );
+
+        let now = batch_start + Duration::from_millis(299_500);
+        assert_eq!(
+            BatchId::time_into_batch(now).unwrap(),
+            Duration::from_millis(299_500)
+        );
+        assert_eq!(
+            BatchId::time_until_next_batch(now).unwrap(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            now + BatchId::time_until_next_batch(now).unwrap(),
+            BatchId(1).solve_start_time()
+        );
+
+        let timing = BatchTiming::with_batch_duration(Duration::from_secs(60));
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(125);
+        assert_eq!(timing.time_into_batch(now).unwrap(), Duration::from_secs(5));
+        assert_eq!(
+            timing.time_until_next_batch(now).unwrap(),
+            Duration::from_secs(55)
+        );
+    }
+
+    #[test]
+    fn batch_timing_with_shorter_batches() {
+        let timing = BatchTiming::with_batch_duration(Duration::from_secs(60));
+        assert_eq!(timing.solving_window, Duration::from_secs(48));
+        assert_eq!(
+            BatchTiming::with_batch_duration(BATCH_DURATION),
+            BatchTiming::default()
+        );
+
+        let start_time = SystemTime::UNIX_EPOCH;
+        let batch_id = timing
+            .current_batch(start_time + Duration::from_secs(119))
+            .unwrap();
+        assert_eq!(batch_id, BatchId(1));
+        assert_eq!(
+            timing.solve_start_time(batch_id),
+            start_time + Duration::from_secs(120)
+        );
+        assert_eq!(
+            timing.solve_end_time(batch_id),
+            start_time + Duration::from_secs(168)
+        );
+        assert_eq!(
+            timing
+                .batch_being_solved(start_time + Duration::from_secs(120))
+                .unwrap(),
+            BatchId(1)
+        );
+    }
 }
@@ -1,5 +1,4 @@
-use super::AccountState;
-use crate::util::CeiledDiv;
+use super::{AccountState, Amount};
 use ethcontract::{Address, U256};
 use pricegraph::{Element, PriceFraction, TokenPair, Validity};
 
@@ -81,9 +80,11 @@ fn compute_remaining_buy_sell_amounts(
     // 0 on sellAmount (remaining <= denominator) is nonsense, but solver can handle it.
     // 0 on buyAmount (numerator) is a Market Sell Order.
     let buy_amount = if denominator > 0 {
-        // up-casting to handle overflow
-        let top = U256::from(remaining) * U256::from(numerator);
-        (top).ceiled_div(U256::from(denominator)).as_u128()
+        // Cannot overflow because the remaining amount is at most the denominator.
+        Amount(remaining)
+            .mul_div_ceil(numerator, denominator)
+            .expect("remaining buy amount exceeds numerator")
+            .into()
     } else {
         0
     };
@@ -1,8 +1,17 @@
-use crate::bigint_u256;
 use ethcontract::{Address, U256};
-use num::{BigInt, Zero as _};
 use std::collections::HashMap;
 
+/// The gas used by `submitSolution` independently of the size of the solution
+/// for the transaction itself and updating the objective value and fee token.
+pub const SOLUTION_BASE_GAS: u64 = 100_000;
+
+/// The approximate amount of gas used in a solution per trade. In practice the value depends on how
+/// much gas is used in the reversion of the previous solution.
+pub const GAS_PER_TRADE: u64 = 120_000;
+
+/// The gas used for storing the price of a token that is part of the solution.
+pub const GAS_PER_TOKEN_PRICE: u64 = 20_000;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExecutedOrder {
     pub account_id: Address,
@@ -22,6 +31,7 @@ pub struct Solution {
 pub struct EconomicViabilityInfo {
     pub num_executed_orders: usize,
     pub earned_fee: U256,
+    pub estimated_gas: u64,
 }
 
 impl Solution {
@@ -43,20 +53,38 @@ impl Solution {
         EconomicViabilityInfo {
             num_executed_orders: self.executed_orders.len(),
             earned_fee: self.earned_fee(),
+            estimated_gas: self.estimated_gas(),
         }
     }
 
+    /// Estimates the gas used for submitting the solution based on the number
+    /// of trades and token prices.
+    pub fn estimated_gas(&self) -> u64 {
+        SOLUTION_BASE_GAS
+            + GAS_PER_TRADE * self.executed_orders.len() as u64
+            + GAS_PER_TOKEN_PRICE * self.prices.len() as u64
+    }
+
+    /// Returns the fee earned by the solution, which is zero for solutions
+    /// that buy more than they sell.
     pub fn earned_fee(&self) -> U256 {
         // We expect that only the fee token has an imbalance so by calculating the total imbalance
         // this must be equal to the fee token imbalance. This allows us to calculate the burnt fees
         // without accessing the orderbook solely based on the solution.
-        let mut token_imbalance = BigInt::zero();
-        for executed_order in self.executed_orders.iter() {
-            token_imbalance += executed_order.sell_amount;
-            token_imbalance -= executed_order.buy_amount;
+        // Sums of u128 amounts cannot overflow a U256 for any realistic number of orders.
+        let (total_sell, total_buy) = self.executed_orders.iter().fold(
+            (U256::zero(), U256::zero()),
+            |(total_sell, total_buy), executed_order| {
+                (
+                    total_sell + U256::from(executed_order.sell_amount),
+                    total_buy + U256::from(executed_order.buy_amount),
+                )
+            },
+        );
+        match total_sell.checked_sub(total_buy) {
+            Some(token_imbalance) => token_imbalance / 2,
+            None => U256::zero(),
         }
-        token_imbalance /= 2;
-        bigint_u256::bigint_to_u256_saturating(&token_imbalance)
     }
 }
 
@@ -114,6 +142,15 @@ pub mod tests {
         assert!(!Solution::trivial().is_non_trivial());
     }
 
+    #[test]
+    fn estimated_gas_grows_with_trades_and_prices() {
+        assert_eq!(Solution::trivial().estimated_gas(), SOLUTION_BASE_GAS);
+        assert_eq!(
+            generic_non_trivial_solution().estimated_gas(),
+            SOLUTION_BASE_GAS + 3 * GAS_PER_TRADE + 2 * GAS_PER_TOKEN_PRICE
+        );
+    }
+
     #[test]
     fn test_max_token() {
         assert_eq!(generic_non_trivial_solution().max_token().unwrap(), 2);
@@ -147,4 +184,28 @@ pub mod tests {
         };
         assert_eq!(solution.earned_fee(), U256::from(2));
     }
+
+    #[test]
+    fn earned_fee_does_not_overflow_amounts() {
+        let executed_order = |sell_amount, buy_amount| ExecutedOrder {
+            account_id: Address::zero(),
+            order_id: 0,
+            sell_amount,
+            buy_amount,
+        };
+        let solution = Solution {
+            prices: HashMap::new(),
+            executed_orders: vec![
+                executed_order(std::u128::MAX, 0),
+                executed_order(std::u128::MAX, 0),
+            ],
+        };
+        assert_eq!(solution.earned_fee(), U256::from(std::u128::MAX));
+
+        let solution = Solution {
+            prices: HashMap::new(),
+            executed_orders: vec![executed_order(1, 2)],
+        };
+        assert_eq!(solution.earned_fee(), U256::zero());
+    }
 }
@@ -0,0 +1,314 @@
+//! Module implementing the encoding of solutions as calls of `submitSolution`
+//! of the exchange contract without the generated contract bindings, so that
+//! external tools like simulations or multisig proposals can construct and
+//! audit solution submissions.
+
+use super::{ExecutedOrder, Solution};
+use anyhow::{anyhow, ensure, Result};
+use ethcontract::{
+    common::abi::{decode, encode, short_signature, ParamType, Token},
+    Address, U256,
+};
+use std::collections::HashMap;
+
+/// The arguments of
+/// `submitSolution(uint32 batchId, uint256 claimedObjectiveValue, address[] owners, uint16[] orderIds, uint128[] buyVolumes, uint128[] prices, uint16[] tokenIdsForPrice)`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SubmitSolutionCall {
+    pub batch_id: u32,
+    pub claimed_objective_value: U256,
+    pub owners: Vec<Address>,
+    pub order_ids: Vec<u16>,
+    pub buy_volumes: Vec<u128>,
+    pub prices: Vec<u128>,
+    pub token_ids_for_price: Vec<u16>,
+}
+
+impl Solution {
+    /// The calldata submitting the solution for the batch with the claimed
+    /// objective value, exactly as sent by the driver.
+    pub fn to_submit_calldata(&self, batch_id: u32, claimed_objective_value: U256) -> Vec<u8> {
+        SubmitSolutionCall::new(batch_id, claimed_objective_value, self).encode()
+    }
+}
+
+impl SubmitSolutionCall {
+    /// The call submitting the solution in the representation of the contract,
+    /// which only contains touched orders and non-zero prices of tokens other
+    /// than the fee token.
+    pub fn new(batch_id: u32, claimed_objective_value: U256, solution: &Solution) -> Self {
+        let (prices, token_ids_for_price) = encode_prices_for_contract(&solution.prices);
+        let (owners, order_ids, buy_volumes) =
+            encode_execution_for_contract(&solution.executed_orders);
+        SubmitSolutionCall {
+            batch_id,
+            claimed_objective_value,
+            owners,
+            order_ids,
+            buy_volumes,
+            prices,
+            token_ids_for_price,
+        }
+    }
+
+    /// The ABI encoded calldata of the call.
+    pub fn encode(&self) -> Vec<u8> {
+        let uints = |values: &[u128]| {
+            Token::Array(
+                values
+                    .iter()
+                    .map(|value| Token::Uint(U256::from(*value)))
+                    .collect(),
+            )
+        };
+        let token_ids =
+            |ids: &[u16]| Token::Array(ids.iter().map(|id| Token::Uint(U256::from(*id))).collect());
+        let arguments = encode(&[
+            Token::Uint(self.batch_id.into()),
+            Token::Uint(self.claimed_objective_value),
+            Token::Array(self.owners.iter().copied().map(Token::Address).collect()),
+            token_ids(&self.order_ids),
+            uints(&self.buy_volumes),
+            uints(&self.prices),
+            token_ids(&self.token_ids_for_price),
+        ]);
+        [&selector()[..], &arguments].concat()
+    }
+
+    /// Decodes the calldata of a `submitSolution` call.
+    pub fn decode(calldata: &[u8]) -> Result<Self> {
+        ensure!(
+            calldata.len() >= 4 && calldata[..4] == selector()[..],
+            "calldata is not a call of submitSolution"
+        );
+        let mut arguments = decode(&parameters(), &calldata[4..])?.into_iter();
+        let mut next = || {
+            arguments
+                .next()
+                .ok_or_else(|| anyhow!("missing submitSolution argument"))
+        };
+        Ok(SubmitSolutionCall {
+            batch_id: uint(next()?, 32)?.low_u32(),
+            claimed_objective_value: uint(next()?, 256)?,
+            owners: array(next()?, address)?,
+            order_ids: array(next()?, |token| Ok(uint(token, 16)?.low_u32() as u16))?,
+            buy_volumes: array(next()?, |token| Ok(uint(token, 128)?.low_u128()))?,
+            prices: array(next()?, |token| Ok(uint(token, 128)?.low_u128()))?,
+            token_ids_for_price: array(next()?, |token| Ok(uint(token, 16)?.low_u32() as u16))?,
+        })
+    }
+}
+
+fn parameters() -> [ParamType; 7] {
+    [
+        ParamType::Uint(32),
+        ParamType::Uint(256),
+        ParamType::Array(Box::new(ParamType::Address)),
+        ParamType::Array(Box::new(ParamType::Uint(16))),
+        ParamType::Array(Box::new(ParamType::Uint(128))),
+        ParamType::Array(Box::new(ParamType::Uint(128))),
+        ParamType::Array(Box::new(ParamType::Uint(16))),
+    ]
+}
+
+fn selector() -> [u8; 4] {
+    short_signature("submitSolution", &parameters())
+}
+
+fn uint(token: Token, bits: usize) -> Result<U256> {
+    match token {
+        Token::Uint(value) if value.bits() <= bits => Ok(value),
+        token => Err(anyhow!("{:?} is not a uint{}", token, bits)),
+    }
+}
+
+fn address(token: Token) -> Result<Address> {
+    match token {
+        Token::Address(address) => Ok(address),
+        token => Err(anyhow!("{:?} is not an address", token)),
+    }
+}
+
+fn array<T>(token: Token, element: impl Fn(Token) -> Result<T>) -> Result<Vec<T>> {
+    match token {
+        Token::Array(tokens) => tokens.into_iter().map(element).collect(),
+        token => Err(anyhow!("{:?} is not an array", token)),
+    }
+}
+
+fn encode_prices_for_contract(price_map: &HashMap<u16, u128>) -> (Vec<u128>, Vec<u16>) {
+    // Representing the solution's price vector as:
+    // sorted_touched_token_ids, non_zero_prices (excluding price at token with id 0)
+    let mut token_ids: Vec<u16> = price_map
+        .keys()
+        .copied()
+        .filter(|t| *t > 0 && price_map[t] > 0)
+        .collect();
+    token_ids.sort_unstable();
+    let prices = token_ids
+        .iter()
+        .map(|token_id| price_map[token_id])
+        .collect();
+    (prices, token_ids)
+}
+
+fn encode_execution_for_contract(
+    executed_orders: &[ExecutedOrder],
+) -> (Vec<Address>, Vec<u16>, Vec<u128>) {
+    let mut owners = vec![];
+    let mut order_ids = vec![];
+    let mut volumes = vec![];
+    for order in executed_orders {
+        if order.buy_amount > 0 {
+            // order was touched!
+            // Note that above condition is only holds for sell orders.
+            owners.push(order.account_id);
+            order_ids.push(order.order_id);
+            volumes.push(order.buy_amount);
+        }
+    }
+    (owners, order_ids, volumes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_util::map_from_slice;
+    use ::contracts::BatchExchange;
+
+    fn solution() -> Solution {
+        Solution {
+            prices: map_from_slice(&[(0, 1_000_000), (3, 2_000_000), (1, 500)]),
+            executed_orders: vec![
+                ExecutedOrder {
+                    account_id: Address::from_low_u64_be(1),
+                    order_id: 4,
+                    sell_amount: 10,
+                    buy_amount: 20,
+                },
+                ExecutedOrder {
+                    account_id: Address::from_low_u64_be(2),
+                    order_id: 0,
+                    sell_amount: 0,
+                    buy_amount: 0,
+                },
+                ExecutedOrder {
+                    account_id: Address::from_low_u64_be(3),
+                    order_id: 1,
+                    sell_amount: 20,
+                    buy_amount: u128::max_value(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn encodes_calldata_like_contract_abi() {
+        let calldata = solution().to_submit_calldata(42, 1337.into());
+
+        let uints = |values: &[u128]| {
+            Token::Array(
+                values
+                    .iter()
+                    .map(|value| Token::Uint(U256::from(*value)))
+                    .collect(),
+            )
+        };
+        let expected = BatchExchange::raw_contract()
+            .abi
+            .function("submitSolution")
+            .unwrap()
+            .encode_input(&[
+                Token::Uint(42.into()),
+                Token::Uint(1337.into()),
+                Token::Array(vec![
+                    Token::Address(Address::from_low_u64_be(1)),
+                    Token::Address(Address::from_low_u64_be(3)),
+                ]),
+                uints(&[4, 1]),
+                uints(&[20, u128::max_value()]),
+                uints(&[500, 2_000_000]),
+                uints(&[1, 3]),
+            ])
+            .unwrap();
+        assert_eq!(calldata, expected);
+    }
+
+    #[test]
+    fn decodes_encoded_calldata() {
+        let call = SubmitSolutionCall::new(42, 1337.into(), &solution());
+        assert_eq!(SubmitSolutionCall::decode(&call.encode()).unwrap(), call);
+    }
+
+    #[test]
+    fn rejects_invalid_calldata() {
+        let mut calldata = solution().to_submit_calldata(42, 1337.into());
+        assert!(SubmitSolutionCall::decode(&calldata[..3]).is_err());
+        assert!(SubmitSolutionCall::decode(&calldata[..100]).is_err());
+
+        // Make the first order id exceed 16 bits. It follows the 7 head words
+        // and the 3 words of the owners and the length of the order ids.
+        let order_id = 4 + 32 * (7 + 3 + 1);
+        calldata[order_id + 29] = 1;
+        assert!(SubmitSolutionCall::decode(&calldata).is_err());
+
+        calldata[0] ^= 1;
+        assert!(SubmitSolutionCall::decode(&calldata).is_err());
+    }
+
+    #[test]
+    fn generic_encode_execution_test() {
+        let address_1 = Address::from_low_u64_be(1);
+        let address_2 = Address::from_low_u64_be(2);
+
+        let order_1 = ExecutedOrder {
+            order_id: 0,
+            account_id: address_1,
+            sell_amount: 1,
+            buy_amount: 1,
+        };
+        let order_2 = ExecutedOrder {
+            order_id: 1,
+            account_id: address_2,
+            sell_amount: 0,
+            buy_amount: 0,
+        };
+
+        let expected_owners = vec![address_1];
+        let expected_order_ids = vec![0];
+        let expected_volumes = vec![1];
+
+        let expected_results = (expected_owners, expected_order_ids, expected_volumes);
+
+        assert_eq!(
+            encode_execution_for_contract(&[order_1, order_2]),
+            expected_results
+        );
+    }
+
+    #[test]
+    fn generic_price_encoding() {
+        let price_map = map_from_slice(&[(0, u128::max_value()), (1, 0), (2, 1), (3, 2)]);
+        // Only contain non fee-tokens and non zero prices
+        let expected_prices = vec![1, 2];
+        let expected_token_ids = vec![2, 3];
+
+        assert_eq!(
+            encode_prices_for_contract(&price_map),
+            (expected_prices, expected_token_ids)
+        );
+    }
+
+    #[test]
+    fn unsorted_price_encoding() {
+        let unordered_price_map = map_from_slice(&[(4, 2), (1, 3), (5, 0), (0, 2), (3, 1)]);
+
+        // Only contain non fee-token and non zero prices
+        let expected_prices = vec![3, 1, 2];
+        let expected_token_ids = vec![1, 3, 4];
+        assert_eq!(
+            encode_prices_for_contract(&unordered_price_map),
+            (expected_prices, expected_token_ids)
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod filter_expression;
 mod filtered_orderbook;
 pub mod streamed;
 mod util;
@@ -6,9 +7,13 @@ pub use self::{
     filtered_orderbook::{FilteredOrderbookReader, OrderbookFilter},
     streamed::Orderbook as EventBasedOrderbook,
 };
-use crate::models::{AccountState, Order};
+use crate::{
+    history::Settlement,
+    models::{AccountState, Order, Solution},
+};
 use anyhow::Result;
 use ethcontract::BlockNumber;
+use std::sync::Arc;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait::async_trait]
@@ -37,6 +42,55 @@ pub trait StableXOrderBookReading: Send + Sync {
     async fn initialize(&self) -> Result<()> {
         Ok(())
     }
+
+    /// Checks that a solution for the batch can be applied to the orderbook
+    /// without overdrawing balances or violating order limits. Orderbooks
+    /// that cannot replay solutions accept all of them.
+    async fn verify_solution(&self, _batch_id_to_solve: u32, _solution: &Solution) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the final settlement of a past batch, or `None` if no solution
+    /// was submitted for it or the orderbook does not keep settlements.
+    async fn settlement_for_batch(&self, _batch_id: u32) -> Result<Option<Settlement>> {
+        Ok(None)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> StableXOrderBookReading for Arc<T>
+where
+    T: StableXOrderBookReading + ?Sized,
+{
+    async fn get_auction_data_for_batch(
+        &self,
+        batch_id_to_solve: u32,
+    ) -> Result<(AccountState, Vec<Order>)> {
+        self.as_ref()
+            .get_auction_data_for_batch(batch_id_to_solve)
+            .await
+    }
+
+    async fn get_auction_data_for_block(
+        &self,
+        block_number: BlockNumber,
+    ) -> Result<(AccountState, Vec<Order>)> {
+        self.as_ref().get_auction_data_for_block(block_number).await
+    }
+
+    async fn initialize(&self) -> Result<()> {
+        self.as_ref().initialize().await
+    }
+
+    async fn verify_solution(&self, batch_id_to_solve: u32, solution: &Solution) -> Result<()> {
+        self.as_ref()
+            .verify_solution(batch_id_to_solve, solution)
+            .await
+    }
+
+    async fn settlement_for_batch(&self, batch_id: u32) -> Result<Option<Settlement>> {
+        self.as_ref().settlement_for_batch(batch_id).await
+    }
 }
 
 /// Always suceeds with empty orderbook.
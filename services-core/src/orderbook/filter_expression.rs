@@ -0,0 +1,306 @@
+//! A small expression language for excluding orders from the orderbook.
+//!
+//! Each expression is a single condition that orders have to satisfy in order
+//! to be kept:
+//! - `sell_amount >= 1000000` compares the remaining sell amount of the order
+//! - `sell_value >= 1000000000000000000` compares the remaining sell amount of
+//!   the order valued in OWL atoms, using the prices of the infallible price
+//!   source of the filtered orderbook
+//! - `balance >= 1000000` compares the balance of the order's sell token
+//! - `valid_for >= 10` compares the number of batches after the batch being
+//!   solved for which the order stays valid
+//! - `pair in [1/2, 1/3]` keeps orders trading one of the specified token pairs
+//!   in either direction
+//!
+//! Supported comparison operators are `<`, `<=`, `==`, `!=`, `>=` and `>`.
+
+use crate::{
+    models::{AccountState, Order, TokenId},
+    price_estimation::price_source::InfalliblePriceSource,
+};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use ethcontract::U256;
+use serde::Deserialize;
+use std::{cmp::Ordering, collections::HashSet, convert::TryFrom, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    SellAmount,
+    SellValue,
+    Balance,
+    ValidFor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+            Comparison::Greater => ordering == Ordering::Greater,
+        }
+    }
+}
+
+/// A single condition that orders need to satisfy to be kept in the orderbook.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum FilterExpression {
+    Compare(Field, Comparison, U256),
+    /// Unordered token pairs, stored with the smaller token id first.
+    PairIn(HashSet<(u16, u16)>),
+}
+
+impl FilterExpression {
+    /// Returns whether the order satisfies the expression. Conditions on the
+    /// validity of an order are only evaluated when the batch being solved is
+    /// known, and conditions on the value of an order only when prices are
+    /// available.
+    pub fn matches(
+        &self,
+        order: &Order,
+        state: &AccountState,
+        batch_id: Option<u32>,
+        prices: Option<&dyn InfalliblePriceSource>,
+    ) -> bool {
+        match self {
+            FilterExpression::Compare(field, comparison, value) => {
+                let actual = match field {
+                    Field::SellAmount => U256::from(order.remaining_sell_amount),
+                    Field::SellValue => match prices {
+                        Some(prices) => sell_value(order, prices),
+                        None => return true,
+                    },
+                    Field::Balance => state.read_balance(order.sell_token, order.account_id),
+                    Field::ValidFor => match batch_id {
+                        Some(batch_id) => order.valid_until.saturating_sub(batch_id).into(),
+                        None => return true,
+                    },
+                };
+                comparison.holds(actual.cmp(value))
+            }
+            FilterExpression::PairIn(pairs) => {
+                pairs.contains(&unordered_pair(order.buy_token, order.sell_token))
+            }
+        }
+    }
+
+    /// Returns whether the expression values orders and thus can only be
+    /// evaluated with prices.
+    pub fn requires_prices(&self) -> bool {
+        matches!(self, FilterExpression::Compare(Field::SellValue, _, _))
+    }
+}
+
+/// The remaining sell amount of an order in OWL atoms.
+fn sell_value(order: &Order, prices: &dyn InfalliblePriceSource) -> U256 {
+    let price = prices.price(TokenId(order.sell_token));
+    U256::from(order.remaining_sell_amount) * U256::from(price.get()) / U256::exp10(18)
+}
+
+fn unordered_pair(a: u16, b: u16) -> (u16, u16) {
+    (a.min(b), a.max(b))
+}
+
+impl FromStr for FilterExpression {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let mut parts = expression.split_whitespace();
+        let field = parts
+            .next()
+            .ok_or_else(|| anyhow!("empty filter expression"))?;
+        let operator = parts
+            .next()
+            .ok_or_else(|| anyhow!("missing operator in filter expression {:?}", expression))?;
+        let operand = parts.collect::<Vec<_>>().join(" ");
+
+        if field == "pair" {
+            if operator != "in" {
+                bail!("pair filters only support the 'in' operator");
+            }
+            return parse_pairs(&operand)
+                .map(FilterExpression::PairIn)
+                .with_context(|| format!("invalid pair list in {:?}", expression));
+        }
+
+        let field = match field {
+            "sell_amount" => Field::SellAmount,
+            "sell_value" => Field::SellValue,
+            "balance" => Field::Balance,
+            "valid_for" => Field::ValidFor,
+            _ => bail!("unknown field {:?} in filter expression", field),
+        };
+        let comparison = match operator {
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            ">" => Comparison::Greater,
+            _ => bail!("unknown operator {:?} in filter expression", operator),
+        };
+        let value = U256::from_dec_str(&operand)
+            .map_err(|err| anyhow!("invalid value {:?}: {:?}", operand, err))?;
+        Ok(FilterExpression::Compare(field, comparison, value))
+    }
+}
+
+impl TryFrom<String> for FilterExpression {
+    type Error = Error;
+
+    fn try_from(expression: String) -> Result<Self> {
+        expression.parse()
+    }
+}
+
+fn parse_pairs(list: &str) -> Result<HashSet<(u16, u16)>> {
+    let list = list
+        .trim()
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+        .ok_or_else(|| anyhow!("pair list must be enclosed in brackets"))?;
+    list.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let mut tokens = pair.split('/').map(|token| token.trim().parse::<u16>());
+            match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some(a), Some(b), None) => Ok(unordered_pair(a?, b?)),
+                _ => bail!("pair {:?} is not of the form <token>/<token>", pair.trim()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::order::test_util::create_order_for_test,
+        price_estimation::price_source::MockInfalliblePriceSource,
+    };
+    use ethcontract::Address;
+
+    #[test]
+    fn parses_expressions() {
+        assert_eq!(
+            "sell_amount >= 1000".parse::<FilterExpression>().unwrap(),
+            FilterExpression::Compare(Field::SellAmount, Comparison::GreaterOrEqual, 1000.into())
+        );
+        assert_eq!(
+            "valid_for > 10".parse::<FilterExpression>().unwrap(),
+            FilterExpression::Compare(Field::ValidFor, Comparison::Greater, 10.into())
+        );
+        assert_eq!(
+            "pair in [2/1, 3 / 4]".parse::<FilterExpression>().unwrap(),
+            FilterExpression::PairIn([(1, 2), (3, 4)].iter().copied().collect())
+        );
+        assert_eq!(
+            "sell_value >= 10".parse::<FilterExpression>().unwrap(),
+            FilterExpression::Compare(Field::SellValue, Comparison::GreaterOrEqual, 10.into())
+        );
+        assert_eq!(
+            serde_json::from_str::<FilterExpression>(r#""balance < 5""#).unwrap(),
+            FilterExpression::Compare(Field::Balance, Comparison::Less, 5.into())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in &[
+            "",
+            "sell_amount",
+            "sell_amount => 1",
+            "fee >= 1",
+            "balance >= -1",
+            "pair == [1/2]",
+            "pair in 1/2",
+            "pair in [1/2/3]",
+        ] {
+            assert!(
+                expression.parse::<FilterExpression>().is_err(),
+                "{:?} should not parse",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let mut order = create_order_for_test();
+        order.account_id = Address::from_low_u64_be(1);
+        order.buy_token = 1;
+        order.sell_token = 2;
+        order.remaining_sell_amount = 100;
+        order.valid_until = 15;
+        let mut state = AccountState::default();
+        state.increase_balance(order.account_id, 2, 50);
+
+        let matches = |expression: &str, batch_id| {
+            expression
+                .parse::<FilterExpression>()
+                .unwrap()
+                .matches(&order, &state, batch_id, None)
+        };
+        assert!(matches("sell_amount >= 100", None));
+        assert!(!matches("sell_amount > 100", None));
+        assert!(matches("balance == 50", None));
+        assert!(!matches("balance >= 51", None));
+        assert!(matches("valid_for >= 5", Some(10)));
+        assert!(!matches("valid_for >= 6", Some(10)));
+        assert!(matches("valid_for >= 6", None));
+        assert!(matches("pair in [2/1]", None));
+        assert!(!matches("pair in [1/3, 2/3]", None));
+    }
+
+    #[test]
+    fn evaluates_sell_value_with_prices() {
+        let mut order = create_order_for_test();
+        order.sell_token = 2;
+        order.remaining_sell_amount = 3_000_000;
+        let state = AccountState::default();
+
+        // NOTE: A token with 6 decimals worth 2 OWL.
+        let mut prices = MockInfalliblePriceSource::new();
+        prices
+            .expect_price()
+            .withf(|token_id| *token_id == TokenId(2))
+            .returning(|_| nonzero!(2 * 10u128.pow(30)));
+
+        let matches = |expression: &str, prices: Option<&dyn InfalliblePriceSource>| {
+            expression
+                .parse::<FilterExpression>()
+                .unwrap()
+                .matches(&order, &state, None, prices)
+        };
+        assert!(matches("sell_value == 6000000000000000000", Some(&prices)));
+        assert!(!matches("sell_value > 6000000000000000000", Some(&prices)));
+        assert!(matches("sell_value > 6000000000000000000", None));
+    }
+
+    #[test]
+    fn only_sell_value_requires_prices() {
+        let requires_prices = |expression: &str| {
+            expression
+                .parse::<FilterExpression>()
+                .unwrap()
+                .requires_prices()
+        };
+        assert!(requires_prices("sell_value >= 10"));
+        assert!(!requires_prices("sell_amount >= 10"));
+        assert!(!requires_prices("valid_for >= 10"));
+        assert!(!requires_prices("pair in [1/2]"));
+    }
+}
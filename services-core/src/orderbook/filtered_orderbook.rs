@@ -1,6 +1,11 @@
-use super::*;
-
-use crate::models::{AccountState, Order};
+use super::{filter_expression::FilterExpression, *};
+
+use crate::{
+    history::Settlement,
+    metrics::StableXMetrics,
+    models::{AccountState, Order, Solution},
+    price_estimation::price_source::InfalliblePriceSource,
+};
 use anyhow::Error;
 use ethcontract::Address;
 use serde::Deserialize;
@@ -29,6 +34,11 @@ pub struct OrderbookFilter {
     /// User addresses mapped to which of their orders to filter
     #[serde(default)]
     users: HashMap<Address, UserOrderFilter>,
+
+    /// Expressions that all orders need to satisfy, for example to exclude
+    /// dust orders with `"sell_amount >= 1000000"`.
+    #[serde(default)]
+    orders: Vec<FilterExpression>,
 }
 
 impl OrderbookFilter {
@@ -39,8 +49,37 @@ impl OrderbookFilter {
         }
     }
 
-    /// Applies the filter for the specified auction state.
-    pub fn apply(&self, (state, orders): (AccountState, Vec<Order>)) -> (AccountState, Vec<Order>) {
+    /// The markets, that is unordered token pairs, orders are restricted to by
+    /// `pair in` expressions, or `None` if orders of all markets are kept.
+    pub fn allowed_markets(&self) -> Option<HashSet<(u16, u16)>> {
+        self.orders
+            .iter()
+            .filter_map(|expression| match expression {
+                FilterExpression::PairIn(pairs) => Some(pairs),
+                _ => None,
+            })
+            .fold(None, |allowed, pairs| match allowed {
+                None => Some(pairs.clone()),
+                Some(allowed) => Some(allowed.intersection(pairs).copied().collect()),
+            })
+    }
+
+    /// Returns whether the filter has expressions on the value of orders,
+    /// which are ignored unless the filtered orderbook has a price source.
+    pub fn requires_prices(&self) -> bool {
+        self.orders.iter().any(FilterExpression::requires_prices)
+    }
+
+    /// Applies the filter for the specified auction state. Expressions on the
+    /// validity of orders are only evaluated if the batch being solved is
+    /// specified, and expressions on the value of orders only if prices are
+    /// specified.
+    pub fn apply(
+        &self,
+        (state, orders): (AccountState, Vec<Order>),
+        batch_id: Option<u32>,
+        prices: Option<&dyn InfalliblePriceSource>,
+    ) -> (AccountState, Vec<Order>) {
         let token_filtered_orders: Vec<Order> = match &self.tokens {
             TokenFilter::Whitelist(token_list) => orders
                 .into_iter()
@@ -63,7 +102,14 @@ impl OrderbookFilter {
                 true
             }
         });
-        util::canonicalize_auction_data(state, user_filtered_orders)
+        let expression_filtered_orders: Vec<Order> = user_filtered_orders
+            .filter(|o| {
+                self.orders
+                    .iter()
+                    .all(|expression| expression.matches(o, &state, batch_id, prices))
+            })
+            .collect();
+        util::canonicalize_auction_data(state, expression_filtered_orders)
     }
 }
 
@@ -84,11 +130,52 @@ impl FromStr for OrderbookFilter {
 pub struct FilteredOrderbookReader {
     orderbook: Box<dyn StableXOrderBookReading>,
     filter: OrderbookFilter,
+    prices: Option<Arc<dyn InfalliblePriceSource>>,
+    metrics: Option<Arc<StableXMetrics>>,
 }
 
 impl FilteredOrderbookReader {
     pub fn new(orderbook: Box<dyn StableXOrderBookReading>, filter: OrderbookFilter) -> Self {
-        Self { orderbook, filter }
+        Self {
+            orderbook,
+            filter,
+            prices: None,
+            metrics: None,
+        }
+    }
+
+    /// Records the number of orders before and after filtering as well as the
+    /// number of markets the filter restricts orders to.
+    pub fn with_metrics(mut self, metrics: Arc<StableXMetrics>) -> Self {
+        metrics.market_allowlist_configured(
+            self.filter
+                .allowed_markets()
+                .map_or(0, |markets| markets.len()),
+        );
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Uses the prices of the specified source for filter expressions on the
+    /// value of orders. Without a price source these expressions are ignored.
+    pub fn with_price_source(mut self, prices: Arc<dyn InfalliblePriceSource>) -> Self {
+        self.prices = Some(prices);
+        self
+    }
+
+    fn apply_filter(
+        &self,
+        (state, orders): (AccountState, Vec<Order>),
+        batch_id: Option<u32>,
+    ) -> (AccountState, Vec<Order>) {
+        let unfiltered_orders = orders.len();
+        let auction_data = self
+            .filter
+            .apply((state, orders), batch_id, self.prices.as_deref());
+        if let Some(metrics) = &self.metrics {
+            metrics.orderbook_filtered(unfiltered_orders, auction_data.1.len());
+        }
+        auction_data
     }
 }
 
@@ -102,7 +189,7 @@ impl StableXOrderBookReading for FilteredOrderbookReader {
             .orderbook
             .get_auction_data_for_batch(batch_id_to_solve)
             .await?;
-        Ok(self.filter.apply(auction_data))
+        Ok(self.apply_filter(auction_data, Some(batch_id_to_solve)))
     }
 
     async fn get_auction_data_for_block(
@@ -110,18 +197,31 @@ impl StableXOrderBookReading for FilteredOrderbookReader {
         block: BlockNumber,
     ) -> Result<(AccountState, Vec<Order>)> {
         let auction_data = self.orderbook.get_auction_data_for_block(block).await?;
-        Ok(self.filter.apply(auction_data))
+        Ok(self.apply_filter(auction_data, None))
     }
 
     async fn initialize(&self) -> Result<()> {
         self.orderbook.initialize().await
     }
+
+    async fn verify_solution(&self, batch_id_to_solve: u32, solution: &Solution) -> Result<()> {
+        self.orderbook
+            .verify_solution(batch_id_to_solve, solution)
+            .await
+    }
+
+    async fn settlement_for_batch(&self, batch_id: u32) -> Result<Option<Settlement>> {
+        self.orderbook.settlement_for_batch(batch_id).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::order::test_util::create_order_for_test;
+    use crate::{
+        models::{order::test_util::create_order_for_test, TokenId},
+        price_estimation::price_source::MockInfalliblePriceSource,
+    };
     use futures::FutureExt as _;
     use mockall::predicate::eq;
     use std::str::FromStr;
@@ -150,6 +250,7 @@ mod tests {
             .iter()
             .cloned()
             .collect(),
+            orders: Vec::new(),
         };
         assert_eq!(
             blacklist_filter,
@@ -165,6 +266,7 @@ mod tests {
         let whitelist_filter = OrderbookFilter {
             tokens: TokenFilter::Whitelist([1, 2].iter().copied().collect()),
             users: HashMap::new(),
+            orders: Vec::new(),
         };
         assert_eq!(
             whitelist_filter,
@@ -223,6 +325,7 @@ mod tests {
             .iter()
             .cloned()
             .collect(),
+            orders: Vec::new(),
         };
 
         let reader = FilteredOrderbookReader::new(Box::new(inner), filter);
@@ -256,6 +359,7 @@ mod tests {
         let filter = OrderbookFilter {
             tokens: TokenFilter::Whitelist([2, 3].iter().copied().collect()),
             users: HashMap::new(),
+            orders: Vec::new(),
         };
 
         let reader = FilteredOrderbookReader::new(Box::new(inner), filter);
@@ -280,6 +384,7 @@ mod tests {
         let filter = OrderbookFilter {
             tokens: TokenFilter::default(),
             users: HashMap::new(),
+            orders: Vec::new(),
         };
 
         let reader = FilteredOrderbookReader::new(Box::new(inner), filter);
@@ -293,6 +398,154 @@ mod tests {
         assert_eq!(state, AccountState::default());
     }
 
+    #[test]
+    fn test_expression_orderbook_filter() {
+        let mut dust = create_order_for_test();
+        dust.valid_until = 20;
+        let mut expiring = create_order_for_test();
+        expiring.remaining_sell_amount = 100;
+        expiring.valid_until = 11;
+        let mut good_order = create_order_for_test();
+        good_order.remaining_sell_amount = 100;
+        good_order.valid_until = 20;
+        let orders = vec![dust, expiring, good_order.clone()];
+
+        let mut inner = MockStableXOrderBookReading::default();
+        inner.expect_get_auction_data_for_batch().return_once({
+            let result = (AccountState::with_balance_for(&orders), orders);
+            move |_| Ok(result)
+        });
+
+        let filter: OrderbookFilter = r#"{
+            "orders": ["sell_amount >= 100", "valid_for >= 5"]
+        }"#
+        .parse()
+        .unwrap();
+
+        let reader = FilteredOrderbookReader::new(Box::new(inner), filter);
+
+        let (_, filtered_orders) = reader
+            .get_auction_data_for_batch(10)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(filtered_orders, vec![good_order]);
+    }
+
+    #[test]
+    fn filters_orders_by_sell_value_with_price_source() {
+        let mut cheap = create_order_for_test();
+        cheap.sell_token = 1;
+        cheap.remaining_sell_amount = 10u128.pow(18);
+        let mut valuable = create_order_for_test();
+        valuable.sell_token = 2;
+        valuable.remaining_sell_amount = 10u128.pow(18);
+        let orders = vec![cheap, valuable.clone()];
+
+        let mut inner = MockStableXOrderBookReading::default();
+        inner.expect_get_auction_data_for_batch().return_once({
+            let result = (AccountState::with_balance_for(&orders), orders);
+            move |_| Ok(result)
+        });
+
+        let mut prices = MockInfalliblePriceSource::new();
+        prices.expect_price().returning(|token_id| match token_id {
+            TokenId(1) => nonzero!(10u128.pow(17)),
+            _ => nonzero!(10u128.pow(19)),
+        });
+
+        let filter: OrderbookFilter = r#"{
+            "orders": ["sell_value >= 1000000000000000000"]
+        }"#
+        .parse()
+        .unwrap();
+
+        let reader = FilteredOrderbookReader::new(Box::new(inner), filter)
+            .with_price_source(Arc::new(prices));
+
+        let (_, filtered_orders) = reader
+            .get_auction_data_for_batch(10)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(filtered_orders, vec![valuable]);
+    }
+
+    #[test]
+    fn allowed_markets_of_pair_expressions() {
+        let filter = |orders: &str| -> OrderbookFilter {
+            format!(r#"{{"orders": {}}}"#, orders).parse().unwrap()
+        };
+        assert_eq!(filter(r#"["sell_amount >= 1"]"#).allowed_markets(), None);
+        assert_eq!(
+            filter(r#"["pair in [2/1, 1/7]"]"#).allowed_markets(),
+            Some(vec![(1, 2), (1, 7)].into_iter().collect())
+        );
+        assert_eq!(
+            filter(r#"["pair in [2/1, 1/7]", "pair in [1/7, 2/7]"]"#).allowed_markets(),
+            Some(vec![(1, 7)].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn filters_orders_outside_of_market_allowlist() {
+        let stable_order = Order::for_token_pair(1, 2);
+        let orders = vec![
+            Order::for_token_pair(1, 3),
+            stable_order.clone(),
+            Order::for_token_pair(3, 2),
+        ];
+
+        let mut inner = MockStableXOrderBookReading::default();
+        inner.expect_get_auction_data_for_batch().return_once({
+            let result = (AccountState::with_balance_for(&orders), orders);
+            move |_| Ok(result)
+        });
+
+        let registry = Arc::new(prometheus::Registry::new());
+        let filter = r#"{"orders": ["pair in [1/2]"]}"#.parse().unwrap();
+        let reader = FilteredOrderbookReader::new(Box::new(inner), filter)
+            .with_metrics(Arc::new(StableXMetrics::new(registry.clone())));
+
+        let (_, filtered_orders) = reader
+            .get_auction_data_for_batch(0)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(filtered_orders, vec![stable_order]);
+
+        let gauge = |name: &str, stage: Option<&str>| {
+            registry
+                .gather()
+                .into_iter()
+                .find(|family| family.get_name() == name)
+                .unwrap()
+                .get_metric()
+                .iter()
+                .find(|metric| {
+                    stage.map_or(true, |stage| metric.get_label()[0].get_value() == stage)
+                })
+                .unwrap()
+                .get_gauge()
+                .get_value()
+        };
+        assert_eq!(
+            gauge(
+                "dfusion_service_orderbook_filtered_orders",
+                Some("unfiltered")
+            ),
+            3.0
+        );
+        assert_eq!(
+            gauge(
+                "dfusion_service_orderbook_filtered_orders",
+                Some("filtered")
+            ),
+            1.0
+        );
+        assert_eq!(gauge("dfusion_service_market_allowlist_markets", None), 1.0);
+    }
+
     #[test]
     fn forwards_block_number_to_inner_filter() {
         let mut inner = MockStableXOrderBookReading::default();
@@ -304,6 +557,7 @@ mod tests {
         let filter = OrderbookFilter {
             tokens: TokenFilter::default(),
             users: HashMap::new(),
+            orders: Vec::new(),
         };
 
         let reader = FilteredOrderbookReader::new(Box::new(inner), filter);
@@ -1,6 +1,8 @@
 mod balance;
 mod block_timestamp_reading;
+pub mod checkpoint;
 mod order;
+mod orders;
 mod state;
 mod updating_orderbook;
 
@@ -4,10 +4,13 @@ use anyhow::{anyhow, ensure, Result};
 use num::BigInt;
 use num::Zero as _;
 use serde::{Deserialize, Serialize};
+use serde_with::rust::display_fromstr;
 
-/// The balance of a token for a user.
+/// The balance of a token for a user. Amounts are serialized as decimal
+/// strings so that they are readable in debug dumps of the state.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Balance {
+    #[serde(with = "display_fromstr")]
     balance: BigInt,
     deposit: Flux,
     withdraw: Flux,
@@ -56,11 +59,28 @@ impl Balance {
 
     pub fn get_balance_at_beginning_of_batch(&self, batch_id: BatchId) -> BigInt {
         let balance = self.balance_with_deposit_and_proceeds(batch_id);
-        // Withdraw requests can be for amounts larger than balance.
-        match self.withdraw.amount(batch_id) {
-            Some(amount) if amount < &balance => balance - amount,
-            Some(_) => BigInt::zero(),
-            None => balance,
+        self.subtract_withdraw_request(balance, batch_id)
+    }
+
+    /// Returns the balance that the contract's `getBalance` reports during the
+    /// batch. Unlike the balance at the beginning of the batch this includes
+    /// the proceeds of the solution that was submitted in the batch.
+    pub fn get_current_balance(&self, batch_id: BatchId) -> BigInt {
+        let mut balance = self.balance_with_deposit_and_proceeds(batch_id);
+        if self.proceeds.batch_id == batch_id {
+            balance += &self.proceeds.amount;
+        }
+        self.subtract_withdraw_request(balance, batch_id)
+    }
+
+    /// Returns the balance at the end of a solution submission in the batch,
+    /// including the proceeds of its trades.
+    pub fn get_balance_after_solution(&self, batch_id: BatchId) -> BigInt {
+        let balance = self.get_balance_at_beginning_of_batch(batch_id);
+        if self.proceeds.batch_id == batch_id {
+            balance + &self.proceeds.amount
+        } else {
+            balance
         }
     }
 
@@ -111,6 +131,15 @@ impl Balance {
         Ok(())
     }
 
+    fn subtract_withdraw_request(&self, balance: BigInt, batch_id: BatchId) -> BigInt {
+        // Withdraw requests can be for amounts larger than balance.
+        match self.withdraw.amount(batch_id) {
+            Some(amount) if amount < &balance => balance - amount,
+            Some(_) => BigInt::zero(),
+            None => balance,
+        }
+    }
+
     fn balance_with_deposit_and_proceeds(&self, current_batch_id: BatchId) -> BigInt {
         let mut result = self.balance.clone();
         if let Some(deposit) = self.deposit.amount(current_batch_id) {
@@ -132,6 +161,7 @@ impl Balance {
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 struct Flux {
     batch_id: BatchId,
+    #[serde(with = "display_fromstr")]
     amount: BigInt,
 }
 
@@ -225,4 +255,23 @@ mod tests {
             BigInt::from(1)
         );
     }
+
+    #[test]
+    fn get_current_balance_includes_proceeds_of_batch() {
+        let balance = Balance {
+            balance: BigInt::from(2),
+            deposit: Flux::default(),
+            withdraw: Flux {
+                batch_id: 1,
+                amount: BigInt::from(3),
+            },
+            proceeds: Flux {
+                batch_id: 2,
+                amount: BigInt::from(2),
+            },
+        };
+        assert_eq!(balance.get_balance_at_beginning_of_batch(2), BigInt::zero());
+        assert_eq!(balance.get_current_balance(2), BigInt::from(1));
+        assert_eq!(balance.get_current_balance(3), BigInt::from(1));
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{contracts::Web3, transport::HttpTransport};
+use crate::{contracts::Web3, transport::NodeTransport};
 use anyhow::{Context as _, Result};
 use ethcontract::{
     web3::{
@@ -63,7 +63,7 @@ impl BatchedBlockReading for Web3 {
     }
 }
 
-type BatchedWeb3 = ethcontract::web3::Web3<Batch<HttpTransport>>;
+type BatchedWeb3 = ethcontract::web3::Web3<Batch<NodeTransport>>;
 
 async fn query_block_timestamps_batched(
     batched_web3: &BatchedWeb3,
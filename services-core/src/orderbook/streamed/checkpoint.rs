@@ -0,0 +1,581 @@
+//! Checkpoints of the event based orderbook for bootstrapping a new or
+//! recovering instance from a trusted peer instead of from the genesis events.
+//!
+//! A checkpoint is the orderbook file of the peer together with metadata that
+//! is sent as HTTP headers: the format version, the last block with events,
+//! the checksum of the file, the root hash of the orderbook state at that
+//! block and a signature over all of them. Requests are authenticated and
+//! checkpoints are signed with a secret shared between the instances. Since
+//! the peer could still be wrong, imported checkpoints are additionally
+//! compared to the orders and balances of the exchange contract at their
+//! block.
+//!
+//! Instances with an orderbook file write it through a `CheckpointFile` and
+//! serve it with `SnapshotHandler` on the monitoring HTTP server.
+
+use super::{BatchId, State};
+use crate::{
+    contracts::stablex_contract::StableXContract,
+    history::events::EventRegistry,
+    http::{HttpClient, HttpFactory, HttpLabel},
+    http_server::Handler,
+    models::Order,
+};
+use anyhow::{anyhow, ensure, Context as _, Error, Result};
+use ethcontract::{common::hash::keccak256, Address, BlockNumber, H256};
+use isahc::{
+    config::Configurable,
+    http::{header::AUTHORIZATION, HeaderMap, Request},
+};
+use pricegraph::{Element, EncodingFormat};
+use rouille::{Request as ServerRequest, Response};
+use std::{
+    fmt::{self, Debug, Formatter},
+    fs::{self, File},
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use url::Url;
+
+pub const VERSION_HEADER: &str = "X-Orderbook-Version";
+pub const BLOCK_HEADER: &str = "X-Orderbook-Block";
+pub const CHECKSUM_HEADER: &str = "X-Orderbook-Checksum";
+pub const STATE_ROOT_HEADER: &str = "X-Orderbook-State-Root";
+pub const SIGNATURE_HEADER: &str = "X-Orderbook-Signature";
+
+/// Orderbook files of long histories are large, so downloading them takes
+/// much longer than other requests.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The number of orders read from the exchange contract per call when
+/// verifying a checkpoint.
+const ORDER_PAGE_SIZE: u16 = 500;
+
+/// The secret shared between instances exchanging checkpoints.
+#[derive(Clone)]
+pub struct CheckpointSecret(String);
+
+impl CheckpointSecret {
+    /// The value of the `Authorization` header authenticating requests.
+    pub fn authorization(&self) -> String {
+        format!("Bearer {}", self.0)
+    }
+
+    /// Checks the `Authorization` header of a request. The values are compared
+    /// by their signatures, so that the comparison does not leak the secret
+    /// through its timing.
+    fn authorizes(&self, authorization: Option<&str>) -> bool {
+        let expected = self.sign(self.authorization().as_bytes());
+        authorization.map(|authorization| self.sign(authorization.as_bytes())) == Some(expected)
+    }
+
+    /// Signs a message by hashing it together with the secret. Keccak is not
+    /// susceptible to length extension, so this is a secure MAC.
+    fn sign(&self, message: &[u8]) -> H256 {
+        let mut data = self.0.as_bytes().to_vec();
+        data.extend_from_slice(message);
+        H256(keccak256(data))
+    }
+}
+
+impl FromStr for CheckpointSecret {
+    type Err = Error;
+
+    fn from_str(secret: &str) -> Result<Self> {
+        ensure!(!secret.is_empty(), "checkpoint secret must not be empty");
+        Ok(CheckpointSecret(secret.to_owned()))
+    }
+}
+
+// NOTE: The secret is not printed since options get logged on startup.
+impl Debug for CheckpointSecret {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("CheckpointSecret(..)")
+    }
+}
+
+/// Describes the orderbook file of a checkpoint.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CheckpointMetadata {
+    /// The format version of the orderbook file.
+    pub version: u32,
+    /// The last block containing events, at which the state root is taken.
+    pub block: u64,
+    /// The Keccak hash of the orderbook file.
+    pub checksum: H256,
+    pub state_root: H256,
+}
+
+impl CheckpointMetadata {
+    /// Creates the metadata of the encoded orderbook.
+    pub fn new(registry: &EventRegistry, bytes: &[u8]) -> Result<Self> {
+        let block = registry.last_handled_block().unwrap_or(0);
+        Ok(CheckpointMetadata {
+            version: EventRegistry::VERSION,
+            block,
+            checksum: H256(keccak256(bytes)),
+            state_root: state_root(&registry.state_at_block(block)?)?,
+        })
+    }
+
+    /// The headers describing a checkpoint, including its signature.
+    pub fn headers(&self, secret: &CheckpointSecret) -> Vec<(&'static str, String)> {
+        vec![
+            (VERSION_HEADER, self.version.to_string()),
+            (BLOCK_HEADER, self.block.to_string()),
+            (CHECKSUM_HEADER, format!("{:x}", self.checksum)),
+            (STATE_ROOT_HEADER, format!("{:x}", self.state_root)),
+            (
+                SIGNATURE_HEADER,
+                format!("{:x}", secret.sign(self.message().as_bytes())),
+            ),
+        ]
+    }
+
+    /// Reads the metadata and its signature from the headers of a response.
+    fn from_headers(headers: &HeaderMap) -> Result<(Self, H256)> {
+        let header = |name: &str| -> Result<&str> {
+            headers
+                .get(name)
+                .ok_or_else(|| anyhow!("missing {} header", name))?
+                .to_str()
+                .with_context(|| format!("invalid {} header", name))
+        };
+        let hash = |name: &str| -> Result<H256> {
+            let value = header(name)?;
+            value
+                .trim_start_matches("0x")
+                .parse()
+                .map_err(|_| anyhow!("invalid {} header {:?}", name, value))
+        };
+        let metadata = CheckpointMetadata {
+            version: header(VERSION_HEADER)?.parse()?,
+            block: header(BLOCK_HEADER)?.parse()?,
+            checksum: hash(CHECKSUM_HEADER)?,
+            state_root: hash(STATE_ROOT_HEADER)?,
+        };
+        Ok((metadata, hash(SIGNATURE_HEADER)?))
+    }
+
+    fn message(&self) -> String {
+        format!(
+            "{}:{}:{:x}:{:x}",
+            self.version, self.block, self.checksum, self.state_root
+        )
+    }
+
+    /// Verifies the signature of the metadata and that it describes the
+    /// orderbook file.
+    fn verify(&self, signature: H256, bytes: &[u8], secret: &CheckpointSecret) -> Result<()> {
+        ensure!(
+            secret.sign(self.message().as_bytes()) == signature,
+            "invalid checkpoint signature"
+        );
+        ensure!(
+            self.version == EventRegistry::VERSION,
+            "unsupported checkpoint version {}, expected {}",
+            self.version,
+            EventRegistry::VERSION,
+        );
+        ensure!(
+            H256(keccak256(bytes)) == self.checksum,
+            "checkpoint does not match its checksum"
+        );
+        Ok(())
+    }
+}
+
+/// The root hash of an orderbook state. It is the hash of the debug JSON
+/// encoding, which is canonical because it sorts all maps.
+pub fn state_root(state: &State) -> Result<H256> {
+    Ok(H256(keccak256(state.to_debug_json()?)))
+}
+
+/// Reads all orders of the exchange contract at the block, each with the
+/// balance of its sell token.
+pub async fn read_onchain_orders(
+    contract: &dyn StableXContract,
+    block: u64,
+) -> Result<Vec<Element>> {
+    let mut elements = Vec::new();
+    let mut previous_page_user = Address::zero();
+    let mut previous_page_user_offset = 0u16;
+    loop {
+        let page = contract
+            .get_auction_data_paginated(
+                ORDER_PAGE_SIZE,
+                previous_page_user,
+                previous_page_user_offset,
+                Some(BlockNumber::Number(block.into())),
+            )
+            .await?;
+        let previous_len = elements.len();
+        // The packed format has no order IDs and a page can start in the
+        // middle of the orders of the last user of the previous page, so the
+        // IDs are counted across pages.
+        for mut element in Element::read_all_with_format(&page, EncodingFormat::Packed)? {
+            if element.user != previous_page_user {
+                previous_page_user = element.user;
+                previous_page_user_offset = 0;
+            }
+            element.id = previous_page_user_offset;
+            previous_page_user_offset = previous_page_user_offset.wrapping_add(1);
+            elements.push(element);
+        }
+        if elements.len() - previous_len < ORDER_PAGE_SIZE as usize {
+            return Ok(elements);
+        }
+    }
+}
+
+/// Verifies that the orderbook state matches the orders read from the
+/// exchange contract during the batch, both their remaining amounts and the
+/// balances of their sell tokens.
+pub fn verify_onchain_orders(state: &State, batch_id: BatchId, elements: &[Element]) -> Result<()> {
+    let mut onchain_orders = Vec::new();
+    for element in elements {
+        // Users that never held the sell token have no balance in the state.
+        let balance = state
+            .current_balance((element.user, element.pair.sell), batch_id)
+            .unwrap_or_default();
+        ensure!(
+            balance == element.balance,
+            "balance {} of user {:?} for token {} does not match on-chain balance {}",
+            balance,
+            element.user,
+            element.pair.sell,
+            element.balance,
+        );
+        if element.valid.from <= batch_id
+            && batch_id <= element.valid.to
+            && element.remaining_sell_amount > 0
+        {
+            onchain_orders.push(Order {
+                id: element.id,
+                account_id: element.user,
+                buy_token: element.pair.buy,
+                sell_token: element.pair.sell,
+                numerator: element.price.numerator,
+                denominator: element.price.denominator,
+                remaining_sell_amount: element.remaining_sell_amount,
+                valid_from: element.valid.from,
+                valid_until: element.valid.to,
+            });
+        }
+    }
+
+    // The orders of the state are those valid when the next batch starts
+    // collecting orders, which are the ones valid in this batch.
+    let (_, mut orders) = state.canonicalized_auction_state_at_beginning_of_batch(batch_id + 1)?;
+    orders.sort_by_key(|order| (order.account_id, order.id));
+    onchain_orders.sort_by_key(|order| (order.account_id, order.id));
+    ensure!(
+        orders == onchain_orders,
+        "{} orders valid in batch {} do not match the {} valid on-chain orders",
+        orders.len(),
+        batch_id,
+        onchain_orders.len(),
+    );
+    Ok(())
+}
+
+/// A verified orderbook checkpoint.
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub metadata: CheckpointMetadata,
+    pub registry: EventRegistry,
+}
+
+/// A trusted peer serving orderbook checkpoints.
+#[derive(Debug)]
+pub struct CheckpointSource {
+    client: HttpClient,
+    url: Url,
+    secret: CheckpointSecret,
+}
+
+impl CheckpointSource {
+    pub fn new(http_factory: &HttpFactory, url: Url, secret: CheckpointSecret) -> Result<Self> {
+        let client = http_factory
+            .with_config(|builder| builder.timeout(DOWNLOAD_TIMEOUT))?
+            .without_circuit_breaker();
+        Ok(CheckpointSource {
+            client,
+            url,
+            secret,
+        })
+    }
+
+    /// Downloads the current checkpoint of the peer and verifies its
+    /// signature and checksum.
+    pub async fn fetch(&self) -> Result<Checkpoint> {
+        let request = Request::get(self.url.as_str())
+            .header(AUTHORIZATION, self.secret.authorization())
+            .body(())?;
+        let response = self
+            .client
+            .get_bytes_async(request, HttpLabel::OrderbookCheckpoint)
+            .await?;
+        let (metadata, signature) = CheckpointMetadata::from_headers(response.headers())?;
+        metadata.verify(signature, response.body(), &self.secret)?;
+        let registry = EventRegistry::read(response.body().as_slice())?;
+        ensure!(
+            registry.last_handled_block().unwrap_or(0) == metadata.block,
+            "checkpoint events end at a different block than its metadata"
+        );
+        Ok(Checkpoint { metadata, registry })
+    }
+}
+
+/// The orderbook file of an instance serving checkpoints. The metadata of the
+/// checkpoint is computed once whenever the file is written, so that serving
+/// it does not need to read the file.
+#[derive(Debug)]
+pub struct CheckpointFile {
+    path: PathBuf,
+    /// `None` until the file is first written, since a file left by a
+    /// previous run has not been checked to be complete.
+    metadata: Mutex<Option<CheckpointMetadata>>,
+}
+
+impl CheckpointFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            metadata: Mutex::new(None),
+        }
+    }
+
+    /// Writes the orderbook to the file and computes the metadata of the
+    /// checkpoint. Like `EventRegistry::write_to_file` the file is written to
+    /// a temporary file first and then renamed, so that it is always complete.
+    pub fn write(&self, registry: &EventRegistry) -> Result<()> {
+        let bytes = registry.to_bytes()?;
+        let metadata = CheckpointMetadata::new(registry, &bytes)?;
+        let temp_path = self.path.with_extension("temp");
+        fs::write(&temp_path, &bytes)
+            .with_context(|| format!("couldn't write {}", temp_path.display()))?;
+        // The file is replaced while holding the lock, so that an opened file
+        // always matches the metadata returned with it.
+        let mut current = self.metadata.lock().unwrap();
+        fs::rename(&temp_path, &self.path)?;
+        *current = Some(metadata);
+        Ok(())
+    }
+
+    /// Opens the file with the metadata of its checkpoint, or returns `None`
+    /// if it has not been written yet.
+    fn open(&self) -> Result<Option<(File, CheckpointMetadata)>> {
+        let metadata = self.metadata.lock().unwrap();
+        match *metadata {
+            Some(metadata) => Ok(Some((File::open(&self.path)?, metadata))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Serves the orderbook file as a checkpoint to requests authenticated with
+/// the shared secret.
+pub struct SnapshotHandler {
+    checkpoint_file: Arc<CheckpointFile>,
+    secret: CheckpointSecret,
+}
+
+impl SnapshotHandler {
+    pub fn new(checkpoint_file: Arc<CheckpointFile>, secret: CheckpointSecret) -> Self {
+        Self {
+            checkpoint_file,
+            secret,
+        }
+    }
+}
+
+impl Handler for SnapshotHandler {
+    fn handle_request(&self, request: &ServerRequest) -> Result<Response> {
+        if !self.secret.authorizes(request.header("Authorization")) {
+            return Ok(Response::text("unauthorized").with_status_code(401));
+        }
+        // The opened file is streamed even if it is replaced in the meantime.
+        let (file, metadata) = match self.checkpoint_file.open()? {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(Response::empty_404()),
+        };
+        Ok(metadata.headers(&self.secret).into_iter().fold(
+            Response::from_file("application/octet-stream", file),
+            |response, (name, value)| response.with_additional_header(name, value),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::stablex_contract::MockStableXContract;
+    use futures::FutureExt as _;
+    use isahc::http::HeaderValue;
+    use mockall::predicate::eq;
+    use pricegraph::{PriceFraction, TokenPair, Validity};
+
+    fn element(user: u8) -> Element {
+        Element {
+            user: Address::repeat_byte(user),
+            balance: 0.into(),
+            pair: TokenPair { buy: 0, sell: 1 },
+            valid: Validity { from: 0, to: 10 },
+            price: PriceFraction {
+                numerator: 1,
+                denominator: 1,
+            },
+            remaining_sell_amount: 1,
+            id: 0,
+        }
+    }
+
+    #[test]
+    fn signed_metadata_roundtrip() {
+        let registry = EventRegistry::default();
+        let bytes = registry.to_bytes().unwrap();
+        let metadata = CheckpointMetadata::new(&registry, &bytes).unwrap();
+        assert_eq!(metadata.block, 0);
+        assert_eq!(metadata.state_root, state_root(&State::default()).unwrap());
+
+        let secret: CheckpointSecret = "secret".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        for (name, value) in metadata.headers(&secret) {
+            headers.insert(name, HeaderValue::from_str(&value).unwrap());
+        }
+        let (parsed, signature) = CheckpointMetadata::from_headers(&headers).unwrap();
+        assert_eq!(parsed, metadata);
+        assert!(parsed.verify(signature, &bytes, &secret).is_ok());
+
+        let other_secret: CheckpointSecret = "other".parse().unwrap();
+        assert!(parsed.verify(signature, &bytes, &other_secret).is_err());
+        assert!(parsed.verify(signature, b"tampered", &secret).is_err());
+        let forged = CheckpointMetadata { block: 1, ..parsed };
+        assert!(forged.verify(signature, &bytes, &secret).is_err());
+    }
+
+    #[test]
+    fn serves_signed_snapshot_to_authorized_requests() {
+        let secret: CheckpointSecret = "secret".parse().unwrap();
+        let path = std::env::temp_dir().join(format!("orderbook-snapshot-{}", std::process::id()));
+        let checkpoint_file = Arc::new(CheckpointFile::new(path.clone()));
+        let handler = SnapshotHandler::new(checkpoint_file.clone(), secret.clone());
+        for authorization in &[
+            vec![],
+            vec![("Authorization".to_owned(), "Bearer other".to_owned())],
+        ] {
+            let request = ServerRequest::fake_http(
+                "GET",
+                "/orderbook/snapshot",
+                authorization.clone(),
+                vec![],
+            );
+            assert_eq!(handler.handle_request(&request).unwrap().status_code, 401);
+        }
+        let request = ServerRequest::fake_http(
+            "GET",
+            "/orderbook/snapshot",
+            vec![("Authorization".to_owned(), secret.authorization())],
+            vec![],
+        );
+        assert_eq!(handler.handle_request(&request).unwrap().status_code, 404);
+
+        checkpoint_file.write(&EventRegistry::default()).unwrap();
+        let response = handler.handle_request(&request).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(response.status_code, 200);
+        let mut headers = HeaderMap::new();
+        for (name, value) in &response.headers {
+            if let Ok(name) = isahc::http::header::HeaderName::from_bytes(name.as_bytes()) {
+                headers.insert(name, HeaderValue::from_str(value).unwrap());
+            }
+        }
+        let (metadata, signature) = CheckpointMetadata::from_headers(&headers).unwrap();
+        let (mut body, _) = response.data.into_reader_and_size();
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut body, &mut bytes).unwrap();
+        assert!(metadata.verify(signature, &bytes, &secret).is_ok());
+    }
+
+    #[test]
+    fn counts_order_ids_across_pages() {
+        let block = Some(BlockNumber::Number(42.into()));
+        let mut first_page = vec![element(1); ORDER_PAGE_SIZE as usize - 1];
+        first_page.push(element(2));
+        let second_page = vec![element(2); 2];
+
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_get_auction_data_paginated()
+            .with(eq(ORDER_PAGE_SIZE), eq(Address::zero()), eq(0), eq(block))
+            .returning(move |_, _, _, _| {
+                Ok(Element::write_all_with_format(
+                    &first_page,
+                    EncodingFormat::Packed,
+                ))
+            });
+        contract
+            .expect_get_auction_data_paginated()
+            .with(
+                eq(ORDER_PAGE_SIZE),
+                eq(Address::repeat_byte(2)),
+                eq(1),
+                eq(block),
+            )
+            .returning(move |_, _, _, _| {
+                Ok(Element::write_all_with_format(
+                    &second_page,
+                    EncodingFormat::Packed,
+                ))
+            });
+
+        let elements = read_onchain_orders(&contract, 42)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(elements.len(), ORDER_PAGE_SIZE as usize + 2);
+        assert_eq!(elements[ORDER_PAGE_SIZE as usize - 2].id, 498);
+        let ids = elements[ORDER_PAGE_SIZE as usize - 1..]
+            .iter()
+            .map(|element| (element.user, element.id))
+            .collect::<Vec<_>>();
+        let user = Address::repeat_byte(2);
+        assert_eq!(ids, vec![(user, 0), (user, 1), (user, 2)]);
+    }
+
+    #[test]
+    fn verifies_state_against_onchain_orders() {
+        let state = State::default();
+        assert!(verify_onchain_orders(&state, 5, &[]).is_ok());
+
+        // Orders that are not valid in the batch or have nothing left to sell
+        // are not part of the orderbook.
+        let expired = Element {
+            valid: Validity { from: 0, to: 4 },
+            ..element(1)
+        };
+        let filled = Element {
+            remaining_sell_amount: 0,
+            ..element(1)
+        };
+        assert!(verify_onchain_orders(&state, 5, &[expired, filled]).is_ok());
+
+        assert!(verify_onchain_orders(&state, 5, &[element(1)]).is_err());
+        let funded = Element {
+            balance: 1.into(),
+            valid: Validity { from: 0, to: 4 },
+            ..element(1)
+        };
+        assert!(verify_onchain_orders(&state, 5, &[funded]).is_err());
+    }
+
+    #[test]
+    fn secret_is_not_printed() {
+        let secret: CheckpointSecret = "hunter2".parse().unwrap();
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+        assert!("".parse::<CheckpointSecret>().is_err());
+    }
+}
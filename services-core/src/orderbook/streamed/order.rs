@@ -1,6 +1,6 @@
 use super::*;
-use crate::models::Order as ModelOrder;
-use anyhow::{anyhow, ensure, Result};
+use crate::models::{Amount, Order as ModelOrder};
+use anyhow::{ensure, Result};
 use serde::{Deserialize, Serialize};
 
 /// Change from a potential solution that might still get replaced by a better solution in the same
@@ -56,6 +56,13 @@ impl Order {
         self.valid_from <= batch_id && batch_id <= self.valid_until
     }
 
+    /// Returns whether trading the amounts respects the limit price of the
+    /// order.
+    pub fn satisfies_limit_price(&self, sell_amount: u128, buy_amount: u128) -> bool {
+        Amount(buy_amount).as_u256() * U256::from(self.price_denominator)
+            >= Amount(sell_amount).as_u256() * U256::from(self.price_numerator)
+    }
+
     pub fn get_used_amount(&self, batch_id: BatchId) -> u128 {
         if self.pending_used_amount.batch_id < batch_id {
             // Cannot fail because of the invariant.
@@ -82,11 +89,9 @@ impl Order {
         // Now a potential previous used amount has been cleared. If there is an existing used
         // amount for this batch then setting the batch_id does nothing and we add to the amount.
         self.pending_used_amount.batch_id = batch_id;
-        self.pending_used_amount.amount = self
-            .pending_used_amount
-            .amount
-            .checked_add(used_amount)
-            .ok_or_else(|| anyhow!("math overflow"))?;
+        self.pending_used_amount.amount = Amount(self.pending_used_amount.amount)
+            .checked_add(Amount(used_amount))?
+            .into();
         self.ensure_used_amount_invariant()
     }
 
@@ -98,11 +103,9 @@ impl Order {
             self.pending_used_amount.batch_id == batch_id,
             "reverting non existent trade"
         );
-        self.pending_used_amount.amount = self
-            .pending_used_amount
-            .amount
-            .checked_sub(used_amount)
-            .ok_or_else(|| anyhow!("math underflow"))?;
+        self.pending_used_amount.amount = Amount(self.pending_used_amount.amount)
+            .checked_sub(Amount(used_amount))?
+            .into();
         // The invariant hasn't been changed because the total used amount decreased.
         Ok(())
     }
@@ -135,12 +138,10 @@ impl Order {
     }
 
     fn ensure_used_amount_invariant(&self) -> Result<()> {
-        let total_used_amount = self
-            .used_amount
-            .checked_add(self.pending_used_amount.amount)
-            .ok_or_else(|| anyhow!("math overflow"))?;
+        let total_used_amount =
+            Amount(self.used_amount).checked_add(Amount(self.pending_used_amount.amount))?;
         ensure!(
-            total_used_amount <= self.price_denominator,
+            total_used_amount <= Amount(self.price_denominator),
             "trade by more than order limit"
         );
         Ok(())
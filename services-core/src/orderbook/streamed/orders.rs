@@ -0,0 +1,194 @@
+use super::*;
+use order::Order;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::iter::FromIterator;
+
+type OrderKey = (UserId, OrderId);
+
+/// The orders of all users indexed by the last batch they are valid in.
+///
+/// Orders are never removed unless they are explicitly deleted, so over time
+/// almost all orders are expired. The index allows finding the orders that are
+/// valid in a batch by only visiting orders that have not yet expired instead
+/// of all orders ever placed.
+///
+/// Only the orders are serialized and the index is rebuilt when deserializing,
+/// so the serialized representation is the same as for a plain map.
+#[derive(Clone, Debug, Default)]
+pub struct Orders {
+    orders: HashMap<OrderKey, Order>,
+    by_valid_until: BTreeMap<BatchId, HashSet<OrderKey>>,
+}
+
+impl Orders {
+    pub fn get(&self, key: &OrderKey) -> Option<&Order> {
+        self.orders.get(key)
+    }
+
+    /// Returns a mutable reference to an order for applying trades. The
+    /// validity of the order must not be changed through it, which is what
+    /// `Orders::set_valid_until` is for.
+    pub fn get_mut(&mut self, key: &OrderKey) -> Option<&mut Order> {
+        self.orders.get_mut(key)
+    }
+
+    /// Inserts a new order. Returns false without modifying the orders if an
+    /// order with the same key already exists.
+    pub fn insert(&mut self, key: OrderKey, order: Order) -> bool {
+        if self.orders.contains_key(&key) {
+            return false;
+        }
+        self.index(key, order.valid_until);
+        self.orders.insert(key, order);
+        true
+    }
+
+    /// Changes the last batch an order is valid in. Returns false if the order
+    /// does not exist.
+    pub fn set_valid_until(&mut self, key: OrderKey, valid_until: BatchId) -> bool {
+        let order = match self.orders.get_mut(&key) {
+            Some(order) => order,
+            None => return false,
+        };
+        let previous_valid_until = std::mem::replace(&mut order.valid_until, valid_until);
+        self.unindex(key, previous_valid_until);
+        self.index(key, valid_until);
+        true
+    }
+
+    pub fn remove(&mut self, key: &OrderKey) -> Option<Order> {
+        let order = self.orders.remove(key)?;
+        self.unindex(*key, order.valid_until);
+        Some(order)
+    }
+
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.by_valid_until.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OrderKey, &Order)> + '_ {
+        self.orders.iter()
+    }
+
+    /// Returns the orders that are valid in the batch, visiting only orders
+    /// that have not expired before it.
+    pub fn valid_in_batch(
+        &self,
+        batch_id: BatchId,
+    ) -> impl Iterator<Item = (&OrderKey, &Order)> + '_ {
+        self.by_valid_until
+            .range(batch_id..)
+            .flat_map(|(_, keys)| keys.iter())
+            .map(move |key| (key, &self.orders[key]))
+            .filter(move |(_, order)| order.is_valid_in_batch(batch_id))
+    }
+
+    fn index(&mut self, key: OrderKey, valid_until: BatchId) {
+        self.by_valid_until
+            .entry(valid_until)
+            .or_default()
+            .insert(key);
+    }
+
+    fn unindex(&mut self, key: OrderKey, valid_until: BatchId) {
+        if let Some(keys) = self.by_valid_until.get_mut(&valid_until) {
+            keys.remove(&key);
+            if keys.is_empty() {
+                self.by_valid_until.remove(&valid_until);
+            }
+        }
+    }
+}
+
+impl FromIterator<(OrderKey, Order)> for Orders {
+    fn from_iter<I: IntoIterator<Item = (OrderKey, Order)>>(iter: I) -> Self {
+        let mut orders = Orders::default();
+        for (key, order) in iter {
+            orders.index(key, order.valid_until);
+            orders.orders.insert(key, order);
+        }
+        orders
+    }
+}
+
+impl Serialize for Orders {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.orders.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Orders {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let orders = HashMap::<OrderKey, Order>::deserialize(deserializer)?;
+        Ok(orders.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(valid_from: BatchId, valid_until: BatchId) -> Order {
+        Order::new(0, 1, valid_from, valid_until, 1, 1)
+    }
+
+    fn key(user: u64, id: OrderId) -> OrderKey {
+        (UserId::from_low_u64_be(user), id)
+    }
+
+    fn valid_keys(orders: &Orders, batch_id: BatchId) -> Vec<OrderKey> {
+        let mut keys = orders
+            .valid_in_batch(batch_id)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn finds_orders_valid_in_batch() {
+        let mut orders = Orders::default();
+        assert!(orders.insert(key(1, 0), order(0, 2)));
+        assert!(orders.insert(key(1, 1), order(3, 5)));
+        assert!(orders.insert(key(2, 0), order(1, BatchId::max_value())));
+        assert!(!orders.insert(key(1, 0), order(0, 10)));
+
+        assert_eq!(valid_keys(&orders, 0), vec![key(1, 0)]);
+        assert_eq!(valid_keys(&orders, 2), vec![key(1, 0), key(2, 0)]);
+        assert_eq!(valid_keys(&orders, 4), vec![key(1, 1), key(2, 0)]);
+        assert_eq!(valid_keys(&orders, 6), vec![key(2, 0)]);
+    }
+
+    #[test]
+    fn updates_index_when_orders_change() {
+        let mut orders = Orders::default();
+        orders.insert(key(1, 0), order(0, BatchId::max_value()));
+        orders.insert(key(1, 1), order(0, 5));
+
+        assert!(orders.set_valid_until(key(1, 0), 1));
+        assert!(!orders.set_valid_until(key(3, 0), 1));
+        assert_eq!(valid_keys(&orders, 1), vec![key(1, 0), key(1, 1)]);
+        assert_eq!(valid_keys(&orders, 2), vec![key(1, 1)]);
+
+        assert!(orders.remove(&key(1, 1)).is_some());
+        assert_eq!(valid_keys(&orders, 2), vec![]);
+        assert_eq!(orders.by_valid_until.len(), 1);
+    }
+
+    #[test]
+    fn rebuilds_index_when_deserializing() {
+        let mut orders = Orders::default();
+        orders.insert(key(1, 0), order(0, 2));
+        orders.insert(key(1, 1), order(3, 5));
+
+        let serialized = bincode::serialize(&orders).unwrap();
+        let map: HashMap<OrderKey, Order> = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(map.len(), 2);
+
+        let orders: Orders = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(valid_keys(&orders, 1), vec![key(1, 0)]);
+        assert_eq!(valid_keys(&orders, 3), vec![key(1, 1)]);
+    }
+}
@@ -1,16 +1,17 @@
 use super::*;
 use crate::{
     bigint_u256,
-    models::{AccountState, Order as ModelOrder},
-    orderbook::util,
+    models::{AccountState, Order as ModelOrder, Solution},
 };
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 use balance::Balance;
 use contracts::batch_exchange::{event_data::*, Event};
+use num::Signed as _;
 use order::Order;
+use orders::Orders;
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::Iterator;
 
 // Most types, fields, functions in this module mirror the smart contract because we need to
@@ -23,7 +24,7 @@ use std::iter::Iterator;
 /// can only be processed if the OrderPlacement for the traded order has previously been observed.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct State {
-    orders: HashMap<(UserId, OrderId), Order>,
+    orders: Orders,
     balances: HashMap<(UserId, TokenAddress), Balance>,
     tokens: Tokens,
     last_solution: LastSolution,
@@ -33,6 +34,39 @@ pub struct State {
     last_batch_id: BatchId,
 }
 
+/// A readable representation of the state for debugging. Maps with composite
+/// keys are turned into sorted lists because JSON objects only support string
+/// keys.
+#[derive(Debug, Deserialize, Serialize)]
+struct DebugState {
+    orders: Vec<DebugOrder>,
+    balances: Vec<DebugBalance>,
+    tokens: Vec<DebugToken>,
+    last_solution: LastSolution,
+    solution_partially_received: bool,
+    last_batch_id: BatchId,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct DebugOrder {
+    user: UserId,
+    id: OrderId,
+    order: Order,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct DebugBalance {
+    user: UserId,
+    token: TokenAddress,
+    balance: Balance,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct DebugToken {
+    id: TokenId,
+    address: TokenAddress,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 struct LastSolution {
     batch_id: BatchId,
@@ -63,6 +97,11 @@ impl State {
         impl Iterator<Item = ((UserId, TokenId), U256)> + '_,
         impl Iterator<Item = ModelOrder> + '_,
     )> {
+        self.ensure_orderbook_available(batch_id)?;
+        Ok((self.account_state(batch_id), self.orders(batch_id)))
+    }
+
+    fn ensure_orderbook_available(&self, batch_id: BatchId) -> Result<()> {
         // We can only serve state up until the most recent batch, as we are not keeping
         // historic information about orders and balance updates around.
         ensure!(self.last_batch_id <= batch_id, "batch is in the past");
@@ -73,22 +112,122 @@ impl State {
             !self.solution_partially_received,
             "retrieved orderbook with a partially applied solution",
         );
-        Ok((self.account_state(batch_id), self.orders(batch_id)))
+        Ok(())
     }
 
     /// Returns the canonicalized auction state when the requested batch started collecting orders given all events received so far.
     ///
+    /// This is equivalent to canonicalizing the orderbook returned by
+    /// `State::orderbook_at_beginning_of_batch`, but only the balances sold by
+    /// the orders of the batch are looked up, instead of computing the balances
+    /// of all users and filtering them afterwards.
+    ///
     /// See `State::orderbook_at_beginning_of_batch` for more details on possible errors.
     pub fn canonicalized_auction_state_at_beginning_of_batch(
         &self,
         batch_id: BatchId,
     ) -> Result<(AccountState, Vec<ModelOrder>)> {
-        let (account_state, orders) = self.orderbook_at_beginning_of_batch(batch_id)?;
-        let (account_state, orders) = util::canonicalize_auction_data(account_state, orders);
+        self.ensure_orderbook_available(batch_id)?;
+        let orders = self
+            .orders(batch_id)
+            .filter(|order| order.remaining_sell_amount > 0)
+            .collect::<Vec<_>>();
+
+        let mut account_state = AccountState::default();
+        for order in &orders {
+            let key = (order.account_id, order.sell_token);
+            if let Entry::Vacant(entry) = account_state.0.entry(key) {
+                if let Some(balance) = self.balance_at_beginning_of_batch(key, batch_id) {
+                    entry.insert(balance);
+                }
+            }
+        }
 
         Ok((account_state, orders))
     }
 
+    /// Returns the balance of a user for a listed token when the batch started
+    /// collecting orders, or `None` if the user never held the token or the
+    /// balance overflows a U256.
+    fn balance_at_beginning_of_batch(
+        &self,
+        (user_id, token_id): (UserId, TokenId),
+        batch_id: BatchId,
+    ) -> Option<U256> {
+        let token_address = self.tokens.get_address_by_id(token_id)?;
+        let balance = self.balances.get(&(user_id, token_address))?;
+        bigint_u256::bigint_to_u256(&balance.get_balance_at_beginning_of_batch(batch_id))
+    }
+
+    /// Returns the balance of a user for a listed token as the contract
+    /// reports it during the requested batch, or `None` if the user never held
+    /// the token or the balance overflows a U256.
+    pub fn current_balance(
+        &self,
+        (user_id, token_id): (UserId, TokenId),
+        batch_id: BatchId,
+    ) -> Option<U256> {
+        let token_address = self.tokens.get_address_by_id(token_id)?;
+        let balance = self.balances.get(&(user_id, token_address))?;
+        bigint_u256::bigint_to_u256(&balance.get_current_balance(batch_id))
+    }
+
+    /// Replays the trades of a solution for the batch before `batch_id` as if
+    /// it was submitted in `batch_id`, using the same logic as for trade events.
+    ///
+    /// Errors if the solution trades an unknown order, an order that is not
+    /// valid in the solved batch, more than the remaining amount of an order or
+    /// below its limit price, or if it overdraws a balance.
+    pub fn verify_solution(&self, batch_id: BatchId, solution: &Solution) -> Result<()> {
+        let mut state = self.clone();
+        let mut sell_balances = HashSet::new();
+        for executed_order in &solution.executed_orders {
+            let key = (executed_order.account_id, executed_order.order_id);
+            let order = state
+                .orders
+                .get(&key)
+                .ok_or_else(|| anyhow!("unknown order {:?}", key))?;
+            ensure!(
+                order.is_valid_in_batch(batch_id - 1),
+                "order {:?} is not valid in batch {}",
+                key,
+                batch_id - 1
+            );
+            ensure!(
+                order.satisfies_limit_price(executed_order.sell_amount, executed_order.buy_amount),
+                "order {:?} traded below its limit price",
+                key
+            );
+            let sell_token = state
+                .tokens
+                .get_address_by_id(order.sell_token)
+                .ok_or_else(|| anyhow!("unknown sell token"))?;
+            sell_balances.insert((executed_order.account_id, sell_token));
+
+            state
+                .apply_trade_internal(
+                    executed_order.account_id,
+                    executed_order.order_id,
+                    |order| order.trade(executed_order.sell_amount, batch_id),
+                    |sell_balance| sell_balance.sell(executed_order.sell_amount, batch_id),
+                    |buy_balance| buy_balance.buy(executed_order.buy_amount, batch_id),
+                )
+                .map_err(|err| anyhow!("invalid trade of order {:?}: {}", key, err))?;
+        }
+
+        for key in sell_balances {
+            ensure!(
+                !state.balances[&key]
+                    .get_balance_after_solution(batch_id)
+                    .is_negative(),
+                "solution overdraws balance of user {:?} for token {:?}",
+                key.0,
+                key.1
+            );
+        }
+        Ok(())
+    }
+
     fn account_state(
         &self,
         batch_id: BatchId,
@@ -109,17 +248,100 @@ impl State {
             })
     }
 
+    /// Returns the balances of all users as the contract reports them during
+    /// the requested batch, that is after the solution submitted in it was
+    /// applied. This includes balances of tokens that are not listed on the
+    /// exchange. Balances that overflow a U256 are skipped.
+    pub fn current_balances(
+        &self,
+        batch_id: BatchId,
+    ) -> impl Iterator<Item = ((UserId, TokenAddress), U256)> + '_ {
+        self.balances.iter().filter_map(move |(key, balance)| {
+            let balance = balance.get_current_balance(batch_id);
+            Some((*key, bigint_u256::bigint_to_u256(&balance)?))
+        })
+    }
+
     fn orders(&self, batch_id: BatchId) -> impl Iterator<Item = ModelOrder> + '_ {
         self.orders
-            .iter()
             // State is returned **excluding** the given `batch_id` however order validity is internally stored
             // **including** `batch_id`. Thus we need subtract 1 here to get all orders valid for batch_id -1.
-            .filter(move |(_, order)| order.is_valid_in_batch(batch_id - 1))
+            .valid_in_batch(batch_id - 1)
             .map(move |((user_id, order_id), order)| {
                 order.as_model_order(batch_id, *user_id, *order_id)
             })
     }
 
+    /// Dumps the orders, balances, tokens and last solution as pretty printed
+    /// JSON for debugging.
+    pub fn to_debug_json(&self) -> Result<String> {
+        let mut orders: Vec<_> = self
+            .orders
+            .iter()
+            .map(|((user, id), order)| DebugOrder {
+                user: *user,
+                id: *id,
+                order: *order,
+            })
+            .collect();
+        orders.sort_by_key(|order| (order.user, order.id));
+        let mut balances: Vec<_> = self
+            .balances
+            .iter()
+            .map(|((user, token), balance)| DebugBalance {
+                user: *user,
+                token: *token,
+                balance: balance.clone(),
+            })
+            .collect();
+        balances.sort_by_key(|balance| (balance.user, balance.token));
+        let tokens = self
+            .tokens
+            .0
+            .iter()
+            .map(|(id, address)| DebugToken {
+                id: *id,
+                address: *address,
+            })
+            .collect();
+
+        Ok(serde_json::to_string_pretty(&DebugState {
+            orders,
+            balances,
+            tokens,
+            last_solution: self.last_solution.clone(),
+            solution_partially_received: self.solution_partially_received,
+            last_batch_id: self.last_batch_id,
+        })?)
+    }
+
+    /// Restores a state from JSON created with `State::to_debug_json`.
+    pub fn from_debug_json(json: &str) -> Result<Self> {
+        let debug_state: DebugState = serde_json::from_str(json)?;
+        Ok(State {
+            orders: debug_state
+                .orders
+                .into_iter()
+                .map(|order| ((order.user, order.id), order.order))
+                .collect(),
+            balances: debug_state
+                .balances
+                .into_iter()
+                .map(|balance| ((balance.user, balance.token), balance.balance))
+                .collect(),
+            tokens: Tokens(
+                debug_state
+                    .tokens
+                    .into_iter()
+                    .map(|token| (token.id, token.address))
+                    .collect(),
+            ),
+            last_solution: debug_state.last_solution,
+            solution_partially_received: debug_state.solution_partially_received,
+            last_batch_id: debug_state.last_batch_id,
+        })
+    }
+
     /// Reset the state to the default state in which no events have been applied.
     pub fn clear(&mut self) {
         self.orders.clear();
@@ -191,10 +413,10 @@ impl State {
             event.price_numerator,
             event.price_denominator,
         );
-        match self.orders.entry((event.owner, event.index)) {
-            Entry::Vacant(entry) => entry.insert(order),
-            Entry::Occupied(_) => bail!("order already exists"),
-        };
+        ensure!(
+            self.orders.insert((event.owner, event.index), order),
+            "order already exists"
+        );
         Ok(())
     }
 
@@ -203,11 +425,11 @@ impl State {
         event: &OrderCancellation,
         block_batch_id: BatchId,
     ) -> Result<()> {
-        let order = self
-            .orders
-            .get_mut(&(event.owner, event.id))
-            .ok_or_else(|| anyhow!("unknown order"))?;
-        order.valid_until = block_batch_id - 1;
+        ensure!(
+            self.orders
+                .set_valid_until((event.owner, event.id), block_batch_id - 1),
+            "unknown order"
+        );
         Ok(())
     }
 
@@ -338,7 +560,8 @@ impl Tokens {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::AccountState;
+    use crate::models::{AccountState, ExecutedOrder};
+    use serde_json::json;
 
     macro_rules! apply_event {
         (to $state:ident for batch $batch:expr; TokenListing token $token:expr) => {
@@ -485,6 +708,23 @@ mod tests {
         assert_balance!(in state at beginning of batch 1; user 3, has token 1, balance 1);
     }
 
+    #[test]
+    fn balances_include_unlisted_tokens() {
+        let mut state = state_with_fee();
+        // token id 1 is not listed
+        apply_event!(to state for batch 0; Deposit token 0, to user 3, amount 1);
+        apply_event!(to state for batch 0; Deposit token 1, to user 3, amount 2);
+
+        let balances: HashMap<_, _> = state.current_balances(1).collect();
+        assert_eq!(
+            balances,
+            hash_map! {
+                (address(3), address(0)) => U256::from(1),
+                (address(3), address(1)) => U256::from(2),
+            }
+        );
+    }
+
     #[test]
     fn multiple_deposits_in_different_batches() {
         let mut state = state_with_fee();
@@ -620,6 +860,9 @@ mod tests {
         assert_balance!(in state at beginning of batch 2; user 4, has token 0, balance 23);
         assert_used_amount!(in state for batch 2; of order number 0, from user 2, is 1);
         assert_used_amount!(in state for batch 2; of order number 0, from user 3, is 2);
+        let balances: HashMap<_, _> = state.current_balances(1).collect();
+        assert_eq!(balances[&(address(2), address(0))], U256::from(12));
+        assert_eq!(balances[&(address(4), address(0))], U256::from(23));
 
         apply_event!(to state for batch 1; TradeReversion order number 0, from user 3, selling 2, for 1);
         apply_event!(to state for batch 1; TradeReversion order number 0, from user 2, selling 1, for 2);
@@ -634,6 +877,74 @@ mod tests {
         assert_used_amount!(in state for batch 2; of order number 0, from user 3, is 0);
     }
 
+    #[test]
+    fn verifies_solutions() {
+        let mut state = state_with_fee();
+        apply_event!(to state for batch 0; TokenListing token 1);
+        apply_event!(to state for batch 0; Deposit token 1, to user 2, amount 10);
+        apply_event!(to state for batch 0; Deposit token 0, to user 3, amount 6);
+        apply_event!(
+            to state for batch 0; OrderPlacement number 0, from user 2,
+            selling 5, of token 1, for at least 5, of token 0, for batch interval [0, 10]
+        );
+        apply_event!(
+            to state for batch 0; OrderPlacement number 0, from user 3,
+            selling 10, of token 0, for at least 3, of token 1, for batch interval [0, 10]
+        );
+
+        let solution = |trades: &[(u64, u128, u128)]| Solution {
+            prices: Default::default(),
+            executed_orders: trades
+                .iter()
+                .map(|&(user, sell_amount, buy_amount)| ExecutedOrder {
+                    account_id: address(user),
+                    order_id: 0,
+                    sell_amount,
+                    buy_amount,
+                })
+                .collect(),
+        };
+
+        assert!(state
+            .verify_solution(2, &solution(&[(2, 5, 5), (3, 5, 5)]))
+            .is_ok());
+        // Traded more than the order amount.
+        assert!(state.verify_solution(2, &solution(&[(2, 6, 6)])).is_err());
+        // Traded below the limit price.
+        assert!(state.verify_solution(2, &solution(&[(2, 5, 4)])).is_err());
+        // Overdraws the balance of the seller.
+        assert!(state.verify_solution(2, &solution(&[(3, 7, 7)])).is_err());
+        // Unknown order.
+        assert!(state.verify_solution(2, &solution(&[(4, 1, 1)])).is_err());
+        // Order expired before the solved batch.
+        assert!(state.verify_solution(20, &solution(&[(2, 5, 5)])).is_err());
+    }
+
+    #[test]
+    fn debug_json_roundtrip() {
+        let mut state = state_with_fee();
+        apply_event!(to state for batch 0; TokenListing token 1);
+        apply_event!(to state for batch 0; Deposit token 1, to user 2, amount 10);
+        apply_event!(
+            to state for batch 0; OrderPlacement number 0, from user 2,
+            selling 5, of token 1, for at least 5, of token 0, for batch interval [0, 10]
+        );
+        apply_event!(to state for batch 1; Trade order number 0, from user 2, selling 1, for 2);
+        apply_event!(to state for batch 1; SolutionSubmission from user 4, with fee 23);
+
+        let json = state.to_debug_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["orders"][0]["user"], json!(address(2)));
+        assert_eq!(value["tokens"].as_array().unwrap().len(), 2);
+
+        let restored = State::from_debug_json(&json).unwrap();
+        assert_eq!(restored.to_debug_json().unwrap(), json);
+        assert_balance!(in restored at beginning of batch 2; user 2, has token 0, balance 2);
+        assert_balance!(in restored at beginning of batch 2; user 2, has token 1, balance 9);
+        assert_balance!(in restored at beginning of batch 2; user 4, has token 0, balance 23);
+        assert_used_amount!(in restored for batch 2; of order number 0, from user 2, is 1);
+    }
+
     #[test]
     fn orderbook_batch_id() {
         let mut state = state_with_fee();
@@ -656,6 +967,51 @@ mod tests {
         assert_eq!(balance, U256::one());
     }
 
+    #[test]
+    fn canonicalized_auction_state_only_includes_sold_balances() {
+        let mut state = state_with_fee();
+        apply_event!(to state for batch 0; TokenListing token 1);
+        for token in 0..2 {
+            for user in 2..5 {
+                apply_event!(to state for batch 0; Deposit token token, to user user, amount 10);
+            }
+        }
+        apply_event!(
+            to state for batch 0; OrderPlacement number 0, from user 2,
+            selling 5, of token 1, for at least 5, of token 0, for batch interval [0, 10]
+        );
+        apply_event!(
+            to state for batch 0; OrderPlacement number 1, from user 2,
+            selling 5, of token 1, for at least 4, of token 0, for batch interval [0, 10]
+        );
+        apply_event!(
+            to state for batch 0; OrderPlacement number 0, from user 3,
+            selling 5, of token 0, for at least 5, of token 1, for batch interval [0, 0]
+        );
+
+        let (account_state, orders) = state
+            .canonicalized_auction_state_at_beginning_of_batch(2)
+            .unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(
+            account_state,
+            AccountState(
+                vec![((address(2), 1), U256::from(10))]
+                    .into_iter()
+                    .collect()
+            )
+        );
+
+        let (balances, orders) = state.orderbook_at_beginning_of_batch(2).unwrap();
+        let mut expected = crate::orderbook::util::canonicalize_auction_data(balances, orders);
+        let mut actual = state
+            .canonicalized_auction_state_at_beginning_of_batch(2)
+            .unwrap();
+        expected.1.sort_by_key(|order| (order.account_id, order.id));
+        actual.1.sort_by_key(|order| (order.account_id, order.id));
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn orderbook_partial_solution() {
         let mut state = state_with_fee();
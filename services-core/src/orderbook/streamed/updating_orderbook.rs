@@ -1,29 +1,65 @@
 use super::*;
 use crate::{
-    contracts::{stablex_contract::StableXContract, Web3},
-    history::events::EventRegistry,
-    models::{AccountState, BatchId, Order},
+    contracts::{
+        stablex_contract::{CachedStableXContract, ExchangeEvent, StableXContract},
+        Web3,
+    },
+    event_export::{EventExporter, EventSink, ExportedEvent, ExportedMessage, RetractedEvent},
+    history::{
+        events::{EventRegistry, EventSortKey},
+        Settlement,
+    },
+    metrics::StableXMetrics,
+    models::{batch_id::BatchTiming, AccountState, Order, Solution},
     orderbook::StableXOrderBookReading,
+    startup::StartupProgress,
+    transport::NewHeads,
 };
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, ensure, Result};
+use async_std::task::{self, JoinHandle};
 use block_timestamp_reading::{BlockTimestampReading, CachedBlockTimestampReader};
-use ethcontract::{errors::ExecutionError, BlockNumber, H256};
+use checkpoint::{Checkpoint, CheckpointFile, CheckpointSource};
+use contracts::batch_exchange;
+use ethcontract::{BlockNumber, H256};
 use futures::{
+    channel::mpsc,
     future::{BoxFuture, FutureExt as _},
     lock::Mutex,
     stream::{Stream, StreamExt as _},
+    SinkExt as _,
 };
 use log::{error, info, warn};
-use std::{collections::HashSet, convert::TryFrom, path::PathBuf, sync::Arc};
-
-type Event = ethcontract::contract::Event<contracts::batch_exchange::Event>;
+use rand::seq::IteratorRandom as _;
+use std::{
+    collections::{BTreeSet, HashSet},
+    convert::TryFrom,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 const BLOCK_CONFIRMATION_COUNT: u64 = 25;
+/// The number of event chunks after which the orderbook is written to disk
+/// while updating, so that a restart during a long sync can resume from there.
+const CHECKPOINT_CHUNK_COUNT: usize = 10;
+/// The default number of fetched event chunks that are buffered while they
+/// wait to be applied to the orderbook.
+const DEFAULT_EVENT_BUFFER_SIZE: usize = 4;
 
 /// An event based orderbook that automatically updates itself with new events from the contract.
 pub struct UpdatingOrderbook {
     contract: Arc<dyn StableXContract>,
+    /// The timing of the exchange's batches, which events are assigned to by
+    /// their block timestamp.
+    batch_timing: BatchTiming,
     web3: Web3,
+    /// The latest block pushed by the node if it is connected over a socket,
+    /// which avoids polling for the block number and updating when there is
+    /// no new block.
+    new_heads: Option<NewHeads>,
     block_page_size: usize,
     /// We need a mutex because otherwise the struct wouldn't be Sync which is needed because we use
     /// the orderbook in multiple threads. The mutex is locked in `get_auction_data_for_batch` while
@@ -32,11 +68,37 @@ pub struct UpdatingOrderbook {
     context: Mutex<Option<Context>>,
     /// File path where orderbook is written to disk.
     filestore: Option<PathBuf>,
+    /// Block from which events recovered from disk are discarded and fetched
+    /// again from the node.
+    reindex_from_block: Option<u64>,
+    startup_progress: Option<Arc<StartupProgress>>,
+    /// The number of fetched event chunks buffered before fetching waits for
+    /// them to be applied.
+    event_buffer_size: usize,
+    metrics: Option<Arc<StableXMetrics>>,
+    event_exporter: Option<EventExporter>,
+    /// Peer from which the orderbook is bootstrapped when it could not be
+    /// recovered from disk.
+    checkpoint_source: Option<CheckpointSource>,
+    /// Set when a checkpoint did not match the exchange contract, in which
+    /// case the orderbook is built from the genesis events instead.
+    checkpoint_rejected: AtomicBool,
+    /// The orderbook file served as a checkpoint to other instances, through
+    /// which the orderbook is written instead of to `filestore`.
+    checkpoint_file: Option<Arc<CheckpointFile>>,
+    /// Contract whose cached number of tokens is invalidated when a token is
+    /// listed.
+    cached_contract: Option<Arc<CachedStableXContract>>,
 }
 
 struct Context {
     orderbook: EventRegistry,
     last_handled_block: u64,
+    /// The hash of the head block of the last update, if known.
+    last_head: Option<H256>,
+    /// Events deleted to be fetched again, which are retracted from the event
+    /// export if they are not part of the chain anymore.
+    deleted_events: BTreeSet<EventSortKey>,
     block_timestamp_reader: CachedBlockTimestampReader<Web3>,
 }
 
@@ -48,23 +110,91 @@ impl UpdatingOrderbook {
         web3: Web3,
         block_page_size: usize,
         path: Option<PathBuf>,
+        reindex_from_block: Option<u64>,
     ) -> Self {
+        let new_heads = web3.transport().new_heads();
         Self {
+            batch_timing: BatchTiming::with_batch_duration(contract.batch_duration()),
             contract,
             web3,
+            new_heads,
             block_page_size,
             context: Mutex::new(None),
             filestore: path,
+            reindex_from_block,
+            startup_progress: None,
+            event_buffer_size: DEFAULT_EVENT_BUFFER_SIZE,
+            metrics: None,
+            event_exporter: None,
+            checkpoint_source: None,
+            checkpoint_rejected: AtomicBool::new(false),
+            checkpoint_file: None,
+            cached_contract: None,
         }
     }
 
+    /// Limits the number of fetched event chunks that are kept in memory
+    /// while they wait to be applied, which bounds the memory used during long
+    /// syncs.
+    pub fn with_event_buffer_size(mut self, event_buffer_size: usize) -> Self {
+        self.event_buffer_size = event_buffer_size.max(1);
+        self
+    }
+
+    /// Reports the number of buffered events.
+    pub fn with_metrics(mut self, metrics: Arc<StableXMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Reports the number of fetched events and remaining blocks while the
+    /// orderbook is initialized.
+    pub fn with_startup_progress(mut self, startup_progress: Arc<StartupProgress>) -> Self {
+        self.startup_progress = Some(startup_progress);
+        self
+    }
+
+    /// Publishes every handled event to the sink. Events of recent blocks are
+    /// published again on every update because they are fetched again in case
+    /// of reorgs, and retracted if a reorg removed them.
+    pub fn with_event_sink(mut self, event_sink: Arc<dyn EventSink>) -> Self {
+        self.event_exporter = Some(EventExporter::new(event_sink));
+        self
+    }
+
+    /// Bootstraps the orderbook from a checkpoint of a trusted peer when it
+    /// could not be recovered from disk, instead of fetching all events since
+    /// the deployment of the contract.
+    pub fn with_checkpoint_source(mut self, checkpoint_source: CheckpointSource) -> Self {
+        self.checkpoint_source = Some(checkpoint_source);
+        self
+    }
+
+    /// Writes the orderbook through the checkpoint file, which computes the
+    /// metadata of the checkpoint served to other instances on every write.
+    pub fn with_checkpoint_file(mut self, checkpoint_file: Arc<CheckpointFile>) -> Self {
+        self.checkpoint_file = Some(checkpoint_file);
+        self
+    }
+
+    /// Invalidates the cached number of tokens of the contract whenever a
+    /// token listing event is handled.
+    pub fn with_cached_contract(mut self, cached_contract: Arc<CachedStableXContract>) -> Self {
+        self.cached_contract = Some(cached_contract);
+        self
+    }
+
     /// Recover the orderbook from file if possible.
     fn load_orderbook_from_file(&self, context: &mut Context) {
         // TODO: use async file io
         match &self.filestore {
             Some(path) => match EventRegistry::try_from(path.as_path()) {
-                Ok(orderbook) => {
+                Ok(mut orderbook) => {
                     info!("successfully recovered orderbook from path");
+                    if let Some(block) = self.reindex_from_block {
+                        info!("reindexing orderbook events starting at block {}", block);
+                        orderbook.delete_events_starting_at_block(block);
+                    }
                     context.last_handled_block = orderbook.last_handled_block().unwrap_or(0);
                     context.orderbook = orderbook;
                 }
@@ -84,6 +214,78 @@ impl UpdatingOrderbook {
         };
     }
 
+    /// Imports the checkpoint of the peer if one is configured and the
+    /// orderbook is empty, once it is verified against the exchange contract.
+    /// Failing to import is not an error as the orderbook can always be built
+    /// from the node.
+    async fn import_checkpoint(&self, context: &mut Context) {
+        let checkpoint_source = match &self.checkpoint_source {
+            Some(checkpoint_source) => checkpoint_source,
+            None => return,
+        };
+        if context.orderbook.last_handled_block().is_some()
+            || self.checkpoint_rejected.load(Ordering::SeqCst)
+        {
+            return;
+        }
+        let checkpoint = match checkpoint_source.fetch().await {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                warn!("failed to import orderbook checkpoint: {:?}", err);
+                return;
+            }
+        };
+        if let Err(err) = self.verify_checkpoint(context, &checkpoint).await {
+            warn!(
+                "failed to verify orderbook checkpoint at block {}: {:?}",
+                checkpoint.metadata.block, err
+            );
+            return;
+        }
+        info!(
+            "imported orderbook checkpoint at block {}",
+            checkpoint.metadata.block
+        );
+        context.last_handled_block = checkpoint.metadata.block;
+        context.orderbook = checkpoint.registry;
+    }
+
+    /// Verifies a checkpoint against the orders and balances that the exchange
+    /// contract reports at the block of the checkpoint. A checkpoint that does
+    /// not match is rejected, so that the orderbook is built from the genesis
+    /// events instead of importing it again.
+    async fn verify_checkpoint(
+        &self,
+        context: &mut Context,
+        checkpoint: &Checkpoint,
+    ) -> Result<()> {
+        let block = checkpoint.metadata.block;
+        let state = checkpoint.registry.state_at_block(block)?;
+        let state_root = checkpoint::state_root(&state)?;
+        let timestamp = context
+            .block_timestamp_reader
+            .block_timestamp(BlockNumber::Number(block.into()).into())
+            .await?;
+        let batch = self.batch_timing.batch_at_timestamp(timestamp);
+        let elements = checkpoint::read_onchain_orders(self.contract.as_ref(), block).await?;
+
+        let verification = if state_root == checkpoint.metadata.state_root {
+            checkpoint::verify_onchain_orders(&state, batch.into(), &elements)
+        } else {
+            Err(anyhow!(
+                "checkpoint has state root {:?} but its events have state root {:?}",
+                checkpoint.metadata.state_root,
+                state_root,
+            ))
+        };
+        if verification.is_err() {
+            self.checkpoint_rejected.store(true, Ordering::SeqCst);
+        }
+        verification?;
+        info!("verified orderbook checkpoint at block {}", block);
+        Ok(())
+    }
+
     /// Use the context, ensuring that the orderbook has been initialized and updated.
     async fn do_with_context<T, F>(&self, callback: F) -> Result<T>
     where
@@ -99,12 +301,15 @@ impl UpdatingOrderbook {
                 let mut context = Context {
                     orderbook: EventRegistry::default(),
                     last_handled_block: 0,
+                    last_head: None,
+                    deleted_events: BTreeSet::new(),
                     block_timestamp_reader: CachedBlockTimestampReader::new(
                         self.web3.clone(),
                         BLOCK_CONFIRMATION_COUNT,
                     ),
                 };
                 self.load_orderbook_from_file(&mut context);
+                self.import_checkpoint(&mut context).await;
                 self.update(&mut context).await?;
                 let result = callback(&mut context).await;
                 *context_guard = Some(context);
@@ -117,7 +322,12 @@ impl UpdatingOrderbook {
     async fn update(&self, context: &mut Context) -> Result<()> {
         // We cannot use BlockNumber::Pending here because we are not guaranteed to get metadata for
         // pending blocks but we need the metadata in the functions below.
-        let current_block = self.web3.eth().block_number().await?.as_u64();
+        let head = self.new_heads.as_ref().and_then(NewHeads::latest);
+        let current_block = match head {
+            Some(head) if context.last_head == Some(head.hash) => return Ok(()),
+            Some(head) => head.number.as_u64(),
+            None => self.web3.eth().block_number().await?.as_u64(),
+        };
         let from_block = context
             .last_handled_block
             .saturating_sub(BLOCK_CONFIRMATION_COUNT);
@@ -135,7 +345,9 @@ impl UpdatingOrderbook {
             current_block,
         );
         self.update_with_events_between_blocks(context, from_block, current_block)
-            .await
+            .await?;
+        context.last_head = head.map(|head| head.hash);
+        Ok(())
     }
 
     async fn update_with_events_between_blocks(
@@ -145,50 +357,131 @@ impl UpdatingOrderbook {
         to_block: u64,
     ) -> Result<()> {
         let mut events = self.chunked_events(from_block, to_block).await?;
-        context
+        let deleted_events = context
             .orderbook
             .delete_events_starting_at_block(from_block);
-        while let Some(chunk) = events.next().await {
-            let events = chunk?;
-            self.prepare_timestamp_cache(context, &events, to_block)
-                .await?;
-            for event in events {
-                self.handle_event(context, event).await?;
-            }
-            context.last_handled_block = to_block;
+        if self.event_exporter.is_some() {
+            context.deleted_events.extend(deleted_events);
         }
 
-        // Update the orderbook on disk before exit.
-        if let Some(filestore) = &self.filestore {
-            if let Err(write_error) = context.orderbook.write_to_file(filestore) {
-                error!("Failed to write to orderbook {}", write_error);
+        // Events are fetched ahead while earlier chunks are applied. The bounded
+        // channel makes fetching wait once the buffer is full. Note that the
+        // channel buffers one chunk per sender in addition to its capacity.
+        let (mut sender, receiver) = mpsc::channel(self.event_buffer_size - 1);
+        let buffered_events = &AtomicUsize::new(0);
+        let fetch = async move {
+            while let Some(chunk) = events.next().await {
+                if let Ok(events) = &chunk {
+                    buffered_events.fetch_add(events.len(), Ordering::SeqCst);
+                    self.report_buffered_events(buffered_events);
+                }
+                // Sending only fails once applying stopped because of an error.
+                if sender.send(chunk).await.is_err() {
+                    break;
+                }
             }
-        }
+        };
+        let apply = async {
+            let mut receiver = receiver;
+            let mut chunk_count = 0;
+            while let Some(chunk) = receiver.next().await {
+                let events = chunk?;
+                buffered_events.fetch_sub(events.len(), Ordering::SeqCst);
+                self.report_buffered_events(buffered_events);
+                if let (Some(startup_progress), Some(last_event)) =
+                    (&self.startup_progress, events.last())
+                {
+                    startup_progress.events_fetched(
+                        events.len(),
+                        to_block.saturating_sub(last_event.block_number),
+                    );
+                }
+                self.prepare_timestamp_cache(context, &events, to_block)
+                    .await?;
+                for event in events {
+                    self.handle_event(context, event).await?;
+                }
+                context.last_handled_block = to_block;
+
+                chunk_count += 1;
+                if chunk_count % CHECKPOINT_CHUNK_COUNT == 0 {
+                    self.write_to_filestore(context);
+                }
+            }
+            Ok(())
+        };
+        // Applying drops the receiver when it fails, which stops fetching.
+        let ((), result): ((), Result<()>) = futures::join!(fetch, apply);
+        result?;
+        self.retract_removed_events(context);
+
+        // Update the orderbook on disk before exit.
+        self.write_to_filestore(context);
 
         Ok(())
     }
 
-    /// Apply a single event to the orderbook.
-    async fn handle_event(&self, context: &mut Context, event: Event) -> Result<()> {
-        match event {
-            Event {
-                data,
-                meta: Some(meta),
-            } => {
-                let block_timestamp = context
-                    .block_timestamp_reader
-                    .block_timestamp(meta.block_hash.into())
-                    .await?;
-                context.orderbook.handle_event_data(
-                    data,
-                    meta.block_number,
-                    meta.log_index,
-                    meta.block_hash,
-                    block_timestamp,
-                );
+    fn report_buffered_events(&self, buffered_events: &AtomicUsize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.orderbook_events_buffered(buffered_events.load(Ordering::SeqCst));
+        }
+    }
+
+    /// Writes the orderbook to disk if a file path was specified. Since the
+    /// last handled block is recovered from the stored events, this also acts
+    /// as a checkpoint from which updating is resumed after a restart.
+    fn write_to_filestore(&self, context: &Context) {
+        let result = match (&self.checkpoint_file, &self.filestore) {
+            (Some(checkpoint_file), _) => checkpoint_file.write(&context.orderbook),
+            (None, Some(filestore)) => context.orderbook.write_to_file(filestore),
+            (None, None) => return,
+        };
+        if let Err(write_error) = result {
+            error!("Failed to write to orderbook {}", write_error);
+        }
+    }
+
+    /// Retracts the exported events that were deleted and not fetched again
+    /// because their block is no longer part of the chain.
+    fn retract_removed_events(&self, context: &mut Context) {
+        let deleted_events = std::mem::take(&mut context.deleted_events);
+        if let Some(event_exporter) = &self.event_exporter {
+            for key in deleted_events {
+                if !context.orderbook.contains_event(&key) {
+                    event_exporter.export(ExportedMessage::Retraction(RetractedEvent::new(
+                        key.block_number,
+                        key.block_hash,
+                        key.log_index,
+                    )));
+                }
             }
-            Event { meta: None, .. } => bail!("event without metadata"),
         }
+    }
+
+    /// Apply a single event to the orderbook.
+    async fn handle_event(&self, context: &mut Context, event: ExchangeEvent) -> Result<()> {
+        let block_timestamp = context
+            .block_timestamp_reader
+            .block_timestamp(event.block_hash.into())
+            .await?;
+        if let Some(event_exporter) = &self.event_exporter {
+            event_exporter.export(ExportedMessage::Event(ExportedEvent::new(
+                &event,
+                block_timestamp,
+            )));
+        }
+        if let (Some(cached_contract), batch_exchange::Event::TokenListing(_)) =
+            (&self.cached_contract, &event.data)
+        {
+            cached_contract.invalidate();
+        }
+        context.orderbook.handle_event_data(
+            event.data,
+            event.block_number,
+            event.log_index,
+            event.block_hash,
+            self.batch_timing.batch_at_timestamp(block_timestamp),
+        );
         Ok(())
     }
 
@@ -196,14 +489,10 @@ impl UpdatingOrderbook {
         &self,
         from_block: u64,
         to_block: u64,
-    ) -> Result<impl Stream<Item = Result<Vec<Event>, ExecutionError>> + '_> {
+    ) -> Result<impl Stream<Item = Result<Vec<ExchangeEvent>>> + '_> {
         let event_stream = self
             .contract
-            .past_events(
-                BlockNumber::Number(from_block.into()),
-                BlockNumber::Number(to_block.into()),
-                self.block_page_size as _,
-            )
+            .past_events(from_block, to_block, self.block_page_size as _)
             .await?;
         let event_chunks = event_stream
             .ready_chunks(self.block_page_size)
@@ -211,23 +500,105 @@ impl UpdatingOrderbook {
         Ok(event_chunks)
     }
 
+    /// Compares a random sample of balances from the orderbook to the balances
+    /// stored in the exchange contract at the same block and returns the
+    /// number of mismatches. Balances are compared at a confirmed block so
+    /// that reorgs do not cause spurious mismatches.
+    pub async fn reconcile_balances(
+        &self,
+        sample_size: usize,
+        metrics: &StableXMetrics,
+    ) -> Result<usize> {
+        let batch_timing = self.batch_timing;
+        let (block, sample) = self
+            .do_with_context(move |context| {
+                async move {
+                    let block = context
+                        .last_handled_block
+                        .saturating_sub(BLOCK_CONFIRMATION_COUNT);
+                    let timestamp = context
+                        .block_timestamp_reader
+                        .block_timestamp(BlockNumber::Number(block.into()).into())
+                        .await?;
+                    let batch = batch_timing.batch_at_timestamp(timestamp);
+                    let state = context.orderbook.state_at_block(block)?;
+                    let sample = state
+                        .current_balances(batch.into())
+                        .choose_multiple(&mut rand::thread_rng(), sample_size);
+                    Ok((block, sample))
+                }
+                .boxed()
+            })
+            .await?;
+
+        let mut mismatches = 0;
+        for ((user, token), balance) in sample {
+            let onchain_balance = self
+                .contract
+                .get_balance(user, token, Some(block.into()))
+                .await?;
+            let matches = balance == onchain_balance;
+            if !matches {
+                warn!(
+                    "orderbook balance {} of user {:?} for token {:?} does not match on-chain \
+                     balance {} at block {}",
+                    balance, user, token, onchain_balance, block,
+                );
+                mismatches += 1;
+            }
+            metrics.orderbook_balance_checked(matches);
+        }
+        Ok(mismatches)
+    }
+
+    /// Spawns a background task that reconciles a sample of balances every
+    /// `interval`.
+    pub fn start_balance_reconciliation(
+        self: Arc<Self>,
+        interval: Duration,
+        sample_size: usize,
+        metrics: Arc<StableXMetrics>,
+    ) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                task::sleep(interval).await;
+                match self.reconcile_balances(sample_size, &metrics).await {
+                    Ok(0) => {}
+                    Ok(mismatches) => error!(
+                        "{} sampled orderbook balances do not match the on-chain state",
+                        mismatches,
+                    ),
+                    Err(err) => warn!("failed to reconcile orderbook balances: {:?}", err),
+                }
+            }
+        })
+    }
+
+    /// Spawns a background task that fetches new events every `interval` so
+    /// that an orderbook which is rarely read, like a fallback orderbook, does
+    /// not have to catch up on many blocks when it is needed.
+    pub fn start_updating(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                task::sleep(interval).await;
+                if let Err(err) = self.do_with_context(|_| immediate!(Ok(()))).await {
+                    warn!("failed to update orderbook: {:?}", err);
+                }
+            }
+        })
+    }
+
     // Retrieve the needed timestamps using a batch transport.
     async fn prepare_timestamp_cache(
         &self,
         context: &mut Context,
-        events: &[Event],
+        events: &[ExchangeEvent],
         latest_block: u64,
     ) -> Result<()> {
         let block_hashes = events
             .iter()
-            .map(|event| {
-                let metadata = event
-                    .meta
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("event without metadata: {:?}", event))?;
-                Ok(metadata.block_hash)
-            })
-            .collect::<Result<HashSet<H256>>>()?;
+            .map(|event| event.block_hash)
+            .collect::<HashSet<H256>>();
         context
             .block_timestamp_reader
             .prepare_cache(block_hashes, self.block_page_size, latest_block)
@@ -252,6 +623,7 @@ impl StableXOrderBookReading for UpdatingOrderbook {
         &self,
         block: BlockNumber,
     ) -> Result<(AccountState, Vec<Order>)> {
+        let batch_timing = self.batch_timing;
         self.do_with_context(move |context| {
             async move {
                 // NOTE: Get the exact timestamp for the block, this will give more
@@ -260,7 +632,7 @@ impl StableXOrderBookReading for UpdatingOrderbook {
                     .block_timestamp_reader
                     .block_timestamp(block.into())
                     .await?;
-                let batch = BatchId::from_timestamp(timestamp);
+                let batch = batch_timing.batch_at_timestamp(timestamp);
 
                 context
                     .orderbook
@@ -274,4 +646,20 @@ impl StableXOrderBookReading for UpdatingOrderbook {
     async fn initialize(&self) -> Result<()> {
         self.do_with_context(|_| immediate!(Ok(()))).await
     }
+
+    async fn verify_solution(&self, batch_id_to_solve: u32, solution: &Solution) -> Result<()> {
+        self.do_with_context(move |context| {
+            immediate!(context
+                .orderbook
+                .verify_solution(batch_id_to_solve, solution))
+        })
+        .await
+    }
+
+    async fn settlement_for_batch(&self, batch_id: u32) -> Result<Option<Settlement>> {
+        self.do_with_context(move |context| {
+            immediate!(Ok(context.orderbook.settlement_for_batch(batch_id)))
+        })
+        .await
+    }
 }
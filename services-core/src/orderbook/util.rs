@@ -1,5 +1,6 @@
 use crate::models::{AccountState, Order};
 use ethcontract::{Address, U256};
+use std::collections::HashSet;
 
 /// Creates a canonical representation of an auction state by removing empty
 /// orders and token balances for which there is not at least one sell order by
@@ -12,14 +13,13 @@ pub fn canonicalize_auction_data(
         .into_iter()
         .filter(|order| order.remaining_sell_amount > 0)
         .collect::<Vec<_>>();
+    let sold_balances = orders
+        .iter()
+        .map(|order| (order.account_id, order.sell_token))
+        .collect::<HashSet<_>>();
     let account_states = account_states
         .into_iter()
-        .filter(|((user, token), _)| {
-            orders
-                .iter()
-                .any(|order| order.account_id == *user && order.sell_token == *token)
-        })
-        .map(|(key, value)| (key, value))
+        .filter(|(key, _)| sold_balances.contains(key))
         .collect();
     (AccountState(account_states), orders)
 }
@@ -6,23 +6,27 @@ mod clients;
 mod orderbook_based;
 pub mod price_source;
 mod priority_price_source;
+pub mod restricted_price_source;
 mod threaded_price_source;
 
-use self::clients::{DexagClient, KrakenClient, OneinchClient};
+use self::clients::{
+    DexagClient, KrakenClient, KrakenTickerStream, OneinchClient, KRAKEN_WEBSOCKET_URL,
+};
 use self::orderbook_based::PricegraphEstimator;
-use crate::contracts::stablex_contract::StableXContractImpl;
+use crate::contracts::stablex_contract::{CachedStableXContract, StableXContract};
 use crate::token_info::{cached::TokenInfoCache, hardcoded::TokenData, TokenInfoFetching};
 use crate::{
     economic_viability::NativeTokenPricing,
     http::HttpFactory,
-    models::{Order, TokenId, TokenInfo},
+    models::{batch_id::BatchTiming, Order, TokenId, TokenInfo},
     orderbook::StableXOrderBookReading,
 };
 use anyhow::Result;
 use average_price_source::AveragePriceSource;
 use log::warn;
-use price_source::PriceSource;
+use price_source::{PriceSource, PriceSourceKind};
 use priority_price_source::PriorityPriceSource;
+use restricted_price_source::RestrictedPriceSource;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::iter;
 use std::iter::FromIterator;
@@ -47,6 +51,11 @@ pub trait PriceEstimating {
     /// the solver, so the amount of OWL in atoms to purchase 1e18 of the
     /// corresponding token.
     async fn get_token_prices(&self, orders: &[Order]) -> Tokens;
+
+    /// Retrieves price estimates for the specified tokens only from sources
+    /// that do not depend on the orderbook, so that orders with extreme limit
+    /// prices cannot move them. Tokens without such an estimate are omitted.
+    async fn get_external_prices(&self, tokens: &[TokenId]) -> HashMap<TokenId, NonZeroU128>;
 }
 
 pub struct PriceOracle {
@@ -55,41 +64,75 @@ pub struct PriceOracle {
     token_info_fetcher: Arc<dyn TokenInfoFetching>,
     /// The price source to use.
     source: Box<dyn PriceSource + Send + Sync>,
+    /// The price sources that do not depend on the orderbook.
+    external_source: Box<dyn PriceSource + Send + Sync>,
     /// The id of the token in which network transactions fees are paid
     native_token: TokenId,
 }
 
 impl PriceOracle {
-    /// Creates a new price oracle from a token whitelist data.
+    /// Creates a new price oracle from a token whitelist data. The token data
+    /// also restricts which price sources are used for each token.
     pub fn new(
         http_factory: &HttpFactory,
         orderbook_reader: Arc<dyn StableXOrderBookReading>,
-        contract: Arc<StableXContractImpl>,
+        contract: Arc<CachedStableXContract>,
         token_data: TokenData,
         update_interval: Duration,
         native_token: TokenId,
         use_external_price_source: bool,
+        use_kraken_websocket: bool,
     ) -> Result<Self> {
+        let batch_timing = BatchTiming::with_batch_duration(contract.batch_duration());
         let cache: HashMap<_, _> = token_data.clone().into();
         let token_info_fetcher = Arc::new(TokenInfoCache::with_cache(contract, cache));
-        let mut price_sources: Vec<Box<dyn PriceSource + Send + Sync>> =
-            vec![Box::new(PricegraphEstimator::new(orderbook_reader))];
-        if use_external_price_source {
-            price_sources.extend(external_price_sources(
+        let token_data = Arc::new(token_data);
+        // The external sources are shared with the orderbook independent
+        // source so that they are only updated once.
+        let external_sources: Vec<Arc<dyn PriceSource + Send + Sync>> = if use_external_price_source
+        {
+            external_price_sources(
                 http_factory,
                 token_info_fetcher.clone(),
+                token_data.clone(),
                 update_interval,
-            )?);
-        }
+                use_kraken_websocket,
+            )?
+            .into_iter()
+            .map(Arc::from)
+            .collect()
+        } else {
+            Vec::new()
+        };
+        let shared = |sources: &[Arc<dyn PriceSource + Send + Sync>]| {
+            sources
+                .iter()
+                .map(|source| -> Box<dyn PriceSource + Send + Sync> { Box::new(source.clone()) })
+                .collect::<Vec<_>>()
+        };
+
+        let mut price_sources = vec![restrict(
+            PriceSourceKind::Pricegraph,
+            &token_data,
+            Box::new(PricegraphEstimator::new(orderbook_reader).with_batch_timing(batch_timing)),
+        )];
+        price_sources.extend(shared(&external_sources));
         let averaged_source = Box::new(AveragePriceSource::new(price_sources));
         let prioritized_source = Box::new(PriorityPriceSource::new(vec![
-            Box::new(token_data),
+            Box::new(TokenData::clone(&token_data)),
             averaged_source,
         ]));
 
+        let mut external_source: Vec<Box<dyn PriceSource + Send + Sync>> =
+            vec![Box::new(TokenData::clone(&token_data))];
+        if !external_sources.is_empty() {
+            external_source.push(Box::new(AveragePriceSource::new(shared(&external_sources))));
+        }
+
         Ok(PriceOracle {
             token_info_fetcher,
             source: prioritized_source,
+            external_source: Box::new(PriorityPriceSource::new(external_source)),
             native_token,
         })
     }
@@ -102,23 +145,14 @@ impl PriceOracle {
         PriceOracle {
             token_info_fetcher,
             source: Box::new(source),
+            external_source: Box::new(price_source::NoopPriceSource),
             native_token: TokenId(1),
         }
     }
 
     /// Gets price estimates for some tokens
     async fn get_prices(&self, tokens: &[TokenId]) -> HashMap<TokenId, NonZeroU128> {
-        if tokens.is_empty() {
-            return HashMap::new();
-        }
-
-        match self.source.get_prices(tokens).await {
-            Ok(prices) => prices,
-            Err(err) => {
-                warn!("failed to retrieve token prices: {}", err);
-                HashMap::new()
-            }
-        }
+        get_prices_or_default(self.source.as_ref(), tokens).await
     }
 }
 
@@ -162,6 +196,28 @@ impl PriceEstimating for PriceOracle {
         }
         tokens
     }
+
+    async fn get_external_prices(&self, tokens: &[TokenId]) -> HashMap<TokenId, NonZeroU128> {
+        get_prices_or_default(self.external_source.as_ref(), tokens).await
+    }
+}
+
+/// Gets price estimates for some tokens from a source, logging failures.
+async fn get_prices_or_default(
+    source: &(dyn PriceSource + Send + Sync),
+    tokens: &[TokenId],
+) -> HashMap<TokenId, NonZeroU128> {
+    if tokens.is_empty() {
+        return HashMap::new();
+    }
+
+    match source.get_prices(tokens).await {
+        Ok(prices) => prices,
+        Err(err) => {
+            warn!("failed to retrieve token prices: {}", err);
+            HashMap::new()
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -173,18 +229,44 @@ impl NativeTokenPricing for PriceOracle {
 }
 
 /// Create the external price sources used by PriceOracle.
+///
+/// With `use_kraken_websocket` Kraken prices are kept up to date through a
+/// WebSocket ticker subscription, falling back to the periodically updated
+/// REST prices for asset pairs without a recent ticker.
+///
+/// Each source is only used for the tokens that `token_data` allows it for.
 pub fn external_price_sources(
     http_factory: &HttpFactory,
     token_info_fetcher: Arc<dyn TokenInfoFetching>,
+    token_data: Arc<TokenData>,
     update_interval: Duration,
+    use_kraken_websocket: bool,
 ) -> Result<Vec<Box<dyn PriceSource + Send + Sync>>> {
     let kraken = KrakenClient::new(http_factory, token_info_fetcher.clone())?;
+    let kraken: Box<dyn PriceSource + Send + Sync> = if use_kraken_websocket {
+        let ticker_stream = KrakenTickerStream::connect(KRAKEN_WEBSOCKET_URL);
+        let kraken = kraken.with_ticker_stream(ticker_stream.clone());
+        Box::new(PriorityPriceSource::new(vec![
+            Box::new(ticker_stream),
+            thread_and_box(kraken, token_info_fetcher.clone(), update_interval),
+        ]))
+    } else {
+        thread_and_box(kraken, token_info_fetcher.clone(), update_interval)
+    };
     let dexag = DexagClient::new(http_factory, token_info_fetcher.clone())?;
     let oneinch = OneinchClient::new(http_factory, token_info_fetcher.clone())?;
     Ok(vec![
-        thread_and_box(kraken, token_info_fetcher.clone(), update_interval),
-        thread_and_box(dexag, token_info_fetcher.clone(), update_interval),
-        thread_and_box(oneinch, token_info_fetcher, update_interval),
+        restrict(PriceSourceKind::Kraken, &token_data, kraken),
+        restrict(
+            PriceSourceKind::Dexag,
+            &token_data,
+            thread_and_box(dexag, token_info_fetcher.clone(), update_interval),
+        ),
+        restrict(
+            PriceSourceKind::Oneinch,
+            &token_data,
+            thread_and_box(oneinch, token_info_fetcher, update_interval),
+        ),
     ])
 }
 
@@ -196,6 +278,18 @@ fn thread_and_box(
     Box::new(ThreadedPriceSource::new(token_info_fetcher, price_source, update_interval).0)
 }
 
+fn restrict(
+    kind: PriceSourceKind,
+    token_data: &Arc<TokenData>,
+    price_source: Box<dyn PriceSource + Send + Sync>,
+) -> Box<dyn PriceSource + Send + Sync> {
+    Box::new(RestrictedPriceSource::new(
+        kind,
+        token_data.clone(),
+        price_source,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
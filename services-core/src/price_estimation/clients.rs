@@ -4,5 +4,5 @@ mod kraken;
 mod oneinch;
 
 pub use dexag::DexagClient;
-pub use kraken::KrakenClient;
+pub use kraken::{KrakenClient, KrakenTickerStream, DEFAULT_WEBSOCKET_URL as KRAKEN_WEBSOCKET_URL};
 pub use oneinch::OneinchClient;
@@ -1,11 +1,10 @@
 use super::super::generic_client::{Api, GenericToken};
-use crate::http::{HttpClient, HttpFactory, HttpLabel};
+use crate::http::{HttpClient, HttpFactory, HttpLabel, HttpService};
 use anyhow::{Context, Result};
 use ethcontract::Address;
 use isahc::prelude::Configurable;
 use serde::Deserialize;
 use serde_with::rust::display_fromstr;
-use std::time::Duration;
 use url::Url;
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -34,7 +33,7 @@ pub struct DexagHttpApi {
 impl DexagHttpApi {
     pub fn with_url(http_factory: &HttpFactory, base_url: &str) -> Result<Self> {
         let client = http_factory
-            .with_config(|builder| builder.timeout(Duration::from_secs(30)))
+            .create_for(HttpService::PriceApi)
             .context("failed to initialize HTTP client")?;
         let base_url = base_url
             .parse()
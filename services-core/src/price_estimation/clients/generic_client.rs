@@ -4,12 +4,22 @@ use crate::models::TokenId;
 use crate::token_info::{TokenBaseInfo, TokenInfoFetching};
 use anyhow::{anyhow, Context, Result};
 use futures::{
-    future::{self, BoxFuture},
+    future::BoxFuture,
     lock::Mutex,
+    stream::{self, StreamExt as _},
 };
 use std::num::NonZeroU128;
+use std::time::{Duration, Instant};
 use std::{any, collections::HashMap, sync::Arc};
 
+/// The interval after which the token list of an API is fetched again so that
+/// newly listed tokens get priced without a restart.
+const TOKEN_LIST_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The maximum number of concurrent price requests to an API. Requesting the
+/// prices of all tokens at once runs into rate limits and request timeouts.
+const MAX_CONCURRENT_PRICE_REQUESTS: usize = 8;
+
 /// Provides a generic interface to communicate in a standardized way
 /// with specific API token implementations
 pub trait GenericToken {
@@ -42,13 +52,16 @@ struct Tokens<T: Api> {
     // This is cached in the struct because we don't expect it to change often.
     tokens: HashMap<String, T::Token>,
     stable_coin: T::Token,
+    updated: Instant,
 }
 
 pub struct GenericClient<T: Api> {
     api: T,
     /// Lazily retrieved the first time it is needed when `get_prices` is
-    /// called. We don't want to use the network in `new`.
+    /// called. We don't want to use the network in `new`. Refreshed when it
+    /// is older than the refresh interval.
     api_tokens: Mutex<Option<Tokens<T>>>,
+    token_list_refresh_interval: Duration,
     token_info_fetcher: Arc<dyn TokenInfoFetching>,
 }
 
@@ -66,6 +79,7 @@ impl<T: Api> GenericClient<T> {
         Self {
             api,
             api_tokens: Mutex::new(None),
+            token_list_refresh_interval: TOKEN_LIST_REFRESH_INTERVAL,
             token_info_fetcher,
         }
     }
@@ -85,6 +99,7 @@ impl<T: Api> GenericClient<T> {
         Ok(Tokens {
             tokens,
             stable_coin,
+            updated: Instant::now(),
         })
     }
 }
@@ -102,16 +117,28 @@ where
         }
 
         let mut api_tokens_guard = self.api_tokens.lock().await;
-        let api_tokens: &Tokens<T> = match api_tokens_guard.as_ref() {
-            Some(api_tokens) => api_tokens,
-            None => {
-                let initialized = self
-                    .create_api_tokens()
-                    .await
-                    .with_context(|| anyhow!("failed to perform lazy initialization"))?;
-                api_tokens_guard.get_or_insert(initialized)
-            }
+        let needs_refresh = match api_tokens_guard.as_ref() {
+            Some(api_tokens) => api_tokens.updated.elapsed() >= self.token_list_refresh_interval,
+            None => true,
         };
+        if needs_refresh {
+            match self.create_api_tokens().await {
+                Ok(api_tokens) => *api_tokens_guard = Some(api_tokens),
+                // Keep using the previous token list if there is one.
+                Err(err) if api_tokens_guard.is_some() => log::warn!(
+                    "failed to refresh {} token list: {:?}",
+                    any::type_name::<T>(),
+                    err,
+                ),
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| anyhow!("failed to perform lazy initialization"))
+                }
+            }
+        }
+        let api_tokens: &Tokens<T> = api_tokens_guard
+            .as_ref()
+            .expect("token list initialized above");
 
         let token_infos = self.token_info_fetcher.get_token_infos(tokens).await?;
         let (tokens_, futures): (Vec<TokenIdAndInfo>, Vec<_>) = token_infos
@@ -134,8 +161,10 @@ where
             )
             .unzip();
 
-        let joined = future::join_all(futures);
-        let results = joined.await;
+        let results: Vec<_> = stream::iter(futures)
+            .buffered(MAX_CONCURRENT_PRICE_REQUESTS)
+            .collect()
+            .await;
         assert_eq!(tokens_.len(), results.len());
 
         Ok(tokens_
@@ -267,6 +296,44 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn refreshes_token_list() {
+        initialize_mockapi_context();
+        let tokens = hash_map! {
+            TokenId::from(1) => TokenInfoOverride::new(Address::from_low_u64_be(0), "ETH", 18, None)
+        };
+        let mut api = MockApi::new();
+        let mut seq = Sequence::new();
+
+        api.expect_get_token_list()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| Ok(vec!["DAI".into()]));
+        api.expect_get_token_list()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| Err(anyhow!("")));
+        api.expect_get_token_list()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| Ok(vec!["DAI".into(), "ETH".into()]));
+        api.expect_get_price().returning(|_, _| Ok(1.0));
+
+        let mut client =
+            GenericClient::<MockApi>::with_api_and_tokens(api, Arc::new(TokenData::from(tokens)));
+        client.token_list_refresh_interval = Duration::from_secs(0);
+        let get_prices = || client.get_prices(&[1.into()]).now_or_never().unwrap();
+
+        // ETH is not listed yet.
+        assert_eq!(get_prices().unwrap(), hash_map! {});
+        // The previous list is used when refreshing fails.
+        assert_eq!(get_prices().unwrap(), hash_map! {});
+        assert_eq!(
+            get_prices().unwrap(),
+            hash_map! { TokenId(1) => nonzero!(1e18 as u128) }
+        );
+    }
+
     #[test]
     fn get_token_prices() {
         initialize_mockapi_context();
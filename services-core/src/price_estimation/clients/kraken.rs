@@ -1,6 +1,9 @@
 //! Implementation of a price source for Kraken.
 
 mod api;
+mod websocket;
+
+pub use self::websocket::{KrakenTickerStream, DEFAULT_WEBSOCKET_URL};
 
 use self::api::{Asset, AssetPair, KrakenApi, KrakenHttpApi};
 use super::super::PriceSource;
@@ -20,6 +23,9 @@ pub struct KrakenClient<Api> {
     /// used for testing.
     api: Api,
     token_info_fetcher: Arc<dyn TokenInfoFetching>,
+    /// An optional WebSocket ticker subscription that is preferred over the
+    /// REST ticker for asset pairs that it has a recent ticker for.
+    ticker_stream: Option<KrakenTickerStream>,
 }
 
 impl KrakenClient<KrakenHttpApi> {
@@ -36,6 +42,13 @@ impl KrakenClient<KrakenHttpApi> {
 
 type TokenIdAndInfo = (TokenId, TokenBaseInfo);
 
+/// A token together with the name of its USD asset pair in the WebSocket API.
+struct TokenAssetPair {
+    token_id: TokenId,
+    token_info: TokenBaseInfo,
+    ws_name: String,
+}
+
 impl<Api> KrakenClient<Api>
 where
     Api: KrakenApi,
@@ -45,9 +58,18 @@ where
         KrakenClient {
             api,
             token_info_fetcher,
+            ticker_stream: None,
         }
     }
 
+    /// Uses a WebSocket ticker subscription for the prices of the asset pairs
+    /// it has recent tickers for. The pairs of all tokens the client is asked
+    /// for are tracked by the stream.
+    pub fn with_ticker_stream(mut self, ticker_stream: KrakenTickerStream) -> Self {
+        self.ticker_stream = Some(ticker_stream);
+        self
+    }
+
     // Clippy complains about this but the lifetimes are needed.
     /// Generates a mapping between Kraken asset pair identifiers and tokens
     /// that are used when computing the price map.
@@ -55,7 +77,7 @@ where
     async fn get_token_asset_pairs(
         &self,
         tokens: impl IntoIterator<Item = TokenIdAndInfo>,
-    ) -> Result<HashMap<String, TokenIdAndInfo>> {
+    ) -> Result<HashMap<String, TokenAssetPair>> {
         // TODO(nlordell): If these calls start taking too long, we can consider
         //   caching this information somehow. The only thing that is
         //   complicated is determining when the cache needs to be invalidated
@@ -73,7 +95,16 @@ where
             .filter_map(|(token_id, token_info)| {
                 let asset = find_asset(token_info.symbol(), &assets)?;
                 let pair = find_asset_pair(asset, &usd, &asset_pairs)?;
-                Some((pair.to_owned(), (token_id, token_info)))
+                // WebSocket pair names are made of the alternative asset names.
+                let ws_name = format!("{}/{}", assets[asset].altname, assets[&usd].altname);
+                Some((
+                    pair.to_owned(),
+                    TokenAssetPair {
+                        token_id,
+                        token_info,
+                        ws_name,
+                    },
+                ))
             })
             .collect();
 
@@ -93,17 +124,31 @@ where
             .await
             .context("failed to generate asset pairs mapping for tokens")?;
 
-        let asset_pairs: Vec<_> = token_asset_pairs.keys().map(String::as_str).collect();
-        let ticker_infos = self.api.ticker(&asset_pairs).await?;
+        let mut ticker_infos = HashMap::new();
+        if let Some(ticker_stream) = &self.ticker_stream {
+            for (pair, token) in &token_asset_pairs {
+                ticker_stream.track(token.token_id, token.token_info.clone(), &token.ws_name);
+                if let Some(info) = ticker_stream.ticker(&token.ws_name) {
+                    ticker_infos.insert(pair.clone(), info);
+                }
+            }
+        }
+
+        let asset_pairs: Vec<_> = token_asset_pairs
+            .keys()
+            .filter(|pair| !ticker_infos.contains_key(*pair))
+            .map(String::as_str)
+            .collect();
+        ticker_infos.extend(self.api.ticker(&asset_pairs).await?);
 
         let prices = stream::iter(ticker_infos)
             .filter_map(|(pair, info)| {
-                let token_id_and_pair = token_asset_pairs.get(&pair);
+                let token = token_asset_pairs.get(&pair);
                 async move {
-                    let token_id_and_pair = token_id_and_pair?;
-                    let price = token_id_and_pair.1.get_owl_price(info.p.last_24h());
-                    log::debug!("Fetched price for token {}: {}", token_id_and_pair.0, price);
-                    Some((token_id_and_pair.0, NonZeroU128::new(price)?))
+                    let token = token?;
+                    let price = token.token_info.get_owl_price(info.p.last_24h());
+                    log::debug!("Fetched price for token {}: {}", token.token_id, price);
+                    Some((token.token_id, NonZeroU128::new(price)?))
                 }
             })
             .collect()
@@ -1,4 +1,4 @@
-use crate::http::{HttpClient, HttpFactory, HttpLabel};
+use crate::http::{HttpClient, HttpFactory, HttpLabel, HttpService};
 use anyhow::{anyhow, Context, Result};
 use futures::future::{BoxFuture, FutureExt as _};
 use serde::Deserialize;
@@ -42,7 +42,7 @@ impl KrakenHttpApi {
     }
 
     pub fn with_url(http_factory: &HttpFactory, base_url: &str) -> Result<Self> {
-        let client = http_factory.create()?;
+        let client = http_factory.create_for(HttpService::PriceApi)?;
         Ok(KrakenHttpApi {
             base_url: base_url.into(),
             client,
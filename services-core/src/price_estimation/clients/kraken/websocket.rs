@@ -0,0 +1,238 @@
+//! Kraken WebSocket ticker subscription keeping asset prices fresh in memory.
+
+use super::api::TickerInfo;
+use crate::models::TokenId;
+use crate::price_estimation::price_source::PriceSource;
+use crate::token_info::TokenBaseInfo;
+use anyhow::{Context, Result};
+use async_std::task;
+use async_tungstenite::{async_std::connect_async, tungstenite::Message};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    SinkExt as _, StreamExt as _,
+};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU128,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+/// The default Kraken WebSocket API URL.
+pub const DEFAULT_WEBSOCKET_URL: &str = "wss://ws.kraken.com";
+
+/// Tickers that were not updated for this long are considered stale, in which
+/// case the REST API is used instead.
+const MAX_TICKER_AGE: Duration = Duration::from_secs(60);
+
+/// The time to wait before reconnecting after the connection was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct State {
+    /// Maps tokens to the WebSocket name of their USD asset pair.
+    tokens: HashMap<TokenId, (String, TokenBaseInfo)>,
+    /// The asset pairs that are subscribed to.
+    pairs: HashSet<String>,
+    /// The latest ticker for each asset pair and when it was received.
+    tickers: HashMap<String, (TickerInfo, Instant)>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    subscribe: UnboundedSender<String>,
+}
+
+/// A subscription to the Kraken ticker channel. Asset pairs are subscribed to
+/// as tokens get tracked and the received tickers are kept in memory so that
+/// prices are available immediately.
+#[derive(Clone)]
+pub struct KrakenTickerStream(Arc<Inner>);
+
+impl KrakenTickerStream {
+    /// Connects to the WebSocket API in a background task. The connection is
+    /// reestablished when it is lost and closed when the stream is dropped.
+    pub fn connect(url: impl Into<String>) -> Self {
+        let (subscribe, subscriptions) = mpsc::unbounded();
+        let inner = Arc::new(Inner {
+            state: Mutex::new(State::default()),
+            subscribe,
+        });
+        task::spawn(run(url.into(), Arc::downgrade(&inner), subscriptions));
+        KrakenTickerStream(inner)
+    }
+
+    /// Tracks the price of a token through its USD asset pair, subscribing to
+    /// the pair if it is not yet subscribed to.
+    pub fn track(&self, token_id: TokenId, token_info: TokenBaseInfo, pair: &str) {
+        let mut state = self.0.state.lock().unwrap();
+        state.tokens.insert(token_id, (pair.to_owned(), token_info));
+        if state.pairs.insert(pair.to_owned()) {
+            // The receiver only goes away once this stream was dropped.
+            let _ = self.0.subscribe.unbounded_send(pair.to_owned());
+        }
+    }
+
+    /// Returns the latest ticker for an asset pair unless it is stale.
+    pub fn ticker(&self, pair: &str) -> Option<TickerInfo> {
+        let state = self.0.state.lock().unwrap();
+        let (ticker, received) = state.tickers.get(pair)?;
+        if received.elapsed() > MAX_TICKER_AGE {
+            return None;
+        }
+        Some(ticker.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl PriceSource for KrakenTickerStream {
+    async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>> {
+        Ok(tokens
+            .iter()
+            .filter_map(|token_id| {
+                let (pair, token_info) = self.0.state.lock().unwrap().tokens.get(token_id)?.clone();
+                let ticker = self.ticker(&pair)?;
+                let price = token_info.get_owl_price(ticker.p.last_24h());
+                Some((*token_id, NonZeroU128::new(price)?))
+            })
+            .collect())
+    }
+}
+
+async fn run(url: String, inner: Weak<Inner>, mut subscriptions: UnboundedReceiver<String>) {
+    while inner.upgrade().is_some() {
+        if let Err(err) = connection(&url, &inner, &mut subscriptions).await {
+            log::warn!("Kraken WebSocket connection failed: {:?}", err);
+        }
+        task::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Handles a single WebSocket connection until it fails or the stream is
+/// dropped.
+async fn connection(
+    url: &str,
+    inner: &Weak<Inner>,
+    subscriptions: &mut UnboundedReceiver<String>,
+) -> Result<()> {
+    let (socket, _) = connect_async(url)
+        .await
+        .context("failed to connect to Kraken WebSocket API")?;
+    let (mut sink, stream) = socket.split();
+    let mut stream = stream.fuse();
+
+    let pairs = match inner.upgrade() {
+        Some(inner) => inner.state.lock().unwrap().pairs.clone(),
+        None => return Ok(()),
+    };
+    for pair in pairs {
+        sink.send(subscribe_message(&pair)).await?;
+    }
+
+    loop {
+        futures::select! {
+            pair = subscriptions.next() => match pair {
+                Some(pair) => sink.send(subscribe_message(&pair)).await?,
+                None => return Ok(()),
+            },
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => match inner.upgrade() {
+                    Some(inner) => {
+                        handle_message(&mut inner.state.lock().unwrap(), &text);
+                    }
+                    None => return Ok(()),
+                },
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+                None => anyhow::bail!("Kraken WebSocket connection closed"),
+            },
+        }
+    }
+}
+
+fn subscribe_message(pair: &str) -> Message {
+    Message::Text(
+        json!({
+            "event": "subscribe",
+            "pair": [pair],
+            "subscription": { "name": "ticker" },
+        })
+        .to_string(),
+    )
+}
+
+/// Updates the state with a ticker message. Other messages like heartbeats and
+/// subscription status events are only logged.
+fn handle_message(state: &mut State, text: &str) {
+    match serde_json::from_str::<(u64, TickerInfo, String, String)>(text) {
+        Ok((_, ticker, channel, pair)) if channel == "ticker" => {
+            state.tickers.insert(pair, (ticker, Instant::now()));
+        }
+        _ => log::debug!("received Kraken WebSocket message: {}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethcontract::Address;
+    use futures::FutureExt as _;
+
+    fn stream() -> KrakenTickerStream {
+        let (subscribe, _) = mpsc::unbounded();
+        KrakenTickerStream(Arc::new(Inner {
+            state: Mutex::new(State::default()),
+            subscribe,
+        }))
+    }
+
+    #[test]
+    fn parses_ticker_messages() {
+        let mut state = State::default();
+        // Sample from https://docs.kraken.com/websockets/#message-ticker
+        handle_message(
+            &mut state,
+            r#"[0,{"a":["5525.40000",1,"1.000"],"b":["5525.10000",1,"1.000"],"c":["5525.10000","0.00398963"],"v":["2634.11501494","3591.17907851"],"p":["5631.44067","5653.78939"],"t":[11493,16267],"l":["5505.00000","5505.00000"],"h":["5783.00000","5783.00000"],"o":["5760.70000","5763.40000"]},"ticker","XBT/USD"]"#,
+        );
+        handle_message(&mut state, r#"{"event":"heartbeat"}"#);
+
+        assert_eq!(state.tickers.len(), 1);
+        assert_eq!(
+            state.tickers["XBT/USD"].0,
+            TickerInfo::new(5631.44067, 5653.78939)
+        );
+    }
+
+    #[test]
+    fn returns_prices_of_tracked_tokens() {
+        let stream = stream();
+        let address = Address::from_low_u64_be(0);
+        stream.track(
+            TokenId(1),
+            TokenBaseInfo::new(address, "WETH", 18),
+            "ETH/USD",
+        );
+        stream.track(
+            TokenId(2),
+            TokenBaseInfo::new(address, "GNO", 18),
+            "GNO/USD",
+        );
+        stream.0.state.lock().unwrap().tickers.insert(
+            "ETH/USD".into(),
+            (TickerInfo::new(100.0, 99.0), Instant::now()),
+        );
+
+        let prices = stream
+            .get_prices(&[TokenId(1), TokenId(2), TokenId(3)])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            prices,
+            hash_map! {
+                TokenId(1) => nonzero!(99e18 as u128),
+            }
+        );
+    }
+}
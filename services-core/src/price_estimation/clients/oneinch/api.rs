@@ -1,11 +1,11 @@
 use super::super::generic_client::{Api, GenericToken};
-use crate::http::{HttpClient, HttpFactory, HttpLabel};
+use crate::http::{HttpClient, HttpFactory, HttpLabel, HttpService};
 use anyhow::{Context, Result};
 use ethcontract::Address;
 use isahc::prelude::Configurable;
 use serde::Deserialize;
 use serde_with::rust::display_fromstr;
-use std::{collections::HashMap, time::Duration};
+use std::collections::HashMap;
 use url::Url;
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -39,7 +39,7 @@ pub struct OneinchHttpApi {
 impl OneinchHttpApi {
     pub fn with_url(http_factory: &HttpFactory, api_url: &str) -> Result<Self> {
         let client = http_factory
-            .with_config(|builder| builder.timeout(Duration::from_secs(30)))
+            .create_for(HttpService::PriceApi)
             .context("failed to initialize HTTP client")?;
         let api_url = api_url
             .parse()
@@ -152,6 +152,8 @@ mod tests {
         let timeout = 20;
         let api = OneinchHttpApi::bind(&HttpFactory::new(
             Duration::from_secs(timeout),
+            Default::default(),
+            Default::default(),
             HttpMetrics::default(),
         ))
         .unwrap();
@@ -1,5 +1,5 @@
 use super::price_source::PriceSource;
-use crate::models::{BatchId, TokenId};
+use crate::models::{batch_id::BatchTiming, TokenId};
 use crate::orderbook::StableXOrderBookReading;
 use anyhow::Result;
 use pricegraph::Pricegraph;
@@ -10,18 +10,31 @@ use std::time::SystemTime;
 
 pub struct PricegraphEstimator {
     orderbook_reader: Arc<dyn StableXOrderBookReading>,
+    /// The timing of the exchange's batches, which determines the batch whose
+    /// orderbook the prices are estimated from.
+    batch_timing: BatchTiming,
 }
 
 impl PricegraphEstimator {
     pub fn new(orderbook_reader: Arc<dyn StableXOrderBookReading>) -> Self {
-        Self { orderbook_reader }
+        Self {
+            orderbook_reader,
+            batch_timing: BatchTiming::default(),
+        }
+    }
+
+    /// Uses the batch timing of an exchange deployment with a different batch
+    /// duration than the production one.
+    pub fn with_batch_timing(mut self, batch_timing: BatchTiming) -> Self {
+        self.batch_timing = batch_timing;
+        self
     }
 }
 
 #[async_trait::async_trait]
 impl PriceSource for PricegraphEstimator {
     async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>> {
-        let batch = BatchId::currently_being_solved(SystemTime::now())?;
+        let batch = self.batch_timing.batch_being_solved(SystemTime::now())?;
         let (account_state, orders) = self
             .orderbook_reader
             .get_auction_data_for_batch(batch.into())
@@ -1,7 +1,23 @@
 use crate::models::{TokenId, TokenInfo};
 use anyhow::Result;
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::num::NonZeroU128;
+use std::sync::Arc;
+
+/// The different kinds of price sources. Used to configure which sources may
+/// be used for the price of a token.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceSourceKind {
+    /// The external price of the token data.
+    Hardcoded,
+    /// Prices estimated from the orderbook.
+    Pricegraph,
+    Kraken,
+    Dexag,
+    Oneinch,
+}
 
 /// A token representation.
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -26,6 +42,27 @@ pub trait PriceSource {
     async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>>;
 }
 
+#[async_trait::async_trait]
+impl<T> PriceSource for Arc<T>
+where
+    T: PriceSource + Send + Sync + ?Sized,
+{
+    async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>> {
+        self.as_ref().get_prices(tokens).await
+    }
+}
+
+/// A price source that always has a price for every token, falling back to
+/// approximate prices for tokens without an estimate. Since it cannot fail,
+/// its prices can be used where waiting for or handling errors of a price
+/// source is not an option, for example when filtering the orderbook.
+#[cfg_attr(test, mockall::automock)]
+pub trait InfalliblePriceSource: Send + Sync {
+    /// Returns the price of the token in OWL, that is the amount of OWL in
+    /// atoms to purchase 1e18 atoms of the token.
+    fn price(&self, token_id: TokenId) -> NonZeroU128;
+}
+
 /// A no-op price source that always succeeds and finds no prices.
 pub struct NoopPriceSource;
 
@@ -0,0 +1,82 @@
+use super::price_source::{PriceSource, PriceSourceKind};
+use crate::models::TokenId;
+use crate::token_info::hardcoded::TokenData;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::num::NonZeroU128;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A price source that is only queried for the tokens that the token data
+/// allows it to be used for. The source is held through a pointer so that both
+/// boxed and borrowed sources can be restricted.
+pub struct RestrictedPriceSource<T> {
+    kind: PriceSourceKind,
+    token_data: Arc<TokenData>,
+    source: T,
+}
+
+impl<T> RestrictedPriceSource<T> {
+    pub fn new(kind: PriceSourceKind, token_data: Arc<TokenData>, source: T) -> Self {
+        Self {
+            kind,
+            token_data,
+            source,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> PriceSource for RestrictedPriceSource<T>
+where
+    T: Deref + Send + Sync,
+    T::Target: PriceSource + Sync,
+{
+    async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>> {
+        let tokens: Vec<_> = tokens
+            .iter()
+            .copied()
+            .filter(|token| self.token_data.allows_price_source(*token, self.kind))
+            .collect();
+        if tokens.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.source.get_prices(&tokens).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price_estimation::price_source::MockPriceSource;
+    use crate::token_info::hardcoded::TokenInfoOverride;
+    use ethcontract::Address;
+    use futures::FutureExt as _;
+
+    #[test]
+    fn only_queries_allowed_tokens() {
+        let address = Address::from_low_u64_be(0);
+        let token_data = TokenData::from(hash_map! {
+            TokenId(1) => TokenInfoOverride {
+                price_sources: Some(vec![PriceSourceKind::Hardcoded].into_iter().collect()),
+                ..TokenInfoOverride::new(address, "WETH", 18, None)
+            },
+            TokenId(2) => TokenInfoOverride::new(address, "USDC", 6, None),
+        });
+
+        let mut source = MockPriceSource::new();
+        source
+            .expect_get_prices()
+            .withf(|tokens| tokens == &[TokenId(2), TokenId(3)][..])
+            .returning(|_| Ok(hash_map! { TokenId(2) => nonzero!(1) }));
+
+        let restricted =
+            RestrictedPriceSource::new(PriceSourceKind::Kraken, Arc::new(token_data), &source);
+        let prices = restricted
+            .get_prices(&[TokenId(1), TokenId(2), TokenId(3)])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(prices, hash_map! { TokenId(2) => nonzero!(1) });
+    }
+}
@@ -1,19 +1,25 @@
+pub mod grpc_solver;
 pub mod naive_solver;
 pub mod optimization_price_finder;
 pub mod price_finder_interface;
+pub mod solver_plugin;
 
 pub use self::{
+    grpc_solver::GrpcSolver,
     naive_solver::NaiveSolver,
     optimization_price_finder::OptimisationPriceFinder,
     price_finder_interface::{Fee, InternalOptimizer, PriceFinding, SolverType},
+    solver_plugin::SolverPlugin,
 };
 use crate::{
     metrics::{SolverMetrics, StableXMetrics},
+    models::batch_id::BatchTiming,
     price_estimation::PriceEstimating,
 };
 use log::info;
 use std::sync::Arc;
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_price_finder(
     fee: Option<Fee>,
     solver_type: SolverType,
@@ -21,19 +27,36 @@ pub fn create_price_finder(
     internal_optimizer: InternalOptimizer,
     solver_metrics: SolverMetrics,
     stablex_metrics: Arc<StableXMetrics>,
+    price_bounds_deviation: Option<f64>,
+    solver_plugin: Option<SolverPlugin>,
+    grpc_solver: Option<GrpcSolver>,
+    batch_timing: BatchTiming,
 ) -> Arc<dyn PriceFinding + Send + Sync> {
     if solver_type == SolverType::NaiveSolver {
         info!("Using naive price finder");
         Arc::new(NaiveSolver::new(fee))
     } else {
         info!("Using {:?} optimization price finder", solver_type);
-        Arc::new(OptimisationPriceFinder::new(
+        let mut price_finder = OptimisationPriceFinder::new(
             fee,
             solver_type,
             price_oracle,
             internal_optimizer,
             solver_metrics,
             stablex_metrics,
-        ))
+        )
+        .with_batch_timing(batch_timing);
+        if let Some(deviation) = price_bounds_deviation {
+            price_finder = price_finder.with_price_bounds(deviation);
+        }
+        if solver_type == SolverType::PluginSolver {
+            let plugin = solver_plugin.expect("the plugin solver requires a solver plugin");
+            price_finder = price_finder.with_plugin(plugin);
+        }
+        if solver_type == SolverType::GrpcSolver {
+            let solver = grpc_solver.expect("the gRPC solver requires a solver URL");
+            price_finder = price_finder.with_grpc_solver(solver);
+        }
+        Arc::new(price_finder)
     }
 }
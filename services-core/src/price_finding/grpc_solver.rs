@@ -0,0 +1,355 @@
+//! Module implementing solvers that are served over gRPC, so that remote
+//! solver farms can serve many drivers. The protocol is defined in
+//! `proto/solver.proto`.
+//!
+//! The instance is submitted with the deadline by which the driver needs the
+//! solution to submit it in time for the batch. Every call is additionally
+//! bounded by the remaining time, so that the driver never waits for the
+//! solver past the deadline. Incumbents are streamed until shortly before the
+//! deadline, which leaves time to request the best solution, and the last
+//! incumbent is used if that request fails.
+
+use super::optimization_price_finder::Io;
+use super::price_finder_interface::{InternalOptimizer, SolverType};
+use anyhow::{anyhow, Context, Result};
+use futures::{Future, StreamExt as _};
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    runtime::{self, Runtime},
+    time,
+};
+use tonic::{metadata::MetadataValue, transport::Channel, Request};
+use url::Url;
+
+// The server is only used by the tests.
+#[allow(clippy::all, dead_code)]
+mod proto {
+    tonic::include_proto!("dfusion.solver.v1");
+}
+
+use proto::{solver_client::SolverClient, Incumbent, InstanceRef, SubmitInstanceRequest};
+
+/// The schema version of the solver input and output used over gRPC. The
+/// protocol is newer than the versioned schema, so servers need to support
+/// at least this version.
+const SCHEMA_VERSION: u32 = 2;
+
+/// The time before the deadline at which streaming incumbents stops, so that
+/// there is time left to request the best solution.
+const GET_BEST_MARGIN: Duration = Duration::from_secs(5);
+
+/// A solver served over gRPC.
+pub struct GrpcSolver {
+    client: SolverClient<Channel>,
+    get_best_margin: Duration,
+    // The driver does not run on tokio, so the solver has its own runtime for
+    // the gRPC client.
+    runtime: Mutex<Runtime>,
+}
+
+impl GrpcSolver {
+    /// Creates a solver connecting to the specified URL. The connection is
+    /// established on the first request.
+    pub fn new(url: &Url) -> Result<Self> {
+        let runtime = runtime::Builder::new()
+            .threaded_scheduler()
+            .core_threads(1)
+            .enable_all()
+            .build()?;
+        let endpoint = Channel::from_shared(url.to_string())?;
+        let channel = runtime.enter(|| endpoint.connect_lazy())?;
+        Ok(GrpcSolver {
+            client: SolverClient::new(channel),
+            get_best_margin: GET_BEST_MARGIN,
+            runtime: Mutex::new(runtime),
+        })
+    }
+
+    async fn solve(
+        &self,
+        name: String,
+        input: String,
+        time_limit: Duration,
+        min_avg_fee_per_order: u128,
+    ) -> Result<String> {
+        let deadline = Instant::now() + time_limit;
+        let mut client = self.client.clone();
+
+        let request = SubmitInstanceRequest {
+            name,
+            schema_version: SCHEMA_VERSION,
+            input,
+            min_avg_fee_per_order: min_avg_fee_per_order.to_string(),
+            deadline: unix_millis(SystemTime::now() + time_limit)?,
+        };
+        let instance_id = until_deadline(
+            deadline,
+            client.submit_instance(with_deadline(request, deadline)?),
+        )
+        .await?
+        .context("error submitting instance")?
+        .into_inner()
+        .instance_id;
+        let instance = InstanceRef { instance_id };
+
+        let mut last_incumbent = None;
+        let stream_deadline = deadline
+            .checked_sub(self.get_best_margin)
+            .filter(|stream_deadline| *stream_deadline > Instant::now());
+        if let Some(stream_deadline) = stream_deadline {
+            let streaming = stream_incumbents(
+                &mut client,
+                instance.clone(),
+                stream_deadline,
+                &mut last_incumbent,
+            );
+            // The solver keeps searching until the deadline, so the stream is
+            // usually cut off.
+            let _ = until_deadline(stream_deadline, streaming).await;
+        }
+
+        let best = match get_best(&mut client, instance, deadline).await {
+            Ok(best) => best,
+            Err(err) => match last_incumbent {
+                Some(incumbent) => {
+                    log::warn!(
+                        "error getting best solution, using the last incumbent: {:?}",
+                        err
+                    );
+                    incumbent
+                }
+                None => return Err(err.context("error getting best solution")),
+            },
+        };
+        log::info!(
+            "gRPC solver found solution with objective value {}",
+            best.objective_value
+        );
+        Ok(best.output)
+    }
+}
+
+/// Requests the best solution found for an instance.
+async fn get_best(
+    client: &mut SolverClient<Channel>,
+    instance: InstanceRef,
+    deadline: Instant,
+) -> Result<Incumbent> {
+    let request = with_deadline(instance, deadline)?;
+    Ok(until_deadline(deadline, client.get_best(request))
+        .await??
+        .into_inner())
+}
+
+/// Streams the incumbents of an instance and keeps the last one.
+async fn stream_incumbents(
+    client: &mut SolverClient<Channel>,
+    instance: InstanceRef,
+    deadline: Instant,
+    last_incumbent: &mut Option<Incumbent>,
+) -> Result<()> {
+    let instance_id = instance.instance_id.clone();
+    let mut incumbents = client
+        .stream_incumbents(with_deadline(instance, deadline)?)
+        .await
+        .context("error streaming gRPC solver incumbents")?
+        .into_inner();
+    while let Some(incumbent) = incumbents.next().await {
+        let incumbent = incumbent.context("gRPC solver incumbent stream failed")?;
+        log::debug!(
+            "gRPC solver found solution with objective value {} for instance {}",
+            incumbent.objective_value,
+            instance_id,
+        );
+        *last_incumbent = Some(incumbent);
+    }
+    Ok(())
+}
+
+/// Creates a request that the server times out at the deadline.
+fn with_deadline<T>(message: T, deadline: Instant) -> Result<Request<T>> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert(
+        "grpc-timeout",
+        MetadataValue::from_str(&format!("{}m", remaining(deadline)?.as_millis()))?,
+    );
+    Ok(request)
+}
+
+/// Waits for the future at most until the deadline.
+async fn until_deadline<T>(deadline: Instant, future: impl Future<Output = T>) -> Result<T> {
+    time::timeout(remaining(deadline)?, future)
+        .await
+        .map_err(|_| anyhow!("gRPC solver deadline has passed"))
+}
+
+fn remaining(deadline: Instant) -> Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .ok_or_else(|| anyhow!("gRPC solver deadline has passed"))
+}
+
+fn unix_millis(time: SystemTime) -> Result<u64> {
+    Ok(time.duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
+
+impl Io for GrpcSolver {
+    fn run_solver(
+        &self,
+        input_file: &str,
+        input: &str,
+        _result_folder: &str,
+        _solver_type: SolverType,
+        time_limit: Duration,
+        min_avg_fee_per_order: u128,
+        _internal_optimizer: InternalOptimizer,
+    ) -> Result<String> {
+        let name = Path::new(input_file)
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.runtime.lock().unwrap().block_on(self.solve(
+            name,
+            input.to_owned(),
+            time_limit,
+            min_avg_fee_per_order,
+        ))
+    }
+
+    fn supported_schema_versions(&self, _solver_type: SolverType) -> Result<Vec<u32>> {
+        Ok(vec![SCHEMA_VERSION])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, Stream};
+    use proto::{
+        solver_server::{Solver, SolverServer},
+        SubmitInstanceResponse,
+    };
+    use std::pin::Pin;
+    use tokio::net::TcpListener;
+    use tonic::{transport::Server, Response, Status};
+
+    struct MockSolver {
+        incumbents: Vec<&'static str>,
+        best: Option<&'static str>,
+    }
+
+    fn incumbent(output: &str) -> Incumbent {
+        Incumbent {
+            output: output.to_owned(),
+            objective_value: 1.0,
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Solver for MockSolver {
+        async fn submit_instance(
+            &self,
+            request: Request<SubmitInstanceRequest>,
+        ) -> Result<Response<SubmitInstanceResponse>, Status> {
+            assert!(request.metadata().get("grpc-timeout").is_some());
+            assert_eq!(request.get_ref().schema_version, SCHEMA_VERSION);
+            Ok(Response::new(SubmitInstanceResponse {
+                instance_id: request.into_inner().name,
+            }))
+        }
+
+        type StreamIncumbentsStream =
+            Pin<Box<dyn Stream<Item = Result<Incumbent, Status>> + Send + Sync>>;
+
+        async fn stream_incumbents(
+            &self,
+            request: Request<InstanceRef>,
+        ) -> Result<Response<Self::StreamIncumbentsStream>, Status> {
+            assert_eq!(request.get_ref().instance_id, "instance");
+            let incumbents = self
+                .incumbents
+                .iter()
+                .map(|output| Ok(incumbent(output)))
+                .collect::<Vec<_>>();
+            Ok(Response::new(Box::pin(stream::iter(incumbents))))
+        }
+
+        async fn get_best(&self, _: Request<InstanceRef>) -> Result<Response<Incumbent>, Status> {
+            match self.best {
+                Some(output) => Ok(Response::new(incumbent(output))),
+                None => Err(Status::unavailable("no solution")),
+            }
+        }
+    }
+
+    /// Serves the mock solver on a local port and returns a solver using it.
+    fn solver(mock: MockSolver) -> (GrpcSolver, Runtime) {
+        let mut runtime = runtime::Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        runtime.spawn(async move {
+            Server::builder()
+                .add_service(SolverServer::new(mock))
+                .serve_with_incoming(listener.incoming())
+                .await
+        });
+
+        let mut solver = GrpcSolver::new(&url.parse().unwrap()).unwrap();
+        solver.get_best_margin = Duration::from_secs(1);
+        (solver, runtime)
+    }
+
+    fn run(solver: &GrpcSolver) -> Result<String> {
+        solver.run_solver(
+            "instances/instance.json",
+            "{}",
+            "",
+            SolverType::GrpcSolver,
+            Duration::from_secs(10),
+            0,
+            InternalOptimizer::Scip,
+        )
+    }
+
+    #[test]
+    fn returns_best_solution() {
+        let (solver, _server) = solver(MockSolver {
+            incumbents: vec!["first", "second"],
+            best: Some("best"),
+        });
+        assert_eq!(run(&solver).unwrap(), "best");
+    }
+
+    #[test]
+    fn falls_back_to_last_incumbent() {
+        let (solver, _server) = solver(MockSolver {
+            incumbents: vec!["first", "second"],
+            best: None,
+        });
+        assert_eq!(run(&solver).unwrap(), "second");
+
+        let (solver, _server) = solver(MockSolver {
+            incumbents: vec![],
+            best: None,
+        });
+        assert!(run(&solver).is_err());
+    }
+
+    #[test]
+    fn requests_time_out_at_deadline() {
+        let request = with_deadline((), Instant::now() + Duration::from_secs(60)).unwrap();
+        assert!(request.metadata().get("grpc-timeout").is_some());
+
+        let deadline = Instant::now();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(with_deadline((), deadline).is_err());
+    }
+}
@@ -1,12 +1,13 @@
-use crate::models::{AccountState, ExecutedOrder, Order, Solution};
+use crate::models::{AccountState, Amount, AmountError, ExecutedOrder, Order, Solution};
 use crate::price_finding::price_finder_interface::{Fee, PriceFinding};
-use crate::util::{CeiledDiv, CheckedConvertU128};
+use crate::util::CeiledDiv;
 
 use std::collections::HashMap;
 use std::time::Duration;
 
 use anyhow::Result;
 use ethcontract::U256;
+use log::warn;
 
 const BASE_UNIT: u128 = 1_000_000_000_000_000_000u128;
 const BASE_PRICE: u128 = BASE_UNIT;
@@ -143,23 +144,28 @@ impl PriceFinding for NaiveSolver {
                 order
             })
             .collect::<Vec<_>>();
-        let solution = if let Some(first_match) = find_first_match(&orders, state, &self.fee) {
-            let (executed_orders, prices) = create_executed_orders(&first_match, &self.fee);
-            if let Some(ref fee) = self.fee {
-                create_solution_with_fee(&first_match.orders, fee, executed_orders, prices)
-            } else {
-                Solution {
-                    prices,
-                    executed_orders: executed_orders.to_vec(),
-                }
-            }
-        } else {
-            Solution::trivial()
+        let solution = match find_first_match(&orders, state, &self.fee) {
+            Some(first_match) => create_solution(&first_match, &self.fee).unwrap_or_else(|err| {
+                warn!("naive solver could not settle its match: {}", err);
+                Solution::trivial()
+            }),
+            None => Solution::trivial(),
         };
         Ok(solution)
     }
 }
 
+fn create_solution(first_match: &Match, fee: &Option<Fee>) -> Result<Solution, AmountError> {
+    let (executed_orders, prices) = create_executed_orders(first_match, fee)?;
+    match fee {
+        Some(fee) => create_solution_with_fee(&first_match.orders, fee, executed_orders, prices),
+        None => Ok(Solution {
+            prices,
+            executed_orders: executed_orders.to_vec(),
+        }),
+    }
+}
+
 fn find_first_match(orders: &[Order], state: &AccountState, fee: &Option<Fee>) -> Option<Match> {
     for (i, x) in orders.iter().enumerate() {
         for y in orders.iter().skip(i + 1) {
@@ -174,7 +180,10 @@ fn find_first_match(orders: &[Order], state: &AccountState, fee: &Option<Fee>) -
     None
 }
 
-fn create_executed_orders(first_match: &Match, fee: &Option<Fee>) -> (ExecutedOrderPair, PriceMap) {
+fn create_executed_orders(
+    first_match: &Match,
+    fee: &Option<Fee>,
+) -> Result<(ExecutedOrderPair, PriceMap), AmountError> {
     fn create_executed_order(order: &Order, sell_amount: u128, buy_amount: u128) -> ExecutedOrder {
         ExecutedOrder {
             account_id: order.account_id,
@@ -185,8 +194,8 @@ fn create_executed_orders(first_match: &Match, fee: &Option<Fee>) -> (ExecutedOr
     }
 
     // Preprocess order to leave "space" for fee to be taken
-    let x = order_with_buffer_for_fee(&first_match.orders[0], fee);
-    let y = order_with_buffer_for_fee(&first_match.orders[1], fee);
+    let x = order_with_buffer_for_fee(&first_match.orders[0], fee)?;
+    let y = order_with_buffer_for_fee(&first_match.orders[1], fee)?;
 
     let create_orders = |x_sell_amount, x_buy_amount, y_sell_amount, y_buy_amount| {
         [
@@ -214,7 +223,7 @@ fn create_executed_orders(first_match: &Match, fee: &Option<Fee>) -> (ExecutedOr
         }
     };
 
-    (executed_orders, prices)
+    Ok((executed_orders, prices))
 }
 
 fn create_solution_with_fee(
@@ -222,17 +231,14 @@ fn create_solution_with_fee(
     fee: &Fee,
     mut executed_orders: ExecutedOrderPair,
     mut prices: PriceMap,
-) -> Solution {
+) -> Result<Solution, AmountError> {
     // normalize prices so fee token price is BASE_PRICE
     let pre_normalized_fee_price = prices.get(&fee.token).copied().unwrap_or(0);
     if pre_normalized_fee_price == 0 {
-        return Solution::trivial();
+        return Ok(Solution::trivial());
     }
     for price in prices.values_mut() {
-        *price = match normalize_price(*price, pre_normalized_fee_price) {
-            Some(price) => price,
-            None => return Solution::trivial(),
-        };
+        *price = normalize_price(*price, pre_normalized_fee_price)?;
     }
 
     // apply fee to volumes account for rounding errors, moving them to
@@ -242,28 +248,25 @@ fn create_solution_with_fee(
         if order.sell_token == fee.token {
             let price_buy = prices[&order.buy_token];
             executed_order.sell_amount =
-                executed_sell_amount(fee, executed_order.buy_amount, price_buy, BASE_PRICE);
+                executed_sell_amount(fee, executed_order.buy_amount, price_buy, BASE_PRICE)?;
         } else {
             let price_sell = prices[&order.sell_token];
-            executed_order.buy_amount = match executed_buy_amount(
-                fee,
-                executed_order.sell_amount,
-                BASE_PRICE,
-                price_sell,
-            ) {
-                Some(exec_buy_amt) => exec_buy_amt,
-                None => return Solution::trivial(),
-            };
+            executed_order.buy_amount =
+                match executed_buy_amount(fee, executed_order.sell_amount, BASE_PRICE, price_sell)?
+                {
+                    Some(exec_buy_amt) => exec_buy_amt,
+                    None => return Ok(Solution::trivial()),
+                };
         }
     }
 
-    Solution {
+    Ok(Solution {
         prices,
         executed_orders: executed_orders.to_vec(),
-    }
+    })
 }
 
-fn order_with_buffer_for_fee(order: &Order, fee: &Option<Fee>) -> Order {
+fn order_with_buffer_for_fee(order: &Order, fee: &Option<Fee>) -> Result<Order, AmountError> {
     match fee {
         Some(fee) => {
             let mut order = order.clone();
@@ -272,33 +275,41 @@ fn order_with_buffer_for_fee(order: &Order, fee: &Option<Fee>) -> Order {
             // b) give away less stuff (while receiving the same)
             let fee_denominator = (1.0 / fee.ratio) as u128;
             if fee.token == order.buy_token {
-                order.numerator =
-                    (order.numerator * fee_denominator).ceiled_div(fee_denominator - 1)
+                order.numerator = Amount(order.numerator)
+                    .mul_div_ceil(fee_denominator, fee_denominator - 1)?
+                    .into();
             } else if fee.token == order.sell_token {
-                order.denominator = order.denominator * (fee_denominator - 1) / fee_denominator
+                order.denominator = Amount(order.denominator)
+                    .mul_div(fee_denominator - 1, fee_denominator)?
+                    .into();
             }
-            order
+            Ok(order)
         }
-        None => order.clone(),
+        None => Ok(order.clone()),
     }
 }
 
 /// Normalizes a price base on the pre-normalized fee price.
-fn normalize_price(price: u128, pre_normalized_fee_price: u128) -> Option<u128> {
-    // upcast to u256 to avoid overflows
-    (U256::from(price) * U256::from(BASE_PRICE))
-        .ceiled_div(U256::from(pre_normalized_fee_price))
-        .as_u128_checked()
+fn normalize_price(price: u128, pre_normalized_fee_price: u128) -> Result<u128, AmountError> {
+    Amount(price)
+        .mul_div_ceil(BASE_PRICE, pre_normalized_fee_price)
+        .map(u128::from)
 }
 
 /// Calculate the executed sell amount from the fee, executed buy amount, and
 /// the buy and sell prices of the traded tokens.
-fn executed_sell_amount(fee: &Fee, exec_buy_amt: u128, buy_price: u128, sell_price: u128) -> u128 {
+fn executed_sell_amount(
+    fee: &Fee,
+    exec_buy_amt: u128,
+    buy_price: u128,
+    sell_price: u128,
+) -> Result<u128, AmountError> {
     let fee_denominator = (1.0 / fee.ratio) as u128;
-    ((((U256::from(exec_buy_amt) * U256::from(buy_price)) / U256::from(fee_denominator - 1))
-        * U256::from(fee_denominator))
-        / U256::from(sell_price))
-    .as_u128()
+    let buy_value = U256::from(exec_buy_amt) * U256::from(buy_price);
+    let sell_value = (buy_value / U256::from(fee_denominator - 1))
+        .checked_mul(U256::from(fee_denominator))
+        .ok_or(AmountError::Overflow)?;
+    Amount::from_u256(sell_value / U256::from(sell_price)).map(u128::from)
 }
 
 /// Calculate the executed buy amount from the fee, executed sell amount, and
@@ -310,23 +321,24 @@ fn executed_buy_amount(
     exec_sell_amt: u128,
     buy_price: u128,
     sell_price: u128,
-) -> Option<u128> {
+) -> Result<Option<u128>, AmountError> {
     let fee_denominator = (1.0 / fee.ratio) as u128;
-    let exec_buy_amt = (((U256::from(exec_sell_amt) * U256::from(sell_price))
+    // Cannot overflow since the sell value is divided by the fee denominator
+    // before being multiplied by a smaller number.
+    let sell_value = ((U256::from(exec_sell_amt) * U256::from(sell_price))
         / U256::from(fee_denominator))
-        * U256::from(fee_denominator - 1))
-    .ceiled_div(U256::from(buy_price))
-    .as_u128();
+        * U256::from(fee_denominator - 1);
+    let exec_buy_amt = Amount::from_u256(sell_value.ceiled_div(U256::from(buy_price)))?.into();
 
     // we need to account for rounding errors here, since this function is
     // essentially an inverse of `executed_sell_amount`; when the buy price is
     // higher than the sell price, there are executed sell amounts that cannot
     // be satisfied, check the executed buy amount correctly "round trips" to
     // the specified executed sell amount and return `None` if it doesn't
-    if exec_sell_amt == executed_sell_amount(fee, exec_buy_amt, buy_price, sell_price) {
-        Some(exec_buy_amt)
+    if exec_sell_amt == executed_sell_amount(fee, exec_buy_amt, buy_price, sell_price)? {
+        Ok(Some(exec_buy_amt))
     } else {
-        None
+        Ok(None)
     }
 }
 
@@ -3,24 +3,29 @@ use crate::{
         solver_metrics::{SolverMetrics, SolverStats},
         StableXMetrics,
     },
-    models::{self, solution::Solution, TokenId, TokenInfo},
+    models::{self, batch_id::BatchTiming, solution::Solution, TokenId, TokenInfo},
     price_estimation::PriceEstimating,
-    price_finding::price_finder_interface::{Fee, InternalOptimizer, PriceFinding, SolverType},
+    price_finding::{
+        grpc_solver::GrpcSolver,
+        price_finder_interface::{Fee, InternalOptimizer, PriceFinding, SolverType},
+        solver_plugin::SolverPlugin,
+    },
 };
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use chrono::Utc;
 use ethcontract::U256;
-use log::error;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_with::rust::display_fromstr;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fmt::{Debug, Display};
 use std::fs::{create_dir_all, File};
 use std::io::{BufReader, BufWriter, Read, Write};
+use std::num::NonZeroU128;
 use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 /// A number wrapper type that correctly serializes large integers to strings to
@@ -41,6 +46,37 @@ where
 
 pub type TokenDataType = BTreeMap<TokenId, Option<TokenInfo>>;
 
+/// The versions of the JSON schema of the solver input and output. Solvers
+/// report the versions they support, so that the driver and solvers can be
+/// upgraded independently.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum SchemaVersion {
+    /// The original schema without a `version` field. Solvers that cannot
+    /// report their supported versions are assumed to only support it.
+    V1 = 1,
+    /// Adds the `version` field to the input and output and the optional
+    /// `priceBounds` of tokens to the input.
+    V2 = 2,
+}
+
+impl SchemaVersion {
+    const ALL: &'static [SchemaVersion] = &[SchemaVersion::V1, SchemaVersion::V2];
+
+    /// Returns the latest version that is supported by both the driver and the
+    /// solver.
+    fn negotiate(supported_by_solver: &[u32]) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|version| supported_by_solver.contains(&version.number()))
+    }
+
+    fn number(self) -> u32 {
+        self as u32
+    }
+}
+
 mod solver_output {
     use super::{Num, TokenId};
     use crate::{metrics::solver_metrics::SolverStats, models::solution::Solution};
@@ -67,6 +103,9 @@ mod solver_output {
     #[derive(Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Output {
+        /// The schema version of the output, which is missing for version 1.
+        #[serde(default)]
+        pub version: Option<u32>,
         pub orders: Vec<ExecutedOrder>,
         pub prices: HashMap<TokenId, Option<Num<u128>>>,
         #[serde(flatten)]
@@ -106,7 +145,7 @@ mod solver_output {
 }
 
 mod solver_input {
-    use super::{Num, TokenDataType, TokenId};
+    use super::{Num, SchemaVersion, TokenDataType, TokenId};
     use crate::models;
     use crate::price_finding;
     use ethcontract::{Address, U256};
@@ -170,6 +209,13 @@ mod solver_input {
 
     pub type Accounts = BTreeMap<Address, BTreeMap<TokenId, Num<U256>>>;
 
+    /// The range of prices that the solver may assign to a token.
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub struct PriceBounds {
+        pub min: Num<u128>,
+        pub max: Num<u128>,
+    }
+
     /// JSON serializable solver input data.
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
@@ -179,11 +225,67 @@ mod solver_input {
         pub accounts: Accounts,
         pub orders: Vec<Order>,
         pub fee: Option<Fee>,
+        /// Bounds for the prices of tokens, omitted if no prices are bounded
+        /// so that the input is unchanged for solvers that do not support
+        /// them.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub price_bounds: BTreeMap<TokenId, PriceBounds>,
+    }
+
+    impl Input {
+        /// Serializes the input in the specified schema version.
+        pub fn to_json(self, version: SchemaVersion) -> serde_json::Result<String> {
+            match version {
+                SchemaVersion::V1 => serde_json::to_string(&v1::Input::from(self)),
+                SchemaVersion::V2 => serde_json::to_string(&Versioned {
+                    version: version.number(),
+                    input: self,
+                }),
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Versioned<T> {
+        version: u32,
+        #[serde(flatten)]
+        input: T,
+    }
+
+    /// The input of version 1 of the schema.
+    mod v1 {
+        use super::{Accounts, Fee, Order, TokenDataType, TokenId};
+        use serde::Serialize;
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        pub struct Input {
+            pub tokens: TokenDataType,
+            pub ref_token: TokenId,
+            pub accounts: Accounts,
+            pub orders: Vec<Order>,
+            pub fee: Option<Fee>,
+        }
+
+        impl From<super::Input> for Input {
+            fn from(input: super::Input) -> Self {
+                if !input.price_bounds.is_empty() {
+                    log::warn!("price bounds are not supported by the solver and are ignored");
+                }
+                Input {
+                    tokens: input.tokens,
+                    ref_token: input.ref_token,
+                    accounts: input.accounts,
+                    orders: input.orders,
+                    fee: input.fee,
+                }
+            }
+        }
     }
 }
 
 #[cfg_attr(test, mockall::automock)]
-trait Io {
+pub(super) trait Io {
     #[allow(clippy::too_many_arguments)]
     fn run_solver(
         &self,
@@ -195,6 +297,9 @@ trait Io {
         min_avg_fee_per_order: u128,
         internal_optimizer: InternalOptimizer,
     ) -> Result<String>;
+
+    /// Returns the schema versions supported by the solver.
+    fn supported_schema_versions(&self, solver_type: SolverType) -> Result<Vec<u32>>;
 }
 
 pub struct OptimisationPriceFinder {
@@ -205,6 +310,14 @@ pub struct OptimisationPriceFinder {
     internal_optimizer: InternalOptimizer,
     solver_metrics: SolverMetrics,
     stablex_metrics: Arc<StableXMetrics>,
+    /// The maximum relative deviation of token prices from their external
+    /// prices, or `None` if prices are not bounded.
+    price_bounds_deviation: Option<f64>,
+    /// The schema version negotiated with the solver on the first run.
+    schema_version: Mutex<Option<SchemaVersion>>,
+    /// The timing of the exchange's batches, which determines the batch the
+    /// instance files are named after.
+    batch_timing: BatchTiming,
 }
 
 impl OptimisationPriceFinder {
@@ -224,8 +337,113 @@ impl OptimisationPriceFinder {
             internal_optimizer,
             solver_metrics,
             stablex_metrics,
+            price_bounds_deviation: None,
+            schema_version: Mutex::new(None),
+            batch_timing: BatchTiming::default(),
         }
     }
+
+    /// Uses the batch timing of an exchange deployment with a different batch
+    /// duration than the production one.
+    pub fn with_batch_timing(mut self, batch_timing: BatchTiming) -> Self {
+        self.batch_timing = batch_timing;
+        self
+    }
+
+    /// Bounds the prices of tokens to the specified relative deviation from
+    /// their prices of external sources, so that orders with extreme limit
+    /// prices cannot make the solver output absurd prices. Tokens without an
+    /// external price are not bounded.
+    pub fn with_price_bounds(mut self, deviation: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&deviation),
+            "price bounds deviation must be in [0, 1)"
+        );
+        self.price_bounds_deviation = Some(deviation);
+        self
+    }
+
+    /// Runs the solver plugin instead of a solver process.
+    pub fn with_plugin(mut self, plugin: SolverPlugin) -> Self {
+        self.io_methods = Arc::new(plugin);
+        self
+    }
+
+    /// Runs the solver served over gRPC instead of a solver process.
+    pub fn with_grpc_solver(mut self, solver: GrpcSolver) -> Self {
+        self.io_methods = Arc::new(solver);
+        self
+    }
+}
+
+impl OptimisationPriceFinder {
+    /// Returns the schema version to use with the solver, negotiating it if
+    /// this has not happened yet.
+    async fn schema_version(&self) -> Result<SchemaVersion> {
+        if let Some(version) = *self.schema_version.lock().unwrap() {
+            return Ok(version);
+        }
+
+        let io_methods = self.io_methods.clone();
+        let solver_type = self.solver_type;
+        let supported =
+            blocking::unblock(move || io_methods.supported_schema_versions(solver_type))
+                .await
+                .context("error querying supported schema versions of the solver")?;
+        let version = SchemaVersion::negotiate(&supported).ok_or_else(|| {
+            anyhow!(
+                "solver supports none of the schema versions {:?}",
+                supported
+            )
+        })?;
+        info!("Using solver schema version {}", version.number());
+        *self.schema_version.lock().unwrap() = Some(version);
+        Ok(version)
+    }
+}
+
+/// Computes the bounds of token prices that deviate at most the relative
+/// deviation from their external prices.
+///
+/// The bounds are rounded towards the external price so that integer prices
+/// within them never exceed the deviation, while the external price itself is
+/// always within its bounds.
+fn price_bounds(
+    external_prices: &HashMap<TokenId, NonZeroU128>,
+    deviation: f64,
+) -> BTreeMap<TokenId, solver_input::PriceBounds> {
+    external_prices
+        .iter()
+        .map(|(token, price)| {
+            let price = price.get();
+            let bounds = solver_input::PriceBounds {
+                min: Num(((price as f64 * (1.0 - deviation)).ceil() as u128).min(price)),
+                max: Num(((price as f64 * (1.0 + deviation)).floor() as u128).max(price)),
+            };
+            (*token, bounds)
+        })
+        .collect()
+}
+
+/// Checks that the solver only assigned prices within their bounds, since
+/// solvers that do not support price bounds silently ignore them.
+fn verify_price_bounds(
+    solution: &Solution,
+    price_bounds: &BTreeMap<TokenId, solver_input::PriceBounds>,
+) -> Result<()> {
+    for (token, bounds) in price_bounds {
+        if let Some(price) = solution.price(token.0) {
+            ensure!(
+                bounds.min.0 <= price && price <= bounds.max.0,
+                "solver price {} of token {} is outside of its bounds [{}, {}]",
+                price,
+                token.0,
+                bounds.min.0,
+                bounds.max.0,
+            );
+        }
+    }
+    Ok(())
 }
 
 fn serialize_balances(
@@ -245,11 +463,27 @@ fn serialize_balances(
     accounts
 }
 
-fn deserialize_result(result: String) -> Result<(Solution, SolverStats)> {
+fn deserialize_result(result: String, version: SchemaVersion) -> Result<(Solution, SolverStats)> {
     let output: solver_output::Output = serde_json::from_str(&result)?;
+    let output_version = output.version.unwrap_or_else(|| SchemaVersion::V1.number());
+    if output_version != version.number() {
+        return Err(anyhow!(
+            "solver output has schema version {} instead of {}",
+            output_version,
+            version.number()
+        ));
+    }
     Ok(output.into_solution())
 }
 
+/// Parses a stored solver output of any schema version, so that solutions can
+/// be inspected offline.
+pub fn parse_solver_output(output: &str) -> Result<Solution> {
+    let output: solver_output::Output = serde_json::from_str(output)?;
+    let (solution, _) = output.into_solution();
+    Ok(solution)
+}
+
 #[async_trait::async_trait]
 impl PriceFinding for OptimisationPriceFinder {
     async fn find_prices(
@@ -259,18 +493,37 @@ impl PriceFinding for OptimisationPriceFinder {
         time_limit: Duration,
         min_avg_earned_fee: u128,
     ) -> Result<Solution> {
+        let schema_version = self.schema_version().await?;
         let price_oracle = &*self.price_oracle;
+        let tokens = price_oracle.get_token_prices(&orders).await;
+        let price_bounds = match self.price_bounds_deviation {
+            Some(deviation) => {
+                // The price of the reference token is fixed by the exchange.
+                let bounded_tokens = tokens
+                    .keys()
+                    .copied()
+                    .filter(|token| *token != TokenId::reference())
+                    .collect::<Vec<_>>();
+                let external_prices = price_oracle.get_external_prices(&bounded_tokens).await;
+                price_bounds(&external_prices, deviation)
+            }
+            None => BTreeMap::new(),
+        };
         let input = solver_input::Input {
-            tokens: price_oracle.get_token_prices(&orders).await,
+            tokens,
             ref_token: TokenId(0),
             accounts: serialize_balances(&state, &orders),
             orders: orders.iter().map(From::from).collect(),
             fee: self.fee.as_ref().map(From::from),
+            price_bounds: price_bounds.clone(),
         };
 
         let now = Utc::now();
         // We are solving the batch before the current one
-        let batch_id = (now.timestamp() / 300) - 1;
+        let batch_id = self
+            .batch_timing
+            .batch_at_timestamp(now.timestamp() as u64)
+            .prev();
         let date = now.format("%Y-%m-%d");
         let current_directory = env::current_dir()?;
 
@@ -282,12 +535,15 @@ impl PriceFinding for OptimisationPriceFinder {
             &now.to_rfc3339()
         );
 
+        // The solver type is part of the result folder since solvers racing
+        // for the same batch would otherwise overwrite each other's results.
         let result_folder = format!(
-            "{}/results/{}/instance_{}_{}/",
+            "{}/results/{}/instance_{}_{}_{:?}/",
             &current_directory.display(),
             &date,
             &batch_id,
-            &now.to_rfc3339()
+            &now.to_rfc3339(),
+            self.solver_type
         );
 
         // `blocking::unblock` requires the closure to be 'static.
@@ -301,7 +557,7 @@ impl PriceFinding for OptimisationPriceFinder {
         let result = blocking::unblock(move || {
             io_methods.run_solver(
                 &input_file,
-                &serde_json::to_string(&input)?,
+                &input.to_json(schema_version)?,
                 &result_folder,
                 solver_type,
                 time_limit,
@@ -311,9 +567,10 @@ impl PriceFinding for OptimisationPriceFinder {
         })
         .await
         .with_context(|| format!("error running {:?} solver", self.solver_type))?;
-        let (solution, solver_stats) =
-            deserialize_result(result).context("error deserializing solver output")?;
+        let (solution, solver_stats) = deserialize_result(result, schema_version)
+            .context("error deserializing solver output")?;
         self.solver_metrics.handle_stats(&solver_stats);
+        verify_price_bounds(&solution, &price_bounds)?;
         Ok(solution)
     }
 }
@@ -321,7 +578,7 @@ impl PriceFinding for OptimisationPriceFinder {
 pub struct DefaultIo;
 
 impl DefaultIo {
-    fn write_input(&self, input_file: &str, input: &str) -> std::io::Result<()> {
+    pub(super) fn write_input(&self, input_file: &str, input: &str) -> std::io::Result<()> {
         if let Some(parent) = Path::new(input_file).parent() {
             create_dir_all(parent)?;
         }
@@ -376,6 +633,19 @@ impl Io for DefaultIo {
         self.read_output(result_folder)
             .with_context(|| format!("error reading solver output from {}", result_folder))
     }
+
+    fn supported_schema_versions(&self, solver: SolverType) -> Result<Vec<u32>> {
+        let output = solver.query_schema_versions()?;
+        if !output.status.success() {
+            // Solvers predating the schema versions do not know the argument.
+            info!(
+                "Solver does not report supported schema versions - stderr: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return Ok(vec![SchemaVersion::V1.number()]);
+        }
+        serde_json::from_slice(&output.stdout).context("error parsing supported schema versions")
+    }
 }
 
 #[cfg(test)]
@@ -458,7 +728,7 @@ pub mod tests {
             ],
         };
 
-        let solution = deserialize_result(json.to_string())
+        let solution = deserialize_result(json.to_string(), SchemaVersion::V1)
             .expect("Should not fail to parse")
             .0;
         assert_eq!(solution, expected_solution);
@@ -478,7 +748,9 @@ pub mod tests {
                 "d": null
             }
         });
-        let stats = deserialize_result(json.to_string()).unwrap().1;
+        let stats = deserialize_result(json.to_string(), SchemaVersion::V1)
+            .unwrap()
+            .1;
         assert_eq!(stats.obj_vals.len(), 2);
         assert_eq!(stats.solver.len(), 2);
     }
@@ -491,7 +763,7 @@ pub mod tests {
                 "TB": "2",
             },
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
 
         let json = json!({
             "orders": [],
@@ -499,7 +771,7 @@ pub mod tests {
                 "tkn1": "1",
             },
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
 
         let json = json!({
             "orders": [],
@@ -507,7 +779,7 @@ pub mod tests {
                 "TX": "1",
             },
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
 
         let json = json!({
             "orders": [],
@@ -515,7 +787,7 @@ pub mod tests {
                 "T9999999999": "1",
             },
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
     }
 
     #[test]
@@ -523,7 +795,7 @@ pub mod tests {
         let json = json!({
             "orders": []
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
     }
 
     #[test]
@@ -533,7 +805,7 @@ pub mod tests {
                 "T0000": "100",
             },
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
     }
 
     #[test]
@@ -550,7 +822,7 @@ pub mod tests {
                 }
             ]
         });
-        let result = deserialize_result(json.to_string())
+        let result = deserialize_result(json.to_string(), SchemaVersion::V1)
             .map_err(|err| err.to_string())
             .expect("Should not fail to parse")
             .0;
@@ -572,7 +844,7 @@ pub mod tests {
                 }
             ]
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
     }
 
     #[test]
@@ -589,7 +861,7 @@ pub mod tests {
                 }
             ]
         });
-        let result = deserialize_result(json.to_string())
+        let result = deserialize_result(json.to_string(), SchemaVersion::V1)
             .expect("Should not fail to parse")
             .0;
         assert_eq!(result.executed_orders[0].buy_amount, 0);
@@ -610,7 +882,7 @@ pub mod tests {
                 }
             ]
         });
-        deserialize_result(json.to_string()).expect_err("Should fail to parse");
+        deserialize_result(json.to_string(), SchemaVersion::V1).expect_err("Should fail to parse");
     }
 
     #[test]
@@ -693,6 +965,9 @@ pub mod tests {
             internal_optimizer: InternalOptimizer::Scip,
             solver_metrics: SolverMetrics::new(Arc::new(Registry::new())),
             stablex_metrics: Arc::new(StableXMetrics::new(Arc::new(Registry::new()))),
+            price_bounds_deviation: None,
+            schema_version: Mutex::new(Some(SchemaVersion::V2)),
+            batch_timing: BatchTiming::default(),
         };
         let orders = vec![];
         assert!(solver
@@ -764,6 +1039,7 @@ pub mod tests {
             accounts,
             orders: orders.iter().map(From::from).collect(),
             fee: None,
+            price_bounds: BTreeMap::new(),
         };
         let result = serde_json::to_string(&input).expect("Unable to serialize account state");
         assert_eq!(
@@ -771,4 +1047,235 @@ pub mod tests {
             r#"{"tokens":{"T0001":null,"T0002":{"alias":"T1","decimals":18,"externalPrice":1000000000000000000}},"refToken":"T0000","accounts":{"0x13a0b42b9c180065510615972858bf41d1972a55":{},"0x4fd7c947ca0aba9d8678885e2b8c4d6a4e946984":{"T0000":"100","T0001":"100","T0002":"100","T0003":"100"}},"orders":[{"accountID":"0x0000000000000000000000000000000000000000","sellToken":"T0001","buyToken":"T0002","sellAmount":"100","buyAmount":"200","orderID":0},{"accountID":"0x0000000000000000000000000000000000000001","sellToken":"T0002","buyToken":"T0001","sellAmount":"200","buyAmount":"100","orderID":0}],"fee":null}"#
         );
     }
+
+    #[test]
+    fn computes_price_bounds_from_external_prices() {
+        let external_prices = hash_map! {
+            TokenId(1) => nonzero!(1_000_000),
+            TokenId(2) => nonzero!(1),
+        };
+        assert_eq!(
+            price_bounds(&external_prices, 0.5),
+            btree_map! {
+                TokenId(1) => solver_input::PriceBounds {
+                    min: Num(500_000),
+                    max: Num(1_500_000),
+                },
+                TokenId(2) => solver_input::PriceBounds {
+                    min: Num(1),
+                    max: Num(1),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn price_bounds_are_rounded_towards_external_price() {
+        let large_price = 10u128.pow(30) + 1;
+        let external_prices = hash_map! {
+            TokenId(1) => nonzero!(3),
+            TokenId(2) => NonZeroU128::new(large_price).unwrap(),
+        };
+        let bounds = price_bounds(&external_prices, 0.0);
+        assert_eq!(
+            bounds[&TokenId(1)],
+            solver_input::PriceBounds {
+                min: Num(3),
+                max: Num(3),
+            }
+        );
+        assert_eq!(
+            bounds[&TokenId(2)],
+            solver_input::PriceBounds {
+                min: Num(large_price),
+                max: Num(large_price),
+            }
+        );
+        assert_eq!(
+            price_bounds(&external_prices, 0.1)[&TokenId(1)],
+            solver_input::PriceBounds {
+                min: Num(3),
+                max: Num(3),
+            }
+        );
+    }
+
+    #[test]
+    fn verifies_solution_prices_against_bounds() {
+        let price_bounds = btree_map! {
+            TokenId(1) => solver_input::PriceBounds { min: Num(90), max: Num(110) },
+            TokenId(2) => solver_input::PriceBounds { min: Num(1), max: Num(2) },
+        };
+        let solution = |price: u128| Solution {
+            prices: hash_map! { 0 => 1, 1 => price },
+            executed_orders: vec![],
+        };
+        assert!(verify_price_bounds(&solution(90), &price_bounds).is_ok());
+        assert!(verify_price_bounds(&solution(110), &price_bounds).is_ok());
+        assert!(verify_price_bounds(&solution(89), &price_bounds).is_err());
+        assert!(verify_price_bounds(&solution(111), &price_bounds).is_err());
+    }
+
+    #[test]
+    fn serializes_price_bounds_of_tokens_except_reference_token() {
+        let mut price_oracle = MockPriceEstimating::new();
+        price_oracle.expect_get_token_prices().returning(|_| {
+            btree_map! {
+                TokenId(0) => Some(TokenInfo::new("OWL", 18, 1_000_000_000_000_000_000)),
+                TokenId(1) => None,
+            }
+        });
+        price_oracle
+            .expect_get_external_prices()
+            .withf(|tokens| tokens == [TokenId(1)])
+            .returning(|_| hash_map! { TokenId(1) => nonzero!(100) });
+
+        let mut io_methods = MockIo::new();
+        io_methods
+            .expect_run_solver()
+            .times(1)
+            .withf(|_, content: &str, _, _, _, _, _| {
+                let json: serde_json::value::Value = serde_json::from_str(content).unwrap();
+                json["priceBounds"]
+                    == json!({
+                        "T0001": { "min": "90", "max": "110" },
+                    })
+            })
+            .returning(|_, _, _, _, _, _, _| Err(anyhow!("")));
+        let solver = OptimisationPriceFinder {
+            io_methods: Arc::new(io_methods),
+            fee: None,
+            solver_type: SolverType::StandardSolver,
+            price_oracle: Arc::new(price_oracle),
+            internal_optimizer: InternalOptimizer::Scip,
+            solver_metrics: SolverMetrics::new(Arc::new(Registry::new())),
+            stablex_metrics: Arc::new(StableXMetrics::new(Arc::new(Registry::new()))),
+            price_bounds_deviation: None,
+            schema_version: Mutex::new(Some(SchemaVersion::V2)),
+            batch_timing: BatchTiming::default(),
+        }
+        .with_price_bounds(0.1);
+        let orders = vec![];
+        assert!(solver
+            .find_prices(
+                &orders,
+                &AccountState::with_balance_for(&orders),
+                Duration::from_secs(180),
+                10u128.pow(18)
+            )
+            .wait()
+            .is_err());
+    }
+
+    #[test]
+    fn negotiates_latest_common_schema_version() {
+        assert_eq!(SchemaVersion::negotiate(&[1]), Some(SchemaVersion::V1));
+        assert_eq!(SchemaVersion::negotiate(&[1, 2]), Some(SchemaVersion::V2));
+        assert_eq!(SchemaVersion::negotiate(&[2, 3]), Some(SchemaVersion::V2));
+        assert_eq!(SchemaVersion::negotiate(&[3]), None);
+    }
+
+    #[test]
+    fn serializes_input_in_schema_version() {
+        let input = || solver_input::Input {
+            tokens: btree_map! { TokenId(0) => None },
+            ref_token: TokenId::reference(),
+            accounts: solver_input::Accounts::new(),
+            orders: vec![],
+            fee: None,
+            price_bounds: btree_map! {
+                TokenId(1) => solver_input::PriceBounds { min: Num(1), max: Num(2) },
+            },
+        };
+
+        let v1: serde_json::Value =
+            serde_json::from_str(&input().to_json(SchemaVersion::V1).unwrap()).unwrap();
+        assert_eq!(
+            v1,
+            json!({
+                "tokens": { "T0000": null },
+                "refToken": "T0000",
+                "accounts": {},
+                "orders": [],
+                "fee": null,
+            })
+        );
+
+        let v2: serde_json::Value =
+            serde_json::from_str(&input().to_json(SchemaVersion::V2).unwrap()).unwrap();
+        assert_eq!(
+            v2,
+            json!({
+                "version": 2,
+                "tokens": { "T0000": null },
+                "refToken": "T0000",
+                "accounts": {},
+                "orders": [],
+                "fee": null,
+                "priceBounds": { "T0001": { "min": "1", "max": "2" } },
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_result_checks_schema_version() {
+        let output = |version: Option<u32>| {
+            let mut json = json!({ "prices": {}, "orders": [] });
+            if let Some(version) = version {
+                json["version"] = json!(version);
+            }
+            json.to_string()
+        };
+
+        assert!(deserialize_result(output(None), SchemaVersion::V1).is_ok());
+        assert!(deserialize_result(output(Some(2)), SchemaVersion::V2).is_ok());
+        assert!(deserialize_result(output(None), SchemaVersion::V2).is_err());
+        assert!(deserialize_result(output(Some(2)), SchemaVersion::V1).is_err());
+    }
+
+    #[test]
+    fn negotiates_schema_version_once() {
+        let mut price_oracle = MockPriceEstimating::new();
+        price_oracle
+            .expect_get_token_prices()
+            .returning(|_| btree_map! { TokenId(0) => None });
+
+        let mut io_methods = MockIo::new();
+        io_methods
+            .expect_supported_schema_versions()
+            .times(1)
+            .returning(|_| Ok(vec![1]));
+        io_methods
+            .expect_run_solver()
+            .times(2)
+            .withf(|_, content: &str, _, _, _, _, _| {
+                let json: serde_json::value::Value = serde_json::from_str(content).unwrap();
+                json.get("version").is_none()
+            })
+            .returning(|_, _, _, _, _, _, _| Ok(json!({ "prices": {}, "orders": [] }).to_string()));
+        let solver = OptimisationPriceFinder {
+            io_methods: Arc::new(io_methods),
+            fee: None,
+            solver_type: SolverType::StandardSolver,
+            price_oracle: Arc::new(price_oracle),
+            internal_optimizer: InternalOptimizer::Scip,
+            solver_metrics: SolverMetrics::new(Arc::new(Registry::new())),
+            stablex_metrics: Arc::new(StableXMetrics::new(Arc::new(Registry::new()))),
+            price_bounds_deviation: None,
+            schema_version: Mutex::new(None),
+            batch_timing: BatchTiming::default(),
+        };
+        let orders = vec![];
+        for _ in 0..2 {
+            assert!(solver
+                .find_prices(
+                    &orders,
+                    &AccountState::with_balance_for(&orders),
+                    Duration::from_secs(180),
+                    10u128.pow(18)
+                )
+                .wait()
+                .is_ok());
+        }
+    }
 }
@@ -62,6 +62,8 @@ arg_enum! {
         StandardSolver,
         OpenSolver,
         BestRingSolver,
+        PluginSolver,
+        GrpcSolver,
     }
 }
 
@@ -90,8 +92,41 @@ impl SolverType {
             SolverType::NaiveSolver => {
                 panic!("fn execute should not be called by the naive solver")
             }
+            SolverType::PluginSolver | SolverType::GrpcSolver => {
+                panic!("fn execute should not be called by in-process or remote solvers")
+            }
         }
     }
+
+    /// Asks the solver for the versions of the input and output JSON schema
+    /// that it supports. Solvers print them as a JSON array of integers.
+    pub fn query_schema_versions(self) -> Result<Output> {
+        let mut command = match self {
+            SolverType::OpenSolver => {
+                let mut command = Command::new("gp_match");
+                command.arg("--supported-schema-versions");
+                command
+            }
+            SolverType::StandardSolver | SolverType::BestRingSolver => {
+                let mut command = Command::new("python");
+                command
+                    .current_dir("/app/batchauctions")
+                    .args(&["-m", "src._run"])
+                    .arg("--supportedSchemaVersions");
+                command
+            }
+            SolverType::NaiveSolver => {
+                panic!("fn query_schema_versions should not be called by the naive solver")
+            }
+            SolverType::PluginSolver | SolverType::GrpcSolver => {
+                panic!(
+                    "fn query_schema_versions should not be called by in-process or remote solvers"
+                )
+            }
+        };
+        debug!("Using schema version command `{:?}`", command);
+        Ok(command.output()?)
+    }
 }
 
 pub fn execute_open_solver(
@@ -0,0 +1,430 @@
+//! Module implementing solvers that are loaded from shared libraries, so that
+//! research solvers written in Rust or C++ can be iterated on without
+//! rebuilding the driver or shelling out to Python.
+//!
+//! A plugin exports the following functions with the C ABI:
+//!
+//! ```c
+//! // The version of this interface implemented by the plugin, currently 1.
+//! uint32_t dfusion_solver_abi_version(void);
+//!
+//! // The solver schema versions supported by the plugin as a JSON array, for
+//! // example "[2]". The string is owned by the plugin.
+//! const char *dfusion_solver_schema_versions(void);
+//!
+//! // Solves a batch. `input` is the solver input JSON and `options` a JSON
+//! // object with the `timeLimit` in seconds, the `minAvgFeePerOrder` as a
+//! // decimal string and the `memoryLimit` in bytes. Returns the solver output
+//! // JSON, which is released with `dfusion_solver_free`, or null on failure.
+//! char *dfusion_solver_solve(const char *input, const char *options);
+//!
+//! void dfusion_solver_free(char *output);
+//! ```
+//!
+//! Plugins are not loaded into the driver but into a host process, which is
+//! the driver executable started with `HOST_ARG`. This way the memory limit
+//! and the time limit of a batch are enforced with resource limits of the
+//! host process, which is killed if it has not returned by the deadline, and
+//! a crashing plugin does not take down the driver.
+
+use super::optimization_price_finder::{DefaultIo, Io, Num};
+use super::price_finder_interface::{InternalOptimizer, SolverType};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use libloading::Library;
+use serde::Serialize;
+use std::{
+    env,
+    ffi::{CStr, CString, OsString},
+    io::{self, Read, Write},
+    os::{raw::c_char, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// The version of the plugin interface implemented by the driver.
+pub const ABI_VERSION: u32 = 1;
+
+/// The first argument with which the driver executable is started as the host
+/// process of a solver plugin.
+pub const HOST_ARG: &str = "solver-plugin-host";
+
+/// The time a plugin may run past its time limit, which accounts for loading
+/// the plugin, parsing the input and serializing the output.
+const TIME_LIMIT_GRACE: Duration = Duration::from_secs(10);
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type SchemaVersionsFn = unsafe extern "C" fn() -> *const c_char;
+type SolveFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Options {
+    time_limit: u64,
+    min_avg_fee_per_order: Num<u128>,
+    memory_limit: u64,
+}
+
+/// A solver plugin that is run in a host process for every call.
+pub struct SolverPlugin {
+    /// The host executable and the arguments selecting the plugin, which are
+    /// followed by the command for the host.
+    host: PathBuf,
+    host_args: Vec<OsString>,
+    memory_limit: u64,
+    time_limit_grace: Duration,
+}
+
+impl SolverPlugin {
+    /// Creates the plugin from the shared library at the specified path, which
+    /// is loaded once to check that it implements the plugin interface. The
+    /// memory limit in bytes is enforced on the host process and passed to the
+    /// plugin with every batch.
+    pub fn load(path: &Path, memory_limit: u64) -> Result<Self> {
+        let plugin = SolverPlugin {
+            host: env::current_exe().context("error locating the solver plugin host")?,
+            host_args: vec![HOST_ARG.into(), path.into()],
+            memory_limit,
+            time_limit_grace: TIME_LIMIT_GRACE,
+        };
+        plugin
+            .schema_versions()
+            .with_context(|| format!("error loading solver plugin {}", path.display()))?;
+        Ok(plugin)
+    }
+
+    fn schema_versions(&self) -> Result<String> {
+        self.run_host("schema-versions", None, "", Duration::from_secs(0))
+    }
+
+    /// Runs the plugin in a host process and kills it if it did not return
+    /// within the time limit plus the grace period.
+    fn solve(&self, input: &str, options: &str, time_limit: Duration) -> Result<String> {
+        self.run_host("solve", Some(options), input, time_limit)
+    }
+
+    fn run_host(
+        &self,
+        command: &str,
+        options: Option<&str>,
+        input: &str,
+        time_limit: Duration,
+    ) -> Result<String> {
+        let deadline = time_limit + self.time_limit_grace;
+        let mut host = Command::new(&self.host);
+        host.args(&self.host_args)
+            .arg(command)
+            .args(options)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        let memory_limit = self.memory_limit;
+        // Limiting the CPU time to the deadline is a last resort in case
+        // killing the host fails, as it can only exceed the limit that early
+        // if the plugin is multithreaded.
+        let cpu_time_limit = deadline.as_secs() + 1;
+        // Setting resource limits is async-signal-safe, so it can be done
+        // between forking and executing the host.
+        unsafe {
+            host.pre_exec(move || {
+                if memory_limit > 0 {
+                    check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(memory_limit)))?;
+                }
+                check(libc::setrlimit(libc::RLIMIT_CPU, &rlimit(cpu_time_limit)))
+            });
+        }
+
+        let mut child = host
+            .spawn()
+            .context("error starting the solver plugin host")?;
+        let mut stdin = child.stdin.take().expect("host stdin is piped");
+        let input = input.to_owned();
+        // The input and output are transferred on separate threads as the host
+        // might not read all of its input before writing output.
+        thread::spawn(move || {
+            // The host closing its input early is reported by its exit status.
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        let mut stdout = child.stdout.take().expect("host stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = String::new();
+            let result = stdout.read_to_string(&mut output).map(|_| output);
+            let _ = sender.send(result);
+        });
+
+        let output = match receiver.recv_timeout(deadline) {
+            Ok(output) => output,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!(
+                    "solver plugin exceeded its time limit of {}s and was killed",
+                    time_limit.as_secs()
+                );
+            }
+        };
+        let status = child.wait()?;
+        ensure!(
+            status.success(),
+            "solver plugin host failed with {}",
+            status
+        );
+        output.context("solver plugin output is not UTF-8")
+    }
+}
+
+fn rlimit(limit: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: limit as _,
+        rlim_max: limit as _,
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+impl Io for SolverPlugin {
+    fn run_solver(
+        &self,
+        input_file: &str,
+        input: &str,
+        _result_folder: &str,
+        _solver_type: SolverType,
+        time_limit: Duration,
+        min_avg_fee_per_order: u128,
+        _internal_optimizer: InternalOptimizer,
+    ) -> Result<String> {
+        // The instance is stored like for the other solvers so that batches
+        // can be replayed while iterating on the plugin.
+        DefaultIo
+            .write_input(input_file, input)
+            .with_context(|| format!("error writing instance to {}", input_file))?;
+
+        let options = serde_json::to_string(&Options {
+            time_limit: time_limit.as_secs_f64().round() as u64,
+            min_avg_fee_per_order: Num(min_avg_fee_per_order),
+            memory_limit: self.memory_limit,
+        })?;
+        self.solve(input, &options, time_limit)
+    }
+
+    fn supported_schema_versions(&self, _solver_type: SolverType) -> Result<Vec<u32>> {
+        serde_json::from_str(&self.schema_versions()?)
+            .context("error parsing supported schema versions of the solver plugin")
+    }
+}
+
+/// Runs the host process of a solver plugin and exits if the executable was
+/// started as one, which has to be checked before parsing any options.
+pub fn run_host_if_requested() {
+    let args = env::args_os().collect::<Vec<_>>();
+    if args.get(1).map(|arg| arg == HOST_ARG) != Some(true) {
+        return;
+    }
+    if let Err(err) = run_host(&args[2..]) {
+        eprintln!("solver plugin host failed: {:?}", err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+/// Runs a host command with the plugin at the path of the first argument.
+fn run_host(args: &[OsString]) -> Result<()> {
+    let (path, command, options) = match args {
+        [path, command] => (path, command, None),
+        [path, command, options] => (path, command, Some(options)),
+        _ => bail!("invalid solver plugin host arguments {:?}", args),
+    };
+    let plugin = LoadedPlugin::load(Path::new(path))?;
+    let output = match (
+        command.to_str(),
+        options.and_then(|options| options.to_str()),
+    ) {
+        (Some("schema-versions"), None) => plugin.schema_versions()?,
+        (Some("solve"), Some(options)) => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            plugin.solve(&input, options)?
+        }
+        _ => bail!("invalid solver plugin host arguments {:?}", args),
+    };
+    io::stdout().write_all(output.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+struct Functions {
+    schema_versions: SchemaVersionsFn,
+    solve: SolveFn,
+    free: FreeFn,
+}
+
+/// A solver plugin loaded into the host process.
+struct LoadedPlugin {
+    /// The library that the functions point into, which must therefore be
+    /// kept loaded as long as they are used.
+    _library: Library,
+    functions: Functions,
+}
+
+impl LoadedPlugin {
+    fn load(path: &Path) -> Result<Self> {
+        // Loading the library runs its initialization routines, which we have
+        // to trust just like the rest of the plugin.
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("error loading solver plugin {}", path.display()))?;
+        let (abi_version, functions) = unsafe {
+            let abi_version: AbiVersionFn = *library.get(b"dfusion_solver_abi_version\0")?;
+            let functions = Functions {
+                schema_versions: *library.get(b"dfusion_solver_schema_versions\0")?,
+                solve: *library.get(b"dfusion_solver_solve\0")?,
+                free: *library.get(b"dfusion_solver_free\0")?,
+            };
+            (abi_version(), functions)
+        };
+        if abi_version != ABI_VERSION {
+            return Err(anyhow!(
+                "solver plugin {} implements ABI version {} instead of {}",
+                path.display(),
+                abi_version,
+                ABI_VERSION
+            ));
+        }
+        Ok(LoadedPlugin {
+            _library: library,
+            functions,
+        })
+    }
+
+    fn schema_versions(&self) -> Result<String> {
+        let versions = unsafe { CStr::from_ptr((self.functions.schema_versions)()) };
+        Ok(versions.to_str()?.to_owned())
+    }
+
+    fn solve(&self, input: &str, options: &str) -> Result<String> {
+        let input = CString::new(input)?;
+        let options = CString::new(options)?;
+        unsafe { call(self.functions, &input, &options) }
+    }
+}
+
+/// Calls the solve function of the plugin and copies and releases its output.
+///
+/// # Safety
+///
+/// The functions must be valid implementations of the plugin interface.
+unsafe fn call(functions: Functions, input: &CStr, options: &CStr) -> Result<String> {
+    let output = (functions.solve)(input.as_ptr(), options.as_ptr());
+    if output.is_null() {
+        return Err(anyhow!("solver plugin failed to solve the batch"));
+    }
+    let result = CStr::from_ptr(output)
+        .to_str()
+        .map(str::to_owned)
+        .context("solver plugin output is not UTF-8");
+    (functions.free)(output);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    unsafe extern "C" fn schema_versions() -> *const c_char {
+        b"[1, 2]\0".as_ptr() as _
+    }
+
+    unsafe extern "C" fn echo(input: *const c_char, _: *const c_char) -> *mut c_char {
+        CStr::from_ptr(input).to_owned().into_raw()
+    }
+
+    unsafe extern "C" fn fail(_: *const c_char, _: *const c_char) -> *mut c_char {
+        std::ptr::null_mut()
+    }
+
+    unsafe extern "C" fn free(output: *mut c_char) {
+        drop(CString::from_raw(output));
+    }
+
+    fn functions(solve: SolveFn) -> Functions {
+        Functions {
+            schema_versions,
+            solve,
+            free,
+        }
+    }
+
+    /// A plugin with a shell script as its host, which is passed the host
+    /// command and options as `$1` and `$2`.
+    fn scripted_plugin(script: &str) -> SolverPlugin {
+        SolverPlugin {
+            host: "/bin/sh".into(),
+            host_args: vec!["-c".into(), script.into(), "sh".into()],
+            memory_limit: 0,
+            time_limit_grace: Duration::from_millis(200),
+        }
+    }
+
+    #[test]
+    fn calls_plugin_functions() {
+        assert_eq!(
+            unsafe {
+                call(
+                    functions(echo),
+                    &CString::new("{}").unwrap(),
+                    &CString::default(),
+                )
+            }
+            .unwrap(),
+            "{}"
+        );
+        assert!(unsafe {
+            call(
+                functions(fail),
+                &CString::new("{}").unwrap(),
+                &CString::default(),
+            )
+        }
+        .is_err());
+    }
+
+    #[test]
+    fn solves_and_reports_schema_versions_in_host() {
+        let plugin =
+            scripted_plugin(r#"case "$1" in schema-versions) echo "[1, 2]";; solve) cat;; esac"#);
+        assert_eq!(
+            plugin
+                .supported_schema_versions(SolverType::PluginSolver)
+                .unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            plugin.solve("{}", "{}", Duration::from_secs(1)).unwrap(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn reports_failing_host() {
+        let plugin = scripted_plugin("cat >/dev/null; exit 1");
+        assert!(plugin.solve("{}", "{}", Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn kills_host_exceeding_time_limit() {
+        let plugin = scripted_plugin("exec sleep 10");
+        let start = Instant::now();
+        assert!(plugin.solve("{}", "{}", Duration::from_secs(0)).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,19 +1,27 @@
-mod gas_price_stream;
+pub(crate) mod gas_price_stream;
+mod round_robin;
+mod transaction_monitor;
+
+pub use self::{round_robin::RoundRobinSolutionSubmitter, transaction_monitor::TransactionMonitor};
 
 use crate::{
-    contracts::stablex_contract::StableXContract,
-    models::{BatchId, Solution},
+    contracts::stablex_contract::{StableXContract, SOLUTION_SUBMISSION_GAS_LIMIT},
+    error::ErrorCode,
+    gas_price::{GasEstimateFeedback, SubmissionEstimator},
+    models::{batch_id::BatchTiming, BatchId, Solution},
     util::AsyncSleeping,
 };
 use anyhow::{anyhow, Error, Result};
 use ethcontract::{
     errors::{ExecutionError, MethodError},
     jsonrpc::types::Error as RpcError,
+    transaction::TransactionResult as SentTransaction,
     web3::{error::Error as Web3Error, types::TransactionReceipt},
-    U256,
+    H256, U256,
 };
 use futures::future::FutureExt as _;
 use gas_estimation::GasPriceEstimating;
+use serde::Serialize;
 use std::{
     str::FromStr,
     sync::Arc,
@@ -38,25 +46,77 @@ pub trait StableXSolutionSubmitting {
         solution: Solution,
     ) -> Result<U256, SolutionSubmissionError>;
 
-    /// Submits the provided solution and returns the result of the submission
+    /// Simulates the submission of a verified solution and returns the gas
+    /// limit to submit it with, which is the gas used by the simulation plus a
+    /// safety margin.
+    async fn estimate_gas_limit(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256, SolutionSubmissionError>;
+
+    /// Submits the provided solution and returns the receipt of the mined
+    /// transaction
     ///
     /// # Arguments
     /// * `batch_index` - the auction for which this solutions should be evaluated
     /// * `orders` - the list of orders for which this solution is applicable
     /// * `solution` - the solution to be evaluated
     /// * `claimed_objective_value` - the objective value of the provided solution.
+    /// * `gas_limit` - the gas limit of every transaction submitting the solution.
     async fn submit_solution(
         &self,
         batch_index: u32,
         solution: Solution,
         claimed_objective_value: U256,
+        gas_limit: U256,
         gas_price_cap: f64,
-    ) -> Result<(), SolutionSubmissionError>;
+    ) -> Result<SubmissionReceipt, SolutionSubmissionError>;
+
+    /// Returns true if a previously sent transaction of the submitting account
+    /// might still be pending and block the nonce of the next submission.
+    fn has_pending_transactions(&self) -> bool;
+}
+
+/// The receipt of a mined solution submission transaction.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionReceipt {
+    pub batch_index: u32,
+    pub transaction_hash: H256,
+    /// The block the transaction was mined in. Only missing if the node did
+    /// not return the receipt of the transaction.
+    pub block_number: Option<u64>,
+    /// The gas price the mined transaction was sent with, which is the price
+    /// paid per unit of gas.
+    pub gas_price: U256,
+    pub gas_used: Option<U256>,
+}
+
+impl SubmissionReceipt {
+    fn new(batch_index: u32, gas_price: U256, transaction: SentTransaction) -> Self {
+        match transaction {
+            SentTransaction::Receipt(receipt) => Self {
+                batch_index,
+                transaction_hash: receipt.transaction_hash,
+                block_number: receipt.block_number.map(|block| block.as_u64()),
+                gas_price,
+                gas_used: receipt.gas_used,
+            },
+            SentTransaction::Hash(transaction_hash) => Self {
+                batch_index,
+                transaction_hash,
+                gas_price,
+                ..Default::default()
+            },
+        }
+    }
 }
 
 /// Configuration for specifying additional errors that are considered benign
 /// during solution submission.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CustomBenignErrors(Vec<String>);
 
 /// Revert reasons that are always considered benign regardless of configuration.
@@ -114,11 +174,40 @@ impl SolutionSubmissionError {
     }
 }
 
+/// The default relative margin added to the simulated gas use of a solution
+/// for the gas limit of its submission. Other solutions might be submitted
+/// before ours, in which case reverting their trades costs additional gas.
+pub const DEFAULT_GAS_LIMIT_MARGIN: f64 = 0.5;
+
+/// Limits how often a pending solution transaction is replaced with a higher
+/// gas price, so that the node is not spammed with replacements.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplacementPolicy {
+    /// The minimum time between replacements.
+    pub min_interval: Duration,
+    /// The minimum relative increase of the estimated gas price compared to
+    /// the estimate of the previous transaction, for example 0.1 for 10%.
+    pub min_increase: f64,
+}
+
+impl Default for ReplacementPolicy {
+    fn default() -> Self {
+        ReplacementPolicy {
+            min_interval: Duration::from_secs(0),
+            min_increase: 0.0,
+        }
+    }
+}
+
 pub struct StableXSolutionSubmitter {
     contract: Arc<dyn StableXContract>,
     gas_price_estimator: Arc<dyn GasPriceEstimating>,
     custom_benign_errors: CustomBenignErrors,
     async_sleep: Box<dyn AsyncSleeping>,
+    transaction_monitor: TransactionMonitor,
+    gas_estimate_feedback: Option<Arc<GasEstimateFeedback>>,
+    replacement_policy: ReplacementPolicy,
+    gas_limit_margin: f64,
 }
 
 impl StableXSolutionSubmitter {
@@ -142,13 +231,69 @@ impl StableXSolutionSubmitter {
         async_sleep: impl AsyncSleeping,
     ) -> Self {
         Self {
+            transaction_monitor: TransactionMonitor::new(
+                contract.clone(),
+                gas_price_estimator.clone(),
+            ),
             contract,
             gas_price_estimator,
             custom_benign_errors,
             async_sleep: Box::new(async_sleep),
+            gas_estimate_feedback: None,
+            replacement_policy: ReplacementPolicy::default(),
+            gas_limit_margin: DEFAULT_GAS_LIMIT_MARGIN,
+        }
+    }
+
+    /// Sets the relative margin, for example 0.2 for 20%, that is added to the
+    /// simulated gas use of a solution for the gas limit of its submission.
+    pub fn with_gas_limit_margin(mut self, gas_limit_margin: f64) -> Self {
+        self.gas_limit_margin = gas_limit_margin;
+        self
+    }
+
+    /// Sets how often pending transactions may be replaced.
+    pub fn with_replacement_policy(mut self, replacement_policy: ReplacementPolicy) -> Self {
+        self.replacement_policy = replacement_policy;
+        self
+    }
+
+    /// Estimates the gas prices of submissions with the estimator of the
+    /// feedback and records the outcome of every submission for the estimator
+    /// that produced the gas price it was last sent with.
+    pub fn with_gas_estimate_feedback(mut self, feedback: Arc<GasEstimateFeedback>) -> Self {
+        self.gas_estimate_feedback = Some(feedback);
+        self
+    }
+
+    fn record_gas_estimate_feedback(
+        &self,
+        submission_estimator: Option<SubmissionEstimator>,
+        submission_start: Instant,
+        time_limit: Duration,
+        mined: bool,
+    ) {
+        let (feedback, submission_estimator) =
+            match (&self.gas_estimate_feedback, submission_estimator) {
+                (Some(feedback), Some(submission_estimator)) => (feedback, submission_estimator),
+                _ => return,
+            };
+        if let Some(estimate) = submission_estimator.into_latest() {
+            let mined_after = if mined {
+                Some(submission_start.elapsed())
+            } else {
+                None
+            };
+            feedback.submission_completed(&estimate, time_limit, mined_after);
         }
     }
 
+    /// Cancels transactions of the submitting account that are still pending
+    /// and would block the nonce used for the next solution submission.
+    pub async fn recover_stuck_transactions(&self) -> Result<()> {
+        self.transaction_monitor.recover_stuck_transactions().await
+    }
+
     /// Turn a method error from a solution submission into a SolutionSubmissionError.
     async fn convert_submit_error(
         &self,
@@ -175,9 +320,9 @@ impl StableXSolutionSubmitter {
         batch_index: u32,
         solution: Solution,
         result: SolutionResult,
-    ) -> Result<(), SolutionSubmissionError> {
+    ) -> Result<SubmissionReceipt, SolutionSubmissionError> {
         match result.0 {
-            Ok(()) => Ok(()),
+            Ok(receipt) => Ok(receipt),
             Err(err) => Err(self.convert_submit_error(batch_index, solution, err).await),
         }
     }
@@ -196,18 +341,61 @@ impl StableXSolutionSubmitting for StableXSolutionSubmitter {
             .map_err(|err| SolutionSubmissionError::new(err, &self.custom_benign_errors))
     }
 
+    async fn estimate_gas_limit(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256, SolutionSubmissionError> {
+        let err = match self
+            .contract
+            .estimate_solution_gas(batch_index, solution.clone(), claimed_objective_value)
+            .await
+        {
+            Ok(gas_used) => return Ok(gas_limit_with_margin(gas_used, self.gas_limit_margin)),
+            Err(err) => err,
+        };
+        if ErrorCode::classify(&err) == Some(ErrorCode::ContractReverted) {
+            return Err(SolutionSubmissionError::new(
+                err,
+                &self.custom_benign_errors,
+            ));
+        }
+
+        // NOTE: The solution was already verified, so the simulation most
+        // likely failed because of the node and the submission can proceed.
+        // Gas estimate might be off, as we race with other solution
+        // submissions and thus might have to revert trades which costs more
+        // gas than expected, so we fall back to the fixed gas limit.
+        log::warn!(
+            "failed to simulate solution submission, using the fixed gas limit: {:?}",
+            err
+        );
+        Ok(SOLUTION_SUBMISSION_GAS_LIMIT.into())
+    }
+
     async fn submit_solution(
         &self,
         batch_index: u32,
         solution: Solution,
         claimed_objective_value: U256,
+        gas_limit: U256,
         gas_price_cap: f64,
-    ) -> Result<(), SolutionSubmissionError> {
-        let target_confirm_time = Instant::now()
-            + BatchId::from(batch_index)
-                .solve_end_time()
+    ) -> Result<SubmissionReceipt, SolutionSubmissionError> {
+        let submission_start = Instant::now();
+        let batch_timing = BatchTiming::with_batch_duration(self.contract.batch_duration());
+        let target_confirm_time = submission_start
+            + batch_timing
+                .solve_end_time(BatchId::from(batch_index))
                 .duration_since(SystemTime::now())
                 .unwrap_or_else(|_| Duration::from_secs(0));
+        // A previous submission timed out without us knowing whether its
+        // transaction was mined so it might still be blocking the nonce.
+        if self.transaction_monitor.has_outstanding_transactions() {
+            if let Err(err) = self.recover_stuck_transactions().await {
+                log::error!("failed to recover stuck transactions: {:?}", err);
+            }
+        }
         let nonce = self
             .contract
             .get_transaction_count()
@@ -221,11 +409,14 @@ impl StableXSolutionSubmitting for StableXSolutionSubmitter {
             batch_index,
             solution: solution.clone(),
             claimed_objective_value,
+            gas_limit,
             nonce,
+            transaction_monitor: &self.transaction_monitor,
         };
         let cancellation_sender = CancellationSender {
             contract: self.contract.as_ref(),
             nonce,
+            transaction_monitor: &self.transaction_monitor,
         };
         let cancel_future = async {
             let cancel_duration = cancel_instant
@@ -235,21 +426,49 @@ impl StableXSolutionSubmitting for StableXSolutionSubmitter {
             cancellation_sender
         };
 
+        let submission_estimator = self
+            .gas_estimate_feedback
+            .as_ref()
+            .map(|feedback| feedback.submission_estimator());
+        let gas_price_estimator: &dyn GasPriceEstimating = match &submission_estimator {
+            Some(submission_estimator) => submission_estimator,
+            None => self.gas_price_estimator.as_ref(),
+        };
         let stream = gas_price_stream::gas_price_stream(
             target_confirm_time,
+            SOLUTION_SUBMISSION_GAS_LIMIT as f64,
             gas_price_cap,
-            self.gas_price_estimator.as_ref(),
+            self.replacement_policy,
+            gas_price_estimator,
             self.async_sleep.as_ref(),
         );
 
         match transaction_retry::retry(solution_sender, cancel_future.boxed(), stream).await {
             Some(RetryResult::Submitted(result)) => {
                 log::info!("solution submission transaction completed first");
+                if result.was_mined() {
+                    self.transaction_monitor.transaction_mined(nonce);
+                }
+                self.record_gas_estimate_feedback(
+                    submission_estimator,
+                    submission_start,
+                    target_confirm_time - submission_start,
+                    result.was_mined(),
+                );
                 self.convert_submit_result(batch_index, solution, result)
                     .await
             }
             Some(RetryResult::Cancelled(result)) => {
                 log::info!("cancel transaction completed first");
+                if result.was_mined() {
+                    self.transaction_monitor.transaction_mined(nonce);
+                }
+                self.record_gas_estimate_feedback(
+                    submission_estimator,
+                    submission_start,
+                    target_confirm_time - submission_start,
+                    false,
+                );
                 convert_cancel_result(result)
             }
             None => {
@@ -260,6 +479,17 @@ impl StableXSolutionSubmitting for StableXSolutionSubmitter {
             }
         }
     }
+
+    fn has_pending_transactions(&self) -> bool {
+        self.transaction_monitor.has_outstanding_transactions()
+    }
+}
+
+/// The gas limit for a solution submission with the specified gas use, which
+/// never exceeds `SOLUTION_SUBMISSION_GAS_LIMIT`.
+fn gas_limit_with_margin(gas_used: U256, margin: f64) -> U256 {
+    let gas_limit = U256::from_f64_lossy(gas_used.to_f64_lossy() * (1.0 + margin));
+    gas_limit.min(SOLUTION_SUBMISSION_GAS_LIMIT.into())
 }
 
 fn extract_transaction_receipt(err: &MethodError) -> Option<&TransactionReceipt> {
@@ -269,15 +499,18 @@ fn extract_transaction_receipt(err: &MethodError) -> Option<&TransactionReceipt>
     }
 }
 
-fn convert_cancel_result(result: CancellationResult) -> Result<(), SolutionSubmissionError> {
+fn convert_cancel_result(
+    result: CancellationResult,
+) -> Result<SubmissionReceipt, SolutionSubmissionError> {
     let error = match result.0 {
-        Ok(_) => anyhow!("solution submission transaction not confirmed in time"),
+        Ok(_) => Error::new(ErrorCode::SubmissionTimeout)
+            .context("solution submission transaction not confirmed in time"),
         Err(err) => Error::from(err).context("failed to cancel solution submission"),
     };
     Err(SolutionSubmissionError::Unexpected(error))
 }
 
-fn is_transaction_error(error: &ExecutionError) -> bool {
+pub(crate) fn is_transaction_error(error: &ExecutionError) -> bool {
     // This is the error as we've seen it on openethereum nodes. The code and error messages can
     // be found in openethereum's source code in `rpc/src/v1/helpers/errors.rs`.
     // TODO: check how this looks on geth and infura. Not recognizing the error is not a serious
@@ -285,7 +518,7 @@ fn is_transaction_error(error: &ExecutionError) -> bool {
     matches!(error, ExecutionError::Web3(Web3Error::Rpc(RpcError { code, .. })) if code.code() == -32010)
 }
 
-struct SolutionResult(Result<(), MethodError>);
+struct SolutionResult(Result<SubmissionReceipt, MethodError>);
 impl TransactionResult for SolutionResult {
     fn was_mined(&self) -> bool {
         if let Err(err) = &self.0 {
@@ -301,23 +534,30 @@ struct SolutionSender<'a> {
     batch_index: u32,
     solution: Solution,
     claimed_objective_value: U256,
+    gas_limit: U256,
     nonce: U256,
+    transaction_monitor: &'a TransactionMonitor,
 }
 #[async_trait::async_trait]
 impl<'a> TransactionSending for SolutionSender<'a> {
     type Output = SolutionResult;
     async fn send(&self, gas_price: f64) -> Self::Output {
         log::info!("submitting solution transaction at gas price {}", gas_price);
+        self.transaction_monitor
+            .transaction_sent(self.nonce, gas_price);
+        let gas_price = U256::from_f64_lossy(gas_price);
         let result = self
             .contract
             .submit_solution(
                 self.batch_index,
                 self.solution.clone(),
                 self.claimed_objective_value,
-                U256::from_f64_lossy(gas_price),
+                self.gas_limit,
+                gas_price,
                 self.nonce,
             )
-            .await;
+            .await
+            .map(|transaction| SubmissionReceipt::new(self.batch_index, gas_price, transaction));
         SolutionResult(result)
     }
 }
@@ -336,12 +576,15 @@ impl TransactionResult for CancellationResult {
 struct CancellationSender<'a> {
     contract: &'a dyn StableXContract,
     nonce: U256,
+    transaction_monitor: &'a TransactionMonitor,
 }
 #[async_trait::async_trait]
 impl<'a> TransactionSending for CancellationSender<'a> {
     type Output = CancellationResult;
     async fn send(&self, gas_price: f64) -> Self::Output {
         log::info!("submitting noop transaction at gas price {}", gas_price);
+        self.transaction_monitor
+            .transaction_sent(self.nonce, gas_price);
         let result = self
             .contract
             .send_noop_transaction(U256::from_f64_lossy(gas_price), self.nonce)
@@ -359,7 +602,7 @@ mod tests {
     };
     use anyhow::anyhow;
     use ethcontract::jsonrpc::types::ErrorCode;
-    use ethcontract::{web3::types::H2048, H256};
+    use ethcontract::web3::types::H2048;
     use futures::future;
     use mockall::predicate::{always, eq};
 
@@ -437,6 +680,9 @@ mod tests {
     #[test]
     fn test_benign_solution_submission_failure() {
         let mut contract = MockStableXContract::new();
+        contract
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
 
         let tx_hash = H256::zero();
         let block_number = 42.into();
@@ -470,7 +716,7 @@ mod tests {
             });
         contract
             .expect_submit_solution()
-            .return_once(|_, _, _, _, _| {
+            .return_once(|_, _, _, _, _, _| {
                 Err(MethodError::from_parts(
                     "submitSolution(uint32,uint256,address[],uint16[],uint128[],uint128[],uint16[])"
                         .to_owned(),
@@ -493,7 +739,7 @@ mod tests {
             sleep,
         );
         let result = submitter
-            .submit_solution(0, Solution::trivial(), U256::zero(), 0.0)
+            .submit_solution(0, Solution::trivial(), U256::zero(), U256::zero(), 0.0)
             .now_or_never()
             .unwrap();
 
@@ -504,6 +750,66 @@ mod tests {
             }
         };
     }
+
+    #[test]
+    fn returns_receipt_of_mined_solution() {
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_batch_duration()
+            .return_const(Duration::from_secs(300));
+        contract
+            .expect_get_transaction_count()
+            .returning(|| Ok(U256::from(0)));
+        contract
+            .expect_submit_solution()
+            .return_once(|_, _, _, _, _, _| {
+                Ok(SentTransaction::Receipt(TransactionReceipt {
+                    transaction_hash: H256::from_low_u64_be(1),
+                    transaction_index: 0.into(),
+                    block_hash: None,
+                    block_number: Some(42.into()),
+                    cumulative_gas_used: U256::zero(),
+                    gas_used: Some(100_000.into()),
+                    contract_address: None,
+                    logs: vec![],
+                    status: Some(1.into()),
+                    root: None,
+                    logs_bloom: H2048::zero(),
+                }))
+            });
+        let mut gas_price = MockGasPriceEstimating::new();
+        gas_price
+            .expect_estimate_with_limits()
+            .returning(|_, _| Ok(1e9));
+        let mut sleep = MockAsyncSleeping::new();
+        sleep
+            .expect_sleep()
+            .returning(|_| future::pending().boxed());
+
+        let submitter = StableXSolutionSubmitter::with_estimator_and_sleep(
+            Arc::new(contract),
+            Arc::new(gas_price),
+            CustomBenignErrors::default(),
+            sleep,
+        );
+        let receipt = submitter
+            .submit_solution(7, Solution::trivial(), U256::zero(), U256::zero(), 1e10)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            receipt,
+            SubmissionReceipt {
+                batch_index: 7,
+                transaction_hash: H256::from_low_u64_be(1),
+                block_number: Some(42),
+                gas_price: 1_000_000_000.into(),
+                gas_used: Some(100_000.into()),
+            }
+        );
+    }
+
     #[test]
     fn test_cancellation_resul_was_mined() {
         let transaction_error = ExecutionError::Web3(Web3Error::Rpc(RpcError {
@@ -528,7 +834,7 @@ mod tests {
             message: "".into(),
             data: None,
         }));
-        let result = SolutionResult(Ok(()));
+        let result = SolutionResult(Ok(SubmissionReceipt::default()));
         assert!(result.was_mined());
 
         let result = SolutionResult(Err(MethodError::from_parts(
@@ -540,4 +846,85 @@ mod tests {
         let result = SolutionResult(Err(MethodError::from_parts("".into(), transaction_error)));
         assert!(!result.was_mined());
     }
+
+    #[test]
+    fn estimates_gas_limit_with_margin() {
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_estimate_solution_gas()
+            .with(eq(3), always(), eq(U256::from(42)))
+            .returning(|_, _, _| Ok(200_000.into()));
+
+        let submitter = StableXSolutionSubmitter::with_estimator_and_sleep(
+            Arc::new(contract),
+            Arc::new(MockGasPriceEstimating::new()),
+            CustomBenignErrors::default(),
+            MockAsyncSleeping::new(),
+        )
+        .with_gas_limit_margin(0.2);
+        let gas_limit = submitter
+            .estimate_gas_limit(3, Solution::trivial(), 42.into())
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(gas_limit, U256::from(240_000));
+    }
+
+    #[test]
+    fn falls_back_to_fixed_gas_limit_when_simulation_fails() {
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_estimate_solution_gas()
+            .returning(|_, _, _| Err(anyhow!(Web3Error::Unreachable)));
+
+        let submitter = StableXSolutionSubmitter::with_estimator_and_sleep(
+            Arc::new(contract),
+            Arc::new(MockGasPriceEstimating::new()),
+            CustomBenignErrors::default(),
+            MockAsyncSleeping::new(),
+        );
+        let gas_limit = submitter
+            .estimate_gas_limit(0, Solution::trivial(), U256::zero())
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(gas_limit, U256::from(SOLUTION_SUBMISSION_GAS_LIMIT));
+    }
+
+    #[test]
+    fn fails_gas_limit_estimation_when_simulation_reverts() {
+        let mut contract = MockStableXContract::new();
+        contract
+            .expect_estimate_solution_gas()
+            .returning(|_, _, _| {
+                Err(anyhow!(MethodError::from_parts(
+                    "submitSolution(uint32,uint256,address[],uint16[],uint128[],uint128[],uint16[])"
+                        .to_owned(),
+                    ExecutionError::Revert(Some("SafeMath: subtraction overflow".to_owned())),
+                )))
+            });
+
+        let submitter = StableXSolutionSubmitter::with_estimator_and_sleep(
+            Arc::new(contract),
+            Arc::new(MockGasPriceEstimating::new()),
+            CustomBenignErrors::default(),
+            MockAsyncSleeping::new(),
+        );
+        let result = submitter
+            .estimate_gas_limit(0, Solution::trivial(), U256::zero())
+            .now_or_never()
+            .unwrap();
+
+        assert!(matches!(result, Err(SolutionSubmissionError::Benign(_))));
+    }
+
+    #[test]
+    fn gas_limit_with_margin_is_capped() {
+        assert_eq!(
+            gas_limit_with_margin(5_000_000.into(), 0.5),
+            U256::from(SOLUTION_SUBMISSION_GAS_LIMIT)
+        );
+    }
 }
@@ -1,16 +1,23 @@
-use crate::{contracts::stablex_contract::SOLUTION_SUBMISSION_GAS_LIMIT, util::AsyncSleeping};
-use futures::stream::{self, Stream, StreamExt as _};
+use super::ReplacementPolicy;
+use crate::util::AsyncSleeping;
+use futures::{
+    future,
+    stream::{self, Stream, StreamExt as _},
+};
 use gas_estimation::GasPriceEstimating;
 use std::time::{Duration, Instant};
 use transaction_retry::gas_price_increase;
 
 const GAS_PRICE_REFRESH_INTERVAL: Duration = Duration::from_secs(15);
 
-/// Create a never ending stream of gas prices based on checking the estimator in fixed intervals
-/// and enforcing the minimum increase. Errors are ignored.
+/// Create a never ending stream of gas prices for transactions with the specified gas limit based
+/// on checking the estimator in fixed intervals and enforcing the minimum increase. Errors are
+/// ignored.
 pub fn gas_price_stream<'a>(
     target_confirm_time: Instant,
+    gas_limit: f64,
     gas_price_cap: f64,
+    replacement_policy: ReplacementPolicy,
     estimator: &'a dyn GasPriceEstimating,
     sleep: &'a dyn AsyncSleeping,
 ) -> impl Stream<Item = f64> + 'a {
@@ -20,7 +27,7 @@ pub fn gas_price_stream<'a>(
         }
         let time_remaining = target_confirm_time.saturating_duration_since(Instant::now());
         let estimate = estimator
-            .estimate_with_limits(SOLUTION_SUBMISSION_GAS_LIMIT as f64, time_remaining)
+            .estimate_with_limits(gas_limit, time_remaining)
             .await;
         Some((estimate, false))
     })
@@ -36,5 +43,64 @@ pub fn gas_price_stream<'a>(
             }
         }
     });
+    let stream = limit_replacements(replacement_policy, stream);
     gas_price_increase::enforce_minimum_increase_and_cap(gas_price_cap, stream)
 }
+
+/// Drops gas prices that would replace the previous transaction too early or
+/// without increasing the previous estimate sufficiently.
+fn limit_replacements(
+    policy: ReplacementPolicy,
+    stream: impl Stream<Item = f64>,
+) -> impl Stream<Item = f64> {
+    let mut previous: Option<(Instant, f64)> = None;
+    stream.filter(move |gas_price| {
+        let now = Instant::now();
+        let replace = match previous {
+            Some((time, previous_gas_price)) => {
+                now.duration_since(time) >= policy.min_interval
+                    && *gas_price >= previous_gas_price * (1.0 + policy.min_increase)
+            }
+            None => true,
+        };
+        if replace {
+            previous = Some((now, *gas_price));
+        } else {
+            log::debug!("not replacing transaction with gas price {}", gas_price);
+        }
+        future::ready(replace)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::FutureWaitExt as _;
+
+    fn limited(policy: ReplacementPolicy, gas_prices: Vec<f64>) -> Vec<f64> {
+        limit_replacements(policy, stream::iter(gas_prices))
+            .collect::<Vec<_>>()
+            .wait()
+    }
+
+    #[test]
+    fn replaces_only_with_sufficient_increase() {
+        let policy = ReplacementPolicy {
+            min_interval: Duration::from_secs(0),
+            min_increase: 0.1,
+        };
+        assert_eq!(
+            limited(policy, vec![10.0, 10.5, 11.0, 9.0, 11.5, 12.2]),
+            vec![10.0, 11.0, 12.2]
+        );
+    }
+
+    #[test]
+    fn replaces_only_after_min_interval() {
+        let policy = ReplacementPolicy {
+            min_interval: Duration::from_secs(3600),
+            min_increase: 0.0,
+        };
+        assert_eq!(limited(policy, vec![10.0, 20.0, 30.0]), vec![10.0]);
+    }
+}
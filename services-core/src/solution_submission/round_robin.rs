@@ -0,0 +1,164 @@
+use super::{SolutionSubmissionError, StableXSolutionSubmitting, SubmissionReceipt};
+use crate::{metrics::StableXMetrics, models::Solution};
+use ethcontract::{Address, U256};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+type Submitter = Box<dyn StableXSolutionSubmitting + Send + Sync>;
+
+/// Spreads solution submissions over several accounts. Each batch the next
+/// account without pending transactions is used so that a solution transaction
+/// that is still pending does not block the submission for the next batch and
+/// gas costs are shared between the accounts.
+pub struct RoundRobinSolutionSubmitter {
+    submitters: Vec<(Address, Submitter)>,
+    next: AtomicUsize,
+    metrics: Arc<StableXMetrics>,
+}
+
+impl RoundRobinSolutionSubmitter {
+    /// Creates a new submitter from the submitters of the individual accounts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no submitters are passed.
+    pub fn new(submitters: Vec<(Address, Submitter)>, metrics: Arc<StableXMetrics>) -> Self {
+        assert!(!submitters.is_empty(), "at least one submitter is required");
+        Self {
+            submitters,
+            next: AtomicUsize::new(0),
+            metrics,
+        }
+    }
+
+    /// Selects the next account that has no pending transactions. Falls back
+    /// to the next account in turn if all of them are busy, in which case its
+    /// stuck transactions are cancelled before submitting.
+    fn select(&self) -> &(Address, Submitter) {
+        let start = self.next.fetch_add(1, Ordering::SeqCst);
+        let count = self.submitters.len();
+        let index = (start..start + count)
+            .map(|index| index % count)
+            .find(|index| !self.submitters[*index].1.has_pending_transactions())
+            .unwrap_or(start % count);
+        self.next.store(index + 1, Ordering::SeqCst);
+        &self.submitters[index]
+    }
+}
+
+#[async_trait::async_trait]
+impl StableXSolutionSubmitting for RoundRobinSolutionSubmitter {
+    async fn get_solution_objective_value(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+    ) -> Result<U256, SolutionSubmissionError> {
+        // Verification does not depend on the account.
+        self.submitters[0]
+            .1
+            .get_solution_objective_value(batch_index, solution)
+            .await
+    }
+
+    async fn estimate_gas_limit(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+    ) -> Result<U256, SolutionSubmissionError> {
+        // The gas used by a solution does not depend on the account either.
+        self.submitters[0]
+            .1
+            .estimate_gas_limit(batch_index, solution, claimed_objective_value)
+            .await
+    }
+
+    async fn submit_solution(
+        &self,
+        batch_index: u32,
+        solution: Solution,
+        claimed_objective_value: U256,
+        gas_limit: U256,
+        gas_price_cap: f64,
+    ) -> Result<SubmissionReceipt, SolutionSubmissionError> {
+        let (account, submitter) = self.select();
+        log::info!(
+            "submitting solution for batch {} with account {:?}",
+            batch_index,
+            account
+        );
+        self.metrics.solution_submission_account_selected(*account);
+        submitter
+            .submit_solution(
+                batch_index,
+                solution,
+                claimed_objective_value,
+                gas_limit,
+                gas_price_cap,
+            )
+            .await
+    }
+
+    fn has_pending_transactions(&self) -> bool {
+        self.submitters
+            .iter()
+            .all(|(_, submitter)| submitter.has_pending_transactions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solution_submission::MockStableXSolutionSubmitting;
+    use futures::FutureExt as _;
+
+    fn submitter(pending: bool, submissions: usize) -> Submitter {
+        let mut submitter = MockStableXSolutionSubmitting::new();
+        submitter
+            .expect_has_pending_transactions()
+            .return_const(pending);
+        submitter
+            .expect_submit_solution()
+            .times(submissions)
+            .returning(|_, _, _, _, _| Ok(Default::default()));
+        Box::new(submitter)
+    }
+
+    fn submit(submitter: &RoundRobinSolutionSubmitter) {
+        submitter
+            .submit_solution(0, Solution::trivial(), U256::zero(), U256::zero(), 0.0)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn alternates_between_free_accounts() {
+        let submitter = RoundRobinSolutionSubmitter::new(
+            vec![
+                (Address::from_low_u64_be(0), submitter(false, 2)),
+                (Address::from_low_u64_be(1), submitter(false, 1)),
+            ],
+            Arc::new(StableXMetrics::default()),
+        );
+        for _ in 0..3 {
+            submit(&submitter);
+        }
+    }
+
+    #[test]
+    fn skips_accounts_with_pending_transactions() {
+        let submitter = RoundRobinSolutionSubmitter::new(
+            vec![
+                (Address::from_low_u64_be(0), submitter(true, 0)),
+                (Address::from_low_u64_be(1), submitter(false, 2)),
+            ],
+            Arc::new(StableXMetrics::default()),
+        );
+        submit(&submitter);
+        submit(&submitter);
+        assert!(!submitter.has_pending_transactions());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::contracts::stablex_contract::StableXContract;
+use anyhow::{Context as _, Result};
+use ethcontract::U256;
+use gas_estimation::GasPriceEstimating;
+use std::{collections::BTreeMap, sync::Arc, sync::Mutex, time::Duration};
+
+/// Nodes only accept a transaction replacing a pending one with the same nonce
+/// if the gas price was increased by at least 12.5% (Geth requires 10%,
+/// OpenEthereum 12.5%).
+const MIN_REPLACEMENT_GAS_PRICE_FACTOR: f64 = 1.125;
+const NOOP_TRANSACTION_GAS_LIMIT: f64 = 21_000.0;
+/// Stuck transactions block all further submissions so we aim for a fast
+/// confirmation when replacing them.
+const RECOVERY_TARGET_CONFIRM_TIME: Duration = Duration::from_secs(30);
+
+/// Keeps track of solution and cancellation transactions that were sent but
+/// whose outcome is unknown, and cancels transactions that block the nonce of
+/// the submitting account.
+pub struct TransactionMonitor {
+    contract: Arc<dyn StableXContract>,
+    gas_price_estimator: Arc<dyn GasPriceEstimating>,
+    /// Maps nonces of outstanding transactions to the highest gas price with
+    /// which a transaction for this nonce was sent.
+    outstanding: Mutex<BTreeMap<U256, f64>>,
+}
+
+impl TransactionMonitor {
+    pub fn new(
+        contract: Arc<dyn StableXContract>,
+        gas_price_estimator: Arc<dyn GasPriceEstimating>,
+    ) -> Self {
+        Self {
+            contract,
+            gas_price_estimator,
+            outstanding: Default::default(),
+        }
+    }
+
+    /// Records that a transaction was sent with the specified nonce and gas
+    /// price.
+    pub fn transaction_sent(&self, nonce: U256, gas_price: f64) {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        let highest_gas_price = outstanding.entry(nonce).or_insert(gas_price);
+        *highest_gas_price = highest_gas_price.max(gas_price);
+    }
+
+    /// Records that a transaction with the specified nonce was mined, which
+    /// also implies that all transactions with lower nonces were mined.
+    pub fn transaction_mined(&self, nonce: U256) {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        *outstanding = outstanding.split_off(&(nonce + 1));
+    }
+
+    /// Returns true if there are transactions for which it is not known
+    /// whether they were mined.
+    pub fn has_outstanding_transactions(&self) -> bool {
+        !self.outstanding.lock().unwrap().is_empty()
+    }
+
+    /// Cancels all transactions that are still pending on the node by sending
+    /// noop transactions with a sufficiently increased gas price for their
+    /// nonces.
+    pub async fn recover_stuck_transactions(&self) -> Result<()> {
+        let mined_count = self.update_mined_transactions().await?;
+        let pending_count = self.contract.get_pending_transaction_count().await?;
+
+        let mut nonce = mined_count;
+        while nonce < pending_count {
+            let gas_price = self.replacement_gas_price(nonce).await?;
+            log::warn!(
+                "cancelling stuck transaction with nonce {} at gas price {}",
+                nonce,
+                gas_price
+            );
+            self.transaction_sent(nonce, gas_price);
+            self.contract
+                .send_noop_transaction(U256::from_f64_lossy(gas_price), nonce)
+                .await
+                .with_context(|| format!("failed to cancel transaction with nonce {}", nonce))?;
+            nonce += U256::one();
+        }
+
+        // Only the node's transaction count tells us whether a noop actually
+        // got mined; the ones that did not stay outstanding and get replaced
+        // with a higher gas price on the next recovery.
+        self.update_mined_transactions().await?;
+        Ok(())
+    }
+
+    /// Marks all transactions below the account's current nonce as mined and
+    /// returns that nonce.
+    async fn update_mined_transactions(&self) -> Result<U256> {
+        let mined_count = self.contract.get_transaction_count().await?;
+        if mined_count > U256::zero() {
+            self.transaction_mined(mined_count - 1);
+        }
+        Ok(mined_count)
+    }
+
+    async fn replacement_gas_price(&self, nonce: U256) -> Result<f64> {
+        let estimate = self
+            .gas_price_estimator
+            .estimate_with_limits(NOOP_TRANSACTION_GAS_LIMIT, RECOVERY_TARGET_CONFIRM_TIME)
+            .await?;
+        let previous = self
+            .outstanding
+            .lock()
+            .unwrap()
+            .get(&nonce)
+            .copied()
+            .unwrap_or(0.0);
+        Ok(estimate.max(previous * MIN_REPLACEMENT_GAS_PRICE_FACTOR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        contracts::stablex_contract::MockStableXContract, gas_price::MockGasPriceEstimating,
+    };
+    use ethcontract::{transaction::TransactionResult, H256};
+    use futures::FutureExt as _;
+    use mockall::{predicate::eq, Sequence};
+
+    #[test]
+    fn tracks_outstanding_transactions() {
+        let monitor = TransactionMonitor::new(
+            Arc::new(MockStableXContract::new()),
+            Arc::new(MockGasPriceEstimating::new()),
+        );
+        assert!(!monitor.has_outstanding_transactions());
+
+        monitor.transaction_sent(1.into(), 10.0);
+        monitor.transaction_sent(2.into(), 10.0);
+        monitor.transaction_mined(1.into());
+        assert!(monitor.has_outstanding_transactions());
+
+        monitor.transaction_mined(2.into());
+        assert!(!monitor.has_outstanding_transactions());
+    }
+
+    #[test]
+    fn cancels_pending_nonces_with_increased_gas_price() {
+        let mut contract = MockStableXContract::new();
+        let mut sequence = Sequence::new();
+        contract
+            .expect_get_transaction_count()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(5.into()));
+        contract
+            .expect_get_pending_transaction_count()
+            .returning(|| Ok(7.into()));
+        contract
+            .expect_send_noop_transaction()
+            .with(eq(U256::from(112)), eq(U256::from(5)))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(TransactionResult::Hash(H256::zero())));
+        contract
+            .expect_send_noop_transaction()
+            .with(eq(U256::from(50)), eq(U256::from(6)))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(TransactionResult::Hash(H256::zero())));
+        contract
+            .expect_get_transaction_count()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(7.into()));
+
+        let mut gas_price_estimator = MockGasPriceEstimating::new();
+        gas_price_estimator
+            .expect_estimate_with_limits()
+            .returning(|_, _| Ok(50.0));
+
+        let monitor = TransactionMonitor::new(Arc::new(contract), Arc::new(gas_price_estimator));
+        monitor.transaction_sent(4.into(), 1000.0);
+        monitor.transaction_sent(5.into(), 100.0);
+        monitor
+            .recover_stuck_transactions()
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(!monitor.has_outstanding_transactions());
+    }
+
+    #[test]
+    fn keeps_noop_transactions_outstanding_until_mined() {
+        let mut contract = MockStableXContract::new();
+        let mut sequence = Sequence::new();
+        contract
+            .expect_get_transaction_count()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(5.into()));
+        contract
+            .expect_get_pending_transaction_count()
+            .returning(|| Ok(6.into()));
+        contract
+            .expect_send_noop_transaction()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(TransactionResult::Hash(H256::zero())));
+        contract
+            .expect_get_transaction_count()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(5.into()));
+
+        let mut gas_price_estimator = MockGasPriceEstimating::new();
+        gas_price_estimator
+            .expect_estimate_with_limits()
+            .returning(|_, _| Ok(50.0));
+
+        let monitor = TransactionMonitor::new(Arc::new(contract), Arc::new(gas_price_estimator));
+        monitor
+            .recover_stuck_transactions()
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(monitor.has_outstanding_transactions());
+    }
+}
@@ -0,0 +1,161 @@
+//! Module implementing progress reporting for the service startup.
+
+use crate::{health::HealthReporting, http_server::Handler};
+use anyhow::Result;
+use async_std::task::{self, JoinHandle};
+use rouille::{Request, Response};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A snapshot of the startup progress.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupStatus {
+    pub initializing: bool,
+    /// The startup steps that are currently running.
+    pub running_steps: BTreeSet<&'static str>,
+    /// The number of exchange events fetched for the initial orderbook.
+    pub events_fetched: u64,
+    /// The number of blocks the initial orderbook still needs to fetch events
+    /// for, if known.
+    pub blocks_remaining: Option<u64>,
+}
+
+/// Tracks the progress of the service startup. The service is reported as
+/// initializing until the startup finished.
+pub struct StartupProgress {
+    health: Arc<dyn HealthReporting>,
+    status: Mutex<StartupStatus>,
+}
+
+impl StartupProgress {
+    pub fn new(health: Arc<dyn HealthReporting>) -> Self {
+        health.notify_initializing(true);
+        StartupProgress {
+            health,
+            status: Mutex::new(StartupStatus {
+                initializing: true,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Returns the current startup progress.
+    pub fn status(&self) -> StartupStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Runs a startup step, logging when it starts and finishes.
+    pub async fn step<T>(&self, name: &'static str, step: impl Future<Output = T>) -> T {
+        log::info!("startup step '{}' started", name);
+        self.status.lock().unwrap().running_steps.insert(name);
+        let start = Instant::now();
+        let result = step.await;
+        self.status.lock().unwrap().running_steps.remove(name);
+        log::info!(
+            "startup step '{}' finished after {:?}",
+            name,
+            start.elapsed()
+        );
+        result
+    }
+
+    /// Records that events were fetched for the initial orderbook. Later
+    /// orderbook updates are not tracked.
+    pub fn events_fetched(&self, count: usize, blocks_remaining: u64) {
+        let mut status = self.status.lock().unwrap();
+        if status.initializing {
+            status.events_fetched += count as u64;
+            status.blocks_remaining = Some(blocks_remaining);
+        }
+    }
+
+    /// Marks the startup as finished.
+    pub fn finished(&self) {
+        self.status.lock().unwrap().initializing = false;
+        self.health.notify_initializing(false);
+        log::info!("startup finished");
+    }
+
+    /// Logs the startup progress in the specified interval until the startup
+    /// finished.
+    pub fn start_logging(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            loop {
+                task::sleep(interval).await;
+                let status = self.status();
+                if !status.initializing {
+                    break;
+                }
+                log::info!(
+                    "startup in progress: running {:?}, {} events fetched, {:?} blocks remaining",
+                    status.running_steps,
+                    status.events_fetched,
+                    status.blocks_remaining
+                );
+            }
+        })
+    }
+}
+
+impl Handler for StartupProgress {
+    fn handle_request(&self, _: &Request) -> Result<Response> {
+        Ok(Response::json(&self.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::MockHealthReporting;
+    use futures::FutureExt as _;
+    use mockall::{predicate::eq, Sequence};
+
+    #[test]
+    fn tracks_progress_until_finished() {
+        let mut health = MockHealthReporting::new();
+        let mut seq = Sequence::new();
+        health
+            .expect_notify_initializing()
+            .with(eq(true))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_const(());
+        health
+            .expect_notify_initializing()
+            .with(eq(false))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_const(());
+        let progress = StartupProgress::new(Arc::new(health));
+
+        let running = progress
+            .step("orderbook", async {
+                progress.events_fetched(10, 500);
+                progress.events_fetched(5, 0);
+                progress.status().running_steps
+            })
+            .now_or_never()
+            .unwrap();
+        assert_eq!(running, vec!["orderbook"].into_iter().collect());
+        assert_eq!(
+            progress.status(),
+            StartupStatus {
+                initializing: true,
+                running_steps: BTreeSet::new(),
+                events_fetched: 15,
+                blocks_remaining: Some(0),
+            }
+        );
+
+        progress.finished();
+        progress.events_fetched(10, 0);
+        assert!(!progress.status().initializing);
+        assert_eq!(progress.status().events_fetched, 15);
+    }
+}
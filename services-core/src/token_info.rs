@@ -122,6 +122,13 @@ impl TokenBaseInfo {
         (usd_price * 10f64.powi(pow)) as _
     }
 
+    /// Converts a price in the unit expected by the contract back into USD.
+    /// This is the inverse of `get_owl_price`.
+    pub fn get_usd_price(&self, owl_price: u128) -> f64 {
+        let pow = 36 - (self.decimals as i32);
+        owl_price as f64 / 10f64.powi(pow)
+    }
+
     /// Returns true if the token alias or symbol matches the speciefied symbol.
     pub fn matches_symbol(&self, symbol: &str) -> bool {
         self.alias == symbol || self.symbol() == symbol
@@ -156,6 +163,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn token_get_usd_price() {
+        let address = Address::from_low_u64_be(0);
+        for (token, owl_price, expected) in &[
+            (TokenBaseInfo::new(address, "USDC", 6), 0.99e30, 0.99),
+            (TokenBaseInfo::new(address, "DAI", 18), 1.01e18, 1.01),
+            (TokenBaseInfo::new(address, "WETH", 18), 400e18, 400.0),
+        ] {
+            let usd_price = token.get_usd_price(*owl_price as u128);
+            assert!((usd_price - expected).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn weth_token_symbol_is_eth() {
         assert_eq!(
@@ -1,11 +1,18 @@
 //! This module contains fallback token data that should be used by the price
 //! estimator when prices are not available.
 
-use crate::{models::TokenId, price_estimation::price_source::PriceSource};
+use crate::{
+    models::TokenId,
+    price_estimation::price_source::{PriceSource, PriceSourceKind},
+};
 use anyhow::{anyhow, Context, Error, Result};
 use ethcontract::Address;
 use serde::Deserialize;
-use std::{collections::HashMap, num::NonZeroU128, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU128,
+    str::FromStr,
+};
 
 use super::{TokenBaseInfo, TokenInfoFetching};
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -16,6 +23,10 @@ pub struct TokenInfoOverride {
     pub alias: String,
     pub decimals: u8,
     pub external_price: Option<NonZeroU128>,
+    /// The price sources that may be used for this token. All sources are
+    /// used if this is not specified.
+    #[serde(default)]
+    pub price_sources: Option<HashSet<PriceSourceKind>>,
 }
 
 impl TokenInfoOverride {
@@ -31,6 +42,7 @@ impl TokenInfoOverride {
             alias: alias.to_owned(),
             decimals,
             external_price,
+            price_sources: None,
         }
     }
 }
@@ -52,6 +64,20 @@ impl Into<TokenBaseInfo> for TokenInfoOverride {
 #[serde(transparent)]
 pub struct TokenData(HashMap<TokenId, TokenInfoOverride>);
 
+impl TokenData {
+    /// Returns whether the price source may be used for the price of a token.
+    pub fn allows_price_source(&self, token: TokenId, kind: PriceSourceKind) -> bool {
+        match self
+            .0
+            .get(&token)
+            .and_then(|info| info.price_sources.as_ref())
+        {
+            Some(price_sources) => price_sources.contains(&kind),
+            None => true,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl TokenInfoFetching for TokenData {
     async fn get_token_info(&self, id: TokenId) -> Result<TokenBaseInfo> {
@@ -72,6 +98,9 @@ impl PriceSource for TokenData {
     async fn get_prices(&self, tokens: &[TokenId]) -> Result<HashMap<TokenId, NonZeroU128>> {
         let mut result = HashMap::new();
         for token in tokens {
+            if !self.allows_price_source(*token, PriceSourceKind::Hardcoded) {
+                continue;
+            }
             if let Some(price) = self.0.get(token).and_then(|info| info.external_price) {
                 result.insert(*token, price);
             }
@@ -116,6 +145,7 @@ impl FromStr for TokenData {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::FutureExt as _;
 
     #[test]
     fn token_fallback_data_from_str() {
@@ -142,4 +172,36 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn restricts_price_sources_per_token() {
+        let json = r#"{
+          "T0001": {
+            "address": "0x000000000000000000000000000000000000000a",
+            "alias": "WETH",
+            "decimals": 18,
+            "externalPrice": 200000000000000000000,
+            "priceSources": ["kraken", "pricegraph"]
+          },
+          "T0004": {
+            "address": "0x000000000000000000000000000000000000000B",
+            "alias": "USDC",
+            "decimals": 6
+          }
+        }"#;
+        let token_data = TokenData::from_str(json).unwrap();
+
+        assert!(token_data.allows_price_source(TokenId(1), PriceSourceKind::Kraken));
+        assert!(!token_data.allows_price_source(TokenId(1), PriceSourceKind::Dexag));
+        assert!(token_data.allows_price_source(TokenId(4), PriceSourceKind::Dexag));
+        assert!(token_data.allows_price_source(TokenId(5), PriceSourceKind::Dexag));
+
+        // The external price is not used since hardcoded is not allowed.
+        let prices = token_data
+            .get_prices(&[TokenId(1)])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(prices.is_empty());
+    }
 }
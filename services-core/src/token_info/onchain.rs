@@ -1,7 +1,7 @@
 use anyhow::Result;
 
 use super::{TokenBaseInfo, TokenId, TokenInfoFetching};
-use crate::contracts::stablex_contract::StableXContractImpl;
+use crate::contracts::stablex_contract::{CachedStableXContract, StableXContractImpl};
 
 #[async_trait::async_trait]
 impl TokenInfoFetching for StableXContractImpl {
@@ -21,11 +21,25 @@ impl TokenInfoFetching for StableXContractImpl {
     }
 }
 
+#[async_trait::async_trait]
+impl TokenInfoFetching for CachedStableXContract {
+    async fn get_token_info(&self, id: TokenId) -> Result<TokenBaseInfo> {
+        TokenInfoFetching::get_token_info(self.inner().as_ref(), id).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<TokenId>> {
+        let num_tokens = self.num_tokens().await?;
+        let ids: Vec<TokenId> = (0..num_tokens).map(|token| token.into()).collect();
+        Ok(ids)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::contracts::web3_provider;
     use crate::http::HttpFactory;
+    use crate::transport::RetryPolicy;
     use crate::util::FutureWaitExt as _;
     use ethcontract::secret::PrivateKey;
     use std::time::Duration;
@@ -36,6 +50,7 @@ mod tests {
             &http_factory,
             "https://staging-openethereum.mainnet.gnosisdev.com",
             Duration::from_secs(10),
+            RetryPolicy::default(),
         )
         .expect("Error creating web3");
         StableXContractImpl::new(
@@ -43,7 +58,8 @@ mod tests {
             PrivateKey::from_hex_str(
                 "0x0102030405060708091011121314151617181920212223242526272829303132",
             )
-            .expect("Invalid private key"),
+            .expect("Invalid private key")
+            .into(),
             false,
         )
         .wait()
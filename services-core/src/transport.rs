@@ -1,5 +1,14 @@
-use crate::http::{HttpClient, HttpFactory, HttpLabel};
-use anyhow::Error;
+mod proxy;
+mod retry;
+mod socket;
+
+pub use self::retry::RetryPolicy;
+pub use self::socket::{Endpoint, Head, NewHeads, SocketTransport};
+
+use self::retry::RetryBudget;
+use crate::http::{HttpClient, HttpFactory, HttpLabel, HttpService};
+use crate::models::batch_id::BatchTiming;
+use anyhow::{Context as _, Error, Result};
 use ethcontract::jsonrpc::types::{Call, Output, Request};
 use ethcontract::web3::helpers;
 use ethcontract::web3::{BatchTransport, Error as Web3Error, RequestId, Transport};
@@ -12,9 +21,105 @@ use std::fmt::{self, Debug, Formatter};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// The transport to the node, selected by the scheme of the node URL:
+/// `ws://` and `wss://` URLs connect over WebSocket, `ipc://` URLs connect to
+/// the IPC socket at the path of the URL and all other URLs use HTTP.
+#[derive(Clone, Debug)]
+pub enum NodeTransport {
+    Http(HttpTransport),
+    Socket(SocketTransport),
+}
+
+impl NodeTransport {
+    /// Creates the transport for the node URL. The retry policy only applies
+    /// to HTTP, socket transports reconnect instead. WebSocket connections use
+    /// the proxy of the node's HTTP clients.
+    pub fn new(
+        http_factory: &HttpFactory,
+        url: &str,
+        timeout: Duration,
+        retry_policy: RetryPolicy,
+    ) -> Result<NodeTransport, Error> {
+        let parsed = Url::parse(url).with_context(|| format!("invalid node URL {}", url))?;
+        Ok(match parsed.scheme() {
+            "ws" | "wss" => {
+                let proxy = http_factory
+                    .proxy_for(HttpService::Node, parsed.host_str().unwrap_or_default())?;
+                NodeTransport::Socket(SocketTransport::new(
+                    Endpoint::WebSocket(parsed),
+                    proxy,
+                    timeout,
+                ))
+            }
+            "ipc" => NodeTransport::Socket(SocketTransport::new(
+                Endpoint::Ipc(parsed.path().into()),
+                None,
+                timeout,
+            )),
+            _ => NodeTransport::Http(HttpTransport::new(
+                http_factory,
+                url,
+                timeout,
+                retry_policy,
+            )?),
+        })
+    }
+
+    /// Sets the batch timing of the exchange that retry budgets are tracked
+    /// by. Socket transports reconnect instead of retrying so this only
+    /// applies to HTTP.
+    pub fn set_batch_timing(&self, batch_timing: BatchTiming) {
+        if let NodeTransport::Http(http) = self {
+            http.0.retry_budget.set_batch_timing(batch_timing);
+        }
+    }
+
+    /// The latest block pushed by the node, if the transport supports
+    /// subscriptions.
+    pub fn new_heads(&self) -> Option<NewHeads> {
+        match self {
+            NodeTransport::Http(_) => None,
+            NodeTransport::Socket(socket) => Some(socket.new_heads()),
+        }
+    }
+}
+
+impl Transport for NodeTransport {
+    type Out = BoxFuture<'static, RpcResult>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        match self {
+            NodeTransport::Http(http) => http.prepare(method, params),
+            NodeTransport::Socket(socket) => socket.prepare(method, params),
+        }
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        match self {
+            NodeTransport::Http(http) => http.send(id, request),
+            NodeTransport::Socket(socket) => socket.send(id, request),
+        }
+    }
+}
+
+impl BatchTransport for NodeTransport {
+    type Batch = BoxFuture<'static, Result<Vec<RpcResult>, Web3Error>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        match self {
+            NodeTransport::Http(http) => http.send_batch(requests),
+            NodeTransport::Socket(socket) => socket.send_batch(requests),
+        }
+    }
+}
 
-/// An HTTP transport implementation with timeout and logging.
+/// An HTTP transport implementation with timeout, retries and logging.
 #[derive(Clone)]
 pub struct HttpTransport(Arc<HttpTransportInner>);
 
@@ -22,6 +127,8 @@ struct HttpTransportInner {
     url: String,
     client: HttpClient,
     id: AtomicUsize,
+    retry_policy: RetryPolicy,
+    retry_budget: RetryBudget,
 }
 
 impl HttpTransport {
@@ -30,19 +137,26 @@ impl HttpTransport {
         http_factory: &HttpFactory,
         url: impl Into<String>,
         timeout: Duration,
+        retry_policy: RetryPolicy,
     ) -> Result<HttpTransport, Error> {
-        let client = http_factory.with_config(|builder| {
-            builder
-                .timeout(timeout)
-                // NOTE: This is needed as curl will try to upgrade to HTTP/2
-                //   which causes a HTTP 400 error with Ganache.
-                .version_negotiation(VersionNegotiation::http11())
-        })?;
+        let client = http_factory
+            .with_profile(HttpService::Node, |builder| {
+                builder
+                    .timeout(timeout)
+                    // NOTE: This is needed as curl will try to upgrade to HTTP/2
+                    //   which causes a HTTP 400 error with Ganache.
+                    .version_negotiation(VersionNegotiation::http11())
+            })?
+            // The node is not an external API that can be skipped when it is
+            // down, failing fast would only replace timeouts with errors.
+            .without_circuit_breaker();
 
         Ok(HttpTransport(Arc::new(HttpTransportInner {
             url: url.into(),
             client,
             id: AtomicUsize::default(),
+            retry_budget: RetryBudget::new(retry_policy.retry_budget_per_batch),
+            retry_policy,
         })))
     }
 }
@@ -53,13 +167,13 @@ impl HttpTransportInner {
     /// Execute an HTTP JSON RPC request.
     async fn execute_rpc(self: Arc<Self>, id: RequestId, request: Request) -> RpcResult {
         let label: HttpLabel = (&request).into();
+        let retryable = retry::is_retryable(&request);
 
         let request = serde_json::to_string(&request)?;
         debug!("[id:{}] sending request: '{}'", id, &request);
 
         let content = self
-            .client
-            .post_raw_json_async(&self.url, request, label)
+            .post_with_retries(id, request, label, retryable)
             .await
             .map_err(|err| {
                 warn!("[id:{}] returned an error: '{}'", id, err.to_string());
@@ -81,6 +195,46 @@ impl HttpTransportInner {
         Ok(json)
     }
 
+    /// Post a JSON RPC request, retrying transient errors according to the
+    /// transport's retry policy unless the request is not retryable.
+    async fn post_with_retries(
+        &self,
+        id: RequestId,
+        request: String,
+        label: HttpLabel,
+        retryable: bool,
+    ) -> Result<String> {
+        let mut attempt = 1;
+        loop {
+            let err = match self
+                .client
+                .post_raw_json_async(&self.url, request.clone(), label)
+                .await
+            {
+                Ok(content) => return Ok(content),
+                Err(err) => err,
+            };
+            if !retryable || attempt >= self.retry_policy.max_attempts || !retry::is_transient(&err)
+            {
+                return Err(err);
+            }
+            if !self.retry_budget.try_spend(SystemTime::now()) {
+                warn!("[id:{}] retry budget for this batch exhausted", id);
+                self.client.metrics().retry_budget_exhausted();
+                return Err(err);
+            }
+
+            let backoff = self.retry_policy.backoff(attempt);
+            warn!(
+                "[id:{}] attempt {} failed with transient error '{}', retrying in {:?}",
+                id, attempt, err, backoff,
+            );
+            self.client.metrics().retry(label);
+            async_std::task::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
     async fn execute_single_rpc(self: Arc<Self>, id: RequestId, call: Call) -> RpcResult {
         let json = self.execute_rpc(id, Request::Single(call)).await?;
         let output = Output::deserialize(json)?;
@@ -0,0 +1,260 @@
+//! Tunneling socket connections to the node through the proxy that HTTP
+//! requests are sent through. HTTP proxies are asked to `CONNECT` to the node
+//! and SOCKS5 proxies to open a stream to it.
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use async_std::net::{TcpStream, ToSocketAddrs as _};
+use futures::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use isahc::http::Uri;
+use std::net::IpAddr;
+
+/// The maximum size of the response of an HTTP proxy to a `CONNECT` request.
+const MAX_CONNECT_RESPONSE_SIZE: usize = 8192;
+
+/// Opens a TCP stream to the host through the proxy.
+pub async fn connect(proxy: &Uri, host: &str, port: u16) -> Result<TcpStream> {
+    let authority = proxy
+        .authority()
+        .ok_or_else(|| anyhow!("proxy URL {} has no host", proxy))?;
+    let credentials = credentials(authority.as_str());
+    let address = (authority.host(), authority.port_u16().unwrap_or(1080));
+    let mut stream = TcpStream::connect(address)
+        .await
+        .with_context(|| format!("failed to connect to proxy {}", authority.host()))?;
+
+    match proxy.scheme_str().unwrap_or("http") {
+        "http" => http_connect(&mut stream, credentials, host, port).await?,
+        "socks5" => {
+            let ip = (host, port)
+                .to_socket_addrs()
+                .await?
+                .next()
+                .ok_or_else(|| anyhow!("failed to resolve {}", host))?
+                .ip();
+            socks5_connect(&mut stream, credentials, Target::Ip(ip), port).await?
+        }
+        "socks5h" => socks5_connect(&mut stream, credentials, Target::Domain(host), port).await?,
+        scheme => bail!("unsupported proxy scheme {} for node sockets", scheme),
+    }
+    Ok(stream)
+}
+
+/// The user name and password in the authority of the proxy URL.
+fn credentials(authority: &str) -> Option<(&str, &str)> {
+    let user_info = &authority[..authority.rfind('@')?];
+    Some(match user_info.find(':') {
+        Some(colon) => (&user_info[..colon], &user_info[colon + 1..]),
+        None => (user_info, ""),
+    })
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    credentials: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if let Some((user, password)) = credentials {
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::encode(format!("{}:{}", user, password))
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // The response is read byte by byte so that nothing sent through the
+    // tunnel after it is consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        ensure!(
+            response.len() < MAX_CONNECT_RESPONSE_SIZE,
+            "proxy response exceeds {} bytes",
+            MAX_CONNECT_RESPONSE_SIZE
+        );
+        let mut byte = [0];
+        stream.read_exact(&mut byte).await?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    ensure!(
+        status.split_whitespace().nth(1) == Some("200"),
+        "proxy refused to connect to {}:{}: {}",
+        host,
+        port,
+        status
+    );
+    Ok(())
+}
+
+/// The destination of a SOCKS5 connection, which the proxy resolves if it is
+/// a domain name.
+enum Target<'a> {
+    Ip(IpAddr),
+    Domain(&'a str),
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    credentials: Option<(&str, &str)>,
+    target: Target<'_>,
+    port: u16,
+) -> Result<()> {
+    const NO_AUTHENTICATION: u8 = 0;
+    const PASSWORD_AUTHENTICATION: u8 = 2;
+
+    let method = match credentials {
+        Some(_) => PASSWORD_AUTHENTICATION,
+        None => NO_AUTHENTICATION,
+    };
+    stream.write_all(&[5, 1, method]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    ensure!(
+        reply == [5, method],
+        "SOCKS5 proxy does not accept the authentication method"
+    );
+    if let Some((user, password)) = credentials {
+        ensure!(
+            user.len() <= 255 && password.len() <= 255,
+            "SOCKS5 proxy credentials are too long"
+        );
+        let mut request = vec![1, user.len() as u8];
+        request.extend_from_slice(user.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+        stream.read_exact(&mut reply).await?;
+        ensure!(reply[1] == 0, "SOCKS5 proxy rejected the credentials");
+    }
+
+    let mut request = vec![5, 1, 0];
+    match target {
+        Target::Ip(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Target::Ip(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Target::Domain(domain) => {
+            ensure!(domain.len() <= 255, "host name is too long");
+            request.extend_from_slice(&[3, domain.len() as u8]);
+            request.extend_from_slice(domain.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    ensure!(
+        reply[1] == 0,
+        "SOCKS5 proxy failed to connect with reply {}",
+        reply[1]
+    );
+    // The reply ends with the address the proxy bound, which is not needed.
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        kind => bail!("SOCKS5 proxy replied with unknown address type {}", kind),
+    };
+    let mut bound = vec![0; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::{net::TcpListener, task};
+
+    #[test]
+    fn parses_credentials() {
+        assert_eq!(credentials("proxy:3128"), None);
+        assert_eq!(credentials("user@proxy:3128"), Some(("user", "")));
+        assert_eq!(
+            credentials("user:pass@word@proxy:3128"),
+            Some(("user", "pass@word"))
+        );
+    }
+
+    #[test]
+    fn tunnels_through_http_proxy() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy: Uri = format!("http://user:pass@{}", listener.local_addr().unwrap())
+                .parse()
+                .unwrap();
+            let proxy_task = task::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\ntunneled")
+                    .await
+                    .unwrap();
+                String::from_utf8(request).unwrap()
+            });
+
+            let mut stream = connect(&proxy, "node", 8546).await.unwrap();
+            let mut tunneled = [0; 8];
+            stream.read_exact(&mut tunneled).await.unwrap();
+            assert_eq!(&tunneled, b"tunneled");
+
+            let request = proxy_task.await;
+            assert!(request.starts_with("CONNECT node:8546 HTTP/1.1\r\n"));
+            assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+        });
+    }
+
+    #[test]
+    fn tunnels_through_socks5_proxy() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy: Uri = format!("socks5h://{}", listener.local_addr().unwrap())
+                .parse()
+                .unwrap();
+            let proxy_task = task::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut greeting = [0; 3];
+                stream.read_exact(&mut greeting).await.unwrap();
+                assert_eq!(greeting, [5, 1, 0]);
+                stream.write_all(&[5, 0]).await.unwrap();
+
+                let mut request = [0; 11];
+                stream.read_exact(&mut request).await.unwrap();
+                assert_eq!(&request[..5], &[5, 1, 0, 3, 4]);
+                assert_eq!(&request[5..9], b"node");
+                assert_eq!(u16::from_be_bytes([request[9], request[10]]), 8546);
+                stream
+                    .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+                    .await
+                    .unwrap();
+                stream.write_all(b"tunneled").await.unwrap();
+            });
+
+            let mut stream = connect(&proxy, "node", 8546).await.unwrap();
+            let mut tunneled = [0; 8];
+            stream.read_exact(&mut tunneled).await.unwrap();
+            assert_eq!(&tunneled, b"tunneled");
+            proxy_task.await;
+        });
+    }
+}
@@ -0,0 +1,214 @@
+//! Retry handling for transient JSON RPC transport errors.
+
+use crate::http::HttpStatusError;
+use crate::models::{batch_id::BatchTiming, BatchId};
+use anyhow::Error;
+use ethcontract::jsonrpc::types::{Call, Request};
+use isahc::http::StatusCode;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Policy describing how transient transport errors are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of times a request is attempted, including the
+    /// initial attempt. A value of `1` disables retries.
+    pub max_attempts: u32,
+    /// The backoff before the first retry. The backoff doubles with every
+    /// subsequent retry.
+    pub initial_backoff: Duration,
+    /// The upper bound for the backoff between two attempts.
+    pub max_backoff: Duration,
+    /// The maximum number of retries across all requests within a single
+    /// batch. This prevents an unhealthy node from being hammered with
+    /// retries for the whole duration of a batch.
+    pub retry_budget_per_batch: usize,
+}
+
+impl RetryPolicy {
+    /// Computes the backoff to wait before the specified retry attempt, where
+    /// the first retry is attempt `1`.
+    ///
+    /// The backoff grows exponentially and has a random jitter of up to half
+    /// of its value applied so that concurrent requests failing at the same
+    /// time don't all retry at the same time.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = 2u32
+            .checked_pow(attempt.saturating_sub(1))
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        let half = exponential / 2;
+        half + half.mul_f64(rand::random())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            retry_budget_per_batch: 50,
+        }
+    }
+}
+
+/// Keeps track of the number of retries spent in the current batch.
+#[derive(Debug)]
+pub struct RetryBudget {
+    limit: usize,
+    batch_timing: Mutex<BatchTiming>,
+    spent: Mutex<(BatchId, usize)>,
+}
+
+impl RetryBudget {
+    pub fn new(limit: usize) -> Self {
+        RetryBudget {
+            limit,
+            batch_timing: Mutex::new(BatchTiming::default()),
+            spent: Mutex::new((BatchId(0), 0)),
+        }
+    }
+
+    /// Sets the batch timing of the exchange, which is only known once the
+    /// contract has been read over the transport using this budget.
+    pub fn set_batch_timing(&self, batch_timing: BatchTiming) {
+        *self.batch_timing.lock().unwrap() = batch_timing;
+    }
+
+    /// Attempts to spend a retry from the budget of the batch at the specified
+    /// time. Returns `false` if the budget is used up.
+    pub fn try_spend(&self, now: SystemTime) -> bool {
+        let batch_id = self
+            .batch_timing
+            .lock()
+            .unwrap()
+            .current_batch(now)
+            .unwrap_or(BatchId(0));
+        let mut spent = self.spent.lock().unwrap();
+        if spent.0 != batch_id {
+            *spent = (batch_id, 0);
+        }
+        if spent.1 >= self.limit {
+            return false;
+        }
+        spent.1 += 1;
+        true
+    }
+}
+
+/// Returns true if the error is likely to be resolved by retrying the request,
+/// that is if the node is rate limiting us, had an internal error or could not
+/// be reached in time.
+pub fn is_transient(err: &Error) -> bool {
+    if let Some(err) = err.downcast_ref::<HttpStatusError>() {
+        return err.status == StatusCode::TOO_MANY_REQUESTS || err.status.is_server_error();
+    }
+    matches!(
+        err.downcast_ref::<isahc::Error>(),
+        Some(isahc::Error::Timeout)
+            | Some(isahc::Error::ConnectFailed)
+            | Some(isahc::Error::NoResponse)
+            | Some(isahc::Error::Io(_))
+    )
+}
+
+/// Returns true if none of the JSON RPC methods of the request sends a
+/// transaction. A transaction may have reached the node even if the request
+/// failed, so sending it again only fails because the transaction or its
+/// nonce is already known, and external signers would sign it again.
+pub fn is_retryable(request: &Request) -> bool {
+    let calls = match request {
+        Request::Single(call) => std::slice::from_ref(call),
+        Request::Batch(calls) => calls.as_slice(),
+    };
+    calls.iter().all(|call| match call {
+        Call::MethodCall(call) => is_retryable_method(&call.method),
+        Call::Notification(notification) => is_retryable_method(&notification.method),
+        _ => true,
+    })
+}
+
+fn is_retryable_method(method: &str) -> bool {
+    !method.starts_with("eth_send")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::batch_id::BATCH_DURATION;
+    use anyhow::anyhow;
+    use ethcontract::web3::helpers;
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..Default::default()
+        };
+        for (attempt, max) in &[(1, 1), (2, 2), (3, 4), (4, 8), (5, 10), (100, 10)] {
+            let max = Duration::from_secs(*max);
+            let backoff = policy.backoff(*attempt);
+            assert!(max / 2 <= backoff && backoff <= max);
+        }
+    }
+
+    #[test]
+    fn retry_budget_resets_every_batch() {
+        let budget = RetryBudget::new(2);
+        let now = SystemTime::UNIX_EPOCH + BATCH_DURATION * 42;
+        assert!(budget.try_spend(now));
+        assert!(budget.try_spend(now));
+        assert!(!budget.try_spend(now));
+        assert!(budget.try_spend(now + BATCH_DURATION));
+    }
+
+    #[test]
+    fn retry_budget_uses_batch_timing() {
+        let budget = RetryBudget::new(1);
+        let batch_duration = Duration::from_secs(60);
+        budget.set_batch_timing(BatchTiming::with_batch_duration(batch_duration));
+        let now = SystemTime::UNIX_EPOCH + batch_duration * 42;
+        assert!(budget.try_spend(now));
+        assert!(!budget.try_spend(now));
+        assert!(budget.try_spend(now + batch_duration));
+    }
+
+    #[test]
+    fn transient_errors() {
+        let status_error = |status| {
+            Error::from(HttpStatusError {
+                status,
+                body: String::new(),
+            })
+        };
+        assert!(is_transient(&status_error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(is_transient(&status_error(StatusCode::BAD_GATEWAY)));
+        assert!(!is_transient(&status_error(StatusCode::BAD_REQUEST)));
+        assert!(is_transient(&Error::from(isahc::Error::Timeout)));
+        assert!(!is_transient(&Error::from(
+            isahc::Error::CouldntResolveHost
+        )));
+        assert!(!is_transient(&anyhow!("some other error")));
+    }
+
+    #[test]
+    fn transactions_are_not_retried() {
+        let call = |method| helpers::build_request(1, method, vec![]);
+        assert!(is_retryable(&Request::Single(call("eth_call"))));
+        assert!(is_retryable(&Request::Batch(vec![
+            call("eth_blockNumber"),
+            call("eth_getBalance"),
+        ])));
+        assert!(!is_retryable(&Request::Single(call(
+            "eth_sendRawTransaction"
+        ))));
+        assert!(!is_retryable(&Request::Single(call("eth_sendTransaction"))));
+        assert!(!is_retryable(&Request::Batch(vec![
+            call("eth_call"),
+            call("eth_sendRawTransaction"),
+        ])));
+    }
+}
@@ -0,0 +1,725 @@
+//! JSON RPC transport over a persistent WebSocket or IPC connection to the
+//! node. Unlike HTTP, these connections support subscriptions, so that new
+//! blocks are pushed by the node instead of being polled for.
+//!
+//! The connection is handled by a background task that reconnects when the
+//! connection is lost and renews all subscriptions on the new connection.
+//! Requests that were in flight when the connection was lost fail. WebSocket
+//! connections are tunneled through the HTTP proxy unless the node bypasses
+//! it.
+
+use super::RpcResult;
+use anyhow::{anyhow, bail, Context as _, Error, Result};
+use async_std::task;
+use async_tungstenite::{
+    async_std::{client_async_tls, connect_async},
+    tungstenite::Message,
+};
+use ethcontract::jsonrpc::types::{Call, Id, Output, Request};
+use ethcontract::web3::helpers;
+use ethcontract::web3::types::{H256, U64};
+use ethcontract::web3::{BatchTransport, Error as Web3Error, RequestId, Transport};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture, FutureExt as _},
+    sink::{self, Sink, SinkExt as _},
+    stream::{self, BoxStream, StreamExt as _},
+};
+use isahc::http::Uri;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    iter,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// The time to wait before reconnecting after the connection was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The time after which the latest block pushed by the node is considered
+/// outdated, which is about two blocks on mainnet. Users then fall back to
+/// polling the node until new blocks are pushed again.
+const MAX_HEAD_AGE: Duration = Duration::from_secs(30);
+
+/// Where the node accepts connections.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Endpoint {
+    WebSocket(Url),
+    Ipc(PathBuf),
+}
+
+/// A transport sending requests over a persistent connection to the node.
+#[derive(Clone)]
+pub struct SocketTransport(Arc<Inner>);
+
+struct Inner {
+    endpoint: Endpoint,
+    timeout: Duration,
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    shared: Arc<Shared>,
+}
+
+/// A message to the connection task.
+enum Outgoing {
+    /// A request to send to the node.
+    Request(String),
+    /// A new subscription to subscribe to on the current connection.
+    Subscribe(usize),
+}
+
+/// State shared between the transport and its connection task.
+#[derive(Default)]
+struct Shared {
+    id: AtomicUsize,
+    pending: Mutex<HashMap<RequestId, oneshot::Sender<RpcResult>>>,
+    subscriptions: Mutex<HashMap<usize, Subscription>>,
+}
+
+struct Subscription {
+    params: Vec<Value>,
+    sender: mpsc::UnboundedSender<Value>,
+    state: SubscriptionState,
+}
+
+/// The state of a subscription on the current connection. Subscriptions are
+/// only made by the connection task, so that every subscription is made once
+/// per connection.
+#[derive(Debug, Eq, PartialEq)]
+enum SubscriptionState {
+    Unsubscribed,
+    Subscribing,
+    /// Subscribed with the ID the node assigned to the subscription.
+    Subscribed(String),
+}
+
+impl SocketTransport {
+    /// Creates a transport connecting to the endpoint in a background task.
+    /// WebSocket connections are tunneled through the proxy if there is one.
+    /// The connection is closed once all clones of the transport are dropped.
+    pub fn new(endpoint: Endpoint, proxy: Option<Uri>, timeout: Duration) -> Self {
+        let (outgoing, receiver) = mpsc::unbounded();
+        let shared = Arc::new(Shared::default());
+        task::spawn(run(endpoint.clone(), proxy, receiver, shared.clone()));
+        SocketTransport(Arc::new(Inner {
+            endpoint,
+            timeout,
+            outgoing,
+            shared,
+        }))
+    }
+
+    /// Subscribes to notifications with `eth_subscribe`. The subscription is
+    /// renewed whenever the connection is reestablished and ends when the
+    /// receiver is dropped. The receiver ends if the node rejects the
+    /// subscription or the transport is closed.
+    pub fn subscribe(&self, params: Vec<Value>) -> mpsc::UnboundedReceiver<Value> {
+        let (sender, receiver) = mpsc::unbounded();
+        let subscription = self.0.shared.next_id();
+        // The lock is held until the subscription is inserted so that the
+        // connection task cannot handle the message before.
+        let mut subscriptions = self.0.shared.subscriptions.lock().unwrap();
+        if self
+            .0
+            .outgoing
+            .unbounded_send(Outgoing::Subscribe(subscription))
+            .is_ok()
+        {
+            subscriptions.insert(
+                subscription,
+                Subscription {
+                    params,
+                    sender,
+                    state: SubscriptionState::Unsubscribed,
+                },
+            );
+        }
+        receiver
+    }
+
+    /// Keeps track of the latest block with a `newHeads` subscription.
+    pub fn new_heads(&self) -> NewHeads {
+        let heads = NewHeads::default();
+        task::spawn(watch_new_heads(self.clone(), Arc::downgrade(&heads.0)));
+        heads
+    }
+}
+
+impl Inner {
+    async fn execute(self: Arc<Self>, id: RequestId, call: Call) -> RpcResult {
+        let request = serde_json::to_string(&Request::Single(call))?;
+        let (sender, receiver) = oneshot::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        if self
+            .outgoing
+            .unbounded_send(Outgoing::Request(request))
+            .is_err()
+        {
+            self.shared.pending.lock().unwrap().remove(&id);
+            return Err(Web3Error::Transport("connection to node closed".to_owned()));
+        }
+
+        let result = async_std::future::timeout(self.timeout, receiver).await;
+        self.shared.pending.lock().unwrap().remove(&id);
+        match result {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(Web3Error::Transport("connection to node lost".to_owned())),
+            Err(_) => Err(Web3Error::Transport(format!(
+                "request timed out after {:?}",
+                self.timeout
+            ))),
+        }
+    }
+}
+
+impl Shared {
+    fn next_id(&self) -> usize {
+        self.id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Requests for subscribing to the subscriptions that are not subscribed
+    /// to on the current connection.
+    fn subscribe(self: &Arc<Self>, subscriptions: impl IntoIterator<Item = usize>) -> Vec<String> {
+        let mut all = self.subscriptions.lock().unwrap();
+        subscriptions
+            .into_iter()
+            .filter_map(|subscription| {
+                let Subscription { params, state, .. } = all.get_mut(&subscription)?;
+                if *state != SubscriptionState::Unsubscribed {
+                    return None;
+                }
+                *state = SubscriptionState::Subscribing;
+
+                let id = self.next_id();
+                let (sender, response) = oneshot::channel();
+                self.pending.lock().unwrap().insert(id, sender);
+                let call = helpers::build_request(id, "eth_subscribe", params.clone());
+                let request = serde_json::to_string(&Request::Single(call))
+                    .expect("JSON RPC requests are always serializable");
+
+                let shared = self.clone();
+                task::spawn(async move {
+                    match response.await {
+                        Ok(Ok(node_id)) => shared.subscribed(subscription, node_id),
+                        Ok(Err(err)) => {
+                            shared.subscription_failed(subscription, anyhow!("{}", err))
+                        }
+                        // The connection was lost, the subscription is
+                        // renewed on the next one.
+                        Err(_) => (),
+                    }
+                });
+                Some(request)
+            })
+            .collect()
+    }
+
+    /// Requests for renewing all subscriptions on a new connection.
+    fn resubscribe(self: &Arc<Self>) -> Vec<String> {
+        let subscriptions = {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            for subscription in subscriptions.values_mut() {
+                subscription.state = SubscriptionState::Unsubscribed;
+            }
+            subscriptions.keys().copied().collect::<Vec<_>>()
+        };
+        self.subscribe(subscriptions)
+    }
+
+    /// Records the ID the node assigned to the subscription.
+    fn subscribed(&self, subscription: usize, node_id: Value) {
+        let node_id = match node_id.as_str() {
+            Some(node_id) => node_id.to_owned(),
+            None => {
+                return self.subscription_failed(
+                    subscription,
+                    anyhow!("unexpected subscription ID {}", node_id),
+                )
+            }
+        };
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if let Some(subscription) = subscriptions.get_mut(&subscription) {
+            subscription.state = SubscriptionState::Subscribed(node_id);
+        }
+    }
+
+    /// Ends a subscription the node did not accept, which ends its receiver.
+    fn subscription_failed(&self, subscription: usize, err: Error) {
+        if let Some(Subscription { params, .. }) =
+            self.subscriptions.lock().unwrap().remove(&subscription)
+        {
+            log::error!("failed to subscribe to {:?}: {:?}", params, err);
+        }
+    }
+
+    /// Handles a message received from the node. Returns an `eth_unsubscribe`
+    /// request for notifications of subscriptions that were dropped.
+    fn handle_message(&self, message: Value) -> Option<String> {
+        match parse_message(message) {
+            Ok(Incoming::Response(id, result)) => {
+                if let Some(sender) = self.pending.lock().unwrap().remove(&id) {
+                    let _ = sender.send(result);
+                }
+                None
+            }
+            Ok(Incoming::Notification {
+                subscription,
+                result,
+            }) => {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                let local = subscriptions.iter().find_map(|(local, s)| match &s.state {
+                    SubscriptionState::Subscribed(node_id) if *node_id == subscription => {
+                        Some(*local)
+                    }
+                    _ => None,
+                })?;
+                if subscriptions[&local].sender.unbounded_send(result).is_ok() {
+                    return None;
+                }
+                subscriptions.remove(&local);
+                let call = helpers::build_request(
+                    self.next_id(),
+                    "eth_unsubscribe",
+                    vec![subscription.into()],
+                );
+                serde_json::to_string(&Request::Single(call)).ok()
+            }
+            Err(err) => {
+                log::warn!("unexpected message from node: {:?}", err);
+                None
+            }
+        }
+    }
+}
+
+/// A message received from the node.
+#[derive(Debug)]
+enum Incoming {
+    Response(RequestId, RpcResult),
+    Notification { subscription: String, result: Value },
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    params: NotificationParams,
+}
+
+#[derive(Deserialize)]
+struct NotificationParams {
+    subscription: String,
+    result: Value,
+}
+
+fn parse_message(message: Value) -> Result<Incoming> {
+    if message.get("method").and_then(Value::as_str) == Some("eth_subscription") {
+        let Notification {
+            params:
+                NotificationParams {
+                    subscription,
+                    result,
+                },
+        } = serde_json::from_value(message)?;
+        return Ok(Incoming::Notification {
+            subscription,
+            result,
+        });
+    }
+
+    let output = Output::deserialize(message)?;
+    let id = match output.id() {
+        Id::Num(id) => *id as RequestId,
+        id => bail!("response with unexpected ID {:?}", id),
+    };
+    Ok(Incoming::Response(
+        id,
+        helpers::to_result_from_output(output),
+    ))
+}
+
+/// Handles connections to the endpoint until all transports are dropped.
+async fn run(
+    endpoint: Endpoint,
+    proxy: Option<Uri>,
+    mut outgoing: mpsc::UnboundedReceiver<Outgoing>,
+    shared: Arc<Shared>,
+) {
+    loop {
+        match connection(&endpoint, proxy.as_ref(), &mut outgoing, &shared).await {
+            Ok(()) => return,
+            Err(err) => log::warn!("connection to node at {:?} failed: {:?}", endpoint, err),
+        }
+        // Dropping the senders fails the requests waiting for a response.
+        shared.pending.lock().unwrap().clear();
+        task::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+type MessageSink = Pin<Box<dyn Sink<String, Error = Error> + Send>>;
+type MessageStream = BoxStream<'static, Result<Value>>;
+
+/// Handles a single connection until it fails or all transports are dropped.
+async fn connection(
+    endpoint: &Endpoint,
+    proxy: Option<&Uri>,
+    outgoing: &mut mpsc::UnboundedReceiver<Outgoing>,
+    shared: &Arc<Shared>,
+) -> Result<()> {
+    let (mut sink, stream) = match endpoint {
+        Endpoint::WebSocket(url) => connect_web_socket(url, proxy).await?,
+        Endpoint::Ipc(path) => connect_ipc(path).await?,
+    };
+    let mut stream = stream.fuse();
+    for request in shared.resubscribe() {
+        sink.send(request).await?;
+    }
+
+    loop {
+        futures::select! {
+            request = outgoing.next() => match request {
+                Some(Outgoing::Request(request)) => sink.send(request).await?,
+                Some(Outgoing::Subscribe(subscription)) => {
+                    for request in shared.subscribe(iter::once(subscription)) {
+                        sink.send(request).await?;
+                    }
+                }
+                None => return Ok(()),
+            },
+            message = stream.next() => match message {
+                Some(message) => {
+                    if let Some(request) = shared.handle_message(message?) {
+                        sink.send(request).await?;
+                    }
+                }
+                None => bail!("connection closed"),
+            },
+        }
+    }
+}
+
+async fn connect_web_socket(
+    url: &Url,
+    proxy: Option<&Uri>,
+) -> Result<(MessageSink, MessageStream)> {
+    let connected = match proxy {
+        Some(proxy) => {
+            let host = url
+                .host_str()
+                .ok_or_else(|| anyhow!("node URL has no host"))?;
+            let port = url
+                .port_or_known_default()
+                .ok_or_else(|| anyhow!("node URL has no port"))?;
+            let stream = super::proxy::connect(proxy, host, port)
+                .await
+                .context("failed to connect to node through proxy")?;
+            client_async_tls(url.as_str(), stream).await
+        }
+        None => connect_async(url.as_str()).await,
+    };
+    let (socket, _) = connected.context("failed to connect to node WebSocket")?;
+    let (sink, stream) = socket.split();
+    let sink = sink
+        .sink_map_err(Error::from)
+        .with(|request: String| future::ok::<_, Error>(Message::Text(request)));
+    let stream = stream.filter_map(|message| {
+        future::ready(match message {
+            Ok(Message::Text(text)) => {
+                Some(serde_json::from_str::<Value>(&text).map_err(Error::from))
+            }
+            Ok(Message::Binary(bytes)) => {
+                Some(serde_json::from_slice::<Value>(&bytes).map_err(Error::from))
+            }
+            Ok(_) => None,
+            Err(err) => Some(Err(err.into())),
+        })
+    });
+    Ok((Box::pin(sink), stream.boxed()))
+}
+
+#[cfg(unix)]
+async fn connect_ipc(path: &Path) -> Result<(MessageSink, MessageStream)> {
+    use async_std::os::unix::net::UnixStream;
+    use futures::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let socket = UnixStream::connect(path)
+        .await
+        .context("failed to connect to node IPC socket")?;
+    let (reader, writer) = socket.split();
+    let sink = sink::unfold(writer, |mut writer, request: String| async move {
+        writer.write_all(request.as_bytes()).await?;
+        Ok::<_, Error>(writer)
+    });
+    let stream = stream::unfold(
+        (reader, JsonSplitter::default()),
+        |(mut reader, mut splitter)| async move {
+            let mut buffer = [0; 4096];
+            let messages = match reader.read(&mut buffer).await {
+                Ok(0) => return None,
+                Ok(len) => splitter.push(&buffer[..len]),
+                Err(err) => Err(err.into()),
+            };
+            let messages = match messages {
+                Ok(messages) => messages.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            };
+            Some((stream::iter(messages), (reader, splitter)))
+        },
+    )
+    .flatten();
+    Ok((Box::pin(sink), stream.boxed()))
+}
+
+#[cfg(not(unix))]
+async fn connect_ipc(_: &Path) -> Result<(MessageSink, MessageStream)> {
+    bail!("IPC connections are only supported on unix")
+}
+
+/// Splits the stream of concatenated JSON values received over IPC into
+/// messages.
+#[derive(Debug, Default)]
+struct JsonSplitter {
+    buffer: Vec<u8>,
+}
+
+impl JsonSplitter {
+    /// Adds received bytes and returns all messages that are complete.
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Value>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        let mut values = serde_json::Deserializer::from_slice(&self.buffer).into_iter();
+        let consumed = loop {
+            match values.next() {
+                Some(Ok(message)) => messages.push(message),
+                Some(Err(err)) if err.is_eof() => break values.byte_offset(),
+                Some(Err(err)) => return Err(err.into()),
+                None => break values.byte_offset(),
+            }
+        };
+        self.buffer.drain(..consumed);
+        Ok(messages)
+    }
+}
+
+/// The latest block announced by the node.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub struct Head {
+    pub number: U64,
+    pub hash: H256,
+}
+
+/// The latest block, kept up to date by a `newHeads` subscription for as long
+/// as a clone of it exists.
+#[derive(Clone, Debug, Default)]
+pub struct NewHeads(Arc<Mutex<Option<(Head, Instant)>>>);
+
+impl NewHeads {
+    /// The latest block or `None` if the subscription has not received a
+    /// block recently, for example because the connection was lost.
+    pub fn latest(&self) -> Option<Head> {
+        self.latest_at(Instant::now())
+    }
+
+    fn latest_at(&self, now: Instant) -> Option<Head> {
+        match *self.0.lock().unwrap() {
+            Some((head, received)) if now.saturating_duration_since(received) < MAX_HEAD_AGE => {
+                Some(head)
+            }
+            _ => None,
+        }
+    }
+}
+
+async fn watch_new_heads(transport: SocketTransport, latest: Weak<Mutex<Option<(Head, Instant)>>>) {
+    while latest.upgrade().is_some() {
+        let mut heads = transport.subscribe(vec!["newHeads".into()]);
+        while let Some(head) = heads.next().await {
+            let latest = match latest.upgrade() {
+                Some(latest) => latest,
+                None => return,
+            };
+            match serde_json::from_value(head) {
+                Ok(head) => *latest.lock().unwrap() = Some((head, Instant::now())),
+                Err(err) => log::warn!("failed to parse new block: {:?}", err),
+            }
+        }
+
+        // The subscription failed, so the last block is outdated until the
+        // subscription is renewed.
+        if let Some(latest) = latest.upgrade() {
+            *latest.lock().unwrap() = None;
+        }
+        task::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+impl Debug for SocketTransport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("SocketTransport")
+            .field(&self.0.endpoint)
+            .finish()
+    }
+}
+
+impl Transport for SocketTransport {
+    type Out = BoxFuture<'static, RpcResult>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.0.shared.next_id();
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        self.0.clone().execute(id, request).boxed()
+    }
+}
+
+impl BatchTransport for SocketTransport {
+    type Batch = BoxFuture<'static, Result<Vec<RpcResult>, Web3Error>>;
+
+    /// Requests of a batch are sent individually over the connection, which
+    /// avoids the overhead of separate HTTP requests the same way batching
+    /// does.
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let requests = requests
+            .into_iter()
+            .map(|(id, request)| self.send(id, request))
+            .collect::<Vec<_>>();
+        future::join_all(requests).map(Ok).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splits_concatenated_json_messages() {
+        let mut splitter = JsonSplitter::default();
+        assert_eq!(
+            splitter.push(br#"{"id":1}{"id":2}{"id""#).unwrap(),
+            vec![json!({"id": 1}), json!({"id": 2})]
+        );
+        assert_eq!(splitter.push(br#":3}"#).unwrap(), vec![json!({"id": 3})]);
+        assert!(splitter.push(b"}").is_err());
+    }
+
+    #[test]
+    fn parses_responses_and_notifications() {
+        assert!(matches!(
+            parse_message(json!({"jsonrpc": "2.0", "id": 7, "result": "0x1"})).unwrap(),
+            Incoming::Response(7, Ok(result)) if result == json!("0x1")
+        ));
+        assert!(matches!(
+            parse_message(json!({
+                "jsonrpc": "2.0",
+                "id": 8,
+                "error": {"code": -32000, "message": "error"},
+            }))
+            .unwrap(),
+            Incoming::Response(8, Err(_))
+        ));
+        assert!(matches!(
+            parse_message(json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": {"subscription": "0xcd0c", "result": {"number": "0x1"}},
+            }))
+            .unwrap(),
+            Incoming::Notification { subscription, result }
+                if subscription == "0xcd0c" && result == json!({"number": "0x1"})
+        ));
+        assert!(parse_message(json!({"unexpected": true})).is_err());
+    }
+
+    #[test]
+    fn routes_notifications_until_subscription_is_dropped() {
+        let shared = Shared::default();
+        let (sender, mut receiver) = mpsc::unbounded();
+        shared.subscriptions.lock().unwrap().insert(
+            0,
+            Subscription {
+                params: vec![json!("newHeads")],
+                sender,
+                state: SubscriptionState::Subscribing,
+            },
+        );
+        shared.subscribed(0, json!("0xcd0c"));
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": {"subscription": "0xcd0c", "result": 1},
+        });
+        assert_eq!(shared.handle_message(notification.clone()), None);
+        assert_eq!(receiver.try_next().unwrap(), Some(json!(1)));
+
+        drop(receiver);
+        let unsubscribe = shared.handle_message(notification).unwrap();
+        assert!(unsubscribe.contains("eth_unsubscribe"));
+        assert!(shared.subscriptions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn subscribes_once_per_connection() {
+        let shared = Arc::new(Shared::default());
+        let (sender, mut receiver) = mpsc::unbounded();
+        shared.subscriptions.lock().unwrap().insert(
+            0,
+            Subscription {
+                params: vec![json!("newHeads")],
+                sender,
+                state: SubscriptionState::Unsubscribed,
+            },
+        );
+
+        // A subscription made before the connection is established is only
+        // subscribed to when connecting.
+        assert_eq!(shared.resubscribe().len(), 1);
+        assert!(shared.subscribe(iter::once(0)).is_empty());
+        assert_eq!(shared.pending.lock().unwrap().len(), 1);
+
+        // The connection was lost before the node responded.
+        shared.pending.lock().unwrap().clear();
+        assert_eq!(shared.resubscribe().len(), 1);
+
+        shared.subscription_failed(0, anyhow!("error"));
+        assert!(shared.subscriptions.lock().unwrap().is_empty());
+        assert_eq!(receiver.try_next().unwrap(), None);
+    }
+
+    #[test]
+    fn new_heads_expire() {
+        let heads = NewHeads::default();
+        let head = Head {
+            number: 1.into(),
+            hash: H256::zero(),
+        };
+        let now = Instant::now();
+        *heads.0.lock().unwrap() = Some((head, now));
+        assert_eq!(heads.latest_at(now), Some(head));
+        assert_eq!(heads.latest_at(now + MAX_HEAD_AGE), None);
+    }
+
+    #[test]
+    fn responds_to_pending_requests() {
+        let shared = Shared::default();
+        let (sender, mut receiver) = oneshot::channel();
+        shared.pending.lock().unwrap().insert(3, sender);
+        shared.handle_message(json!({"jsonrpc": "2.0", "id": 3, "result": true}));
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Some(Ok(result)) if result == json!(true)
+        ));
+    }
+}